@@ -465,12 +465,21 @@ func boardFQBN(id string) (string, error) {
 		"mega":     "arduino:avr:mega",
 		"leonardo": "arduino:avr:leonardo",
 		"micro":    "arduino:avr:micro",
+		"nano_every": "arduino:megaavr:nona4809",
+		"attiny1614": "megaTinyCore:megaavr:atxy4:chip=1614",
+		"attiny3216": "megaTinyCore:megaavr:atxy6:chip=3216",
 		"due":      "arduino:sam:arduino_due_x",
 		"mkr1000":  "arduino:samd:mkr1000",
 		"esp32":    "esp32:esp32:esp32",
 		"esp8266":  "esp8266:esp8266:generic",
 		"pico":     "rp2040:rp2040:rpipico",
 		"teensy40": "teensy:avr:teensy40",
+		"teensy41": "teensy:avr:teensy41",
+		"teensylc": "teensy:avr:teensyLC",
+
+		"bluepill":       "STMicroelectronics:stm32:GenF1:pnum=BLUEPILL_F103C8",
+		"blackpill_f411": "STMicroelectronics:stm32:GenF4:pnum=BLACKPILL_F411CE",
+		"nucleo_f401re":  "STMicroelectronics:stm32:Nucleo_64:pnum=NUCLEO_F401RE",
 	}
 	fqbn, ok := table[strings.ToLower(id)]
 	if !ok {
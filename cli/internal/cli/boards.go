@@ -2,7 +2,9 @@ package cli
 
 import (
 	"fmt"
+	"io/fs"
 	"os"
+	"os/exec"
 	"path/filepath"
 
 	"github.com/spf13/cobra"
@@ -26,12 +28,20 @@ var boardCatalog = []boardInfo{
 	{"mega",     "Arduino Mega 2560",            256,   8,   "arduino:avr:mega"},
 	{"leonardo", "Arduino Leonardo",             32,    2,   "arduino:avr:leonardo"},
 	{"micro",    "Arduino Micro",                32,    2,   "arduino:avr:micro"},
+	{"nano_every", "Arduino Nano Every (ATmega4809)", 48, 6, "arduino:megaavr:nona4809"},
+	{"attiny1614", "ATtiny1614 (megaTinyCore)",     16, 2, "megaTinyCore:megaavr:atxy4:chip=1614"},
+	{"attiny3216", "ATtiny3216 (megaTinyCore)",     32, 2, "megaTinyCore:megaavr:atxy6:chip=3216"},
 	{"due",      "Arduino Due (SAM3X8E)",        512,   96,  "arduino:sam:arduino_due_x"},
 	{"mkr1000",  "Arduino MKR1000 (SAMD21)",     256,   32,  "arduino:samd:mkr1000"},
 	{"esp32",    "ESP32 Dev Module",             4096,  520, "esp32:esp32:esp32"},
 	{"esp8266",  "ESP8266 Generic",              4096,  80,  "esp8266:esp8266:generic"},
 	{"pico",     "Raspberry Pi Pico (RP2040)",   2048,  264, "rp2040:rp2040:rpipico"},
 	{"teensy40", "Teensy 4.0 (iMXRT1062)",       1984,  1024,"teensy:avr:teensy40"},
+	{"teensy41", "Teensy 4.1 (iMXRT1062)",       8192,  1024,"teensy:avr:teensy41"},
+	{"teensylc", "Teensy LC (MKL26Z64)",         62,    8,   "teensy:avr:teensyLC"},
+	{"bluepill", "STM32 Blue Pill (F103C8)",     64,    20,  "STMicroelectronics:stm32:GenF1:pnum=BLUEPILL_F103C8"},
+	{"blackpill_f411", "STM32 Black Pill (F411CE)", 512, 128, "STMicroelectronics:stm32:GenF4:pnum=BLACKPILL_F411CE"},
+	{"nucleo_f401re",  "STM32 Nucleo-F401RE",       512, 96,  "STMicroelectronics:stm32:Nucleo_64:pnum=NUCLEO_F401RE"},
 }
 
 func newBoardsCmd() *cobra.Command {
@@ -56,7 +66,7 @@ func newBoardsListCmd() *cobra.Command {
 			ui.ColorTitle.Printf("  %-12s  %-34s  %7s  %6s  %s\n", "ID", "NAME", "FLASH", "RAM", "FQBN")
 			ui.ColorMuted.Println("  " + hline(90, "─"))
 
-			for _, b := range boardCatalog {
+			for _, b := range allBoards() {
 				ui.ColorKey.Printf("  %-12s", b.ID)
 				fmt.Printf("  %-34s", b.Name)
 				ui.ColorNumber.Printf("  %5dK", b.FlashKB)
@@ -97,9 +107,18 @@ func hline(n int, ch string) string {
 // ── clean ─────────────────────────────────────────────────────────────────────
 
 func newCleanCmd() *cobra.Command {
-	return &cobra.Command{
+	var (
+		deep   bool
+		dryRun bool
+	)
+
+	cmd := &cobra.Command{
 		Use:   "clean",
 		Short: "Remove the build/ directory",
+		Long: `Remove the build/ directory.
+
+With --deep, tsuki-flash's global object cache and unfinished module
+downloads go too. --dry-run lists what would be removed, with sizes.`,
 		RunE: func(cmd *cobra.Command, args []string) error {
 			dir := projectDir()
 			_, m, err := manifest.Find(dir)
@@ -108,21 +127,76 @@ func newCleanCmd() *cobra.Command {
 			}
 
 			buildDir := filepath.Join(dir, m.Build.OutputDir)
+
+			// The global caches belong to tsuki-flash, which knows where they
+			// are; it also covers the core archives in build/.cache.
+			if deep {
+				flashBin := cfg.FlashBinary
+				if flashBin == "" {
+					flashBin = "tsuki-flash"
+				}
+				flashArgs := []string{"clean", "--deep", "--build-dir", filepath.Join(buildDir, ".cache")}
+				if dryRun {
+					flashArgs = append(flashArgs, "--dry-run")
+				}
+				flash := exec.Command(flashBin, flashArgs...)
+				flash.Stdout = os.Stdout
+				flash.Stderr = os.Stderr
+				if err := flash.Run(); err != nil {
+					return fmt.Errorf("%s clean --deep: %w", flashBin, err)
+				}
+			}
+
 			if _, err := os.Stat(buildDir); os.IsNotExist(err) {
 				ui.Info(fmt.Sprintf("%s does not exist — nothing to clean", m.Build.OutputDir))
 				return nil
 			}
 
+			if dryRun {
+				ui.Info(fmt.Sprintf("would remove %s (%s)", buildDir, humanSize(dirSize(buildDir))))
+				return nil
+			}
+
 			sp := ui.NewSpinner(fmt.Sprintf("Removing %s…", buildDir))
 			sp.Start()
+			size := dirSize(buildDir)
 			if err := os.RemoveAll(buildDir); err != nil {
 				sp.Stop(false, "failed to remove build directory")
 				return err
 			}
-			sp.Stop(true, fmt.Sprintf("Removed %s", buildDir))
+			sp.Stop(true, fmt.Sprintf("Removed %s (%s)", buildDir, humanSize(size)))
 			return nil
 		},
 	}
+
+	cmd.Flags().BoolVar(&deep, "deep", false, "also clear tsuki-flash's global caches")
+	cmd.Flags().BoolVarP(&dryRun, "dry-run", "n", false, "list what would be removed without deleting anything")
+	return cmd
+}
+
+// dirSize is the total size of the regular files under dir.
+func dirSize(dir string) int64 {
+	var total int64
+	_ = filepath.WalkDir(dir, func(_ string, d fs.DirEntry, err error) error {
+		if err == nil && d.Type().IsRegular() {
+			if info, err := d.Info(); err == nil {
+				total += info.Size()
+			}
+		}
+		return nil
+	})
+	return total
+}
+
+func humanSize(n int64) string {
+	switch {
+	case n < 1024:
+		return fmt.Sprintf("%d B", n)
+	case n < 1024*1024:
+		return fmt.Sprintf("%.1f KB", float64(n)/1024)
+	default:
+		return fmt.Sprintf("%.1f MB", float64(n)/(1024*1024))
+	}
 }
 
 // ── version ───────────────────────────────────────────────────────────────────
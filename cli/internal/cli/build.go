@@ -19,6 +19,9 @@ import (
 	"os"
 	"os/exec"
 	"path/filepath"
+	"slices"
+	"sort"
+	"strconv"
 	"strings"
 
 	"github.com/spf13/cobra"
@@ -42,6 +45,15 @@ type Options struct {
 	// Backend selects the compiler: "tsuki-flash" or "arduino-cli".
 	// Defaults to "arduino-cli" if empty.
 	Backend     string
+	// Shared workspace packages, transpiled into the sketch next to src/.
+	Shared      []manifest.SharedPackage
+	// CoreCache is handed to arduino-cli as --build-cache-path so the
+	// members of a workspace reuse one compiled core per board.
+	CoreCache   string
+	// KeepAll disables dead code elimination in tsuki-core.
+	KeepAll     bool
+	// Locked fails the build when package resolution would change tsuki.lock.
+	Locked      bool
 }
 
 // Result holds the outputs of a successful build.
@@ -50,6 +62,12 @@ type Result struct {
 	SketchDir   string // path to the generated Arduino sketch dir
 	FirmwareHex string
 	Warnings    []string
+	// ArduinoLibs are the Arduino libraries the built-in packages the
+	// sketch imports compile against (dht → "DHT sensor library").
+	ArduinoLibs []string
+	// IncludeDirs are the src/ directories of the tsuki packages that
+	// vendor their C++ headers.
+	IncludeDirs []string
 }
 
 // Run executes the full build pipeline.
@@ -92,27 +110,39 @@ func Run(projectDir string, m *manifest.Manifest, opts Options) (*Result, error)
 		return nil, fmt.Errorf("no .go files found in %s", srcDir)
 	}
 
-	// Resolve declared packages
-	pkgNames := m.PackageNames()
-	libsDir  := pkgmgr.LibsDir()
+	// Resolve declared packages to the installed versions in tsuki.lock.
+	// The core gets them as name@version so it loads exactly those.
+	libsDir := pkgmgr.LibsDir()
+	pkgs, err := resolvePackages(projectDir, m.PackageNames(), opts.Locked)
+	if err != nil {
+		return nil, err
+	}
+	pkgNames := make([]string, len(pkgs))
+	for i, p := range pkgs {
+		pkgNames[i] = p.Name + "@" + p.Version
+	}
 
 	if len(pkgNames) > 0 {
 		ui.SectionTitle(fmt.Sprintf("Transpiling  [board: %s]  [packages: %s]",
 			board, strings.Join(pkgNames, ", ")))
-		for _, name := range pkgNames {
-			if ok, _ := pkgmgr.IsInstalled(name); !ok {
-				return nil, fmt.Errorf(
-					"package %q declared in goduino.json is not installed\n"+
-						"  Run: tsuki pkg install %s", name, name,
-				)
-			}
-		}
 	} else {
 		ui.SectionTitle(fmt.Sprintf("Transpiling  [board: %s]", board))
 	}
 
 	result := &Result{SketchDir: sketchDir}
 
+	preludeDir := ""
+	if m.Build.PreludeDir != "" {
+		preludeDir = filepath.Join(projectDir, m.Build.PreludeDir)
+	}
+	localPkgs := make([]string, len(opts.Shared))
+	for i, p := range opts.Shared {
+		localPkgs[i] = p.Name
+	}
+	if err := transpileShared(transpiler, result, opts.Shared, localPkgs, sketchDir, board, m, libsDir, pkgNames, preludeDir); err != nil {
+		return nil, err
+	}
+
 	for _, goFile := range goFiles {
 		base    := strings.TrimSuffix(filepath.Base(goFile), ".go")
 		cppFile := filepath.Join(sketchDir, base+".cpp") // write INTO sketch dir
@@ -125,8 +155,15 @@ func Run(projectDir string, m *manifest.Manifest, opts Options) (*Result, error)
 			OutputFile: cppFile,
 			Board:      board,
 			SourceMap:  opts.SourceMap || m.Build.SourceMap,
+			CpuMHz:     m.CpuMHz,
+			ProgmemMin: m.Build.ProgmemMin,
 			LibsDir:    libsDir,
 			PkgNames:   pkgNames,
+
+			LocalPackages: localPkgs,
+			PreludeDir:    preludeDir,
+			NoSplitMain:   m.Build.SplitMain != nil && !*m.Build.SplitMain,
+			KeepAll:       opts.KeepAll,
 		})
 		if err != nil {
 			sp.Stop(false, fmt.Sprintf("failed: %s", filepath.Base(goFile)))
@@ -136,6 +173,16 @@ func Run(projectDir string, m *manifest.Manifest, opts Options) (*Result, error)
 		sp.Stop(true, fmt.Sprintf("%s  →  %s", filepath.Base(goFile), filepath.Base(cppFile)))
 		result.CppFiles = append(result.CppFiles, tr.OutputFile)
 		result.Warnings  = append(result.Warnings, tr.Warnings...)
+		for _, lib := range tr.ArduinoLibs {
+			if !slices.Contains(result.ArduinoLibs, lib) {
+				result.ArduinoLibs = append(result.ArduinoLibs, lib)
+			}
+		}
+		for _, dir := range tr.IncludeDirs {
+			if !slices.Contains(result.IncludeDirs, dir) {
+				result.IncludeDirs = append(result.IncludeDirs, dir)
+			}
+		}
 	}
 
 	for _, w := range result.Warnings {
@@ -167,20 +214,24 @@ func Run(projectDir string, m *manifest.Manifest, opts Options) (*Result, error)
 	buildCacheDir := filepath.Join(baseOutDir, ".cache")
 	_ = os.MkdirAll(buildCacheDir, 0755)
 
+	if err := ensureArduinoLibs(result.ArduinoLibs, buildCacheDir, backend, opts); err != nil {
+		return result, err
+	}
+
 	switch backend {
 	case "tsuki-flash":
 		// Uses .arduino15 (or TSUKI_SDK_ROOT) as the SDK source.
-		if err := compileTsukiFlash(result, m, board, opts, buildCacheDir, pkgNames, libsDir, false); err != nil {
+		if err := compileTsukiFlash(result, m, board, opts, buildCacheDir, pkgs, libsDir, false); err != nil {
 			return result, err
 		}
 	case "tsuki-flash+cores":
 		// Fully standalone: tsuki-modules provides the SDK — no arduino-cli, no .arduino15.
 		// Auto-installs the SDK on first run via `tsuki-flash modules install avr` internally.
-		if err := compileTsukiFlash(result, m, board, opts, buildCacheDir, pkgNames, libsDir, true); err != nil {
+		if err := compileTsukiFlash(result, m, board, opts, buildCacheDir, pkgs, libsDir, true); err != nil {
 			return result, err
 		}
 	default: // "arduino-cli" or anything unrecognised
-		if err := compileArduinoCLI(result, board, opts, sketchDir, buildCacheDir); err != nil {
+		if err := compileArduinoCLI(result, m, board, opts, sketchDir, buildCacheDir); err != nil {
 			return result, err
 		}
 	}
@@ -193,6 +244,64 @@ func Run(projectDir string, m *manifest.Manifest, opts Options) (*Result, error)
 	return result, nil
 }
 
+// resolvePackages picks the installed version of every declared package and
+// keeps tsuki.lock in step with it. With locked, a change is an error.
+func resolvePackages(projectDir string, names []string, locked bool) ([]pkgmgr.LockEntry, error) {
+	lock, err := pkgmgr.ReadLock(projectDir)
+	if err != nil {
+		return nil, err
+	}
+	resolved, err := pkgmgr.ResolveInstalled(names, lock)
+	if err != nil {
+		return nil, err
+	}
+	changes := pkgmgr.LockDiff(lock, resolved)
+	if len(changes) == 0 {
+		return resolved, nil
+	}
+	if locked {
+		return nil, fmt.Errorf("--locked: packages differ from %s:\n  %s",
+			pkgmgr.LockFileName, strings.Join(changes, "\n  "))
+	}
+	if err := pkgmgr.WriteLock(projectDir, resolved); err != nil {
+		return nil, fmt.Errorf("writing %s: %w", pkgmgr.LockFileName, err)
+	}
+	ui.Step("lock", fmt.Sprintf("updated %s", pkgmgr.LockFileName))
+	return resolved, nil
+}
+
+// ensureArduinoLibs installs the Arduino libraries the sketch needs that this
+// build directory hasn't installed before. Installed names (with their
+// `@version` pin, so a new pin installs again) are recorded in
+// <cacheDir>/arduino-libs so later builds skip the library manager.
+func ensureArduinoLibs(libs []string, cacheDir, backend string, opts Options) error {
+	stamp := filepath.Join(cacheDir, "arduino-libs")
+	var done []string
+	if raw, err := os.ReadFile(stamp); err == nil {
+		for _, line := range strings.Split(string(raw), "\n") {
+			if line != "" {
+				done = append(done, line)
+			}
+		}
+	}
+	var installed []string
+	for _, lib := range libs {
+		if slices.Contains(done, lib) {
+			continue
+		}
+		if err := installArduinoLib(lib, backend, opts.FlashBinary, opts.ArduinoCLI); err != nil {
+			return err
+		}
+		installed = append(installed, lib)
+		done = append(done, lib)
+		_ = os.WriteFile(stamp, []byte(strings.Join(done, "\n")+"\n"), 0644)
+	}
+	if len(installed) > 0 {
+		ui.Step("libs", fmt.Sprintf("installed %s", strings.Join(installed, ", ")))
+	}
+	return nil
+}
+
 // ─────────────────────────────────────────────────────────────────────────────
 //  Backend: tsuki-flash
 // ─────────────────────────────────────────────────────────────────────────────
@@ -203,7 +312,7 @@ func compileTsukiFlash(
 	board string,
 	opts Options,
 	buildCacheDir string,
-	pkgNames []string,
+	pkgs []pkgmgr.LockEntry,
 	libsDir string,
 	useModules bool, // true → backend is "tsuki-flash+cores", pass --use-modules
 ) error {
@@ -212,22 +321,15 @@ func compileTsukiFlash(
 		flashBin = "tsuki-flash"
 	}
 
-	// Build the --include list from installed tsuki packages.
+	// Build the --include list from the resolved tsuki packages and the
+	// vendored header directories tsuki-core reported for them.
 	var includeArgs []string
-	for _, pkg := range pkgNames {
-		pkgDir := filepath.Join(libsDir, pkg)
-		// Walk to find the versioned subdirectory.
-		entries, err := os.ReadDir(pkgDir)
-		if err == nil {
-			for _, e := range entries {
-				if e.IsDir() {
-					includeArgs = append(includeArgs, filepath.Join(pkgDir, e.Name()))
-					break
-				}
-			}
-		} else {
-			// Fall back to the package root itself.
-			includeArgs = append(includeArgs, pkgDir)
+	for _, pkg := range pkgs {
+		includeArgs = append(includeArgs, filepath.Join(libsDir, pkg.Name, pkg.Version))
+	}
+	for _, dir := range result.IncludeDirs {
+		if !slices.Contains(includeArgs, dir) {
+			includeArgs = append(includeArgs, dir)
 		}
 	}
 
@@ -247,13 +349,37 @@ func compileTsukiFlash(
 	for _, inc := range includeArgs {
 		args = append(args, "--include", inc)
 	}
+	if m.CpuMHz > 0 {
+		args = append(args, "--cpu-mhz", strconv.Itoa(m.CpuMHz))
+	}
+	if m.PartitionScheme != "" {
+		args = append(args, "--partition-scheme", m.PartitionScheme)
+	}
+	for _, opt := range m.BoardOptionList() {
+		args = append(args, "--board-option", opt)
+	}
+	// "=" keeps flags that start with a dash from reading as options.
+	for _, f := range m.Build.CFlagList() {
+		args = append(args, "--cflags="+f)
+	}
+	for _, f := range m.Build.CxxFlagList() {
+		args = append(args, "--cxxflags="+f)
+	}
+	for _, d := range m.Build.Defines {
+		args = append(args, "--defines="+d)
+	}
+	for _, f := range m.Build.LdFlags {
+		args = append(args, "--ldflags="+f)
+	}
+	args = append(args, budgetArgs(m.Budget)...)
 	if opts.Verbose {
 		args = append(args, "--verbose")
 	}
 	if useModules {
 		// Instructs tsuki-flash to use ~/.tsuki/modules as the SDK root instead
 		// of .arduino15. The first invocation auto-installs the SDK via the
-		// tsuki-modules AVR module if it is not already present.
+		// pinned tsuki-modules module (avr, esp32, esp8266, rp2040) if it is
+		// not already present.
 		args = append(args, "--use-modules")
 	}
 
@@ -310,6 +436,7 @@ func compileTsukiFlash(
 
 func compileArduinoCLI(
 	result *Result,
+	m *manifest.Manifest,
 	board string,
 	opts Options,
 	sketchDir string,
@@ -319,6 +446,7 @@ func compileArduinoCLI(
 	if err != nil {
 		return fmt.Errorf("unknown board %q — run `tsuki boards list`", board)
 	}
+	fqbn = m.WithBoardOptions(fqbn)
 
 	arduinoCLI := opts.ArduinoCLI
 	if arduinoCLI == "" {
@@ -331,6 +459,28 @@ func compileArduinoCLI(
 		"--build-path", buildCacheDir,
 		"--warnings", "all",
 	}
+	if m.CpuMHz > 0 {
+		args = append(args, "--build-property", fmt.Sprintf("build.f_cpu=%dL", m.CpuMHz*1000000))
+	}
+	var defines []string
+	for _, d := range m.Build.Defines {
+		defines = append(defines, "-D"+d)
+	}
+	for _, p := range []struct {
+		prop  string
+		flags []string
+	}{
+		{"compiler.c.extra_flags", append(m.Build.CFlagList(), defines...)},
+		{"compiler.cpp.extra_flags", append(m.Build.CxxFlagList(), defines...)},
+		{"compiler.c.elf.extra_flags", m.Build.LdFlags},
+	} {
+		if len(p.flags) > 0 {
+			args = append(args, "--build-property", p.prop+"="+strings.Join(p.flags, " "))
+		}
+	}
+	if opts.CoreCache != "" {
+		args = append(args, "--build-cache-path", opts.CoreCache)
+	}
 	if opts.Verbose {
 		args = append(args, "--verbose")
 	}
@@ -351,6 +501,57 @@ func compileArduinoCLI(
 	sp.Stop(true, fmt.Sprintf("firmware written to %s", buildCacheDir))
 	return nil
 }
+// transpileShared writes each shared workspace package into the sketch as
+// <pkg>_<file>.cpp, plus <pkg>.h with its declarations for the importers.
+func transpileShared(
+	transpiler *core.Transpiler,
+	result *Result,
+	shared []manifest.SharedPackage,
+	localPkgs []string,
+	sketchDir, board string,
+	m *manifest.Manifest,
+	libsDir string,
+	pkgNames []string,
+	preludeDir string,
+) error {
+	for _, pkg := range shared {
+		var headers []string
+		for _, goFile := range pkg.Files {
+			base := pkg.Name + "_" + strings.TrimSuffix(filepath.Base(goFile), ".go")
+			header := base + ".h"
+			tr, err := transpiler.Transpile(core.TranspileRequest{
+				InputFile:     goFile,
+				OutputFile:    filepath.Join(sketchDir, base+".cpp"),
+				Board:         board,
+				CpuMHz:        m.CpuMHz,
+				ProgmemMin:    m.Build.ProgmemMin,
+				LibsDir:       libsDir,
+				PkgNames:      pkgNames,
+				LocalPackages: localPkgs,
+				Header:        filepath.Join(sketchDir, header),
+				PreludeDir:    preludeDir,
+			})
+			if err != nil {
+				return fmt.Errorf("shared package %s: %w", pkg.Name, err)
+			}
+			headers = append(headers, header)
+			result.CppFiles = append(result.CppFiles, tr.OutputFile)
+			result.Warnings = append(result.Warnings, tr.Warnings...)
+		}
+
+		var sb strings.Builder
+		sb.WriteString("// Auto-generated by tsuki — do not edit.\n#pragma once\n")
+		for _, h := range headers {
+			fmt.Fprintf(&sb, "#include \"%s\"\n", h)
+		}
+		if err := os.WriteFile(filepath.Join(sketchDir, pkg.Name+".h"), []byte(sb.String()), 0644); err != nil {
+			return fmt.Errorf("writing %s.h: %w", pkg.Name, err)
+		}
+		ui.Step("shared", fmt.Sprintf("%s → %s.h (%d file(s))", pkg.Name, pkg.Name, len(pkg.Files)))
+	}
+	return nil
+}
+
 // writeInoStub creates <sketchDir>/<sketchName>.ino — the required entry
 // point for arduino-cli. The stub must NOT #include the generated .cpp files:
 // arduino-cli independently compiles every .cpp in the sketch directory as its
@@ -388,15 +589,32 @@ func newBuildCmd() *cobra.Command {
 	var output string
 	var compile bool
 	var verbose bool
+	var all bool
+	var jobs int
+	var keepAll bool
+	var locked bool
 
 	cmd := &cobra.Command{
 		Use:   "build",
 		Short: "Transpile and optionally compile the project",
 		Example: `  tsuki build
   tsuki build --board esp32
-  tsuki build --compile`,
+  tsuki build --compile
+  tsuki build --all --compile        # every member of the workspace
+  tsuki build --locked               # CI: fail if tsuki.lock would change`,
 		RunE: func(cmd *cobra.Command, args []string) error {
 			dir := projectDir()
+			if all {
+				ws, err := manifest.FindWorkspace(dir)
+				if err != nil {
+					return err
+				}
+				if ws == nil {
+					return fmt.Errorf("--all needs a %s in this directory or above", manifest.WorkspaceFileName)
+				}
+				return buildAll(ws, compile, verbose, locked, jobs)
+			}
+
 			m, err := manifest.Load(dir)
 			if err != nil {
 				return err
@@ -412,6 +630,11 @@ func newBuildCmd() *cobra.Command {
 				FlashBinary: cfg.FlashBinary,
 				Backend:     m.Backend,
 				SourceMap:   m.Build.SourceMap,
+				KeepAll:     keepAll,
+				Locked:      locked,
+			}
+			if err := joinWorkspace(dir, m, &opts); err != nil {
+				return err
 			}
 
 			res, err := Run(dir, m, opts)
@@ -430,6 +653,10 @@ func newBuildCmd() *cobra.Command {
 	cmd.Flags().StringVarP(&output, "out", "o", "", "output directory")
 	cmd.Flags().BoolVarP(&compile, "compile", "c", false, "compile to firmware after transpile")
 	cmd.Flags().BoolVarP(&verbose, "verbose", "v", false, "verbose output")
+	cmd.Flags().BoolVar(&keepAll, "keep-all", false, "emit unreferenced functions and types too")
+	cmd.Flags().BoolVar(&all, "all", false, "build every member of the workspace")
+	cmd.Flags().BoolVar(&locked, "locked", false, "fail if package resolution differs from tsuki.lock")
+	cmd.Flags().IntVarP(&jobs, "jobs", "j", 0, "members built in parallel with --all (default: workspace jobs or CPU count)")
 	return cmd
 }
 
@@ -538,16 +765,47 @@ func boardFQBN(id string) (string, error) {
 		"mega":     "arduino:avr:mega",
 		"leonardo": "arduino:avr:leonardo",
 		"micro":    "arduino:avr:micro",
+		"nano_every": "arduino:megaavr:nona4809",
+		"attiny1614": "megaTinyCore:megaavr:atxy4:chip=1614",
+		"attiny3216": "megaTinyCore:megaavr:atxy6:chip=3216",
 		"due":      "arduino:sam:arduino_due_x",
 		"mkr1000":  "arduino:samd:mkr1000",
 		"esp32":    "esp32:esp32:esp32",
 		"esp8266":  "esp8266:esp8266:generic",
 		"pico":     "rp2040:rp2040:rpipico",
 		"teensy40": "teensy:avr:teensy40",
+		"teensy41": "teensy:avr:teensy41",
+		"teensylc": "teensy:avr:teensyLC",
+
+		"bluepill":       "STMicroelectronics:stm32:GenF1:pnum=BLUEPILL_F103C8",
+		"blackpill_f411": "STMicroelectronics:stm32:GenF4:pnum=BLACKPILL_F411CE",
+		"nucleo_f401re":  "STMicroelectronics:stm32:Nucleo_64:pnum=NUCLEO_F401RE",
 	}
 	fqbn, ok := table[strings.ToLower(id)]
 	if !ok {
 		return "", fmt.Errorf("unknown board")
 	}
 	return fqbn, nil
-}
\ No newline at end of file
+}
+// budgetArgs turns the manifest's size budget into tsuki-flash flags.
+func budgetArgs(b *manifest.Budget) []string {
+	if b == nil {
+		return nil
+	}
+	var args []string
+	if b.Flash != "" {
+		args = append(args, "--budget-flash", b.Flash)
+	}
+	if b.Ram != "" {
+		args = append(args, "--budget-ram", b.Ram)
+	}
+	names := make([]string, 0, len(b.PerPackage))
+	for name := range b.PerPackage {
+		names = append(names, name)
+	}
+	sort.Strings(names)
+	for _, name := range names {
+		args = append(args, "--budget-module", name+"="+b.PerPackage[name])
+	}
+	return args
+}
@@ -0,0 +1,34 @@
+package cli
+
+import (
+	"fmt"
+
+	"github.com/spf13/cobra"
+	"github.com/tsuki/cli/internal/core"
+	"github.com/tsuki/cli/internal/pkgmgr"
+)
+
+func newDocCmd() *cobra.Command {
+	return &cobra.Command{
+		Use:   "doc [package]",
+		Short: "Show the functions and constants a package supports",
+		Long: `Without an argument, lists the built-in and installed packages.
+With a package name, lists its functions, constants and types with the C++
+each one is translated to.`,
+		Example: `  tsuki doc
+  tsuki doc arduino
+  tsuki doc ws2812`,
+		Args: cobra.MaximumNArgs(1),
+		RunE: func(cmd *cobra.Command, args []string) error {
+			transpiler := core.New(cfg.CoreBinary, cfg.Verbose)
+			if !transpiler.Installed() {
+				return fmt.Errorf("tsuki-core not found — install it or set core_binary in config")
+			}
+			pkg := ""
+			if len(args) == 1 {
+				pkg = args[0]
+			}
+			return transpiler.Doc(pkg, pkgmgr.LibsDir())
+		},
+	}
+}
@@ -0,0 +1,116 @@
+package cli
+
+import (
+	"fmt"
+	"os"
+	"path/filepath"
+	"sort"
+	"strings"
+
+	"github.com/spf13/cobra"
+	"github.com/tsuki/cli/internal/manifest"
+	"github.com/tsuki/cli/internal/scaffold"
+	"github.com/tsuki/cli/internal/ui"
+)
+
+// newNewCmd is the non-interactive counterpart of `tsuki init`: every choice
+// comes from flags, so it suits scripts and CI.
+func newNewCmd() *cobra.Command {
+	var (
+		flagBoard    string
+		flagTemplate string
+		flagList     bool
+	)
+
+	cmd := &cobra.Command{
+		Use:   "new <name>",
+		Short: "Create a project from a template",
+		Long: `Creates <name>/ with a project manifest, src/main.go from the template,
+an empty build/ directory and a .gitignore. The example code is adapted to
+the board (LED and analog pins, baud rate).
+
+Templates are built in (blink, sensor, wifi); each directory under the
+templates dir (tsuki config set templates_dir <dir>) adds one or replaces the
+built-in one of the same name.`,
+		Example: `  tsuki new blinky
+  tsuki new weather --board esp32 --template wifi
+  tsuki new --list`,
+		Args: func(cmd *cobra.Command, args []string) error {
+			if flagList {
+				return cobra.NoArgs(cmd, args)
+			}
+			return cobra.ExactArgs(1)(cmd, args)
+		},
+		RunE: func(cmd *cobra.Command, args []string) error {
+			templatesDir := cfg.ResolvedTemplatesDir()
+			if flagList {
+				for _, name := range scaffold.Names(templatesDir) {
+					fmt.Println(name)
+				}
+				return nil
+			}
+			board := flagBoard
+			if board == "" {
+				board = cfg.DefaultBoard
+			}
+			return newProject(args[0], board, flagTemplate, templatesDir)
+		},
+	}
+
+	cmd.Flags().StringVarP(&flagBoard, "board", "b", "", "target board (default: default_board from config)")
+	cmd.Flags().StringVarP(&flagTemplate, "template", "t", "blink", "project template")
+	cmd.Flags().BoolVar(&flagList, "list", false, "list the available templates")
+	return cmd
+}
+
+func newProject(name, board, tmpl, templatesDir string) error {
+	name = sanitizeName(name)
+	dir := filepath.Join(projectDir(), name)
+	if entries, err := os.ReadDir(dir); err == nil && len(entries) > 0 {
+		return fmt.Errorf("%s already exists and is not empty", dir)
+	}
+
+	data := scaffold.DataFor(name, board)
+	for _, b := range boardChoices {
+		if strings.EqualFold(b.id, board) {
+			data.BoardName = b.name
+		}
+	}
+	files, err := scaffold.Render(tmpl, templatesDir, data)
+	if err != nil {
+		return err
+	}
+
+	for _, sub := range []string{"src", "build"} {
+		if err := os.MkdirAll(filepath.Join(dir, sub), 0755); err != nil {
+			return err
+		}
+	}
+	if err := manifest.Default(name, board).Save(dir); err != nil {
+		return err
+	}
+	if _, ok := files[".gitignore"]; !ok {
+		files[".gitignore"] = []byte("build/\n*.hex\n*.bin\n*.uf2\n.tsuki-cache.json\n")
+	}
+
+	paths := make([]string, 0, len(files))
+	for p := range files {
+		paths = append(paths, p)
+	}
+	sort.Strings(paths)
+	for _, p := range paths {
+		dest := filepath.Join(dir, filepath.FromSlash(p))
+		if err := os.MkdirAll(filepath.Dir(dest), 0755); err != nil {
+			return err
+		}
+		if err := os.WriteFile(dest, files[p], 0644); err != nil {
+			return err
+		}
+		ui.Step("create", filepath.Join(name, filepath.FromSlash(p)))
+	}
+
+	ui.Success(fmt.Sprintf("created %s (%s, template %s)", name, data.BoardName, tmpl))
+	printStep("cd", name)
+	printStep("tsuki build", "--compile")
+	return nil
+}
@@ -94,55 +94,24 @@ func newPkgInstallCmd() *cobra.Command {
 				{Key: "path",        Value: pkg.Path},
 			}, false)
 
-			// Suggest adding to project manifest
+			// Pin it in the project's tsuki.lock when the project uses it;
+			// otherwise suggest adding it.
 			fmt.Println()
-			ui.Info(fmt.Sprintf("Add to your project: tsuki pkg add %s", pkg.Name))
+			if projDir, m, err := manifest.Find(projectDir()); err == nil && m.HasPackage(pkg.Name) {
+				if err := pkgmgr.UpdateLock(projDir, pkg.Name, pkg.Version); err != nil {
+					return fmt.Errorf("updating %s: %w", pkgmgr.LockFileName, err)
+				}
+				ui.Info(fmt.Sprintf("Locked %s@%s in %s", pkg.Name, pkg.Version, pkgmgr.LockFileName))
+			} else {
+				ui.Info(fmt.Sprintf("Add to your project: tsuki pkg add %s", pkg.Name))
+			}
 
 			// If arduino_lib is set, auto-install it via tsuki-flash or arduino-cli.
 			if pkg.ArduinoLib != "" {
 				fmt.Println()
 				ui.Warn(fmt.Sprintf("This package requires the '%s' Arduino library.", pkg.ArduinoLib))
 
-				flashBin := cfg.FlashBinary
-				if flashBin == "" {
-					flashBin = "tsuki-flash"
-				}
-
-				// Use tsuki-flash when: backend is explicitly set, OR the binary is on PATH.
-				useTsukiFlash := cfg.Backend == "tsuki-flash"
-				if !useTsukiFlash {
-					if _, err := exec.LookPath(flashBin); err == nil {
-						useTsukiFlash = true
-					}
-				}
-
-				if useTsukiFlash {
-					ui.Info(fmt.Sprintf("Installing '%s' via tsuki-flash lib install…", pkg.ArduinoLib))
-					libCmd := exec.Command(flashBin, "lib", "install", pkg.ArduinoLib)
-					libCmd.Stdout = os.Stdout
-					libCmd.Stderr = os.Stderr
-					if libErr := libCmd.Run(); libErr != nil {
-						ui.Warn("Auto-install failed. Run manually:")
-						ui.Info(fmt.Sprintf("  tsuki-flash lib install \"%s\"", pkg.ArduinoLib))
-					} else {
-						ui.Success(fmt.Sprintf("'%s' installed successfully.", pkg.ArduinoLib))
-					}
-				} else {
-					arduinoCLI := cfg.ArduinoCLI
-					if arduinoCLI == "" {
-						arduinoCLI = "arduino-cli"
-					}
-					ui.Info(fmt.Sprintf("Installing '%s' via arduino-cli…", pkg.ArduinoLib))
-					libCmd := exec.Command(arduinoCLI, "lib", "install", pkg.ArduinoLib)
-					libCmd.Stdout = os.Stdout
-					libCmd.Stderr = os.Stderr
-					if libErr := libCmd.Run(); libErr != nil {
-						ui.Warn("Auto-install failed. Run manually:")
-						ui.Info(fmt.Sprintf("  arduino-cli lib install \"%s\"", pkg.ArduinoLib))
-					} else {
-						ui.Success(fmt.Sprintf("'%s' installed successfully.", pkg.ArduinoLib))
-					}
-				}
+				_ = installArduinoLib(pkg.ArduinoLib, cfg.Backend, cfg.FlashBinary, cfg.ArduinoCLI)
 			}
 
 			return nil
@@ -197,6 +166,9 @@ This records the dependency so 'tsuki build' loads it automatically.`,
 			if err := m.Save(projDir); err != nil {
 				return fmt.Errorf("saving manifest: %w", err)
 			}
+			if err := pkgmgr.UpdateLock(projDir, name, installedVer); err != nil {
+				return fmt.Errorf("updating %s: %w", pkgmgr.LockFileName, err)
+			}
 
 			ui.Success(fmt.Sprintf("Added %s@%s to goduino.json", name, ver))
 			ui.Info("Run 'tsuki build' to transpile with this package")
@@ -352,4 +324,51 @@ func newPkgInfoCmd() *cobra.Command {
 		},
 	}
 	return cmd
-}
\ No newline at end of file
+}
+// installArduinoLib installs an Arduino library through tsuki-flash when that
+// is the backend or on PATH, else through arduino-cli. name may pin a version
+// as `Name@1.2.3`. A failure is reported with the command to run by hand and
+// returned.
+func installArduinoLib(name, backend, flashBin, arduinoCLI string) error {
+	if flashBin == "" {
+		flashBin = "tsuki-flash"
+	}
+
+	// Use tsuki-flash when: backend is explicitly set, OR the binary is on PATH.
+	useTsukiFlash := strings.HasPrefix(backend, "tsuki-flash")
+	if !useTsukiFlash {
+		if _, err := exec.LookPath(flashBin); err == nil {
+			useTsukiFlash = true
+		}
+	}
+
+	tool := flashBin
+	if !useTsukiFlash {
+		tool = arduinoCLI
+		if tool == "" {
+			tool = "arduino-cli"
+		}
+	}
+
+	// arduino-cli takes the pin as written; tsuki-flash as --version.
+	args := []string{"lib", "install", name}
+	if lib, version, ok := strings.Cut(name, "@"); ok && useTsukiFlash {
+		args = []string{"lib", "install", lib, "--version", version}
+	}
+
+	ui.Info(fmt.Sprintf("Installing '%s' via %s lib install…", name, tool))
+	libCmd := exec.Command(tool, args...)
+	libCmd.Stdout = os.Stdout
+	libCmd.Stderr = os.Stderr
+	if err := libCmd.Run(); err != nil {
+		ui.Warn("Auto-install failed. Run manually:")
+		hint := fmt.Sprintf("  %s lib install \"%s\"", tool, args[2])
+		if len(args) > 3 {
+			hint += " " + strings.Join(args[3:], " ")
+		}
+		ui.Info(hint)
+		return fmt.Errorf("installing Arduino library %q: %w", name, err)
+	}
+	ui.Success(fmt.Sprintf("'%s' installed successfully.", name))
+	return nil
+}
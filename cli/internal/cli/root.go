@@ -66,14 +66,18 @@ func init() {
 
 	rootCmd.AddCommand(
 		newInitCmd(),
+		newNewCmd(),
 		newBuildCmd(),
 		newUploadCmd(),
 		newCheckCmd(),
+		newTestCmd(),
+		newSimCmd(),
 		newConfigCmd(),
 		newBoardsCmd(),
 		newCleanCmd(),
 		newVersionCmd(),
 		newPkgCmd(),
+		newDocCmd(),
 	)
 }
 
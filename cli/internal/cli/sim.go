@@ -0,0 +1,57 @@
+package cli
+
+import (
+	"fmt"
+	"path/filepath"
+
+	"github.com/spf13/cobra"
+	"github.com/tsuki/cli/internal/core"
+	"github.com/tsuki/cli/internal/manifest"
+)
+
+func newSimCmd() *cobra.Command {
+	var req core.SimRequest
+
+	cmd := &cobra.Command{
+		Use:   "sim [file.go]",
+		Short: "Run the sketch on this machine with a simulated board",
+		Long: `Builds the sketch (default: src/main.go of the project) for this machine
+with g++ and runs setup() and loop() for --ms of simulated time. Time only
+advances on delay(). Every pin change and all Serial output are printed, as
+text or, with --json, as one JSON object per line:
+
+  {"t_us":500000,"pin":13,"digital":0}
+  {"t_us":500000,"serial":"ready"}
+
+--trace writes the events to a file as one JSON document. Commit one as a
+golden trace and have CI run with --expect, which fails when the events of
+the run differ from it.`,
+		Example: `  tsuki sim
+  tsuki sim --ms 60000 --json > trace.jsonl
+  tsuki sim --ms 5000 --trace tests/blink.json
+  tsuki sim --ms 5000 --expect tests/blink.json`,
+		Args: cobra.MaximumNArgs(1),
+		RunE: func(cmd *cobra.Command, args []string) error {
+			if len(args) == 1 {
+				req.File = args[0]
+			} else {
+				root, _, err := manifest.Find(projectDir())
+				if err != nil {
+					return err
+				}
+				req.File = filepath.Join(root, "src", "main.go")
+			}
+			transpiler := core.New(cfg.CoreBinary, cfg.Verbose)
+			if !transpiler.Installed() {
+				return fmt.Errorf("tsuki-core not found — install it or set core_binary in config")
+			}
+			return transpiler.Sim(req)
+		},
+	}
+
+	cmd.Flags().IntVar(&req.MS, "ms", 10000, "simulated time to run for, in milliseconds")
+	cmd.Flags().BoolVar(&req.JSON, "json", false, "print the trace as JSON lines")
+	cmd.Flags().StringVar(&req.Trace, "trace", "", "write the events to this file as a JSON trace")
+	cmd.Flags().StringVar(&req.Expect, "expect", "", "fail when the events differ from this golden trace")
+	return cmd
+}
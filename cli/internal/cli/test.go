@@ -0,0 +1,39 @@
+package cli
+
+import (
+	"fmt"
+	"path/filepath"
+
+	"github.com/spf13/cobra"
+	"github.com/tsuki/cli/internal/core"
+	"github.com/tsuki/cli/internal/manifest"
+)
+
+func newTestCmd() *cobra.Command {
+	var run string
+
+	cmd := &cobra.Command{
+		Use:   "test",
+		Short: "Run the project's Go tests on this machine",
+		Long: `Builds the TestXxx(t *testing.T) functions of src/*_test.go, together with
+the rest of src/, for this machine with g++ and runs them. Arduino calls are
+simulated: pins keep the last value written, millis() advances only on
+delay(), and Serial prints to the terminal.`,
+		Example: `  tsuki test
+  tsuki test --run Clamp -v`,
+		RunE: func(cmd *cobra.Command, args []string) error {
+			root, _, err := manifest.Find(projectDir())
+			if err != nil {
+				return err
+			}
+			transpiler := core.New(cfg.CoreBinary, cfg.Verbose)
+			if !transpiler.Installed() {
+				return fmt.Errorf("tsuki-core not found — install it or set core_binary in config")
+			}
+			return transpiler.Test(filepath.Join(root, "src"), run, cfg.Verbose)
+		},
+	}
+
+	cmd.Flags().StringVar(&run, "run", "", "only run tests whose name contains this")
+	return cmd
+}
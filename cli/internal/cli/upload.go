@@ -13,6 +13,8 @@ func newUploadCmd() *cobra.Command {
 		board    string
 		buildDir string
 		backend  string
+		force    bool
+		verify   bool
 	)
 
 	cmd := &cobra.Command{
@@ -20,7 +22,8 @@ func newUploadCmd() *cobra.Command {
 		Short: "Upload compiled firmware to a connected board",
 		Example: `  tsuki upload
   tsuki upload --port /dev/ttyUSB0
-  tsuki upload --port COM3 --board uno`,
+  tsuki upload --port COM3 --board uno
+  tsuki upload --verify`,
 		RunE: func(cmd *cobra.Command, args []string) error {
 			dir := projectDir()
 			_, m, err := manifest.Find(dir)
@@ -47,6 +50,8 @@ func newUploadCmd() *cobra.Command {
 				ArduinoCLI:  cfg.ArduinoCLI,
 				FlashBinary: cfg.FlashBinary,
 				Backend:     effectiveBackend,
+				Force:       force,
+				Verify:      verify,
 				Verbose:     cfg.Verbose,
 			})
 		},
@@ -56,5 +61,7 @@ func newUploadCmd() *cobra.Command {
 	cmd.Flags().StringVarP(&board, "board", "b", "", "target board (overrides manifest)")
 	cmd.Flags().StringVar(&buildDir, "build-dir", "", "directory with compiled firmware")
 	cmd.Flags().StringVar(&backend, "backend", "", "override backend: tsuki-flash | tsuki-flash+cores | arduino-cli")
+	cmd.Flags().BoolVar(&force, "force", false, "flash even if the firmware was built for a different board")
+	cmd.Flags().BoolVar(&verify, "verify", false, "read the flash back after uploading and compare")
 	return cmd
 }
\ No newline at end of file
@@ -0,0 +1,199 @@
+package cli
+
+import (
+	"bufio"
+	"bytes"
+	"encoding/json"
+	"fmt"
+	"os"
+	"path/filepath"
+	"strconv"
+	"strings"
+
+	"github.com/tsuki/cli/internal/config"
+	"github.com/tsuki/cli/internal/ui"
+)
+
+// ── user board files ──────────────────────────────────────────────────────────
+//
+// The same files tsuki-flash and tsuki-core merge into their catalogs: one
+// board at the top level, or a list (`[[board]]` in TOML, an array or
+// {"board": [...]} in JSON). Only the fields `boards list` shows are read.
+
+type userBoard struct {
+	ID      string `json:"id"`
+	Name    string `json:"name"`
+	FQBN    string `json:"fqbn"`
+	FlashKB int    `json:"flash_kb"`
+	RAMKB   int    `json:"ram_kb"`
+}
+
+// allBoards returns the built-in catalog with the user's boards merged in;
+// a user board with a built-in ID replaces it.
+func allBoards() []boardInfo {
+	boards := append([]boardInfo(nil), boardCatalog...)
+	for _, u := range userBoards() {
+		b := boardInfo{u.ID, u.Name, u.FlashKB, u.RAMKB, u.FQBN}
+		replaced := false
+		for i := range boards {
+			if strings.EqualFold(boards[i].ID, b.ID) {
+				boards[i] = b
+				replaced = true
+				break
+			}
+		}
+		if !replaced {
+			boards = append(boards, b)
+		}
+	}
+	return boards
+}
+
+func userBoards() []userBoard {
+	dir, err := config.BoardsDir()
+	if err != nil {
+		return nil
+	}
+	entries, err := os.ReadDir(dir) // sorted by name
+	if err != nil {
+		return nil
+	}
+
+	var boards []userBoard
+	for _, e := range entries {
+		path := filepath.Join(dir, e.Name())
+		var found []userBoard
+		switch filepath.Ext(e.Name()) {
+		case ".json":
+			found, err = readJSONBoards(path)
+		case ".toml":
+			found, err = readTOMLBoards(path)
+		default:
+			continue
+		}
+		if err == nil {
+			for _, b := range found {
+				if b.ID == "" {
+					err = fmt.Errorf("a board has no id")
+				}
+			}
+		}
+		if err != nil {
+			ui.Warn(fmt.Sprintf("skipping %s: %v", path, err))
+			continue
+		}
+		boards = append(boards, found...)
+	}
+	return boards
+}
+
+func readJSONBoards(path string) ([]userBoard, error) {
+	data, err := os.ReadFile(path)
+	if err != nil {
+		return nil, err
+	}
+	data = bytes.TrimSpace(data)
+	if bytes.HasPrefix(data, []byte("[")) {
+		var list []userBoard
+		err := json.Unmarshal(data, &list)
+		return list, err
+	}
+	var wrapped struct {
+		Board []userBoard `json:"board"`
+	}
+	if err := json.Unmarshal(data, &wrapped); err != nil {
+		return nil, err
+	}
+	if wrapped.Board != nil {
+		return wrapped.Board, nil
+	}
+	var one userBoard
+	err = json.Unmarshal(data, &one)
+	return []userBoard{one}, err
+}
+
+// readTOMLBoards reads the flat `key = value` lines of a board file. The
+// toolchain table and define lists are left to tsuki-flash.
+func readTOMLBoards(path string) ([]userBoard, error) {
+	f, err := os.Open(path)
+	if err != nil {
+		return nil, err
+	}
+	defer f.Close()
+
+	var boards []userBoard
+	cur := &userBoard{}
+	inBoard := true // top-level keys describe a single board
+	listed := false // seen a [[board]] header
+	sc := bufio.NewScanner(f)
+	for lineNo := 1; sc.Scan(); lineNo++ {
+		line := strings.TrimSpace(sc.Text())
+		switch {
+		case line == "" || strings.HasPrefix(line, "#"):
+			continue
+		case line == "[[board]]":
+			if listed || cur.ID != "" {
+				boards = append(boards, *cur)
+			}
+			cur = &userBoard{}
+			inBoard, listed = true, true
+			continue
+		case strings.HasPrefix(line, "["):
+			inBoard = false
+			continue
+		}
+
+		key, value, ok := strings.Cut(line, "=")
+		if !ok || !inBoard {
+			continue
+		}
+		key = strings.TrimSpace(key)
+		value = strings.TrimSpace(value)
+
+		switch key {
+		case "id", "name", "fqbn":
+			s, err := strconv.Unquote(tomlString(value))
+			if err != nil {
+				return nil, fmt.Errorf("line %d: %s must be a string", lineNo, key)
+			}
+			switch key {
+			case "id":
+				cur.ID = s
+			case "name":
+				cur.Name = s
+			case "fqbn":
+				cur.FQBN = s
+			}
+		case "flash_kb", "ram_kb":
+			n, err := strconv.Atoi(strings.TrimSpace(strings.SplitN(value, "#", 2)[0]))
+			if err != nil {
+				return nil, fmt.Errorf("line %d: %s must be a number", lineNo, key)
+			}
+			if key == "flash_kb" {
+				cur.FlashKB = n
+			} else {
+				cur.RAMKB = n
+			}
+		}
+	}
+	if err := sc.Err(); err != nil {
+		return nil, err
+	}
+	return append(boards, *cur), nil
+}
+
+// tomlString cuts a quoted TOML string off any trailing comment.
+func tomlString(value string) string {
+	if len(value) < 2 || value[0] != '"' {
+		return value
+	}
+	for i := 1; i < len(value); i++ {
+		switch value[i] {
+		case '\\':
+			i++
+		case '"':
+			return value[:i+1]
+		}
+	}
+	return value
+}
@@ -0,0 +1,118 @@
+// ─────────────────────────────────────────────────────────────────────────────
+//  tsuki :: workspace  —  several firmwares in one repository
+//
+//  A tsuki_workspace.json above the projects lists them as members. Building
+//  a member (from its own directory or through `tsuki build --all`) applies
+//  its workspace profile and board, transpiles the shared packages into its
+//  sketch, and points arduino-cli at a core cache common to all members.
+// ─────────────────────────────────────────────────────────────────────────────
+
+package cli
+
+import (
+	"fmt"
+	"os"
+	"os/exec"
+	"path/filepath"
+	"runtime"
+	"strings"
+	"sync"
+	"time"
+
+	"github.com/tsuki/cli/internal/manifest"
+	"github.com/tsuki/cli/internal/ui"
+)
+
+// joinWorkspace applies the enclosing workspace, if any, to the project in
+// dir: its member overrides on m, and its shared packages and core cache on
+// opts. Projects outside a workspace, or not listed in it, are left alone.
+func joinWorkspace(dir string, m *manifest.Manifest, opts *Options) error {
+	ws, err := manifest.FindWorkspace(dir)
+	if err != nil || ws == nil {
+		return err
+	}
+	member, ok := ws.MemberAt(dir)
+	if !ok {
+		return nil
+	}
+	ws.Apply(m, member)
+	opts.Backend = m.Backend
+	opts.SourceMap = opts.SourceMap || m.Build.SourceMap
+	opts.CoreCache = filepath.Join(ws.Dir, "build", ".core-cache")
+	opts.Shared, err = ws.SharedPackages()
+	return err
+}
+
+// buildAll builds every member, `jobs` at a time. Each member runs as its own
+// `tsuki build` process so outputs don't interleave; a member's output is
+// printed as one block when it finishes.
+func buildAll(ws *manifest.Workspace, compile, verbose, locked bool, jobs int) error {
+	exe, err := os.Executable()
+	if err != nil {
+		return fmt.Errorf("locating tsuki executable: %w", err)
+	}
+	if jobs <= 0 {
+		jobs = ws.Jobs
+	}
+	if jobs <= 0 {
+		jobs = runtime.NumCPU()
+	}
+	args := []string{"build"}
+	if compile {
+		args = append(args, "--compile")
+	}
+	if verbose {
+		args = append(args, "--verbose")
+	}
+	if locked {
+		args = append(args, "--locked")
+	}
+
+	ui.SectionTitle(fmt.Sprintf("Workspace  [%d members]  [jobs: %d]", len(ws.Members), jobs))
+
+	type outcome struct {
+		err  error
+		took time.Duration
+	}
+	results := make([]outcome, len(ws.Members))
+	sem := make(chan struct{}, jobs)
+	var wg sync.WaitGroup
+	var mu sync.Mutex
+	for i, member := range ws.Members {
+		wg.Add(1)
+		go func(i int, member manifest.Member) {
+			defer wg.Done()
+			sem <- struct{}{}
+			defer func() { <-sem }()
+
+			start := time.Now()
+			cmd := exec.Command(exe, args...)
+			cmd.Dir = ws.MemberDir(member)
+			out, err := cmd.CombinedOutput()
+			results[i] = outcome{err: err, took: time.Since(start)}
+
+			mu.Lock()
+			defer mu.Unlock()
+			ui.SectionTitle(member.Path)
+			fmt.Print(strings.TrimRight(string(out), "\n") + "\n")
+		}(i, member)
+	}
+	wg.Wait()
+
+	ui.SectionTitle("Summary")
+	failed := 0
+	for i, member := range ws.Members {
+		r := results[i]
+		line := fmt.Sprintf("%-24s %5.1fs", member.Path, r.took.Seconds())
+		if r.err != nil {
+			failed++
+			ui.Fail(line)
+		} else {
+			ui.Success(line)
+		}
+	}
+	if failed > 0 {
+		return fmt.Errorf("%d of %d workspace members failed to build", failed, len(ws.Members))
+	}
+	return nil
+}
@@ -63,6 +63,12 @@ type Config struct {
 
 	// VerifySignatures controls whether package signatures are verified on install.
 	VerifySignatures bool `json:"verify_signatures" comment:"verify package signatures on install"`
+
+	// ── Project templates ───────────────────────────────────────────────────
+
+	// TemplatesDir holds project templates for `tsuki new`, one directory
+	// each, next to the built-in ones.
+	TemplatesDir string `json:"templates_dir,omitempty" comment:"directory of project templates for tsuki new (leave empty for default)"`
 }
 
 // Default returns a Config with sensible defaults.
@@ -150,6 +156,17 @@ func (c *Config) ResolvedKeysDir() string {
 	return defaultKeysDir()
 }
 
+// ResolvedTemplatesDir returns the effective project-templates directory.
+func (c *Config) ResolvedTemplatesDir() string {
+	if c.TemplatesDir != "" {
+		return c.TemplatesDir
+	}
+	if env := os.Getenv("tsuki_TEMPLATES"); env != "" {
+		return env
+	}
+	return defaultTemplatesDir()
+}
+
 // ResolvedKeysIndexURL returns the effective global key-index URL.
 func (c *Config) ResolvedKeysIndexURL() string {
 	if c.KeysIndexURL != "" {
@@ -187,6 +204,18 @@ func defaultKeysDir() string {
 	return filepath.Join(home, ".local", "share", "tsuki", "keys")
 }
 
+func defaultTemplatesDir() string {
+	if runtime.GOOS == "windows" {
+		base := os.Getenv("APPDATA")
+		if base == "" {
+			base = filepath.Join(os.Getenv("USERPROFILE"), "AppData", "Roaming")
+		}
+		return filepath.Join(base, "tsuki", "templates")
+	}
+	home, _ := os.UserHomeDir()
+	return filepath.Join(home, ".local", "share", "tsuki", "templates")
+}
+
 // ── Config file I/O ───────────────────────────────────────────────────────────
 
 func configPath() (string, error) {
@@ -203,6 +232,16 @@ func configPath() (string, error) {
 	return filepath.Join(base, "tsuki", "config.json"), nil
 }
 
+// BoardsDir is where users drop their own board definitions (*.toml,
+// *.json), next to config.json. tsuki-flash reads the same directory.
+func BoardsDir() (string, error) {
+	path, err := configPath()
+	if err != nil {
+		return "", err
+	}
+	return filepath.Join(filepath.Dir(path), "boards"), nil
+}
+
 // Load reads the config from disk. Returns defaults if the file doesn't exist.
 // After loading, it migrates a legacy registry_url into registry_urls if needed.
 func Load() (*Config, error) {
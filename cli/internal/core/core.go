@@ -10,6 +10,7 @@ import (
 	"fmt"
 	"os"
 	"os/exec"
+	"strconv"
 	"strings"
 
 	"github.com/tsuki/cli/internal/ui"
@@ -36,6 +37,21 @@ type TranspileRequest struct {
 	OutputFile string
 	Board      string
 	SourceMap  bool
+	// Optional: CPU clock override in MHz (manifest cpu_mhz).
+	CpuMHz     int
+	// Optional: PROGMEM threshold in bytes (manifest build.progmem_min).
+	ProgmemMin int
+	// Optional: workspace packages transpiled into the same sketch.
+	// Passed as --local-packages proto,util to tsuki-core.
+	LocalPackages []string
+	// Optional: also write the file's declarations here (shared packages).
+	Header string
+	// Optional: directory of prelude overrides (manifest build.prelude_dir).
+	PreludeDir string
+	// Optional: keep main()'s endless loop inside setup().
+	NoSplitMain bool
+	// Optional: disable dead code elimination.
+	KeepAll bool
 	// Optional: root directory where external libs are installed.
 	// Passed as --libs-dir to tsuki-core.
 	LibsDir  string
@@ -48,6 +64,11 @@ type TranspileRequest struct {
 type TranspileResult struct {
 	OutputFile string
 	Warnings   []string
+	// Arduino libraries the sketch's imports compile against (`lib  <name>`
+	// lines from tsuki-core).
+	ArduinoLibs []string
+	// Vendored header directories of the loaded packages (`inc  <dir>`).
+	IncludeDirs []string
 }
 
 // Transpile transpiles a single .go file to C++.
@@ -57,6 +78,27 @@ func (t *Transpiler) Transpile(req TranspileRequest) (*TranspileResult, error) {
 	if req.SourceMap {
 		args = append(args, "--source-map")
 	}
+	if req.CpuMHz > 0 {
+		args = append(args, "--cpu-mhz", strconv.Itoa(req.CpuMHz))
+	}
+	if req.ProgmemMin > 0 {
+		args = append(args, "--progmem-min", strconv.Itoa(req.ProgmemMin))
+	}
+	if len(req.LocalPackages) > 0 {
+		args = append(args, "--local-packages", strings.Join(req.LocalPackages, ","))
+	}
+	if req.Header != "" {
+		args = append(args, "--header", req.Header)
+	}
+	if req.PreludeDir != "" {
+		args = append(args, "--prelude-dir", req.PreludeDir)
+	}
+	if req.NoSplitMain {
+		args = append(args, "--no-split-main")
+	}
+	if req.KeepAll {
+		args = append(args, "--keep-all")
+	}
 
 	// Pass library info to core
 	if req.LibsDir != "" {
@@ -84,8 +126,10 @@ func (t *Transpiler) Transpile(req TranspileRequest) (*TranspileResult, error) {
 	}
 
 	return &TranspileResult{
-		OutputFile: req.OutputFile,
-		Warnings:   parseWarnings(stderr.String()),
+		OutputFile:  req.OutputFile,
+		Warnings:    parseWarnings(stderr.String()),
+		ArduinoLibs: parseArduinoLibs(stderr.String()),
+		IncludeDirs: parsePrefixed(stderr.String(), "inc  "),
 	}, nil
 }
 
@@ -116,6 +160,86 @@ func (t *Transpiler) Check(inputFile, board, libsDir string, pkgNames []string)
 	return warnings, errors, nil
 }
 
+// Doc prints what package pkg maps to, or the list of packages when pkg is
+// empty, straight to the terminal.
+func (t *Transpiler) Doc(pkg, libsDir string) error {
+	args := []string{"doc"}
+	if pkg != "" {
+		args = append(args, pkg)
+	}
+	if libsDir != "" {
+		args = append(args, "--libs-dir", libsDir)
+	}
+
+	cmd := exec.Command(t.binary, args...)
+	cmd.Stdout = os.Stdout
+	cmd.Stderr = os.Stderr
+	if err := cmd.Run(); err != nil {
+		return fmt.Errorf("tsuki-core doc: %w", err)
+	}
+	return nil
+}
+
+// Test runs the TestXxx functions of the package in dir on this machine,
+// streaming their output. A non-nil error means a test failed or the tests
+// could not be built.
+func (t *Transpiler) Test(dir, run string, verbose bool) error {
+	args := []string{"test", dir}
+	if run != "" {
+		args = append(args, "--run", run)
+	}
+	if verbose {
+		args = append(args, "-v")
+	}
+
+	cmd := exec.Command(t.binary, args...)
+	cmd.Stdout = os.Stdout
+	cmd.Stderr = os.Stderr
+	if err := cmd.Run(); err != nil {
+		return fmt.Errorf("tests failed")
+	}
+	return nil
+}
+
+// SimRequest bundles the parameters of a `tsuki-core sim` run.
+type SimRequest struct {
+	File string
+	// Simulated time to run for, in milliseconds.
+	MS int
+	// Print the trace as JSON lines instead of text.
+	JSON bool
+	// Optional: write the events to this file as a JSON trace.
+	Trace string
+	// Optional: fail when the events differ from this golden trace.
+	Expect string
+}
+
+// Sim runs a sketch on this machine with a simulated board, streaming the
+// pin and Serial trace.
+func (t *Transpiler) Sim(req SimRequest) error {
+	args := []string{"sim", req.File, "--ms", strconv.Itoa(req.MS)}
+	if req.JSON {
+		args = append(args, "--json")
+	}
+	if req.Trace != "" {
+		args = append(args, "--trace", req.Trace)
+	}
+	if req.Expect != "" {
+		args = append(args, "--expect", req.Expect)
+	}
+
+	cmd := exec.Command(t.binary, args...)
+	cmd.Stdout = os.Stdout
+	cmd.Stderr = os.Stderr
+	if err := cmd.Run(); err != nil {
+		if req.Expect != "" {
+			return fmt.Errorf("simulation failed or its trace differs from %s", req.Expect)
+		}
+		return fmt.Errorf("simulation failed")
+	}
+	return nil
+}
+
 // Version returns the version string of the core binary.
 func (t *Transpiler) Version() (string, error) {
 	out, err := exec.Command(t.binary, "--version").Output()
@@ -131,6 +255,23 @@ func (t *Transpiler) Installed() bool {
 	return err == nil
 }
 
+// parseArduinoLibs collects the `lib  <name>` lines tsuki-core prints after a
+// successful transpile.
+func parseArduinoLibs(output string) []string {
+	return parsePrefixed(output, "lib  ")
+}
+
+// parsePrefixed returns the rest of every output line starting with prefix.
+func parsePrefixed(output, prefix string) []string {
+	var values []string
+	for _, line := range strings.Split(output, "\n") {
+		if value, ok := strings.CutPrefix(strings.TrimRight(line, "\r"), prefix); ok {
+			values = append(values, value)
+		}
+	}
+	return values
+}
+
 // ── Error rendering ───────────────────────────────────────────────────────────
 
 func renderCoreError(raw, inputFile string) {
@@ -8,6 +8,7 @@ import (
 	"fmt"
 	"os/exec"
 	"path/filepath"
+	"strconv"
 	"strings"
 
 	"github.com/tsuki/cli/internal/manifest"
@@ -22,6 +23,8 @@ type Options struct {
 	ArduinoCLI  string
 	FlashBinary string // path to tsuki-flash binary
 	Backend     string // "tsuki-flash" or "arduino-cli"
+	Force       bool   // flash even if the firmware targets another board
+	Verify      bool   // read the flash back after writing and compare
 	Verbose     bool
 }
 
@@ -32,10 +35,17 @@ var boardFQBN = map[string]string{
 	"mega":     "arduino:avr:mega",
 	"leonardo": "arduino:avr:leonardo",
 	"micro":    "arduino:avr:micro",
+	"nano_every": "arduino:megaavr:nona4809",
+	"attiny1614": "megaTinyCore:megaavr:atxy4:chip=1614",
+	"attiny3216": "megaTinyCore:megaavr:atxy6:chip=3216",
 	"due":      "arduino:sam:arduino_due_x",
 	"esp32":    "esp32:esp32:esp32",
 	"esp8266":  "esp8266:esp8266:generic",
 	"pico":     "rp2040:rp2040:rpipico",
+
+	"bluepill":       "STMicroelectronics:stm32:GenF1:pnum=BLUEPILL_F103C8",
+	"blackpill_f411": "STMicroelectronics:stm32:GenF4:pnum=BLACKPILL_F411CE",
+	"nucleo_f401re":  "STMicroelectronics:stm32:Nucleo_64:pnum=NUCLEO_F401RE",
 }
 
 // Run uploads the firmware to the board.
@@ -58,9 +68,9 @@ func Run(projectDir string, m *manifest.Manifest, opts Options) error {
 
 	switch backend {
 	case "tsuki-flash":
-		return uploadTsukiFlash(board, buildDir, opts)
+		return uploadTsukiFlash(board, buildDir, m, opts)
 	default:
-		return uploadArduinoCLI(board, buildDir, opts)
+		return uploadArduinoCLI(board, buildDir, m, opts)
 	}
 }
 
@@ -68,7 +78,7 @@ func Run(projectDir string, m *manifest.Manifest, opts Options) error {
 //  Backend: tsuki-flash upload
 // ─────────────────────────────────────────────────────────────────────────────
 
-func uploadTsukiFlash(board, buildDir string, opts Options) error {
+func uploadTsukiFlash(board, buildDir string, m *manifest.Manifest, opts Options) error {
 	flashBin := opts.FlashBinary
 	if flashBin == "" {
 		flashBin = "tsuki-flash"
@@ -93,6 +103,20 @@ func uploadTsukiFlash(board, buildDir string, opts Options) error {
 		"--port", port,
 		"--build-dir", buildDir,
 	}
+	if m.CpuMHz > 0 {
+		// Bootloader baud scales with the clock the firmware runs at.
+		args = append(args, "--cpu-mhz", strconv.Itoa(m.CpuMHz))
+	}
+	for _, opt := range m.BoardOptionList() {
+		// Some choices change the upload baud (e.g. the Nano's old bootloader).
+		args = append(args, "--board-option", opt)
+	}
+	if opts.Force {
+		args = append(args, "--force")
+	}
+	if opts.Verify {
+		args = append(args, "--verify")
+	}
 	if opts.Verbose {
 		args = append(args, "--verbose")
 	}
@@ -117,11 +141,12 @@ func uploadTsukiFlash(board, buildDir string, opts Options) error {
 //  Backend: arduino-cli upload
 // ─────────────────────────────────────────────────────────────────────────────
 
-func uploadArduinoCLI(board, buildDir string, opts Options) error {
+func uploadArduinoCLI(board, buildDir string, m *manifest.Manifest, opts Options) error {
 	fqbn, ok := boardFQBN[strings.ToLower(board)]
 	if !ok {
 		return fmt.Errorf("unknown board %q — run `tsuki boards list` for the full list", board)
 	}
+	fqbn = m.WithBoardOptions(fqbn)
 
 	port := opts.Port
 	if port == "" {
@@ -147,6 +172,9 @@ func uploadArduinoCLI(board, buildDir string, opts Options) error {
 		"--port", port,
 		"--input-dir", buildDir,
 	}
+	if opts.Verify {
+		args = append(args, "--verify")
+	}
 	if opts.Verbose {
 		args = append(args, "--verbose")
 	}
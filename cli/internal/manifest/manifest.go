@@ -25,6 +25,8 @@ import (
 	"fmt"
 	"os"
 	"path/filepath"
+	"sort"
+	"strings"
 )
 
 const FileName = "tsuki_package.json"
@@ -33,14 +35,41 @@ type Manifest struct {
 	Name        string       `json:"name"`
 	Version     string       `json:"version"`
 	Board       string       `json:"board"`
+	// CPU clock override in MHz (e.g. 80 on ESP32 to save power, 8 for a
+	// bare ATmega328 on its internal oscillator). Zero keeps the board default.
+	CpuMHz      int          `json:"cpu_mhz,omitempty"`
+	// ESP32 partition table: a scheme of the core ("huge_app", "no_ota", …)
+	// or a partitions .csv. Empty keeps the core's "default".
+	PartitionScheme string   `json:"partition_scheme,omitempty"`
+	// Board menu choices, named as in the core's boards.txt, e.g.
+	// {"cpu": "atmega328old"}. Passed as tsuki-flash --board-option and
+	// appended to the FQBN for arduino-cli.
+	BoardOptions map[string]string `json:"board_options,omitempty"`
 	GoVersion   string       `json:"go_version"`
 	Description string       `json:"description,omitempty"`
 	// Compiler backend: "tsuki-flash", "tsuki-flash+cores", or "arduino-cli".
 	// Empty string falls back to the global CLI config (cfg.Backend).
 	Backend     string       `json:"backend,omitempty"`
+	// Arduino libraries pinned for this project, relative to it. The
+	// tsuki-flash backend installs into and compiles from it before the
+	// global ~/.arduino15/libraries. Empty means "libs" when that folder exists.
+	LibrariesDir string       `json:"libraries_dir,omitempty"`
 	// External tsukilib packages used by this project.
 	Packages    []Package    `json:"packages"`
 	Build       BuildConfig  `json:"build"`
+	// Firmware size limits enforced after linking (tsuki-flash backend).
+	Budget      *Budget      `json:"budget,omitempty"`
+}
+
+// Budget caps firmware size. Values are a share of the board ("90%") or a
+// size ("4KB", "512"). PerPackage keys are the module names tsuki-flash
+// reports: a library directory name, "core", "sketch" or a tsuki package.
+//
+//	"budget": { "flash": "90%", "ram": "75%", "per_package": { "LiquidCrystal": "4KB" } }
+type Budget struct {
+	Flash      string            `json:"flash,omitempty"`
+	Ram        string            `json:"ram,omitempty"`
+	PerPackage map[string]string `json:"per_package,omitempty"`
 }
 
 // Package is a single tsukilib dependency declared in the manifest.
@@ -55,8 +84,60 @@ type BuildConfig struct {
 	OutputDir  string   `json:"output_dir"`
 	CppStd     string   `json:"cpp_std"`
 	Optimize   string   `json:"optimize"`
+	// Compiler flags for both C and C++ sources.
 	ExtraFlags []string `json:"extra_flags"`
+	// Flags for C sources, C++ sources and the link, after the generated
+	// ones, e.g. "ldflags": ["-Wl,-u,vfprintf", "-lprintf_flt"] for float
+	// printf on AVR.
+	CFlags     []string `json:"cflags,omitempty"`
+	CxxFlags   []string `json:"cxxflags,omitempty"`
+	LdFlags    []string `json:"ldflags,omitempty"`
+	// Defines for every source, the core's included: "KEY" or "KEY=VAL".
+	Defines    []string `json:"defines,omitempty"`
 	SourceMap  bool     `json:"source_map"`
+	// AVR: string literals of at least this many bytes stay in flash
+	// (F() in Serial prints, PROGMEM string tables). Zero disables.
+	ProgmemMin int      `json:"progmem_min,omitempty"`
+	// Directory (relative to the project) of prelude files replacing the
+	// ones shipped with tsuki-core; see `tsuki-core --dump-prelude`.
+	PreludeDir string   `json:"prelude_dir,omitempty"`
+	// Whether main() ending in an endless `for` is split into setup() and
+	// loop(). Nil means yes.
+	SplitMain  *bool    `json:"split_main,omitempty"`
+}
+
+// BoardOptionList returns BoardOptions as "menu=choice", sorted by menu.
+func (m *Manifest) BoardOptionList() []string {
+	opts := make([]string, 0, len(m.BoardOptions))
+	for menu, choice := range m.BoardOptions {
+		opts = append(opts, menu+"="+choice)
+	}
+	sort.Strings(opts)
+	return opts
+}
+
+// WithBoardOptions appends the board options to fqbn, after any the FQBN
+// already carries (vendor:arch:board:menu=choice,menu=choice).
+func (m *Manifest) WithBoardOptions(fqbn string) string {
+	opts := m.BoardOptionList()
+	if len(opts) == 0 {
+		return fqbn
+	}
+	sep := ":"
+	if strings.Count(fqbn, ":") >= 3 {
+		sep = ","
+	}
+	return fqbn + sep + strings.Join(opts, ",")
+}
+
+// CFlagList returns the flags for C sources: ExtraFlags, then CFlags.
+func (b *BuildConfig) CFlagList() []string {
+	return append(append([]string{}, b.ExtraFlags...), b.CFlags...)
+}
+
+// CxxFlagList returns the flags for C++ sources: ExtraFlags, then CxxFlags.
+func (b *BuildConfig) CxxFlagList() []string {
+	return append(append([]string{}, b.ExtraFlags...), b.CxxFlags...)
 }
 
 // Default returns a manifest with sensible defaults.
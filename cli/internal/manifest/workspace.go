@@ -0,0 +1,180 @@
+package manifest
+
+import (
+	"encoding/json"
+	"fmt"
+	"os"
+	"path/filepath"
+	"sort"
+	"strings"
+)
+
+const WorkspaceFileName = "tsuki_workspace.json"
+
+// Workspace groups several firmware projects that share Go packages.
+//
+//	{
+//	  "members":  [ { "path": "sensor-node", "profile": "battery" },
+//	                { "path": "base-station", "board": "esp32" } ],
+//	  "shared":   [ "shared/proto" ],
+//	  "profiles": { "battery": { "cpu_mhz": 8, "build": { "progmem_min": 16 } } },
+//	  "jobs":     4
+//	}
+type Workspace struct {
+	// Directory holding tsuki_workspace.json; member and shared paths are
+	// relative to it.
+	Dir      string             `json:"-"`
+	Members  []Member           `json:"members"`
+	// Directories of Go packages every member can import by directory name.
+	Shared   []string           `json:"shared,omitempty"`
+	Profiles map[string]Profile `json:"profiles,omitempty"`
+	// Members built at once by `tsuki build --all` (0 = one per CPU).
+	Jobs     int                `json:"jobs,omitempty"`
+}
+
+// Member is one firmware project of the workspace.
+type Member struct {
+	Path    string `json:"path"`
+	// Overrides the member manifest's board, after the profile.
+	Board   string `json:"board,omitempty"`
+	// Name of an entry in Workspace.Profiles.
+	Profile string `json:"profile,omitempty"`
+}
+
+// Profile is a set of manifest overrides shared by several members. Zero
+// values leave the member's own setting alone.
+type Profile struct {
+	Board   string  `json:"board,omitempty"`
+	CpuMHz  int     `json:"cpu_mhz,omitempty"`
+	Backend string  `json:"backend,omitempty"`
+	Budget  *Budget `json:"budget,omitempty"`
+	Build   struct {
+		Optimize   string `json:"optimize,omitempty"`
+		SourceMap  bool   `json:"source_map,omitempty"`
+		ProgmemMin int    `json:"progmem_min,omitempty"`
+	} `json:"build"`
+}
+
+// SharedPackage is a shared directory resolved to its Go package.
+type SharedPackage struct {
+	Name  string   // import name: the directory's base name
+	Dir   string
+	Files []string // .go files, sorted
+}
+
+// LoadWorkspace reads tsuki_workspace.json from dir.
+func LoadWorkspace(dir string) (*Workspace, error) {
+	data, err := os.ReadFile(filepath.Join(dir, WorkspaceFileName))
+	if err != nil {
+		return nil, fmt.Errorf("reading %s: %w", WorkspaceFileName, err)
+	}
+	var w Workspace
+	if err := json.Unmarshal(data, &w); err != nil {
+		return nil, fmt.Errorf("parsing %s: %w", WorkspaceFileName, err)
+	}
+	w.Dir = dir
+	for _, m := range w.Members {
+		if m.Profile == "" {
+			continue
+		}
+		if _, ok := w.Profiles[m.Profile]; !ok {
+			return nil, fmt.Errorf("%s: member %q uses unknown profile %q", WorkspaceFileName, m.Path, m.Profile)
+		}
+	}
+	return &w, nil
+}
+
+// FindWorkspace searches upward from startDir for tsuki_workspace.json.
+// It returns nil without error when the project is not in a workspace.
+func FindWorkspace(startDir string) (*Workspace, error) {
+	dir := startDir
+	for {
+		if _, err := os.Stat(filepath.Join(dir, WorkspaceFileName)); err == nil {
+			return LoadWorkspace(dir)
+		}
+		parent := filepath.Dir(dir)
+		if parent == dir {
+			return nil, nil
+		}
+		dir = parent
+	}
+}
+
+// MemberDir is the absolute project directory of m.
+func (w *Workspace) MemberDir(m Member) string {
+	return filepath.Join(w.Dir, m.Path)
+}
+
+// MemberAt returns the member whose project directory is dir.
+func (w *Workspace) MemberAt(dir string) (Member, bool) {
+	for _, m := range w.Members {
+		if filepath.Clean(w.MemberDir(m)) == filepath.Clean(dir) {
+			return m, true
+		}
+	}
+	return Member{}, false
+}
+
+// Apply overlays the member's profile and board on its manifest.
+func (w *Workspace) Apply(man *Manifest, m Member) {
+	if p, ok := w.Profiles[m.Profile]; ok {
+		if p.Board != "" {
+			man.Board = p.Board
+		}
+		if p.CpuMHz != 0 {
+			man.CpuMHz = p.CpuMHz
+		}
+		if p.Backend != "" {
+			man.Backend = p.Backend
+		}
+		if p.Budget != nil {
+			man.Budget = p.Budget
+		}
+		if p.Build.Optimize != "" {
+			man.Build.Optimize = p.Build.Optimize
+		}
+		if p.Build.SourceMap {
+			man.Build.SourceMap = true
+		}
+		if p.Build.ProgmemMin != 0 {
+			man.Build.ProgmemMin = p.Build.ProgmemMin
+		}
+	}
+	if m.Board != "" {
+		man.Board = m.Board
+	}
+}
+
+// SharedPackages resolves the shared directories. Two directories with the
+// same base name would both be imported under that name and are rejected.
+func (w *Workspace) SharedPackages() ([]SharedPackage, error) {
+	var pkgs []SharedPackage
+	seen := map[string]string{}
+	for _, rel := range w.Shared {
+		dir := filepath.Join(w.Dir, rel)
+		name := filepath.Base(dir)
+		if prev, dup := seen[name]; dup {
+			return nil, fmt.Errorf("%s: shared packages %q and %q are both named %q",
+				WorkspaceFileName, prev, rel, name)
+		}
+		seen[name] = rel
+		files, err := filepath.Glob(filepath.Join(dir, "*.go"))
+		if err != nil || len(files) == 0 {
+			return nil, fmt.Errorf("%s: no .go files in shared package %s", WorkspaceFileName, rel)
+		}
+		files = withoutTests(files)
+		sort.Strings(files)
+		pkgs = append(pkgs, SharedPackage{Name: name, Dir: dir, Files: files})
+	}
+	return pkgs, nil
+}
+
+func withoutTests(files []string) []string {
+	out := files[:0]
+	for _, f := range files {
+		if !strings.HasSuffix(f, "_test.go") {
+			out = append(out, f)
+		}
+	}
+	return out
+}
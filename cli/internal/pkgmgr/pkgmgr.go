@@ -25,7 +25,9 @@ package pkgmgr
 
 import (
 	"crypto/ed25519"
+	"crypto/sha256"
 	"crypto/x509"
+	"encoding/hex"
 	"encoding/json"
 	"encoding/pem"
 	"fmt"
@@ -33,7 +35,9 @@ import (
 	"net/http"
 	"os"
 	"path/filepath"
+	"slices"
 	"sort"
+	"strconv"
 	"strings"
 	"time"
 
@@ -133,6 +137,8 @@ const (
 type InstallOptions struct {
 	Source  string
 	Version string
+	// Sha256 is the checksum the registry lists for Source, if any.
+	Sha256  string
 }
 
 // Install fetches a tsukilib.toml, optionally verifies its Ed25519
@@ -151,6 +157,14 @@ func Install(opts InstallOptions) (*InstalledPackage, error) {
 		version = opts.Version
 	}
 
+	if opts.Sha256 != "" {
+		sum := sha256.Sum256([]byte(tomlData))
+		if actual := hex.EncodeToString(sum[:]); !strings.EqualFold(actual, opts.Sha256) {
+			return nil, fmt.Errorf("checksum mismatch for %s@%s\n  expected: %s\n  actual:   %s",
+				name, version, opts.Sha256, actual)
+		}
+	}
+
 	// Signature verification
 	cfg, _ := config.Load()
 	if cfg != nil && cfg.VerifySignatures {
@@ -399,10 +413,25 @@ type RegistryIndex struct {
 }
 
 type RegistryPackage struct {
-	Description string            `json:"description"`
-	Author      string            `json:"author"`
-	Latest      string            `json:"latest"`
-	Versions    map[string]string `json:"versions"` // version -> TOML URL
+	Description string                     `json:"description"`
+	Author      string                     `json:"author"`
+	Latest      string                     `json:"latest"`
+	Versions    map[string]RegistryVersion `json:"versions"`
+}
+
+// RegistryVersion is one published version: a bare TOML URL in older
+// registries, or an object that also carries the file's SHA-256.
+type RegistryVersion struct {
+	URL    string `json:"url"`
+	Sha256 string `json:"sha256,omitempty"`
+}
+
+func (v *RegistryVersion) UnmarshalJSON(data []byte) error {
+	if err := json.Unmarshal(data, &v.URL); err == nil {
+		return nil
+	}
+	type plain RegistryVersion
+	return json.Unmarshal(data, (*plain)(v))
 }
 
 type RegistryEntry struct {
@@ -494,7 +523,7 @@ func SearchRegistry(query string) ([]RegistryEntry, error) {
 				Name:        name,
 				Version:     pkg.Latest,
 				Description: pkg.Description,
-				URL:         pkg.Versions[pkg.Latest],
+				URL:         pkg.Versions[pkg.Latest].URL,
 			})
 		}
 	}
@@ -522,7 +551,7 @@ func InstallFromRegistry(name, version string) (*InstalledPackage, error) {
 		ver = entry.Latest
 	}
 
-	tomlURL, ok := entry.Versions[ver]
+	published, ok := entry.Versions[ver]
 	if !ok {
 		versions := make([]string, 0, len(entry.Versions))
 		for v := range entry.Versions {
@@ -535,7 +564,7 @@ func InstallFromRegistry(name, version string) (*InstalledPackage, error) {
 		)
 	}
 
-	return Install(InstallOptions{Source: tomlURL, Version: ver})
+	return Install(InstallOptions{Source: published.URL, Version: ver, Sha256: published.Sha256})
 }
 
 // ── Print helpers ─────────────────────────────────────────────────────────────
@@ -676,27 +705,40 @@ func parseKV(line string) (key, value string, ok bool) {
 }
 
 // ── Lock file ─────────────────────────────────────────────────────────────────
+//
+// tsuki.lock sits next to tsuki_package.json and pins every declared package
+// to the installed version and tsukilib.toml checksum the project was built
+// with. `tsuki pkg install` and `tsuki build` keep it current; with
+// `tsuki build --locked` a difference is an error instead.
+
+const LockFileName = "tsuki.lock"
 
 type LockEntry struct {
 	Name    string `json:"name"`
 	Version string `json:"version"`
-	Path    string `json:"path"`
+	// Sha256 is the hex SHA-256 of the installed tsukilib.toml.
+	Sha256  string `json:"sha256"`
+	// Path was written by older versions; it is machine-specific and ignored.
+	Path    string `json:"path,omitempty"`
 }
 
-func WriteLock(projectDir string, pkgs []InstalledPackage) error {
-	entries := make([]LockEntry, len(pkgs))
-	for i, p := range pkgs {
-		entries[i] = LockEntry{Name: p.Name, Version: p.Version, Path: p.Path}
+// WriteLock writes entries, sorted by name, to the project's tsuki.lock.
+func WriteLock(projectDir string, entries []LockEntry) error {
+	sorted := append([]LockEntry(nil), entries...)
+	sort.Slice(sorted, func(i, j int) bool { return sorted[i].Name < sorted[j].Name })
+	for i := range sorted {
+		sorted[i].Path = ""
 	}
-	data, err := json.MarshalIndent(entries, "", "  ")
+	data, err := json.MarshalIndent(sorted, "", "  ")
 	if err != nil {
 		return err
 	}
-	return os.WriteFile(filepath.Join(projectDir, "tsuki.lock"), append(data, '\n'), 0644)
+	return os.WriteFile(filepath.Join(projectDir, LockFileName), append(data, '\n'), 0644)
 }
 
+// ReadLock reads the project's tsuki.lock; a missing file is (nil, nil).
 func ReadLock(projectDir string) ([]LockEntry, error) {
-	data, err := os.ReadFile(filepath.Join(projectDir, "tsuki.lock"))
+	data, err := os.ReadFile(filepath.Join(projectDir, LockFileName))
 	if os.IsNotExist(err) {
 		return nil, nil
 	}
@@ -704,5 +746,118 @@ func ReadLock(projectDir string) ([]LockEntry, error) {
 		return nil, err
 	}
 	var entries []LockEntry
-	return entries, json.Unmarshal(data, &entries)
-}
\ No newline at end of file
+	if err := json.Unmarshal(data, &entries); err != nil {
+		return nil, fmt.Errorf("parsing %s: %w", LockFileName, err)
+	}
+	return entries, nil
+}
+
+// UpdateLock records name@version, as installed, in the project's tsuki.lock.
+func UpdateLock(projectDir, name, version string) error {
+	entries, err := ReadLock(projectDir)
+	if err != nil {
+		return err
+	}
+	entry, err := lockEntry(name, version)
+	if err != nil {
+		return err
+	}
+	if i := findLock(entries, name); i >= 0 {
+		entries[i] = entry
+	} else {
+		entries = append(entries, entry)
+	}
+	return WriteLock(projectDir, entries)
+}
+
+// ResolveInstalled picks the installed version of each package a build
+// uses: the locked one while it is installed, else the highest installed.
+func ResolveInstalled(names []string, lock []LockEntry) ([]LockEntry, error) {
+	resolved := make([]LockEntry, 0, len(names))
+	for _, name := range names {
+		versions := InstalledVersions(name)
+		if len(versions) == 0 {
+			return nil, fmt.Errorf(
+				"package %q declared in tsuki_package.json is not installed\n"+
+					"  Run: tsuki pkg install %s", name, name,
+			)
+		}
+		version := versions[len(versions)-1]
+		if i := findLock(lock, name); i >= 0 && slices.Contains(versions, lock[i].Version) {
+			version = lock[i].Version
+		}
+		entry, err := lockEntry(name, version)
+		if err != nil {
+			return nil, err
+		}
+		resolved = append(resolved, entry)
+	}
+	return resolved, nil
+}
+
+// LockDiff describes, one line per package, how resolved differs from lock.
+func LockDiff(lock, resolved []LockEntry) []string {
+	var changes []string
+	for _, r := range resolved {
+		i := findLock(lock, r.Name)
+		switch {
+		case i < 0:
+			changes = append(changes, fmt.Sprintf("%s@%s is not in %s", r.Name, r.Version, LockFileName))
+		case lock[i].Version != r.Version:
+			changes = append(changes, fmt.Sprintf("%s is locked at %s but %s would be used (install %s@%s)",
+				r.Name, lock[i].Version, r.Version, r.Name, lock[i].Version))
+		case lock[i].Sha256 != r.Sha256:
+			changes = append(changes, fmt.Sprintf("%s@%s: installed tsukilib.toml does not match the locked checksum",
+				r.Name, r.Version))
+		}
+	}
+	for _, l := range lock {
+		if findLock(resolved, l.Name) < 0 {
+			changes = append(changes, fmt.Sprintf("%s@%s is locked but no longer declared", l.Name, l.Version))
+		}
+	}
+	return changes
+}
+
+// InstalledVersions lists the installed versions of name, lowest first.
+func InstalledVersions(name string) []string {
+	var versions []string
+	entries, _ := os.ReadDir(filepath.Join(LibsDir(), name))
+	for _, e := range entries {
+		if _, err := os.Stat(ManifestPath(name, e.Name())); e.IsDir() && err == nil {
+			versions = append(versions, e.Name())
+		}
+	}
+	sort.Slice(versions, func(i, j int) bool { return compareVersions(versions[i], versions[j]) < 0 })
+	return versions
+}
+
+func lockEntry(name, version string) (LockEntry, error) {
+	data, err := os.ReadFile(ManifestPath(name, version))
+	if err != nil {
+		return LockEntry{}, fmt.Errorf("reading %s@%s: %w", name, version, err)
+	}
+	sum := sha256.Sum256(data)
+	return LockEntry{Name: name, Version: version, Sha256: hex.EncodeToString(sum[:])}, nil
+}
+
+func findLock(entries []LockEntry, name string) int {
+	return slices.IndexFunc(entries, func(e LockEntry) bool { return e.Name == name })
+}
+
+// compareVersions orders major.minor.patch numerically, falling back to a
+// string comparison for anything else.
+func compareVersions(a, b string) int {
+	pa, pb := strings.Split(a, "."), strings.Split(b, ".")
+	for i := 0; i < len(pa) && i < len(pb); i++ {
+		na, errA := strconv.Atoi(pa[i])
+		nb, errB := strconv.Atoi(pb[i])
+		if errA != nil || errB != nil {
+			return strings.Compare(a, b)
+		}
+		if na != nb {
+			return na - nb
+		}
+	}
+	return len(pa) - len(pb)
+}
@@ -0,0 +1,151 @@
+// ─────────────────────────────────────────────────────────────────────────────
+//  tsuki :: scaffold  —  project templates for `tsuki new`
+//
+//  A template is a directory laid out like the project it creates. Files
+//  ending in .tmpl are rendered with text/template (the suffix is dropped),
+//  the rest are copied as they are. The built-in templates are embedded in
+//  the binary; a directory of the same name under the user's templates dir
+//  replaces one, and any other directory there adds a template.
+// ─────────────────────────────────────────────────────────────────────────────
+
+package scaffold
+
+import (
+	"bytes"
+	"embed"
+	"fmt"
+	"io/fs"
+	"os"
+	"path"
+	"path/filepath"
+	"sort"
+	"strings"
+	"text/template"
+)
+
+//go:embed templates
+var builtin embed.FS
+
+// Data is what templates see: the project and values picked for its board.
+type Data struct {
+	Name       string
+	Board      string
+	BoardName  string
+	LED        string // Go expression for the on-board LED pin
+	Analog     string // Go expression for the first analog input
+	AnalogName string // the same pin as printed on the board
+	Baud       int
+	WiFi       bool
+}
+
+// boardData holds the board-specific values; boards not listed get the
+// Arduino defaults.
+var boardData = map[string]Data{
+	"esp32":   {BoardName: "ESP32 Dev Module", LED: "2", Analog: "34", AnalogName: "GPIO34", Baud: 115200, WiFi: true},
+	"esp8266": {BoardName: "ESP8266", Analog: "arduino.A0", AnalogName: "A0", Baud: 115200, WiFi: true},
+	"d1_mini": {BoardName: "Wemos D1 Mini", Analog: "arduino.A0", AnalogName: "A0", Baud: 115200, WiFi: true},
+	"pico":    {BoardName: "Raspberry Pi Pico", Analog: "26", AnalogName: "GP26", Baud: 115200},
+}
+
+// DataFor returns the template data for project name on board.
+func DataFor(name, board string) Data {
+	d, ok := boardData[board]
+	if !ok {
+		d = Data{BoardName: board, Analog: "arduino.A0", AnalogName: "A0", Baud: 9600}
+	}
+	if d.LED == "" {
+		d.LED = "arduino.LED_BUILTIN"
+	}
+	d.Name, d.Board = name, board
+	return d
+}
+
+// needsWiFi lists the built-in templates that only make sense on a board
+// with WiFi.
+var needsWiFi = map[string]bool{"wifi": true}
+
+// Names lists the available templates, built-in and from userDir, sorted.
+func Names(userDir string) []string {
+	seen := map[string]bool{}
+	if entries, err := fs.ReadDir(builtin, "templates"); err == nil {
+		for _, e := range entries {
+			seen[e.Name()] = e.IsDir()
+		}
+	}
+	if entries, err := os.ReadDir(userDir); err == nil && userDir != "" {
+		for _, e := range entries {
+			if e.IsDir() {
+				seen[e.Name()] = true
+			}
+		}
+	}
+	var names []string
+	for n, dir := range seen {
+		if dir {
+			names = append(names, n)
+		}
+	}
+	sort.Strings(names)
+	return names
+}
+
+// Render produces the files of template name for data, keyed by their path
+// in the project (slash-separated).
+func Render(name, userDir string, data Data) (map[string][]byte, error) {
+	src, own, err := open(name, userDir)
+	if err != nil {
+		return nil, err
+	}
+	if needsWiFi[name] && !data.WiFi && !own {
+		return nil, fmt.Errorf("template %q needs a board with WiFi; %s has none", name, data.BoardName)
+	}
+
+	files := map[string][]byte{}
+	err = fs.WalkDir(src, ".", func(p string, d fs.DirEntry, err error) error {
+		if err != nil || d.IsDir() {
+			return err
+		}
+		content, err := fs.ReadFile(src, p)
+		if err != nil {
+			return err
+		}
+		if !strings.HasSuffix(p, ".tmpl") {
+			files[p] = content
+			return nil
+		}
+		t, err := template.New(path.Base(p)).Option("missingkey=error").Parse(string(content))
+		if err != nil {
+			return fmt.Errorf("template %s/%s: %w", name, p, err)
+		}
+		var out bytes.Buffer
+		if err := t.Execute(&out, data); err != nil {
+			return fmt.Errorf("template %s/%s: %w", name, p, err)
+		}
+		files[strings.TrimSuffix(p, ".tmpl")] = out.Bytes()
+		return nil
+	})
+	if err != nil {
+		return nil, err
+	}
+	return files, nil
+}
+
+// open finds template name, preferring the user's copy; own reports that
+// it is one.
+func open(name, userDir string) (src fs.FS, own bool, err error) {
+	if name == "" || name == "." || name == ".." || strings.ContainsAny(name, `/\`) {
+		return nil, false, fmt.Errorf("bad template name %q", name)
+	}
+	if userDir != "" {
+		dir := filepath.Join(userDir, name)
+		if fi, err := os.Stat(dir); err == nil && fi.IsDir() {
+			return os.DirFS(dir), true, nil
+		}
+	}
+	if sub, err := fs.Sub(builtin, "templates/"+name); err == nil {
+		if _, err := fs.Stat(sub, "."); err == nil {
+			return sub, false, nil
+		}
+	}
+	return nil, false, fmt.Errorf("unknown template %q (available: %s)", name, strings.Join(Names(userDir), ", "))
+}
@@ -14,6 +14,7 @@ import (
 	"time"
 
 	"github.com/fatih/color"
+	"github.com/mattn/go-isatty"
 )
 
 // ── Color palette ─────────────────────────────────────────────────────────────
@@ -405,6 +406,10 @@ func NewSpinner(msg string) *Spinner {
 }
 
 func (s *Spinner) Start() {
+	// No animation when output is captured (e.g. `tsuki build --all`).
+	if !isatty.IsTerminal(os.Stdout.Fd()) {
+		return
+	}
 	go func() {
 		i := 0
 		for {
@@ -2,7 +2,15 @@
 //  tsuki-flash :: boards  —  supported board database
 // ─────────────────────────────────────────────────────────────────────────────
 
+use std::collections::BTreeMap;
 use std::fmt;
+use std::sync::OnceLock;
+
+use tsuki_core::boards::{BoardDef, BoardOption, ToolchainDef};
+
+use crate::error::{FlashError, Result};
+
+pub use tsuki_core::boards::{Bootloader, Stm32Upload, Updi};
 
 /// Which compiler/programmer family to use.
 #[derive(Debug, Clone, PartialEq)]
@@ -10,14 +18,17 @@ pub enum Toolchain {
     /// AVR microcontrollers — avr-gcc + avrdude
     Avr {
         mcu:   &'static str,   // e.g. "atmega328p"
-        f_cpu: u32,            // e.g. 16_000_000
         programmer: &'static str, // e.g. "arduino"
         baud:  u32,
     },
+    /// megaAVR-0 / tinyAVR 0-, 1- and 2-series — avr-gcc + avrdude over UPDI
+    MegaAvr {
+        mcu:   &'static str,   // e.g. "atmega4809", "attiny1614"
+        updi:  Updi,
+    },
     /// Atmel SAM ARM — arm-none-eabi-gcc + bossac
     Sam {
         mcu: &'static str,
-        f_cpu: u32,
     },
     /// Raspberry Pi RP2040 — arm-none-eabi-gcc + picotool/uf2
     Rp2040,
@@ -27,19 +38,46 @@ pub enum Toolchain {
     },
     /// Espressif ESP8266 — xtensa-lx106-elf-gcc + esptool.py
     Esp8266,
+    /// STMicroelectronics STM32 — arm-none-eabi-gcc (STM32duino) +
+    /// stm32flash / dfu-util / ST-Link drive
+    Stm32 {
+        mcu:    &'static str,   // e.g. "cortex-m3"
+        series: &'static str,   // e.g. "STM32F1xx"
+        upload: Stm32Upload,
+    },
+    /// PJRC Teensy — arm-none-eabi-gcc (Teensyduino) + teensy_loader_cli
+    Teensy {
+        mcu:      &'static str,   // e.g. "imxrt1062", "mkl26z64"
+        model:    &'static str,   // e.g. "TEENSY41"
+        ldscript: &'static str,   // in the core folder
+    },
 }
 
 #[derive(Debug, Clone)]
 pub struct Board {
     pub id:       &'static str,
     pub name:     &'static str,
-    pub fqbn:     &'static str,
+    /// With the picked `--board-option`s appended
+    pub fqbn:     String,
     pub variant:  &'static str,   // pins_arduino.h variant folder
     pub flash_kb: u32,
     pub ram_kb:   u32,
+    /// Stock clock in MHz
+    pub clock_mhz: u32,
     pub toolchain: Toolchain,
     /// Compile-time defines specific to this board
-    pub defines:  &'static [&'static str],
+    pub defines:  Vec<&'static str>,
+    /// What `burn-bootloader` writes (classic AVR only)
+    pub bootloader: Option<&'static Bootloader>,
+    /// Programmer baud for ESP and STM32 serial uploads (None = their
+    /// default); AVR boards keep theirs in the toolchain
+    pub upload_baud: Option<u32>,
+    /// SPI flash mode written into ESP images (None = esptool's default)
+    pub flash_mode: Option<&'static str>,
+    /// ESP32 partition scheme when `--partition-scheme` isn't given
+    pub partition_scheme: Option<&'static str>,
+    /// The menus `--board-option` picks from
+    pub options:  &'static BTreeMap<String, BTreeMap<String, BoardOption>>,
 }
 
 impl fmt::Display for Board {
@@ -49,37 +87,157 @@ impl fmt::Display for Board {
 }
 
 impl Board {
-    /// Return the board catalog.
+    /// Return the board catalog: the boards of the shared database
+    /// (src/boards.toml) that have a toolchain.
     pub fn catalog() -> &'static [Board] {
-        &BOARDS
+        static BOARDS: OnceLock<Vec<Board>> = OnceLock::new();
+        BOARDS.get_or_init(|| {
+            tsuki_core::boards::catalog().iter().filter_map(Board::from_def).collect()
+        })
     }
 
     /// Find a board by its short ID (case-insensitive).
     pub fn find(id: &str) -> Option<&'static Board> {
-        let id_lower = id.to_lowercase();
-        BOARDS.iter().find(|b| b.id.eq_ignore_ascii_case(&id_lower))
+        Self::catalog().iter().find(|b| b.id.eq_ignore_ascii_case(id))
+    }
+
+    fn from_def(def: &'static BoardDef) -> Option<Board> {
+        let toolchain = match def.toolchain.as_ref()? {
+            ToolchainDef::Avr { mcu, programmer, baud } => Toolchain::Avr {
+                mcu: mcu.as_str(), programmer: programmer.as_str(), baud: *baud,
+            },
+            ToolchainDef::MegaAvr { mcu, updi } => Toolchain::MegaAvr { mcu: mcu.as_str(), updi: *updi },
+            ToolchainDef::Sam { mcu }           => Toolchain::Sam { mcu: mcu.as_str() },
+            ToolchainDef::Rp2040                => Toolchain::Rp2040,
+            ToolchainDef::Stm32 { mcu, series, upload } => Toolchain::Stm32 {
+                mcu: mcu.as_str(), series: series.as_str(), upload: *upload,
+            },
+            ToolchainDef::Esp32 { variant }     => Toolchain::Esp32 { variant: variant.as_str() },
+            ToolchainDef::Esp8266               => Toolchain::Esp8266,
+            ToolchainDef::Teensy { mcu, model, ldscript } => Toolchain::Teensy {
+                mcu: mcu.as_str(), model: model.as_str(), ldscript: ldscript.as_str(),
+            },
+        };
+        Some(Board {
+            id:        def.id.as_str(),
+            name:      def.name.as_str(),
+            fqbn:      def.fqbn.clone(),
+            variant:   def.variant.as_str(),
+            flash_kb:  def.flash_kb,
+            ram_kb:    def.ram_kb,
+            clock_mhz: def.clock_mhz,
+            toolchain,
+            defines:   def.defines.iter().map(String::as_str).collect(),
+            bootloader: def.bootloader.as_ref(),
+            upload_baud: None,
+            flash_mode: None,
+            partition_scheme: None,
+            options:   &def.options,
+        })
     }
 
-    /// The `-mmcu` flag value (AVR only).
+    /// This board with menu choices applied, each `(menu, choice)` as in
+    /// `--board-option cpu=atmega328old`. The choices are appended to the
+    /// FQBN the way arduino-cli expects them.
+    pub fn with_options(&self, picked: &[(String, String)]) -> Result<Board> {
+        let mut board = self.clone();
+        for (i, (menu, choice)) in picked.iter().enumerate() {
+            let unknown = |option: String, available: Vec<&str>| FlashError::UnknownBoardOption {
+                board: self.id.to_owned(),
+                option,
+                available: if available.is_empty() { "none".into() } else { available.join(", ") },
+            };
+            let choices = self.options.get(menu)
+                .ok_or_else(|| unknown(menu.clone(), self.options.keys().map(String::as_str).collect()))?;
+            let opt = choices.get(choice)
+                .ok_or_else(|| unknown(format!("{}={}", menu, choice), sorted_choices(choices)))?;
+            if picked[..i].iter().any(|(m, _)| m == menu) {
+                return Err(FlashError::Other(format!("--board-option {} given twice", menu)));
+            }
+
+            board.defines.extend(opt.defines.iter().map(String::as_str));
+            if let Some(mhz) = opt.clock_mhz { board.clock_mhz = mhz; }
+            if let Some(kb) = opt.flash_kb { board.flash_kb = kb; }
+            if let Some(rate) = opt.upload_baud {
+                match &mut board.toolchain {
+                    Toolchain::Avr { baud, .. } => *baud = rate,
+                    _ => board.upload_baud = Some(rate),
+                }
+            }
+            if let Some(mode) = &opt.flash_mode { board.flash_mode = Some(mode); }
+            if let Some(scheme) = &opt.partition_scheme { board.partition_scheme = Some(scheme); }
+            if let Some(bl) = &opt.bootloader { board.bootloader = Some(bl); }
+
+            // vendor:arch:board, then menu=choice pairs separated by commas.
+            let sep = if board.fqbn.split(':').count() > 3 { ',' } else { ':' };
+            board.fqbn = format!("{}{}{}={}", board.fqbn, sep, menu, choice);
+        }
+        Ok(board)
+    }
+
+    /// The `-mmcu` flag value (AVR and megaAVR only).
     pub fn avr_mcu(&self) -> Option<&'static str> {
-        if let Toolchain::Avr { mcu, .. } = &self.toolchain {
-            Some(mcu)
-        } else {
-            None
+        match &self.toolchain {
+            Toolchain::Avr { mcu, .. } | Toolchain::MegaAvr { mcu, .. } => Some(mcu),
+            _ => None,
         }
     }
 
     /// CPU frequency in Hz.
     pub fn f_cpu(&self) -> u32 {
+        self.clock_mhz * 1_000_000
+    }
+
+    /// Clock speeds (MHz) the MCU can be configured for.
+    pub fn supported_mhz(&self) -> &[u32] {
         match &self.toolchain {
-            Toolchain::Avr { f_cpu, .. } => *f_cpu,
-            Toolchain::Sam { f_cpu, .. } => *f_cpu,
-            Toolchain::Rp2040            => 133_000_000,
-            Toolchain::Esp32 { .. }      => 240_000_000,
-            Toolchain::Esp8266           => 80_000_000,
+            Toolchain::Avr { mcu: "atmega328p", .. } => &[1, 8, 12, 16, 20],
+            Toolchain::Avr { .. }        => &[8, 16],
+            // The internal oscillator runs at 16 or 20 MHz (OSCCFG fuse);
+            // the core divides it down for the rest.
+            Toolchain::MegaAvr { mcu: "atmega4809", .. } => &[16, 20],
+            Toolchain::MegaAvr { .. }    => &[1, 2, 4, 5, 8, 10, 16, 20],
+            Toolchain::Sam { .. }        => &[84],
+            Toolchain::Rp2040            => &[50, 100, 125, 133, 150, 200, 250],
+            Toolchain::Esp32 { .. }      => &[80, 160, 240],
+            Toolchain::Esp8266           => &[80, 160],
+            // STM32duino's clock setup is per variant; only the stock clock.
+            Toolchain::Stm32 { .. }      => std::slice::from_ref(&self.clock_mhz),
+            // The F_CPU menu choices of Teensyduino, without overclocking.
+            Toolchain::Teensy { mcu: "imxrt1062", .. } => &[24, 150, 396, 450, 528, 600],
+            Toolchain::Teensy { .. }     => &[24, 48],
+        }
+    }
+
+    /// CPU frequency in Hz, honouring a `cpu_mhz` override from the manifest.
+    pub fn clock_hz(&self, cpu_mhz: Option<u32>) -> Result<u32> {
+        match cpu_mhz {
+            None => Ok(self.f_cpu()),
+            Some(mhz) if self.supported_mhz().contains(&mhz) => Ok(mhz * 1_000_000),
+            Some(mhz) => Err(FlashError::UnsupportedClock {
+                board:     self.id.to_owned(),
+                mhz,
+                supported: self.supported_mhz().iter()
+                    .map(|m| m.to_string()).collect::<Vec<_>>().join(", "),
+            }),
         }
     }
 
+    /// Bootloader baud rate for the given clock (AVR only).
+    ///
+    /// Optiboot's UART divisor scales with F_CPU, so a board re-clocked below
+    /// its stock crystal needs a proportionally slower upload rate.
+    pub fn upload_baud(&self, cpu_mhz: Option<u32>) -> Result<Option<u32>> {
+        let Some((_, baud)) = self.avrdude_programmer() else { return Ok(None) };
+        let hz = self.clock_hz(cpu_mhz)? as u64;
+        let scaled = baud as u64 * hz / self.f_cpu() as u64;
+        let snapped = STD_BAUDS.iter().copied()
+            .filter(|b| *b as u64 <= scaled)
+            .max()
+            .unwrap_or(STD_BAUDS[0]);
+        Ok(Some(snapped))
+    }
+
     /// avrdude programmer type (AVR only).
     pub fn avrdude_programmer(&self) -> Option<(&'static str, u32)> {
         if let Toolchain::Avr { programmer, baud, .. } = &self.toolchain {
@@ -94,176 +252,120 @@ impl Board {
     pub fn arch(&self) -> &'static str {
         match &self.toolchain {
             Toolchain::Avr { .. }   => "avr",
+            // tinyAVR parts are only supported by megaTinyCore.
+            Toolchain::MegaAvr { mcu, .. } if mcu.starts_with("attiny") => "megatinycore",
+            Toolchain::MegaAvr { .. } => "megaavr",
             Toolchain::Sam { .. }   => "sam",
             Toolchain::Rp2040       => "rp2040",
             Toolchain::Esp32 { .. } => "esp32",
             Toolchain::Esp8266      => "esp8266",
+            Toolchain::Stm32 { .. } => "stm32",
+            Toolchain::Teensy { .. } => "teensy",
         }
     }
+
+    /// Whether the board can be flashed without a serial port: over its
+    /// boot ROM's USB drive, DFU or HalfKay.
+    pub fn port_optional(&self) -> bool {
+        matches!(&self.toolchain,
+            Toolchain::Rp2040 | Toolchain::Teensy { .. }
+            | Toolchain::Stm32 { upload: Stm32Upload::Dfu | Stm32Upload::StLink, .. })
+    }
 }
 
-// ─────────────────────────────────────────────────────────────────────────────
-//  Static board table
-// ─────────────────────────────────────────────────────────────────────────────
+/// A menu's choices, sizes in numeric order (4M before 16M).
+pub fn sorted_choices(choices: &BTreeMap<String, BoardOption>) -> Vec<&str> {
+    let mut keys: Vec<&str> = choices.keys().map(String::as_str).collect();
+    keys.sort_by_key(|k| {
+        let digits = k.len() - k.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        (k[..digits].parse::<u32>().unwrap_or(0), *k)
+    });
+    keys
+}
 
-static BOARDS: &[Board] = &[
-    // ── AVR ───────────────────────────────────────────────────────────────────
-    Board {
-        id: "uno", name: "Arduino Uno",
-        fqbn: "arduino:avr:uno",
-        variant: "standard",
-        flash_kb: 32, ram_kb: 2,
-        toolchain: Toolchain::Avr {
-            mcu: "atmega328p", f_cpu: 16_000_000,
-            programmer: "arduino", baud: 115200,
-        },
-        defines: &["ARDUINO_AVR_UNO", "ARDUINO_ARCH_AVR"],
-    },
-    Board {
-        id: "nano", name: "Arduino Nano",
-        fqbn: "arduino:avr:nano",
-        variant: "eightanaloginputs",
-        flash_kb: 32, ram_kb: 2,
-        toolchain: Toolchain::Avr {
-            mcu: "atmega328p", f_cpu: 16_000_000,
-            programmer: "arduino", baud: 115200,
-        },
-        defines: &["ARDUINO_AVR_NANO", "ARDUINO_ARCH_AVR"],
-    },
-    Board {
-        id: "nano_old", name: "Arduino Nano (old bootloader)",
-        fqbn: "arduino:avr:nano:cpu=atmega328old",
-        variant: "eightanaloginputs",
-        flash_kb: 32, ram_kb: 2,
-        toolchain: Toolchain::Avr {
-            mcu: "atmega328p", f_cpu: 16_000_000,
-            programmer: "arduino", baud: 57600,
-        },
-        defines: &["ARDUINO_AVR_NANO", "ARDUINO_ARCH_AVR"],
-    },
-    Board {
-        id: "mega", name: "Arduino Mega 2560",
-        fqbn: "arduino:avr:mega",
-        variant: "mega",
-        flash_kb: 256, ram_kb: 8,
-        toolchain: Toolchain::Avr {
-            mcu: "atmega2560", f_cpu: 16_000_000,
-            programmer: "wiring", baud: 115200,
-        },
-        defines: &["ARDUINO_AVR_MEGA2560", "ARDUINO_ARCH_AVR"],
-    },
-    Board {
-        id: "leonardo", name: "Arduino Leonardo",
-        fqbn: "arduino:avr:leonardo",
-        variant: "leonardo",
-        flash_kb: 32, ram_kb: 2,
-        toolchain: Toolchain::Avr {
-            mcu: "atmega32u4", f_cpu: 16_000_000,
-            programmer: "avr109", baud: 57600,
-        },
-        defines: &["ARDUINO_AVR_LEONARDO", "ARDUINO_ARCH_AVR", "USB_VID=0x2341", "USB_PID=0x0036"],
-    },
-    Board {
-        id: "micro", name: "Arduino Micro",
-        fqbn: "arduino:avr:micro",
-        variant: "micro",
-        flash_kb: 32, ram_kb: 2,
-        toolchain: Toolchain::Avr {
-            mcu: "atmega32u4", f_cpu: 16_000_000,
-            programmer: "avr109", baud: 57600,
-        },
-        defines: &["ARDUINO_AVR_MICRO", "ARDUINO_ARCH_AVR", "USB_VID=0x2341", "USB_PID=0x0037"],
-    },
-    Board {
-        id: "pro_mini_5v", name: "Arduino Pro Mini 5V",
-        fqbn: "arduino:avr:pro:cpu=16MHzatmega328",
-        variant: "eightanaloginputs",
-        flash_kb: 32, ram_kb: 2,
-        toolchain: Toolchain::Avr {
-            mcu: "atmega328p", f_cpu: 16_000_000,
-            programmer: "arduino", baud: 57600,
-        },
-        defines: &["ARDUINO_AVR_PRO", "ARDUINO_ARCH_AVR"],
-    },
-    Board {
-        id: "pro_mini_3v3", name: "Arduino Pro Mini 3.3V",
-        fqbn: "arduino:avr:pro:cpu=8MHzatmega328",
-        variant: "eightanaloginputs",
-        flash_kb: 32, ram_kb: 2,
-        toolchain: Toolchain::Avr {
-            mcu: "atmega328p", f_cpu: 8_000_000,
-            programmer: "arduino", baud: 57600,
-        },
-        defines: &["ARDUINO_AVR_PRO", "ARDUINO_ARCH_AVR"],
-    },
-    // ── ARM SAM ───────────────────────────────────────────────────────────────
-    Board {
-        id: "due", name: "Arduino Due",
-        fqbn: "arduino:sam:arduino_due_x",
-        variant: "arduino_due_x",
-        flash_kb: 512, ram_kb: 96,
-        toolchain: Toolchain::Sam {
-            mcu: "cortex-m3", f_cpu: 84_000_000,
-        },
-        defines: &["ARDUINO_SAM_DUE", "ARDUINO_ARCH_SAM", "__SAM3X8E__"],
-    },
-    // ── RP2040 ────────────────────────────────────────────────────────────────
-    Board {
-        id: "pico", name: "Raspberry Pi Pico",
-        fqbn: "rp2040:rp2040:rpipico",
-        variant: "rpipico",
-        flash_kb: 2048, ram_kb: 264,
-        toolchain: Toolchain::Rp2040,
-        defines: &["ARDUINO_RASPBERRY_PI_PICO", "ARDUINO_ARCH_RP2040"],
-    },
-    // ── ESP32 ─────────────────────────────────────────────────────────────────
-    Board {
-        id: "esp32", name: "ESP32 Dev Module",
-        fqbn: "esp32:esp32:esp32",
-        variant: "esp32",
-        flash_kb: 4096, ram_kb: 520,
-        toolchain: Toolchain::Esp32 { variant: "esp32" },
-        defines: &["ARDUINO_ESP32_DEV", "ARDUINO_ARCH_ESP32", "ESP32"],
-    },
-    Board {
-        id: "esp32s2", name: "ESP32-S2 Dev Module",
-        fqbn: "esp32:esp32:esp32s2",
-        variant: "esp32s2",
-        flash_kb: 4096, ram_kb: 320,
-        toolchain: Toolchain::Esp32 { variant: "esp32s2" },
-        defines: &["ARDUINO_ESP32S2_DEV", "ARDUINO_ARCH_ESP32", "CONFIG_IDF_TARGET_ESP32S2"],
-    },
-    Board {
-        id: "esp32c3", name: "ESP32-C3 Dev Module",
-        fqbn: "esp32:esp32:esp32c3",
-        variant: "esp32c3",
-        flash_kb: 4096, ram_kb: 400,
-        toolchain: Toolchain::Esp32 { variant: "esp32c3" },
-        defines: &["ARDUINO_ESP32C3_DEV", "ARDUINO_ARCH_ESP32", "CONFIG_IDF_TARGET_ESP32C3"],
-    },
-    // ── ESP8266 ───────────────────────────────────────────────────────────────
-    Board {
-        id: "esp8266", name: "ESP8266 Generic",
-        fqbn: "esp8266:esp8266:generic",
-        variant: "esp8266",
-        flash_kb: 1024, ram_kb: 80,
-        toolchain: Toolchain::Esp8266,
-        defines: &["ARDUINO_ESP8266_GENERIC", "ARDUINO_ARCH_ESP8266", "ESP8266"],
-    },
-    Board {
-        id: "d1_mini", name: "Wemos D1 Mini",
-        fqbn: "esp8266:esp8266:d1_mini",
-        variant: "d1_mini",
-        flash_kb: 4096, ram_kb: 80,
-        toolchain: Toolchain::Esp8266,
-        defines: &["ARDUINO_ESP8266_WEMOS_D1MINI", "ARDUINO_ARCH_ESP8266", "ESP8266"],
-    },
-    Board {
-        id: "nodemcu", name: "NodeMCU 1.0 (ESP-12E)",
-        fqbn: "esp8266:esp8266:nodemcuv2",
-        variant: "nodemcu",
-        flash_kb: 4096, ram_kb: 80,
-        toolchain: Toolchain::Esp8266,
-        defines: &["ARDUINO_ESP8266_NODEMCU_ESP12E", "ARDUINO_ARCH_ESP8266", "ESP8266"],
-    },
-];
\ No newline at end of file
+/// Parse a `--board-option` value: `menu=choice`.
+pub fn parse_option(s: &str) -> std::result::Result<(String, String), String> {
+    let (menu, choice) = s.split_once('=')
+        .ok_or_else(|| format!("expected MENU=CHOICE, got `{}`", s))?;
+    Ok((menu.trim().to_owned(), choice.trim().to_owned()))
+}
+
+/// Serial rates avrdude bootloaders are known to sync at.
+const STD_BAUDS: &[u32] = &[9600, 19200, 38400, 57600, 115200];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pick(options: &[&str]) -> Vec<(String, String)> {
+        options.iter().map(|o| parse_option(o).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_parse_option() {
+        assert_eq!(parse_option("cpu=atmega328old"), Ok(("cpu".into(), "atmega328old".into())));
+        assert_eq!(parse_option(" FlashSize = 8M "), Ok(("FlashSize".into(), "8M".into())));
+        assert_eq!(parse_option("a=b=c"), Ok(("a".into(), "b=c".into())));
+        assert_eq!(parse_option("cpu"), Err("expected MENU=CHOICE, got `cpu`".into()));
+    }
+
+    #[test]
+    fn test_avr_option() {
+        let nano = Board::find("nano").unwrap();
+        let old = nano.with_options(&pick(&["cpu=atmega328old"])).unwrap();
+        assert_eq!(old.fqbn, "arduino:avr:nano:cpu=atmega328old");
+        // The option's bootloader and baud replace the board's.
+        assert_eq!(old.upload_baud(None).unwrap(), Some(57600));
+        assert_eq!(old.bootloader.unwrap().file, "atmega/ATmegaBOOT_168_atmega328.hex");
+        assert_eq!(old.upload_baud, None);
+        // The board itself is left alone.
+        assert_eq!(nano.fqbn, "arduino:avr:nano");
+        assert_eq!(nano.upload_baud(None).unwrap(), Some(115200));
+
+        let same = nano.with_options(&pick(&["cpu=atmega328"])).unwrap();
+        assert_eq!((same.clock_mhz, same.flash_kb, &same.defines), (nano.clock_mhz, nano.flash_kb, &nano.defines));
+        assert_eq!(nano.with_options(&[]).unwrap().fqbn, nano.fqbn);
+    }
+
+    #[test]
+    fn test_esp_options() {
+        let esp32 = Board::find("esp32").unwrap();
+        let board = esp32.with_options(&pick(&["FlashSize=16M", "PartitionScheme=huge_app", "FlashMode=qio"])).unwrap();
+        // Appended in the order given, after the first with a colon.
+        assert_eq!(board.fqbn, "esp32:esp32:esp32:FlashSize=16M,PartitionScheme=huge_app,FlashMode=qio");
+        assert_eq!(board.flash_kb, 16384);
+        assert_eq!(board.partition_scheme, Some("huge_app"));
+        assert_eq!(board.flash_mode, Some("qio"));
+        assert_eq!((esp32.partition_scheme, esp32.flash_mode), (None, None));
+    }
+
+    #[test]
+    fn test_defines_and_existing_menus() {
+        let blackpill = Board::find("blackpill_f411").unwrap();
+        let board = blackpill.with_options(&pick(&["usb=CDCgen"])).unwrap();
+        // The FQBN already names a menu choice: the next follows a comma.
+        assert_eq!(board.fqbn, "STMicroelectronics:stm32:GenF4:pnum=BLACKPILL_F411CE,usb=CDCgen");
+        assert_eq!(&board.defines[..blackpill.defines.len()], &blackpill.defines[..]);
+        assert_eq!(&board.defines[blackpill.defines.len()..], ["USBCON", "USBD_USE_CDC"]);
+    }
+
+    #[test]
+    fn test_bad_options() {
+        let esp32 = Board::find("esp32").unwrap();
+        let err = |board: &Board, options: &[&str]| board.with_options(&pick(options)).unwrap_err().to_string();
+
+        let menu = err(esp32, &["Flash=4M"]);
+        assert!(menu.contains("Flash") && menu.contains("FlashMode, FlashSize, PartitionScheme"), "{}", menu);
+        let choice = err(esp32, &["FlashSize=2M"]);
+        assert!(choice.contains("FlashSize=2M") && choice.contains("4M, 8M, 16M"), "{}", choice);
+        assert!(err(esp32, &["FlashMode=qio", "FlashMode=dio"]).contains("given twice"));
+        assert!(err(Board::find("uno").unwrap(), &["cpu=atmega328"]).contains("none"));
+    }
+
+    #[test]
+    fn test_sorted_choices() {
+        let esp32 = Board::find("esp32").unwrap();
+        assert_eq!(sorted_choices(&esp32.options["FlashSize"]), ["4M", "8M", "16M"]);
+        assert_eq!(sorted_choices(&esp32.options["FlashMode"]), ["dio", "dout", "qio", "qout"]);
+    }
+}
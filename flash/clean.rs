@@ -0,0 +1,155 @@
+// ─────────────────────────────────────────────────────────────────────────────
+//  tsuki-flash :: clean  —  `clean`: delete build intermediates and caches
+//
+//  A plain clean removes what a build dir holds besides the firmware: the
+//  sketch and library objects and the build cache manifest. The next compile
+//  starts over, and `upload` still has something to send. The core archives
+//  are kept, as they only change with the core or the flags, and rebuilding
+//  them is most of a compile. `--deep` removes them too, with the global
+//  object cache and any half-finished module downloads.
+// ─────────────────────────────────────────────────────────────────────────────
+
+use std::path::{Path, PathBuf};
+
+use colored::Colorize;
+use walkdir::WalkDir;
+
+use crate::compile::objcache;
+use crate::error::Result;
+use crate::modules;
+
+/// Removed from the build dir by every clean.
+const INTERMEDIATES: &[&str] = &["sketch", "libs", ".tsuki-cache.json", "boot2.o", "memmap_default.ld"];
+/// Core objects and archives, removed by `--deep`. STM32 builds have a
+/// second archive for the core's SrcWrapper library.
+const CORE: &[&str] = &["core", "core.a", "srcwrapper", "srcwrapper.a"];
+
+/// Delete what `clean` covers, or with `dry_run` only list it, with the
+/// space it takes.
+pub fn run(build_dir: &Path, deep: bool, dry_run: bool) -> Result<()> {
+    let targets = targets(build_dir, deep)?;
+    if targets.is_empty() {
+        println!("{} Nothing to clean in {}", "✓".green().bold(), build_dir.display());
+        return Ok(());
+    }
+
+    let mut total = 0;
+    for path in &targets {
+        let bytes = disk_usage(path);
+        total += bytes;
+        if dry_run {
+            println!("  {:>9}  {}", human(bytes), path.display());
+            continue;
+        }
+        println!("{} {:>9}  {}", "Removing".cyan(), human(bytes), path.display());
+        if path.is_dir() {
+            std::fs::remove_dir_all(path)?;
+        } else {
+            std::fs::remove_file(path)?;
+        }
+    }
+
+    if dry_run {
+        println!("{} would be reclaimed {}", human(total).bold(), "(dry run: nothing was deleted)".dimmed());
+    } else {
+        println!("{} Reclaimed {}", "✓".green().bold(), human(total).bold());
+    }
+    Ok(())
+}
+
+/// What there is to delete, in the order it is listed.
+fn targets(build_dir: &Path, deep: bool) -> Result<Vec<PathBuf>> {
+    let names = INTERMEDIATES.iter().chain(if deep { CORE } else { &[] });
+    let mut targets: Vec<PathBuf> = names.map(|name| build_dir.join(name)).collect();
+    if deep {
+        targets.push(objcache::root()?);
+        if let Ok(root) = modules::modules_root() {
+            targets.push(root.join("staging"));
+        }
+    }
+    targets.retain(|p| p.exists());
+    Ok(targets)
+}
+
+fn disk_usage(path: &Path) -> u64 {
+    WalkDir::new(path).into_iter().flatten()
+        .filter(|e| e.file_type().is_file())
+        .map(|e| e.metadata().map_or(0, |m| m.len()))
+        .sum()
+}
+
+/// `512 B`, `4.0 KB`, `12.3 MB`.
+fn human(bytes: u64) -> String {
+    match bytes {
+        b if b < 1024        => format!("{} B", b),
+        b if b < 1024 * 1024 => format!("{:.1} KB", b as f64 / 1024.0),
+        b                    => format!("{:.1} MB", b as f64 / (1024.0 * 1024.0)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A build dir after an AVR compile of `blink`.
+    fn build_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("tsuki-clean-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        for sub in ["sketch", "libs/Servo", "core"] {
+            std::fs::create_dir_all(dir.join(sub)).unwrap();
+        }
+        for (file, bytes) in [
+            ("sketch/1a2b3c4d_blink.cpp.o", 1000), ("libs/Servo/Servo.a", 2000), ("core/wiring.c.o", 500),
+            ("core.a", 4000), (".tsuki-cache.json", 24),
+            ("blink.hex", 3000), ("blink.elf", 9000), ("blink.meta.json", 300),
+        ] {
+            std::fs::write(dir.join(file), vec![0u8; bytes]).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn test_plain_clean() {
+        let dir = build_dir("plain");
+        assert_eq!(targets(&dir, false).unwrap(), [dir.join("sketch"), dir.join("libs"), dir.join(".tsuki-cache.json")]);
+
+        run(&dir, false, true).unwrap();
+        assert!(dir.join("sketch").exists(), "a dry run deletes nothing");
+
+        run(&dir, false, false).unwrap();
+        for gone in ["sketch", "libs", ".tsuki-cache.json"] {
+            assert!(!dir.join(gone).exists(), "{}", gone);
+        }
+        // The firmware, its metadata and the core are kept.
+        for kept in ["blink.hex", "blink.elf", "blink.meta.json", "core.a", "core/wiring.c.o"] {
+            assert!(dir.join(kept).exists(), "{}", kept);
+        }
+        assert!(targets(&dir, false).unwrap().is_empty());
+        run(&dir, false, false).unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_deep_clean_targets() {
+        let dir = build_dir("deep");
+        std::fs::write(dir.join("srcwrapper.a"), "").unwrap();
+        // Only list here: a deep clean also removes the user's global caches.
+        let in_build: Vec<PathBuf> = targets(&dir, true).unwrap().into_iter().filter(|p| p.starts_with(&dir)).collect();
+        assert_eq!(in_build, [
+            dir.join("sketch"), dir.join("libs"), dir.join(".tsuki-cache.json"),
+            dir.join("core"), dir.join("core.a"), dir.join("srcwrapper.a"),
+        ]);
+        assert_eq!(disk_usage(&dir.join("core")), 500);
+        assert_eq!(disk_usage(&dir), 1000 + 2000 + 500 + 4000 + 24 + 3000 + 9000 + 300);
+        assert_eq!(disk_usage(&dir.join("missing")), 0);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_human() {
+        assert_eq!(human(0), "0 B");
+        assert_eq!(human(1023), "1023 B");
+        assert_eq!(human(4096), "4.0 KB");
+        assert_eq!(human(12_900_000), "12.3 MB");
+    }
+}
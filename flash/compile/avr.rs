@@ -1,11 +1,13 @@
 // ─────────────────────────────────────────────────────────────────────────────
 //  tsuki-flash :: compile :: avr
 //
-//  Compiles Arduino AVR sketches using avr-gcc/avr-g++ directly.
+//  Compiles Arduino AVR sketches using avr-gcc/avr-g++ directly. megaAVR and
+//  tinyAVR boards (arduino:megaavr, megaTinyCore) go through here too.
 //
 //  Pipeline:
 //    1. Discover + compile Arduino core → core.a  (cached, rebuilt only if stale)
 //    2. Compile sketch .cpp files in PARALLEL     (rayon, incremental cache)
+//       and the libraries it includes → libs/<name>/<name>.a
 //    3. Link everything → firmware.elf
 //    4. avr-objcopy → firmware.hex  +  firmware.with_bootloader.hex
 //    5. avr-size report
@@ -13,20 +15,24 @@
 
 use std::path::{Path, PathBuf};
 use std::process::Command;
-use std::sync::Mutex;
 use rayon::prelude::*;
 use walkdir::WalkDir;
 
-use crate::boards::Board;
+use crate::boards::{Board, Toolchain};
 use crate::error::{FlashError, Result};
+use crate::events::{self, Event};
+use crate::process::{self, Stage};
 use crate::sdk::{SdkPaths};
 use super::cache::{CacheManifest, obj_path, hash_str};
-use super::{CompileRequest, CompileResult};
+use super::libraries;
+use super::objcache::ObjCache;
+use super::{CompileRequest, CompileResult, Failures};
 
 pub fn run(req: &CompileRequest, board: &Board, sdk: &SdkPaths) -> Result<CompileResult> {
     let mcu = board.avr_mcu()
         .ok_or_else(|| FlashError::Other(format!("Board '{}' is not an AVR board", board.id)))?;
 
+    let f_cpu = board.clock_hz(req.cpu_mhz)?;
     std::fs::create_dir_all(&req.build_dir)?;
 
     // Resolve full paths to compiler binaries
@@ -41,12 +47,33 @@ pub fn run(req: &CompileRequest, board: &Board, sdk: &SdkPaths) -> Result<Compil
         .copied()
         .unwrap_or("ARDUINO_AVR_UNO");
 
-    let common_flags: Vec<String> = vec![
+    // 32u4 boards enumerate over native USB; the core's USB stack reads its
+    // vendor/product ids from these.
+    let usb_defines: Vec<String> = board.defines.iter()
+        .filter(|d| d.starts_with("USB_"))
+        .map(|d| format!("-D{}", d))
+        .collect();
+
+    // megaAVR cores take their configuration (timer for millis(), pin
+    // emulation, …) from the board's defines, so all of them go in.
+    let mega = matches!(board.toolchain, Toolchain::MegaAvr { .. });
+    let board_defines: Vec<String> = if mega {
+        board.defines.iter().map(|d| format!("-D{}", d)).collect()
+    } else {
+        // Plus whatever a --board-option adds.
+        [board_define, "ARDUINO_ARCH_AVR"].into_iter()
+            .chain(board.defines.iter().copied().filter(|d| !d.starts_with("ARDUINO_") && !d.starts_with("USB_")))
+            .map(|d| format!("-D{}", d))
+            .collect()
+    };
+
+    let mut common_flags: Vec<String> = vec![
         format!("-mmcu={}", mcu),
-        format!("-DF_CPU={}L", board.f_cpu()),
+        format!("-DF_CPU={}L", f_cpu),
         format!("-DARDUINO={}", arduino_ver),
-        format!("-D{}", board_define),
-        "-DARDUINO_ARCH_AVR".into(),
+    ];
+    common_flags.extend(board_defines);
+    common_flags.extend([
         "-Os".into(),
         "-w".into(),
         "-ffunction-sections".into(),
@@ -55,7 +82,13 @@ pub fn run(req: &CompileRequest, board: &Board, sdk: &SdkPaths) -> Result<Compil
         "-MMD".into(),
         format!("-I{}", sdk.core_dir.display()),
         format!("-I{}", sdk.variant_dir.display()),
-    ];
+    ]);
+    // arduino:megaavr builds on ArduinoCore-API, shipped in the core's api/.
+    let api_dir = sdk.core_dir.join("api");
+    if mega && api_dir.is_dir() {
+        common_flags.push(format!("-I{}", api_dir.display()));
+    }
+    common_flags.extend(usb_defines.iter().cloned());
 
     // Add extra include dirs (external libraries)
     let mut includes: Vec<String> = common_flags.clone();
@@ -65,29 +98,34 @@ pub fn run(req: &CompileRequest, board: &Board, sdk: &SdkPaths) -> Result<Compil
     if let Some(ld) = &sdk.libraries_dir {
         includes.push(format!("-I{}", ld.display()));
     }
+    includes.extend(req.extra_flags.define_flags());
 
-    let cflags: Vec<&str> = vec!["-x", "c", "-std=gnu11"];
+    let mut cflags: Vec<&str> = vec!["-x", "c", "-std=gnu11"];
     // hoist the formatted string so it lives long enough to be borrowed
     let cxx_std_flag = format!("-std=gnu++{}", req.cpp_std.trim_start_matches("c++"));
-    let cxxflags: Vec<&str> = vec![
+    let mut cxxflags: Vec<&str> = vec![
         "-x", "c++",
         &cxx_std_flag,
         "-fpermissive", "-fno-exceptions",
         "-fno-threadsafe-statics",
         "-Wno-error=narrowing",
     ];
+    cflags.extend(req.extra_flags.cflags.iter().map(String::as_str));
+    cxxflags.extend(req.extra_flags.cxxflags.iter().map(String::as_str));
 
     // ── Flags fingerprint for incremental cache ───────────────────────────
     let flags_sig = hash_str(&format!("{:?}{:?}{:?}", includes, cflags, cxxflags));
-    let core_sig  = hash_str(&format!("core{}{}", mcu, sdk.sdk_version));
+    let core_sig  = hash_str(&format!("core{}{}{}{:?}{:?}{:?}{:?}", mcu, f_cpu, sdk.sdk_version, usb_defines,
+                                      req.extra_flags.defines, cflags, cxxflags));
 
     // ── Step 1: Build core.a ──────────────────────────────────────────────
     let core_dir  = req.build_dir.join("core");
     std::fs::create_dir_all(&core_dir)?;
     let core_a = req.build_dir.join("core.a");
 
-    build_core(&cc, &cxx, &ar, &sdk.core_dir, &core_dir, &core_a,
-               &includes, &cflags, &cxxflags, &core_sig, req.verbose)?;
+    let obj_cache = ObjCache::open(req.obj_cache);
+    build_core(&cc, &cxx, &ar, &sdk.core_dir, if mega { 2 } else { 1 }, &core_dir, &core_a,
+               &includes, &cflags, &cxxflags, &core_sig, obj_cache.as_ref(), req.verbose)?;
 
     // ── Step 2: Compile sketch sources ───────────────────────────────────
     let sketch_dir = req.build_dir.join("sketch");
@@ -102,7 +140,7 @@ pub fn run(req: &CompileRequest, board: &Board, sdk: &SdkPaths) -> Result<Compil
     }
 
     // Parallel compilation with error collection
-    let errors: Mutex<Vec<String>> = Mutex::new(Vec::new());
+    let failures = Failures::default();
     let mut manifest = CacheManifest::load(&sketch_dir);
 
     let obj_files: Vec<PathBuf> = sources.par_iter().map(|src| {
@@ -111,6 +149,7 @@ pub fn run(req: &CompileRequest, board: &Board, sdk: &SdkPaths) -> Result<Compil
             if req.verbose {
                 eprintln!("  [cache] {}", src.display());
             }
+            events::emit(Event::Fresh { unit: "sketch", file: src, cache: "build" });
             return obj;
         }
 
@@ -131,15 +170,9 @@ pub fn run(req: &CompileRequest, board: &Board, sdk: &SdkPaths) -> Result<Compil
         if req.verbose {
             eprintln!("  [compile] {}", src.display());
         }
+        events::emit(Event::Compiling { unit: "sketch", file: src });
 
-        let out = cmd.output().expect("failed to spawn compiler");
-        if !out.status.success() {
-            let stderr = String::from_utf8_lossy(&out.stderr).to_string();
-            errors.lock().unwrap().push(format!(
-                "In {}:\n{}", src.display(), stderr
-            ));
-        }
-
+        failures.compile(&mut cmd, &obj, || format!("In {}:\n", src.display()));
         obj
     }).collect();
 
@@ -147,17 +180,17 @@ pub fn run(req: &CompileRequest, board: &Board, sdk: &SdkPaths) -> Result<Compil
     for src in &sources {
         let obj = obj_path(&sketch_dir, src);
         if obj.exists() {
-            manifest.record(src, &flags_sig);
+            manifest.record(src, &obj, &flags_sig);
         }
     }
     let _ = manifest.save(&sketch_dir);
 
-    let compile_errors = errors.into_inner().unwrap();
-    if !compile_errors.is_empty() {
-        return Err(FlashError::CompileFailed {
-            output: compile_errors.join("\n\n"),
-        });
-    }
+    failures.check("\n\n")?;
+
+    // ── Libraries ─────────────────────────────────────────────────────────
+    let lib_archives = libraries::build(&req.libraries, &cc, &cxx, &ar, &req.build_dir.join("libs"),
+                                        &includes, &cflags, &cxxflags, &flags_sig,
+                                        obj_cache.as_ref(), req.verbose)?;
 
     // ── Step 3: Link elf ──────────────────────────────────────────────────
     let elf_path = req.build_dir.join(format!("{}.elf", req.project_name));
@@ -171,12 +204,15 @@ pub fn run(req: &CompileRequest, board: &Board, sdk: &SdkPaths) -> Result<Compil
     for obj in &obj_files {
         link_cmd.arg(obj);
     }
+    link_cmd.args(libraries::link_group(&lib_archives));
     link_cmd.arg(&core_a);
+    link_cmd.args(&req.extra_flags.ldflags);
     link_cmd.args(["-L", req.build_dir.to_str().unwrap()]);
     link_cmd.arg("-lm");
     link_cmd.arg("-o").arg(&elf_path);
 
-    let link_out = link_cmd.output()?;
+    events::emit(Event::Linking { output: &elf_path });
+    let link_out = process::output(&mut link_cmd, Stage::Link)?;
     if !link_out.status.success() {
         return Err(FlashError::LinkFailed {
             output: String::from_utf8_lossy(&link_out.stderr).to_string(),
@@ -205,7 +241,9 @@ pub fn run(req: &CompileRequest, board: &Board, sdk: &SdkPaths) -> Result<Compil
         hex_path: Some(hex_path),
         bin_path: None,
         elf_path: Some(elf_path),
+        uf2_path: None,
         size_info,
+        usage:    None,
     })
 }
 
@@ -213,12 +251,16 @@ pub fn run(req: &CompileRequest, board: &Board, sdk: &SdkPaths) -> Result<Compil
 //  Core library compilation
 // ─────────────────────────────────────────────────────────────────────────────
 
-fn build_core(
+/// Compile the core sources up to `depth` directories below `core_src`
+/// (1: only its own files) and archive them into `core_a`.
+#[allow(clippy::too_many_arguments)]
+pub(super) fn build_core(
     cc: &str, cxx: &str, ar: &str,
-    core_src: &Path, core_obj_dir: &Path, core_a: &Path,
+    core_src: &Path, depth: usize, core_obj_dir: &Path, core_a: &Path,
     includes: &[String],
     cflags: &[&str], cxxflags: &[&str],
     core_sig: &str,
+    obj_cache: Option<&ObjCache>,
     verbose: bool,
 ) -> Result<()> {
     // Check if core.a is already up-to-date via a sentinel file
@@ -234,7 +276,8 @@ fn build_core(
     }
 
     let core_sources: Vec<PathBuf> = WalkDir::new(core_src)
-        .max_depth(1)
+        .max_depth(depth)
+        .follow_links(true)
         .into_iter()
         .flatten()
         .filter(|e| e.file_type().is_file())
@@ -246,7 +289,7 @@ fn build_core(
         .collect();
 
     // Compile core sources in parallel
-    let errors: Mutex<Vec<String>> = Mutex::new(Vec::new());
+    let failures = Failures::default();
 
     let obj_files: Vec<PathBuf> = core_sources.par_iter().map(|src| {
         let obj = obj_path(core_obj_dir, src);
@@ -256,33 +299,39 @@ fn build_core(
         let is_asm = ext == "S";
         let compiler = if is_c || is_asm { cc } else { cxx };
 
-        let mut cmd = Command::new(compiler);
-        cmd.args(includes);
-
+        let mut args: Vec<&str> = includes.iter().map(String::as_str).collect();
         if is_asm {
-            cmd.arg("-x").arg("assembler-with-cpp");
+            args.extend(["-x", "assembler-with-cpp"]);
         } else if is_c {
-            cmd.args(cflags);
+            args.extend(cflags);
         } else {
-            cmd.args(cxxflags);
+            args.extend(cxxflags);
         }
 
+        let key = obj_cache.and_then(|c| c.key(compiler, &args, src));
+        if let (Some(cache), Some(key)) = (obj_cache, &key) {
+            if cache.fetch(key, &obj) {
+                if verbose { eprintln!("  [obj-cache] {}", src.display()); }
+                events::emit(Event::Fresh { unit: "core", file: src, cache: "objects" });
+                return obj;
+            }
+        }
+        events::emit(Event::Compiling { unit: "core", file: src });
+
+        let mut cmd = Command::new(compiler);
+        cmd.args(&args);
         cmd.arg("-c").arg(src).arg("-o").arg(&obj);
 
-        let out = cmd.output().expect("compiler spawn failed");
-        if !out.status.success() {
-            errors.lock().unwrap().push(
-                String::from_utf8_lossy(&out.stderr).to_string()
-            );
+        if failures.compile(&mut cmd, &obj, String::new) {
+            if let (Some(cache), Some(key)) = (obj_cache, &key) {
+                cache.store(key, src, &obj);
+            }
         }
 
         obj
     }).collect();
 
-    let errs = errors.into_inner().unwrap();
-    if !errs.is_empty() {
-        return Err(FlashError::CompileFailed { output: errs.join("\n") });
-    }
+    failures.check("\n")?;
 
     // Archive into core.a
     let mut ar_cmd = Command::new(ar);
@@ -293,7 +342,7 @@ fn build_core(
         }
     }
 
-    let ar_out = ar_cmd.output()?;
+    let ar_out = process::output(&mut ar_cmd, Stage::Link)?;
     if !ar_out.status.success() {
         return Err(FlashError::CompileFailed {
             output: String::from_utf8_lossy(&ar_out.stderr).to_string(),
@@ -310,7 +359,7 @@ fn build_core(
 //  Helpers
 // ─────────────────────────────────────────────────────────────────────────────
 
-fn collect_sketch_sources(sketch_dir: &Path) -> Result<Vec<PathBuf>> {
+pub(super) fn collect_sketch_sources(sketch_dir: &Path) -> Result<Vec<PathBuf>> {
     let mut sources = Vec::new();
     for entry in WalkDir::new(sketch_dir).max_depth(3).into_iter().flatten() {
         if !entry.file_type().is_file() { continue; }
@@ -323,7 +372,7 @@ fn collect_sketch_sources(sketch_dir: &Path) -> Result<Vec<PathBuf>> {
     Ok(sources)
 }
 
-fn resolve_tool(bin_dir: &Path, name: &str) -> String {
+pub(super) fn resolve_tool(bin_dir: &Path, name: &str) -> String {
     if bin_dir.as_os_str().is_empty() {
         return name.to_owned(); // rely on PATH
     }
@@ -331,8 +380,8 @@ fn resolve_tool(bin_dir: &Path, name: &str) -> String {
     if p.exists() { p.to_string_lossy().to_string() } else { name.to_owned() }
 }
 
-fn run_tool(program: &str, args: &[&str]) -> Result<()> {
-    let out = Command::new(program).args(args).output()?;
+pub(super) fn run_tool(program: &str, args: &[&str]) -> Result<()> {
+    let out = process::output(Command::new(program).args(args), Stage::Link)?;
     if !out.status.success() {
         return Err(FlashError::CompileFailed {
             output: String::from_utf8_lossy(&out.stderr).to_string(),
@@ -343,16 +392,16 @@ fn run_tool(program: &str, args: &[&str]) -> Result<()> {
 
 fn firmware_size(bin_dir: &Path, elf: &Path, board: &Board) -> String {
     let avr_size = resolve_tool(bin_dir, "avr-size");
-    let out = Command::new(&avr_size)
-        .args(["--format=avr", &format!("--mcu={}", board.avr_mcu().unwrap_or("atmega328p")), elf.to_str().unwrap()])
-        .output();
+    let out = process::output(Command::new(&avr_size)
+        .args(["--format=avr", &format!("--mcu={}", board.avr_mcu().unwrap_or("atmega328p")), elf.to_str().unwrap()]),
+        Stage::Link);
 
     match out {
         Ok(o) if o.status.success() =>
             String::from_utf8_lossy(&o.stdout).trim().to_string(),
         _ => {
             // Fallback: plain size
-            let o = Command::new(&avr_size).arg(elf).output();
+            let o = process::output(Command::new(&avr_size).arg(elf), Stage::Link);
             match o {
                 Ok(o) => String::from_utf8_lossy(&o.stdout).trim().to_string(),
                 Err(_) => "(size unknown)".into(),
@@ -2,13 +2,17 @@
 //  tsuki-flash :: compile :: cache
 //
 //  Stores a per-file SHA-256 fingerprint alongside each .o file so that
-//  unchanged source files are never recompiled.
+//  unchanged source files are never recompiled. The headers each object was
+//  built against come from the `.d` file the compiler writes with `-MMD`;
+//  their size and mtime are recorded too, so editing a header rebuilds the
+//  sources that include it.
 //
 //  Cache manifest lives at <build_dir>/.tsuki-cache.json
 // ─────────────────────────────────────────────────────────────────────────────
 
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
 use sha2::{Sha256, Digest};
 use serde::{Deserialize, Serialize};
 
@@ -20,6 +24,9 @@ pub struct CacheManifest {
     pub entries: HashMap<String, String>,
     /// Compiler flags hash — if flags change, everything is stale.
     pub flags_hash: String,
+    /// Maps source path → the headers it included → their `stamp`.
+    #[serde(default)]
+    pub deps: HashMap<String, HashMap<String, String>>,
 }
 
 impl CacheManifest {
@@ -37,31 +44,89 @@ impl CacheManifest {
     pub fn save(&self, build_dir: &Path) -> std::io::Result<()> {
         let path = build_dir.join(MANIFEST_FILE);
         let json = serde_json::to_string_pretty(self)
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+            .map_err(std::io::Error::other)?;
         std::fs::write(path, json)
     }
 
     /// True if `src_path` is up-to-date and its output object file exists.
+    /// Sources whose headers are unknown (no `.d` file at the last build)
+    /// are never fresh.
     pub fn is_fresh(&self, src: &Path, obj: &Path, flags_hash: &str) -> bool {
         if self.flags_hash != flags_hash { return false; }
         if !obj.exists() { return false; }
         let key = src.to_string_lossy().to_string();
-        match self.entries.get(&key) {
+        let source_fresh = match self.entries.get(&key) {
             Some(cached) => hash_file(src).as_deref() == Some(cached.as_str()),
             None => false,
-        }
+        };
+        source_fresh && self.deps.get(&key).is_some_and(|headers| {
+            headers.iter().all(|(h, s)| stamp(Path::new(h)).as_deref() == Some(s.as_str()))
+        })
     }
 
-    /// Record a successfully compiled source file.
-    pub fn record(&mut self, src: &Path, flags_hash: &str) {
+    /// Record a successfully compiled source file and the headers its
+    /// object `obj` depends on.
+    pub fn record(&mut self, src: &Path, obj: &Path, flags_hash: &str) {
         let key = src.to_string_lossy().to_string();
         if let Some(hash) = hash_file(src) {
-            self.entries.insert(key, hash);
+            self.entries.insert(key.clone(), hash);
+        }
+        match read_deps(obj, src) {
+            Some(headers) => {
+                let stamps = headers.iter()
+                    .filter_map(|h| Some((h.to_string_lossy().to_string(), stamp(h)?)))
+                    .collect();
+                self.deps.insert(key, stamps);
+            }
+            None => { self.deps.remove(&key); }
         }
         self.flags_hash = flags_hash.to_owned();
     }
 }
 
+/// The headers listed in the `.d` file next to `obj` (`-MMD` names it after
+/// the object), other than `src` itself. None if there is no such file.
+pub(super) fn read_deps(obj: &Path, src: &Path) -> Option<Vec<PathBuf>> {
+    let text = std::fs::read_to_string(obj.with_extension("d")).ok()?;
+    // `obj.o: src.cpp a.h \<newline> b.h`, spaces in paths escaped as `\ `.
+    // Later rules are -MP's phony targets; only the first one matters.
+    let mut deps = Vec::new();
+    let mut word = String::new();
+    let mut target_done = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&'\n') => { chars.next(); }
+            '\\' if chars.peek() == Some(&'\r') => { chars.next(); chars.next_if_eq(&'\n'); }
+            '\\' if chars.peek() == Some(&' ') => word.push(chars.next()?),
+            ' ' | '\t' | '\r' | '\n' => {
+                // `C:\…` is a drive letter, not the end of the target.
+                if !target_done && word.ends_with(':') && word.len() > 2 {
+                    target_done = true;
+                } else if target_done && !word.is_empty() {
+                    deps.push(PathBuf::from(&word));
+                }
+                word.clear();
+                if c == '\n' && target_done { break; }
+            }
+            _ => word.push(c),
+        }
+    }
+    if target_done && !word.is_empty() {
+        deps.push(PathBuf::from(word));
+    }
+    deps.retain(|d| d != src);
+    Some(deps)
+}
+
+/// A header's size and modification time; changes whenever it is edited,
+/// replaced or restored from an older copy.
+fn stamp(path: &Path) -> Option<String> {
+    let meta = std::fs::metadata(path).ok()?;
+    let mtime = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some(format!("{}:{}", mtime.as_nanos(), meta.len()))
+}
+
 /// SHA-256 of the file content, hex-encoded.
 pub fn hash_file(path: &Path) -> Option<String> {
     let data = std::fs::read(path).ok()?;
@@ -91,4 +156,82 @@ pub fn obj_path(build_dir: &Path, src: &Path) -> PathBuf {
     let short = &hex::encode(Sha256::digest(full.as_bytes()))[..8];
 
     build_dir.join(format!("{short}_{fname}.o"))
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("tsuki-cache-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn deps_of(depfile: &str) -> Option<Vec<PathBuf>> {
+        // Tests run in parallel; each depfile gets its own directory.
+        static N: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+        let dir = scratch(&format!("deps{}", N.fetch_add(1, std::sync::atomic::Ordering::Relaxed)));
+        let obj = dir.join("sketch.cpp.o");
+        std::fs::write(obj.with_extension("d"), depfile).unwrap();
+        let deps = read_deps(&obj, Path::new("/src/sketch.cpp"));
+        std::fs::remove_dir_all(&dir).ok();
+        deps
+    }
+
+    #[test]
+    fn test_read_deps() {
+        let deps = deps_of("/b/sketch.cpp.o: /src/sketch.cpp /src/a.h \\\n  /src/my\\ lib/b.h \\\r\n /src/c.h\n\n\
+                            /src/a.h:\n\n/src/my\\ lib/b.h:\n").unwrap();
+        assert_eq!(deps, [
+            PathBuf::from("/src/a.h"),
+            PathBuf::from("/src/my lib/b.h"),
+            PathBuf::from("/src/c.h"),
+        ]);
+    }
+
+    #[test]
+    fn test_read_deps_edge_cases() {
+        // A Windows target keeps its drive letter, and no trailing newline.
+        assert_eq!(deps_of("C:\\b\\sketch.cpp.o: C:\\src\\a.h").unwrap(), [PathBuf::from("C:\\src\\a.h")]);
+        assert_eq!(deps_of("sketch.cpp.o: /src/sketch.cpp\n").unwrap(), Vec::<PathBuf>::new());
+        assert_eq!(deps_of("").unwrap(), Vec::<PathBuf>::new());
+        assert!(read_deps(Path::new("/nonexistent/sketch.cpp.o"), Path::new("sketch.cpp")).is_none());
+    }
+
+    #[test]
+    fn test_header_change_is_stale() {
+        let dir = scratch("fresh");
+        let (src, header, obj) = (dir.join("main.cpp"), dir.join("pins.h"), dir.join("main.o"));
+        std::fs::write(&src, "#include \"pins.h\"\n").unwrap();
+        std::fs::write(&header, "#define LED 13\n").unwrap();
+        std::fs::write(&obj, "").unwrap();
+
+        let mut manifest = CacheManifest::default();
+        manifest.record(&src, &obj, "flags");
+        // No `.d` file: the headers are unknown.
+        assert!(!manifest.is_fresh(&src, &obj, "flags"));
+
+        std::fs::write(obj.with_extension("d"), format!("main.o: {} {}\n", src.display(), header.display())).unwrap();
+        manifest.record(&src, &obj, "flags");
+        assert!(manifest.is_fresh(&src, &obj, "flags"));
+        assert!(!manifest.is_fresh(&src, &obj, "other flags"));
+
+        manifest.save(&dir).unwrap();
+        let manifest = CacheManifest::load(&dir);
+        assert!(manifest.is_fresh(&src, &obj, "flags"));
+
+        std::fs::write(&header, "#define LED 2\n#define BTN 0\n").unwrap();
+        assert!(!manifest.is_fresh(&src, &obj, "flags"));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_obj_path() {
+        let a = obj_path(Path::new("/b"), Path::new("/x/main.cpp"));
+        let b = obj_path(Path::new("/b"), Path::new("/y/main.cpp"));
+        assert_ne!(a, b);
+        assert!(a.starts_with("/b"));
+        assert!(a.to_string_lossy().ends_with("_main.cpp.o"));
+    }
+}
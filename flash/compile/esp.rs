@@ -5,34 +5,44 @@
 //
 //  Pipeline:
 //    1. Compile sketch sources  (parallel, incremental cache)
+//       and the libraries it includes → <lib>.a
 //    2. Link → firmware.elf
 //    3. esptool.py → firmware.bin  +  firmware.hex (for consistency)
+//    4. ESP32: the rest of the flash image next to it — bootloader,
+//       partition table (from the chosen scheme's .csv), boot_app0
 // ─────────────────────────────────────────────────────────────────────────────
 
 use std::path::{Path, PathBuf};
 use std::process::Command;
-use std::sync::Mutex;
 use rayon::prelude::*;
 use walkdir::WalkDir;
 
 use crate::boards::{Board, Toolchain};
 use crate::error::{FlashError, Result};
+use crate::events::{self, Event};
+use crate::process::{self, Stage};
 use crate::sdk::SdkPaths;
 use super::cache::{CacheManifest, hash_str, obj_path};
-use super::{CompileRequest, CompileResult};
+use super::libraries;
+use super::objcache::ObjCache;
+use super::partitions::PartitionTable;
+use super::{CompileRequest, CompileResult, Failures};
 
 pub fn run(req: &CompileRequest, board: &Board, sdk: &SdkPaths) -> Result<CompileResult> {
+    let f_cpu = board.clock_hz(req.cpu_mhz)?;
     std::fs::create_dir_all(&req.build_dir)?;
 
-    let (cc, cxx, is_esp32) = match &board.toolchain {
+    let (cc, cxx, ar, is_esp32) = match &board.toolchain {
         Toolchain::Esp32 { .. } => (
             resolve_tool(&sdk.toolchain_bin, "xtensa-esp32-elf-gcc"),
             resolve_tool(&sdk.toolchain_bin, "xtensa-esp32-elf-g++"),
+            resolve_tool(&sdk.toolchain_bin, "xtensa-esp32-elf-ar"),
             true,
         ),
         Toolchain::Esp8266 => (
             resolve_tool(&sdk.toolchain_bin, "xtensa-lx106-elf-gcc"),
             resolve_tool(&sdk.toolchain_bin, "xtensa-lx106-elf-g++"),
+            resolve_tool(&sdk.toolchain_bin, "xtensa-lx106-elf-ar"),
             false,
         ),
         _ => return Err(FlashError::Other("Not an ESP board".into())),
@@ -46,7 +56,7 @@ pub fn run(req: &CompileRequest, board: &Board, sdk: &SdkPaths) -> Result<Compil
 
     let common_flags: Vec<String> = {
         let mut f = vec![
-            format!("-DF_CPU={}L", board.f_cpu()),
+            format!("-DF_CPU={}L", f_cpu),
             "-DARDUINO=10819".into(),
             "-Os".into(), "-w".into(),
             "-ffunction-sections".into(), "-fdata-sections".into(),
@@ -55,7 +65,7 @@ pub fn run(req: &CompileRequest, board: &Board, sdk: &SdkPaths) -> Result<Compil
             format!("-I{}", sdk.core_dir.display()),
             format!("-I{}", sdk.variant_dir.display()),
         ];
-        for d in board.defines {
+        for d in &board.defines {
             f.push(format!("-D{}", d));
         }
         for extra in &req.lib_include_dirs {
@@ -64,15 +74,19 @@ pub fn run(req: &CompileRequest, board: &Board, sdk: &SdkPaths) -> Result<Compil
         for flag in arch_flags {
             f.push(flag.to_string());
         }
+        f.extend(req.extra_flags.define_flags());
         f
     };
 
-    let cxxflags = [
+    let cxx_std_flag = format!("-std=gnu++{}", req.cpp_std.trim_start_matches("c++"));
+    let cflags: Vec<&str> = req.extra_flags.cflags.iter().map(String::as_str).collect();
+    let mut cxxflags: Vec<&str> = vec![
         "-fpermissive", "-fno-exceptions", "-fno-threadsafe-statics",
-        &format!("-std=gnu++{}", req.cpp_std.trim_start_matches("c++")),
+        &cxx_std_flag,
     ];
+    cxxflags.extend(req.extra_flags.cxxflags.iter().map(String::as_str));
 
-    let flags_sig = hash_str(&format!("{:?}{:?}", common_flags, cxxflags));
+    let flags_sig = hash_str(&format!("{:?}{:?}{:?}", common_flags, cflags, cxxflags));
     let sketch_obj_dir = req.build_dir.join("sketch");
     std::fs::create_dir_all(&sketch_obj_dir)?;
 
@@ -81,43 +95,42 @@ pub fn run(req: &CompileRequest, board: &Board, sdk: &SdkPaths) -> Result<Compil
         return Err(FlashError::Other("No source files found".into()));
     }
 
-    let errors: Mutex<Vec<String>> = Mutex::new(Vec::new());
+    let failures = Failures::default();
     let mut manifest = CacheManifest::load(&sketch_obj_dir);
 
     let obj_files: Vec<PathBuf> = sources.par_iter().map(|src| {
         let obj = obj_path(&sketch_obj_dir, src);
         if manifest.is_fresh(src, &obj, &flags_sig) {
+            events::emit(Event::Fresh { unit: "sketch", file: src, cache: "build" });
             return obj;
         }
+        events::emit(Event::Compiling { unit: "sketch", file: src });
 
         let is_c = src.extension().and_then(|e| e.to_str()) == Some("c");
         let compiler = if is_c { &cc } else { &cxx };
 
         let mut cmd = Command::new(compiler);
         cmd.args(&common_flags);
-        if !is_c { cmd.args(&cxxflags); }
+        cmd.args(if is_c { &cflags } else { &cxxflags });
         cmd.arg("-c").arg(src).arg("-o").arg(&obj);
 
-        let out = cmd.output().expect("compiler spawn failed");
-        if !out.status.success() {
-            errors.lock().unwrap().push(
-                format!("In {}:\n{}", src.display(),
-                        String::from_utf8_lossy(&out.stderr))
-            );
-        }
+        failures.compile(&mut cmd, &obj, || format!("In {}:\n", src.display()));
         obj
     }).collect();
 
     for src in &sources {
         let obj = obj_path(&sketch_obj_dir, src);
-        if obj.exists() { manifest.record(src, &flags_sig); }
+        if obj.exists() { manifest.record(src, &obj, &flags_sig); }
     }
     let _ = manifest.save(&sketch_obj_dir);
 
-    let errs = errors.into_inner().unwrap();
-    if !errs.is_empty() {
-        return Err(FlashError::CompileFailed { output: errs.join("\n\n") });
-    }
+    failures.check("\n\n")?;
+
+    // ── Libraries ─────────────────────────────────────────────────────────
+    let obj_cache = ObjCache::open(req.obj_cache);
+    let lib_archives = libraries::build(&req.libraries, &cc, &cxx, &ar, &req.build_dir.join("libs"),
+                                        &common_flags, &cflags, &cxxflags, &flags_sig,
+                                        obj_cache.as_ref(), req.verbose)?;
 
     // ── Link ──────────────────────────────────────────────────────────────
     let elf = req.build_dir.join(format!("{}.elf", req.project_name));
@@ -131,11 +144,14 @@ pub fn run(req: &CompileRequest, board: &Board, sdk: &SdkPaths) -> Result<Compil
     link_cmd.args(&common_flags)
         .arg(format!("-Wl,-T{}", link_script))
         .arg("-Wl,--gc-sections")
-        .arg("-Wl,-Map,/dev/null");
+        .arg(format!("-Wl,-Map,{}", req.build_dir.join(format!("{}.map", req.project_name)).display()));
     for obj in &obj_files { link_cmd.arg(obj); }
+    link_cmd.args(libraries::link_group(&lib_archives));
+    link_cmd.args(&req.extra_flags.ldflags);
     link_cmd.arg("-lm").arg("-o").arg(&elf);
 
-    let link_out = link_cmd.output()?;
+    events::emit(Event::Linking { output: &elf });
+    let link_out = process::output(&mut link_cmd, Stage::Link)?;
     if !link_out.status.success() {
         return Err(FlashError::LinkFailed {
             output: String::from_utf8_lossy(&link_out.stderr).to_string(),
@@ -148,21 +164,89 @@ pub fn run(req: &CompileRequest, board: &Board, sdk: &SdkPaths) -> Result<Compil
 
     if let Some(tool) = &esptool {
         let chip = if is_esp32 { "esp32" } else { "esp8266" };
-        let _ = Command::new(tool)
-            .args(["--chip", chip, "elf2image", "--output"])
-            .arg(&bin)
-            .arg(&elf)
-            .output();
+        let mut cmd = Command::new(tool);
+        cmd.args(["--chip", chip, "elf2image"]);
+        if let Some(mode) = board.flash_mode {
+            cmd.args(["--flash_mode", mode]);
+        }
+        cmd.arg("--output").arg(&bin).arg(&elf);
+        process::output(&mut cmd, Stage::Link)?;
+    }
+
+    if let Toolchain::Esp32 { variant } = &board.toolchain {
+        write_image_parts(req, board, variant, sdk, esptool.as_deref())?;
     }
 
     Ok(CompileResult {
         hex_path: None,
         bin_path: if bin.exists() { Some(bin) } else { None },
         elf_path: Some(elf),
+        uf2_path: None,
         size_info: String::new(),
+        usage:    None,
     })
 }
 
+/// Write `<name>.partitions.bin`, `<name>.bootloader.bin` and
+/// `<name>.boot_app0.bin` beside the app, for esptool to flash together.
+fn write_image_parts(req: &CompileRequest, board: &Board, chip: &str, sdk: &SdkPaths, esptool: Option<&str>) -> Result<()> {
+    let platform = sdk.platform_dir();
+    let out = |part: &str| req.build_dir.join(format!("{}.{}.bin", req.project_name, part));
+
+    let scheme = req.partition_scheme.as_deref().or(board.partition_scheme).unwrap_or("default");
+    let csv = partition_csv(scheme, &platform.join("tools").join("partitions"))?;
+    let text = std::fs::read_to_string(&csv)
+        .map_err(|e| FlashError::Other(format!("Cannot read {}: {}", csv.display(), e)))?;
+    std::fs::write(out("partitions"), PartitionTable::from_csv(&text)?.to_bin())?;
+
+    let boot_app0 = platform.join("tools").join("partitions").join("boot_app0.bin");
+    if boot_app0.is_file() {
+        std::fs::copy(&boot_app0, out("boot_app0"))?;
+    }
+
+    // Cores up to 1.0.x ship a prebuilt bootloader .bin; later ones an .elf
+    // to convert for the flash settings.
+    for dir in [platform.join("tools/sdk").join(chip).join("bin"), platform.join("tools/sdk/bin")] {
+        let bin = dir.join("bootloader_dio_80m.bin");
+        if bin.is_file() {
+            std::fs::copy(&bin, out("bootloader"))?;
+            break;
+        }
+        let elf = dir.join("bootloader_dio_80m.elf");
+        if let (true, Some(tool)) = (elf.is_file(), esptool) {
+            process::output(Command::new(tool)
+                .args(["--chip", chip, "elf2image",
+                       "--flash_mode", board.flash_mode.unwrap_or("dio"), "--flash_freq", "80m",
+                       "--flash_size", &format!("{}MB", board.flash_kb / 1024),
+                       "--output"])
+                .arg(out("bootloader"))
+                .arg(&elf), Stage::Link)?;
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// The partitions .csv for a partition scheme: a path, or a scheme name
+/// looked up in the core's tools/partitions.
+fn partition_csv(scheme: &str, schemes: &Path) -> Result<PathBuf> {
+    if scheme.ends_with(".csv") {
+        return Ok(PathBuf::from(scheme));
+    }
+    let csv = schemes.join(format!("{}.csv", scheme));
+    if csv.is_file() {
+        return Ok(csv);
+    }
+
+    let mut known: Vec<String> = std::fs::read_dir(schemes).into_iter().flatten().flatten()
+        .filter_map(|e| e.file_name().to_str()?.strip_suffix(".csv").map(str::to_owned))
+        .collect();
+    known.sort();
+    Err(FlashError::Other(format!(
+        "Unknown partition scheme '{}'\n  Available: {}", scheme,
+        if known.is_empty() { "none found in the core".into() } else { known.join(", ") })))
+}
+
 fn collect_sources(dir: &Path) -> Result<Vec<PathBuf>> {
     Ok(WalkDir::new(dir).max_depth(3).into_iter().flatten()
         .filter(|e| e.file_type().is_file())
@@ -182,7 +266,7 @@ fn resolve_tool(bin_dir: &Path, name: &str) -> String {
 
 fn which_esptool() -> Option<String> {
     for candidate in &["esptool.py", "esptool"] {
-        if Command::new(candidate).arg("version").output().is_ok() {
+        if process::output(Command::new(candidate).arg("version"), Stage::Link).is_ok() {
             return Some(candidate.to_string());
         }
     }
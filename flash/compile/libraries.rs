@@ -0,0 +1,458 @@
+// ─────────────────────────────────────────────────────────────────────────────
+//  tsuki-flash :: compile :: libraries
+//
+//  Arduino libraries the sketch uses, found the way the Arduino IDE finds
+//  them: each `#include` of the sketch (and then of every library picked) is
+//  looked up among the top-level headers of the libraries in the include
+//  dirs, the installed ones and the platform's. Each library picked is added
+//  to the include path and its sources are compiled into
+//  <build_dir>/libs/<name>/<name>.a for the link.
+//
+//  Layouts (Arduino library specification):
+//    1.5  library.properties + src/   → src/, compiled recursively
+//    1.0  headers and sources at the root, plus an optional utility/ folder
+//
+//  A library's library.properties may also name the libraries it needs
+//  (`depends=`), which are picked along with it, and the architectures it
+//  supports (`architectures=`), which decide between libraries that provide
+//  the same header.
+// ─────────────────────────────────────────────────────────────────────────────
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use rayon::prelude::*;
+use walkdir::WalkDir;
+
+use crate::error::{FlashError, Result};
+use crate::events::{self, Event};
+use crate::process::{self, Stage};
+use super::cache::{CacheManifest, obj_path};
+use super::objcache::ObjCache;
+use super::Failures;
+
+const HEADER_EXTS: &[&str] = &["h", "hh", "hpp"];
+const SOURCE_EXTS: &[&str] = &["c", "cpp", "S"];
+
+#[derive(Debug, Clone)]
+pub struct Library {
+    pub name:         String,
+    pub root:         PathBuf,
+    /// Directories its headers are included from.
+    pub include_dirs: Vec<PathBuf>,
+    pub sources:      Vec<PathBuf>,
+    /// Names of the libraries it depends on, from `depends=`.
+    pub depends:      Vec<String>,
+    /// From `architectures=`; empty when it doesn't say.
+    pub architectures: Vec<String>,
+}
+
+/// A `depends=` entry no library in the search path provides.
+#[derive(Debug, Clone)]
+pub struct MissingDep {
+    pub library:    String,
+    pub dependency: String,
+}
+
+/// Result of [`resolve`].
+#[derive(Debug, Default)]
+pub struct Resolution {
+    pub libraries: Vec<Library>,
+    pub missing:   Vec<MissingDep>,
+}
+
+impl Library {
+    /// The library in `dir`, if it looks like one: a library.properties or
+    /// headers at the top of its include dir.
+    pub fn at(dir: &Path) -> Option<Library> {
+        let props = std::fs::read_to_string(dir.join("library.properties")).ok();
+        let property = |key: &str| props.as_deref().and_then(|p| p.lines().find_map(|l| {
+            let (k, v) = l.split_once('=')?;
+            (k.trim() == key).then(|| v.trim().to_owned())
+        }));
+        let name = property("name")
+            .or_else(|| Some(dir.file_name()?.to_string_lossy().into_owned()))?;
+        // `depends=Adafruit Unified Sensor (>=1.1.0), Wire`: the version
+        // constraints are left to `lib install`.
+        let list = |key: &str| property(key).map(|v| v.split(',')
+            .map(|d| d.split_once('(').map_or(d, |(n, _)| n).trim().to_owned())
+            .filter(|d| !d.is_empty())
+            .collect::<Vec<_>>()).unwrap_or_default();
+        let depends = list("depends");
+        let architectures = list("architectures");
+
+        let src = dir.join("src");
+        let (include_dirs, sources) = if props.is_some() && src.is_dir() {
+            (vec![src.clone()], files(&src, usize::MAX, SOURCE_EXTS))
+        } else {
+            let mut sources = files(dir, 1, SOURCE_EXTS);
+            let mut include_dirs = vec![dir.to_path_buf()];
+            let utility = dir.join("utility");
+            if utility.is_dir() {
+                sources.extend(files(&utility, usize::MAX, SOURCE_EXTS));
+                include_dirs.push(utility);
+            }
+            (include_dirs, sources)
+        };
+
+        let lib = Library { name, root: dir.to_path_buf(), include_dirs, sources, depends, architectures };
+        (props.is_some() || !lib.headers().is_empty()).then_some(lib)
+    }
+
+    /// Header names directly in the include dirs, e.g. `Servo.h`.
+    pub fn headers(&self) -> Vec<String> {
+        self.include_dirs.iter().take(1)
+            .flat_map(|d| files(d, 1, HEADER_EXTS))
+            .filter_map(|p| Some(p.file_name()?.to_string_lossy().into_owned()))
+            .collect()
+    }
+
+    /// Whether it declares support for the board architecture `arch`
+    /// (`Board::arch`), or doesn't say.
+    pub fn supports(&self, arch: &str) -> bool {
+        self.architectures.is_empty() || self.architectures.iter().any(|a| {
+            a == "*" || a.eq_ignore_ascii_case(arch)
+                // megaTinyCore runs the megaAVR libraries.
+                || (arch == "megatinycore" && a == "megaavr")
+        })
+    }
+
+    /// File-name-safe name for the archive and its object directory.
+    pub fn archive_name(&self) -> String {
+        self.name.chars().map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '-' { c } else { '_' }).collect()
+    }
+}
+
+/// The libraries the sketch in `sketch_dir` includes, directly or through
+/// other libraries, and those they depend on. `search` is in priority order;
+/// each entry is a library itself or a folder of them. Libraries that
+/// support `arch` are preferred over those that don't.
+pub fn resolve(sketch_dir: &Path, search: &[PathBuf], arch: &str) -> Resolution {
+    let mut candidates: Vec<Library> = Vec::new();
+    let mut seen_roots: HashSet<PathBuf> = HashSet::new();
+    for dir in search {
+        let found = match Library::at(dir) {
+            Some(lib) => vec![lib],
+            None => {
+                let mut subdirs: Vec<PathBuf> = std::fs::read_dir(dir).into_iter().flatten().flatten()
+                    .map(|e| e.path())
+                    .filter(|p| p.is_dir())
+                    .collect();
+                subdirs.sort();
+                subdirs.iter().filter_map(|d| Library::at(d)).collect()
+            }
+        };
+        for lib in found {
+            if seen_roots.insert(lib.root.clone()) {
+                candidates.push(lib);
+            }
+        }
+    }
+
+    // Header → library. Where several provide it, one for this architecture
+    // wins, then one named after the header, then the one found first.
+    let mut by_header: HashMap<String, usize> = HashMap::new();
+    for (compatible, named_first) in [(true, true), (true, false), (false, true), (false, false)] {
+        for (i, lib) in candidates.iter().enumerate() {
+            if compatible && !lib.supports(arch) { continue }
+            let dir_name = lib.root.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+            for header in lib.headers() {
+                let stem = header.rsplit_once('.').map_or(header.as_str(), |(s, _)| s);
+                if !named_first || stem == dir_name {
+                    by_header.entry(header.clone()).or_insert(i);
+                }
+            }
+        }
+    }
+    let by_name = |name: &str| {
+        let mut named = candidates.iter().enumerate().filter(|(_, l)| l.name.eq_ignore_ascii_case(name));
+        named.clone().find(|(_, l)| l.supports(arch)).or_else(|| named.next()).map(|(i, _)| i)
+    };
+
+    let local: HashSet<String> = files(sketch_dir, 3, HEADER_EXTS).iter()
+        .filter_map(|p| Some(p.file_name()?.to_string_lossy().into_owned()))
+        .collect();
+    let mut queue: Vec<PathBuf> = files(sketch_dir, 3, &["c", "cpp", "ino", "h", "hpp"]);
+    let mut wanted: Vec<usize> = Vec::new();
+    let mut picked: Vec<usize> = Vec::new();
+    let mut missing: Vec<MissingDep> = Vec::new();
+    loop {
+        while let Some(file) = queue.pop() {
+            let Ok(text) = std::fs::read_to_string(&file) else { continue };
+            for header in includes(&text) {
+                if local.contains(header) { continue }
+                if let Some(&i) = by_header.get(header) { wanted.push(i); }
+            }
+        }
+        let Some(i) = wanted.pop() else { break };
+        if picked.contains(&i) { continue }
+        picked.push(i);
+        let lib = &candidates[i];
+        queue.extend(lib.sources.iter().cloned());
+        queue.extend(lib.include_dirs.iter().take(1).flat_map(|d| files(d, 1, HEADER_EXTS)));
+        for dep in &lib.depends {
+            match by_name(dep) {
+                Some(j) => wanted.push(j),
+                None if !missing.iter().any(|m| &m.dependency == dep) => missing.push(MissingDep {
+                    library:    lib.name.clone(),
+                    dependency: dep.clone(),
+                }),
+                None => {}
+            }
+        }
+    }
+    Resolution {
+        libraries: picked.into_iter().map(|i| candidates[i].clone()).collect(),
+        missing,
+    }
+}
+
+/// The targets of the `#include` lines in `text`.
+fn includes(text: &str) -> impl Iterator<Item = &str> {
+    text.lines().filter_map(|line| {
+        let rest = line.trim_start().strip_prefix('#')?.trim_start().strip_prefix("include")?.trim_start();
+        let close = match rest.chars().next()? { '<' => '>', '"' => '"', _ => return None };
+        let rest = &rest[1..];
+        Some(&rest[..rest.find(close)?])
+    })
+}
+
+/// Files with one of `exts` up to `depth` levels below `dir`, skipping
+/// hidden folders and the examples a library ships.
+fn files(dir: &Path, depth: usize, exts: &[&str]) -> Vec<PathBuf> {
+    let mut out: Vec<PathBuf> = WalkDir::new(dir).max_depth(depth).follow_links(true).into_iter()
+        .filter_entry(|e| e.depth() == 0 || !e.file_name().to_str()
+            .is_some_and(|n| n.starts_with('.') || n == "examples" || n == "extras"))
+        .flatten()
+        .filter(|e| e.file_type().is_file())
+        .filter(|e| e.path().extension().and_then(|x| x.to_str()).is_some_and(|x| exts.contains(&x)))
+        .map(|e| e.into_path())
+        .collect();
+    out.sort();
+    out
+}
+
+/// Linker arguments for the library archives: grouped, since libraries
+/// may depend on each other in either order.
+pub(super) fn link_group(archives: &[PathBuf]) -> Vec<String> {
+    if archives.is_empty() { return Vec::new(); }
+    let mut args = vec!["-Wl,--start-group".to_owned()];
+    args.extend(archives.iter().map(|a| a.display().to_string()));
+    args.push("-Wl,--end-group".into());
+    args
+}
+
+/// Compile every library's sources in parallel, each into its own archive
+/// under `libs_dir`, and return the archives for the link. Objects are
+/// cached per library like the sketch's; an archive is rewritten only when
+/// one of its objects was.
+#[allow(clippy::too_many_arguments)]
+pub(super) fn build(
+    libraries: &[Library],
+    cc: &str, cxx: &str, ar: &str,
+    libs_dir: &Path,
+    includes: &[String],
+    cflags: &[&str], cxxflags: &[&str],
+    flags_sig: &str,
+    obj_cache: Option<&ObjCache>,
+    verbose: bool,
+) -> Result<Vec<PathBuf>> {
+    let libraries: Vec<&Library> = libraries.iter().filter(|l| !l.sources.is_empty()).collect();
+    let dirs: Vec<PathBuf> = libraries.iter().map(|l| libs_dir.join(l.archive_name())).collect();
+    for dir in &dirs {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut manifests: Vec<CacheManifest> = dirs.iter().map(|d| CacheManifest::load(d)).collect();
+    let rebuilt: Vec<AtomicBool> = libraries.iter().map(|_| AtomicBool::new(false)).collect();
+    let failures = Failures::default();
+
+    let jobs: Vec<(usize, &PathBuf)> = libraries.iter().enumerate()
+        .flat_map(|(i, l)| l.sources.iter().map(move |s| (i, s)))
+        .collect();
+    jobs.par_iter().for_each(|&(i, src)| {
+        let obj = obj_path(&dirs[i], src);
+        if manifests[i].is_fresh(src, &obj, flags_sig) {
+            if verbose { eprintln!("  [cache] {}", src.display()); }
+            events::emit(Event::Fresh { unit: &libraries[i].name, file: src, cache: "build" });
+            return;
+        }
+        rebuilt[i].store(true, Ordering::Relaxed);
+
+        let ext = src.extension().and_then(|e| e.to_str()).unwrap_or("");
+        let compiler = if ext == "cpp" { cxx } else { cc };
+        let mut args: Vec<&str> = includes.iter().map(String::as_str).collect();
+        match ext {
+            "S"   => args.extend(["-x", "assembler-with-cpp"]),
+            "c"   => args.extend(cflags),
+            _     => args.extend(cxxflags),
+        }
+
+        let key = obj_cache.and_then(|c| c.key(compiler, &args, src));
+        if let (Some(cache), Some(key)) = (obj_cache, &key) {
+            if cache.fetch(key, &obj) {
+                if verbose { eprintln!("  [obj-cache] {}", src.display()); }
+                events::emit(Event::Fresh { unit: &libraries[i].name, file: src, cache: "objects" });
+                return;
+            }
+        }
+
+        if verbose { eprintln!("  [compile] {}", src.display()); }
+        events::emit(Event::Compiling { unit: &libraries[i].name, file: src });
+        let mut cmd = Command::new(compiler);
+        cmd.args(&args).arg("-c").arg(src).arg("-o").arg(&obj);
+        if failures.compile(&mut cmd, &obj, || format!("In {} ({}):\n", src.display(), libraries[i].name)) {
+            if let (Some(cache), Some(key)) = (obj_cache, &key) {
+                cache.store(key, src, &obj);
+            }
+        }
+    });
+
+    for (i, lib) in libraries.iter().enumerate() {
+        for src in &lib.sources {
+            let obj = obj_path(&dirs[i], src);
+            if obj.exists() { manifests[i].record(src, &obj, flags_sig); }
+        }
+        let _ = manifests[i].save(&dirs[i]);
+    }
+
+    failures.check("\n\n")?;
+
+    let mut archives = Vec::new();
+    for (i, lib) in libraries.iter().enumerate() {
+        let archive = dirs[i].join(format!("{}.a", lib.archive_name()));
+        if rebuilt[i].load(Ordering::Relaxed) || !archive.exists() {
+            let _ = std::fs::remove_file(&archive);
+            let out = process::output(Command::new(ar).arg("rcs").arg(&archive)
+                .args(lib.sources.iter().map(|s| obj_path(&dirs[i], s))), Stage::Link)?;
+            if !out.status.success() {
+                return Err(FlashError::CompileFailed {
+                    output: String::from_utf8_lossy(&out.stderr).to_string(),
+                });
+            }
+        }
+        archives.push(archive);
+    }
+    Ok(archives)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("tsuki-libs-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn write(path: &Path, text: &str) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, text).unwrap();
+    }
+
+    #[test]
+    fn test_library_properties() {
+        let dir = scratch("props");
+        let lib = dir.join("DHT_sensor");
+        write(&lib.join("library.properties"), "\
+# comment line without an equals sign
+name = DHT sensor library
+version=1.4.4
+depends
+depends=Adafruit Unified Sensor (>=1.1.0), , Wire
+architectures=avr, esp32
+");
+        write(&lib.join("src/DHT.h"), "");
+        write(&lib.join("src/DHT.cpp"), "");
+        write(&lib.join("src/impl/detail.c"), "");
+        write(&lib.join("examples/Test/Test.cpp"), "");
+
+        let lib = Library::at(&lib).unwrap();
+        assert_eq!(lib.name, "DHT sensor library");
+        assert_eq!(lib.depends, ["Adafruit Unified Sensor", "Wire"]);
+        assert_eq!(lib.architectures, ["avr", "esp32"]);
+        assert_eq!(lib.headers(), ["DHT.h"]);
+        let sources: Vec<String> = lib.sources.iter().map(|s| s.file_name().unwrap().to_string_lossy().into_owned()).collect();
+        assert_eq!(sources, ["DHT.cpp", "detail.c"]);
+        assert!(lib.supports("AVR") && lib.supports("esp32") && !lib.supports("rp2040"));
+        assert_eq!(lib.archive_name(), "DHT_sensor_library");
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_legacy_layout() {
+        let dir = scratch("legacy");
+        write(&dir.join("Servo/Servo.h"), "");
+        write(&dir.join("Servo/Servo.cpp"), "");
+        write(&dir.join("Servo/utility/timers.c"), "");
+        // Properties that only hold lines without `=` name nothing.
+        write(&dir.join("Bare/library.properties"), "just some text\n");
+        std::fs::create_dir_all(dir.join("NotALib/docs")).unwrap();
+
+        let servo = Library::at(&dir.join("Servo")).unwrap();
+        assert_eq!(servo.name, "Servo");
+        assert_eq!(servo.include_dirs, [dir.join("Servo"), dir.join("Servo/utility")]);
+        assert_eq!(servo.sources.len(), 2);
+        assert!(servo.depends.is_empty() && servo.supports("anything"));
+
+        let bare = Library::at(&dir.join("Bare")).unwrap();
+        assert_eq!(bare.name, "Bare");
+        assert!(bare.depends.is_empty() && bare.architectures.is_empty());
+        assert!(Library::at(&dir.join("NotALib")).is_none());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_includes() {
+        let text = "#include <Wire.h>\n  #  include \"pins.h\"\n// #include <Nope.h>\n#include MACRO\n#include <broken.h\n#define X\n";
+        assert_eq!(includes(text).collect::<Vec<_>>(), ["Wire.h", "pins.h"]);
+    }
+
+    #[test]
+    fn test_resolve() {
+        let dir = scratch("resolve");
+        let (sketch, libs) = (dir.join("sketch"), dir.join("libs"));
+        write(&sketch.join("main.cpp"), "#include <Sensor.h>\n#include \"local.h\"\n");
+        write(&sketch.join("local.h"), "");
+        write(&libs.join("Sensor/library.properties"), "name=Sensor\ndepends=Bus, Missing (>=2)\n");
+        write(&libs.join("Sensor/src/Sensor.h"), "");
+        write(&libs.join("Bus/library.properties"), "name=Bus\n");
+        write(&libs.join("Bus/src/Bus.h"), "");
+        // The sketch has its own local.h, and never includes Unused.h.
+        write(&libs.join("Other/local.h"), "");
+        write(&libs.join("Unused/Unused.h"), "");
+
+        let res = resolve(&sketch, &[libs], "avr");
+        let names: Vec<&str> = res.libraries.iter().map(|l| l.name.as_str()).collect();
+        assert_eq!(names, ["Sensor", "Bus"]);
+        assert_eq!(res.missing.len(), 1);
+        assert_eq!((res.missing[0].library.as_str(), res.missing[0].dependency.as_str()), ("Sensor", "Missing"));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_resolve_prefers_architecture() {
+        let dir = scratch("arch");
+        let (sketch, libs) = (dir.join("sketch"), dir.join("libs"));
+        write(&sketch.join("main.cpp"), "#include <Servo.h>\n");
+        write(&libs.join("AServo/library.properties"), "name=AServo\narchitectures=esp32\n");
+        write(&libs.join("AServo/src/Servo.h"), "");
+        write(&libs.join("Servo/library.properties"), "name=Servo\narchitectures=avr,megaavr\n");
+        write(&libs.join("Servo/src/Servo.h"), "");
+
+        let picked = |arch| resolve(&sketch, std::slice::from_ref(&libs), arch).libraries[0].name.clone();
+        assert_eq!(picked("esp32"), "AServo");
+        assert_eq!(picked("megatinycore"), "Servo");
+        // Neither supports it: the one named after the header.
+        assert_eq!(picked("rp2040"), "Servo");
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_link_group() {
+        assert!(link_group(&[]).is_empty());
+        assert_eq!(link_group(&[PathBuf::from("a.a"), PathBuf::from("b.a")]),
+                   ["-Wl,--start-group", "a.a", "b.a", "-Wl,--end-group"]);
+    }
+}
@@ -0,0 +1,306 @@
+// ─────────────────────────────────────────────────────────────────────────────
+//  tsuki-flash :: compile :: meta
+//
+//  Records which board a firmware image was built for, so `upload` can refuse
+//  to flash it onto a different board, and what else went into it (versions,
+//  flags, the source commit, image hashes), so a flashed device can be traced
+//  back to its build with `tsuki-flash info`.
+//
+//  Metadata lives at <build_dir>/<name>.meta.json, next to the .hex/.bin. A
+//  copy goes into the .elf as a `.tsuki_build_info` section, which is not
+//  loaded and takes no flash.
+// ─────────────────────────────────────────────────────────────────────────────
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::boards::Board;
+use crate::error::Result;
+use crate::process::{self, Stage};
+use crate::sdk::SdkPaths;
+use super::CompileRequest;
+
+/// Name of the ELF section holding the embedded copy.
+pub const ELF_SECTION: &str = ".tsuki_build_info";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BuildMeta {
+    /// Board id the firmware targets (e.g. "uno").
+    pub board: String,
+    /// Fully-qualified board name, for humans reading the file.
+    pub fqbn:  String,
+    /// F_CPU the firmware was compiled with.
+    pub f_cpu: u32,
+    // Metadata written before these existed has none of the rest.
+    /// tsuki-flash version that built it.
+    #[serde(default)]
+    pub tsuki_version: String,
+    /// Core architecture and version, e.g. `avr 1.8.6`.
+    #[serde(default)]
+    pub core:          String,
+    /// First line of the compiler's `--version`.
+    #[serde(default)]
+    pub compiler:      String,
+    /// Hash of everything on the compile lines besides the sources: board,
+    /// clock, defines, C++ standard, partition scheme and user flags.
+    #[serde(default)]
+    pub flags:         String,
+    /// Commit checked out where the sketch is, with `-dirty` if tracked
+    /// files had changes. None outside a git repository.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git:           Option<String>,
+    /// The files an upload can write.
+    #[serde(default)]
+    pub artifacts:     Vec<Artifact>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Artifact {
+    /// File name, in the build dir.
+    pub file:   String,
+    pub bytes:  u64,
+    pub sha256: String,
+}
+
+impl BuildMeta {
+    fn path(build_dir: &Path, name: &str) -> PathBuf {
+        build_dir.join(format!("{}.meta.json", name))
+    }
+
+    /// Describe the firmware `req` just produced in its build dir.
+    pub fn collect(req: &CompileRequest, board: &Board, sdk: &SdkPaths) -> Result<Self> {
+        let f_cpu = board.clock_hz(req.cpu_mhz)?;
+        let gcc = super::size::toolchain_tool(board, &sdk.toolchain_bin, "gcc");
+        let compiler = process::output(Command::new(&gcc).arg("--version"), Stage::Compile).ok()
+            .and_then(|o| String::from_utf8_lossy(&o.stdout).lines().next().map(str::to_owned))
+            .unwrap_or_default();
+
+        Ok(BuildMeta {
+            board: board.id.to_owned(),
+            fqbn:  board.fqbn.to_owned(),
+            f_cpu,
+            tsuki_version: env!("CARGO_PKG_VERSION").to_owned(),
+            core:          format!("{} {}", board.arch(), sdk.sdk_version),
+            compiler,
+            flags:         fingerprint(req, board, f_cpu),
+            git:           git_commit(&req.sketch_dir),
+            artifacts:     artifacts(&req.build_dir, &req.project_name)?,
+        })
+    }
+
+    /// Load the metadata for `name`, or None if absent or unreadable
+    /// (e.g. firmware built by an older tsuki-flash or by arduino-cli).
+    pub fn load(build_dir: &Path, name: &str) -> Option<Self> {
+        let data = std::fs::read_to_string(Self::path(build_dir, name)).ok()?;
+        serde_json::from_str(&data).ok()
+    }
+
+    /// Persist to disk.
+    pub fn save(&self, build_dir: &Path, name: &str) -> std::io::Result<()> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(std::io::Error::other)?;
+        std::fs::write(Self::path(build_dir, name), json)
+    }
+
+    /// Copy the saved metadata into `elf`, replacing what an earlier build
+    /// put there.
+    pub fn embed(build_dir: &Path, name: &str, elf: &Path, objcopy: &str) -> Result<()> {
+        let section = format!("{}={}", ELF_SECTION, Self::path(build_dir, name).display());
+        super::avr::run_tool(objcopy, &[
+            "--remove-section", ELF_SECTION,
+            "--add-section", &section,
+            &elf.to_string_lossy(),
+        ])
+    }
+}
+
+impl Artifact {
+    /// Whether the file in `build_dir` is still the one built, or None if
+    /// it is gone.
+    pub fn unchanged(&self, build_dir: &Path) -> Option<bool> {
+        let data = std::fs::read(build_dir.join(&self.file)).ok()?;
+        Some(hex::encode(Sha256::digest(&data)) == self.sha256)
+    }
+}
+
+/// The firmware files in `build_dir`: `<name>.hex`, `.bin`, `.uf2` and the
+/// parts written with them (`<name>.with_bootloader.hex`, `<name>.partitions.bin`, …).
+fn artifacts(build_dir: &Path, name: &str) -> Result<Vec<Artifact>> {
+    let prefix = format!("{}.", name);
+    let mut files: Vec<String> = std::fs::read_dir(build_dir)?.flatten()
+        .filter_map(|e| e.file_name().into_string().ok())
+        .filter(|f| f.starts_with(&prefix)
+            && [".hex", ".bin", ".uf2"].iter().any(|ext| f.ends_with(ext)))
+        .collect();
+    files.sort();
+
+    files.into_iter().map(|file| {
+        let data = std::fs::read(build_dir.join(&file))?;
+        Ok(Artifact { bytes: data.len() as u64, sha256: hex::encode(Sha256::digest(&data)), file })
+    }).collect()
+}
+
+fn fingerprint(req: &CompileRequest, board: &Board, f_cpu: u32) -> String {
+    let extra = &req.extra_flags;
+    let mut parts = vec![
+        board.fqbn.clone(),
+        f_cpu.to_string(),
+        req.cpp_std.clone(),
+        req.partition_scheme.clone().unwrap_or_default(),
+    ];
+    parts.extend(board.defines.iter().map(|d| format!("-D{}", d)));
+    parts.extend(extra.define_flags());
+    for (kind, flags) in [("c", &extra.cflags), ("cxx", &extra.cxxflags), ("ld", &extra.ldflags)] {
+        parts.extend(flags.iter().map(|f| format!("{}:{}", kind, f)));
+    }
+    hex::encode(Sha256::digest(parts.join("\n")))[..16].to_owned()
+}
+
+fn git_commit(dir: &Path) -> Option<String> {
+    let git = |args: &[&str]| process::output(Command::new("git").arg("-C").arg(dir).args(args), Stage::Compile).ok()
+        .filter(|o| o.status.success())
+        .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_owned());
+    let commit = git(&["rev-parse", "HEAD"])?;
+    let dirty = git(&["status", "--porcelain", "--untracked-files=no"]).is_some_and(|s| !s.is_empty());
+    Some(if dirty { format!("{}-dirty", commit) } else { commit })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::ExtraFlags;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("tsuki-meta-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn request(extra_flags: ExtraFlags) -> CompileRequest {
+        CompileRequest {
+            sketch_dir:       PathBuf::from("sketch"),
+            build_dir:        PathBuf::from("build"),
+            project_name:     "blink".into(),
+            cpp_std:          "c++11".into(),
+            lib_include_dirs: Vec::new(),
+            libraries:        Vec::new(),
+            use_modules:      false,
+            obj_cache:        false,
+            cpu_mhz:          None,
+            partition_scheme: None,
+            budget:           Default::default(),
+            extra_flags,
+            verbose:          false,
+        }
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = scratch("save");
+        let meta = BuildMeta {
+            board: "uno".into(),
+            fqbn:  "arduino:avr:uno".into(),
+            f_cpu: 16_000_000,
+            tsuki_version: "3.0.0".into(),
+            core:          "avr 1.8.6".into(),
+            compiler:      "avr-gcc (GCC) 7.3.0".into(),
+            flags:         "0123456789abcdef".into(),
+            git:           None,
+            artifacts:     vec![Artifact { file: "blink.hex".into(), bytes: 2, sha256: "ff".into() }],
+        };
+        meta.save(&dir, "blink").unwrap();
+        let json: serde_json::Value = serde_json::from_str(
+            &std::fs::read_to_string(dir.join("blink.meta.json")).unwrap()).unwrap();
+        assert_eq!(json["board"], "uno");
+        assert_eq!(json["f_cpu"], 16_000_000);
+        assert_eq!(json["core"], "avr 1.8.6");
+        assert_eq!(json["artifacts"][0]["file"], "blink.hex");
+        // Outside a git repository there is no `git` key at all.
+        assert!(json.get("git").is_none());
+
+        let loaded = BuildMeta::load(&dir, "blink").unwrap();
+        assert_eq!((loaded.board, loaded.compiler, loaded.git), (meta.board, meta.compiler, None));
+        assert!(BuildMeta::load(&dir, "other").is_none());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_load_old_and_broken() {
+        let dir = scratch("old");
+        std::fs::write(dir.join("old.meta.json"), r#"{"board":"nano","fqbn":"arduino:avr:nano","f_cpu":16000000}"#).unwrap();
+        let old = BuildMeta::load(&dir, "old").unwrap();
+        assert_eq!(old.board, "nano");
+        assert!(old.tsuki_version.is_empty() && old.flags.is_empty() && old.artifacts.is_empty());
+
+        std::fs::write(dir.join("broken.meta.json"), "{\"board\":").unwrap();
+        assert!(BuildMeta::load(&dir, "broken").is_none());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_artifacts() {
+        let dir = scratch("artifacts");
+        for file in ["blink.hex", "blink.with_bootloader.hex", "blink.bin", "blink.elf",
+                     "blink.meta.json", "blinker.hex", "other.uf2"] {
+            std::fs::write(dir.join(file), file).unwrap();
+        }
+        let found = artifacts(&dir, "blink").unwrap();
+        let files: Vec<&str> = found.iter().map(|a| a.file.as_str()).collect();
+        assert_eq!(files, ["blink.bin", "blink.hex", "blink.with_bootloader.hex"]);
+        assert_eq!(found[0].bytes, 9);
+        assert_eq!(found[0].sha256, hex::encode(Sha256::digest(b"blink.bin")));
+
+        assert_eq!(found[1].unchanged(&dir), Some(true));
+        std::fs::write(dir.join("blink.hex"), "rebuilt").unwrap();
+        assert_eq!(found[1].unchanged(&dir), Some(false));
+        std::fs::remove_file(dir.join("blink.hex")).unwrap();
+        assert_eq!(found[1].unchanged(&dir), None);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let uno = Board::find("uno").unwrap();
+        let base = fingerprint(&request(ExtraFlags::default()), uno, 16_000_000);
+        assert_eq!(base.len(), 16);
+        assert_eq!(fingerprint(&request(ExtraFlags::default()), uno, 16_000_000), base);
+        assert_ne!(fingerprint(&request(ExtraFlags::default()), uno, 8_000_000), base);
+        assert_ne!(fingerprint(&request(ExtraFlags::default()), Board::find("nano").unwrap(), 16_000_000), base);
+
+        let with = |f: fn(&mut ExtraFlags)| {
+            let mut extra = ExtraFlags::default();
+            f(&mut extra);
+            fingerprint(&request(extra), uno, 16_000_000)
+        };
+        let c = with(|e| e.cflags.push("-O3".into()));
+        let cxx = with(|e| e.cxxflags.push("-O3".into()));
+        assert!(c != base && cxx != base && c != cxx);
+        assert_ne!(with(|e| e.ldflags.push("-lm".into())), base);
+        assert_ne!(with(|e| e.defines.push("LED=2".into())), base);
+    }
+
+    #[test]
+    fn test_git_commit() {
+        let dir = scratch("git");
+        assert_eq!(git_commit(&dir), None);
+        let git = |args: &[&str]| assert!(Command::new("git").arg("-C").arg(&dir)
+            .args(["-c", "user.name=t", "-c", "user.email=t@t", "-c", "commit.gpgsign=false"])
+            .args(args).output().unwrap().status.success());
+        std::fs::write(dir.join("main.go"), "package main\n").unwrap();
+        git(&["init", "-q"]);
+        git(&["add", "main.go"]);
+        git(&["commit", "-q", "-m", "init"]);
+
+        let clean = git_commit(&dir).unwrap();
+        assert_eq!(clean.len(), 40);
+        // Untracked files don't count; edits to tracked ones do.
+        std::fs::write(dir.join("notes.txt"), "").unwrap();
+        assert_eq!(git_commit(&dir).unwrap(), clean);
+        std::fs::write(dir.join("main.go"), "package main\n\nfunc main() {}\n").unwrap();
+        assert_eq!(git_commit(&dir).unwrap(), format!("{}-dirty", clean));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -5,10 +5,26 @@
 pub mod avr;
 pub mod cache;
 pub mod esp;
+pub mod libraries;
+pub mod meta;
+pub mod objcache;
+pub mod partitions;
+pub mod rp2040;
+pub mod sam;
+pub mod size;
+pub mod stm32;
+pub mod teensy;
+pub mod uf2;
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+use std::time::Instant;
+use colored::Colorize;
 use crate::boards::{Board, Toolchain};
 use crate::error::{FlashError, Result};
+use crate::events::{self, Event};
+use crate::process::{self, Stage};
 use crate::sdk;
 
 /// Inputs to a compile run.
@@ -24,63 +40,219 @@ pub struct CompileRequest {
     pub cpp_std:          String,
     /// Extra -I dirs (tsuki libraries, passed via --include).
     pub lib_include_dirs: Vec<PathBuf>,
+    /// Libraries the sketch includes, compiled and linked with it. Filled
+    /// in by `compile()` from the include dirs; callers leave it empty.
+    pub libraries:        Vec<libraries::Library>,
     /// When true the tsuki-modules SDK store (~/.tsuki/modules) is preferred
     /// over .arduino15. sdk::resolve() handles this transparently; the flag
     /// is here for documentation and future per-request overrides.
     pub use_modules:      bool,
+    /// Reuse core and library objects from the global object cache
+    /// (`objcache::root`).
+    pub obj_cache:        bool,
+    /// Clock override from the manifest's `cpu_mhz` (None = board default).
+    pub cpu_mhz:          Option<u32>,
+    /// ESP32 partition table: a scheme of the core (`huge_app`, …) or a
+    /// .csv path (None = the core's `default`).
+    pub partition_scheme: Option<String>,
+    /// Size limits checked against the linked firmware.
+    pub budget:           size::Budget,
+    /// User flags added to the generated ones.
+    pub extra_flags:      ExtraFlags,
     /// Print every compiler command.
     pub verbose:          bool,
 }
 
+/// Flags from `--cflags`, `--cxxflags`, `--defines` and `--ldflags` (or the
+/// manifest's `build` section). Each backend puts them after its own, so
+/// they win, and into its cache fingerprints, so changing them rebuilds.
+#[derive(Debug, Clone, Default)]
+pub struct ExtraFlags {
+    /// For C sources.
+    pub cflags:   Vec<String>,
+    /// For C++ sources.
+    pub cxxflags: Vec<String>,
+    /// `KEY` or `KEY=VAL`, for every source, the core's included.
+    pub defines:  Vec<String>,
+    /// For the link, after the firmware's objects and before the
+    /// toolchain's own libraries (`-lprintf_flt` must precede `-lm`).
+    pub ldflags:  Vec<String>,
+}
+
+impl ExtraFlags {
+    /// `defines` as -D flags.
+    pub fn define_flags(&self) -> impl Iterator<Item = String> + '_ {
+        self.defines.iter().map(|d| format!("-D{}", d))
+    }
+}
+
 /// Outputs of a compile run.
 #[derive(Debug)]
 pub struct CompileResult {
     pub hex_path:  Option<PathBuf>,
     pub bin_path:  Option<PathBuf>,
     pub elf_path:  Option<PathBuf>,
+    pub uf2_path:  Option<PathBuf>,
     pub size_info: String,
+    /// (flash, ram) bytes of the linked firmware, when it could be measured.
+    pub usage:     Option<(u64, u64)>,
+}
+
+/// What went wrong in a batch of compiles run in parallel.
+#[derive(Default)]
+struct Failures {
+    /// Compiler errors, reported together.
+    errors:  Mutex<Vec<String>>,
+    /// A compiler that didn't finish (timeout, Ctrl-C), reported instead.
+    stopped: Mutex<Option<FlashError>>,
+}
+
+impl Failures {
+    /// Run `cmd`, the compile of `obj`, and tell whether it succeeded. A
+    /// failed compile's stderr is kept, after `context`, and its `obj`
+    /// deleted: the build cache would take an old or half-written object
+    /// left there for an up-to-date one.
+    pub fn compile(&self, cmd: &mut Command, obj: &Path, context: impl FnOnce() -> String) -> bool {
+        match process::output(cmd, Stage::Compile) {
+            Ok(out) if out.status.success() => return true,
+            Ok(out) => self.errors.lock().unwrap()
+                .push(format!("{}{}", context(), String::from_utf8_lossy(&out.stderr))),
+            Err(e) => { self.stopped.lock().unwrap().get_or_insert(e); }
+        }
+        let _ = std::fs::remove_file(obj);
+        false
+    }
+
+    /// The batch's outcome, with the compiler errors joined by `separator`.
+    pub fn check(self, separator: &str) -> Result<()> {
+        if let Some(e) = self.stopped.into_inner().unwrap() {
+            return Err(e);
+        }
+        let errors = self.errors.into_inner().unwrap();
+        if errors.is_empty() { Ok(()) } else { Err(FlashError::CompileFailed { output: errors.join(separator) }) }
+    }
 }
 
 /// Run the full compile pipeline for the given board.
 ///
-/// Automatically appends `lib_manager::libs_root()` to the include path so
-/// libraries installed via `tsuki-flash lib install <name>` are found without
-/// requiring explicit `--include` flags.
+/// Automatically appends the library roots (`lib_manager::search_roots()`)
+/// to the include path so libraries installed via `tsuki-flash lib install
+/// <name>` are found without requiring explicit `--include` flags.
 pub fn compile(req: &CompileRequest, board: &Board) -> Result<CompileResult> {
+    let t0 = Instant::now();
+    events::emit(Event::BuildStarted { board: board.id, sketch: &req.sketch_dir });
+    let res = build(req, board);
+    if let Err(FlashError::CompileFailed { output } | FlashError::LinkFailed { output }) = &res {
+        events::emit_diagnostics(output);
+    }
+    let artifacts = res.as_ref().map_or_else(|_| Vec::new(), |r| {
+        [&r.hex_path, &r.bin_path, &r.elf_path, &r.uf2_path].into_iter().flatten().map(|p| p.as_path()).collect()
+    });
+    events::emit(Event::BuildFinished { success: res.is_ok(), seconds: t0.elapsed().as_secs_f64(), artifacts });
+    res
+}
+
+fn build(req: &CompileRequest, board: &Board) -> Result<CompileResult> {
     let sdk = sdk::resolve(board.arch(), board.variant)?;
-    let augmented = augment_lib_includes(req);
-
-    match &board.toolchain {
-        Toolchain::Avr { .. }   => avr::run(&augmented, board, &sdk),
-        Toolchain::Esp32 { .. } => esp::run(&augmented, board, &sdk),
-        Toolchain::Esp8266      => esp::run(&augmented, board, &sdk),
-        Toolchain::Sam { .. }   => Err(FlashError::Other(
-            "SAM (Due) compile not yet implemented — use arduino-cli for now".into(),
-        )),
-        Toolchain::Rp2040 => Err(FlashError::Other(
-            "RP2040 compile not yet implemented — use arduino-cli for now".into(),
-        )),
+    let augmented = augment_lib_includes(req, board, &sdk);
+
+    // Ctrl-C or a tool running out of time leaves no half-written image.
+    let firmware = req.build_dir.join(format!("{}.", req.project_name));
+    let mut res = process::writing(&firmware, || {
+        let res = match &board.toolchain {
+            Toolchain::Avr { .. }   => avr::run(&augmented, board, &sdk),
+            Toolchain::MegaAvr { .. } => avr::run(&augmented, board, &sdk),
+            Toolchain::Esp32 { .. } => esp::run(&augmented, board, &sdk),
+            Toolchain::Esp8266      => esp::run(&augmented, board, &sdk),
+            Toolchain::Sam { .. }   => sam::run(&augmented, board, &sdk),
+            Toolchain::Rp2040       => rp2040::run(&augmented, board, &sdk),
+            Toolchain::Stm32 { .. } => stm32::run(&augmented, board, &sdk),
+            Toolchain::Teensy { .. } => teensy::run(&augmented, board, &sdk),
+        }?;
+
+        meta::BuildMeta::collect(req, board, &sdk)?.save(&req.build_dir, &req.project_name)?;
+        if let Some(elf) = &res.elf_path {
+            let objcopy = size::toolchain_tool(board, &sdk.toolchain_bin, "objcopy");
+            meta::BuildMeta::embed(&req.build_dir, &req.project_name, elf, &objcopy)?;
+        }
+        Ok(res)
+    })?;
+
+    let nm = size::nm_tool(board, &sdk.toolchain_bin);
+    let report = res.elf_path.as_deref().map(|elf| size::SizeReport::analyze(&nm, elf));
+    if let Some(Ok(report)) = &report {
+        let (flash, ram) = report.section_usage();
+        res.usage = Some((flash, ram));
+        events::emit(Event::Size {
+            flash, ram,
+            flash_max: board.flash_kb as u64 * 1024,
+            ram_max:   board.ram_kb as u64 * 1024,
+        });
+        if let Some(prev) = size::HistoryEntry::record(&req.build_dir, &req.project_name, report) {
+            if !res.size_info.is_empty() { res.size_info.push('\n'); }
+            res.size_info += &prev.compare(report);
+        }
     }
+    if !req.budget.is_empty() {
+        let report = report.ok_or_else(|| FlashError::Other(
+            "size budget: no .elf was produced to measure".into()))??;
+        req.budget.check(&report, board)?;
+    }
+
+    Ok(res)
 }
 
-/// Appends `lib_manager::libs_root()` to lib_include_dirs if it exists and
-/// is not already present, so installed libraries are auto-found.
-fn augment_lib_includes(req: &CompileRequest) -> CompileRequest {
+/// Appends the project's and the global library roots to lib_include_dirs
+/// if they exist and are not already present, so installed libraries are
+/// auto-found. Then
+/// resolves the libraries the sketch includes (the platform's last) and adds
+/// their own include dirs, warning about dependencies that aren't installed
+/// and libraries that don't list the board's architecture.
+fn augment_lib_includes(req: &CompileRequest, board: &Board, sdk: &sdk::SdkPaths) -> CompileRequest {
     let mut dirs = req.lib_include_dirs.clone();
 
-    if let Ok(libs_root) = crate::lib_manager::libs_root() {
-        if libs_root.is_dir() && !dirs.contains(&libs_root) {
+    for libs_root in crate::lib_manager::search_roots(&req.sketch_dir) {
+        if !dirs.contains(&libs_root) {
             dirs.push(libs_root);
         }
     }
 
+    // The platform's own libraries (Wire, SPI, …) sit next to its cores/.
+    let mut search = dirs.clone();
+    search.extend(sdk.libraries_dir.clone());
+    let platform_libs = sdk.platform_dir().join("libraries");
+    if platform_libs.is_dir() { search.push(platform_libs); }
+    let resolved = libraries::resolve(&req.sketch_dir, &search, board.arch());
+    for dep in &resolved.missing {
+        eprintln!("{} {} depends on \"{}\", which is not installed\n  Install it with: {}",
+            "!".yellow(), dep.library, dep.dependency,
+            format!("tsuki-flash lib install \"{}\"", dep.dependency).bold());
+    }
+    let libraries = resolved.libraries;
+    for lib in &libraries {
+        if !lib.supports(board.arch()) {
+            eprintln!("{} {} lists architectures {}, not {}; it may not build for {}",
+                "!".yellow(), lib.name, lib.architectures.join(","), board.arch(), board.id);
+        }
+        if req.verbose { eprintln!("  [lib] using {} ({})", lib.name, lib.root.display()); }
+        for dir in &lib.include_dirs {
+            if !dirs.contains(dir) { dirs.push(dir.clone()); }
+        }
+    }
+
     CompileRequest {
         sketch_dir:       req.sketch_dir.clone(),
         build_dir:        req.build_dir.clone(),
         project_name:     req.project_name.clone(),
         cpp_std:          req.cpp_std.clone(),
         lib_include_dirs: dirs,
+        libraries,
         use_modules:      req.use_modules,
+        obj_cache:        req.obj_cache,
+        cpu_mhz:          req.cpu_mhz,
+        partition_scheme: req.partition_scheme.clone(),
+        budget:           req.budget.clone(),
+        extra_flags:      req.extra_flags.clone(),
         verbose:          req.verbose,
     }
 }
\ No newline at end of file
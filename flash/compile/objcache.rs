@@ -0,0 +1,242 @@
+// ─────────────────────────────────────────────────────────────────────────────
+//  tsuki-flash :: compile :: objcache
+//
+//  Object files shared between build directories and projects, for
+//  `compile --obj-cache`. An object is keyed by the compiler's version, the
+//  flags, and the source's path and content; an entry also records the
+//  headers the source included (from its `.d` file) with their content
+//  hashes, and only counts as a hit while those still match.
+//
+//  Layout:  <root>/<key[..2]>/<key>/{obj.o, deps}
+//           <root>/stats.json     hit and miss counters
+// ─────────────────────────────────────────────────────────────────────────────
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde::{Deserialize, Serialize};
+use walkdir::WalkDir;
+
+use super::cache::{hash_file, hash_str, read_deps};
+use crate::error::{FlashError, Result};
+use crate::process::{self, Stage};
+
+const STATS_FILE: &str = "stats.json";
+
+/// Where the cache lives: `$TSUKI_OBJCACHE_DIR`, else
+/// `$XDG_CACHE_HOME/tsuki/objcache`, else `~/.cache/tsuki/objcache`.
+pub fn root() -> Result<PathBuf> {
+    if let Ok(dir) = std::env::var("TSUKI_OBJCACHE_DIR") {
+        return Ok(PathBuf::from(dir));
+    }
+    let base = match std::env::var("XDG_CACHE_HOME") {
+        Ok(x) if !x.is_empty() => PathBuf::from(x),
+        _ => std::env::var("HOME").or_else(|_| std::env::var("USERPROFILE"))
+            .map(|h| PathBuf::from(h).join(".cache"))
+            .map_err(|_| FlashError::Other("Cannot determine home directory".into()))?,
+    };
+    Ok(base.join("tsuki").join("objcache"))
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Stats {
+    pub hits:   u64,
+    pub misses: u64,
+}
+
+/// Entries, bytes on disk and counters of the cache at `root`.
+pub fn stats(root: &Path) -> (usize, u64, Stats) {
+    let mut entries = 0;
+    let mut bytes = 0;
+    for e in WalkDir::new(root).into_iter().flatten().filter(|e| e.file_type().is_file()) {
+        if e.file_name() == "obj.o" { entries += 1; }
+        bytes += e.metadata().map_or(0, |m| m.len());
+    }
+    let counters = std::fs::read_to_string(root.join(STATS_FILE)).ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default();
+    (entries, bytes, counters)
+}
+
+/// Remove every entry and reset the counters.
+pub fn clear(root: &Path) -> Result<()> {
+    if root.exists() {
+        std::fs::remove_dir_all(root)?;
+    }
+    Ok(())
+}
+
+/// An open cache, for one compile run. Counters are added to `stats.json`
+/// when it is dropped.
+pub struct ObjCache {
+    root:      PathBuf,
+    /// Compiler path → its `--version` banner.
+    compilers: Mutex<HashMap<String, String>>,
+    hits:      AtomicU64,
+    misses:    AtomicU64,
+}
+
+impl ObjCache {
+    /// The cache if `enabled` and its directory can be created.
+    pub fn open(enabled: bool) -> Option<Self> {
+        if !enabled { return None; }
+        let root = root().ok()?;
+        std::fs::create_dir_all(&root).ok()?;
+        Some(Self {
+            root,
+            compilers: Mutex::new(HashMap::new()),
+            hits:      AtomicU64::new(0),
+            misses:    AtomicU64::new(0),
+        })
+    }
+
+    /// The key for compiling `src` with `compiler` and `args` (everything
+    /// but `-c src -o obj`), or None when the source can't be read.
+    pub fn key(&self, compiler: &str, args: &[&str], src: &Path) -> Option<String> {
+        let version = self.compiler_version(compiler);
+        let content = hash_file(src)?;
+        Some(hash_str(&format!("{}\0{}\0{}\0{}", version, args.join("\0"), src.display(), content)))
+    }
+
+    /// Copy the cached object for `key` to `obj`, if its headers are unchanged.
+    pub fn fetch(&self, key: &str, obj: &Path) -> bool {
+        let entry = self.entry(key);
+        let hit = std::fs::read_to_string(entry.join("deps")).is_ok_and(|deps| {
+            deps.lines().all(|line| match line.split_once(' ') {
+                Some((hash, header)) => hash_file(Path::new(header)).as_deref() == Some(hash),
+                None => false,
+            })
+        }) && std::fs::copy(entry.join("obj.o"), obj).is_ok();
+        let counter = if hit { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        hit
+    }
+
+    /// Add a freshly compiled `obj` of `src` under `key`. Best-effort: a
+    /// cache that can't be written only costs a later recompile.
+    pub fn store(&self, key: &str, src: &Path, obj: &Path) {
+        let Some(headers) = read_deps(obj, src) else { return };
+        let deps: Option<Vec<String>> = headers.iter()
+            .map(|h| Some(format!("{} {}", hash_file(h)?, h.display())))
+            .collect();
+        let Some(deps) = deps else { return };
+
+        // Build the entry aside and rename it in, so a concurrent build
+        // never sees half of one. An entry already there was built against
+        // other headers; this one replaces it.
+        let entry = self.entry(key);
+        let tmp = entry.with_extension(format!("tmp{}", std::process::id()));
+        let written = std::fs::create_dir_all(&tmp).is_ok()
+            && std::fs::copy(obj, tmp.join("obj.o")).is_ok()
+            && std::fs::write(tmp.join("deps"), deps.join("\n")).is_ok();
+        let _ = std::fs::remove_dir_all(&entry);
+        if !written || std::fs::rename(&tmp, &entry).is_err() {
+            let _ = std::fs::remove_dir_all(&tmp);
+        }
+    }
+
+    fn entry(&self, key: &str) -> PathBuf {
+        self.root.join(&key[..2]).join(key)
+    }
+
+    fn compiler_version(&self, compiler: &str) -> String {
+        let mut known = self.compilers.lock().unwrap();
+        known.entry(compiler.to_owned()).or_insert_with(|| {
+            let banner = process::output(Command::new(compiler).arg("--version"), Stage::Compile)
+                .map(|o| String::from_utf8_lossy(&o.stdout).lines().next().unwrap_or_default().to_owned())
+                .unwrap_or_default();
+            format!("{} {}", compiler, banner)
+        }).clone()
+    }
+}
+
+impl Drop for ObjCache {
+    fn drop(&mut self) {
+        let path = self.root.join(STATS_FILE);
+        let mut stats: Stats = std::fs::read_to_string(&path).ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default();
+        stats.hits   += self.hits.load(Ordering::Relaxed);
+        stats.misses += self.misses.load(Ordering::Relaxed);
+        if let Ok(json) = serde_json::to_string(&stats) {
+            let _ = std::fs::write(path, json);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache_in(root: &Path) -> ObjCache {
+        std::fs::create_dir_all(root).unwrap();
+        ObjCache {
+            root:      root.to_owned(),
+            compilers: Mutex::new(HashMap::new()),
+            hits:      AtomicU64::new(0),
+            misses:    AtomicU64::new(0),
+        }
+    }
+
+    #[test]
+    fn test_key() {
+        let dir = std::env::temp_dir().join(format!("tsuki-objcache-key-{}", std::process::id()));
+        let cache = cache_in(&dir.join("cache"));
+        let src = dir.join("wiring.c");
+        std::fs::write(&src, "int x;\n").unwrap();
+        let cc = "tsuki-no-such-gcc";
+
+        let key = cache.key(cc, &["-Os"], &src).unwrap();
+        assert_eq!(cache.key(cc, &["-Os"], &src).unwrap(), key);
+        assert_ne!(cache.key(cc, &["-O2"], &src).unwrap(), key);
+        assert_ne!(cache.key("tsuki-other-gcc", &["-Os"], &src).unwrap(), key);
+        std::fs::write(&src, "int y;\n").unwrap();
+        assert_ne!(cache.key(cc, &["-Os"], &src).unwrap(), key);
+        assert!(cache.key(cc, &["-Os"], &dir.join("missing.c")).is_none());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_stale_headers_miss() {
+        let dir = std::env::temp_dir().join(format!("tsuki-objcache-hit-{}", std::process::id()));
+        let root = dir.join("cache");
+        let cache = cache_in(&root);
+        let (src, header, obj) = (dir.join("main.cpp"), dir.join("pins.h"), dir.join("main.o"));
+        std::fs::write(&src, "#include \"pins.h\"\n").unwrap();
+        std::fs::write(&header, "#define LED 13\n").unwrap();
+        std::fs::write(&obj, "object").unwrap();
+        let key = "ab".repeat(32);
+
+        // Nothing stored yet, and nothing stored without a `.d` file.
+        assert!(!cache.fetch(&key, &dir.join("out.o")));
+        cache.store(&key, &src, &obj);
+        assert!(!cache.fetch(&key, &dir.join("out.o")));
+
+        std::fs::write(obj.with_extension("d"), format!("main.o: {} {}\n", src.display(), header.display())).unwrap();
+        cache.store(&key, &src, &obj);
+        assert!(cache.fetch(&key, &dir.join("out.o")));
+        assert_eq!(std::fs::read_to_string(dir.join("out.o")).unwrap(), "object");
+
+        std::fs::write(&header, "#define LED 2\n").unwrap();
+        assert!(!cache.fetch(&key, &dir.join("stale.o")));
+        assert!(!dir.join("stale.o").exists());
+
+        // Storing again replaces the entry built against the old header.
+        std::fs::write(&obj, "rebuilt").unwrap();
+        cache.store(&key, &src, &obj);
+        assert!(cache.fetch(&key, &dir.join("out.o")));
+        assert_eq!(std::fs::read_to_string(dir.join("out.o")).unwrap(), "rebuilt");
+
+        drop(cache);
+        let (entries, bytes, counters) = stats(&root);
+        assert_eq!(entries, 1);
+        assert!(bytes > 0);
+        assert_eq!((counters.hits, counters.misses), (2, 3));
+        clear(&root).unwrap();
+        assert_eq!(stats(&root).0, 0);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
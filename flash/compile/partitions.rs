@@ -0,0 +1,183 @@
+// ─────────────────────────────────────────────────────────────────────────────
+//  tsuki-flash :: compile :: partitions  —  ESP32 partition tables
+//
+//  Reads the partitions.csv format of ESP-IDF / arduino-esp32 and writes the
+//  binary table the bootloader reads at 0x8000, as gen_esp32part.py does:
+//  32-byte entries, an MD5 entry over them, 0xFF up to 0xC00 bytes.
+// ─────────────────────────────────────────────────────────────────────────────
+
+use md5::{Digest, Md5};
+
+use crate::error::{FlashError, Result};
+
+/// Where the partition table lives in flash.
+pub const TABLE_OFFSET: u32 = 0x8000;
+/// The table's size in flash; the bootloader reads no further.
+const TABLE_SIZE: usize = 0xC00;
+
+const MAGIC:     [u8; 2] = [0xAA, 0x50];
+const MD5_MAGIC: [u8; 2] = [0xEB, 0xEB];
+
+const TYPE_APP:  u8 = 0x00;
+const TYPE_DATA: u8 = 0x01;
+const SUBTYPE_OTA: u8 = 0x00;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Partition {
+    pub name:    String,
+    pub kind:    u8,
+    pub subtype: u8,
+    pub offset:  u32,
+    pub size:    u32,
+    pub flags:   u32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PartitionTable {
+    pub partitions: Vec<Partition>,
+}
+
+impl PartitionTable {
+    /// Parse a partitions.csv: `name, type, subtype, offset, size, flags`.
+    /// A blank offset follows the previous partition (apps 64 KB-aligned).
+    pub fn from_csv(text: &str) -> Result<Self> {
+        let mut partitions = Vec::new();
+        let mut next = TABLE_OFFSET + 0x1000;
+
+        for (n, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() { continue; }
+            let err = |msg: String| FlashError::Other(format!("partitions.csv line {}: {}", n + 1, msg));
+
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            if fields.len() < 5 {
+                return Err(err("expected name, type, subtype, offset, size".into()));
+            }
+            let kind = parse_type(fields[1]).ok_or_else(|| err(format!("unknown type '{}'", fields[1])))?;
+            let subtype = parse_subtype(kind, fields[2])
+                .ok_or_else(|| err(format!("unknown subtype '{}'", fields[2])))?;
+            let align = if kind == TYPE_APP { 0x10000 } else { 0x1000 };
+            let offset = match fields[3] {
+                "" => next.div_ceil(align) * align,
+                s  => parse_size(s).ok_or_else(|| err(format!("bad offset '{}'", s)))?,
+            };
+            if offset % align != 0 {
+                return Err(err(format!("offset {:#x} is not {:#x}-aligned", offset, align)));
+            }
+            if offset < next {
+                return Err(err(format!("offset {:#x} overlaps the previous partition", offset)));
+            }
+            let size = parse_size(fields[4]).ok_or_else(|| err(format!("bad size '{}'", fields[4])))?;
+            let flags = match fields.get(5).copied().unwrap_or("") {
+                ""          => 0,
+                "encrypted" => 1,
+                f           => return Err(err(format!("unknown flag '{}'", f))),
+            };
+            if fields[0].len() > 16 {
+                return Err(err(format!("name '{}' is longer than 16 bytes", fields[0])));
+            }
+
+            partitions.push(Partition { name: fields[0].to_owned(), kind, subtype, offset, size, flags });
+            next = offset + size;
+        }
+        Ok(PartitionTable { partitions })
+    }
+
+    /// The table as flashed at TABLE_OFFSET.
+    pub fn to_bin(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(TABLE_SIZE);
+        for p in &self.partitions {
+            out.extend_from_slice(&MAGIC);
+            out.push(p.kind);
+            out.push(p.subtype);
+            out.extend_from_slice(&p.offset.to_le_bytes());
+            out.extend_from_slice(&p.size.to_le_bytes());
+            let mut label = [0u8; 16];
+            label[..p.name.len()].copy_from_slice(p.name.as_bytes());
+            out.extend_from_slice(&label);
+            out.extend_from_slice(&p.flags.to_le_bytes());
+        }
+        let digest = Md5::digest(&out);
+        out.extend_from_slice(&MD5_MAGIC);
+        out.extend_from_slice(&[0xFF; 14]);
+        out.extend_from_slice(&digest);
+        out.resize(TABLE_SIZE, 0xFF);
+        out
+    }
+
+    /// Read back a table written by `to_bin` (or gen_esp32part.py).
+    pub fn from_bin(data: &[u8]) -> Self {
+        let partitions = data.chunks_exact(32)
+            .take_while(|e| e[..2] == MAGIC)
+            .map(|e| Partition {
+                name:    String::from_utf8_lossy(&e[12..28]).trim_end_matches('\0').to_owned(),
+                kind:    e[2],
+                subtype: e[3],
+                offset:  u32::from_le_bytes([e[4], e[5], e[6], e[7]]),
+                size:    u32::from_le_bytes([e[8], e[9], e[10], e[11]]),
+                flags:   u32::from_le_bytes([e[28], e[29], e[30], e[31]]),
+            })
+            .collect();
+        PartitionTable { partitions }
+    }
+
+    /// Where the app goes: the factory partition, or the first OTA slot.
+    pub fn app_offset(&self) -> Option<u32> {
+        self.partitions.iter()
+            .filter(|p| p.kind == TYPE_APP)
+            .min_by_key(|p| p.subtype)
+            .map(|p| p.offset)
+    }
+
+    /// The OTA data partition, which boot_app0.bin initialises.
+    pub fn otadata_offset(&self) -> Option<u32> {
+        self.partitions.iter()
+            .find(|p| p.kind == TYPE_DATA && p.subtype == SUBTYPE_OTA)
+            .map(|p| p.offset)
+    }
+}
+
+fn parse_type(s: &str) -> Option<u8> {
+    match s {
+        "app"  => Some(TYPE_APP),
+        "data" => Some(TYPE_DATA),
+        _      => parse_size(s).and_then(|n| u8::try_from(n).ok()),
+    }
+}
+
+fn parse_subtype(kind: u8, s: &str) -> Option<u8> {
+    let named = match (kind, s) {
+        (TYPE_APP, "factory") => Some(0x00),
+        (TYPE_APP, "test")    => Some(0x20),
+        (TYPE_APP, s) if s.starts_with("ota_") => {
+            s[4..].parse::<u8>().ok().filter(|n| *n < 16).map(|n| 0x10 + n)
+        }
+        (TYPE_DATA, "ota")       => Some(0x00),
+        (TYPE_DATA, "phy")       => Some(0x01),
+        (TYPE_DATA, "nvs")       => Some(0x02),
+        (TYPE_DATA, "coredump")  => Some(0x03),
+        (TYPE_DATA, "nvs_keys")  => Some(0x04),
+        (TYPE_DATA, "efuse")     => Some(0x05),
+        (TYPE_DATA, "undefined") => Some(0x06),
+        (TYPE_DATA, "esphttpd")  => Some(0x80),
+        (TYPE_DATA, "fat")       => Some(0x81),
+        (TYPE_DATA, "spiffs")    => Some(0x82),
+        (TYPE_DATA, "littlefs")  => Some(0x83),
+        _ => None,
+    };
+    named.or_else(|| parse_size(s).and_then(|n| u8::try_from(n).ok()))
+}
+
+/// `0x10000`, `65536`, `64K` or `1M`.
+fn parse_size(s: &str) -> Option<u32> {
+    let s = s.trim();
+    if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        return u32::from_str_radix(hex, 16).ok();
+    }
+    let (digits, unit) = match s.char_indices().last()? {
+        (i, 'K' | 'k') => (&s[..i], 1024),
+        (i, 'M' | 'm') => (&s[..i], 1024 * 1024),
+        _              => (s, 1),
+    };
+    digits.parse::<u32>().ok()?.checked_mul(unit)
+}
@@ -0,0 +1,271 @@
+// ─────────────────────────────────────────────────────────────────────────────
+//  tsuki-flash :: compile :: rp2040
+//
+//  Compiles sketches for RP2040 boards with arm-none-eabi-gcc against the
+//  Arduino-Pico core (earlephilhower/arduino-pico, `rp2040:rp2040`).
+//
+//  Pipeline:
+//    1. Compile the core (cores/rp2040, recursively) → core.a  (cached)
+//    2. Assemble the board's second-stage bootloader (boot2)
+//    3. Compile sketch sources  (parallel, incremental cache)
+//       and the libraries it includes → libs/<name>/<name>.a
+//    4. Fill in the core's linker script template, link → firmware.elf
+//    5. firmware.elf → firmware.uf2  (built-in writer)  +  firmware.bin
+// ─────────────────────────────────────────────────────────────────────────────
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use rayon::prelude::*;
+
+use crate::boards::Board;
+use crate::error::{FlashError, Result};
+use crate::events::{self, Event};
+use crate::process::{self, Stage};
+use crate::sdk::SdkPaths;
+use super::avr::{build_core, collect_sketch_sources, resolve_tool, run_tool};
+use super::cache::{CacheManifest, hash_str, obj_path};
+use super::libraries;
+use super::objcache::ObjCache;
+use super::uf2;
+use super::{CompileRequest, CompileResult, Failures};
+
+/// Where the RP2040 maps its external flash.
+const XIP_BASE: u32 = 0x1000_0000;
+/// Flash kept at the end for the core's EEPROM emulation.
+const EEPROM_SIZE: u32 = 4096;
+
+/// Per-board settings from the core's boards.txt that aren't in `Board`.
+struct Rp2040Board {
+    /// Second-stage bootloader for the board's flash chip (boot2/<name>.S).
+    boot2:   &'static str,
+    usb_pid: &'static str,
+    product: &'static str,
+}
+
+/// The only RP2040 board in the catalog is the Pico; new ones add an arm
+/// here.
+fn board_settings(_board: &Board) -> Rp2040Board {
+    Rp2040Board { boot2: "boot2_w25q080_2_padded_checksum", usb_pid: "0x000a", product: "Pico" }
+}
+
+pub fn run(req: &CompileRequest, board: &Board, sdk: &SdkPaths) -> Result<CompileResult> {
+    let f_cpu = board.clock_hz(req.cpu_mhz)?;
+    std::fs::create_dir_all(&req.build_dir)?;
+    let platform = sdk.platform_dir();
+    let settings = board_settings(board);
+
+    let cc      = resolve_tool(&sdk.toolchain_bin, "arm-none-eabi-gcc");
+    let cxx     = resolve_tool(&sdk.toolchain_bin, "arm-none-eabi-g++");
+    let ar      = resolve_tool(&sdk.toolchain_bin, "arm-none-eabi-ar");
+    let objcopy = resolve_tool(&sdk.toolchain_bin, "arm-none-eabi-objcopy");
+
+    // Newer cores keep the prebuilt SDK under lib/rp2040/, older ones in lib/.
+    let lib_dir = [platform.join("lib").join("rp2040"), platform.join("lib")].into_iter()
+        .find(|d| d.join("platform_inc.txt").is_file())
+        .ok_or_else(|| FlashError::Other(format!(
+            "{} doesn't look like the Arduino-Pico core (no lib/platform_inc.txt)", platform.display())))?;
+
+    // ── Shared compiler flags ─────────────────────────────────────────────
+    let flash_bytes = board.flash_kb * 1024;
+    let mut common_flags: Vec<String> = vec![
+        "-mcpu=cortex-m0plus".into(), "-mthumb".into(), "-march=armv6-m".into(),
+        "-Os".into(), "-w".into(),
+        "-ffunction-sections".into(), "-fdata-sections".into(),
+        "-MMD".into(),
+        format!("-DF_CPU={}L", f_cpu),
+        "-DARDUINO=10819".into(),
+        "-DTARGET_RP2040".into(), "-DPICO_RP2040".into(),
+        format!("-DARDUINO_VARIANT=\"{}\"", board.variant),
+        format!("-DPICO_FLASH_SIZE_BYTES={}", flash_bytes),
+        "-DCFG_TUSB_MCU=OPT_MCU_RP2040".into(),
+        "-DUSBD_VID=0x2e8a".into(),
+        format!("-DUSBD_PID={}", settings.usb_pid),
+        "-DUSBD_MAX_POWER_MA=250".into(),
+        "-DUSB_MANUFACTURER=\"Raspberry Pi\"".into(),
+        format!("-DUSB_PRODUCT=\"{}\"", settings.product),
+        "-DLWIP_IPV6=0".into(), "-DLWIP_IPV4=1".into(),
+        format!("-iprefix{}/", platform.display()),
+        format!("@{}", lib_dir.join("platform_inc.txt").display()),
+        format!("-I{}", platform.join("include").display()),
+        format!("-I{}", sdk.core_dir.display()),
+        format!("-I{}", sdk.variant_dir.display()),
+    ];
+    for d in &board.defines {
+        common_flags.push(format!("-D{}", d));
+    }
+
+    let mut includes = common_flags.clone();
+    for lib_dir in &req.lib_include_dirs {
+        includes.push(format!("-I{}", lib_dir.display()));
+    }
+    if let Some(ld) = &sdk.libraries_dir {
+        includes.push(format!("-I{}", ld.display()));
+    }
+    includes.extend(req.extra_flags.define_flags());
+
+    // The core needs C++17; a newer standard asked for is kept.
+    let std_num: u32 = req.cpp_std.trim_start_matches("c++").trim_start_matches("gnu++").parse().unwrap_or(0);
+    let cxx_std_flag = format!("-std=gnu++{}", if (17..98).contains(&std_num) { std_num } else { 17 });
+    let mut cflags: Vec<&str> = vec!["-x", "c", "-std=gnu17"];
+    let mut cxxflags: Vec<&str> = vec![
+        "-x", "c++",
+        &cxx_std_flag,
+        "-fno-exceptions", "-fno-rtti",
+        "-fno-threadsafe-statics",
+    ];
+    cflags.extend(req.extra_flags.cflags.iter().map(String::as_str));
+    cxxflags.extend(req.extra_flags.cxxflags.iter().map(String::as_str));
+
+    let flags_sig = hash_str(&format!("{:?}{:?}{:?}", includes, cflags, cxxflags));
+    let core_sig  = hash_str(&format!("core{}{}{}{}", board.id, f_cpu, sdk.sdk_version, flags_sig));
+
+    // ── Step 1: core.a ────────────────────────────────────────────────────
+    let core_obj_dir = req.build_dir.join("core");
+    std::fs::create_dir_all(&core_obj_dir)?;
+    let core_a = req.build_dir.join("core.a");
+    let obj_cache = ObjCache::open(req.obj_cache);
+    build_core(&cc, &cxx, &ar, &sdk.core_dir, usize::MAX, &core_obj_dir, &core_a,
+               &includes, &cflags, &cxxflags, &core_sig, obj_cache.as_ref(), req.verbose)?;
+
+    // ── Step 2: boot2 ─────────────────────────────────────────────────────
+    let boot2_src = platform.join("boot2").join(format!("{}.S", settings.boot2));
+    let boot2_obj = req.build_dir.join("boot2.o");
+    let mut boot2_cmd = Command::new(&cc);
+    boot2_cmd.args(&common_flags).args(["-x", "assembler-with-cpp", "-c"])
+        .arg(&boot2_src).arg("-o").arg(&boot2_obj);
+    let out = process::output(&mut boot2_cmd, Stage::Compile)?;
+    if !out.status.success() {
+        return Err(FlashError::CompileFailed {
+            output: format!("In {}:\n{}", boot2_src.display(), String::from_utf8_lossy(&out.stderr)),
+        });
+    }
+
+    // ── Step 3: Sketch sources ────────────────────────────────────────────
+    let sketch_dir = req.build_dir.join("sketch");
+    std::fs::create_dir_all(&sketch_dir)?;
+    let mut sources = collect_sketch_sources(&req.sketch_dir)?;
+    if sources.is_empty() {
+        return Err(FlashError::Other(format!(
+            "No .cpp/.c/.ino sources found in {}", req.sketch_dir.display()
+        )));
+    }
+    // The variant's own sources (pin tables on some boards) go with the sketch.
+    sources.extend(collect_sketch_sources(&sdk.variant_dir)?);
+
+    let failures = Failures::default();
+    let mut manifest = CacheManifest::load(&sketch_dir);
+
+    let obj_files: Vec<PathBuf> = sources.par_iter().map(|src| {
+        let obj = obj_path(&sketch_dir, src);
+        if manifest.is_fresh(src, &obj, &flags_sig) {
+            if req.verbose { eprintln!("  [cache] {}", src.display()); }
+            events::emit(Event::Fresh { unit: "sketch", file: src, cache: "build" });
+            return obj;
+        }
+
+        let is_c = src.extension().and_then(|e| e.to_str()) == Some("c");
+        let mut cmd = Command::new(if is_c { &cc } else { &cxx });
+        cmd.args(&includes);
+        cmd.args(if is_c { &cflags } else { &cxxflags });
+        cmd.arg("-c").arg(src).arg("-o").arg(&obj);
+
+        if req.verbose { eprintln!("  [compile] {}", src.display()); }
+        events::emit(Event::Compiling { unit: "sketch", file: src });
+
+        failures.compile(&mut cmd, &obj, || format!("In {}:\n", src.display()));
+        obj
+    }).collect();
+
+    for src in &sources {
+        let obj = obj_path(&sketch_dir, src);
+        if obj.exists() { manifest.record(src, &obj, &flags_sig); }
+    }
+    let _ = manifest.save(&sketch_dir);
+
+    failures.check("\n\n")?;
+
+    // ── Libraries ─────────────────────────────────────────────────────────
+    let lib_archives = libraries::build(&req.libraries, &cc, &cxx, &ar, &req.build_dir.join("libs"),
+                                        &includes, &cflags, &cxxflags, &flags_sig,
+                                        obj_cache.as_ref(), req.verbose)?;
+
+    // ── Step 4: Link ──────────────────────────────────────────────────────
+    let memmap = req.build_dir.join("memmap_default.ld");
+    write_linker_script(&lib_dir.join("memmap_default.ld"), &memmap, flash_bytes, board.ram_kb)?;
+
+    let elf_path = req.build_dir.join(format!("{}.elf", req.project_name));
+    let map_path = req.build_dir.join(format!("{}.map", req.project_name));
+    let mut link_cmd = Command::new(&cxx);
+    link_cmd
+        .args(["-mcpu=cortex-m0plus", "-mthumb", "-Os", "-u", "_printf_float", "-u", "_scanf_float"])
+        .args(["-Wl,--check-sections", "-Wl,--gc-sections", "-Wl,--unresolved-symbols=report-all"])
+        .arg(format!("-Wl,--script={}", memmap.display()))
+        .arg(format!("-Wl,-Map,{}", map_path.display()));
+    let wrap = lib_dir.join("platform_wrap.txt");
+    if wrap.is_file() {
+        link_cmd.arg(format!("@{}", wrap.display()));
+    }
+    link_cmd.arg(&boot2_obj);
+    for obj in &obj_files {
+        link_cmd.arg(obj);
+    }
+    link_cmd.args(libraries::link_group(&lib_archives));
+    link_cmd.arg("-Wl,--whole-archive").arg(&core_a).arg("-Wl,--no-whole-archive");
+    link_cmd.args(&req.extra_flags.ldflags);
+    link_cmd.arg("-Wl,--start-group");
+    for lib in ["libpico.a", "libbearssl.a"] {
+        let path = lib_dir.join(lib);
+        if path.is_file() { link_cmd.arg(path); }
+    }
+    link_cmd.args(["-lm", "-lc", "-lstdc++", "-lc", "-Wl,--end-group"]);
+    link_cmd.arg("-o").arg(&elf_path);
+
+    events::emit(Event::Linking { output: &elf_path });
+    let link_out = process::output(&mut link_cmd, Stage::Link)?;
+    if !link_out.status.success() {
+        return Err(FlashError::LinkFailed {
+            output: String::from_utf8_lossy(&link_out.stderr).to_string(),
+        });
+    }
+
+    // ── Step 5: .uf2 + .bin ───────────────────────────────────────────────
+    let uf2_path = req.build_dir.join(format!("{}.uf2", req.project_name));
+    uf2::write(&elf_path, &uf2_path, uf2::FAMILY_RP2040, XIP_BASE, XIP_BASE + flash_bytes)?;
+
+    let bin_path = req.build_dir.join(format!("{}.bin", req.project_name));
+    run_tool(&objcopy, &["-O", "binary", elf_path.to_str().unwrap(), bin_path.to_str().unwrap()])?;
+
+    let size_info = firmware_size(&sdk.toolchain_bin, &elf_path);
+    Ok(CompileResult {
+        hex_path: None,
+        bin_path: Some(bin_path),
+        elf_path: Some(elf_path),
+        uf2_path: Some(uf2_path),
+        size_info,
+        usage:    None,
+    })
+}
+
+/// Fill in the core's linker script template the way its build does: all
+/// flash but the EEPROM sector for the program, no filesystem.
+fn write_linker_script(template: &Path, out: &Path, flash_bytes: u32, ram_kb: u32) -> Result<()> {
+    let text = std::fs::read_to_string(template).map_err(|e| FlashError::Other(
+        format!("cannot read the linker script template {}: {}", template.display(), e)))?;
+    let eeprom_start = XIP_BASE + flash_bytes - EEPROM_SIZE;
+    let text = text
+        .replace("__FLASH_LENGTH__", &(flash_bytes - EEPROM_SIZE).to_string())
+        .replace("__EEPROM_START__", &eeprom_start.to_string())
+        .replace("__FS_START__", &eeprom_start.to_string())
+        .replace("__FS_END__", &eeprom_start.to_string())
+        .replace("__RAM_LENGTH__", &format!("{}k", ram_kb.min(256)));
+    std::fs::write(out, text)?;
+    Ok(())
+}
+
+fn firmware_size(bin_dir: &Path, elf: &Path) -> String {
+    let size = resolve_tool(bin_dir, "arm-none-eabi-size");
+    match process::output(Command::new(&size).arg(elf), Stage::Link) {
+        Ok(o) if o.status.success() => String::from_utf8_lossy(&o.stdout).trim().to_string(),
+        _ => "(size unknown)".into(),
+    }
+}
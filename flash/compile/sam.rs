@@ -0,0 +1,219 @@
+// ─────────────────────────────────────────────────────────────────────────────
+//  tsuki-flash :: compile :: sam
+//
+//  Compiles sketches for the Arduino Due (SAM3X8E) with arm-none-eabi-gcc
+//  against the Arduino SAM core (`arduino:sam`).
+//
+//  Pipeline:
+//    1. Compile the core (cores/arduino, recursively) → core.a  (cached)
+//    2. Compile sketch + variant sources  (parallel, incremental cache)
+//       and the libraries it includes → libs/<name>/<name>.a
+//    3. Link against core.a and the variant's prebuilt libsam → firmware.elf
+//    4. firmware.elf → firmware.bin  (what bossac writes)
+// ─────────────────────────────────────────────────────────────────────────────
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use rayon::prelude::*;
+
+use crate::boards::{Board, Toolchain};
+use crate::error::{FlashError, Result};
+use crate::events::{self, Event};
+use crate::process::{self, Stage};
+use crate::sdk::SdkPaths;
+use super::avr::{build_core, collect_sketch_sources, resolve_tool, run_tool};
+use super::cache::{CacheManifest, hash_str, obj_path};
+use super::libraries;
+use super::objcache::ObjCache;
+use super::{CompileRequest, CompileResult, Failures};
+
+/// Per-board settings from the core's boards.txt that aren't in `Board`.
+struct SamBoard {
+    /// Prebuilt chip library shipped in the variant folder.
+    system_lib: &'static str,
+    usb_pid:    &'static str,
+    product:    &'static str,
+}
+
+/// The Due (programming port) is the only SAM board in the catalog.
+fn board_settings(_board: &Board) -> SamBoard {
+    SamBoard { system_lib: "libsam_sam3x8e_gcc_rel.a", usb_pid: "0x003e", product: "Arduino Due" }
+}
+
+pub fn run(req: &CompileRequest, board: &Board, sdk: &SdkPaths) -> Result<CompileResult> {
+    let f_cpu = board.clock_hz(req.cpu_mhz)?;
+    let Toolchain::Sam { mcu, .. } = &board.toolchain else {
+        return Err(FlashError::Other("Not a SAM board".into()));
+    };
+    std::fs::create_dir_all(&req.build_dir)?;
+    let platform = sdk.platform_dir();
+    let system   = platform.join("system");
+    let settings = board_settings(board);
+
+    let cc      = resolve_tool(&sdk.toolchain_bin, "arm-none-eabi-gcc");
+    let cxx     = resolve_tool(&sdk.toolchain_bin, "arm-none-eabi-g++");
+    let ar      = resolve_tool(&sdk.toolchain_bin, "arm-none-eabi-ar");
+    let objcopy = resolve_tool(&sdk.toolchain_bin, "arm-none-eabi-objcopy");
+
+    // ── Shared compiler flags ─────────────────────────────────────────────
+    let mut common_flags: Vec<String> = vec![
+        format!("-mcpu={}", mcu), "-mthumb".into(),
+        "-Os".into(), "-w".into(),
+        "-ffunction-sections".into(), "-fdata-sections".into(),
+        "-nostdlib".into(), "--param".into(), "max-inline-insns-single=500".into(),
+        "-Dprintf=iprintf".into(),
+        "-MMD".into(),
+        format!("-DF_CPU={}L", f_cpu),
+        "-DARDUINO=10819".into(),
+        "-DUSB_VID=0x2341".into(),
+        format!("-DUSB_PID={}", settings.usb_pid),
+        "-DUSBCON".into(),
+        "-DUSB_MANUFACTURER=\"Arduino LLC\"".into(),
+        format!("-DUSB_PRODUCT=\"{}\"", settings.product),
+        format!("-I{}", system.join("libsam").display()),
+        format!("-I{}", system.join("CMSIS").join("CMSIS").join("Include").display()),
+        format!("-I{}", system.join("CMSIS").join("Device").join("ATMEL").display()),
+        format!("-I{}", sdk.core_dir.display()),
+        format!("-I{}", sdk.variant_dir.display()),
+    ];
+    for d in &board.defines {
+        common_flags.push(format!("-D{}", d));
+    }
+
+    let mut includes = common_flags.clone();
+    for lib_dir in &req.lib_include_dirs {
+        includes.push(format!("-I{}", lib_dir.display()));
+    }
+    if let Some(ld) = &sdk.libraries_dir {
+        includes.push(format!("-I{}", ld.display()));
+    }
+    includes.extend(req.extra_flags.define_flags());
+
+    let cxx_std_flag = format!("-std=gnu++{}", req.cpp_std.trim_start_matches("c++").trim_start_matches("gnu++"));
+    let mut cflags: Vec<&str> = vec!["-x", "c", "-std=gnu11"];
+    let mut cxxflags: Vec<&str> = vec![
+        "-x", "c++",
+        &cxx_std_flag,
+        "-fno-exceptions", "-fno-rtti",
+        "-fno-threadsafe-statics",
+    ];
+    cflags.extend(req.extra_flags.cflags.iter().map(String::as_str));
+    cxxflags.extend(req.extra_flags.cxxflags.iter().map(String::as_str));
+
+    let flags_sig = hash_str(&format!("{:?}{:?}{:?}", includes, cflags, cxxflags));
+    let core_sig  = hash_str(&format!("core{}{}{}{}", board.id, f_cpu, sdk.sdk_version, flags_sig));
+
+    // ── Step 1: core.a ────────────────────────────────────────────────────
+    let core_obj_dir = req.build_dir.join("core");
+    std::fs::create_dir_all(&core_obj_dir)?;
+    let core_a = req.build_dir.join("core.a");
+    let obj_cache = ObjCache::open(req.obj_cache);
+    build_core(&cc, &cxx, &ar, &sdk.core_dir, usize::MAX, &core_obj_dir, &core_a,
+               &includes, &cflags, &cxxflags, &core_sig, obj_cache.as_ref(), req.verbose)?;
+
+    // ── Step 2: Sketch sources ────────────────────────────────────────────
+    let sketch_dir = req.build_dir.join("sketch");
+    std::fs::create_dir_all(&sketch_dir)?;
+    let mut sources = collect_sketch_sources(&req.sketch_dir)?;
+    if sources.is_empty() {
+        return Err(FlashError::Other(format!(
+            "No .cpp/.c/.ino sources found in {}", req.sketch_dir.display()
+        )));
+    }
+    // variant.cpp holds the pin tables and the startup code's init().
+    sources.extend(collect_sketch_sources(&sdk.variant_dir)?);
+
+    let failures = Failures::default();
+    let mut manifest = CacheManifest::load(&sketch_dir);
+
+    let obj_files: Vec<PathBuf> = sources.par_iter().map(|src| {
+        let obj = obj_path(&sketch_dir, src);
+        if manifest.is_fresh(src, &obj, &flags_sig) {
+            if req.verbose { eprintln!("  [cache] {}", src.display()); }
+            events::emit(Event::Fresh { unit: "sketch", file: src, cache: "build" });
+            return obj;
+        }
+
+        let is_c = src.extension().and_then(|e| e.to_str()) == Some("c");
+        let mut cmd = Command::new(if is_c { &cc } else { &cxx });
+        cmd.args(&includes);
+        cmd.args(if is_c { &cflags } else { &cxxflags });
+        cmd.arg("-c").arg(src).arg("-o").arg(&obj);
+
+        if req.verbose { eprintln!("  [compile] {}", src.display()); }
+        events::emit(Event::Compiling { unit: "sketch", file: src });
+
+        failures.compile(&mut cmd, &obj, || format!("In {}:\n", src.display()));
+        obj
+    }).collect();
+
+    for src in &sources {
+        let obj = obj_path(&sketch_dir, src);
+        if obj.exists() { manifest.record(src, &obj, &flags_sig); }
+    }
+    let _ = manifest.save(&sketch_dir);
+
+    failures.check("\n\n")?;
+
+    // ── Libraries ─────────────────────────────────────────────────────────
+    let lib_archives = libraries::build(&req.libraries, &cc, &cxx, &ar, &req.build_dir.join("libs"),
+                                        &includes, &cflags, &cxxflags, &flags_sig,
+                                        obj_cache.as_ref(), req.verbose)?;
+
+    // ── Step 3: Link ──────────────────────────────────────────────────────
+    let linker_script = sdk.variant_dir.join("linker_scripts").join("gcc").join("flash.ld");
+    let system_lib    = sdk.variant_dir.join(settings.system_lib);
+    let elf_path = req.build_dir.join(format!("{}.elf", req.project_name));
+    let map_path = req.build_dir.join(format!("{}.map", req.project_name));
+    let mut link_cmd = Command::new(&cxx);
+    link_cmd
+        .args([&format!("-mcpu={}", mcu) as &str, "-mthumb", "-Os"])
+        .args(["-Wl,--gc-sections", "-Wl,--check-sections", "-Wl,--entry=Reset_Handler"])
+        .args(["-Wl,--unresolved-symbols=report-all", "-Wl,--warn-common"])
+        .arg(format!("-T{}", linker_script.display()))
+        .arg(format!("-Wl,-Map,{}", map_path.display()));
+    // The core's syscall stubs are only reached through newlib, which comes
+    // after them on the command line.
+    link_cmd.arg("-Wl,--start-group");
+    for sym in ["_sbrk", "link", "_close", "_fstat", "_isatty", "_lseek", "_read", "_write", "_exit", "kill", "_getpid"] {
+        link_cmd.args(["-u", sym]);
+    }
+    for obj in &obj_files {
+        link_cmd.arg(obj);
+    }
+    link_cmd.args(libraries::link_group(&lib_archives));
+    link_cmd.arg(&system_lib).arg(&core_a);
+    link_cmd.args(&req.extra_flags.ldflags);
+    link_cmd.args(["-Wl,--end-group", "-lm", "-lgcc"]);
+    link_cmd.arg("-o").arg(&elf_path);
+
+    events::emit(Event::Linking { output: &elf_path });
+    let link_out = process::output(&mut link_cmd, Stage::Link)?;
+    if !link_out.status.success() {
+        return Err(FlashError::LinkFailed {
+            output: String::from_utf8_lossy(&link_out.stderr).to_string(),
+        });
+    }
+
+    // ── Step 4: .bin ──────────────────────────────────────────────────────
+    let bin_path = req.build_dir.join(format!("{}.bin", req.project_name));
+    run_tool(&objcopy, &["-O", "binary", elf_path.to_str().unwrap(), bin_path.to_str().unwrap()])?;
+
+    let size_info = firmware_size(&sdk.toolchain_bin, &elf_path);
+    Ok(CompileResult {
+        hex_path: None,
+        bin_path: Some(bin_path),
+        elf_path: Some(elf_path),
+        uf2_path: None,
+        size_info,
+        usage:    None,
+    })
+}
+
+fn firmware_size(bin_dir: &Path, elf: &Path) -> String {
+    let size = resolve_tool(bin_dir, "arm-none-eabi-size");
+    match process::output(Command::new(&size).arg(elf), Stage::Link) {
+        Ok(o) if o.status.success() => String::from_utf8_lossy(&o.stdout).trim().to_string(),
+        _ => "(size unknown)".into(),
+    }
+}
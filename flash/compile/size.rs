@@ -0,0 +1,777 @@
+// ─────────────────────────────────────────────────────────────────────────────
+//  tsuki-flash :: compile :: size
+//
+//  Attributes the linked firmware's symbols to modules, enforces the size
+//  budgets declared in the project manifest, and compares builds (`size-diff`
+//  and the per-project history log). The linker map, when the pipeline
+//  writes one, attributes every input section to its object file and
+//  archive for `tsuki-flash size`.
+//
+//  A symbol's module comes from the source file nm reports for it:
+//    …/libraries/<Name>/…   → <Name>
+//    …/cores/…              → core
+//    pkg__Symbol            → pkg   (tsuki-mangled package symbol)
+//    anything else with a file → sketch, without one → other
+// ─────────────────────────────────────────────────────────────────────────────
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::boards::{Board, Toolchain};
+use crate::error::{FlashError, Result};
+use crate::process::{self, Stage};
+
+// ── Report ────────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone)]
+pub struct SymbolSize {
+    pub name:   String,
+    pub module: String,
+    /// Source file nm found in the debug info, without its directory
+    /// (empty for code built without `-g`).
+    pub file:   String,
+    /// Bytes in program memory (code, read-only and initialised data).
+    pub flash:  u64,
+    /// Bytes of RAM (initialised and zeroed data).
+    pub ram:    u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SizeReport {
+    pub symbols:  Vec<SymbolSize>,
+    /// Allocated ELF sections and their sizes, in file order.
+    pub sections: Vec<(String, u64)>,
+}
+
+impl SizeReport {
+    /// Run `nm` over the ELF and attribute every sized symbol.
+    pub fn analyze(nm: &str, elf: &Path) -> Result<Self> {
+        let sections = Elf::read(elf)?.sections;
+        let out = process::output(Command::new(nm).args(["-S", "-C", "-l", "--defined-only"]).arg(elf), Stage::Link)
+            .map_err(|e| match e {
+                FlashError::Io(e) => FlashError::ToolchainNotFound(format!("{}: {}", nm, e)),
+                e => e,
+            })?;
+        if !out.status.success() {
+            return Err(FlashError::Other(format!(
+                "{} failed: {}", nm, String::from_utf8_lossy(&out.stderr).trim())));
+        }
+        Ok(Self { sections, ..Self::parse(&String::from_utf8_lossy(&out.stdout)) })
+    }
+
+    /// Parse `nm -S -C -l` output: `addr size type name[\tfile:line]`.
+    pub fn parse(nm_output: &str) -> Self {
+        let symbols = nm_output.lines().filter_map(|line| {
+            let (sym, loc) = line.split_once('\t').unwrap_or((line, ""));
+            let mut parts = sym.splitn(4, ' ');
+            let addr  = parts.next()?;
+            // Unsized symbols have no size column; it is as wide as the address.
+            let size  = parts.next().filter(|s| s.len() == addr.len())?;
+            let size  = u64::from_str_radix(size, 16).ok()?;
+            let kind  = parts.next()?.chars().next()?;
+            let name  = parts.next()?.trim().to_owned();
+            let (flash, ram) = match kind.to_ascii_lowercase() {
+                't' | 'w' | 'r' => (size, 0),
+                'd' | 'g'       => (size, size),
+                'b' | 's'       => (0, size),
+                _ => return None,
+            };
+            let file = loc.rsplit_once(':').map_or(loc, |(p, _)| p)
+                .rsplit(['/', '\\']).next().unwrap_or_default().to_owned();
+            Some(SymbolSize { module: module_of(&name, loc), file, name, flash, ram })
+        }).collect();
+        Self { symbols, sections: Vec::new() }
+    }
+
+    pub fn flash(&self) -> u64 { self.symbols.iter().map(|s| s.flash).sum() }
+    pub fn ram(&self)   -> u64 { self.symbols.iter().map(|s| s.ram).sum() }
+
+    /// (flash, ram) taken by the ELF's allocated sections — what `avr-size`
+    /// calls text + data and data + bss, padding and unsized code included.
+    pub fn section_usage(&self) -> (u64, u64) {
+        self.sections.iter().fold((0, 0), |(f, r), (name, size)| {
+            let (in_flash, in_ram) = placement(name);
+            (f + if in_flash { *size } else { 0 }, r + if in_ram { *size } else { 0 })
+        })
+    }
+
+    /// (module, flash, ram), largest flash first.
+    pub fn by_module(&self) -> Vec<(String, u64, u64)> {
+        group(self.symbols.iter().map(|s| (s.module.as_str(), s.flash, s.ram)))
+    }
+
+    /// (source file, flash, ram), largest flash first; symbols without one
+    /// count as `?`.
+    pub fn by_file(&self) -> Vec<(String, u64, u64)> {
+        group(self.symbols.iter().map(|s| (if s.file.is_empty() { "?" } else { &s.file }, s.flash, s.ram)))
+    }
+}
+
+/// Sum (name, flash, ram) rows by name, largest flash first.
+fn group<'a>(rows: impl Iterator<Item = (&'a str, u64, u64)>) -> Vec<(String, u64, u64)> {
+    let mut out: Vec<(String, u64, u64)> = Vec::new();
+    for (name, flash, ram) in rows {
+        match out.iter_mut().find(|(m, ..)| m == name) {
+            Some(e) => { e.1 += flash; e.2 += ram; }
+            None    => out.push((name.to_owned(), flash, ram)),
+        }
+    }
+    out.sort_by(|a, b| b.1.cmp(&a.1).then(b.2.cmp(&a.2)).then_with(|| a.0.cmp(&b.0)));
+    out
+}
+
+/// Whether an output section is stored in flash, occupies RAM, or both
+/// (initialised data is copied from flash at startup). Goes by name, which
+/// holds across the toolchains' linker scripts (`.dram0.bss`, `.flash.rodata`).
+fn placement(section: &str) -> (bool, bool) {
+    let s = section.to_ascii_lowercase();
+    if ["bss", "noinit", "heap", "stack"].iter().any(|k| s.contains(k)) {
+        (false, true)
+    } else if s.contains("data") && !s.contains("rodata") {
+        (true, true)
+    } else {
+        (true, false)
+    }
+}
+
+fn module_of(name: &str, loc: &str) -> String {
+    let path = loc.rsplit_once(':').map_or(loc, |(p, _)| p).replace('\\', "/");
+    if let Some(rest) = path.split("/libraries/").nth(1) {
+        if let Some(lib) = rest.split('/').next() {
+            return lib.to_owned();
+        }
+    }
+    if path.contains("/cores/") {
+        return "core".into();
+    }
+    if let Some((pkg, _)) = name.split_once("__").filter(|(p, _)| !p.is_empty()) {
+        return pkg.to_owned();
+    }
+    if path.is_empty() { "other".into() } else { "sketch".into() }
+}
+
+/// The binutils `nm` matching the board's toolchain.
+pub fn nm_tool(board: &Board, bin_dir: &Path) -> String {
+    toolchain_tool(board, bin_dir, "nm")
+}
+
+/// `tool` (`gcc`, `objcopy`, …) from the board's toolchain.
+pub fn toolchain_tool(board: &Board, bin_dir: &Path, tool: &str) -> String {
+    let prefix = match &board.toolchain {
+        Toolchain::Avr { .. } | Toolchain::MegaAvr { .. } => "avr",
+        Toolchain::Esp32 { .. } => "xtensa-esp32-elf",
+        Toolchain::Esp8266      => "xtensa-lx106-elf",
+        Toolchain::Sam { .. } | Toolchain::Rp2040 | Toolchain::Stm32 { .. }
+        | Toolchain::Teensy { .. } => "arm-none-eabi",
+    };
+    in_dir(bin_dir, &format!("{}-{}", prefix, tool))
+}
+
+/// The `nm` for an ELF's machine type, when no board is given.
+pub fn nm_for_elf(elf: &Path) -> Result<String> {
+    Ok(match Elf::read(elf)?.machine {
+        ELF_AVR    => "avr-nm",
+        ELF_ARM    => "arm-none-eabi-nm",
+        ELF_XTENSA => "xtensa-esp32-elf-nm",
+        _          => "nm",
+    }.to_owned())
+}
+
+fn in_dir(bin_dir: &Path, name: &str) -> String {
+    let p = bin_dir.join(name);
+    if !bin_dir.as_os_str().is_empty() && p.exists() {
+        p.to_string_lossy().into_owned()
+    } else {
+        name.to_owned()
+    }
+}
+
+// ── Link map ──────────────────────────────────────────────────────────────────
+
+/// One input section placed by the linker.
+#[derive(Debug, Clone)]
+pub struct MapInput {
+    /// Output section it went into, e.g. `.text`.
+    pub output:  String,
+    /// Object file, or the member of `archive` it came from.
+    pub object:  String,
+    pub archive: Option<String>,
+    /// Full path of the object or archive.
+    pub path:    String,
+    pub size:    u64,
+}
+
+/// The memory map of a GNU ld `-Map` file.
+#[derive(Debug, Clone, Default)]
+pub struct LinkMap {
+    pub inputs: Vec<MapInput>,
+}
+
+impl LinkMap {
+    pub fn read(path: &Path) -> Result<Self> {
+        Ok(Self::parse(&std::fs::read_to_string(path)?))
+    }
+
+    /// Parse the `Linker script and memory map` part: output sections start
+    /// in column 0, input sections are indented by one space and list their
+    /// address, size and object — on the next line when the name is long.
+    pub fn parse(text: &str) -> Self {
+        let body = text.split_once("Linker script and memory map").map_or(text, |(_, b)| b);
+        let mut inputs = Vec::new();
+        let mut output = String::new();
+        let mut pending: Option<String> = None;
+        for line in body.lines() {
+            if line.starts_with('.') {
+                output = line.split_whitespace().next().unwrap_or_default().to_owned();
+                pending = None;
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (name, rest) = match pending.take() {
+                Some(name) if fields.first().is_some_and(|f| f.starts_with("0x")) => (name, &fields[..]),
+                _ if line.starts_with(" .") || line.starts_with(" COMMON") => match fields.split_first() {
+                    Some((name, [])) => { pending = Some(name.to_string()); continue }
+                    Some((name, rest)) => (name.to_string(), rest),
+                    None => continue,
+                },
+                _ => continue,
+            };
+            let [_, size, path @ ..] = rest else { continue };
+            let Ok(size) = u64::from_str_radix(size.trim_start_matches("0x"), 16) else { continue };
+            if size == 0 || path.is_empty() || name.starts_with(".debug") { continue }
+            let path = path.join(" ");
+            // `…/core.a(wiring.c.o)` names an archive member.
+            let (archive, object) = match path.strip_suffix(')').and_then(|p| p.split_once('(')) {
+                Some((archive, member)) => (Some(base_name(archive)), member.to_owned()),
+                None                    => (None, base_name(&path)),
+            };
+            inputs.push(MapInput { output: output.clone(), object, archive, path, size });
+        }
+        Self { inputs }
+    }
+
+    /// Link-time optimisation hands ld one merged object per partition, so
+    /// the map no longer says where code came from.
+    pub fn is_lto(&self) -> bool {
+        self.inputs.iter().any(|i| i.object.contains(".ltrans"))
+    }
+
+    /// Keep only what went into `sections` (the ELF's allocated sections).
+    pub fn retain_sections(&mut self, sections: &[(String, u64)]) {
+        self.inputs.retain(|i| sections.iter().any(|(s, _)| *s == i.output));
+    }
+
+    /// (object, flash, ram), largest flash first. Objects from an archive
+    /// are shown as `archive: member`.
+    pub fn by_object(&self) -> Vec<(String, u64, u64)> {
+        let names: Vec<String> = self.inputs.iter().map(|i| match &i.archive {
+            Some(a) => format!("{}: {}", a, strip_hash(&i.object)),
+            None    => strip_hash(&i.object).to_owned(),
+        }).collect();
+        group(self.inputs.iter().zip(&names).map(|(i, n)| {
+            let (f, r) = i.sizes();
+            (n.as_str(), f, r)
+        }))
+    }
+
+    /// (library, flash, ram), largest flash first: the archive an object
+    /// came from, the Arduino library its path is in, or `sketch` for the
+    /// objects compiled in `build_dir`.
+    pub fn by_library(&self, build_dir: &Path) -> Vec<(String, u64, u64)> {
+        let build = build_dir.to_string_lossy().replace('\\', "/");
+        let names: Vec<String> = self.inputs.iter().map(|i| {
+            let path = i.path.replace('\\', "/");
+            if let Some(lib) = path.split("/libraries/").nth(1).and_then(|r| r.split('/').next()) {
+                return lib.to_owned();
+            }
+            match &i.archive {
+                Some(a) => a.strip_suffix(".a").unwrap_or(a).to_owned(),
+                None if !build.is_empty() && path.starts_with(build.as_str()) => "sketch".into(),
+                None => "toolchain".into(),
+            }
+        }).collect();
+        group(self.inputs.iter().zip(&names).map(|(i, n)| {
+            let (f, r) = i.sizes();
+            (n.as_str(), f, r)
+        }))
+    }
+}
+
+impl MapInput {
+    fn sizes(&self) -> (u64, u64) {
+        let (in_flash, in_ram) = placement(&self.output);
+        (if in_flash { self.size } else { 0 }, if in_ram { self.size } else { 0 })
+    }
+}
+
+fn base_name(path: &str) -> String {
+    path.rsplit(['/', '\\']).next().unwrap_or(path).to_owned()
+}
+
+/// Drop the path hash `cache::obj_path` puts in front of object names.
+fn strip_hash(object: &str) -> &str {
+    match object.split_once('_') {
+        Some((hash, rest)) if hash.len() == 8 && hash.bytes().all(|b| b.is_ascii_hexdigit()) => rest,
+        _ => object,
+    }
+}
+
+// ── Budgets ───────────────────────────────────────────────────────────────────
+
+/// A size limit: a share of the board's capacity or an absolute size.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Limit {
+    Percent(f64),
+    Bytes(u64),
+}
+
+impl Limit {
+    pub fn bytes(&self, capacity: u64) -> u64 {
+        match self {
+            Self::Percent(p) => (capacity as f64 * p / 100.0) as u64,
+            Self::Bytes(b)   => *b,
+        }
+    }
+}
+
+impl FromStr for Limit {
+    type Err = String;
+
+    /// `90%`, `4KB`, `4K`, `1.5MB`, `512B` or `512`.
+    fn from_str(s: &str) -> std::result::Result<Self, String> {
+        let t = s.trim().to_ascii_uppercase();
+        let bad = || format!("invalid size `{}` (expected e.g. 90%, 4KB or 512)", s);
+        if let Some(p) = t.strip_suffix('%') {
+            let p: f64 = p.trim().parse().map_err(|_| bad())?;
+            return if (0.0..=100.0).contains(&p) { Ok(Self::Percent(p)) } else { Err(bad()) };
+        }
+        let t = t.strip_suffix('B').unwrap_or(&t);
+        let (num, mul) = match t.chars().last() {
+            Some('K') => (&t[..t.len() - 1], 1024.0),
+            Some('M') => (&t[..t.len() - 1], 1024.0 * 1024.0),
+            _         => (t, 1.0),
+        };
+        let n: f64 = num.trim().parse().map_err(|_| bad())?;
+        if n < 0.0 { return Err(bad()) }
+        Ok(Self::Bytes((n * mul) as u64))
+    }
+}
+
+impl std::fmt::Display for Limit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Percent(p) => write!(f, "{}%", p),
+            Self::Bytes(b)   => write!(f, "{} bytes", b),
+        }
+    }
+}
+
+/// Parse `name=size` for `--budget-module`.
+pub fn parse_module_limit(s: &str) -> std::result::Result<(String, Limit), String> {
+    let (name, limit) = s.split_once('=')
+        .ok_or_else(|| format!("expected NAME=SIZE, got `{}`", s))?;
+    Ok((name.trim().to_owned(), limit.parse()?))
+}
+
+/// Size budgets from the manifest's `budget` section. Per-module limits
+/// apply to flash and are matched case-insensitively against module names.
+#[derive(Debug, Clone, Default)]
+pub struct Budget {
+    pub flash:   Option<Limit>,
+    pub ram:     Option<Limit>,
+    pub modules: Vec<(String, Limit)>,
+}
+
+impl Budget {
+    pub fn is_empty(&self) -> bool {
+        self.flash.is_none() && self.ram.is_none() && self.modules.is_empty()
+    }
+
+    /// Fail with a per-module breakdown when any limit is exceeded.
+    pub fn check(&self, report: &SizeReport, board: &Board) -> Result<()> {
+        let flash_cap = board.flash_kb as u64 * 1024;
+        let ram_cap   = board.ram_kb as u64 * 1024;
+        let modules   = report.by_module();
+
+        let mut over = Vec::new();
+        let mut total = |what: &str, used: u64, cap: u64, limit: Option<Limit>| {
+            if let Some(l) = limit.filter(|l| used > l.bytes(cap)) {
+                over.push(format!("{:<6} {} bytes used, budget {} ({} bytes of {})",
+                    what, used, l, l.bytes(cap), cap));
+            }
+        };
+        total("flash", report.flash(), flash_cap, self.flash);
+        total("ram",   report.ram(),   ram_cap,   self.ram);
+        for (name, limit) in &self.modules {
+            let used = modules.iter()
+                .find(|(m, ..)| m.eq_ignore_ascii_case(name))
+                .map_or(0, |m| m.1);
+            if used > limit.bytes(flash_cap) {
+                over.push(format!("{} {} bytes of flash used, budget {}", name, used, limit));
+            }
+        }
+        if over.is_empty() { return Ok(()) }
+
+        let mut breakdown = over.join("\n");
+        breakdown += &format!("\n\n  {:<20} {:>8} {:>8}", "MODULE", "FLASH", "RAM");
+        for (m, f, r) in &modules {
+            breakdown += &format!("\n  {:<20} {:>8} {:>8}", m, f, r);
+        }
+        let mut largest: Vec<&SymbolSize> = report.symbols.iter().collect();
+        largest.sort_by_key(|s| std::cmp::Reverse(s.flash));
+        breakdown += "\n\n  largest symbols:";
+        for s in largest.iter().take(5) {
+            breakdown += &format!("\n  {:>8}  {} ({})", s.flash, s.name, s.module);
+        }
+        Err(FlashError::BudgetExceeded { breakdown })
+    }
+}
+
+// ── ELF sections ──────────────────────────────────────────────────────────────
+
+const ELF_ARM:    u16 = 40;
+const ELF_AVR:    u16 = 83;
+const ELF_XTENSA: u16 = 94;
+const SHF_ALLOC:  u64 = 0x2;
+
+/// The parts of an ELF header `size-diff` needs; enough of the format to
+/// avoid depending on each toolchain's `size`.
+struct Elf {
+    machine:  u16,
+    sections: Vec<(String, u64)>,
+}
+
+impl Elf {
+    fn read(path: &Path) -> Result<Self> {
+        let data = std::fs::read(path)?;
+        Self::parse(&data).ok_or_else(|| FlashError::Other(
+            format!("{} is not a readable ELF file", path.display())))
+    }
+
+    fn parse(b: &[u8]) -> Option<Self> {
+        if b.get(..4)? != b"\x7fELF" { return None }
+        let wide = *b.get(4)? == 2;
+        let le   = *b.get(5)? == 1;
+        let int = |off: usize, n: usize| -> Option<u64> {
+            let bytes = b.get(off..off.checked_add(n)?)?;
+            let fold = |v: u64, x: &u8| (v << 8) | *x as u64;
+            Some(if le { bytes.iter().rev().fold(0, fold) } else { bytes.iter().fold(0, fold) })
+        };
+        let word = if wide { 8 } else { 4 };
+        let (shoff, shentsize, shnum, shstrndx) = if wide {
+            (int(0x28, 8)?, int(0x3A, 2)?, int(0x3C, 2)?, int(0x3E, 2)?)
+        } else {
+            (int(0x20, 4)?, int(0x2E, 2)?, int(0x30, 2)?, int(0x32, 2)?)
+        };
+        // (name offset, flags, file offset, size) of section `i`.
+        let header = |i: u64| -> Option<(u64, u64, u64, u64)> {
+            let at = (shoff + i * shentsize) as usize;
+            let flags = int(at + 8, word)?;
+            let (offset, size) = (int(at + 8 + 2 * word, word)?, int(at + 8 + 3 * word, word)?);
+            Some((int(at, 4)?, flags, offset, size))
+        };
+        let (_, _, strtab, _) = header(shstrndx)?;
+        let mut sections = Vec::new();
+        for i in 0..shnum {
+            let (name, flags, _, size) = header(i)?;
+            if flags & SHF_ALLOC == 0 || size == 0 { continue }
+            let start = (strtab + name) as usize;
+            let len = b.get(start..)?.iter().position(|&c| c == 0)?;
+            sections.push((String::from_utf8_lossy(&b[start..start + len]).into_owned(), size));
+        }
+        Some(Self { machine: int(0x12, 2)? as u16, sections })
+    }
+}
+
+// ── Diff ──────────────────────────────────────────────────────────────────────
+
+/// A section or symbol whose size differs between two builds.
+#[derive(Debug, Clone, Default)]
+pub struct Delta {
+    pub name: String,
+    pub old:  u64,
+    pub new:  u64,
+}
+
+impl Delta {
+    pub fn growth(&self) -> i64 { self.new as i64 - self.old as i64 }
+}
+
+#[derive(Debug, Clone)]
+pub struct SizeDiff {
+    pub flash:    Delta,
+    pub ram:      Delta,
+    /// Changed sections, largest growth first.
+    pub sections: Vec<Delta>,
+    /// Changed symbols (`name (module)`, sized by flash or else RAM),
+    /// largest growth first.
+    pub symbols:  Vec<Delta>,
+}
+
+impl SizeDiff {
+    pub fn between(old: &SizeReport, new: &SizeReport) -> Self {
+        let sections = changed(&old.sections, &new.sections);
+        let keyed = |r: &SizeReport| r.symbols.iter()
+            .map(|s| (format!("{} ({})", s.name, s.module), s.flash.max(s.ram)))
+            .collect::<Vec<_>>();
+        let symbols = changed(&keyed(old), &keyed(new));
+        Self {
+            flash: Delta { name: "flash".into(), old: old.flash(), new: new.flash() },
+            ram:   Delta { name: "ram".into(),   old: old.ram(),   new: new.ram() },
+            sections,
+            symbols,
+        }
+    }
+}
+
+/// Entries whose total size differs, added and removed ones included.
+fn changed(old: &[(String, u64)], new: &[(String, u64)]) -> Vec<Delta> {
+    let mut by_name: HashMap<&str, (u64, u64)> = HashMap::new();
+    for (n, s) in old { by_name.entry(n).or_default().0 += s; }
+    for (n, s) in new { by_name.entry(n).or_default().1 += s; }
+    let mut out: Vec<Delta> = by_name.into_iter()
+        .filter(|(_, (o, n))| o != n)
+        .map(|(name, (old, new))| Delta { name: name.to_owned(), old, new })
+        .collect();
+    out.sort_by(|a, b| b.growth().cmp(&a.growth()).then_with(|| a.name.cmp(&b.name)));
+    out
+}
+
+// ── History ───────────────────────────────────────────────────────────────────
+
+/// Builds kept in `<build_dir>/<name>.size-history.jsonl`.
+const HISTORY_KEEP: usize = 50;
+
+/// One line of the size history, appended after every successful link.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// Seconds since the Unix epoch.
+    pub time:  u64,
+    pub flash: u64,
+    pub ram:   u64,
+}
+
+impl HistoryEntry {
+    fn path(build_dir: &Path, name: &str) -> PathBuf {
+        build_dir.join(format!("{}.size-history.jsonl", name))
+    }
+
+    /// Append `report` to the history and return the previous build's entry.
+    /// The history is best-effort: I/O errors only lose the comparison.
+    pub fn record(build_dir: &Path, name: &str, report: &SizeReport) -> Option<Self> {
+        let path = Self::path(build_dir, name);
+        let mut entries: Vec<Self> = std::fs::read_to_string(&path).unwrap_or_default()
+            .lines()
+            .filter_map(|l| serde_json::from_str(l).ok())
+            .collect();
+        let prev = entries.last().cloned();
+        entries.push(Self {
+            time:  SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()),
+            flash: report.flash(),
+            ram:   report.ram(),
+        });
+        let keep = &entries[entries.len().saturating_sub(HISTORY_KEEP)..];
+        let lines: Vec<String> = keep.iter().filter_map(|e| serde_json::to_string(e).ok()).collect();
+        let _ = std::fs::write(&path, lines.join("\n") + "\n");
+        prev
+    }
+
+    /// `compared to last build: flash +266 B, ram -4 B`.
+    pub fn compare(&self, report: &SizeReport) -> String {
+        format!("compared to last build: flash {}, ram {}",
+            signed(report.flash() as i64 - self.flash as i64),
+            signed(report.ram() as i64 - self.ram as i64))
+    }
+}
+
+/// `+266 B`, `-4 B`, `±0 B`.
+pub fn signed(bytes: i64) -> String {
+    match bytes {
+        0 => "±0 B".into(),
+        b => format!("{:+} B", b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NM: &str = "\
+00000100 00000040 T loop\t/home/u/sketch/build/sketch.cpp:12
+00000140 00000010 t sensors__Read
+00000150 00000080 T digitalWrite\t/sdk/avr/cores/arduino/wiring_digital.c:138
+000001d0 00000020 W Wire::begin()\t/home/u/Arduino/libraries/Wire/src/Wire.cpp:40
+00800100 00000004 D counter\t/home/u/sketch/build/sketch.cpp:3
+00800104 00000100 B buf
+00000200 T _etext
+00800204 00000002 a absolute
+";
+
+    #[test]
+    fn test_parse_nm() {
+        let r = SizeReport::parse(NM);
+        let names: Vec<&str> = r.symbols.iter().map(|s| s.name.as_str()).collect();
+        // Unsized symbols and kinds that take no space are skipped.
+        assert_eq!(names, ["loop", "sensors__Read", "digitalWrite", "Wire::begin()", "counter", "buf"]);
+        let modules: Vec<&str> = r.symbols.iter().map(|s| s.module.as_str()).collect();
+        assert_eq!(modules, ["sketch", "sensors", "core", "Wire", "sketch", "other"]);
+        assert_eq!(r.symbols[3].file, "Wire.cpp");
+        assert_eq!(r.symbols[1].file, "");
+        // Initialised data is stored in flash and copied into RAM.
+        assert_eq!((r.flash(), r.ram()), (0x40 + 0x10 + 0x80 + 0x20 + 4, 4 + 0x100));
+        assert_eq!(r.by_module()[0], ("core".to_owned(), 0x80, 0));
+        assert_eq!(r.by_file().last().unwrap(), &("?".to_owned(), 0x10, 0x100));
+    }
+
+    #[test]
+    fn test_limits() {
+        assert_eq!("90%".parse::<Limit>(), Ok(Limit::Percent(90.0)));
+        assert_eq!("4KB".parse::<Limit>(), Ok(Limit::Bytes(4096)));
+        assert_eq!("4k".parse::<Limit>(), Ok(Limit::Bytes(4096)));
+        assert_eq!("1.5MB".parse::<Limit>(), Ok(Limit::Bytes(1_572_864)));
+        assert_eq!(" 512B ".parse::<Limit>(), Ok(Limit::Bytes(512)));
+        assert_eq!("512".parse::<Limit>(), Ok(Limit::Bytes(512)));
+        for bad in ["150%", "-1", "KB", "lots", ""] {
+            assert!(bad.parse::<Limit>().is_err(), "{}", bad);
+        }
+        assert_eq!(Limit::Percent(50.0).bytes(32 * 1024), 16 * 1024);
+        assert_eq!(parse_module_limit("Wire = 2K"), Ok(("Wire".to_owned(), Limit::Bytes(2048))));
+        assert!(parse_module_limit("Wire").is_err());
+    }
+
+    #[test]
+    fn test_budget() {
+        let board = Board::find("uno").unwrap();
+        let report = SizeReport::parse(NM);
+        let ok = Budget { flash: Some(Limit::Percent(90.0)), ram: Some(Limit::Bytes(512)), modules: vec![] };
+        assert!(ok.check(&report, board).is_ok());
+
+        let tight = Budget {
+            flash:   None,
+            ram:     Some(Limit::Bytes(100)),
+            modules: vec![("wire".into(), Limit::Bytes(16)), ("Servo".into(), Limit::Bytes(1))],
+        };
+        let Err(FlashError::BudgetExceeded { breakdown }) = tight.check(&report, board) else { panic!() };
+        assert!(breakdown.starts_with("ram    260 bytes used, budget 100 bytes (100 bytes of 2048)\n\
+                                       wire 32 bytes of flash used, budget 16 bytes\n"), "{}", breakdown);
+        // A module that is absent uses nothing.
+        assert!(!breakdown.contains("Servo"));
+        assert!(breakdown.contains("largest symbols:\n       128  digitalWrite (core)"));
+    }
+
+    #[test]
+    fn test_link_map() {
+        let map = LinkMap::parse("\
+Archive member included to satisfy reference by file (symbol)
+/sdk/core.a(wiring.c.o)  sketch.cpp.o (millis)
+
+Linker script and memory map
+
+.text           0x00000000      0x1f0
+ .text          0x00000000       0x60 /tmp/b/sketch/1a2b3c4d_sketch.cpp.o
+ .text.a_function_with_a_very_long_name
+                0x00000060       0x40 /tmp/b/core/core.a(wiring.c.o)
+ .text          0x000000a0       0x90 /home/u/Arduino/libraries/Servo/src/Servo.cpp.o
+ .text          0x00000130        0x0 /tmp/b/sketch/empty.o
+.bss            0x00800100       0x20
+ COMMON         0x00800100       0x20 /tmp/b/sketch/1a2b3c4d_sketch.cpp.o
+.debug_info     0x00000000      0x400
+ .debug_info    0x00000000      0x400 /tmp/b/sketch/1a2b3c4d_sketch.cpp.o
+");
+        let objects: Vec<(&str, Option<&str>, u64)> = map.inputs.iter()
+            .map(|i| (i.object.as_str(), i.archive.as_deref(), i.size))
+            .collect();
+        assert_eq!(objects, [
+            ("1a2b3c4d_sketch.cpp.o", None, 0x60),
+            ("wiring.c.o", Some("core.a"), 0x40),
+            ("Servo.cpp.o", None, 0x90),
+            ("1a2b3c4d_sketch.cpp.o", None, 0x20),
+        ]);
+        assert_eq!(map.inputs[3].output, ".bss");
+        assert!(!map.is_lto());
+        assert_eq!(map.by_object()[0], ("Servo.cpp.o".to_owned(), 0x90, 0));
+        assert!(map.by_object().contains(&("core.a: wiring.c.o".to_owned(), 0x40, 0)));
+        assert_eq!(map.by_library(Path::new("/tmp/b/sketch")), [
+            ("Servo".to_owned(), 0x90, 0),
+            ("sketch".to_owned(), 0x60, 0x20),
+            ("core".to_owned(), 0x40, 0),
+        ]);
+    }
+
+    /// A little-endian ELF32 with the given (name, flags, size) sections
+    /// after the null one, and a section name table last.
+    fn elf32(machine: u16, sections: &[(&str, u32, u32)]) -> Vec<u8> {
+        let mut names = vec![0u8];
+        let mut offsets = Vec::new();
+        for (name, ..) in sections.iter().chain([&(".shstrtab", 0, 0)]) {
+            offsets.push(names.len() as u32);
+            names.extend(name.as_bytes());
+            names.push(0);
+        }
+        let shoff = 52 + names.len() as u32;
+        let count = sections.len() as u16 + 2;
+        let mut b = b"\x7fELF\x01\x01\x01".to_vec();
+        b.resize(16, 0);
+        b.extend(2u16.to_le_bytes());
+        b.extend(machine.to_le_bytes());
+        b.resize(0x20, 0);
+        b.extend(shoff.to_le_bytes());
+        b.resize(0x2E, 0);
+        b.extend(40u16.to_le_bytes());
+        b.extend(count.to_le_bytes());
+        b.extend((count - 1).to_le_bytes());
+        b.extend(&names);
+        let header = |b: &mut Vec<u8>, name: u32, flags: u32, offset: u32, size: u32| {
+            for v in [name, 1, flags, 0, offset, size, 0, 0, 1, 0] { b.extend(v.to_le_bytes()); }
+        };
+        b.extend([0u8; 40]);
+        for ((_, flags, size), name) in sections.iter().zip(&offsets) {
+            header(&mut b, *name, *flags, 0, *size);
+        }
+        header(&mut b, *offsets.last().unwrap(), 0, 52, names.len() as u32);
+        b
+    }
+
+    #[test]
+    fn test_elf_sections() {
+        let elf = Elf::parse(&elf32(ELF_AVR, &[
+            (".text", 0x6, 0x1f0), (".data", 0x3, 0x10), (".bss", 0x3, 0x20),
+            (".noinit", 0x3, 0), (".debug_info", 0, 0x400),
+        ])).unwrap();
+        assert_eq!(elf.machine, ELF_AVR);
+        let names: Vec<&str> = elf.sections.iter().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(names, [".text", ".data", ".bss"]);
+        let report = SizeReport { symbols: vec![], sections: elf.sections };
+        assert_eq!(report.section_usage(), (0x1f0 + 0x10, 0x10 + 0x20));
+
+        assert!(Elf::parse(b"\x7fELF").is_none());
+        assert!(Elf::parse(b"MZ\x90\x00").is_none());
+        let mut truncated = elf32(ELF_ARM, &[(".text", 0x6, 4)]);
+        truncated.truncate(truncated.len() - 20);
+        assert!(Elf::parse(&truncated).is_none());
+    }
+
+    #[test]
+    fn test_diff_and_history() {
+        let old = SizeReport::parse(NM);
+        let new = SizeReport::parse(&NM.replace("00000040 T loop", "00000060 T loop")
+            .replace("00000140 00000010 t sensors__Read\n", ""));
+        let diff = SizeDiff::between(&old, &new);
+        assert_eq!(diff.flash.growth(), 0x20 - 0x10);
+        let symbols: Vec<(&str, i64)> = diff.symbols.iter().map(|d| (d.name.as_str(), d.growth())).collect();
+        assert_eq!(symbols, [("loop (sketch)", 0x20), ("sensors__Read (sensors)", -0x10)]);
+
+        let dir = std::env::temp_dir().join(format!("tsuki-size-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        assert!(HistoryEntry::record(&dir, "fw", &old).is_none());
+        let prev = HistoryEntry::record(&dir, "fw", &new).unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+        assert_eq!(prev.compare(&new), "compared to last build: flash +16 B, ram ±0 B");
+        assert_eq!(signed(-4), "-4 B");
+    }
+}
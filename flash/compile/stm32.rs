@@ -0,0 +1,254 @@
+// ─────────────────────────────────────────────────────────────────────────────
+//  tsuki-flash :: compile :: stm32
+//
+//  Compiles sketches for STM32 boards with arm-none-eabi-gcc against the
+//  STM32duino core (`STMicroelectronics:stm32`).
+//
+//  Pipeline:
+//    1. Compile the core (cores/arduino, recursively) → core.a  (cached)
+//    2. Compile SrcWrapper (HAL/LL drivers, system init) → srcwrapper.a  (cached)
+//    3. Compile sketch + variant sources  (parallel, incremental cache)
+//       and the libraries it includes → libs/<name>/<name>.a
+//    4. Link with the variant's linker script → firmware.elf
+//    5. firmware.elf → firmware.bin
+// ─────────────────────────────────────────────────────────────────────────────
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use rayon::prelude::*;
+
+use crate::boards::{Board, Toolchain};
+use crate::error::{FlashError, Result};
+use crate::events::{self, Event};
+use crate::process::{self, Stage};
+use crate::sdk::{self, SdkPaths};
+use super::avr::{build_core, collect_sketch_sources, resolve_tool, run_tool};
+use super::cache::{CacheManifest, hash_str, obj_path};
+use super::libraries;
+use super::objcache::ObjCache;
+use super::{CompileRequest, CompileResult, Failures};
+
+/// Per-board settings from the core's boards.txt that aren't in `Board`.
+struct Stm32Board {
+    /// `pnum` of the board, also its BOARD_NAME.
+    name:      &'static str,
+    variant_h: &'static str,
+    /// Float ABI flags; empty for cores without an FPU.
+    fpu:       &'static [&'static str],
+}
+
+fn board_settings(board: &Board) -> Stm32Board {
+    const HARD_FPU: &[&str] = &["-mfpu=fpv4-sp-d16", "-mfloat-abi=hard"];
+    match board.id {
+        "blackpill_f411" => Stm32Board { name: "BLACKPILL_F411CE", variant_h: "variant_BLACKPILL_F411CE.h", fpu: HARD_FPU },
+        "nucleo_f401re"  => Stm32Board { name: "NUCLEO_F401RE", variant_h: "variant_NUCLEO_F401RE.h", fpu: HARD_FPU },
+        _                => Stm32Board { name: "BLUEPILL_F103C8", variant_h: "variant_PILL_F103Cx.h", fpu: &[] },
+    }
+}
+
+pub fn run(req: &CompileRequest, board: &Board, sdk: &SdkPaths) -> Result<CompileResult> {
+    let f_cpu = board.clock_hz(req.cpu_mhz)?;
+    let Toolchain::Stm32 { mcu, series, .. } = &board.toolchain else {
+        return Err(FlashError::Other("Not an STM32 board".into()));
+    };
+    std::fs::create_dir_all(&req.build_dir)?;
+    let platform = sdk.platform_dir();
+    let system   = platform.join("system");
+    let settings = board_settings(board);
+
+    let cc      = resolve_tool(&sdk.toolchain_bin, "arm-none-eabi-gcc");
+    let cxx     = resolve_tool(&sdk.toolchain_bin, "arm-none-eabi-g++");
+    let ar      = resolve_tool(&sdk.toolchain_bin, "arm-none-eabi-ar");
+    let objcopy = resolve_tool(&sdk.toolchain_bin, "arm-none-eabi-objcopy");
+
+    // CMSIS ships as a tool of its own next to the toolchain.
+    let tools = platform.ancestors().nth(3).map(|p| p.join("tools")).unwrap_or_default();
+    let cmsis = sdk::latest_version_dir(&tools.join("CMSIS"))
+        .map(|v| tools.join("CMSIS").join(v).join("CMSIS"))
+        .ok_or_else(|| FlashError::Other(format!(
+            "CMSIS not found in {} — reinstall the stm32 core", tools.display())))?;
+
+    let src_wrapper = platform.join("libraries").join("SrcWrapper");
+    let core = &sdk.core_dir;
+    let hal  = system.join("Drivers").join(format!("{}_HAL_Driver", series));
+
+    // ── Shared compiler flags ─────────────────────────────────────────────
+    let mut common_flags: Vec<String> = vec![
+        format!("-mcpu={}", mcu), "-mthumb".into(),
+    ];
+    common_flags.extend(settings.fpu.iter().map(|f| f.to_string()));
+    common_flags.extend([
+        "-Os".into(), "-w".into(),
+        "-ffunction-sections".into(), "-fdata-sections".into(),
+        "--param".into(), "max-inline-insns-single=500".into(),
+        "-MMD".into(),
+        format!("-DF_CPU={}L", f_cpu),
+        "-DARDUINO=10819".into(),
+        "-DVECT_TAB_OFFSET=0x0".into(),
+        "-DUSE_HAL_DRIVER".into(), "-DUSE_FULL_LL_DRIVER".into(),
+        format!("-D{}", series),
+        format!("-DBOARD_NAME=\"{}\"", settings.name),
+        format!("-DVARIANT_H=\"{}\"", settings.variant_h),
+        format!("-I{}", core.display()),
+        format!("-I{}", core.join("avr").display()),
+        format!("-I{}", core.join("stm32").display()),
+        format!("-I{}", core.join("stm32").join("usb").display()),
+        format!("-I{}", src_wrapper.join("inc").display()),
+        format!("-I{}", src_wrapper.join("inc").join("LL").display()),
+        format!("-I{}", hal.join("Inc").display()),
+        format!("-I{}", hal.join("Src").display()),
+        format!("-I{}", system.join(series).display()),
+        format!("-I{}", cmsis.join("Core").join("Include").display()),
+        format!("-I{}", system.join("Drivers").join("CMSIS").join("Device").join("ST").join(series).join("Include").display()),
+        format!("-I{}", sdk.variant_dir.display()),
+    ]);
+    for d in &board.defines {
+        common_flags.push(format!("-D{}", d));
+    }
+
+    let mut includes = common_flags.clone();
+    for lib_dir in &req.lib_include_dirs {
+        includes.push(format!("-I{}", lib_dir.display()));
+    }
+    if let Some(ld) = &sdk.libraries_dir {
+        includes.push(format!("-I{}", ld.display()));
+    }
+    includes.extend(req.extra_flags.define_flags());
+
+    // The core needs C++17; a newer standard asked for is kept.
+    let std_num: u32 = req.cpp_std.trim_start_matches("c++").trim_start_matches("gnu++").parse().unwrap_or(0);
+    let cxx_std_flag = format!("-std=gnu++{}", if (17..98).contains(&std_num) { std_num } else { 17 });
+    let mut cflags: Vec<&str> = vec!["-x", "c", "-std=gnu11"];
+    let mut cxxflags: Vec<&str> = vec![
+        "-x", "c++",
+        &cxx_std_flag,
+        "-fno-exceptions", "-fno-rtti",
+        "-fno-threadsafe-statics", "-fno-use-cxa-atexit",
+    ];
+    cflags.extend(req.extra_flags.cflags.iter().map(String::as_str));
+    cxxflags.extend(req.extra_flags.cxxflags.iter().map(String::as_str));
+
+    let flags_sig = hash_str(&format!("{:?}{:?}{:?}", includes, cflags, cxxflags));
+    let core_sig  = hash_str(&format!("core{}{}{}{}", board.id, f_cpu, sdk.sdk_version, flags_sig));
+
+    // ── Step 1: core.a ────────────────────────────────────────────────────
+    let core_obj_dir = req.build_dir.join("core");
+    std::fs::create_dir_all(&core_obj_dir)?;
+    let core_a = req.build_dir.join("core.a");
+    let obj_cache = ObjCache::open(req.obj_cache);
+    build_core(&cc, &cxx, &ar, core, usize::MAX, &core_obj_dir, &core_a,
+               &includes, &cflags, &cxxflags, &core_sig, obj_cache.as_ref(), req.verbose)?;
+
+    // ── Step 2: srcwrapper.a ──────────────────────────────────────────────
+    let wrapper_obj_dir = req.build_dir.join("srcwrapper");
+    std::fs::create_dir_all(&wrapper_obj_dir)?;
+    let wrapper_a = req.build_dir.join("srcwrapper.a");
+    build_core(&cc, &cxx, &ar, &src_wrapper.join("src"), usize::MAX, &wrapper_obj_dir, &wrapper_a,
+               &includes, &cflags, &cxxflags, &core_sig, obj_cache.as_ref(), req.verbose)?;
+
+    // ── Step 3: Sketch sources ────────────────────────────────────────────
+    let sketch_dir = req.build_dir.join("sketch");
+    std::fs::create_dir_all(&sketch_dir)?;
+    let mut sources = collect_sketch_sources(&req.sketch_dir)?;
+    if sources.is_empty() {
+        return Err(FlashError::Other(format!(
+            "No .cpp/.c/.ino sources found in {}", req.sketch_dir.display()
+        )));
+    }
+    // Pin maps and the clock setup live in the variant.
+    sources.extend(collect_sketch_sources(&sdk.variant_dir)?);
+
+    let failures = Failures::default();
+    let mut manifest = CacheManifest::load(&sketch_dir);
+
+    let obj_files: Vec<PathBuf> = sources.par_iter().map(|src| {
+        let obj = obj_path(&sketch_dir, src);
+        if manifest.is_fresh(src, &obj, &flags_sig) {
+            if req.verbose { eprintln!("  [cache] {}", src.display()); }
+            events::emit(Event::Fresh { unit: "sketch", file: src, cache: "build" });
+            return obj;
+        }
+
+        let is_c = src.extension().and_then(|e| e.to_str()) == Some("c");
+        let mut cmd = Command::new(if is_c { &cc } else { &cxx });
+        cmd.args(&includes);
+        cmd.args(if is_c { &cflags } else { &cxxflags });
+        cmd.arg("-c").arg(src).arg("-o").arg(&obj);
+
+        if req.verbose { eprintln!("  [compile] {}", src.display()); }
+        events::emit(Event::Compiling { unit: "sketch", file: src });
+
+        failures.compile(&mut cmd, &obj, || format!("In {}:\n", src.display()));
+        obj
+    }).collect();
+
+    for src in &sources {
+        let obj = obj_path(&sketch_dir, src);
+        if obj.exists() { manifest.record(src, &obj, &flags_sig); }
+    }
+    let _ = manifest.save(&sketch_dir);
+
+    failures.check("\n\n")?;
+
+    // ── Libraries ─────────────────────────────────────────────────────────
+    let lib_archives = libraries::build(&req.libraries, &cc, &cxx, &ar, &req.build_dir.join("libs"),
+                                        &includes, &cflags, &cxxflags, &flags_sig,
+                                        obj_cache.as_ref(), req.verbose)?;
+
+    // ── Step 4: Link ──────────────────────────────────────────────────────
+    // The variant's script is the default; the system one only sizes it.
+    let elf_path = req.build_dir.join(format!("{}.elf", req.project_name));
+    let map_path = req.build_dir.join(format!("{}.map", req.project_name));
+    let mut link_cmd = Command::new(&cxx);
+    link_cmd
+        .args([&format!("-mcpu={}", mcu) as &str, "-mthumb", "-Os"])
+        .args(settings.fpu)
+        .arg("-Wl,--defsym=LD_FLASH_OFFSET=0x0")
+        .arg(format!("-Wl,--defsym=LD_MAX_SIZE={}", board.flash_kb * 1024))
+        .arg(format!("-Wl,--defsym=LD_MAX_DATA_SIZE={}", board.ram_kb * 1024))
+        .args(["-Wl,--gc-sections", "-Wl,--check-sections", "-Wl,--entry=Reset_Handler"])
+        .args(["-Wl,--unresolved-symbols=report-all", "-Wl,--warn-common"])
+        .arg(format!("-Wl,--default-script={}", sdk.variant_dir.join("ldscript.ld").display()))
+        .arg(format!("-Wl,--script={}", system.join("ldscript.ld").display()))
+        .arg(format!("-Wl,-Map,{}", map_path.display()))
+        .arg("--specs=nano.specs");
+    link_cmd.arg("-Wl,--start-group");
+    for obj in &obj_files {
+        link_cmd.arg(obj);
+    }
+    link_cmd.args(libraries::link_group(&lib_archives));
+    link_cmd.arg(&wrapper_a).arg(&core_a);
+    link_cmd.args(&req.extra_flags.ldflags);
+    link_cmd.args(["-lc", "-Wl,--end-group", "-lm", "-lgcc", "-lstdc++"]);
+    link_cmd.arg("-o").arg(&elf_path);
+
+    events::emit(Event::Linking { output: &elf_path });
+    let link_out = process::output(&mut link_cmd, Stage::Link)?;
+    if !link_out.status.success() {
+        return Err(FlashError::LinkFailed {
+            output: String::from_utf8_lossy(&link_out.stderr).to_string(),
+        });
+    }
+
+    // ── Step 5: .bin ──────────────────────────────────────────────────────
+    let bin_path = req.build_dir.join(format!("{}.bin", req.project_name));
+    run_tool(&objcopy, &["-O", "binary", elf_path.to_str().unwrap(), bin_path.to_str().unwrap()])?;
+
+    let size_info = firmware_size(&sdk.toolchain_bin, &elf_path);
+    Ok(CompileResult {
+        hex_path: None,
+        bin_path: Some(bin_path),
+        elf_path: Some(elf_path),
+        uf2_path: None,
+        size_info,
+        usage:    None,
+    })
+}
+
+fn firmware_size(bin_dir: &Path, elf: &Path) -> String {
+    let size = resolve_tool(bin_dir, "arm-none-eabi-size");
+    match process::output(Command::new(&size).arg(elf), Stage::Link) {
+        Ok(o) if o.status.success() => String::from_utf8_lossy(&o.stdout).trim().to_string(),
+        _ => "(size unknown)".into(),
+    }
+}
@@ -0,0 +1,223 @@
+// ─────────────────────────────────────────────────────────────────────────────
+//  tsuki-flash :: compile :: teensy
+//
+//  Compiles sketches for PJRC Teensy boards with the arm-none-eabi-gcc of
+//  Teensyduino (`teensy:avr`), whose cores are per chip family: teensy4
+//  (iMXRT1062) and teensy3 (Kinetis, Teensy LC).
+//
+//  Pipeline:
+//    1. Compile the core (cores/teensy4 or teensy3) → core.a  (cached)
+//    2. Compile sketch sources  (parallel, incremental cache)
+//       and the libraries it includes → libs/<name>/<name>.a
+//    3. Link with the core's linker script → firmware.elf
+//    4. firmware.elf → firmware.hex  (what teensy_loader_cli takes)
+// ─────────────────────────────────────────────────────────────────────────────
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+use rayon::prelude::*;
+
+use crate::boards::{Board, Toolchain};
+use crate::error::{FlashError, Result};
+use crate::events::{self, Event};
+use crate::process::{self, Stage};
+use crate::sdk::SdkPaths;
+use super::avr::{build_core, collect_sketch_sources, resolve_tool, run_tool};
+use super::cache::{CacheManifest, hash_str, obj_path};
+use super::libraries;
+use super::objcache::ObjCache;
+use super::{CompileRequest, CompileResult, Failures};
+
+/// Teensyduino release the core headers check against.
+const TEENSYDUINO: u32 = 159;
+
+/// What boards.txt sets per chip.
+struct Chip {
+    cpu_flags: &'static [&'static str],
+    define:    &'static str,
+    /// CMSIS DSP library matching the FPU.
+    math_lib:  &'static str,
+}
+
+fn chip(mcu: &str) -> Result<Chip> {
+    match mcu {
+        "imxrt1062" => Ok(Chip {
+            cpu_flags: &["-mthumb", "-mcpu=cortex-m7", "-mfloat-abi=hard", "-mfpu=fpv5-d16"],
+            define:    "__IMXRT1062__",
+            math_lib:  "arm_cortexM7lfsp_math",
+        }),
+        "mkl26z64" => Ok(Chip {
+            cpu_flags: &["-mthumb", "-mcpu=cortex-m0plus", "-fsingle-precision-constant"],
+            define:    "__MKL26Z64__",
+            math_lib:  "arm_cortexM0l_math",
+        }),
+        _ => Err(FlashError::Other(format!("tsuki-flash has no compiler settings for the {} Teensy", mcu))),
+    }
+}
+
+pub fn run(req: &CompileRequest, board: &Board, sdk: &SdkPaths) -> Result<CompileResult> {
+    let Toolchain::Teensy { mcu, model, ldscript } = &board.toolchain else {
+        return Err(FlashError::Other("Not a Teensy board".into()));
+    };
+    let chip = chip(mcu)?;
+    let f_cpu = board.clock_hz(req.cpu_mhz)?;
+    std::fs::create_dir_all(&req.build_dir)?;
+
+    let cc      = resolve_tool(&sdk.toolchain_bin, "arm-none-eabi-gcc");
+    let cxx     = resolve_tool(&sdk.toolchain_bin, "arm-none-eabi-g++");
+    let ar      = resolve_tool(&sdk.toolchain_bin, "arm-none-eabi-ar");
+    let objcopy = resolve_tool(&sdk.toolchain_bin, "arm-none-eabi-objcopy");
+
+    // ── Shared compiler flags ─────────────────────────────────────────────
+    let mut common_flags: Vec<String> = chip.cpu_flags.iter().map(|f| f.to_string()).collect();
+    common_flags.extend([
+        "-O2".into(), "-w".into(),
+        "-ffunction-sections".into(), "-fdata-sections".into(), "-nostdlib".into(),
+        "-MMD".into(),
+        format!("-D{}", chip.define),
+        format!("-DTEENSYDUINO={}", TEENSYDUINO),
+        "-DARDUINO=10819".into(),
+        format!("-DARDUINO_{}", model),
+        format!("-DF_CPU={}", f_cpu),
+        // The USB type and keyboard layout menus' defaults.
+        "-DUSB_SERIAL".into(),
+        "-DLAYOUT_US_ENGLISH".into(),
+        format!("-I{}", sdk.core_dir.display()),
+    ]);
+    for d in &board.defines {
+        common_flags.push(format!("-D{}", d));
+    }
+
+    let mut includes = common_flags.clone();
+    for lib_dir in &req.lib_include_dirs {
+        includes.push(format!("-I{}", lib_dir.display()));
+    }
+    if let Some(ld) = &sdk.libraries_dir {
+        includes.push(format!("-I{}", ld.display()));
+    }
+    includes.extend(req.extra_flags.define_flags());
+
+    // The cores are written for C++17; a newer standard asked for is kept.
+    let std_num: u32 = req.cpp_std.trim_start_matches("c++").trim_start_matches("gnu++").parse().unwrap_or(0);
+    let cxx_std_flag = format!("-std=gnu++{}", if (17..98).contains(&std_num) { std_num } else { 17 });
+    let mut cflags: Vec<&str> = vec!["-x", "c"];
+    let mut cxxflags: Vec<&str> = vec![
+        "-x", "c++",
+        &cxx_std_flag,
+        "-fno-exceptions", "-fpermissive", "-fno-rtti",
+        "-fno-threadsafe-statics", "-felide-constructors",
+        "-Wno-error=narrowing",
+    ];
+    cflags.extend(req.extra_flags.cflags.iter().map(String::as_str));
+    cxxflags.extend(req.extra_flags.cxxflags.iter().map(String::as_str));
+
+    let flags_sig = hash_str(&format!("{:?}{:?}{:?}", includes, cflags, cxxflags));
+    let core_sig  = hash_str(&format!("core{}{}{}{}", board.id, f_cpu, sdk.sdk_version, flags_sig));
+
+    // ── Step 1: core.a ────────────────────────────────────────────────────
+    let core_obj_dir = req.build_dir.join("core");
+    std::fs::create_dir_all(&core_obj_dir)?;
+    let core_a = req.build_dir.join("core.a");
+    let obj_cache = ObjCache::open(req.obj_cache);
+    build_core(&cc, &cxx, &ar, &sdk.core_dir, 1, &core_obj_dir, &core_a,
+               &includes, &cflags, &cxxflags, &core_sig, obj_cache.as_ref(), req.verbose)?;
+
+    // ── Step 2: Sketch sources ────────────────────────────────────────────
+    let sketch_dir = req.build_dir.join("sketch");
+    std::fs::create_dir_all(&sketch_dir)?;
+    let sources = collect_sketch_sources(&req.sketch_dir)?;
+    if sources.is_empty() {
+        return Err(FlashError::Other(format!(
+            "No .cpp/.c/.ino sources found in {}", req.sketch_dir.display()
+        )));
+    }
+
+    let failures = Failures::default();
+    let mut manifest = CacheManifest::load(&sketch_dir);
+
+    let obj_files: Vec<PathBuf> = sources.par_iter().map(|src| {
+        let obj = obj_path(&sketch_dir, src);
+        if manifest.is_fresh(src, &obj, &flags_sig) {
+            if req.verbose { eprintln!("  [cache] {}", src.display()); }
+            events::emit(Event::Fresh { unit: "sketch", file: src, cache: "build" });
+            return obj;
+        }
+
+        let is_c = src.extension().and_then(|e| e.to_str()) == Some("c");
+        let mut cmd = Command::new(if is_c { &cc } else { &cxx });
+        cmd.args(&includes);
+        cmd.args(if is_c { &cflags } else { &cxxflags });
+        cmd.arg("-c").arg(src).arg("-o").arg(&obj);
+
+        if req.verbose { eprintln!("  [compile] {}", src.display()); }
+        events::emit(Event::Compiling { unit: "sketch", file: src });
+
+        failures.compile(&mut cmd, &obj, || format!("In {}:\n", src.display()));
+        obj
+    }).collect();
+
+    for src in &sources {
+        let obj = obj_path(&sketch_dir, src);
+        if obj.exists() { manifest.record(src, &obj, &flags_sig); }
+    }
+    let _ = manifest.save(&sketch_dir);
+
+    failures.check("\n\n")?;
+
+    // ── Libraries ─────────────────────────────────────────────────────────
+    let lib_archives = libraries::build(&req.libraries, &cc, &cxx, &ar, &req.build_dir.join("libs"),
+                                        &includes, &cflags, &cxxflags, &flags_sig,
+                                        obj_cache.as_ref(), req.verbose)?;
+
+    // ── Step 3: Link ──────────────────────────────────────────────────────
+    // The RTC starts from the build time on a board that has lost its clock.
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let elf_path = req.build_dir.join(format!("{}.elf", req.project_name));
+    let map_path = req.build_dir.join(format!("{}.map", req.project_name));
+    let mut link_cmd = Command::new(&cc);
+    link_cmd
+        .args(["-O2", "-Wl,--gc-sections,--relax"])
+        .arg(format!("-Wl,--defsym=__rtc_localtime={}", now))
+        .arg(format!("-T{}", sdk.core_dir.join(ldscript).display()))
+        .args(chip.cpu_flags)
+        .arg(format!("-Wl,-Map,{}", map_path.display()));
+    for obj in &obj_files {
+        link_cmd.arg(obj);
+    }
+    link_cmd.args(libraries::link_group(&lib_archives));
+    link_cmd.arg(&core_a);
+    link_cmd.args(&req.extra_flags.ldflags);
+    link_cmd.arg(format!("-l{}", chip.math_lib)).args(["-lm", "-lstdc++"]);
+    link_cmd.arg("-o").arg(&elf_path);
+
+    events::emit(Event::Linking { output: &elf_path });
+    let link_out = process::output(&mut link_cmd, Stage::Link)?;
+    if !link_out.status.success() {
+        return Err(FlashError::LinkFailed {
+            output: String::from_utf8_lossy(&link_out.stderr).to_string(),
+        });
+    }
+
+    // ── Step 4: .hex ──────────────────────────────────────────────────────
+    let hex_path = req.build_dir.join(format!("{}.hex", req.project_name));
+    run_tool(&objcopy, &["-O", "ihex", "-R", ".eeprom", elf_path.to_str().unwrap(), hex_path.to_str().unwrap()])?;
+
+    let size_info = firmware_size(&sdk.toolchain_bin, &elf_path);
+    Ok(CompileResult {
+        hex_path: Some(hex_path),
+        bin_path: None,
+        elf_path: Some(elf_path),
+        uf2_path: None,
+        size_info,
+        usage:    None,
+    })
+}
+
+fn firmware_size(bin_dir: &Path, elf: &Path) -> String {
+    let size = resolve_tool(bin_dir, "arm-none-eabi-size");
+    match process::output(Command::new(&size).arg(elf), Stage::Link) {
+        Ok(o) if o.status.success() => String::from_utf8_lossy(&o.stdout).trim().to_string(),
+        _ => "(size unknown)".into(),
+    }
+}
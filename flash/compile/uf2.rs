@@ -0,0 +1,157 @@
+// ─────────────────────────────────────────────────────────────────────────────
+//  tsuki-flash :: compile :: uf2  —  ELF → UF2 conversion
+//
+//  UF2 is what the RP2040 boot ROM accepts over USB mass storage: 512-byte
+//  blocks, each carrying 256 bytes of flash contents and the address they go
+//  to. The loadable segments of the linked ELF (at their load addresses) are
+//  cut into 256-byte pages, so no elf2uf2/picotool is needed.
+// ─────────────────────────────────────────────────────────────────────────────
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::error::{FlashError, Result};
+
+/// UF2 family id of the RP2040.
+pub const FAMILY_RP2040: u32 = 0xE48B_FF56;
+
+const MAGIC_START0: u32 = 0x0A32_4655;
+const MAGIC_START1: u32 = 0x9E5D_5157;
+const MAGIC_END:    u32 = 0x0AB1_6F30;
+const FLAG_FAMILY:  u32 = 0x0000_2000;
+const PAGE:         u32 = 256;
+const PT_LOAD:      u32 = 1;
+
+/// Convert the ELF at `elf` to UF2 at `out`, keeping the segments that load
+/// into `[flash_start, flash_end)`.
+pub fn write(elf: &Path, out: &Path, family: u32, flash_start: u32, flash_end: u32) -> Result<()> {
+    let data = std::fs::read(elf)?;
+    let segments = load_segments(&data).ok_or_else(|| FlashError::Other(
+        format!("{} is not a 32-bit little-endian ELF file", elf.display())))?;
+
+    // Page address → contents; segments may share a page.
+    let mut pages: BTreeMap<u32, [u8; PAGE as usize]> = BTreeMap::new();
+    for (addr, bytes) in segments {
+        if addr < flash_start || addr as u64 + bytes.len() as u64 > flash_end as u64 { continue }
+        for (i, b) in bytes.iter().enumerate() {
+            let at = addr + i as u32;
+            pages.entry(at & !(PAGE - 1)).or_insert([0; PAGE as usize])[(at % PAGE) as usize] = *b;
+        }
+    }
+    if pages.is_empty() {
+        return Err(FlashError::Other(format!(
+            "{} has nothing to load at {:#010x}..{:#010x}", elf.display(), flash_start, flash_end)));
+    }
+
+    let total = pages.len() as u32;
+    let mut uf2 = Vec::with_capacity(pages.len() * 512);
+    for (n, (addr, page)) in pages.iter().enumerate() {
+        for word in [MAGIC_START0, MAGIC_START1, FLAG_FAMILY, *addr, PAGE, n as u32, total, family] {
+            uf2.extend_from_slice(&word.to_le_bytes());
+        }
+        uf2.extend_from_slice(page);
+        uf2.resize(uf2.len() + 476 - PAGE as usize, 0);
+        uf2.extend_from_slice(&MAGIC_END.to_le_bytes());
+    }
+    std::fs::write(out, uf2)?;
+    Ok(())
+}
+
+/// (load address, file contents) of the PT_LOAD segments with contents.
+fn load_segments(b: &[u8]) -> Option<Vec<(u32, &[u8])>> {
+    if b.get(..4)? != b"\x7fELF" || *b.get(4)? != 1 || *b.get(5)? != 1 { return None }
+    let u32_at = |off: usize| -> Option<u32> { Some(u32::from_le_bytes(b.get(off..off + 4)?.try_into().ok()?)) };
+    let u16_at = |off: usize| -> Option<u16> { Some(u16::from_le_bytes(b.get(off..off + 2)?.try_into().ok()?)) };
+
+    let (phoff, phentsize, phnum) = (u32_at(0x1C)? as usize, u16_at(0x2A)? as usize, u16_at(0x2C)? as usize);
+    let mut segments = Vec::new();
+    for i in 0..phnum {
+        let at = phoff + i * phentsize;
+        let (kind, offset, paddr, filesz) = (u32_at(at)?, u32_at(at + 4)? as usize, u32_at(at + 12)?, u32_at(at + 16)? as usize);
+        if kind != PT_LOAD || filesz == 0 { continue }
+        segments.push((paddr, b.get(offset..offset + filesz)?));
+    }
+    Some(segments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const XIP: u32 = 0x1000_0000;
+
+    /// A 32-bit little-endian ELF with one PT_LOAD segment per
+    /// (address, contents).
+    fn elf(segments: &[(u32, &[u8])]) -> Vec<u8> {
+        let phoff = 0x34;
+        let mut data_at = phoff + 32 * segments.len();
+        let mut b = vec![0u8; data_at];
+        b[..6].copy_from_slice(b"\x7fELF\x01\x01");
+        b[0x1C..0x20].copy_from_slice(&(phoff as u32).to_le_bytes());
+        b[0x2A..0x2C].copy_from_slice(&32u16.to_le_bytes());
+        b[0x2C..0x2E].copy_from_slice(&(segments.len() as u16).to_le_bytes());
+        for (i, (addr, bytes)) in segments.iter().enumerate() {
+            let ph = phoff + 32 * i;
+            for (j, word) in [PT_LOAD, data_at as u32, *addr, *addr, bytes.len() as u32, bytes.len() as u32].iter().enumerate() {
+                b[ph + 4 * j..ph + 4 * j + 4].copy_from_slice(&word.to_le_bytes());
+            }
+            data_at += bytes.len();
+        }
+        for (_, bytes) in segments {
+            b.extend_from_slice(bytes);
+        }
+        b
+    }
+
+    fn word(block: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(block[at..at + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_round_trip() {
+        // 600 bytes of flash: two full pages and one partial. The RAM
+        // segment is outside the range and left out.
+        let image: Vec<u8> = (0..600u32).map(|i| (i * 7) as u8).collect();
+        let dir = std::env::temp_dir().join(format!("tsuki-uf2-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (elf_path, uf2_path) = (dir.join("fw.elf"), dir.join("fw.uf2"));
+        std::fs::write(&elf_path, elf(&[(XIP, &image), (0x2000_0000, &[0xEE; 64])])).unwrap();
+        write(&elf_path, &uf2_path, FAMILY_RP2040, XIP, XIP + 0x20_0000).unwrap();
+        let uf2 = std::fs::read(&uf2_path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(uf2.len(), 3 * 512);
+        let blocks: Vec<&[u8]> = uf2.chunks(512).collect();
+        for (n, block) in blocks.iter().enumerate() {
+            assert_eq!(word(block, 0), MAGIC_START0);
+            assert_eq!(word(block, 4), MAGIC_START1);
+            assert_eq!(word(block, 8), FLAG_FAMILY);
+            assert_eq!(word(block, 12), XIP + 256 * n as u32);
+            assert_eq!(word(block, 16), 256);
+            assert_eq!(word(block, 20), n as u32);
+            assert_eq!(word(block, 24), 3);
+            assert_eq!(word(block, 28), FAMILY_RP2040);
+            assert_eq!(word(block, 508), MAGIC_END);
+            assert!(block[32 + 256..508].iter().all(|&b| b == 0));
+        }
+        assert_eq!(word(blocks[0], 12), 0x1000_0000);
+        assert_eq!(word(blocks[2], 12), 0x1000_0200);
+
+        // Back to the image; the last page is padded.
+        let back: Vec<u8> = blocks.iter().flat_map(|b| b[32..32 + 256].to_vec()).collect();
+        assert_eq!(back[..600], image);
+        assert!(back[600..].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn test_nothing_to_load() {
+        let dir = std::env::temp_dir().join(format!("tsuki-uf2-empty-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let elf_path = dir.join("fw.elf");
+        std::fs::write(&elf_path, elf(&[(0x2000_0000, &[1, 2, 3])])).unwrap();
+        let res = write(&elf_path, &dir.join("fw.uf2"), FAMILY_RP2040, XIP, XIP + 0x20_0000);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(res.is_err());
+        assert!(load_segments(b"MZ\x90\x00").is_none());
+    }
+}
@@ -25,7 +25,11 @@
 //    sysfs path works instead.
 // ─────────────────────────────────────────────────────────────────────────────
 
+use std::net::Ipv4Addr;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
+use crate::boards::Board;
+use crate::mdns;
 
 #[derive(Debug, Clone)]
 pub struct DetectedPort {
@@ -35,10 +39,75 @@ pub struct DetectedPort {
     pub vid_pid:    Option<(u16, u16)>,
 }
 
+impl DetectedPort {
+    /// Whether the board on this port can take firmware built for `target`,
+    /// or None when the port isn't recognised. USB-serial bridges sit on all
+    /// kinds of clones, so for them only the chip family is compared.
+    pub fn accepts(&self, target: &Board) -> Option<bool> {
+        let found  = Board::find(self.board_id?)?;
+        let bridge = self.vid_pid.is_some_and(|(vid, _)| BRIDGE_VIDS.contains(&vid));
+        Some(if bridge {
+            family(found) == family(target)
+        } else {
+            found.arch() == target.arch() && found.avr_mcu() == target.avr_mcu()
+        })
+    }
+}
+
 // ─────────────────────────────────────────────────────────────────────────────
 //  Public API
 // ─────────────────────────────────────────────────────────────────────────────
 
+/// A board on the LAN, as it announces itself over mDNS.
+#[derive(Debug, Clone)]
+pub struct NetworkBoard {
+    pub name:       String,
+    pub host:       String,
+    pub addr:       Option<Ipv4Addr>,
+    pub port:       u16,
+    pub board_id:   Option<&'static str>,
+    /// The core's board name from the `board` TXT record, e.g. "ESP32_DEV".
+    pub board_name: Option<String>,
+    /// Whether it runs ArduinoOTA, so `upload --ota` can reach it. Boards
+    /// only announcing `_esp32._tcp` are listed but can't.
+    pub ota:        bool,
+    /// Whether uploads need the OTA password.
+    pub auth:       bool,
+}
+
+/// mDNS service types boards announce: ArduinoOTA's, and the ESP32 core's
+/// generic one.
+const NETWORK_SERVICES: &[&str] = &["_arduino._tcp", "_esp32._tcp"];
+
+/// Boards answering an mDNS query within `wait`, one per host.
+pub fn detect_network(wait: Duration) -> Vec<NetworkBoard> {
+    let mut boards: Vec<NetworkBoard> = Vec::new();
+    // ArduinoOTA first, so its port is the one kept for a host with both.
+    let mut services = mdns::browse(NETWORK_SERVICES, wait);
+    services.sort_by_key(|s| s.service != "_arduino._tcp");
+
+    for s in services {
+        let ota = s.service == "_arduino._tcp";
+        let board_name = s.txt.get("board").filter(|b| !b.is_empty()).cloned();
+        if let Some(known) = boards.iter_mut().find(|b| b.host.eq_ignore_ascii_case(&s.host)) {
+            known.board_name = known.board_name.take().or(board_name);
+            known.board_id = known.board_id.or_else(|| known.board_name.as_deref().and_then(network_board_id));
+            continue;
+        }
+        boards.push(NetworkBoard {
+            board_id: board_name.as_deref().and_then(network_board_id),
+            auth:     ota && s.txt.get("auth_upload").is_some_and(|v| v == "yes"),
+            name:     s.instance,
+            host:     s.host,
+            addr:     s.addr,
+            port:     s.port,
+            board_name,
+            ota,
+        });
+    }
+    boards
+}
+
 /// Enumerate all serial ports, tagging each with a board guess if possible.
 pub fn detect_all() -> Vec<DetectedPort> {
     let raw = enumerate_raw_ports();
@@ -60,6 +129,28 @@ pub fn best_port() -> Option<String> {
         .map(|p| p.port)
 }
 
+// ─────────────────────────────────────────────────────────────────────────────
+//  mDNS board name → board table
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// ARDUINO_BOARD of the ESP cores, as sent in the `board` TXT record.
+static NETWORK_BOARD_MAP: &[(&str, &str)] = &[
+    ("ESP32_DEV",              "esp32"),
+    ("ESP32S2_DEV",            "esp32s2"),
+    ("ESP32C3_DEV",            "esp32c3"),
+    ("ESP8266_GENERIC",        "esp8266"),
+    ("ESP8266_NODEMCU",        "nodemcu"),
+    ("ESP8266_NODEMCU_ESP12E", "nodemcu"),
+    ("ESP8266_WEMOS_D1MINI",   "d1_mini"),
+];
+
+fn network_board_id(board: &str) -> Option<&'static str> {
+    NETWORK_BOARD_MAP.iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(board))
+        .and_then(|(_, id)| Board::find(id))
+        .map(|b| b.id)
+}
+
 // ─────────────────────────────────────────────────────────────────────────────
 //  VID:PID → board table
 // ─────────────────────────────────────────────────────────────────────────────
@@ -82,6 +173,7 @@ static VID_PID_MAP: &[(u16, u16, &str, &str)] = &[
     (0x2341, 0x003D, "due",      "Arduino Due (prog)"),
     (0x2341, 0x003E, "due",      "Arduino Due (native)"),
     (0x2341, 0x0057, "uno",      "Arduino Uno R4 Minima"),
+    (0x2341, 0x0058, "nano_every", "Arduino Nano Every"),
     (0x2341, 0x1002, "uno",      "Arduino Uno R4 WiFi"),
     // ── Arduino.org clone VID (0x2A03) ────────────────────────────────────
     (0x2A03, 0x0043, "uno",      "Arduino Uno (org clone)"),
@@ -102,8 +194,26 @@ static VID_PID_MAP: &[(u16, u16, &str, &str)] = &[
     (0x2E8A, 0x000A, "pico",     "Raspberry Pi Pico"),
     (0x2E8A, 0x0005, "pico",     "Raspberry Pi Pico (MicroPython)"),
     (0x2E8A, 0x000F, "pico",     "Raspberry Pi Pico W"),
+    // ── STMicroelectronics (0x0483) ───────────────────────────────────────
+    (0x0483, 0x374B, "nucleo_f401re", "STM32 Nucleo (ST-Link V2-1)"),
+    // ── PJRC Teensy (0x16C0) — one PID per USB type, for every model ──────
+    (0x16C0, 0x0483, "teensy41", "Teensy (USB serial)"),
 ];
 
+/// Vendors of USB-serial bridge chips (CH340, FTDI, CP210x). Their VID:PID
+/// names the chip, not the board behind it.
+const BRIDGE_VIDS: &[u16] = &[0x1A86, 0x0403, 0x10C4];
+
+/// ESP32 and ESP8266 boards share the same bridges, so they form one family;
+/// so do classic AVR boards and the UPDI parts programmed through them.
+fn family(board: &Board) -> &'static str {
+    match board.arch() {
+        "esp8266" => "esp32",
+        "megaavr" | "megatinycore" => "avr",
+        arch      => arch,
+    }
+}
+
 // ─────────────────────────────────────────────────────────────────────────────
 //  Classification
 // ─────────────────────────────────────────────────────────────────────────────
@@ -130,7 +240,9 @@ fn classify(port: String, vid_pid: Option<(u16, u16)>) -> DetectedPort {
     DetectedPort { port, board_id: None, board_name: None, vid_pid: None }
 }
 
-fn looks_like_serial(port: &str) -> bool {
+/// Whether `port` is named like a USB serial adapter rather than a
+/// built-in UART or a Bluetooth port.
+pub fn looks_like_serial(port: &str) -> bool {
     port.contains("ttyUSB") || port.contains("ttyACM")
         || port.contains("usbserial") || port.contains("usbmodem")
         || (port.starts_with("COM") && port.len() <= 6)
@@ -296,11 +408,11 @@ fn macos_enumerate() -> Vec<(String, Option<(u16, u16)>)> {
 #[cfg(target_os = "macos")]
 fn macos_ioreg_vid_pid() -> std::collections::HashMap<String, (u16, u16)> {
     use std::collections::HashMap;
+    use crate::process::{self, Stage};
     let mut map = HashMap::new();
 
-    let out = match std::process::Command::new("ioreg")
-        .args(["-r", "-c", "IOUSBHostDevice", "-l"])
-        .output()
+    let out = match process::output_within(std::process::Command::new("ioreg")
+        .args(["-r", "-c", "IOUSBHostDevice", "-l"]), Stage::Upload, process::QUERY)
     {
         Ok(o) if o.status.success() => String::from_utf8_lossy(&o.stdout).to_string(),
         _ => return map,
@@ -366,9 +478,9 @@ fn windows_enumerate() -> Vec<(String, Option<(u16, u16)>)> {
     // Output format (CSV):
     //   Node,DeviceID,PNPDeviceID
     //   HOSTNAME,COM3,USB\VID_1A86&PID_7523\5&...
-    let out = match std::process::Command::new("wmic")
-        .args(["path", "Win32_SerialPort", "get", "DeviceID,PNPDeviceID", "/FORMAT:CSV"])
-        .output()
+    use crate::process::{self, Stage};
+    let out = match process::output_within(std::process::Command::new("wmic")
+        .args(["path", "Win32_SerialPort", "get", "DeviceID,PNPDeviceID", "/FORMAT:CSV"]), Stage::Upload, process::QUERY)
     {
         Ok(o) => String::from_utf8_lossy(&o.stdout).to_string(),
         Err(_) => return windows_enumerate_registry_fallback(),
@@ -406,9 +518,9 @@ fn windows_enumerate() -> Vec<(String, Option<(u16, u16)>)> {
 fn windows_enumerate_registry_fallback() -> Vec<(String, Option<(u16, u16)>)> {
     // Read HKLM\HARDWARE\DEVICEMAP\SERIALCOMM
     // Key values look like:  \Device\Serial0 → COM1
-    let out = std::process::Command::new("reg")
-        .args(["query", r"HKLM\HARDWARE\DEVICEMAP\SERIALCOMM"])
-        .output();
+    use crate::process::{self, Stage};
+    let out = process::output_within(std::process::Command::new("reg")
+        .args(["query", r"HKLM\HARDWARE\DEVICEMAP\SERIALCOMM"]), Stage::Upload, process::QUERY);
 
     let mut results = Vec::new();
 
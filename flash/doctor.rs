@@ -0,0 +1,349 @@
+// ─────────────────────────────────────────────────────────────────────────────
+//  tsuki-flash :: doctor  —  environment checks
+//
+//  `tsuki-flash doctor` looks at what a build and an upload need — cores,
+//  their compilers, the upload tools, serial port access — and prints the
+//  fix for every problem it finds. The fixes live here too, so that error
+//  rendering suggests the same commands the doctor does.
+// ─────────────────────────────────────────────────────────────────────────────
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use colored::Colorize;
+
+use crate::boards::Board;
+use crate::error::{FlashError, Result};
+use crate::process::{self, Stage};
+use crate::{detect, flash, modules, permissions, sdk};
+
+/// Every architecture tsuki-flash builds for, in the order they're checked.
+const ARCHES: &[&str] = &[
+    "avr", "megaavr", "megatinycore", "sam", "esp32", "esp8266", "rp2040", "stm32", "teensy",
+];
+
+// ─────────────────────────────────────────────────────────────────────────────
+//  Fixes
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// The arduino-cli package providing `arch`'s core.
+pub fn arduino_cli_package(arch: &str) -> &str {
+    match arch {
+        "avr"          => "arduino:avr",
+        "sam"          => "arduino:sam",
+        "esp32"        => "esp32:esp32",
+        "esp8266"      => "esp8266:esp8266",
+        "rp2040"       => "rp2040:rp2040",
+        "stm32"        => "STMicroelectronics:stm32",
+        "megaavr"      => "arduino:megaavr",
+        "megatinycore" => "megaTinyCore:megaavr",
+        "teensy"       => "teensy:avr",
+        _              => arch,
+    }
+}
+
+/// The command that installs `arch`'s core: tsuki-modules where it can,
+/// else arduino-cli.
+pub fn install_core(arch: &str) -> String {
+    if modules::arch_to_package(arch).is_ok() {
+        format!("tsuki-flash modules install {}", arch)
+    } else {
+        format!("arduino-cli core install {}", arduino_cli_package(arch))
+    }
+}
+
+pub const INSTALL_ESPTOOL: &str = "pip install esptool";
+
+pub const INSTALL_TEENSY_LOADER: &str =
+    "install teensy_loader_cli from https://www.pjrc.com/teensy/loader_cli.html";
+
+/// How to get read/write access to `port`, when the OS is known to gate it
+/// behind a group.
+pub fn serial_access(port: &str) -> Option<String> {
+    if !cfg!(unix) || cfg!(target_os = "macos") { return None; }
+    let group = port_group(Path::new(port)).unwrap_or_else(|| "dialout".into());
+    Some(format!("tsuki-flash setup-permissions   (or: sudo usermod -aG {} $USER, then log in again)", group))
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+//  Checks
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[derive(PartialEq)]
+enum Status {
+    Ok,
+    /// Not set up, and not needed unless the user wants it.
+    Absent,
+    Warn,
+    Fail,
+}
+
+struct Report {
+    problems: usize,
+}
+
+impl Report {
+    fn section(&self, title: &str) {
+        println!("\n{}", title.bold());
+    }
+
+    fn check(&mut self, status: Status, what: &str, detail: impl AsRef<str>, fix: Option<String>) {
+        let mark = match status {
+            Status::Ok     => "✓".green().bold(),
+            Status::Absent => "·".dimmed(),
+            Status::Warn   => "!".yellow().bold(),
+            Status::Fail   => "✗".red().bold(),
+        };
+        println!("  {} {:<22} {}", mark, what, detail.as_ref().dimmed());
+        if let Some(fix) = fix {
+            println!("    {} {}", "fix:".dimmed(), fix.bold());
+        }
+        if status == Status::Fail { self.problems += 1; }
+    }
+}
+
+/// Run every check and print the results. Fails when something a build or
+/// an upload needs is broken.
+pub fn run() -> Result<()> {
+    let mut report = Report { problems: 0 };
+
+    report.section("Cores");
+    let mut installed = Vec::new();
+    for &arch in ARCHES {
+        match sdk::resolve(arch, default_variant(arch)) {
+            Ok(paths) => {
+                report.check(Status::Ok, arch,
+                    format!("{}  {}", paths.sdk_version, paths.platform_dir().display()), None);
+                installed.push((arch, paths));
+            }
+            Err(_) => report.check(Status::Absent, arch, "not installed", Some(install_core(arch))),
+        }
+    }
+    if installed.is_empty() {
+        report.check(Status::Fail, "any core", "none found — nothing can be built",
+            Some(install_core("avr")));
+    } else {
+        report.section("Compilers");
+        for (arch, paths) in &installed {
+            let cc = compiler(arch);
+            match find_tool(&paths.toolchain_bin, cc) {
+                Some(p) => report.check(Status::Ok, arch, p.display().to_string(), None),
+                None => report.check(Status::Fail, arch,
+                    format!("{} not in {} or on PATH", cc, paths.toolchain_bin.display()),
+                    Some(install_core(arch))),
+            }
+        }
+
+        report.section("Upload tools");
+        check_upload_tools(&mut report, &installed.iter().map(|(a, _)| *a).collect::<Vec<_>>());
+    }
+
+    report.section("Serial ports");
+    check_serial(&mut report, installed.iter().any(|(a, _)| matches!(*a, "teensy" | "rp2040")));
+
+    println!();
+    if report.problems > 0 {
+        return Err(FlashError::Other(format!("{} problem{} found",
+            report.problems, if report.problems == 1 { "" } else { "s" })));
+    }
+    println!("{} No problems found", "✓".green().bold());
+    Ok(())
+}
+
+/// The variant of the first catalog board on `arch`, to resolve its SDK with.
+fn default_variant(arch: &str) -> &'static str {
+    Board::catalog().iter().find(|b| b.arch() == arch).map_or("standard", |b| b.variant)
+}
+
+fn compiler(arch: &str) -> &'static str {
+    match arch {
+        "avr" | "megaavr" | "megatinycore" => "avr-gcc",
+        "esp32"   => "xtensa-esp32-elf-gcc",
+        "esp8266" => "xtensa-lx106-elf-gcc",
+        _         => "arm-none-eabi-gcc",
+    }
+}
+
+fn check_upload_tools(report: &mut Report, arches: &[&str]) {
+    let has = |arch: &str| arches.contains(&arch);
+
+    // The built-in STK500 programmer covers AVR bootloader uploads;
+    // avrdude is only needed for ISP, UPDI and --use-avrdude.
+    if has("avr") || has("megaavr") || has("megatinycore") {
+        let avrdude = flash::avrdude::find_avrdude();
+        match find_tool(Path::new(""), &avrdude) {
+            Some(p) => report.check(Status::Ok, "avrdude", p.display().to_string(), None),
+            None if has("megaavr") || has("megatinycore") => report.check(Status::Warn, "avrdude",
+                "not found — needed to upload to megaAVR and tinyAVR boards",
+                Some("install avrdude with your package manager".into())),
+            None => report.check(Status::Absent, "avrdude",
+                "not found — only needed for ISP programmers and --use-avrdude", None),
+        }
+    }
+    if has("sam") {
+        match flash::bossac::find_bossac() {
+            Some(p) => report.check(Status::Ok, "bossac", p.display().to_string(), None),
+            None    => report.check(Status::Fail, "bossac", "not found — needed to upload to the Due",
+                Some(install_core("sam"))),
+        }
+    }
+    if has("esp32") || has("esp8266") {
+        match python() {
+            Some(v) => report.check(Status::Ok, "python", v, None),
+            None    => report.check(Status::Warn, "python", "not found — esptool needs it",
+                Some("install Python 3 from https://www.python.org".into())),
+        }
+        match flash::esptool::find_esptool() {
+            Some(p) => report.check(Status::Ok, "esptool", p, None),
+            None    => report.check(Status::Warn, "esptool",
+                "not found — ESP builds produce no .bin without it", Some(INSTALL_ESPTOOL.into())),
+        }
+    }
+    if has("stm32") {
+        for (tool, why) in [("dfu-util", "DFU uploads"), ("stm32flash", "serial uploads")] {
+            match find_tool(Path::new(""), tool) {
+                Some(p) => report.check(Status::Ok, tool, p.display().to_string(), None),
+                None    => report.check(Status::Absent, tool, format!("not found — needed for {}", why), None),
+            }
+        }
+    }
+    if has("teensy") {
+        match find_tool(Path::new(""), "teensy_loader_cli") {
+            Some(p) => report.check(Status::Ok, "teensy_loader_cli", p.display().to_string(), None),
+            None    => report.check(Status::Fail, "teensy_loader_cli", "not found — needed to upload to Teensy",
+                Some(INSTALL_TEENSY_LOADER.into())),
+        }
+    }
+}
+
+fn check_serial(report: &mut Report, needs_usb_rules: bool) {
+    let ports: Vec<_> = detect::detect_all().into_iter()
+        .filter(|p| detect::looks_like_serial(&p.port))
+        .collect();
+    if ports.is_empty() {
+        report.check(Status::Absent, "ports", "none found — connect a board to check access",
+            missing_driver_fix());
+    }
+    for p in &ports {
+        let what = p.board_name.unwrap_or("unknown board");
+        match can_access(Path::new(&p.port)) {
+            Some(false) => report.check(Status::Fail, &p.port, format!("{} — no read/write access", what),
+                serial_access(&p.port)),
+            _ => report.check(Status::Ok, &p.port, what, None),
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    if needs_usb_rules {
+        // HalfKay (Teensy) and the RP2040 boot ROM are raw USB devices, not
+        // serial ports: only udev rules give a user access to them.
+        for (vid, what) in [("16c0", "Teensy"), ("2e8a", "RP2040")] {
+            if permissions::covers(vid) {
+                report.check(Status::Ok, "udev rules", format!("{} ({})", what, vid), None);
+            } else {
+                report.check(Status::Warn, "udev rules", format!("none for {} (VID {}) — its USB \
+                    bootloader needs root", what, vid), Some("tsuki-flash setup-permissions".into()));
+            }
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = needs_usb_rules;
+
+    #[cfg(windows)]
+    for (file, chip, url) in WINDOWS_DRIVERS {
+        let root = std::env::var("SystemRoot").unwrap_or_else(|_| r"C:\Windows".into());
+        let path = PathBuf::from(root).join(r"System32\drivers").join(file);
+        if path.is_file() {
+            report.check(Status::Ok, chip, path.display().to_string(), None);
+        } else {
+            report.check(Status::Absent, chip, "driver not installed", Some(url.to_string()));
+        }
+    }
+}
+
+/// USB-serial chips on clone boards, with the driver Windows needs for each.
+#[cfg(windows)]
+const WINDOWS_DRIVERS: &[(&str, &str, &str)] = &[
+    ("CH341SER.SYS", "CH340 driver",  "https://www.wch-ic.com/downloads/CH341SER_EXE.html"),
+    ("silabser.sys", "CP210x driver", "https://www.silabs.com/developers/usb-to-uart-bridge-vcp-drivers"),
+    ("ftser2k.sys",  "FTDI driver",   "https://ftdichip.com/drivers/vcp-drivers/"),
+];
+
+fn missing_driver_fix() -> Option<String> {
+    if cfg!(windows) {
+        Some("a board that doesn't show up as a COM port needs its USB-serial driver (see below)".into())
+    } else {
+        None
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+//  Helpers
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// `name` in `bin_dir`, or on PATH. An absolute `name` is checked as is.
+fn find_tool(bin_dir: &Path, name: &str) -> Option<PathBuf> {
+    let exe = if cfg!(windows) && !name.ends_with(".exe") { format!("{}.exe", name) } else { name.to_owned() };
+    if Path::new(&exe).is_absolute() {
+        return Path::new(&exe).is_file().then(|| PathBuf::from(&exe));
+    }
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    std::iter::once(bin_dir.to_path_buf())
+        .filter(|d| !d.as_os_str().is_empty())
+        .chain(std::env::split_paths(&path))
+        .map(|d| d.join(&exe))
+        .find(|p| p.is_file())
+}
+
+/// The version banner of the first Python found.
+fn python() -> Option<String> {
+    ["python3", "python"].iter().find_map(|py| {
+        let out = process::output_within(Command::new(py).arg("--version"), Stage::Upload, process::QUERY).ok()?;
+        if !out.status.success() { return None; }
+        // Python 2 prints its version on stderr.
+        let text = [out.stdout, out.stderr].concat();
+        Some(String::from_utf8_lossy(&text).trim().to_owned())
+    })
+}
+
+/// Whether the current user may read and write `port`, judged from its
+/// mode bits — opening it would reset most boards. None where unknown.
+#[cfg(unix)]
+fn can_access(port: &Path) -> Option<bool> {
+    use std::os::unix::fs::MetadataExt;
+
+    let meta = std::fs::metadata(port).ok()?;
+    let id = |flag: &str| -> Option<Vec<u32>> {
+        let out = process::output_within(Command::new("id").arg(flag), Stage::Upload, process::QUERY).ok()?;
+        Some(String::from_utf8_lossy(&out.stdout).split_whitespace().filter_map(|s| s.parse().ok()).collect())
+    };
+    let uid = *id("-u")?.first()?;
+    let mode = meta.mode();
+    let rw = |shift: u32| mode >> shift & 0o6 == 0o6;
+    Some(uid == 0
+        || (meta.uid() == uid && rw(6))
+        || (id("-G")?.contains(&meta.gid()) && rw(3))
+        || rw(0))
+}
+
+#[cfg(not(unix))]
+fn can_access(_port: &Path) -> Option<bool> {
+    None
+}
+
+/// The name of the group owning `port` (dialout on Debian, uucp on Arch).
+#[cfg(unix)]
+fn port_group(port: &Path) -> Option<String> {
+    use std::os::unix::fs::MetadataExt;
+
+    let gid = std::fs::metadata(port).ok()?.gid().to_string();
+    std::fs::read_to_string("/etc/group").ok()?.lines()
+        .map(|l| l.split(':').collect::<Vec<_>>())
+        .find(|f| f.len() > 2 && f[2] == gid)
+        .map(|f| f[0].to_owned())
+}
+
+#[cfg(not(unix))]
+fn port_group(_port: &Path) -> Option<String> {
+    None
+}
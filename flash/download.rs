@@ -0,0 +1,316 @@
+// ─────────────────────────────────────────────────────────────────────────────
+//  tsuki-flash :: download  —  shared HTTP download (modules + lib_manager)
+//
+//  Every download streams into `<dest>.part` and is renamed into place once
+//  complete, so a 200 MB toolchain never sits in memory and a half-written
+//  file is never mistaken for a finished one. A `.part` left by an earlier,
+//  interrupted run is resumed with an HTTP Range request, guarded by
+//  If-Range with the ETag or Last-Modified its first response carried: a
+//  file that changed since comes back whole instead of as a tail glued onto
+//  stale bytes, and a `.part` with no validator is started over. Transient
+//  failures (connection errors, 5xx, 429) are retried with exponential
+//  backoff.
+//
+//  Progress is drawn on stderr as one bar per download — several at once
+//  when modules fetch a core and its toolchain in parallel — unless --quiet
+//  or TSUKI_QUIET is set, or stderr is not a terminal.
+// ─────────────────────────────────────────────────────────────────────────────
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+
+use crate::error::{FlashError, Result};
+
+/// Attempts per download, the first included.
+const MAX_ATTEMPTS: u32 = 5;
+
+/// Wait before the first retry; doubled before each further one.
+const FIRST_BACKOFF: Duration = Duration::from_secs(1);
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+static BARS: OnceLock<MultiProgress> = OnceLock::new();
+
+/// Hide progress bars for the rest of the run (`--quiet`).
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+/// Download `url` to `dest`, resuming a previous partial download and
+/// retrying transient failures. With `checksum` (`SHA-256:<hex>` or bare
+/// hex) the file is verified before it is moved into place.
+pub fn to_file(url: &str, dest: &Path, checksum: Option<&str>, verbose: bool) -> Result<()> {
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let part = part_path(dest);
+    let bar = progress_bar(url);
+
+    let mut backoff = FIRST_BACKOFF;
+    let mut attempt = 1;
+    loop {
+        match fetch(url, &part, &bar, verbose) {
+            Ok(()) => break,
+            Err(e) if e.retryable && attempt < MAX_ATTEMPTS => {
+                if verbose {
+                    eprintln!("  [download] {} — retrying in {} s ({}/{})",
+                        e.message, backoff.as_secs(), attempt, MAX_ATTEMPTS - 1);
+                }
+                bar.set_message(format!("{} (retry {})", file_name(url), attempt));
+                std::thread::sleep(backoff);
+                backoff *= 2;
+                attempt += 1;
+            }
+            Err(e) => {
+                bar.abandon();
+                return Err(FlashError::Other(format!("Download failed ({}): {}", url, e.message)));
+            }
+        }
+    }
+    bar.finish_and_clear();
+
+    let _ = fs::remove_file(tag_path(&part));
+    if let Some(cs) = checksum {
+        if let Err(e) = verify_sha256(&part, cs) {
+            // A corrupt file must not be resumed next time.
+            let _ = fs::remove_file(&part);
+            return Err(e);
+        }
+    }
+    fs::rename(&part, dest)?;
+    Ok(())
+}
+
+/// Where the bytes of `dest` accumulate until the download completes.
+fn part_path(dest: &Path) -> PathBuf {
+    let mut name = dest.file_name().unwrap_or_default().to_os_string();
+    name.push(".part");
+    dest.with_file_name(name)
+}
+
+/// Where the validator of `part` is kept: the ETag or Last-Modified of the
+/// response it was started from.
+fn tag_path(part: &Path) -> PathBuf {
+    let mut name = part.file_name().unwrap_or_default().to_os_string();
+    name.push(".tag");
+    part.with_file_name(name)
+}
+
+/// The last path segment of `url`, for progress messages.
+fn file_name(url: &str) -> &str {
+    url.rsplit('/').next().filter(|s| !s.is_empty()).unwrap_or(url)
+}
+
+struct FetchError {
+    message:   String,
+    retryable: bool,
+}
+
+impl FetchError {
+    fn fatal(message: String) -> Self { FetchError { message, retryable: false } }
+    fn transient(message: String) -> Self { FetchError { message, retryable: true } }
+}
+
+/// One attempt: continue `part` from where it ends, or start it over when
+/// it has no validator, the file changed, or the server ignores the range.
+fn fetch(url: &str, part: &Path, bar: &ProgressBar, verbose: bool) -> std::result::Result<(), FetchError> {
+    let tag_file = tag_path(part);
+    let have = fs::metadata(part).map_or(0, |m| m.len());
+    let tag = fs::read_to_string(&tag_file).ok().filter(|_| have > 0);
+    let mut req = ureq::get(url);
+    if let Some(tag) = &tag {
+        req = req.set("Range", &format!("bytes={}-", have)).set("If-Range", tag);
+    }
+    if verbose {
+        if tag.is_some() { eprintln!("  [download] GET {} (resuming at {} bytes)", url, have); }
+        else             { eprintln!("  [download] GET {}", url); }
+    }
+
+    let resp = match req.call() {
+        Ok(r) => r,
+        // The .part already holds the whole file, or no longer matches it.
+        Err(ureq::Error::Status(416, _)) => {
+            let _ = fs::remove_file(part);
+            let _ = fs::remove_file(&tag_file);
+            return Err(FetchError::transient("partial download no longer matches; starting over".into()));
+        }
+        Err(ureq::Error::Status(code, r)) => {
+            let message = format!("HTTP {} {}", code, r.status_text());
+            return Err(if code >= 500 || code == 429 { FetchError::transient(message) } else { FetchError::fatal(message) });
+        }
+        Err(e) => return Err(FetchError::transient(e.to_string())),
+    };
+
+    let resumed = tag.is_some() && resp.status() == 206;
+    if !resumed {
+        // Weak ETags may not be used with If-Range.
+        let validator = resp.header("ETag").filter(|e| !e.starts_with("W/"))
+            .or_else(|| resp.header("Last-Modified"));
+        match validator {
+            Some(v) => fs::write(&tag_file, v)
+                .map_err(|e| FetchError::fatal(format!("{}: {}", tag_file.display(), e)))?,
+            None => { let _ = fs::remove_file(&tag_file); }
+        }
+    }
+    let length: Option<u64> = resp.header("Content-Length").and_then(|l| l.parse().ok());
+    let start = if resumed { have } else { 0 };
+    match length {
+        Some(len) => {
+            bar.set_style(bar_style(true));
+            bar.set_length(start + len);
+        }
+        None => bar.set_style(bar_style(false)),
+    }
+    bar.set_position(start);
+
+    let io_err = |e: io::Error| FetchError::fatal(format!("{}: {}", part.display(), e));
+    let mut out = if resumed {
+        OpenOptions::new().append(true).open(part).map_err(io_err)?
+    } else {
+        File::create(part).map_err(io_err)?
+    };
+
+    let mut reader = resp.into_reader();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = reader.read(&mut buf)
+            .map_err(|e| FetchError::transient(format!("connection lost: {}", e)))?;
+        if n == 0 { break; }
+        out.write_all(&buf[..n]).map_err(io_err)?;
+        bar.inc(n as u64);
+    }
+    out.flush().map_err(io_err)?;
+
+    if let Some(len) = length {
+        if bar.position() < start + len {
+            return Err(FetchError::transient(format!(
+                "connection closed after {} of {} bytes", bar.position(), start + len)));
+        }
+    }
+    Ok(())
+}
+
+fn progress_bar(url: &str) -> ProgressBar {
+    let quiet = QUIET.load(Ordering::Relaxed) || std::env::var("TSUKI_QUIET").is_ok();
+    let bar = if quiet {
+        ProgressBar::hidden()
+    } else {
+        BARS.get_or_init(|| MultiProgress::with_draw_target(ProgressDrawTarget::stderr()))
+            .add(ProgressBar::new_spinner())
+    };
+    bar.set_style(bar_style(false));
+    bar.set_message(file_name(url).to_owned());
+    bar
+}
+
+fn bar_style(sized: bool) -> ProgressStyle {
+    let template = if sized {
+        "  {msg:40!} [{bar:30.cyan/blue}] {bytes:>10}/{total_bytes:<10} {bytes_per_sec:>11} {eta:>4}"
+    } else {
+        "  {msg:40!} {spinner} {bytes:>10} {bytes_per_sec:>11}"
+    };
+    ProgressStyle::with_template(template)
+        .expect("valid progress template")
+        .progress_chars("=> ")
+}
+
+fn verify_sha256(path: &Path, checksum_field: &str) -> Result<()> {
+    use sha2::{Digest, Sha256};
+
+    let expected = checksum_field
+        .strip_prefix("SHA-256:")
+        .unwrap_or(checksum_field)
+        .trim()
+        .to_lowercase();
+
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    let actual = hex::encode(hasher.finalize());
+    if actual != expected {
+        return Err(FlashError::Other(format!(
+            "Checksum mismatch!\n  expected: {}\n  actual:   {}", expected, actual
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+
+    const BODY: &[u8] = b"{\"libraries\": [\"new index\"]}";
+    const ETAG: &str = "\"v2\"";
+
+    /// A server for BODY that honours Range, and If-Range the way RFC 9110
+    /// says: the whole file when the validator doesn't match.
+    fn serve(requests: usize) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/index.json", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming().take(requests) {
+                let mut stream = stream.unwrap();
+                let (mut range, mut if_range) = (None, None);
+                for line in BufReader::new(&stream).lines() {
+                    let line = line.unwrap();
+                    if line.is_empty() { break; }
+                    let (name, value) = line.split_once(": ").unwrap_or((&line, ""));
+                    match name.to_ascii_lowercase().as_str() {
+                        "range"    => range = value.strip_prefix("bytes=")
+                                         .and_then(|r| r.trim_end_matches('-').parse::<usize>().ok()),
+                        "if-range" => if_range = Some(value.to_owned()),
+                        _ => {}
+                    }
+                }
+                let from = range.filter(|_| if_range.as_deref().is_none_or(|t| t == ETAG));
+                let (status, body) = match from {
+                    Some(n) => ("206 Partial Content", &BODY[n..]),
+                    None    => ("200 OK", BODY),
+                };
+                write!(stream, "HTTP/1.1 {}\r\nETag: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    status, ETAG, body.len()).unwrap();
+                stream.write_all(body).unwrap();
+            }
+        });
+        url
+    }
+
+    fn run(name: &str, part: Option<&[u8]>, tag: Option<&str>) -> Vec<u8> {
+        set_quiet(true);
+        let dir = std::env::temp_dir().join(format!("tsuki-download-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let dest = dir.join("index.json");
+        if let Some(p) = part { fs::write(part_path(&dest), p).unwrap(); }
+        if let Some(t) = tag { fs::write(tag_path(&part_path(&dest)), t).unwrap(); }
+
+        to_file(&serve(1), &dest, None, false).unwrap();
+        let got = fs::read(&dest).unwrap();
+        assert!(!part_path(&dest).exists() && !tag_path(&part_path(&dest)).exists());
+        fs::remove_dir_all(&dir).ok();
+        got
+    }
+
+    #[test]
+    fn test_stale_part_is_fetched_whole() {
+        // The index changed since the .part was written: its tail must not
+        // be appended to the old bytes.
+        assert_eq!(run("stale", Some(b"{\"libraries\": [\"old"), Some("\"v1\"")), BODY);
+    }
+
+    #[test]
+    fn test_part_without_validator_starts_over() {
+        assert_eq!(run("untagged", Some(b"{\"libraries\": [\"old"), None), BODY);
+    }
+
+    #[test]
+    fn test_part_resumes_when_unchanged() {
+        assert_eq!(run("resume", Some(&BODY[..10]), Some(ETAG)), BODY);
+        assert_eq!(run("fresh", None, None), BODY);
+    }
+}
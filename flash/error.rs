@@ -4,11 +4,19 @@
 
 use thiserror::Error;
 
+use crate::process::Stage;
+
 #[derive(Debug, Error)]
 pub enum FlashError {
     #[error("Unknown board '{0}' — run `tsuki-flash boards` for the full list")]
     UnknownBoard(String),
 
+    #[error("Board '{board}' cannot run at {mhz} MHz\n  Supported: {supported} MHz")]
+    UnsupportedClock { board: String, mhz: u32, supported: String },
+
+    #[error("Board '{board}' has no option '{option}'\n  Available: {available}")]
+    UnknownBoardOption { board: String, option: String, available: String },
+
     #[error("Toolchain not found: {0}\n  Hint: install avr-gcc or the relevant Arduino SDK")]
     ToolchainNotFound(String),
 
@@ -24,12 +32,27 @@ pub enum FlashError {
     #[error("Flash failed on {port}:\n{output}")]
     FlashFailed { port: String, output: String },
 
+    #[error("`{tool}` was stopped after {secs} s ({} time limit)\n  Hint: {}", .stage.name(), .stage.hint())]
+    Timeout { tool: String, stage: Stage, secs: u64, output: String },
+
     #[error("No board detected on any serial port\n  Hint: connect the board, or pass --port /dev/ttyUSBx")]
     NoBoardDetected,
 
     #[error("Port '{0}' not found or not accessible")]
     PortNotFound(String),
 
+    #[error("No permission to open '{0}'\n  Hint: run `tsuki-flash doctor` to see why")]
+    PortAccessDenied(String),
+
+    #[error("Firmware was built for '{built_for}', not '{board}'\n  Hint: rebuild with --board {board}, or pass --force to flash anyway")]
+    FirmwareMismatch { built_for: String, board: String },
+
+    #[error("Firmware was built for '{built_for}', but the board on {port} looks like {detected}\n  Hint: pick the right --port, or pass --force to flash anyway")]
+    BoardMismatch { port: String, built_for: String, detected: String },
+
+    #[error("Firmware exceeds its size budget:\n  {breakdown}")]
+    BudgetExceeded { breakdown: String },
+
     #[error("No .hex/.bin file found in {0}")]
     NoFirmware(String),
 
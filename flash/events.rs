@@ -0,0 +1,113 @@
+// ─────────────────────────────────────────────────────────────────────────────
+//  tsuki-flash :: events  —  `--message-format json-lines`
+//
+//  A build or upload started with `--message-format json-lines` reports what
+//  it does as it goes, one JSON object per line on stdout, for editor
+//  extensions to drive progress bars and put diagnostics inline. Every
+//  object has a `reason` naming the event, as in cargo's message format:
+//
+//    build-started      board, sketch
+//    compiling          unit (sketch / core / a library's name), file
+//    fresh              unit, file, cache (build / objects): no compile needed
+//    linking            output
+//    diagnostic         level, file, line, column, message
+//    size               flash, ram, flash_max, ram_max (bytes)
+//    build-finished     success, seconds, artifacts
+//    upload-started     board, port, firmware
+//    upload-progress    percent, bytes, total  (built-in programmers only)
+//    upload-finished    success, seconds
+//
+//  Nothing is printed, or even built, while the stream is off.
+// ─────────────────────────────────────────────────────────────────────────────
+
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use serde::Serialize;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, clap::ValueEnum)]
+pub enum MessageFormat {
+    /// Progress and results as text, for people.
+    #[default]
+    Human,
+    /// One JSON event per line on stdout.
+    JsonLines,
+}
+
+/// Turn the event stream on for the rest of the run.
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+#[derive(Serialize)]
+#[serde(tag = "reason", rename_all = "kebab-case")]
+pub enum Event<'a> {
+    BuildStarted   { board: &'a str, sketch: &'a Path },
+    Compiling      { unit: &'a str, file: &'a Path },
+    Fresh          { unit: &'a str, file: &'a Path, cache: &'a str },
+    Linking        { output: &'a Path },
+    Diagnostic     { level: &'a str, file: &'a str, line: u32, column: u32, message: &'a str },
+    Size           { flash: u64, ram: u64, flash_max: u64, ram_max: u64 },
+    BuildFinished  { success: bool, seconds: f64, artifacts: Vec<&'a Path> },
+    UploadStarted  { board: &'a str, port: &'a str, firmware: &'a Path },
+    UploadProgress { percent: u8, bytes: u64, total: u64 },
+    UploadFinished { success: bool, seconds: f64 },
+}
+
+/// Write `event` as one line on stdout, if the stream is on. Lines from
+/// parallel compiles never interleave.
+pub fn emit(event: Event) {
+    if !enabled() { return; }
+    if let Ok(line) = serde_json::to_string(&event) {
+        let mut out = std::io::stdout().lock();
+        let _ = writeln!(out, "{}", line);
+        let _ = out.flush();
+    }
+}
+
+/// A `diagnostic` event for every `file:line:col: level: message` line
+/// of compiler or linker output.
+pub fn emit_diagnostics(output: &str) {
+    if !enabled() { return; }
+    for line in output.lines() {
+        let Some((at, level, message)) = [("error", ": error: "), ("error", ": fatal error: "),
+                                          ("warning", ": warning: "), ("note", ": note: ")]
+            .iter()
+            .find_map(|(level, tag)| line.split_once(tag).map(|(at, msg)| (at, *level, msg)))
+        else { continue };
+        // Split from the right: a Windows path has a colon of its own.
+        let mut at = at.rsplitn(3, ':');
+        let (Some(c), Some(l), Some(file)) = (at.next(), at.next(), at.next()) else { continue };
+        let (Ok(l), Ok(c)) = (l.parse(), c.parse()) else { continue };
+        emit(Event::Diagnostic { level, file, line: l, column: c, message: message.trim() });
+    }
+}
+
+/// Upload progress, reported each time another whole percent is done.
+pub struct Progress {
+    total:   u64,
+    done:    u64,
+    percent: Option<u8>,
+}
+
+impl Progress {
+    pub fn new(total: u64) -> Self {
+        Progress { total, done: 0, percent: None }
+    }
+
+    pub fn advance(&mut self, bytes: u64) {
+        self.done = (self.done + bytes).min(self.total);
+        let percent = (self.done * 100).checked_div(self.total).unwrap_or(100) as u8;
+        if self.percent != Some(percent) {
+            self.percent = Some(percent);
+            emit(Event::UploadProgress { percent, bytes: self.done, total: self.total });
+        }
+    }
+}
@@ -4,17 +4,50 @@
 
 use std::path::Path;
 use std::process::Command;
+use std::time::Duration;
 use crate::boards::Board;
+use crate::detect;
 use crate::error::{FlashError, Result};
+use crate::process::{self, Stage};
+use crate::serial;
+use super::uf2::wait_for;
+
+/// How long a 32u4 board takes to show up again in its bootloader.
+const BOOTLOADER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Flash a .hex file to an AVR board using avrdude at the given baud rate.
+pub fn flash(hex: &Path, port: &str, board: &Board, baud: u32, verbose: bool) -> Result<()> {
+    let (mut cmd, port) = command(port, board, baud, verbose)?;
+    cmd.args(["-D", "-U", &format!("flash:w:{}:i", hex.display())]);
+    run(cmd, &port)
+}
+
+/// Read the whole flash through the board's bootloader.
+pub fn read(port: &str, board: &Board, baud: u32, verbose: bool) -> Result<Vec<u8>> {
+    let (mut cmd, port) = command(port, board, baud, verbose)?;
+    let out = super::read::scratch("avrdude");
+    cmd.args(["-U", &format!("flash:r:{}:r", out.display())]);
+    run(cmd, &port)?;
+    super::read::take(&out)
+}
 
-/// Flash a .hex file to an AVR board using avrdude.
-pub fn flash(hex: &Path, port: &str, board: &Board, verbose: bool) -> Result<()> {
-    let (programmer, baud) = board.avrdude_programmer()
+/// avrdude set up to talk to the board's bootloader, and the port it is
+/// on; the caller adds the `-U` operations.
+fn command(port: &str, board: &Board, baud: u32, verbose: bool) -> Result<(Command, String)> {
+    let (programmer, _) = board.avrdude_programmer()
         .ok_or_else(|| FlashError::Other("Not an AVR board".into()))?;
 
     let mcu = board.avr_mcu()
         .ok_or_else(|| FlashError::Other("Missing MCU for AVR board".into()))?;
 
+    // avr109 (Caterina) boards run the sketch on native USB; the bootloader
+    // has to be reset into, and it enumerates as a port of its own.
+    let port = if programmer == "avr109" {
+        reset_to_bootloader(port, verbose)?
+    } else {
+        port.to_owned()
+    };
+
     // Locate avrdude — prefer the one bundled with the Arduino SDK
     let avrdude = find_avrdude();
 
@@ -23,10 +56,8 @@ pub fn flash(hex: &Path, port: &str, board: &Board, verbose: bool) -> Result<()>
         "-C", &avrdude_conf(&avrdude),
         "-p", mcu,
         "-c", programmer,
-        "-P", port,
+        "-P", &port,
         "-b", &baud.to_string(),
-        "-D",
-        "-U", &format!("flash:w:{}:i", hex.display()),
     ]);
 
     if verbose {
@@ -36,7 +67,11 @@ pub fn flash(hex: &Path, port: &str, board: &Board, verbose: bool) -> Result<()>
         cmd.args(["-q", "-q"]);
     }
 
-    let out = cmd.output()?;
+    Ok((cmd, port))
+}
+
+pub(super) fn run(mut cmd: Command, port: &str) -> Result<()> {
+    let out = process::output(&mut cmd, Stage::Upload)?;
 
     if !out.status.success() {
         let stderr = String::from_utf8_lossy(&out.stderr).to_string();
@@ -51,20 +86,20 @@ pub fn flash(hex: &Path, port: &str, board: &Board, verbose: bool) -> Result<()>
 }
 
 /// Verify flash by reading back and comparing (optional sanity check).
+#[allow(dead_code)]
 pub fn verify(hex: &Path, port: &str, board: &Board) -> Result<()> {
     let (programmer, baud) = board.avrdude_programmer().unwrap();
     let mcu = board.avr_mcu().unwrap();
     let avrdude = find_avrdude();
 
-    let out = Command::new(&avrdude)
+    let out = process::output(Command::new(&avrdude)
         .args([
             "-C", &avrdude_conf(&avrdude),
             "-p", mcu, "-c", programmer,
             "-P", port, "-b", &baud.to_string(),
             "-U", &format!("flash:v:{}:i", hex.display()),
             "-q", "-q",
-        ])
-        .output()?;
+        ]), Stage::Upload)?;
 
     if !out.status.success() {
         return Err(FlashError::FlashFailed {
@@ -80,7 +115,48 @@ pub fn verify(hex: &Path, port: &str, board: &Board) -> Result<()> {
 //  Helpers
 // ─────────────────────────────────────────────────────────────────────────────
 
-fn find_avrdude() -> String {
+/// Touch `port` at 1200 baud and return the port the bootloader comes up
+/// on: one that wasn't there before, or `port` itself once it has gone away
+/// and come back (Linux often reuses the name).
+fn reset_to_bootloader(port: &str, verbose: bool) -> Result<String> {
+    let ports = || detect::detect_all().into_iter().map(|p| p.port).collect::<Vec<_>>();
+    let before = ports();
+
+    if verbose { eprintln!("  [avr109] 1200-baud touch on {}", port); }
+    // A board already sitting in its bootloader has nothing to reset, and
+    // may not answer the touch.
+    if let Err(e) = serial::touch_1200(port) {
+        if verbose { eprintln!("  [avr109] touch failed ({}), trying {} as is", e, port); }
+        return Ok(port.to_owned());
+    }
+
+    let mut gone = false;
+    let found = wait_for(BOOTLOADER_TIMEOUT, || {
+        let now = ports();
+        if let Some(new) = now.iter().find(|p| !before.contains(p)) {
+            return Some(new.clone());
+        }
+        let present = now.iter().any(|p| p == port);
+        gone |= !present;
+        (gone && present).then(|| port.to_owned())
+    });
+
+    match found {
+        Some(p) => {
+            if verbose { eprintln!("  [avr109] bootloader on {}", p); }
+            Ok(p)
+        }
+        // Never saw it drop off: some systems keep the port across the reset.
+        None if !gone => Ok(port.to_owned()),
+        None => Err(FlashError::FlashFailed {
+            port:   port.to_owned(),
+            output: "the board did not come back in its bootloader after the 1200-baud reset\n\
+                     Hint: double-tap the reset button, then upload again".into(),
+        }),
+    }
+}
+
+pub fn find_avrdude() -> String {
     // 1. Arduino CLI cache location
     let home = std::env::var("HOME").unwrap_or_default();
     let candidates = [
@@ -105,7 +181,7 @@ fn find_avrdude() -> String {
     "avrdude".to_owned() // rely on PATH
 }
 
-fn avrdude_conf(avrdude_bin: &str) -> String {
+pub(super) fn avrdude_conf(avrdude_bin: &str) -> String {
     // Try to find avrdude.conf next to the binary
     let bin_path = std::path::Path::new(avrdude_bin);
     if let Some(parent) = bin_path.parent() {
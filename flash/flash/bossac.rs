@@ -0,0 +1,82 @@
+// ─────────────────────────────────────────────────────────────────────────────
+//  tsuki-flash :: flash :: bossac  —  SAM (Arduino Due) programmer
+//
+//  The Due's programming port only lets the SAM-BA boot ROM in once the
+//  flash has been erased: a 1200-baud touch makes its USB bridge assert
+//  ERASE and reset the chip, after which bossac writes the .bin.
+// ─────────────────────────────────────────────────────────────────────────────
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+use crate::error::{FlashError, Result};
+use crate::process::{self, Stage};
+use crate::modules;
+use crate::serial;
+
+/// How long the erase and reset into SAM-BA take after the touch.
+const ERASE_DELAY: Duration = Duration::from_millis(500);
+
+pub fn flash(bin: &Path, port: &str, verbose: bool) -> Result<()> {
+    let bossac = find_bossac().ok_or_else(|| FlashError::ToolchainNotFound(
+        format!("bossac not found — install the SAM core with: {}", crate::doctor::install_core("sam"))
+    ))?;
+
+    if verbose { eprintln!("  [bossac] 1200-baud erase on {}", port); }
+    serial::touch_1200(port)?;
+    std::thread::sleep(ERASE_DELAY);
+
+    let mut cmd = Command::new(&bossac);
+    if verbose {
+        cmd.args(["-i", "-d"]);
+    }
+    cmd.arg(format!("--port={}", port_arg(port)))
+        .args(["-U", "false", "-e", "-w", "-v", "-b"])
+        .arg(bin)
+        .arg("-R");
+
+    let out = process::output(&mut cmd, Stage::Upload)?;
+
+    if !out.status.success() {
+        let stderr = String::from_utf8_lossy(&out.stderr).to_string();
+        let stdout = String::from_utf8_lossy(&out.stdout).to_string();
+        return Err(FlashError::FlashFailed {
+            port:   port.to_owned(),
+            output: format!("{}\n{}", stderr, stdout).trim().to_owned(),
+        });
+    }
+    if verbose {
+        eprint!("{}", String::from_utf8_lossy(&out.stdout));
+    }
+
+    Ok(())
+}
+
+/// The Arduino build of bossac wants the device name (`ttyACM0`), not its
+/// path, and looks it up under /dev itself.
+fn port_arg(port: &str) -> &str {
+    if cfg!(windows) { port } else { port.rsplit('/').next().unwrap_or(port) }
+}
+
+/// bossac from the SAM core's tools (tsuki-modules store, then .arduino15),
+/// else from PATH.
+pub fn find_bossac() -> Option<PathBuf> {
+    let exe = if cfg!(windows) { "bossac.exe" } else { "bossac" };
+    let home = std::env::var("HOME").or_else(|_| std::env::var("USERPROFILE")).unwrap_or_default();
+    let bases = [modules::modules_root().ok(), Some(Path::new(&home).join(".arduino15"))];
+
+    for base in bases.into_iter().flatten() {
+        let tools = base.join("packages/arduino/tools/bossac");
+        let mut versions: Vec<PathBuf> = std::fs::read_dir(&tools).into_iter().flatten().flatten()
+            .map(|e| e.path())
+            .filter(|p| p.join(exe).is_file())
+            .collect();
+        versions.sort();
+        if let Some(dir) = versions.pop() {
+            return Some(dir.join(exe));
+        }
+    }
+
+    process::output(Command::new(exe).arg("--help"), Stage::Upload).ok().map(|_| PathBuf::from(exe))
+}
@@ -1,32 +1,529 @@
 // ─────────────────────────────────────────────────────────────────────────────
 //  tsuki-flash :: flash :: esptool  —  ESP32 / ESP8266 programmer
+//
+//  Talks to the chip's ROM serial bootloader directly, so no Python is
+//  needed: reset into it over DTR/RTS, SLIP-framed commands, deflate-
+//  compressed writes checked by MD5 (ESP32 family; the ESP8266 ROM only
+//  takes plain writes at 115200, read back on --verify). A chip this doesn't
+//  know is handed to esptool.py, if installed.
 // ─────────────────────────────────────────────────────────────────────────────
 
-use std::path::Path;
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::time::{Duration, Instant};
+
+use flate2::{write::ZlibEncoder, Compression};
+use md5::{Digest, Md5};
 use crate::boards::{Board, Toolchain};
+use crate::compile::partitions::{PartitionTable, TABLE_OFFSET};
 use crate::error::{FlashError, Result};
+use crate::process::{self, Stage};
+use crate::events::Progress;
+use crate::serial::Serial;
 
-pub fn flash(firmware: &Path, port: &str, board: &Board, baud: u32, verbose: bool) -> Result<()> {
-    let esptool = find_esptool()
-        .ok_or_else(|| FlashError::ToolchainNotFound(
-            "esptool not found — install with: pip install esptool".into()
-        ))?;
+pub fn flash(firmware: &Path, port: &str, board: &Board, baud: u32, verify: bool, verbose: bool) -> Result<()> {
+    let variant = variant(board)?;
+    let segments = segments(firmware, board)?;
 
-    let chip = match &board.toolchain {
-        Toolchain::Esp32 { variant } => variant.as_ref(),
-        Toolchain::Esp8266           => "esp8266",
-        _ => return Err(FlashError::Other("Not an ESP board".into())),
+    let mut loader = Loader::connect(port, verbose)?;
+    let magic = loader.read_reg(CHIP_DETECT_MAGIC_REG)?;
+    let Some(chip) = Chip::from_magic(magic) else {
+        drop(loader);
+        return esptool_py(&segments, port, variant, board.flash_mode.unwrap_or("dio"), baud, verify, verbose,
+            &format!("unknown ESP chip (magic {:#010x})", magic));
     };
+    if chip.name() != variant {
+        return Err(loader.fail(format!(
+            "the chip on {} is an {}, but the firmware is for {}", port, chip.name(), variant)));
+    }
+    loader.chip = chip;
+    if verbose { eprintln!("  [esptool] {} on {}", chip.name(), port); }
+
+    loader.attach(board, baud)?;
+
+    let images = segments.iter()
+        .map(|(offset, path)| Ok((*offset, path, std::fs::read(path)?)))
+        .collect::<Result<Vec<_>>>()?;
+    let mut progress = Progress::new(images.iter().map(|(_, _, image)| image.len() as u64).sum());
+    for (offset, path, image) in images {
+        if verbose { eprintln!("  [esptool] {} bytes at {:#x} ({})", image.len(), offset, path.display()); }
+        loader.write_flash(offset, image, &mut progress, verify)?;
+    }
+
+    // Not FLASH_END: from the ROM loader that starts the app without a
+    // clean reset. Pulse EN instead.
+    loader.hard_reset();
+    Ok(())
+}
+
+/// Read the whole flash: through esptool.py's stub when it is installed,
+/// else through the ROM loader, which is slow (64 bytes per command) and
+/// can't on the ESP32-S2 and -C3.
+pub fn read(port: &str, board: &Board, baud: u32, verbose: bool) -> Result<Vec<u8>> {
+    let variant = variant(board)?;
+    let size = board.flash_kb * 1024;
+
+    if let Some(esptool) = find_esptool() {
+        let out = super::read::scratch("esptool");
+        let res = process::output(Command::new(&esptool)
+            .args([
+                "--chip", variant,
+                "--port", port,
+                "--baud", &baud.to_string(),
+                "--before", "default_reset",
+                "--after",  "hard_reset",
+                "read_flash", "0", &size.to_string(),
+            ])
+            .arg(&out), Stage::Upload)?;
+        if !res.status.success() {
+            return Err(FlashError::FlashFailed {
+                port: port.to_owned(),
+                output: String::from_utf8_lossy(&res.stderr).to_string(),
+            });
+        }
+        return super::read::take(&out);
+    }
+
+    let mut loader = Loader::connect(port, verbose)?;
+    let magic = loader.read_reg(CHIP_DETECT_MAGIC_REG)?;
+    let chip = Chip::from_magic(magic)
+        .ok_or_else(|| loader.fail(format!("unknown ESP chip (magic {:#010x})", magic)))?;
+    if chip.name() != variant {
+        return Err(loader.fail(format!("the chip on {} is an {}, not an {}", port, chip.name(), variant)));
+    }
+    if !matches!(chip, Chip::Esp8266 | Chip::Esp32) {
+        return Err(FlashError::ToolchainNotFound(format!(
+            "the {} ROM can't read its flash: that needs esptool — install with: {}",
+            chip.name(), crate::doctor::INSTALL_ESPTOOL)));
+    }
+    loader.chip = chip;
+    loader.attach(board, baud)?;
+    if verbose { eprintln!("  [esptool] reading {} KB through the ROM loader (esptool is faster)", board.flash_kb); }
+
+    let data = loader.read_flash_slow(0, size as usize, Some(&mut Progress::new(size as u64)))?;
+    loader.hard_reset();
+    Ok(data)
+}
+
+fn variant(board: &Board) -> Result<&'static str> {
+    match &board.toolchain {
+        Toolchain::Esp32 { variant } => Ok(variant),
+        Toolchain::Esp8266           => Ok("esp8266"),
+        _ => Err(FlashError::Other("Not an ESP board".into())),
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+//  ROM loader protocol
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// The ROM loader always starts at this rate.
+const ROM_BAUD: u32 = 115_200;
+/// Data block size the ROM loader accepts.
+const BLOCK: usize = 0x400;
+/// Most the ROM loader reads per READ_FLASH_SLOW.
+const READ_BLOCK: usize = 64;
+const SECTOR: u32 = 0x1000;
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(3);
+/// Holds a value that tells the chips apart.
+const CHIP_DETECT_MAGIC_REG: u32 = 0x4000_1000;
+
+const FLASH_BEGIN:      u8 = 0x02;
+const FLASH_DATA:       u8 = 0x03;
+const SYNC:             u8 = 0x08;
+const READ_REG:         u8 = 0x0A;
+const SPI_SET_PARAMS:   u8 = 0x0B;
+const SPI_ATTACH:       u8 = 0x0D;
+const READ_FLASH_SLOW:  u8 = 0x0E;
+const CHANGE_BAUDRATE:  u8 = 0x0F;
+const FLASH_DEFL_BEGIN: u8 = 0x10;
+const FLASH_DEFL_DATA:  u8 = 0x11;
+const SPI_FLASH_MD5:    u8 = 0x13;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Chip {
+    Esp8266,
+    Esp32,
+    Esp32S2,
+    Esp32C3,
+}
+
+impl Chip {
+    fn from_magic(magic: u32) -> Option<Chip> {
+        match magic {
+            0xfff0_c101 => Some(Chip::Esp8266),
+            0x00f0_1d83 => Some(Chip::Esp32),
+            0x0000_07c6 => Some(Chip::Esp32S2),
+            0x6921_506f | 0x1b31_506f | 0x4881_606f | 0x4361_606f => Some(Chip::Esp32C3),
+            _ => None,
+        }
+    }
+
+    /// As in the board's toolchain variant.
+    fn name(self) -> &'static str {
+        match self {
+            Chip::Esp8266 => "esp8266",
+            Chip::Esp32   => "esp32",
+            Chip::Esp32S2 => "esp32s2",
+            Chip::Esp32C3 => "esp32c3",
+        }
+    }
+
+    /// Status bytes at the end of every response.
+    fn status_len(self) -> usize {
+        if self == Chip::Esp8266 { 2 } else { 4 }
+    }
+
+    /// Later ROMs take an extra "encrypted" word in FLASH_BEGIN.
+    fn begin_takes_encrypted(self) -> bool {
+        matches!(self, Chip::Esp32S2 | Chip::Esp32C3)
+    }
+}
+
+struct Loader {
+    port:    Serial,
+    chip:    Chip,
+    pending: VecDeque<u8>,
+    verbose: bool,
+}
+
+impl Loader {
+    /// Open `port`, reset the chip into its loader and sync with it.
+    fn connect(port: &str, verbose: bool) -> Result<Loader> {
+        let mut serial = Serial::open(port, ROM_BAUD)?;
+        serial.set_timeout(Duration::from_millis(50));
+        let mut loader = Loader {
+            port: serial,
+            chip: Chip::Esp32,
+            pending: VecDeque::new(),
+            verbose,
+        };
+
+        for attempt in 1..=5 {
+            loader.reset_into_loader();
+            if loader.sync() {
+                return Ok(loader);
+            }
+            if verbose { eprintln!("  [esptool] no answer from the loader (attempt {})", attempt); }
+        }
+        Err(loader.fail("could not connect to the ESP bootloader\n\
+                         Hint: hold BOOT (IO0) and tap EN/RESET, then upload again".into()))
+    }
+
+    fn fail(&self, output: String) -> FlashError {
+        FlashError::FlashFailed { port: self.port.name().to_owned(), output }
+    }
+
+    /// The usual auto-reset circuit: DTR drives IO0 and RTS drives EN, both
+    /// inverted. Hold EN low, let it go with IO0 low, so the ROM waits for
+    /// the loader instead of booting.
+    fn reset_into_loader(&mut self) {
+        self.port.set_dtr(false);
+        self.port.set_rts(true);
+        std::thread::sleep(Duration::from_millis(100));
+        self.port.set_dtr(true);
+        self.port.set_rts(false);
+        std::thread::sleep(Duration::from_millis(50));
+        self.port.set_dtr(false);
+        self.drain();
+    }
+
+    fn hard_reset(&mut self) {
+        self.port.set_rts(true);
+        std::thread::sleep(Duration::from_millis(100));
+        self.port.set_rts(false);
+    }
+
+    /// Drop whatever is waiting, e.g. the ROM's boot message.
+    fn drain(&mut self) {
+        self.pending.clear();
+        self.port.clear_input();
+    }
+
+    fn sync(&mut self) -> bool {
+        let mut data = vec![0x07, 0x07, 0x12, 0x20];
+        data.extend([0x55; 32]);
+        for _ in 0..5 {
+            if self.command(SYNC, &data, 0, Duration::from_millis(100)).is_ok() {
+                // It answers a sync several times over; let those through.
+                std::thread::sleep(Duration::from_millis(50));
+                self.drain();
+                return true;
+            }
+        }
+        false
+    }
+
+    fn read_reg(&mut self, addr: u32) -> Result<u32> {
+        self.command(READ_REG, &addr.to_le_bytes(), 0, DEFAULT_TIMEOUT).map(|(value, _)| value)
+    }
 
-    // Determine file format and flash offset
-    let (write_cmd, offset) = if firmware.extension()
-        .and_then(|e| e.to_str()) == Some("bin")
-    {
-        ("write_flash", "0x1000")
+    /// Set up the SPI flash and move to `baud`; the ESP8266 ROM needs
+    /// neither, and stays at 115200.
+    fn attach(&mut self, board: &Board, baud: u32) -> Result<()> {
+        if self.chip == Chip::Esp8266 {
+            return Ok(());
+        }
+        self.check(SPI_ATTACH, &[0; 8], 0, DEFAULT_TIMEOUT)?;
+        let params = words(&[0, board.flash_kb * 1024, 0x10000, SECTOR, 0x100, 0xFFFF]);
+        self.check(SPI_SET_PARAMS, &params, 0, DEFAULT_TIMEOUT)?;
+        if baud != ROM_BAUD {
+            self.change_baud(baud)?;
+        }
+        Ok(())
+    }
+
+    fn change_baud(&mut self, baud: u32) -> Result<()> {
+        // The second word is the current rate; 0 tells the ROM to assume 115200.
+        self.check(CHANGE_BAUDRATE, &words(&[baud, 0]), 0, DEFAULT_TIMEOUT)?;
+        self.port.set_baud(baud)?;
+        std::thread::sleep(Duration::from_millis(50));
+        self.drain();
+        Ok(())
+    }
+
+    /// Erase and write `image` at `offset`, then have the chip hash what it
+    /// wrote. The ESP8266 ROM can't, so there it is read back on `verify`.
+    fn write_flash(&mut self, offset: u32, mut image: Vec<u8>, progress: &mut Progress, verify: bool) -> Result<()> {
+        let size = image.len();
+        let padded = image.len().next_multiple_of(4);
+        image.resize(padded, 0xFF);
+        let erase_timeout = DEFAULT_TIMEOUT.max(Duration::from_secs(30 * image.len() as u64 / 0x10_0000));
+
+        if self.chip == Chip::Esp8266 {
+            let blocks = image.len().div_ceil(BLOCK);
+            let erase = esp8266_erase_size(offset, image.len() as u32);
+            self.check(FLASH_BEGIN, &words(&[erase, blocks as u32, BLOCK as u32, offset]), 0, erase_timeout)?;
+            for (seq, chunk) in image.chunks(BLOCK).enumerate() {
+                let mut block = chunk.to_vec();
+                block.resize(BLOCK, 0xFF);
+                self.send_block(FLASH_DATA, seq, &block)?;
+                progress.advance(chunk.len() as u64);
+            }
+            if verify {
+                let read = self.read_flash_slow(offset, size, None)?;
+                if let Some(at) = super::read::compare(offset, &image[..size], &read) {
+                    return Err(self.fail(format!("verification failed at {:#x}", at)));
+                }
+                if self.verbose { eprintln!("  [esptool] {} bytes read back and verified", size); }
+            }
+            return Ok(());
+        }
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(&image)?;
+        let compressed = encoder.finish()?;
+
+        let blocks = compressed.len().div_ceil(BLOCK);
+        let params = defl_begin(self.chip, offset, image.len(), compressed.len());
+        self.check(FLASH_DEFL_BEGIN, &params, 0, erase_timeout)?;
+        for (seq, block) in compressed.chunks(BLOCK).enumerate() {
+            self.send_block(FLASH_DEFL_DATA, seq, block)?;
+            // Count the image bytes this block inflates to, roughly.
+            progress.advance((size * (seq + 1) / blocks - size * seq / blocks) as u64);
+        }
+
+        let md5_timeout = DEFAULT_TIMEOUT.max(Duration::from_secs(8 * image.len() as u64 / 0x10_0000));
+        let (_, body) = self.check(SPI_FLASH_MD5, &words(&[offset, image.len() as u32, 0, 0]), 0, md5_timeout)?;
+        // The ROM answers in hex text.
+        let got = String::from_utf8_lossy(body.get(..32).unwrap_or_default()).to_lowercase();
+        let want = hex::encode(Md5::digest(&image));
+        if got != want {
+            return Err(self.fail(format!(
+                "verification failed at {:#x}: flash MD5 {} ≠ image MD5 {}", offset, got, want)));
+        }
+        if self.verbose { eprintln!("  [esptool] {} blocks written, MD5 {} verified", blocks, got); }
+        Ok(())
+    }
+
+    /// Read `len` bytes at `offset`, a READ_BLOCK at a time.
+    fn read_flash_slow(&mut self, offset: u32, len: usize, mut progress: Option<&mut Progress>) -> Result<Vec<u8>> {
+        let mut data = Vec::with_capacity(len);
+        while data.len() < len {
+            let n = READ_BLOCK.min(len - data.len());
+            let at = offset + data.len() as u32;
+            let (_, body) = self.check(READ_FLASH_SLOW, &words(&[at, n as u32]), 0, DEFAULT_TIMEOUT)?;
+            // The answer is a whole block whatever was asked for.
+            let block = body.get(..n).ok_or_else(|| self.fail(format!("short read at {:#x}", at)))?;
+            data.extend_from_slice(block);
+            if let Some(progress) = progress.as_deref_mut() {
+                progress.advance(n as u64);
+            }
+        }
+        Ok(data)
+    }
+
+    fn send_block(&mut self, op: u8, seq: usize, block: &[u8]) -> Result<()> {
+        let mut data = words(&[block.len() as u32, seq as u32, 0, 0]);
+        data.extend_from_slice(block);
+        self.check(op, &data, checksum(block), DEFAULT_TIMEOUT).map(drop)
+    }
+
+    /// `command`, failing on an error status.
+    fn check(&mut self, op: u8, data: &[u8], checksum: u32, timeout: Duration) -> Result<(u32, Vec<u8>)> {
+        let (value, mut body) = self.command(op, data, checksum, timeout)?;
+        match strip_status(&mut body, self.chip.status_len()) {
+            Ok(()) => Ok((value, body)),
+            Err(None) => Err(self.fail(format!("short response to command {:#04x}", op))),
+            Err(Some(code)) => Err(self.fail(format!("command {:#04x} failed: ROM error {:#04x}", op, code))),
+        }
+    }
+
+    /// Send a command and wait for its response: (value, data).
+    fn command(&mut self, op: u8, data: &[u8], checksum: u32, timeout: Duration) -> Result<(u32, Vec<u8>)> {
+        self.port.write_all(&slip(&request(op, data, checksum)))?;
+
+        let deadline = Instant::now() + timeout;
+        loop {
+            let frame = self.read_frame(deadline)?.ok_or_else(|| self.fail(format!(
+                "timed out waiting for the answer to command {:#04x}", op)))?;
+            if let Some((value, body)) = response(&frame, op) {
+                return Ok((value, body.to_vec()));
+            }
+        }
+    }
+
+    fn read_frame(&mut self, deadline: Instant) -> Result<Option<Vec<u8>>> {
+        let mut unslip = Unslip::default();
+        while let Some(b) = self.read_byte(deadline)? {
+            if let Some(frame) = unslip.push(b) {
+                return Ok(Some(frame));
+            }
+        }
+        Ok(None)
+    }
+
+    fn read_byte(&mut self, deadline: Instant) -> Result<Option<u8>> {
+        while self.pending.is_empty() {
+            if Instant::now() >= deadline {
+                return Ok(None);
+            }
+            let mut chunk = [0u8; 512];
+            match self.port.read(&mut chunk) {
+                Ok(n) => self.pending.extend(&chunk[..n]),
+                Err(e) if e.kind() == io::ErrorKind::TimedOut => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(self.pending.pop_front())
+    }
+}
+
+/// A command packet: direction 0, opcode, data length, checksum, data.
+fn request(op: u8, data: &[u8], checksum: u32) -> Vec<u8> {
+    let mut packet = vec![0x00, op];
+    packet.extend((data.len() as u16).to_le_bytes());
+    packet.extend(checksum.to_le_bytes());
+    packet.extend_from_slice(data);
+    packet
+}
+
+/// The (value, data) of a response to `op`; None for anything else.
+fn response(frame: &[u8], op: u8) -> Option<(u32, &[u8])> {
+    if frame.len() < 8 || frame[0] != 0x01 || frame[1] != op {
+        return None;
+    }
+    Some((u32::from_le_bytes([frame[4], frame[5], frame[6], frame[7]]), &frame[8..]))
+}
+
+/// Take the `len` status bytes off the end of a response's data. Err(None)
+/// when they aren't there, Err(Some(code)) when they report an error.
+fn strip_status(body: &mut Vec<u8>, len: usize) -> std::result::Result<(), Option<u8>> {
+    let at = body.len().checked_sub(len).ok_or(None)?;
+    if body[at] != 0 {
+        return Err(Some(body[at + 1]));
+    }
+    body.truncate(at);
+    Ok(())
+}
+
+/// SLIP: frames between 0xC0 bytes, with 0xC0 and 0xDB escaped.
+fn slip(packet: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(packet.len() + 16);
+    out.push(0xC0);
+    for &b in packet {
+        match b {
+            0xC0 => out.extend([0xDB, 0xDC]),
+            0xDB => out.extend([0xDB, 0xDD]),
+            b    => out.push(b),
+        }
+    }
+    out.push(0xC0);
+    out
+}
+
+/// Undoes `slip` a byte at a time; what comes before the first 0xC0 is
+/// noise, e.g. the ROM's boot message.
+#[derive(Default)]
+struct Unslip {
+    frame:   Vec<u8>,
+    started: bool,
+    escaped: bool,
+}
+
+impl Unslip {
+    /// The frame `b` completes, if any.
+    fn push(&mut self, b: u8) -> Option<Vec<u8>> {
+        if !self.started {
+            self.started = b == 0xC0;
+        } else if self.escaped {
+            self.frame.push(match b { 0xDC => 0xC0, 0xDD => 0xDB, b => b });
+            self.escaped = false;
+        } else {
+            match b {
+                0xC0 if self.frame.is_empty() => {}
+                0xC0 => return Some(std::mem::take(&mut self.frame)),
+                0xDB => self.escaped = true,
+                b    => self.frame.push(b),
+            }
+        }
+        None
+    }
+}
+
+/// FLASH_DEFL_BEGIN's parameters. The ROM erases whole blocks of the
+/// uncompressed size up front, and counts blocks of the compressed data.
+fn defl_begin(chip: Chip, offset: u32, image_len: usize, compressed_len: usize) -> Vec<u8> {
+    let erase = image_len.div_ceil(BLOCK) * BLOCK;
+    let blocks = compressed_len.div_ceil(BLOCK);
+    let mut params = words(&[erase as u32, blocks as u32, BLOCK as u32, offset]);
+    if chip.begin_takes_encrypted() {
+        params.extend(0u32.to_le_bytes());
+    }
+    params
+}
+
+fn words(values: &[u32]) -> Vec<u8> {
+    values.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+/// The loader's checksum over a data block.
+fn checksum(data: &[u8]) -> u32 {
+    data.iter().fold(0xEF, |acc, &b| acc ^ b) as u32
+}
+
+/// The ESP8266 ROM erases more than asked for when a region starts
+/// mid-block; ask for less so it lands on the right size (as esptool does).
+fn esp8266_erase_size(offset: u32, size: u32) -> u32 {
+    const SECTORS_PER_BLOCK: u32 = 16;
+    let sectors = size.div_ceil(SECTOR);
+    let start = offset / SECTOR;
+    let head = (SECTORS_PER_BLOCK - start % SECTORS_PER_BLOCK).min(sectors);
+    if sectors < 2 * head {
+        sectors.div_ceil(2) * SECTOR
     } else {
-        ("write_flash", "0x0000")
-    };
+        (sectors - head) * SECTOR
+    }
+}
+
+/// Flash with esptool.py, for what the built-in loader doesn't handle.
+#[allow(clippy::too_many_arguments)]
+fn esptool_py(segments: &[(u32, PathBuf)], port: &str, chip: &str, flash_mode: &str, baud: u32, verify: bool, verbose: bool, why: &str) -> Result<()> {
+    let esptool = find_esptool().ok_or_else(|| FlashError::ToolchainNotFound(format!(
+        "{}: flashing it needs esptool — install with: {}", why, crate::doctor::INSTALL_ESPTOOL)))?;
+    if verbose { eprintln!("  [esptool] {}; handing over to {}", why, esptool); }
 
     let mut cmd = Command::new(&esptool);
     cmd.args([
@@ -35,20 +532,24 @@ pub fn flash(firmware: &Path, port: &str, board: &Board, baud: u32, verbose: boo
         "--baud", &baud.to_string(),
         "--before", "default_reset",
         "--after",  "hard_reset",
-        write_cmd,
+        "write_flash",
         "-z",
-        "--flash_mode", "dio",
+        "--flash_mode", flash_mode,
         "--flash_freq", "80m",
         "--flash_size", "detect",
-        offset,
-        firmware.to_str().unwrap(),
     ]);
+    if verify {
+        cmd.arg("--verify");
+    }
+    for (offset, path) in segments {
+        cmd.arg(format!("{:#x}", offset)).arg(path);
+    }
 
     if verbose {
         cmd.arg("--trace");
     }
 
-    let out = cmd.output()?;
+    let out = process::output(&mut cmd, Stage::Upload)?;
 
     if !out.status.success() {
         return Err(FlashError::FlashFailed {
@@ -60,13 +561,164 @@ pub fn flash(firmware: &Path, port: &str, board: &Board, baud: u32, verbose: boo
     Ok(())
 }
 
-fn find_esptool() -> Option<String> {
+/// (offset, file) pairs to write. An ESP8266 image is one file at 0; an
+/// ESP32 app goes with the bootloader, partition table and boot_app0 the
+/// compiler left beside it, at the offsets its partition table gives.
+fn segments(firmware: &Path, board: &Board) -> Result<Vec<(u32, PathBuf)>> {
+    let Toolchain::Esp32 { variant } = &board.toolchain else {
+        return Ok(vec![(0x0, firmware.to_owned())]);
+    };
+
+    let table_bin = firmware.with_extension("partitions.bin");
+    let Ok(data) = std::fs::read(&table_bin) else {
+        // Built without the other parts: the app alone, where the default
+        // partition table puts it.
+        return Ok(vec![(0x10000, firmware.to_owned())]);
+    };
+    let table = PartitionTable::from_bin(&data);
+    let app = table.app_offset().ok_or_else(|| FlashError::Other(
+        format!("{} has no app partition", table_bin.display())))?;
+
+    let mut segments = Vec::new();
+    let bootloader = firmware.with_extension("bootloader.bin");
+    if bootloader.is_file() {
+        // The ROM loads the bootloader from 0x1000 on the original ESP32 and
+        // the S2, from 0 on later chips.
+        let offset = if matches!(*variant, "esp32" | "esp32s2") { 0x1000 } else { 0x0 };
+        segments.push((offset, bootloader));
+    }
+    segments.push((TABLE_OFFSET, table_bin));
+    let boot_app0 = firmware.with_extension("boot_app0.bin");
+    if let (Some(offset), true) = (table.otadata_offset(), boot_app0.is_file()) {
+        segments.push((offset, boot_app0));
+    }
+    segments.push((app, firmware.to_owned()));
+    Ok(segments)
+}
+
+pub fn find_esptool() -> Option<String> {
     for candidate in &["esptool.py", "esptool"] {
-        if Command::new(candidate).arg("version").output()
+        if process::output(Command::new(candidate).arg("version"), Stage::Upload)
             .map(|o| o.status.success()).unwrap_or(false)
         {
             return Some(candidate.to_string());
         }
     }
     None
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sync_frame() {
+        let mut data = vec![0x07, 0x07, 0x12, 0x20];
+        data.extend([0x55; 32]);
+        let frame = slip(&request(SYNC, &data, 0));
+        assert_eq!(frame.len(), 46);
+        assert_eq!(frame[..14], [0xC0, 0x00, 0x08, 0x24, 0x00, 0x00, 0x00, 0x00, 0x00, 0x07, 0x07, 0x12, 0x20, 0x55]);
+        assert_eq!(frame[45], 0xC0);
+    }
+
+    #[test]
+    fn test_data_block_frame() {
+        // A FLASH_DEFL_DATA block as esptool.py frames it, with both
+        // special bytes escaped.
+        let block = [0xC0, 0xDB, 0x12, 0x34];
+        assert_eq!(checksum(&block), 0xD2);
+        let mut data = words(&[block.len() as u32, 3, 0, 0]);
+        data.extend(block);
+        assert_eq!(slip(&request(FLASH_DEFL_DATA, &data, checksum(&block))), [
+            0xC0, 0x00, 0x11, 0x14, 0x00, 0xD2, 0x00, 0x00, 0x00,
+            0x04, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0xDB, 0xDC, 0xDB, 0xDD, 0x12, 0x34, 0xC0,
+        ]);
+    }
+
+    #[test]
+    fn test_checksum() {
+        assert_eq!(checksum(&[]), 0xEF);
+        assert_eq!(checksum(&[0xFF; 4]), 0xEF);
+        assert_eq!(checksum(&[0xEF]), 0x00);
+        assert_eq!(checksum(&[0x01, 0x02, 0x04]), 0xE8);
+    }
+
+    #[test]
+    fn test_unslip() {
+        let mut unslip = Unslip::default();
+        // Boot noise, an empty frame and then the escaped bytes.
+        let wire = [b'e', b't', 0xC0, 0xC0, 0x01, 0xDB, 0xDC, 0xDB, 0xDD, 0xDB, 0x42, 0x02, 0xC0];
+        let frames: Vec<_> = wire.iter().filter_map(|&b| unslip.push(b)).collect();
+        assert_eq!(frames, [vec![0x01, 0xC0, 0xDB, 0x42, 0x02]]);
+
+        let packet: Vec<u8> = (0..=255).collect();
+        let mut unslip = Unslip::default();
+        assert_eq!(slip(&packet).into_iter().find_map(|b| unslip.push(b)), Some(packet));
+    }
+
+    #[test]
+    fn test_read_reg_responses() {
+        // ESP32: the chip magic and four status bytes.
+        let esp32 = [0x01, READ_REG, 0x04, 0x00, 0x83, 0x1D, 0xF0, 0x00, 0x00, 0x00, 0x00, 0x00];
+        let (value, body) = response(&esp32, READ_REG).unwrap();
+        let chip = Chip::from_magic(value).unwrap();
+        assert_eq!(chip, Chip::Esp32);
+        assert_eq!(chip.status_len(), 4);
+        let mut body = body.to_vec();
+        assert_eq!(strip_status(&mut body, chip.status_len()), Ok(()));
+        assert!(body.is_empty());
+
+        // ESP8266: two status bytes.
+        let esp8266 = [0x01, READ_REG, 0x02, 0x00, 0x01, 0xC1, 0xF0, 0xFF, 0x00, 0x00];
+        let (value, body) = response(&esp8266, READ_REG).unwrap();
+        let chip = Chip::from_magic(value).unwrap();
+        assert_eq!(chip, Chip::Esp8266);
+        assert_eq!(chip.status_len(), 2);
+        let mut body = body.to_vec();
+        assert_eq!(strip_status(&mut body, chip.status_len()), Ok(()));
+        assert!(body.is_empty());
+
+        assert_eq!(response(&esp32, SYNC), None);
+        assert_eq!(response(&esp32[..7], READ_REG), None);
+    }
+
+    #[test]
+    fn test_status() {
+        // MD5 text followed by the status, as long as the chip's status is.
+        let mut body = b"0123456789abcdef0123456789abcdef".to_vec();
+        body.extend([0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(strip_status(&mut body, 4), Ok(()));
+        assert_eq!(body.len(), 32);
+
+        let mut body = vec![0xAA, 0x00, 0x00];
+        assert_eq!(strip_status(&mut body, 2), Ok(()));
+        assert_eq!(body, [0xAA]);
+
+        assert_eq!(strip_status(&mut vec![0x01, 0x05, 0x00, 0x00], 4), Err(Some(0x05)));
+        assert_eq!(strip_status(&mut vec![0x01, 0x08], 2), Err(Some(0x08)));
+        assert_eq!(strip_status(&mut vec![0x00], 2), Err(None));
+    }
+
+    #[test]
+    fn test_esp8266_erase_size() {
+        // Values from esptool.py's ESP8266ROM.get_erase_size.
+        assert_eq!(esp8266_erase_size(0, 0x1000), 0x1000);
+        assert_eq!(esp8266_erase_size(0, 0x40000), 0x30000);
+        assert_eq!(esp8266_erase_size(0x1000, 0x3000), 0x2000);
+        assert_eq!(esp8266_erase_size(0, 0x10001), 0x9000);
+        assert_eq!(esp8266_erase_size(0x8000, 0x30000), 0x28000);
+        assert_eq!(esp8266_erase_size(0xF000, 0x2000), 0x1000);
+    }
+
+    #[test]
+    fn test_defl_begin() {
+        // 0x1801 bytes erase as 7 blocks; 0x900 compressed go in 3.
+        assert_eq!(defl_begin(Chip::Esp32, 0x10000, 0x1801, 0x900),
+                   [0x00, 0x1C, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00]);
+        let c3 = defl_begin(Chip::Esp32C3, 0x10000, 0x1801, 0x900);
+        assert_eq!(c3.len(), 20);
+        assert_eq!(c3[16..], [0; 4]);
+        assert_eq!(defl_begin(Chip::Esp32, 0, 0x400, 0x400)[..8], [0x00, 0x04, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00]);
+    }
+}
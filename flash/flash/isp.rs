@@ -0,0 +1,194 @@
+// ─────────────────────────────────────────────────────────────────────────────
+//  tsuki-flash :: flash :: isp  —  avrdude through an ISP programmer
+//
+//  For classic AVR boards without a working serial bootloader: the sketch,
+//  the bootloader itself and the fuses go over the 6-pin ICSP header. An
+//  upload this way erases the chip, bootloader included.
+// ─────────────────────────────────────────────────────────────────────────────
+
+use std::fmt;
+use std::path::Path;
+use std::process::Command;
+
+use crate::boards::{Board, Toolchain};
+use crate::error::{FlashError, Result};
+use crate::process::{self, Stage};
+use crate::sdk;
+use super::avrdude::{avrdude_conf, find_avrdude};
+
+/// Programmers avrdude can drive over ICSP.
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Programmer {
+    /// USBasp (USB).
+    Usbasp,
+    /// Atmel AVRISP mkII (USB).
+    Avrisp2,
+    /// A second Arduino running the ArduinoISP sketch (serial).
+    #[value(name = "arduino_as_isp")]
+    ArduinoAsIsp,
+}
+
+impl Programmer {
+    fn avrdude_id(self) -> &'static str {
+        match self {
+            Programmer::Usbasp       => "usbasp",
+            Programmer::Avrisp2      => "avrisp2",
+            Programmer::ArduinoAsIsp => "stk500v1",
+        }
+    }
+
+    /// Whether the programmer sits behind a serial port. USB programmers are
+    /// found by avrdude itself (`-P usb`).
+    pub fn uses_serial(self) -> bool {
+        self == Programmer::ArduinoAsIsp
+    }
+}
+
+impl fmt::Display for Programmer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Programmer::Usbasp       => "usbasp",
+            Programmer::Avrisp2      => "avrisp2",
+            Programmer::ArduinoAsIsp => "arduino_as_isp",
+        })
+    }
+}
+
+/// Fuse and lock bytes; None leaves one alone.
+#[derive(Debug, Default)]
+pub struct Fuses {
+    pub low:      Option<u8>,
+    pub high:     Option<u8>,
+    pub extended: Option<u8>,
+    pub lock:     Option<u8>,
+}
+
+impl Fuses {
+    fn memories(&self) -> impl Iterator<Item = (&'static str, u8)> + '_ {
+        [("lfuse", self.low), ("hfuse", self.high), ("efuse", self.extended), ("lock", self.lock)]
+            .into_iter()
+            .filter_map(|(name, v)| v.map(|v| (name, v)))
+    }
+}
+
+/// An avrdude run through `programmer`, before the `-U` operations.
+pub struct Isp<'a> {
+    pub board:      &'a Board,
+    pub programmer: Programmer,
+    /// Serial port for ArduinoISP; "usb" for USB programmers.
+    pub port:       &'a str,
+    /// 0 = the programmer's default.
+    pub baud:       u32,
+    pub verbose:    bool,
+}
+
+impl Isp<'_> {
+    fn command(&self) -> Result<Command> {
+        let Toolchain::Avr { mcu, .. } = &self.board.toolchain else {
+            return Err(FlashError::Other(format!(
+                "ISP programming is only supported on classic AVR boards, not '{}'", self.board.id)));
+        };
+
+        let avrdude = find_avrdude();
+        let mut cmd = Command::new(&avrdude);
+        cmd.args(["-C", &avrdude_conf(&avrdude), "-p", mcu, "-c", self.programmer.avrdude_id(), "-P", self.port]);
+        match (self.programmer, self.baud) {
+            (Programmer::ArduinoAsIsp, 0) => { cmd.args(["-b", "19200"]); }
+            (_, 0) => {}
+            (_, baud) => { cmd.args(["-b", &baud.to_string()]); }
+        }
+        if self.verbose {
+            cmd.arg("-v");
+        } else {
+            cmd.args(["-q", "-q"]);
+        }
+        Ok(cmd)
+    }
+
+    /// Run avrdude, returning its stdout.
+    fn run(&self, mut cmd: Command) -> Result<String> {
+        let out = process::output(&mut cmd, Stage::Upload)?;
+        let stdout = String::from_utf8_lossy(&out.stdout).to_string();
+        if !out.status.success() {
+            let stderr = String::from_utf8_lossy(&out.stderr).to_string();
+            return Err(FlashError::FlashFailed {
+                port:   self.port.to_owned(),
+                output: format!("{}\n{}", stderr, stdout).trim().to_owned(),
+            });
+        }
+        Ok(stdout)
+    }
+
+    /// Erase the chip and write `hex` to it.
+    pub fn upload(&self, hex: &Path) -> Result<()> {
+        let mut cmd = self.command()?;
+        cmd.args(["-e", "-U", &format!("flash:w:{}:i", hex.display())]);
+        self.run(cmd).map(drop)
+    }
+
+    /// Write the board's bootloader, as the Arduino IDE does: erase, unlock
+    /// and set the fuses first, then write the hex and lock it. Two runs, so
+    /// the programmer reconnects once the new clock fuses have taken effect.
+    pub fn burn_bootloader(&self) -> Result<()> {
+        let bl = self.board.bootloader.ok_or_else(|| FlashError::Other(format!(
+            "Board '{}' has no bootloader definition", self.board.id)))?;
+        let hex = sdk::resolve(self.board.arch(), self.board.variant)?
+            .platform_dir().join("bootloaders").join(&bl.file);
+        if !hex.is_file() {
+            return Err(FlashError::NoFirmware(hex.display().to_string()));
+        }
+
+        let fuses = Fuses {
+            low:      Some(bl.low_fuse),
+            high:     Some(bl.high_fuse),
+            extended: Some(bl.extended_fuse),
+            lock:     Some(bl.unlock),
+        };
+        let mut cmd = self.command()?;
+        cmd.arg("-e");
+        push_writes(&mut cmd, &fuses);
+        self.run(cmd)?;
+
+        let mut cmd = self.command()?;
+        cmd.args(["-U", &format!("flash:w:{}:i", hex.display())]);
+        push_writes(&mut cmd, &Fuses { lock: Some(bl.lock), ..Fuses::default() });
+        self.run(cmd).map(drop)
+    }
+
+    pub fn read_fuses(&self) -> Result<Fuses> {
+        let mut cmd = self.command()?;
+        for mem in ["lfuse", "hfuse", "efuse", "lock"] {
+            cmd.args(["-U", &format!("{}:r:-:h", mem)]);
+        }
+        let stdout = self.run(cmd)?;
+
+        // One "0x…" line per memory, in the order asked.
+        let mut values = stdout.lines()
+            .filter_map(|l| u8::from_str_radix(l.trim().strip_prefix("0x")?, 16).ok());
+        Ok(Fuses {
+            low:      values.next(),
+            high:     values.next(),
+            extended: values.next(),
+            lock:     values.next(),
+        })
+    }
+
+    pub fn write_fuses(&self, fuses: &Fuses) -> Result<()> {
+        let mut cmd = self.command()?;
+        push_writes(&mut cmd, fuses);
+        self.run(cmd).map(drop)
+    }
+}
+
+fn push_writes(cmd: &mut Command, fuses: &Fuses) {
+    for (mem, value) in fuses.memories() {
+        cmd.args(["-U", &format!("{}:w:{:#04x}:m", mem, value)]);
+    }
+}
+
+/// Parse a fuse byte. It is always hex, as in datasheets, so the `0x` is
+/// optional: `0xDE` and `DE` are the same.
+pub fn parse_fuse(s: &str) -> std::result::Result<u8, String> {
+    let digits = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")).unwrap_or(s);
+    u8::from_str_radix(digits, 16).map_err(|_| format!("'{}' is not a fuse byte (e.g. 0xDE)", s))
+}
@@ -3,11 +3,24 @@
 // ─────────────────────────────────────────────────────────────────────────────
 
 pub mod avrdude;
+pub mod bossac;
 pub mod esptool;
+pub mod isp;
+pub mod ota;
+pub mod read;
+pub mod stk500;
+pub mod stm32;
+pub mod teensy;
+pub mod uf2;
+pub mod updi;
 
 use std::path::{Path, PathBuf};
-use crate::boards::{Board, Toolchain};
+use crate::boards::{Board, Stm32Upload, Toolchain};
+use crate::compile::meta::BuildMeta;
+use crate::detect;
 use crate::error::{FlashError, Result};
+use crate::events::{self, Event};
+use crate::process;
 
 #[derive(Debug)]
 pub struct FlashRequest {
@@ -19,6 +32,20 @@ pub struct FlashRequest {
     pub port:          String,
     /// Custom baud rate override (0 = use board default).
     pub baud_override: u32,
+    /// Clock the firmware was built for (None = board default); scales
+    /// the AVR bootloader baud rate and picks the megaAVR oscillator fuse.
+    pub cpu_mhz:       Option<u32>,
+    /// Skip the firmware/board interlock.
+    pub force:         bool,
+    /// Upload through an ISP programmer instead of the bootloader (AVR).
+    pub programmer:    Option<isp::Programmer>,
+    /// Upload over WiFi instead of the serial port (ESP).
+    pub ota:           Option<ota::OtaTarget>,
+    /// Upload through avrdude rather than tsuki-flash's own STK500 code (AVR).
+    pub use_avrdude:   bool,
+    /// Read the flash back afterwards where the upload doesn't check what
+    /// it wrote by itself; fail up front where it can't be read.
+    pub verify:        bool,
     /// Print programmer output.
     pub verbose:       bool,
 }
@@ -26,31 +53,120 @@ pub struct FlashRequest {
 /// Flash compiled firmware to a connected board.
 pub fn flash(req: &FlashRequest, board: &Board) -> Result<()> {
     let firmware = find_firmware(&req.build_dir, &req.project_name, board)?;
+    if !req.force {
+        check_target(req, board)?;
+    }
+    if req.verify {
+        check_verifiable(req, board)?;
+    }
+
+    let t0 = std::time::Instant::now();
+    events::emit(Event::UploadStarted { board: board.id, port: &req.port, firmware: &firmware });
+    // A --verify reads the board back into a scratch file.
+    let res = process::writing(&read::scratch_prefix(), || upload(req, board, &firmware));
+    events::emit(Event::UploadFinished { success: res.is_ok(), seconds: t0.elapsed().as_secs_f64() });
+    res
+}
+
+fn upload(req: &FlashRequest, board: &Board, firmware: &Path) -> Result<()> {
+    let firmware = firmware.to_path_buf();
+
+    if let Some(target) = &req.ota {
+        return ota::flash(&firmware, target, board, req.verbose);
+    }
+
+    if let Some(programmer) = req.programmer {
+        return isp::Isp {
+            board,
+            programmer,
+            port:    &req.port,
+            baud:    req.baud_override,
+            verbose: req.verbose,
+        }.upload(&firmware);
+    }
 
     match &board.toolchain {
         Toolchain::Avr { baud, .. } => {
-            let _baud = if req.baud_override > 0 { req.baud_override } else { *baud };
-            avrdude::flash(&firmware, &req.port, board, req.verbose)
+            let baud = match req.baud_override {
+                0 => board.upload_baud(req.cpu_mhz)?.unwrap_or(*baud),
+                b => b,
+            };
+            match stk500::Protocol::for_board(board) {
+                Some(protocol) if !req.use_avrdude =>
+                    stk500::flash(&firmware, &req.port, board, protocol, baud, req.verbose),
+                _ => avrdude::flash(&firmware, &req.port, board, baud, req.verbose),
+            }
+        }
+        Toolchain::MegaAvr { .. } => {
+            let f_cpu = board.clock_hz(req.cpu_mhz)?;
+            updi::flash(&firmware, &req.port, board, f_cpu, req.baud_override, req.verbose)
         }
         Toolchain::Esp32 { .. } | Toolchain::Esp8266 => {
-            let baud = if req.baud_override > 0 { req.baud_override } else { 921_600 };
-            esptool::flash(&firmware, &req.port, board, baud, req.verbose)
+            let baud = if req.baud_override > 0 { req.baud_override } else { board.upload_baud.unwrap_or(921_600) };
+            esptool::flash(&firmware, &req.port, board, baud, req.verify, req.verbose)
+        }
+        Toolchain::Sam { .. } => bossac::flash(&firmware, &req.port, req.verbose),
+        Toolchain::Rp2040 => uf2::flash(&firmware, &req.port, req.verbose),
+        Toolchain::Stm32 { .. } => {
+            let baud = if req.baud_override > 0 { req.baud_override } else { board.upload_baud.unwrap_or(115_200) };
+            stm32::flash(&firmware, &req.port, board, baud, req.verify, req.verbose)
         }
-        Toolchain::Sam { .. } => Err(FlashError::Other(
-            "SAM (Due) flash not yet implemented — use arduino-cli for now".into(),
-        )),
-        Toolchain::Rp2040 => Err(FlashError::Other(
-            "RP2040 flash: copy the .uf2 file to the Pico USB drive manually,\n  or use picotool.".into(),
-        )),
+        Toolchain::Teensy { .. } => teensy::flash(&firmware, &req.port, board, req.verbose),
     }
 }
 
+/// Refuse to flash firmware onto a board it wasn't built for: the build
+/// metadata must name `board`, and the board detected on the port (when it
+/// can be identified) must be able to run it.
+fn check_target(req: &FlashRequest, board: &Board) -> Result<()> {
+    let Some(meta) = BuildMeta::load(&req.build_dir, &req.project_name) else {
+        return Ok(());
+    };
+    if !meta.board.eq_ignore_ascii_case(board.id) {
+        return Err(FlashError::FirmwareMismatch {
+            built_for: meta.board,
+            board:     board.id.to_owned(),
+        });
+    }
+    let Some(port) = detect::detect_all().into_iter().find(|p| p.port == req.port) else {
+        return Ok(());
+    };
+    match port.accepts(board) {
+        Some(false) => Err(FlashError::BoardMismatch {
+            port:      req.port.clone(),
+            built_for: meta.board,
+            detected:  port.board_name.unwrap_or("an unknown board").to_owned(),
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// avrdude, the STK500 code, bossac, stm32flash and the ESP32 loader check
+/// every upload already, and an OTA update is checked by the board. What's
+/// left either reads back on --verify or can't be read at all.
+fn check_verifiable(req: &FlashRequest, board: &Board) -> Result<()> {
+    if req.ota.is_some() || req.programmer.is_some() {
+        return Ok(());
+    }
+    let write_only = match &board.toolchain {
+        Toolchain::Rp2040 => "the RP2040 reboots into the sketch as soon as its drive is written",
+        Toolchain::Teensy { .. } => "the Teensy's HalfKay bootloader only takes writes",
+        Toolchain::Stm32 { upload: Stm32Upload::StLink, .. } => "the ST-Link drive only takes writes",
+        _ => return Ok(()),
+    };
+    Err(FlashError::Other(format!(
+        "--verify: {}, so the upload can't be read back\n  Upload without --verify", write_only)))
+}
+
 /// Locate the firmware file inside build_dir.
-/// Priority: .with_bootloader.hex > .hex > .bin > .elf
+/// Priority: .with_bootloader.hex > .hex > .bin (AVR, megaAVR, Teensy), .uf2 (RP2040),
+/// .bin > .hex (others)
 fn find_firmware(build_dir: &Path, name: &str, board: &Board) -> Result<PathBuf> {
-    let prefer_hex = matches!(&board.toolchain, Toolchain::Avr { .. });
+    let prefer_hex = matches!(&board.toolchain, Toolchain::Avr { .. } | Toolchain::MegaAvr { .. } | Toolchain::Teensy { .. });
 
-    let candidates: &[&str] = if prefer_hex {
+    let candidates: &[&str] = if board.toolchain == Toolchain::Rp2040 {
+        &[&format!("{}.uf2", name) as &str]
+    } else if prefer_hex {
         &[
             &format!("{}.with_bootloader.hex", name) as &str,
             &format!("{}.hex", name),
@@ -0,0 +1,261 @@
+// ─────────────────────────────────────────────────────────────────────────────
+//  tsuki-flash :: flash :: ota  —  ESP uploads over WiFi (ArduinoOTA)
+//
+//  The protocol of espota.py: a UDP invitation names the image's size and
+//  MD5 and a TCP port of ours; the board may answer with a challenge for
+//  the OTA password, then connects back and takes the image, acknowledging
+//  each chunk. It writes the image to its spare OTA slot, checks the MD5
+//  and reboots into it.
+// ─────────────────────────────────────────────────────────────────────────────
+
+use std::io::{ErrorKind, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs, UdpSocket};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use md5::{Digest, Md5};
+
+use crate::boards::{Board, Toolchain};
+use crate::error::{FlashError, Result};
+use crate::events::Progress;
+
+/// A board to upload to over the network.
+#[derive(Debug, Clone)]
+pub struct OtaTarget {
+    /// IP address or host name (`esp32-a1b2c3.local`).
+    pub host:     String,
+    /// ArduinoOTA's port (None = the core's default).
+    pub port:     Option<u16>,
+    pub password: Option<String>,
+}
+
+/// espota.py's command number for a firmware image.
+const FLASH: u32 = 0;
+const AUTH:  u32 = 200;
+const CHUNK: usize = 1460;
+
+/// Upload the app image `firmware` to `target`.
+pub fn flash(firmware: &Path, target: &OtaTarget, board: &Board, verbose: bool) -> Result<()> {
+    let default_port = match board.toolchain {
+        Toolchain::Esp32 { .. } => 3232,
+        Toolchain::Esp8266      => 8266,
+        _ => return Err(FlashError::Other(format!(
+            "Board '{}' has no network upload; OTA is for ESP boards", board.id))),
+    };
+    let fail = |output: String| FlashError::FlashFailed { port: target.host.clone(), output };
+
+    let image = std::fs::read(firmware)?;
+    let md5 = hex::encode(Md5::digest(&image));
+    let remote = (target.host.as_str(), target.port.unwrap_or(default_port))
+        .to_socket_addrs()
+        .ok()
+        .and_then(|mut addrs| addrs.find(SocketAddr::is_ipv4))
+        .ok_or_else(|| FlashError::PortNotFound(target.host.clone()))?;
+
+    let listener = TcpListener::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    let local_port = listener.local_addr()?.port();
+    let udp = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    udp.set_read_timeout(Some(Duration::from_secs(2)))?;
+
+    if verbose { eprintln!("  [ota] inviting {} ({} bytes, MD5 {})", remote, image.len(), md5); }
+    let reply = exchange(&udp, remote, &invitation(local_port, image.len(), &md5))
+        .ok_or_else(|| fail(format!("no answer from {}\n\
+                                     Hint: is the sketch running ArduinoOTA, on this network?", remote)))?;
+
+    if let Some(nonce) = reply.strip_prefix("AUTH ") {
+        let password = target.password.as_deref().ok_or_else(|| fail(
+            "the board wants an OTA password\nHint: pass --password".into()))?;
+        let cnonce = md5_hex(format!("{}{}{}{}", firmware.display(), image.len(), md5, remote.ip()));
+        let reply = exchange(&udp, remote, &auth_reply(password, nonce.trim(), &cnonce))
+            .ok_or_else(|| fail("no answer to the password".into()))?;
+        if reply != "OK" {
+            return Err(fail("the board refused the OTA password".into()));
+        }
+    } else if reply != "OK" {
+        return Err(fail(format!("the board refused the upload: {}", reply)));
+    }
+
+    let mut conn = accept(&listener, Duration::from_secs(10))
+        .ok_or_else(|| fail(format!(
+            "{} never connected back to port {}\nHint: check that a firewall allows it", remote, local_port)))?;
+    conn.set_read_timeout(Some(Duration::from_secs(10)))?;
+
+    // The board acknowledges each chunk with the byte count it got, and ends
+    // with "OK" once the image checks out.
+    let mut acks = String::new();
+    let mut progress = Progress::new(image.len() as u64);
+    for (i, chunk) in image.chunks(CHUNK).enumerate() {
+        conn.write_all(chunk)?;
+        progress.advance(chunk.len() as u64);
+        let mut buf = [0u8; 32];
+        let n = conn.read(&mut buf).map_err(|e| fail(format!("upload stalled: {}", e)))?;
+        acks.push_str(&String::from_utf8_lossy(&buf[..n]));
+        if verbose && i % 64 == 63 {
+            eprintln!("  [ota] {} / {} bytes", (i + 1) * CHUNK, image.len());
+        }
+    }
+    if verbose { eprintln!("  [ota] image sent, waiting for the board to check it"); }
+
+    conn.set_read_timeout(Some(Duration::from_secs(60)))?;
+    while !acks.contains("OK") {
+        let mut buf = [0u8; 32];
+        match conn.read(&mut buf) {
+            Ok(0) | Err(_) => {
+                // Anything but the counts is the board's error message.
+                let said: String = acks.chars().filter(|c| !c.is_ascii_digit()).collect();
+                return Err(fail(format!("the board did not confirm the upload {}", said.trim())
+                    .trim_end().to_owned()));
+            }
+            Ok(n) => acks.push_str(&String::from_utf8_lossy(&buf[..n])),
+        }
+    }
+    Ok(())
+}
+
+/// The UDP invitation: the command, the TCP port the board connects back
+/// to, and the image's size and MD5.
+fn invitation(local_port: u16, size: usize, md5: &str) -> String {
+    format!("{} {} {} {}\n", FLASH, local_port, size, md5)
+}
+
+/// espota.py's answer to an `AUTH <nonce>` challenge.
+fn auth_reply(password: &str, nonce: &str, cnonce: &str) -> String {
+    let response = md5_hex(format!("{}:{}:{}", md5_hex(password), nonce, cnonce));
+    format!("{} {} {}\n", AUTH, cnonce, response)
+}
+
+/// Send `message` and return the board's one-line answer, retrying a few
+/// times: UDP may drop either way.
+fn exchange(udp: &UdpSocket, remote: SocketAddr, message: &str) -> Option<String> {
+    let mut buf = [0u8; 64];
+    for _ in 0..5 {
+        udp.send_to(message.as_bytes(), remote).ok()?;
+        if let Ok((n, from)) = udp.recv_from(&mut buf) {
+            if from.ip() == remote.ip() {
+                return Some(String::from_utf8_lossy(&buf[..n]).trim().to_owned());
+            }
+        }
+    }
+    None
+}
+
+fn accept(listener: &TcpListener, timeout: Duration) -> Option<TcpStream> {
+    listener.set_nonblocking(true).ok()?;
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                stream.set_nonblocking(false).ok()?;
+                return Some(stream);
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => std::thread::sleep(Duration::from_millis(50)),
+            Err(_) => return None,
+        }
+    }
+    None
+}
+
+fn md5_hex(text: impl AsRef<[u8]>) -> String {
+    hex::encode(Md5::digest(text.as_ref()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_invitation_bytes() {
+        assert_eq!(invitation(40123, 3061, "31d7eb78de73d1dce90508313840fd19").as_bytes(),
+                   b"0 40123 3061 31d7eb78de73d1dce90508313840fd19\n");
+    }
+
+    #[test]
+    fn test_auth_reply_bytes() {
+        let cnonce = "c".repeat(32);
+        // md5(md5("secret") + ":" + nonce + ":" + cnonce), as espota.py sends it.
+        assert_eq!(auth_reply("secret", "0123456789abcdef", &cnonce),
+                   format!("200 {} 2c3917b7974547cdb04f1a6977f8cabc\n", cnonce));
+        assert_eq!(md5_hex(""), "d41d8cd98f00b204e9800998ecf8427e");
+    }
+
+    /// A board on localhost that wants `password`, checks the handshake
+    /// byte for byte and returns the image it was sent.
+    fn fake_board(udp: UdpSocket, password: &'static str) -> thread::JoinHandle<Vec<u8>> {
+        thread::spawn(move || {
+            let mut buf = [0u8; 128];
+            let (n, from) = udp.recv_from(&mut buf).unwrap();
+            let invitation = String::from_utf8(buf[..n].to_vec()).unwrap();
+            let fields: Vec<&str> = invitation.trim_end_matches('\n').split(' ').collect();
+            assert_eq!((fields.len(), fields[0]), (4, "0"), "{:?}", invitation);
+            let (port, size) = (fields[1].parse::<u16>().unwrap(), fields[2].parse::<usize>().unwrap());
+            let md5 = fields[3].to_owned();
+
+            udp.send_to(b"AUTH 0123456789abcdef", from).unwrap();
+            let (n, from) = udp.recv_from(&mut buf).unwrap();
+            let auth = String::from_utf8(buf[..n].to_vec()).unwrap();
+            let cnonce = auth.split(' ').nth(1).unwrap();
+            assert_eq!(auth, auth_reply(password, "0123456789abcdef", cnonce));
+            udp.send_to(b"OK", from).unwrap();
+
+            let mut conn = TcpStream::connect((Ipv4Addr::LOCALHOST, port)).unwrap();
+            let mut image = Vec::new();
+            let mut chunk = [0u8; CHUNK];
+            while image.len() < size {
+                let n = conn.read(&mut chunk).unwrap();
+                assert!(n > 0);
+                image.extend_from_slice(&chunk[..n]);
+                conn.write_all(n.to_string().as_bytes()).unwrap();
+            }
+            assert_eq!(hex::encode(Md5::digest(&image)), md5);
+            conn.write_all(b"OK").unwrap();
+            image
+        })
+    }
+
+    #[test]
+    fn test_upload_with_password() {
+        let dir = std::env::temp_dir().join(format!("tsuki-ota-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let firmware = dir.join("fw.bin");
+        let image: Vec<u8> = std::iter::once(0xE9).chain((0..=254).cycle().take(255 * 12)).collect();
+        std::fs::write(&firmware, &image).unwrap();
+
+        let udp = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = udp.local_addr().unwrap().port();
+        let board = fake_board(udp, "secret");
+        let target = OtaTarget { host: "127.0.0.1".into(), port: Some(port), password: Some("secret".into()) };
+        flash(&firmware, &target, Board::find("esp32").unwrap(), false).unwrap();
+        assert_eq!(board.join().unwrap(), image);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_refusals() {
+        let dir = std::env::temp_dir().join(format!("tsuki-ota-refuse-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let firmware = dir.join("fw.bin");
+        std::fs::write(&firmware, [0xE9; 16]).unwrap();
+        let esp = Board::find("esp8266").unwrap();
+
+        let answer = |reply: &'static [u8], password: Option<&str>| {
+            let udp = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+            let port = udp.local_addr().unwrap().port();
+            let board = thread::spawn(move || {
+                let mut buf = [0u8; 128];
+                let (_, from) = udp.recv_from(&mut buf).unwrap();
+                udp.send_to(reply, from).unwrap();
+            });
+            let target = OtaTarget { host: "127.0.0.1".into(), port: Some(port), password: password.map(Into::into) };
+            let err = flash(&firmware, &target, esp, false).unwrap_err().to_string();
+            board.join().unwrap();
+            err
+        };
+        assert!(answer(b"AUTH 0123", None).contains("--password"));
+        assert!(answer(b"ERR busy", Some("x")).contains("refused the upload: ERR busy"));
+
+        let target = OtaTarget { host: "127.0.0.1".into(), port: None, password: None };
+        assert!(flash(&firmware, &target, Board::find("uno").unwrap(), false).is_err());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,146 @@
+// ─────────────────────────────────────────────────────────────────────────────
+//  tsuki-flash :: flash :: read  —  pull the firmware off a board (backups)
+//
+//  The flash is read through whatever an upload to the board goes through,
+//  and saved as Intel HEX (`.hex`) or a raw image (anything else). Erased
+//  bytes at the end are left out of both. A Due (its SAM-BA entry erases the
+//  chip), a Teensy and an ST-Link drive can only be written.
+// ─────────────────────────────────────────────────────────────────────────────
+
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use crate::boards::{Board, Stm32Upload, Toolchain};
+use crate::error::{FlashError, Result};
+use crate::process;
+use super::{avrdude, esptool, stk500, stm32, uf2, updi};
+
+#[derive(Debug)]
+pub struct ReadRequest {
+    /// Serial port (e.g. "/dev/ttyUSB0"); empty for the RP2040's drive.
+    pub port:          String,
+    /// Custom baud rate override (0 = use board default).
+    pub baud_override: u32,
+    /// Read AVR boards through avrdude rather than the STK500 code.
+    pub use_avrdude:   bool,
+    /// Print programmer output.
+    pub verbose:       bool,
+}
+
+/// A board's flash, from the address it is mapped at.
+#[derive(Debug)]
+pub struct Dump {
+    pub base: u32,
+    pub data: Vec<u8>,
+}
+
+/// Read the whole of `board`'s flash.
+pub fn read(req: &ReadRequest, board: &Board) -> Result<Dump> {
+    if let Some(what) = write_only(board) {
+        return Err(FlashError::Other(what.into()));
+    }
+    let baud = |default: u32| if req.baud_override > 0 { req.baud_override } else { default };
+    let port = req.port.as_str();
+
+    // Ctrl-C or a programmer timing out leaves no scratch file behind.
+    let (base, data) = process::writing(&scratch_prefix(), || Ok(match &board.toolchain {
+        Toolchain::Avr { baud: default, .. } => {
+            let baud = baud(board.upload_baud(None)?.unwrap_or(*default));
+            let data = match stk500::Protocol::for_board(board) {
+                Some(protocol) if !req.use_avrdude => stk500::read(port, board, protocol, baud, req.verbose)?,
+                _ => avrdude::read(port, board, baud, req.verbose)?,
+            };
+            (0, data)
+        }
+        Toolchain::MegaAvr { .. } => (0, updi::read(port, board, req.baud_override, req.verbose)?),
+        Toolchain::Esp32 { .. } | Toolchain::Esp8266 =>
+            (0, esptool::read(port, board, baud(board.upload_baud.unwrap_or(921_600)), req.verbose)?),
+        Toolchain::Rp2040 => uf2::read(port, req.verbose)?,
+        Toolchain::Stm32 { .. } =>
+            (stm32::FLASH_BASE, stm32::read(port, board, baud(board.upload_baud.unwrap_or(115_200)), req.verbose)?),
+        Toolchain::Sam { .. } | Toolchain::Teensy { .. } => unreachable!("write-only"),
+    }))?;
+    Ok(Dump { base, data })
+}
+
+/// Why `board`'s flash can't be read, if it can't.
+fn write_only(board: &Board) -> Option<&'static str> {
+    match &board.toolchain {
+        Toolchain::Sam { .. } => Some("a Due can't be read back: entering its bootloader erases the flash"),
+        Toolchain::Teensy { .. } => Some("a Teensy can't be read back: its HalfKay bootloader only takes writes"),
+        Toolchain::Stm32 { upload: Stm32Upload::StLink, .. } =>
+            Some("the ST-Link drive can't be read back: it only takes writes"),
+        _ => None,
+    }
+}
+
+/// Write `dump` to `path`, as Intel HEX if it ends in `.hex` and raw bytes
+/// otherwise. Returns how many bytes of flash went in.
+pub fn save(dump: &Dump, path: &Path) -> Result<usize> {
+    let len = dump.data.iter().rposition(|&b| b != 0xFF).map_or(0, |i| i + 1);
+    let data = &dump.data[..len];
+    let is_hex = path.extension().is_some_and(|e| e.eq_ignore_ascii_case("hex"));
+    if is_hex {
+        std::fs::write(path, intel_hex(dump.base, data))?;
+    } else {
+        std::fs::write(path, data)?;
+    }
+    Ok(len)
+}
+
+/// 16-byte data records, with an extended linear address record wherever
+/// the top half of the address changes. Lines of erased flash are skipped.
+fn intel_hex(base: u32, data: &[u8]) -> String {
+    let mut out = String::new();
+    let mut record = |kind: u8, offset: u16, bytes: &[u8]| {
+        let mut rec = vec![bytes.len() as u8];
+        rec.extend(offset.to_be_bytes());
+        rec.push(kind);
+        rec.extend_from_slice(bytes);
+        rec.push(rec.iter().fold(0u8, |acc, b| acc.wrapping_sub(*b)));
+        let _ = writeln!(out, ":{}", hex::encode_upper(&rec));
+    };
+
+    let mut upper = None;
+    for (i, line) in data.chunks(16).enumerate() {
+        if line.iter().all(|&b| b == 0xFF) { continue; }
+        let addr = base + (i * 16) as u32;
+        if upper != Some(addr >> 16) {
+            upper = Some(addr >> 16);
+            record(0x04, 0, &((addr >> 16) as u16).to_be_bytes());
+        }
+        record(0x00, addr as u16, line);
+    }
+    record(0x01, 0, &[]);
+    out
+}
+
+/// A file for a programmer to read into, removed by `take`.
+pub(super) fn scratch(tool: &str) -> PathBuf {
+    let mut path = scratch_prefix().into_os_string();
+    path.push(format!("{}.bin", tool));
+    let path = PathBuf::from(path);
+    // dfu-util won't overwrite a file.
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+/// What the names of this run's `scratch` files start with, for
+/// `process::writing` to clean them up after an interrupted read.
+pub(super) fn scratch_prefix() -> PathBuf {
+    std::env::temp_dir().join(format!("tsuki-{}-", std::process::id()))
+}
+
+/// The contents of a `scratch` file, which is then deleted.
+pub(super) fn take(path: &Path) -> Result<Vec<u8>> {
+    let data = std::fs::read(path);
+    let _ = std::fs::remove_file(path);
+    Ok(data?)
+}
+
+/// Where `read` differs from `written`, the image expected at `base`.
+pub(super) fn compare(base: u32, written: &[u8], read: &[u8]) -> Option<u32> {
+    let at = written.iter().zip(read).position(|(a, b)| a != b)
+        .or((read.len() < written.len()).then_some(read.len()))?;
+    Some(base + at as u32)
+}
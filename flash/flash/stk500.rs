@@ -0,0 +1,560 @@
+// ─────────────────────────────────────────────────────────────────────────────
+//  tsuki-flash :: flash :: stk500  —  AVR serial bootloaders, without avrdude
+//
+//  Optiboot (Uno, Nano, Pro Mini) speaks STK500v1; the Mega's bootloader
+//  speaks STK500v2. Both take the .hex a page at a time after a DTR/RTS
+//  reset, and both can read pages back, which is how the upload is
+//  verified. Boards on other bootloaders (Caterina) still go through avrdude.
+// ─────────────────────────────────────────────────────────────────────────────
+
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::boards::Board;
+use crate::error::{FlashError, Result};
+use crate::events::Progress;
+use crate::serial::Serial;
+
+/// The two wire protocols, by avrdude programmer id.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Protocol {
+    /// `arduino`: STK500v1 as cut down by optiboot.
+    V1,
+    /// `wiring`: framed STK500v2 messages.
+    V2,
+}
+
+impl Protocol {
+    /// The protocol `board` uploads with, if it is one this module speaks
+    /// and its chip is one it knows.
+    pub fn for_board(board: &Board) -> Option<Protocol> {
+        let (programmer, _) = board.avrdude_programmer()?;
+        part(board.avr_mcu()?)?;
+        match programmer {
+            "arduino" => Some(Protocol::V1),
+            "wiring"  => Some(Protocol::V2),
+            _ => None,
+        }
+    }
+}
+
+/// Signature bytes and flash page size of the chips the boards use.
+struct Part {
+    signature: [u8; 3],
+    page_size: usize,
+}
+
+fn part(mcu: &str) -> Option<Part> {
+    let (signature, page_size) = match mcu {
+        "atmega168"  => ([0x1E, 0x94, 0x06], 128),
+        "atmega328p" => ([0x1E, 0x95, 0x0F], 128),
+        "atmega328"  => ([0x1E, 0x95, 0x14], 128),
+        "atmega1280" => ([0x1E, 0x97, 0x03], 256),
+        "atmega2560" => ([0x1E, 0x98, 0x01], 256),
+        _ => return None,
+    };
+    Some(Part { signature, page_size })
+}
+
+/// Write `hex` through the board's bootloader at `baud`, then read it back.
+pub fn flash(hex: &Path, port: &str, board: &Board, protocol: Protocol, baud: u32, verbose: bool) -> Result<()> {
+    let (mcu, part) = board_part(board)?;
+    let pages = read_hex(hex, part.page_size)?;
+    let flash_size = board.flash_kb * 1024;
+    if let Some((&last, _)) = pages.last_key_value() {
+        if last + part.page_size as u32 > flash_size {
+            return Err(FlashError::Other(format!(
+                "{} does not fit in the {} KB of flash of {}", hex.display(), board.flash_kb, mcu)));
+        }
+    }
+
+    let mut session = Session::connect(port, baud, protocol, board, verbose)?;
+    session.enter()?;
+    let mut progress = Progress::new((pages.len() * part.page_size) as u64);
+    for (&addr, page) in &pages {
+        session.write_page(addr, page)?;
+        progress.advance(page.len() as u64);
+    }
+    if verbose {
+        eprintln!("  [stk500] wrote {} bytes in {} pages", pages.len() * part.page_size, pages.len());
+    }
+    for (&addr, page) in &pages {
+        let read = session.read_page(addr, page.len())?;
+        if read != *page {
+            let at = addr + read.iter().zip(page).position(|(a, b)| a != b).unwrap_or(0) as u32;
+            return Err(session.fail(format!("verification failed at {:#06x}", at)));
+        }
+    }
+    if verbose { eprintln!("  [stk500] verified"); }
+    session.leave()
+}
+
+/// Read the whole flash, bootloader included, through the bootloader.
+pub fn read(port: &str, board: &Board, protocol: Protocol, baud: u32, verbose: bool) -> Result<Vec<u8>> {
+    let (_, part) = board_part(board)?;
+    let flash_size = board.flash_kb * 1024;
+
+    let mut session = Session::connect(port, baud, protocol, board, verbose)?;
+    session.enter()?;
+    let mut data = Vec::with_capacity(flash_size as usize);
+    let mut progress = Progress::new(flash_size as u64);
+    for addr in (0..flash_size).step_by(part.page_size) {
+        data.extend(session.read_page(addr, part.page_size)?);
+        progress.advance(part.page_size as u64);
+    }
+    if verbose { eprintln!("  [stk500] read {} bytes", data.len()); }
+    session.leave()?;
+    Ok(data)
+}
+
+fn board_part(board: &Board) -> Result<(&'static str, Part)> {
+    let mcu = board.avr_mcu().ok_or_else(|| FlashError::Other("Not an AVR board".into()))?;
+    let part = part(mcu).ok_or_else(|| FlashError::Other(format!(
+        "tsuki-flash cannot program {} itself; pass --use-avrdude", mcu)))?;
+    Ok((mcu, part))
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+//  Protocol
+// ─────────────────────────────────────────────────────────────────────────────
+
+// STK500v1
+const STK_OK:         u8 = 0x10;
+const STK_INSYNC:     u8 = 0x14;
+const CRC_EOP:        u8 = 0x20;
+const STK_GET_SYNC:   u8 = 0x30;
+const STK_ENTER_PROG: u8 = 0x50;
+const STK_LEAVE_PROG: u8 = 0x51;
+const STK_LOAD_ADDR:  u8 = 0x55;
+const STK_PROG_PAGE:  u8 = 0x64;
+const STK_READ_PAGE:  u8 = 0x74;
+const STK_READ_SIGN:  u8 = 0x75;
+
+// STK500v2
+const MESSAGE_START:    u8 = 0x1B;
+const TOKEN:            u8 = 0x0E;
+const STATUS_CMD_OK:    u8 = 0x00;
+const CMD_SIGN_ON:      u8 = 0x01;
+const CMD_LOAD_ADDRESS: u8 = 0x06;
+const CMD_ENTER_PROG:   u8 = 0x10;
+const CMD_LEAVE_PROG:   u8 = 0x11;
+const CMD_PROGRAM_FLASH: u8 = 0x13;
+const CMD_READ_FLASH:   u8 = 0x14;
+const CMD_READ_SIGNATURE: u8 = 0x1B;
+
+struct Session {
+    port:       Serial,
+    protocol:   Protocol,
+    flash_size: u32,
+    seq:        u8,
+    verbose:    bool,
+}
+
+impl Session {
+    fn open(port: &str, baud: u32, protocol: Protocol, flash_size: u32, verbose: bool) -> Result<Session> {
+        let serial = Serial::open(port, baud)?;
+        Ok(Session { port: serial, protocol, flash_size, seq: 0, verbose })
+    }
+
+    /// Open the port, sync with the bootloader and check that the chip
+    /// is the board's.
+    fn connect(port: &str, baud: u32, protocol: Protocol, board: &Board, verbose: bool) -> Result<Session> {
+        let (mcu, part) = board_part(board)?;
+        let mut session = Session::open(port, baud, protocol, board.flash_kb * 1024, verbose)?;
+        session.sync()?;
+
+        let signature = session.signature()?;
+        if signature != part.signature {
+            return Err(session.fail(format!(
+                "device signature is {:02X} {:02X} {:02X}, expected {:02X} {:02X} {:02X} ({})\n\
+                 Hint: check --board, or pass --use-avrdude",
+                signature[0], signature[1], signature[2],
+                part.signature[0], part.signature[1], part.signature[2], mcu)));
+        }
+        Ok(session)
+    }
+
+    fn fail(&self, output: String) -> FlashError {
+        FlashError::FlashFailed { port: self.port.name().to_owned(), output }
+    }
+
+    /// Reset the board, as avrdude does: DTR/RTS off, then on. The edge goes
+    /// through a capacitor to RESET, and the bootloader then waits briefly
+    /// for a programmer before starting the sketch.
+    fn reset(&mut self) {
+        self.port.set_dtr(false);
+        self.port.set_rts(false);
+        std::thread::sleep(Duration::from_millis(250));
+        self.port.set_dtr(true);
+        self.port.set_rts(true);
+        std::thread::sleep(Duration::from_millis(50));
+        self.port.clear_input();
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.reset();
+        for attempt in 1..=10 {
+            let synced = match self.protocol {
+                Protocol::V1 => self.command_v1(&[STK_GET_SYNC], 0).is_ok(),
+                Protocol::V2 => self.command_v2(&[CMD_SIGN_ON]).is_ok(),
+            };
+            if synced {
+                // Answers to earlier attempts may still be on their way.
+                std::thread::sleep(Duration::from_millis(50));
+                self.port.clear_input();
+                return Ok(());
+            }
+            if self.verbose { eprintln!("  [stk500] no answer from the bootloader (attempt {})", attempt); }
+            self.port.clear_input();
+        }
+        Err(self.fail("the bootloader did not answer\n\
+                       Hint: check the port and --board, or press reset just before uploading".into()))
+    }
+
+    fn signature(&mut self) -> Result<[u8; 3]> {
+        match self.protocol {
+            Protocol::V1 => {
+                let sig = self.command_v1(&[STK_READ_SIGN], 3)?;
+                Ok([sig[0], sig[1], sig[2]])
+            }
+            Protocol::V2 => {
+                let mut sig = [0; 3];
+                for (i, byte) in sig.iter_mut().enumerate() {
+                    let body = self.command_v2(&[CMD_READ_SIGNATURE, 4, 0x30, 0, i as u8, 0])?;
+                    *byte = *body.get(2).ok_or_else(|| self.fail("short signature answer".into()))?;
+                }
+                Ok(sig)
+            }
+        }
+    }
+
+    fn enter(&mut self) -> Result<()> {
+        match self.protocol {
+            Protocol::V1 => self.command_v1(&[STK_ENTER_PROG], 0).map(drop),
+            // Timing values are for ISP programmers; the bootloader ignores them.
+            Protocol::V2 => self.command_v2(&[CMD_ENTER_PROG, 200, 100, 25, 32, 0, 0x53, 3, 0xAC, 0x53, 0, 0])
+                .map(drop),
+        }
+    }
+
+    fn leave(&mut self) -> Result<()> {
+        match self.protocol {
+            Protocol::V1 => self.command_v1(&[STK_LEAVE_PROG], 0).map(drop),
+            Protocol::V2 => self.command_v2(&[CMD_LEAVE_PROG, 1, 1]).map(drop),
+        }
+    }
+
+    fn load_address(&mut self, addr: u32) -> Result<()> {
+        let cmd = load_address(self.protocol, self.flash_size, addr);
+        match self.protocol {
+            Protocol::V1 => self.command_v1(&cmd, 0).map(drop),
+            Protocol::V2 => self.command_v2(&cmd).map(drop),
+        }
+    }
+
+    fn write_page(&mut self, addr: u32, page: &[u8]) -> Result<()> {
+        self.load_address(addr)?;
+        let cmd = program_page(self.protocol, page);
+        match self.protocol {
+            Protocol::V1 => self.command_v1(&cmd, 0).map(drop),
+            Protocol::V2 => self.command_v2(&cmd).map(drop),
+        }
+    }
+
+    fn read_page(&mut self, addr: u32, len: usize) -> Result<Vec<u8>> {
+        self.load_address(addr)?;
+        let [hi, lo] = (len as u16).to_be_bytes();
+        match self.protocol {
+            Protocol::V1 => self.command_v1(&[STK_READ_PAGE, hi, lo, b'F'], len),
+            Protocol::V2 => {
+                // [cmd, status, data…, status]
+                let body = self.command_v2(&[CMD_READ_FLASH, hi, lo, 0x20])?;
+                body.get(2..2 + len).map(<[u8]>::to_vec)
+                    .ok_or_else(|| self.fail(format!("short read at {:#06x}", addr)))
+            }
+        }
+    }
+
+    /// STK500v1: `cmd` + CRC_EOP, answered by INSYNC, `reply_len` bytes, OK.
+    fn command_v1(&mut self, cmd: &[u8], reply_len: usize) -> Result<Vec<u8>> {
+        let mut out = cmd.to_vec();
+        out.push(CRC_EOP);
+        self.port.write_all(&out)?;
+
+        let mut reply = vec![0; reply_len + 2];
+        self.read_exact(&mut reply)?;
+        answer_v1(&reply).map(<[u8]>::to_vec)
+            .ok_or_else(|| self.fail(format!("lost sync on command {:#04x}", cmd[0])))
+    }
+
+    /// STK500v2: a framed message, answered by one echoing the command with
+    /// a status byte. Returns the answer's body.
+    fn command_v2(&mut self, body: &[u8]) -> Result<Vec<u8>> {
+        self.port.write_all(&message_v2(self.seq, body))?;
+
+        // Skip anything before the start byte (a sketch's last output).
+        let mut msg = vec![0u8; 5];
+        loop {
+            self.read_exact(&mut msg[..1])?;
+            if msg[0] == MESSAGE_START { break; }
+        }
+        self.read_exact(&mut msg[1..])?;
+        let len = u16::from_be_bytes([msg[2], msg[3]]) as usize;
+        msg.resize(5 + len + 1, 0);
+        self.read_exact(&mut msg[5..])?;
+
+        let answer = answer_v2(self.seq, body[0], &msg);
+        if !matches!(answer, Err(AnswerV2::Garbled)) {
+            self.seq = self.seq.wrapping_add(1);
+        }
+        answer.map_err(|e| self.fail(match e {
+            AnswerV2::Garbled => format!("garbled answer to command {:#04x}", body[0]),
+            AnswerV2::Failed  => format!("command {:#04x} failed", body[0]),
+        }))
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
+        match self.port.read_exact(buf) {
+            Err(e) if e.kind() == io::ErrorKind::TimedOut =>
+                Err(self.fail("timed out waiting for the bootloader".into())),
+            other => other.map_err(Into::into),
+        }
+    }
+}
+
+/// LOAD_ADDRESS for the page at byte address `addr`. Both protocols
+/// address flash in words.
+fn load_address(protocol: Protocol, flash_size: u32, addr: u32) -> Vec<u8> {
+    let word = addr / 2;
+    match protocol {
+        Protocol::V1 => vec![STK_LOAD_ADDR, word as u8, (word >> 8) as u8],
+        Protocol::V2 => {
+            // Bit 31 tells the bootloader to use the extended address byte.
+            let word = if flash_size > 0x10000 { word | 0x8000_0000 } else { word };
+            let mut cmd = vec![CMD_LOAD_ADDRESS];
+            cmd.extend(word.to_be_bytes());
+            cmd
+        }
+    }
+}
+
+/// PROG_PAGE / PROGRAM_FLASH writing `page` at the loaded address.
+fn program_page(protocol: Protocol, page: &[u8]) -> Vec<u8> {
+    let [hi, lo] = (page.len() as u16).to_be_bytes();
+    let mut cmd = match protocol {
+        Protocol::V1 => vec![STK_PROG_PAGE, hi, lo, b'F'],
+        // Page mode, write the page; the ISP opcodes are ignored.
+        Protocol::V2 => vec![CMD_PROGRAM_FLASH, hi, lo, 0xC1, 10, 0x40, 0x4C, 0x20, 0, 0],
+    };
+    cmd.extend_from_slice(page);
+    cmd
+}
+
+/// The bytes between STK_INSYNC and STK_OK of an STK500v1 answer.
+fn answer_v1(reply: &[u8]) -> Option<&[u8]> {
+    match reply {
+        [STK_INSYNC, body @ .., STK_OK] => Some(body),
+        _ => None,
+    }
+}
+
+/// An STK500v2 message: start, sequence number, length, token, body and
+/// an XOR checksum over all of it.
+fn message_v2(seq: u8, body: &[u8]) -> Vec<u8> {
+    let mut msg = vec![MESSAGE_START, seq];
+    msg.extend((body.len() as u16).to_be_bytes());
+    msg.push(TOKEN);
+    msg.extend_from_slice(body);
+    msg.push(msg.iter().fold(0, |acc, b| acc ^ b));
+    msg
+}
+
+#[derive(Debug, PartialEq)]
+enum AnswerV2 {
+    /// Not the answer to the message sent, or corrupted on the way.
+    Garbled,
+    /// A status other than STATUS_CMD_OK.
+    Failed,
+}
+
+/// The body of `msg`, the answer to message `seq` carrying command `cmd`.
+fn answer_v2(seq: u8, cmd: u8, msg: &[u8]) -> std::result::Result<Vec<u8>, AnswerV2> {
+    let checksum = msg.iter().fold(0, |acc, b| acc ^ b);
+    let len = msg.get(2..4).map_or(0, |l| u16::from_be_bytes([l[0], l[1]]) as usize);
+    if msg.len() != 5 + len + 1 || msg[0] != MESSAGE_START || msg[1] != seq || msg[4] != TOKEN || checksum != 0 {
+        return Err(AnswerV2::Garbled);
+    }
+    let body = &msg[5..5 + len];
+    if body.first() != Some(&cmd) || body.get(1) != Some(&STATUS_CMD_OK) {
+        return Err(AnswerV2::Failed);
+    }
+    Ok(body.to_vec())
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+//  Intel HEX
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// The pages of `path` that hold data, keyed by address. Bytes the file
+/// doesn't set are 0xFF, as in erased flash.
+fn read_hex(path: &Path, page_size: usize) -> Result<BTreeMap<u32, Vec<u8>>> {
+    let text = std::fs::read_to_string(path)?;
+    hex_pages(&text, page_size)
+        .map_err(|(line, msg)| FlashError::Other(format!("{}: line {}: {}", path.display(), line, msg)))
+}
+
+/// `read_hex` on the file's text; errors carry the line number.
+fn hex_pages(text: &str, page_size: usize) -> std::result::Result<BTreeMap<u32, Vec<u8>>, (usize, &'static str)> {
+    let mut pages: BTreeMap<u32, Vec<u8>> = BTreeMap::new();
+    let mut base = 0u32;
+
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() { continue; }
+        let err = |msg| (n + 1, msg);
+
+        let bytes = line.strip_prefix(':')
+            .and_then(|h| hex::decode(h).ok())
+            .filter(|b| b.len() >= 5 && b.len() == b[0] as usize + 5)
+            .ok_or_else(|| err("not an Intel HEX record"))?;
+        if bytes.iter().fold(0u8, |acc, b| acc.wrapping_add(*b)) != 0 {
+            return Err(err("bad checksum"));
+        }
+        let offset = u16::from_be_bytes([bytes[1], bytes[2]]) as u32;
+        let data = &bytes[4..bytes.len() - 1];
+
+        match bytes[3] {
+            0x00 => {
+                for (i, &b) in data.iter().enumerate() {
+                    let addr = base + offset + i as u32;
+                    let page = addr - addr % page_size as u32;
+                    pages.entry(page).or_insert_with(|| vec![0xFF; page_size])[(addr - page) as usize] = b;
+                }
+            }
+            0x01 => break,
+            0x02 if data.len() == 2 => base = (u16::from_be_bytes([data[0], data[1]]) as u32) << 4,
+            0x04 if data.len() == 2 => base = (u16::from_be_bytes([data[0], data[1]]) as u32) << 16,
+            // Start addresses mean nothing to a bootloader.
+            0x03 | 0x05 => {}
+            _ => return Err(err("unsupported record type")),
+        }
+    }
+    Ok(pages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An Intel HEX record with its checksum.
+    fn record(kind: u8, offset: u16, data: &[u8]) -> String {
+        let mut bytes = vec![data.len() as u8];
+        bytes.extend(offset.to_be_bytes());
+        bytes.push(kind);
+        bytes.extend_from_slice(data);
+        bytes.push(bytes.iter().fold(0u8, |acc, b| acc.wrapping_sub(*b)));
+        format!(":{}\n", hex::encode_upper(bytes))
+    }
+
+    #[test]
+    fn test_pages_split_at_boundaries() {
+        // 8 bytes from 0x7C straddle the first two 128-byte pages.
+        let text = [record(0x00, 0x007C, &[1, 2, 3, 4, 5, 6, 7, 8]), record(0x01, 0, &[])].concat();
+        let pages = hex_pages(&text, 128).unwrap();
+        assert_eq!(pages.keys().copied().collect::<Vec<_>>(), [0x00, 0x80]);
+        assert_eq!(pages[&0x00][..0x7C], [0xFF; 0x7C]);
+        assert_eq!(pages[&0x00][0x7C..], [1, 2, 3, 4]);
+        assert_eq!(pages[&0x80][..4], [5, 6, 7, 8]);
+        assert_eq!(pages[&0x80][4..], [0xFF; 124]);
+    }
+
+    #[test]
+    fn test_last_partial_page() {
+        // 300 bytes: two full 128-byte pages and 44 bytes padded out.
+        let data: Vec<u8> = (0..300).map(|i| i as u8).collect();
+        let text: String = data.chunks(16).enumerate()
+            .map(|(i, chunk)| record(0x00, (i * 16) as u16, chunk))
+            .chain([record(0x01, 0, &[])])
+            .collect();
+        let pages = hex_pages(&text, 128).unwrap();
+        assert_eq!(pages.keys().copied().collect::<Vec<_>>(), [0, 128, 256]);
+        assert!(pages.values().all(|p| p.len() == 128));
+        assert_eq!(pages[&128][..], data[128..256]);
+        assert_eq!(pages[&256][..44], data[256..]);
+        assert_eq!(pages[&256][44..], [0xFF; 84]);
+    }
+
+    #[test]
+    fn test_hex_records() {
+        // An extended linear address puts the data past 64 KB (Mega).
+        let text = [
+            record(0x04, 0, &[0x00, 0x01]), record(0x00, 0x0100, &[0xAA]),
+            record(0x03, 0, &[0, 0, 0, 0]), record(0x01, 0, &[]), record(0x00, 0, &[0xBB]),
+        ].concat();
+        let pages = hex_pages(&text, 256).unwrap();
+        assert_eq!(pages.keys().copied().collect::<Vec<_>>(), [0x10100]);
+        assert_eq!(pages[&0x10100][0], 0xAA);
+
+        let segment = [record(0x02, 0, &[0x10, 0x00]), record(0x00, 0x0004, &[0xCC])].concat();
+        assert_eq!(hex_pages(&segment, 128).unwrap()[&0x10000][4], 0xCC);
+
+        assert_eq!(record(0x00, 0, &[1, 2]), ":020000000102FB\n");
+        assert_eq!(hex_pages("\n:020000000102FC\n", 128), Err((2, "bad checksum")));
+        assert_eq!(hex_pages("0100000001FE\n", 128), Err((1, "not an Intel HEX record")));
+        assert_eq!(hex_pages(&record(0x06, 0, &[]), 128), Err((1, "unsupported record type")));
+    }
+
+    #[test]
+    fn test_load_address_in_words() {
+        // Uno's last page, 0x7F80, is word 0x3FC0, little-endian in v1.
+        assert_eq!(load_address(Protocol::V1, 0x8000, 0x7F80), [STK_LOAD_ADDR, 0xC0, 0x3F]);
+        assert_eq!(load_address(Protocol::V1, 0x8000, 0), [STK_LOAD_ADDR, 0, 0]);
+        // v2 is big-endian, with bit 31 set past 64 KB of flash.
+        assert_eq!(load_address(Protocol::V2, 0x10000, 0xFF00), [CMD_LOAD_ADDRESS, 0x00, 0x00, 0x7F, 0x80]);
+        assert_eq!(load_address(Protocol::V2, 0x40000, 0x3FF00), [CMD_LOAD_ADDRESS, 0x80, 0x01, 0xFF, 0x80]);
+    }
+
+    #[test]
+    fn test_program_page() {
+        let page = [0x5A; 128];
+        let v1 = program_page(Protocol::V1, &page);
+        assert_eq!(v1[..4], [STK_PROG_PAGE, 0x00, 0x80, b'F']);
+        assert_eq!(v1[4..], page);
+
+        let page = [0xA5; 256];
+        let v2 = program_page(Protocol::V2, &page);
+        assert_eq!(v2[..10], [CMD_PROGRAM_FLASH, 0x01, 0x00, 0xC1, 10, 0x40, 0x4C, 0x20, 0, 0]);
+        assert_eq!(v2[10..], page);
+    }
+
+    #[test]
+    fn test_v1_answers() {
+        assert_eq!(answer_v1(&[STK_INSYNC, STK_OK]), Some(&[][..]));
+        assert_eq!(answer_v1(&[STK_INSYNC, 0x1E, 0x95, 0x0F, STK_OK]), Some(&[0x1E, 0x95, 0x0F][..]));
+        // STK_NOSYNC, and a reply cut short.
+        assert_eq!(answer_v1(&[0x15, STK_OK]), None);
+        assert_eq!(answer_v1(&[STK_INSYNC, 0x1E, 0x95]), None);
+    }
+
+    #[test]
+    fn test_v2_messages() {
+        assert_eq!(message_v2(0, &[CMD_SIGN_ON]), [MESSAGE_START, 0x00, 0x00, 0x01, TOKEN, CMD_SIGN_ON, 0x15]);
+
+        let mut body = vec![CMD_SIGN_ON, STATUS_CMD_OK, 8];
+        body.extend(b"AVRISP_2");
+        let answer = message_v2(7, &body);
+        assert_eq!(answer_v2(7, CMD_SIGN_ON, &answer), Ok(body.clone()));
+        assert_eq!(answer_v2(8, CMD_SIGN_ON, &answer), Err(AnswerV2::Garbled));
+
+        let mut corrupted = answer.clone();
+        corrupted[6] ^= 0x01;
+        assert_eq!(answer_v2(7, CMD_SIGN_ON, &corrupted), Err(AnswerV2::Garbled));
+        assert_eq!(answer_v2(7, CMD_SIGN_ON, &answer[..answer.len() - 1]), Err(AnswerV2::Garbled));
+
+        let failed = message_v2(7, &[CMD_READ_FLASH, 0xC0]);
+        assert_eq!(answer_v2(7, CMD_READ_FLASH, &failed), Err(AnswerV2::Failed));
+        assert_eq!(answer_v2(7, CMD_SIGN_ON, &message_v2(7, &[CMD_READ_FLASH, STATUS_CMD_OK])), Err(AnswerV2::Failed));
+    }
+}
@@ -0,0 +1,150 @@
+// ─────────────────────────────────────────────────────────────────────────────
+//  tsuki-flash :: flash :: stm32  —  STM32 programmers
+//
+//  Which one depends on the board (`Stm32Upload`):
+//    Serial  stm32flash, ROM bootloader on USART1 (BOOT0 jumper set to 1)
+//    Dfu     dfu-util,   ROM bootloader over USB (hold BOOT0, tap NRST)
+//    StLink  copy the .bin onto the on-board ST-Link's drive
+// ─────────────────────────────────────────────────────────────────────────────
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+use crate::boards::{Board, Stm32Upload, Toolchain};
+use crate::error::{FlashError, Result};
+use crate::process::{self, Stage};
+use super::uf2::{mount_points, wait_for};
+
+/// Where the STM32's internal flash is mapped.
+pub const FLASH_BASE: u32 = 0x0800_0000;
+/// USB id of the STM32 ROM bootloader in DFU mode.
+const DFU_ID: &str = "0483:df11";
+
+const SERIAL_HINT: &str = "set the BOOT0 jumper to 1 and press reset before uploading";
+const DFU_HINT:    &str = "hold BOOT0 and tap NRST to put the board in DFU mode";
+
+/// stm32flash checks what it writes; over DFU the flash is only read back
+/// on `verify`, and the ST-Link drive can't be read at all.
+pub fn flash(bin: &Path, port: &str, board: &Board, baud: u32, verify: bool, verbose: bool) -> Result<()> {
+    let Toolchain::Stm32 { upload, .. } = &board.toolchain else {
+        return Err(FlashError::Other("Not an STM32 board".into()));
+    };
+    match upload {
+        Stm32Upload::Serial => serial(bin, port, baud, verbose),
+        Stm32Upload::Dfu    => dfu(bin, verify, verbose),
+        Stm32Upload::StLink => stlink(bin, verbose),
+    }
+}
+
+/// Read the whole flash through the ROM bootloader.
+pub fn read(port: &str, board: &Board, baud: u32, verbose: bool) -> Result<Vec<u8>> {
+    let Toolchain::Stm32 { upload, .. } = &board.toolchain else {
+        return Err(FlashError::Other("Not an STM32 board".into()));
+    };
+    let region = format!("{:#010x}:{}", FLASH_BASE, board.flash_kb * 1024);
+    let out = super::read::scratch("stm32");
+    match upload {
+        Stm32Upload::Serial => {
+            let mut cmd = Command::new("stm32flash");
+            cmd.args(["-b", &baud.to_string(), "-S", &region, "-r"]).arg(&out).arg(port);
+            run("stm32flash", cmd, port, verbose, SERIAL_HINT)?;
+        }
+        Stm32Upload::Dfu => {
+            let mut cmd = Command::new("dfu-util");
+            cmd.args(["-a", "0", "-d", DFU_ID, "-s", &region, "-U"]).arg(&out);
+            run("dfu-util", cmd, "DFU", verbose, DFU_HINT)?;
+        }
+        Stm32Upload::StLink => return Err(FlashError::Other("the ST-Link drive can't be read back".into())),
+    }
+    super::read::take(&out)
+}
+
+fn serial(bin: &Path, port: &str, baud: u32, verbose: bool) -> Result<()> {
+    let mut cmd = Command::new("stm32flash");
+    cmd.args(["-b", &baud.to_string(), "-w"])
+        .arg(bin)
+        .args(["-v", "-g", &format!("{:#010x}", FLASH_BASE), port]);
+    run("stm32flash", cmd, port, verbose, SERIAL_HINT)
+}
+
+/// With `verify`, the image is uploaded back before leaving DFU mode, by
+/// the same command that leaves it.
+fn dfu(bin: &Path, verify: bool, verbose: bool) -> Result<()> {
+    let leave = if verify { "" } else { ":leave" };
+    let mut cmd = Command::new("dfu-util");
+    cmd.args(["-a", "0", "-d", DFU_ID, "-s", &format!("{:#010x}{}", FLASH_BASE, leave), "-D"])
+        .arg(bin);
+    run("dfu-util", cmd, "DFU", verbose, DFU_HINT)?;
+    if !verify {
+        return Ok(());
+    }
+
+    let image = std::fs::read(bin)?;
+    let out = super::read::scratch("dfu");
+    let mut cmd = Command::new("dfu-util");
+    cmd.args(["-a", "0", "-d", DFU_ID, "-s", &format!("{:#010x}:{}:leave", FLASH_BASE, image.len()), "-U"])
+        .arg(&out);
+    run("dfu-util", cmd, "DFU", verbose, DFU_HINT)?;
+    if let Some(at) = super::read::compare(FLASH_BASE, &image, &super::read::take(&out)?) {
+        return Err(FlashError::FlashFailed { port: "DFU".into(), output: format!("verification failed at {:#010x}", at) });
+    }
+    if verbose { eprintln!("  [dfu] {} bytes read back and verified", image.len()); }
+    Ok(())
+}
+
+/// Run a programmer, turning a missing binary or a failure into a FlashError.
+fn run(tool: &str, mut cmd: Command, port: &str, verbose: bool, hint: &str) -> Result<()> {
+    let out = process::output(&mut cmd, Stage::Upload).map_err(|e| match e {
+        FlashError::Io(_) => FlashError::ToolchainNotFound(
+            format!("{} not found — install it from your package manager", tool)),
+        e => e,
+    })?;
+
+    if !out.status.success() {
+        let stderr = String::from_utf8_lossy(&out.stderr).to_string();
+        let stdout = String::from_utf8_lossy(&out.stdout).to_string();
+        return Err(FlashError::FlashFailed {
+            port:   port.to_owned(),
+            output: format!("{}\n{}\n{}", stderr, stdout, hint).trim().to_owned(),
+        });
+    }
+    if verbose {
+        eprint!("{}", String::from_utf8_lossy(&out.stdout));
+    }
+    Ok(())
+}
+
+/// The ST-Link programs whatever is copied to its drive, then remounts it,
+/// with a FAIL.TXT on it if that went wrong.
+fn stlink(bin: &Path, verbose: bool) -> Result<()> {
+    let fail = |output: String| FlashError::FlashFailed { port: "ST-Link".into(), output };
+    let drive = find_stlink_drive().ok_or_else(|| fail(
+        "no ST-Link drive found — is the board plugged in over its ST-Link USB port?".into()))?;
+
+    let dest = drive.join(bin.file_name().unwrap_or_default());
+    if verbose { eprintln!("  [st-link] copying {} → {}", bin.display(), dest.display()); }
+    let data = std::fs::read(bin)?;
+    let mut file = std::fs::File::create(&dest)
+        .map_err(|e| fail(format!("cannot write to {}: {}", drive.display(), e)))?;
+    file.write_all(&data).and_then(|()| file.sync_all())
+        .map_err(|e| fail(format!("copying to {} failed: {}", drive.display(), e)))?;
+    drop(file);
+
+    let remounted = wait_for(Duration::from_secs(10), || {
+        (!dest.exists() && drive.join("MBED.HTM").exists()).then_some(())
+    });
+    if let Ok(reason) = std::fs::read_to_string(drive.join("FAIL.TXT")) {
+        return Err(fail(reason.trim().to_owned()));
+    }
+    if remounted.is_none() && verbose {
+        eprintln!("  [st-link] {} has not remounted yet", drive.display());
+    }
+    Ok(())
+}
+
+/// An ST-Link drive is recognised by the MBED.HTM it carries.
+fn find_stlink_drive() -> Option<PathBuf> {
+    mount_points().into_iter().find(|dir| dir.join("MBED.HTM").is_file())
+}
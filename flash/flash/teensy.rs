@@ -0,0 +1,70 @@
+// ─────────────────────────────────────────────────────────────────────────────
+//  tsuki-flash :: flash :: teensy  —  PJRC Teensy (HalfKay bootloader)
+//
+//  A Teensy running a USB-serial sketch reboots into HalfKay when its port
+//  is opened at 134 baud; one running anything else needs its program
+//  button pressed. teensy_loader_cli then finds the HalfKay HID device,
+//  writes the .hex and reboots the board.
+// ─────────────────────────────────────────────────────────────────────────────
+
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use crate::boards::{Board, Toolchain};
+use crate::error::{FlashError, Result};
+use crate::process::{self, Stage};
+use crate::serial::Serial;
+
+/// The baud rate the Teensy cores treat as "reboot into the bootloader".
+const REBOOT_BAUD: u32 = 134;
+/// How long to wait for HalfKay to show up before giving up.
+const LOADER_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub fn flash(hex: &Path, port: &str, board: &Board, verbose: bool) -> Result<()> {
+    let Toolchain::Teensy { model, .. } = &board.toolchain else {
+        return Err(FlashError::Other("Not a Teensy board".into()));
+    };
+    let loader = if cfg!(windows) { "teensy_loader_cli.exe" } else { "teensy_loader_cli" };
+    if process::status(Command::new(loader).arg("--help").stdout(Stdio::null()).stderr(Stdio::null()), Stage::Upload).is_err() {
+        return Err(FlashError::ToolchainNotFound(
+            format!("teensy_loader_cli not found — {}", crate::doctor::INSTALL_TEENSY_LOADER)
+        ));
+    }
+
+    // No port: the board is already in HalfKay, or the user will press the
+    // button while the loader waits.
+    if !port.is_empty() {
+        if verbose { eprintln!("  [teensy] 134-baud reboot on {}", port); }
+        match Serial::open(port, REBOOT_BAUD) {
+            Ok(serial) => drop(serial),
+            Err(e) if verbose => eprintln!("  [teensy] no reboot ({}); waiting for the button", e),
+            Err(_) => {}
+        }
+    }
+
+    let mut cmd = Command::new(loader);
+    cmd.arg(format!("--mcu={}", model)).arg("-w");
+    if verbose {
+        cmd.arg("-v");
+    }
+    cmd.arg(hex);
+
+    let label = if port.is_empty() { "HalfKay".to_owned() } else { port.to_owned() };
+    let out = match process::output_within(&mut cmd, Stage::Upload, LOADER_TIMEOUT) {
+        Err(FlashError::Timeout { secs, .. }) => return Err(FlashError::FlashFailed {
+            port:   label,
+            output: format!("no Teensy in bootloader mode after {}s\n\
+                             Hint: press the program button on the board", secs),
+        }),
+        res => res?,
+    };
+    let output = format!("{}\n{}",
+        String::from_utf8_lossy(&out.stderr), String::from_utf8_lossy(&out.stdout)).trim().to_owned();
+
+    if !out.status.success() {
+        return Err(FlashError::FlashFailed { port: label, output });
+    }
+    if verbose { eprintln!("{}", output); }
+    Ok(())
+}
@@ -0,0 +1,138 @@
+// ─────────────────────────────────────────────────────────────────────────────
+//  tsuki-flash :: flash :: uf2  —  RP2040 upload over the BOOTSEL drive
+//
+//  In its boot ROM the RP2040 shows up as a USB drive (RPI-RP2) and flashes
+//  any .uf2 file copied onto it, then reboots. A board running an Arduino-Pico
+//  sketch is put there by a "1200-baud touch": opening its serial port at
+//  1200 baud and closing it again.
+// ─────────────────────────────────────────────────────────────────────────────
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::error::{FlashError, Result};
+use crate::serial;
+
+/// Volume label of the RP2040 boot ROM's drive.
+pub const DRIVE_LABEL: &str = "RPI-RP2";
+
+const UF2_MAGIC_START0: u32 = 0x0A32_4655;
+const UF2_MAGIC_START1: u32 = 0x9E5D_5157;
+
+const APPEAR_TIMEOUT:      Duration = Duration::from_secs(10);
+const REBOOT_TIMEOUT:      Duration = Duration::from_secs(10);
+const REENUMERATE_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub fn flash(firmware: &Path, port: &str, verbose: bool) -> Result<()> {
+    let fail = |output: String| FlashError::FlashFailed {
+        port: if port.is_empty() { DRIVE_LABEL.into() } else { port.to_owned() },
+        output,
+    };
+
+    let drive = boot_drive(port, verbose)?;
+
+    let name = firmware.file_name().map(|n| n.to_os_string()).unwrap_or_else(|| "firmware.uf2".into());
+    let dest = drive.join(name);
+    if verbose { eprintln!("  [uf2] copying {} → {}", firmware.display(), dest.display()); }
+    let data = std::fs::read(firmware)?;
+    let mut file = std::fs::File::create(&dest)
+        .map_err(|e| fail(format!("cannot write to {}: {}", drive.display(), e)))?;
+    file.write_all(&data).and_then(|()| file.sync_all())
+        .map_err(|e| fail(format!("copying to {} failed: {}", drive.display(), e)))?;
+    drop(file);
+
+    // The drive goes away when the boot ROM has written the flash and
+    // rebooted into the sketch, whose serial port then comes back.
+    if wait_for(REBOOT_TIMEOUT, || (!drive.join("INFO_UF2.TXT").exists()).then_some(())).is_none() {
+        return Err(fail(format!("{} is still mounted: the board did not reboot after the copy", drive.display())));
+    }
+    if !port.is_empty() {
+        let back = wait_for(REENUMERATE_TIMEOUT, || Path::new(port).exists().then_some(()));
+        if back.is_none() && verbose {
+            eprintln!("  [uf2] {} has not come back yet", port);
+        }
+    }
+    Ok(())
+}
+
+/// Read the whole flash off the boot ROM's drive, which shows it as
+/// CURRENT.UF2. The board stays in BOOTSEL mode afterwards.
+pub fn read(port: &str, verbose: bool) -> Result<(u32, Vec<u8>)> {
+    let drive = boot_drive(port, verbose)?;
+    let current = drive.join("CURRENT.UF2");
+    if verbose { eprintln!("  [uf2] reading {}", current.display()); }
+    let uf2 = std::fs::read(&current)?;
+
+    // 512-byte blocks: target address at 12, payload size at 16, payload at 32.
+    let word = |b: &[u8], at: usize| u32::from_le_bytes([b[at], b[at + 1], b[at + 2], b[at + 3]]);
+    let blocks: Vec<(u32, &[u8])> = uf2.chunks_exact(512)
+        .filter(|b| word(b, 0) == UF2_MAGIC_START0 && word(b, 4) == UF2_MAGIC_START1)
+        .map(|b| (word(b, 12), &b[32..32 + (word(b, 16) as usize).min(476)]))
+        .collect();
+    let base = blocks.iter().map(|(addr, _)| *addr).min().ok_or_else(|| FlashError::Other(
+        format!("{} holds no UF2 blocks", current.display())))?;
+    let end = blocks.iter().map(|(addr, data)| addr + data.len() as u32).max().unwrap_or(base);
+
+    let mut image = vec![0xFF; (end - base) as usize];
+    for (addr, data) in blocks {
+        let at = (addr - base) as usize;
+        image[at..at + data.len()].copy_from_slice(data);
+    }
+    Ok((base, image))
+}
+
+/// The boot ROM's drive, after resetting the board on `port` into it if it
+/// isn't there yet.
+fn boot_drive(port: &str, verbose: bool) -> Result<PathBuf> {
+    let fail = |output: String| FlashError::FlashFailed {
+        port: if port.is_empty() { DRIVE_LABEL.into() } else { port.to_owned() },
+        output,
+    };
+
+    match find_drive() {
+        Some(drive) => Ok(drive),
+        None if port.is_empty() => Err(fail(format!(
+            "no {} drive found and no serial port to reset the board through\n\
+             hold BOOTSEL while plugging the board in, or pass --port", DRIVE_LABEL))),
+        None => {
+            if verbose { eprintln!("  [uf2] 1200-baud touch on {}", port); }
+            serial::touch_1200(port)?;
+            wait_for(APPEAR_TIMEOUT, find_drive).ok_or_else(|| fail(format!(
+                "the {} drive did not appear after the 1200-baud reset of {}\n\
+                 hold BOOTSEL while plugging the board in, then retry", DRIVE_LABEL, port)))
+        }
+    }
+}
+
+/// The mount point of the boot ROM's drive, recognised by its INFO_UF2.TXT.
+pub fn find_drive() -> Option<PathBuf> {
+    mount_points().into_iter().find(|dir| {
+        std::fs::read_to_string(dir.join("INFO_UF2.TXT"))
+            .is_ok_and(|info| info.contains(DRIVE_LABEL))
+    })
+}
+
+pub(super) fn mount_points() -> Vec<PathBuf> {
+    if cfg!(windows) {
+        return (b'D'..=b'Z').map(|d| PathBuf::from(format!("{}:\\", d as char))).collect();
+    }
+    if cfg!(target_os = "macos") {
+        return std::fs::read_dir("/Volumes").map(|d| d.flatten().map(|e| e.path()).collect())
+            .unwrap_or_default();
+    }
+    // Mount points in /proc/mounts escape spaces and tabs as octal.
+    std::fs::read_to_string("/proc/mounts").unwrap_or_default().lines()
+        .filter_map(|l| l.split(' ').nth(1))
+        .map(|m| PathBuf::from(m.replace("\\040", " ").replace("\\011", "\t")))
+        .collect()
+}
+
+pub(super) fn wait_for<T>(timeout: Duration, mut probe: impl FnMut() -> Option<T>) -> Option<T> {
+    let start = Instant::now();
+    loop {
+        if let Some(v) = probe() { return Some(v) }
+        if start.elapsed() >= timeout { return None }
+        std::thread::sleep(Duration::from_millis(200));
+    }
+}
@@ -0,0 +1,87 @@
+// ─────────────────────────────────────────────────────────────────────────────
+//  tsuki-flash :: flash :: updi  —  megaAVR / tinyAVR programmer
+//
+//  These chips have no serial bootloader by default: avrdude writes flash
+//  and fuses over their one-wire UPDI pin, through the Nano Every's on-board
+//  jtag2updi bridge or a USB-serial adapter (serialupdi). The fuses carry the
+//  clock choice, so they are written on every upload.
+// ─────────────────────────────────────────────────────────────────────────────
+
+use std::path::Path;
+use std::process::Command;
+use std::time::Duration;
+
+use crate::boards::{Board, Toolchain, Updi};
+use crate::error::{FlashError, Result};
+use crate::serial;
+use super::avrdude::{avrdude_conf, find_avrdude, run};
+
+/// Upload `hex` to a UPDI board whose firmware was built for `f_cpu` Hz.
+pub fn flash(hex: &Path, port: &str, board: &Board, f_cpu: u32, baud: u32, verbose: bool) -> Result<()> {
+    let (mut cmd, mcu) = command(port, board, baud, verbose)?;
+    cmd.args(["-e", "-D", "-U", &format!("flash:w:{}:i", hex.display())]);
+    for (fuse, value) in fuses(mcu, f_cpu) {
+        cmd.args(["-U", &format!("fuse{}:w:{:#04x}:m", fuse, value)]);
+    }
+    run(cmd, port)
+}
+
+/// Read the whole flash over UPDI.
+pub fn read(port: &str, board: &Board, baud: u32, verbose: bool) -> Result<Vec<u8>> {
+    let (mut cmd, _) = command(port, board, baud, verbose)?;
+    let out = super::read::scratch("updi");
+    cmd.args(["-U", &format!("flash:r:{}:r", out.display())]);
+    run(cmd, port)?;
+    super::read::take(&out)
+}
+
+/// avrdude set up to talk UPDI to the board's chip, and that chip; the
+/// caller adds the `-U` operations.
+fn command(port: &str, board: &Board, baud: u32, verbose: bool) -> Result<(Command, &'static str)> {
+    let Toolchain::MegaAvr { mcu, updi, .. } = &board.toolchain else {
+        return Err(FlashError::Other("Not a megaAVR board".into()));
+    };
+
+    let (programmer, default_baud) = match updi {
+        Updi::Jtag2Updi => {
+            // The bridge only talks UPDI after a 1200-baud touch.
+            if verbose { eprintln!("  [updi] 1200-baud touch on {}", port); }
+            serial::touch_1200(port)?;
+            std::thread::sleep(Duration::from_millis(500));
+            ("jtag2updi", 115_200)
+        }
+        Updi::SerialUpdi => ("serialupdi", 230_400),
+    };
+    let baud = if baud > 0 { baud } else { default_baud };
+
+    let avrdude = find_avrdude();
+    let mut cmd = Command::new(&avrdude);
+    cmd.args([
+        "-C", &avrdude_conf(&avrdude),
+        "-p", mcu,
+        "-c", programmer,
+        "-P", port,
+        "-b", &baud.to_string(),
+    ]);
+
+    if verbose {
+        cmd.arg("-v");
+    } else {
+        cmd.args(["-q", "-q"]);
+    }
+
+    Ok((cmd, *mcu))
+}
+
+/// (fuse number, value) to write, as the cores' boards.txt set them: the
+/// oscillator the clock divides from, reset/UPDI pin config, start-up time,
+/// and no bootloader section (BOOTEND = 0).
+fn fuses(mcu: &str, f_cpu: u32) -> Vec<(u8, u8)> {
+    // OSCCFG: 0x02 runs the internal oscillator at 20 MHz, 0x01 at 16 MHz.
+    let osccfg = if 16_000_000 % f_cpu == 0 { 0x01 } else { 0x02 };
+    if mcu == "atmega4809" {
+        vec![(2, osccfg), (5, 0xC9), (8, 0x00)]
+    } else {
+        vec![(0, 0x00), (1, 0x00), (2, osccfg), (5, 0xF6), (6, 0x07), (8, 0x00)]
+    }
+}
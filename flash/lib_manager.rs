@@ -12,18 +12,26 @@
 //
 //    ~/.arduino15/libraries/<LibraryName>/<version>/
 //
+//  Inside a tsuki project with a `libs/` folder (or a `libraries_dir` in its
+//  tsuki_package.json) libraries go there instead, so each project can pin
+//  its own versions; compile looks there before the global folder.
+//
 //  The registry is cached locally at:
 //    ~/.arduino15/.tsuki_lib_index.json   (refreshed after CACHE_TTL_SECS)
 //
 //  Subcommands exposed via this module:
 //    tsuki-flash lib install <name> [--version x.y.z]
+//    tsuki-flash lib install https://github.com/<user>/<repo>[@tag]   (or <user>/<repo>)
 //    tsuki-flash lib search  <query>
 //    tsuki-flash lib list
 //    tsuki-flash lib info    <name>
+//    tsuki-flash lib remove  <name>
+//    tsuki-flash lib upgrade [<name>]
+//    tsuki-flash lib outdated
 // ─────────────────────────────────────────────────────────────────────────────
 
 use std::fs;
-use std::io::{self, Read, Write};
+use std::io;
 use std::path::{Path, PathBuf};
 use std::time::{SystemTime, UNIX_EPOCH};
 
@@ -31,6 +39,7 @@ use colored::Colorize;
 use serde::{Deserialize, Serialize};
 
 use crate::error::{FlashError, Result};
+use crate::output::{self, Format};
 
 // ─────────────────────────────────────────────────────────────────────────────
 //  Constants
@@ -42,6 +51,9 @@ const REGISTRY_URL: &str =
 /// Re-download the index after this many seconds (24 h).
 const CACHE_TTL_SECS: u64 = 86_400;
 
+/// The tsuki project manifest, whose folder may hold project-local libraries.
+const PROJECT_MANIFEST: &str = "tsuki_package.json";
+
 // ─────────────────────────────────────────────────────────────────────────────
 //  Registry data model  (subset of the Arduino JSON schema)
 // ─────────────────────────────────────────────────────────────────────────────
@@ -86,6 +98,10 @@ pub struct InstalledManifest {
     pub version:  String,
     pub url:      String,
     pub installed_at: u64, // unix timestamp
+    /// Where it came from when not the registry, e.g.
+    /// `https://github.com/adafruit/DHT-sensor-library@1.4.6`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source:   Option<String>,
 }
 
 // ─────────────────────────────────────────────────────────────────────────────
@@ -101,9 +117,16 @@ pub struct InstalledManifest {
 ///   4. Download the ZIP archive.
 ///   5. Extract into `<libs_root>/<LibraryName>/`.
 ///   6. Recursively install declared dependencies.
-pub fn install(name: &str, pin_version: Option<&str>, verbose: bool) -> Result<()> {
+///
+/// A GitHub repository (`https://github.com/user/Repo[@tag]` or
+/// `user/Repo[@tag]`) is installed from its source ZIP instead; see
+/// `install_github`. Returns the directory the library was installed in.
+pub fn install(name: &str, pin_version: Option<&str>, verbose: bool) -> Result<PathBuf> {
     let libs_root = libs_root()?;
-    install_inner(name, pin_version, &libs_root, verbose, 0)
+    match GitHubSource::parse(name, pin_version) {
+        Some(src) => install_github(&src, &libs_root, verbose),
+        None      => install_inner(name, pin_version, &libs_root, verbose, 0),
+    }
 }
 
 fn install_inner(
@@ -112,13 +135,14 @@ fn install_inner(
     libs_root: &Path,
     verbose: bool,
     depth: usize,
-) -> Result<()> {
+) -> Result<PathBuf> {
     let indent = "  ".repeat(depth);
 
     let index = load_index(verbose)?;
     let entry = resolve_entry(&index, name, pin_version)?;
 
     let install_dir = libs_root.join(&entry.name);
+    let mut upgrading = false;
 
     // ── Already installed at the right version? ───────────────────────────
     if let Some(installed) = read_manifest(&install_dir) {
@@ -132,9 +156,10 @@ fn install_inner(
                     entry.version.dimmed()
                 );
             }
-            return Ok(());
+            return Ok(install_dir);
         }
         // Different version → upgrade
+        upgrading = true;
         if verbose {
             println!(
                 "{}Upgrading {} {} → {}",
@@ -155,7 +180,9 @@ fn install_inner(
         entry.version.dimmed()
     );
 
-    let zip_bytes = download_zip(&entry.url, entry.checksum.as_deref(), verbose)?;
+    let archive_name = entry.archive_filename.clone()
+        .unwrap_or_else(|| format!("{}-{}.zip", entry.name.replace(' ', "_"), entry.version));
+    let zip_path = download_zip(&entry.url, &archive_name, entry.checksum.as_deref(), verbose)?;
 
     // ── Extract ───────────────────────────────────────────────────────────
     println!(
@@ -165,10 +192,22 @@ fn install_inner(
         entry.name.bold()
     );
 
-    extract_zip(&zip_bytes, &install_dir)?;
+    // The old version goes first, or its files the new one dropped would
+    // still be compiled.
+    if upgrading {
+        fs::remove_dir_all(&install_dir)?;
+    }
+    extract_zip(&zip_path, &install_dir)?;
+    let _ = fs::remove_file(&zip_path);
 
     // ── Write manifest ────────────────────────────────────────────────────
-    write_manifest(&install_dir, &entry)?;
+    write_manifest(&install_dir, &InstalledManifest {
+        name:         entry.name.clone(),
+        version:      entry.version.clone(),
+        url:          entry.url.clone(),
+        installed_at: now_secs(),
+        source:       None,
+    })?;
 
     println!(
         "{}{}  {} {}",
@@ -194,12 +233,167 @@ fn install_inner(
         }
     }
 
+    Ok(install_dir)
+}
+
+fn info_installed(dir: &Path, m: &InstalledManifest, format: Format) -> Result<()> {
+    if format.is_json() {
+        let prop = |key| library_property(dir, key);
+        // Comma-separated in library.properties; `depends` entries may
+        // carry a version constraint in parentheses.
+        let list = |key| prop(key).map(|v| v.split(',')
+            .map(|s| s.split('(').next().unwrap_or_default().trim().to_owned())
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>());
+        output::print_json(&serde_json::json!({
+            "name":          m.name,
+            "version":       m.version,
+            "source":        m.source,
+            "sentence":      prop("sentence"),
+            "maintainer":    prop("maintainer"),
+            "website":       prop("url"),
+            "architectures": list("architectures"),
+            "dependencies":  list("depends").unwrap_or_default().iter()
+                .map(|d| serde_json::json!({ "name": d, "version": null }))
+                .collect::<Vec<_>>(),
+            "installed":     m.version,
+            "path":          dir,
+        }));
+        return Ok(());
+    }
+    println!();
+    println!("  {}  {}", m.name.bold().cyan(), m.version.dimmed());
+    println!();
+    if let Some(s) = library_property(dir, "sentence") {
+        println!("  {}", s);
+        println!();
+    }
+
+    let key_val = |k: &str, v: &str| {
+        println!("  {:<16} {}", format!("{}:", k).dimmed(), v);
+    };
+    key_val("source", m.source.as_deref().unwrap_or("—"));
+    for key in ["maintainer", "url", "architectures", "depends"] {
+        if let Some(v) = library_property(dir, key) {
+            key_val(key, &v);
+        }
+    }
+    println!();
+    println!("  {}  installed at {}", "✓".green().bold(), dir.display().to_string().bold());
+    println!();
     Ok(())
 }
 
+// ─────────────────────────────────────────────────────────────────────────────
+//  GitHub installs
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// A library repository on GitHub, at a tag or branch (None = the default
+/// branch).
+#[derive(Debug)]
+struct GitHubSource {
+    owner:     String,
+    repo:      String,
+    reference: Option<String>,
+}
+
+impl GitHubSource {
+    /// `https://github.com/user/Repo[.git][@ref]`, `github.com/user/Repo…` or
+    /// `user/Repo[@ref]`; `--version` stands in for a missing `@ref`. None for
+    /// a registry name.
+    fn parse(spec: &str, pin_version: Option<&str>) -> Option<Self> {
+        let rest = spec.trim().trim_end_matches('/');
+        let rest = rest.strip_prefix("https://").or_else(|| rest.strip_prefix("http://")).unwrap_or(rest);
+        let path = rest.strip_prefix("github.com/");
+        if path.is_none() && (rest.contains(' ') || rest.contains(':')) {
+            return None;
+        }
+        let (path, reference) = match path.unwrap_or(rest).split_once('@') {
+            Some((p, r)) => (p, Some(r.to_owned())),
+            None         => (path.unwrap_or(rest), pin_version.map(str::to_owned)),
+        };
+        let (owner, repo) = path.split_once('/')?;
+        let repo = repo.trim_end_matches(".git");
+        let valid = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c));
+        (valid(owner) && valid(repo)).then(|| GitHubSource {
+            owner: owner.to_owned(),
+            repo:  repo.to_owned(),
+            reference,
+        })
+    }
+
+    fn zip_url(&self) -> String {
+        format!("https://github.com/{}/{}/archive/{}.zip",
+                self.owner, self.repo, self.reference.as_deref().unwrap_or("HEAD"))
+    }
+
+    /// The form recorded in the manifest, which `parse` reads back.
+    fn label(&self) -> String {
+        match &self.reference {
+            Some(r) => format!("https://github.com/{}/{}@{}", self.owner, self.repo, r),
+            None    => format!("https://github.com/{}/{}", self.owner, self.repo),
+        }
+    }
+}
+
+/// Download a repository's source ZIP, check it is an Arduino library and
+/// install it under the name its library.properties gives, then the
+/// registry libraries its `depends=` lists.
+fn install_github(src: &GitHubSource, libs_root: &Path, verbose: bool) -> Result<PathBuf> {
+    println!("{}  Downloading {}…", "↓".cyan().bold(), src.label().bold());
+    let archive_name = format!("{}-{}-{}.zip", src.owner, src.repo, src.reference.as_deref().unwrap_or("HEAD"));
+    let zip_path = download_zip(&src.zip_url(), &archive_name, None, verbose)?;
+
+    // Unpack aside first: the install dir depends on the library's name.
+    fs::create_dir_all(libs_root)?;
+    let staging = libs_root.join(format!(".tsuki_tmp_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&staging);
+    extract_zip(&zip_path, &staging)?;
+    let _ = fs::remove_file(&zip_path);
+
+    let Some(lib) = crate::compile::libraries::Library::at(&staging) else {
+        let _ = fs::remove_dir_all(&staging);
+        return Err(FlashError::Other(format!(
+            "{} is not an Arduino library (no library.properties or headers at its root)", src.label())));
+    };
+    let name = if staging.join("library.properties").is_file() { lib.name.clone() } else { src.repo.clone() };
+    let version = library_property(&staging, "version")
+        .or_else(|| src.reference.clone())
+        .unwrap_or_else(|| "HEAD".into());
+
+    let install_dir = libs_root.join(&name);
+    if install_dir.exists() {
+        fs::remove_dir_all(&install_dir)?;
+    }
+    fs::rename(&staging, &install_dir)?;
+    write_manifest(&install_dir, &InstalledManifest {
+        name:         name.clone(),
+        version:      version.clone(),
+        url:          src.zip_url(),
+        installed_at: now_secs(),
+        source:       Some(src.label()),
+    })?;
+    println!("{}  {} {}", "✓".green().bold(), name.bold(), version.dimmed());
+
+    if !lib.depends.is_empty() {
+        println!("  {} dependencies:", "↳".dimmed());
+    }
+    for dep in &lib.depends {
+        install_inner(dep, None, libs_root, verbose, 1)?;
+    }
+    Ok(install_dir)
+}
+
+/// A `key=value` line of the library.properties in `dir`.
+fn library_property(dir: &Path, key: &str) -> Option<String> {
+    fs::read_to_string(dir.join("library.properties")).ok()?
+        .lines()
+        .find_map(|l| l.split_once('=').filter(|(k, _)| k.trim() == key).map(|(_, v)| v.trim().to_owned()))
+}
+
 /// Search the registry for libraries matching `query` (case-insensitive
 /// substring match against name, sentence, category).
-pub fn search(query: &str, verbose: bool) -> Result<()> {
+pub fn search(query: &str, verbose: bool, format: Format) -> Result<()> {
     let index = load_index(verbose)?;
     let q = query.to_lowercase();
 
@@ -224,6 +418,15 @@ pub fn search(query: &str, verbose: bool) -> Result<()> {
         }
     }
 
+    if format.is_json() {
+        output::print_json(&hits.iter().map(|lib| serde_json::json!({
+            "name":        lib.name,
+            "version":     lib.version,
+            "sentence":    lib.sentence,
+            "category":    lib.category,
+        })).collect::<Vec<_>>());
+        return Ok(());
+    }
     if hits.is_empty() {
         println!("{} No libraries found matching '{}'", "!".yellow(), query);
         return Ok(());
@@ -253,10 +456,10 @@ pub fn search(query: &str, verbose: bool) -> Result<()> {
 }
 
 /// List all installed libraries (scans the libs_root directory).
-pub fn list() -> Result<()> {
+pub fn list(format: Format) -> Result<()> {
     let libs_root = libs_root()?;
 
-    if !libs_root.exists() {
+    if !libs_root.exists() && !format.is_json() {
         println!("{} No libraries installed yet.", "!".yellow());
         println!(
             "  Install one with: {}",
@@ -265,35 +468,52 @@ pub fn list() -> Result<()> {
         return Ok(());
     }
 
-    let mut entries: Vec<(String, String)> = Vec::new();
+    let mut entries: Vec<(String, String, String)> = Vec::new();
 
-    for dir in fs::read_dir(&libs_root)?.flatten() {
+    for dir in fs::read_dir(&libs_root).into_iter().flatten().flatten() {
         let path = dir.path();
-        if !path.is_dir() { continue; }
+        if !path.is_dir() || path.file_name().is_some_and(|n| n.to_string_lossy().starts_with('.')) { continue; }
 
         if let Some(m) = read_manifest(&path) {
-            entries.push((m.name, m.version));
+            entries.push((m.name, m.version, m.source.unwrap_or_default()));
         } else {
             // Best-effort: use directory name, version unknown
             let name = path.file_name()
                 .map(|n| n.to_string_lossy().to_string())
                 .unwrap_or_default();
-            entries.push((name, "?".into()));
+            entries.push((name, "?".into(), String::new()));
         }
     }
 
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+    if format.is_json() {
+        output::print_json(&serde_json::json!({
+            "path":      libs_root,
+            "libraries": entries.iter().map(|(name, version, source)| serde_json::json!({
+                "name":    name,
+                "version": version,
+                "source":  if source.is_empty() { None } else { Some(source) },
+            })).collect::<Vec<_>>(),
+        }));
+        return Ok(());
+    }
     if entries.is_empty() {
         println!("{} No libraries installed.", "!".yellow());
         return Ok(());
     }
 
-    entries.sort_by(|a, b| a.0.cmp(&b.0));
-
-    println!("{:<40}  {}", "LIBRARY".bold().underline(), "VERSION".bold().underline());
-    println!("{}", "─".repeat(55).dimmed());
+    if entries.iter().any(|e| !e.2.is_empty()) {
+        println!("{:<40}  {:<10}  {}", "LIBRARY".bold().underline(), "VERSION".bold().underline(),
+                 "SOURCE".bold().underline());
+        println!("{}", "─".repeat(90).dimmed());
+    } else {
+        println!("{:<40}  {}", "LIBRARY".bold().underline(), "VERSION".bold().underline());
+        println!("{}", "─".repeat(55).dimmed());
+    }
 
-    for (name, version) in &entries {
-        println!("{:<40}  {}", name.cyan(), version.dimmed());
+    for (name, version, source) in &entries {
+        println!("{:<40}  {:<10}  {}", name.cyan(), version.dimmed(), source.dimmed());
     }
 
     println!("\n  {} installed", entries.len());
@@ -301,14 +521,39 @@ pub fn list() -> Result<()> {
     Ok(())
 }
 
-/// Print detailed info about a library (latest version).
-pub fn info(name: &str, verbose: bool) -> Result<()> {
+/// Print detailed info about a library (latest version). One installed
+/// from GitHub is described from its own library.properties.
+pub fn info(name: &str, verbose: bool, format: Format) -> Result<()> {
+    if let Ok((dir, _)) = find_installed(name) {
+        if let Some(m @ InstalledManifest { source: Some(_), .. }) = read_manifest(&dir) {
+            return info_installed(&dir, &m, format);
+        }
+    }
+
     let index = load_index(verbose)?;
     let entry = resolve_entry(&index, name, None)?;
 
     let libs_root = libs_root()?;
     let installed = read_manifest(&libs_root.join(&entry.name));
 
+    if format.is_json() {
+        output::print_json(&serde_json::json!({
+            "name":          entry.name,
+            "version":       entry.version,
+            "sentence":      entry.sentence,
+            "paragraph":     entry.paragraph,
+            "category":      entry.category,
+            "maintainer":    entry.maintainer,
+            "website":       entry.website,
+            "architectures": entry.architectures,
+            "dependencies":  entry.dependencies.iter().flatten().map(|d| serde_json::json!({
+                "name": d.name, "version": d.version,
+            })).collect::<Vec<_>>(),
+            "installed":     installed.map(|m| m.version),
+        }));
+        return Ok(());
+    }
+
     println!();
     println!("  {}  {}", entry.name.bold().cyan(), entry.version.dimmed());
     println!();
@@ -364,6 +609,133 @@ pub fn info(name: &str, verbose: bool) -> Result<()> {
     Ok(())
 }
 
+/// Delete an installed library. Installed libraries that still declare it
+/// in their `depends=` are pointed out.
+pub fn remove(name: &str) -> Result<()> {
+    let (dir, label) = find_installed(name)?;
+    fs::remove_dir_all(&dir)?;
+    println!("{}  Removed {}", "✓".green().bold(), label.bold());
+
+    for (dir, m) in installed()? {
+        let Some(lib) = crate::compile::libraries::Library::at(&dir) else { continue };
+        if lib.depends.iter().any(|d| d.eq_ignore_ascii_case(&label)) {
+            println!("  {} {} depends on it", "!".yellow(), m.name.bold());
+        }
+    }
+    Ok(())
+}
+
+/// Reinstall `name` (or every installed library) at the registry's latest
+/// version, where that is newer than the installed one. A library installed
+/// from GitHub is fetched again from the same ref when named, and otherwise
+/// left alone.
+pub fn upgrade(name: Option<&str>, verbose: bool) -> Result<()> {
+    let libs_root = libs_root()?;
+    let targets: Vec<InstalledManifest> = match name {
+        Some(n) => {
+            let (dir, _) = find_installed(n)?;
+            vec![read_manifest(&dir).ok_or_else(|| FlashError::Other(format!(
+                "'{}' was not installed by tsuki-flash; reinstall it with: tsuki-flash lib install \"{}\"", n, n)))?]
+        }
+        None => installed()?.into_iter().map(|(_, m)| m).collect(),
+    };
+
+    let index = load_index(verbose)?;
+    let mut upgraded = 0;
+    for m in &targets {
+        if let Some(source) = &m.source {
+            match (name, GitHubSource::parse(source, None)) {
+                (Some(_), Some(src)) => {
+                    install_github(&src, &libs_root, verbose)?;
+                    upgraded += 1;
+                }
+                _ => if verbose { eprintln!("  [lib] skipping {} (from {})", m.name, source); },
+            }
+            continue;
+        }
+        let latest = match resolve_entry(&index, &m.name, None) {
+            Ok(e) => e,
+            Err(e) if name.is_none() => {
+                if verbose { eprintln!("  [lib] skipping {}: {}", m.name, e); }
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        if parse_semver(&latest.version) <= parse_semver(&m.version) {
+            if name.is_some() || verbose {
+                println!("{}  {} {} is up to date", "•".dimmed(), m.name.bold(), m.version.dimmed());
+            }
+            continue;
+        }
+        install_inner(&latest.name, Some(&latest.version), &libs_root, verbose, 0)?;
+        upgraded += 1;
+    }
+
+    if name.is_none() {
+        println!("\n  {} upgraded, {} checked", upgraded, targets.len());
+    }
+    Ok(())
+}
+
+/// List installed libraries the registry has a newer version of.
+pub fn outdated(verbose: bool) -> Result<()> {
+    let index = load_index(verbose)?;
+    let mut rows: Vec<(String, String, String)> = Vec::new();
+    for (_, m) in installed()? {
+        if m.source.is_some() { continue }
+        let Ok(latest) = resolve_entry(&index, &m.name, None) else { continue };
+        if parse_semver(&latest.version) > parse_semver(&m.version) {
+            rows.push((m.name, m.version, latest.version.clone()));
+        }
+    }
+
+    if rows.is_empty() {
+        println!("{} All installed libraries are up to date.", "✓".green().bold());
+        return Ok(());
+    }
+
+    rows.sort();
+    println!(
+        "{:<40} {:<12} {}",
+        "LIBRARY".bold().underline(),
+        "INSTALLED".bold().underline(),
+        "LATEST".bold().underline()
+    );
+    println!("{}", "─".repeat(65).dimmed());
+    for (name, have, latest) in &rows {
+        println!("{:<40} {:<12} {}", name.cyan(), have.dimmed(), latest.green());
+    }
+    println!("\n  {} outdated  →  {}", rows.len(), "tsuki-flash lib upgrade".bold());
+    Ok(())
+}
+
+/// Installed libraries with a tsuki-flash manifest, by directory.
+fn installed() -> Result<Vec<(PathBuf, InstalledManifest)>> {
+    let libs_root = libs_root()?;
+    let Ok(entries) = fs::read_dir(&libs_root) else { return Ok(Vec::new()) };
+    let mut out: Vec<(PathBuf, InstalledManifest)> = entries.flatten()
+        .map(|e| e.path())
+        .filter_map(|p| { let m = read_manifest(&p)?; Some((p, m)) })
+        .collect();
+    out.sort_by(|a, b| a.1.name.cmp(&b.1.name));
+    Ok(out)
+}
+
+/// The directory of installed library `name` — matched against manifest
+/// names, then directory names, case-insensitively — and its name.
+fn find_installed(name: &str) -> Result<(PathBuf, String)> {
+    let libs_root = libs_root()?;
+    if let Some((dir, m)) = installed()?.into_iter().find(|(_, m)| m.name.eq_ignore_ascii_case(name)) {
+        return Ok((dir, m.name));
+    }
+    fs::read_dir(&libs_root).into_iter().flatten().flatten()
+        .map(|e| e.path())
+        .find(|p| p.is_dir() && p.file_name().is_some_and(|n| n.to_string_lossy().eq_ignore_ascii_case(name)))
+        .map(|p| { let n = p.file_name().unwrap().to_string_lossy().into_owned(); (p, n) })
+        .ok_or_else(|| FlashError::Other(format!(
+            "Library '{}' is not installed in {}\n  See: tsuki-flash lib list", name, libs_root.display())))
+}
+
 // ─────────────────────────────────────────────────────────────────────────────
 //  Index loading & caching
 // ─────────────────────────────────────────────────────────────────────────────
@@ -385,26 +757,10 @@ fn load_index(verbose: bool) -> Result<LibraryIndex> {
     // (Re-)download the index.
     println!("{} Fetching Arduino library index…", "→".cyan());
 
-    let resp = ureq::get(REGISTRY_URL)
-        .call()
+    crate::download::to_file(REGISTRY_URL, &cache_path, None, verbose)
         .map_err(|e| FlashError::Other(format!("Failed to download library index: {}", e)))?;
 
-    // into_string() has a ~10 MB cap; library_index.json is ~20 MB, so we
-    // must stream the body manually.
-    let mut body_bytes: Vec<u8> = Vec::with_capacity(24 * 1024 * 1024);
-    resp.into_reader()
-        .read_to_end(&mut body_bytes)
-        .map_err(|e| FlashError::Other(format!("Failed to read library index response: {}", e)))?;
-
-    // Persist to cache.
-    if let Some(parent) = cache_path.parent() {
-        let _ = fs::create_dir_all(parent);
-    }
-    fs::write(&cache_path, &body_bytes)
-        .map_err(|e| FlashError::Other(format!("Failed to cache library index: {}", e)))?;
-
-    serde_json::from_slice::<LibraryIndex>(&body_bytes)
-        .map_err(|e| FlashError::Other(format!("Failed to parse library index: {}", e)))
+    parse_index_file(&cache_path)
 }
 
 fn parse_index_file(path: &Path) -> Result<LibraryIndex> {
@@ -474,59 +830,21 @@ fn resolve_entry<'a>(
 //  Download + extraction
 // ─────────────────────────────────────────────────────────────────────────────
 
-fn download_zip(url: &str, checksum: Option<&str>, verbose: bool) -> Result<Vec<u8>> {
-    if verbose {
-        eprintln!("  [lib] GET {}", url);
-    }
-
-    let resp = ureq::get(url)
-        .call()
-        .map_err(|e| FlashError::Other(format!("Download failed ({}): {}", url, e)))?;
-
-    let mut buf = Vec::new();
-    resp.into_reader()
-        .read_to_end(&mut buf)
-        .map_err(|e| FlashError::Other(format!("Failed to read download body: {}", e)))?;
-
-    // Verify SHA-256 checksum if provided.
-    if let Some(cs) = checksum {
-        verify_sha256(&buf, cs)?;
-    }
-
-    Ok(buf)
-}
-
-fn verify_sha256(data: &[u8], checksum_field: &str) -> Result<()> {
-    use sha2::{Sha256, Digest};
-
-    let expected_hex = checksum_field
-        .strip_prefix("SHA-256:")
-        .unwrap_or(checksum_field)
-        .trim()
-        .to_lowercase();
-
-    let mut hasher = Sha256::new();
-    hasher.update(data);
-    let actual = hex::encode(hasher.finalize());
-
-    if actual != expected_hex {
-        return Err(FlashError::Other(format!(
-            "Checksum mismatch!\n  expected: {}\n  actual:   {}",
-            expected_hex, actual
-        )));
-    }
-    Ok(())
+/// Download a library ZIP into `~/.arduino15/staging/libraries/<file_name>`
+/// (where arduino-cli stages them too) and return its path. It stays there
+/// until extracted, so an interrupted install resumes the download.
+fn download_zip(url: &str, file_name: &str, checksum: Option<&str>, verbose: bool) -> Result<PathBuf> {
+    let path = home_dir()?.join(".arduino15").join("staging").join("libraries").join(file_name);
+    crate::download::to_file(url, &path, checksum, verbose)?;
+    Ok(path)
 }
 
 /// Extract a ZIP archive into `dest_dir`.
 ///
 /// Arduino ZIPs always have a top-level directory named `<LibName>-<version>/`.
 /// We strip that prefix so the library lands directly at `dest_dir/`.
-fn extract_zip(data: &[u8], dest_dir: &Path) -> Result<()> {
-    use std::io::Cursor;
-
-    let cursor = Cursor::new(data);
-    let mut archive = zip::ZipArchive::new(cursor)
+fn extract_zip(zip_path: &Path, dest_dir: &Path) -> Result<()> {
+    let mut archive = zip::ZipArchive::new(fs::File::open(zip_path)?)
         .map_err(|e| FlashError::Other(format!("Failed to open ZIP: {}", e)))?;
 
     // Find the common top-level prefix to strip (e.g. "DHT_sensor_library-1.4.6/").
@@ -565,9 +883,9 @@ fn extract_zip(data: &[u8], dest_dir: &Path) -> Result<()> {
     Ok(())
 }
 
-fn find_zip_prefix(archive: &mut zip::ZipArchive<io::Cursor<&[u8]>>) -> Option<String> {
+fn find_zip_prefix<R: io::Read + io::Seek>(archive: &mut zip::ZipArchive<R>) -> Option<String> {
     // The first entry should be the top-level directory.
-    if archive.len() == 0 { return None; }
+    if archive.is_empty() { return None; }
     let first = archive.by_index(0).ok()?;
     let name = first.name().to_owned();
     // Check that it ends with '/' (is a directory).
@@ -590,14 +908,8 @@ fn find_zip_prefix(archive: &mut zip::ZipArchive<io::Cursor<&[u8]>>) -> Option<S
 //  Manifest helpers
 // ─────────────────────────────────────────────────────────────────────────────
 
-fn write_manifest(install_dir: &Path, entry: &LibraryEntry) -> Result<()> {
-    let m = InstalledManifest {
-        name:         entry.name.clone(),
-        version:      entry.version.clone(),
-        url:          entry.url.clone(),
-        installed_at: now_secs(),
-    };
-    let json = serde_json::to_string_pretty(&m)
+fn write_manifest(install_dir: &Path, m: &InstalledManifest) -> Result<()> {
+    let json = serde_json::to_string_pretty(m)
         .map_err(|e| FlashError::Other(e.to_string()))?;
     fs::write(install_dir.join(".tsuki_lib.json"), json)?;
     Ok(())
@@ -617,11 +929,48 @@ fn read_manifest(install_dir: &Path) -> Option<InstalledManifest> {
 ///
 /// Priority:
 ///   1. `TSUKI_LIBS_ROOT` env var  (explicit override)
-///   2. `~/.arduino15/libraries`   (arduino-cli compatible)
+///   2. The libraries dir of the project around the working directory
+///      (`project_libs_dir`)
+///   3. `~/.arduino15/libraries`   (arduino-cli compatible)
 pub fn libs_root() -> Result<PathBuf> {
     if let Ok(r) = std::env::var("TSUKI_LIBS_ROOT") {
         return Ok(PathBuf::from(r));
     }
+    if let Some(dir) = std::env::current_dir().ok().and_then(|cwd| project_libs_dir(&cwd)) {
+        return Ok(dir);
+    }
+    global_libs_root()
+}
+
+/// The libraries compile searches for a sketch in `sketch_dir`, in priority
+/// order: `TSUKI_LIBS_ROOT` alone when set, else the project's libraries
+/// dir and then the global one.
+pub fn search_roots(sketch_dir: &Path) -> Vec<PathBuf> {
+    if let Ok(r) = std::env::var("TSUKI_LIBS_ROOT") {
+        return vec![PathBuf::from(r)];
+    }
+    let project = std::fs::canonicalize(sketch_dir).ok().and_then(|d| project_libs_dir(&d));
+    project.into_iter().chain(global_libs_root().ok())
+        .filter(|d| d.is_dir())
+        .collect()
+}
+
+/// The project-local libraries dir of the tsuki project containing `start`:
+/// the manifest's `libraries_dir` (relative to the project), else its
+/// `libs/` folder if there is one. None outside a project.
+pub fn project_libs_dir(start: &Path) -> Option<PathBuf> {
+    let project = start.ancestors().find(|d| d.join(PROJECT_MANIFEST).is_file())?;
+    let configured = fs::read_to_string(project.join(PROJECT_MANIFEST)).ok()
+        .and_then(|s| serde_json::from_str::<serde_json::Value>(&s).ok())
+        .and_then(|m| m.get("libraries_dir")?.as_str().map(str::to_owned));
+    match configured {
+        Some(dir) => Some(project.join(dir)),
+        None => Some(project.join("libs")).filter(|d| d.is_dir()),
+    }
+}
+
+/// `~/.arduino15/libraries`, shared by every project.
+pub fn global_libs_root() -> Result<PathBuf> {
     let home = home_dir()?;
     Ok(home.join(".arduino15").join("libraries"))
 }
@@ -661,4 +1010,68 @@ fn quiet_mode() -> bool {
 
 fn parse_semver(s: &str) -> Vec<u32> {
     s.split('.').map(|p| p.parse::<u32>().unwrap_or(0)).collect()
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parts(spec: &str, pin: Option<&str>) -> Option<(String, String, Option<String>)> {
+        GitHubSource::parse(spec, pin).map(|s| (s.owner, s.repo, s.reference))
+    }
+
+    #[test]
+    fn test_github_urls() {
+        let dht = Some(("adafruit".to_owned(), "DHT-sensor-library".to_owned(), None));
+        assert_eq!(parts("https://github.com/adafruit/DHT-sensor-library", None), dht);
+        assert_eq!(parts("http://github.com/adafruit/DHT-sensor-library/", None), dht);
+        assert_eq!(parts("github.com/adafruit/DHT-sensor-library.git", None), dht);
+        assert_eq!(parts(" adafruit/DHT-sensor-library ", None), dht);
+
+        let tagged = Some(("bblanchon".to_owned(), "ArduinoJson".to_owned(), Some("v7.0.4".to_owned())));
+        assert_eq!(parts("https://github.com/bblanchon/ArduinoJson.git@v7.0.4", None), tagged);
+        assert_eq!(parts("bblanchon/ArduinoJson", Some("v7.0.4")), tagged);
+        // An explicit `@ref` wins over `--version`.
+        assert_eq!(parts("bblanchon/ArduinoJson@v7.0.4", Some("6.21.5")), tagged);
+    }
+
+    #[test]
+    fn test_not_github() {
+        for spec in [
+            "Servo",
+            "Adafruit GFX Library",
+            "Adafruit/GFX Library",
+            "https://github.com/adafruit",
+            "https://github.com/adafruit/DHT/tree/master",
+            "https://gitlab.com/user/repo",
+            "git@github.com:user/repo.git",
+            "/repo",
+            "user/",
+        ] {
+            assert!(GitHubSource::parse(spec, None).is_none(), "{}", spec);
+        }
+    }
+
+    #[test]
+    fn test_zip_url_and_label() {
+        let head = GitHubSource::parse("adafruit/RTClib", None).unwrap();
+        assert_eq!(head.zip_url(), "https://github.com/adafruit/RTClib/archive/HEAD.zip");
+        assert_eq!(head.label(), "https://github.com/adafruit/RTClib");
+
+        let tag = GitHubSource::parse("adafruit/RTClib@2.1.3", None).unwrap();
+        assert_eq!(tag.zip_url(), "https://github.com/adafruit/RTClib/archive/2.1.3.zip");
+        // The label recorded in the manifest parses back to the same source.
+        let again = GitHubSource::parse(&tag.label(), None).unwrap();
+        assert_eq!((again.owner, again.repo, again.reference), (tag.owner, tag.repo, tag.reference));
+    }
+
+    #[test]
+    fn test_library_property() {
+        let dir = std::env::temp_dir().join(format!("tsuki-libprop-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        assert_eq!(library_property(&dir, "version"), None);
+        fs::write(dir.join("library.properties"), "name=RTClib\nversion\n version = 2.1.3 \n").unwrap();
+        assert_eq!(library_property(&dir, "version").as_deref(), Some("2.1.3"));
+        assert_eq!(library_property(&dir, "depends"), None);
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -3,23 +3,39 @@
 // ─────────────────────────────────────────────────────────────────────────────
 
 mod boards;
+mod clean;
 mod compile;
 mod detect;
+mod doctor;
+mod download;
 mod error;
+mod events;
 mod flash;
 mod lib_manager;
+mod mdns;
 mod modules;
+mod monitor;
+mod output;
+mod permissions;
+mod process;
 mod sdk;
+mod serial;
 
 use clap::{Args, Parser, Subcommand};
 use colored::Colorize;
-use std::path::PathBuf;
-use std::time::Instant;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
 use boards::Board;
-use compile::{compile, CompileRequest};
+use compile::{compile, CompileRequest, ExtraFlags};
+use compile::size::{self, Budget, Limit};
 use flash::{flash, FlashRequest};
+use flash::isp::{self, Fuses, Isp, Programmer};
+use flash::ota::OtaTarget;
+use flash::read::{self, ReadRequest};
 use error::{FlashError, Result};
+use events::MessageFormat;
+use output::Format;
 
 // ─────────────────────────────────────────────────────────────────────────────
 //  CLI
@@ -43,6 +59,15 @@ struct Cli {
 
     #[arg(long, global = true)]
     no_color: bool,
+
+    /// Output of detect, boards, sdk-info, info, lib, modules list and compile
+    #[arg(long, global = true, value_enum, default_value_t = Format::Text)]
+    format: Format,
+
+    /// Time limit for each compiler, linker, programmer or installer run: SECS
+    /// for all, or compile=, link=, upload=, install=SECS (0: none) [default: 180/300/300/600]
+    #[arg(long, global = true, value_name = "[STAGE=]SECS")]
+    timeout: Vec<process::Timeout>,
 }
 
 #[derive(Subcommand)]
@@ -53,10 +78,24 @@ enum Cmd {
     Upload(UploadArgs),
     /// Compile then immediately upload
     Run(RunArgs),
+    /// Save the firmware on a board to a file (.hex, or raw .bin)
+    Read(ReadArgs),
+    /// Show what takes up flash and RAM in a build, against the board's limits
+    Size(SizeArgs),
+    /// Compare the sizes of two linked firmwares (.elf)
+    SizeDiff(SizeDiffArgs),
+    /// Show what a build was made from: versions, flags, commit, hashes
+    Info(InfoArgs),
+    /// Print a board's serial output, or plot the numbers it prints
+    Monitor(MonitorArgs),
     /// Detect connected boards / serial ports
-    Detect,
+    Detect(DetectArgs),
     /// List all supported boards
     Boards,
+    /// Write a board's bootloader and fuses through an ISP programmer (AVR)
+    BurnBootloader(IspArgs),
+    /// Read or write an AVR board's fuses through an ISP programmer
+    Fuses(FusesArgs),
     /// Print SDK discovery paths for a board
     SdkInfo {
         #[arg(default_value = "uno")]
@@ -66,6 +105,19 @@ enum Cmd {
     Lib(LibArgs),
     /// Manage Arduino SDK cores via tsuki-modules  (no arduino-cli needed)
     Modules(ModulesArgs),
+    /// Inspect or empty the global object cache  (stats / clear)
+    Cache(CacheArgs),
+    /// Delete a build dir's objects and caches, keeping the firmware
+    Clean(CleanArgs),
+    /// Check cores, compilers, upload tools and serial port access, and
+    /// print how to fix what's missing
+    Doctor,
+    /// Install udev rules so boards can be opened without sudo (Linux)
+    SetupPermissions {
+        /// Print the rules and the commands to install them instead
+        #[arg(long)]
+        print: bool,
+    },
 }
 
 // ── Compile args ──────────────────────────────────────────────────────────────
@@ -75,6 +127,11 @@ struct CompileArgs {
     #[arg(long, short = 'b')]
     board: String,
 
+    /// Pick a choice from one of the board's menus, e.g. `cpu=atmega328old`
+    /// (repeatable; `tsuki-flash boards` lists them)
+    #[arg(long, value_parser = boards::parse_option)]
+    board_option: Vec<(String, String)>,
+
     #[arg(long)]
     sketch: PathBuf,
 
@@ -94,6 +151,74 @@ struct CompileArgs {
     /// Use the tsuki-modules SDK store instead of .arduino15
     #[arg(long, default_value_t = false)]
     use_modules: bool,
+
+    /// Share compiled core and library objects across build dirs and projects
+    /// (~/.cache/tsuki/objcache; see `tsuki-flash cache`)
+    #[arg(long, default_value_t = false)]
+    obj_cache: bool,
+
+    /// CPU clock in MHz (overrides the board default F_CPU)
+    #[arg(long)]
+    cpu_mhz: Option<u32>,
+
+    /// ESP32 partition table: a scheme from the core (`default`, `huge_app`,
+    /// `min_spiffs`, `no_ota`, …) or a partitions .csv
+    #[arg(long)]
+    partition_scheme: Option<String>,
+
+    /// Flash budget, as a share of the board (`90%`) or a size (`28KB`)
+    #[arg(long)]
+    budget_flash: Option<Limit>,
+
+    /// RAM budget, as a share of the board or a size
+    #[arg(long)]
+    budget_ram: Option<Limit>,
+
+    /// Flash budget for one module, e.g. `LiquidCrystal=4KB` (repeatable)
+    #[arg(long, value_parser = size::parse_module_limit)]
+    budget_module: Vec<(String, Limit)>,
+
+    #[command(flatten)]
+    flags: FlagArgs,
+
+    /// `json-lines`: report progress and diagnostics as JSON events on
+    /// stdout, one per line
+    #[arg(long, value_enum, default_value_t = MessageFormat::Human)]
+    message_format: MessageFormat,
+}
+
+/// Flags added to the generated ones; each value is split at spaces.
+#[derive(Args)]
+struct FlagArgs {
+    /// Extra flags for C sources (repeatable)
+    #[arg(long, allow_hyphen_values = true)]
+    cflags: Vec<String>,
+
+    /// Extra flags for C++ sources (repeatable)
+    #[arg(long, allow_hyphen_values = true)]
+    cxxflags: Vec<String>,
+
+    /// Define for every source, the core's included: `KEY` or `KEY=VAL`
+    /// (repeatable)
+    #[arg(long)]
+    defines: Vec<String>,
+
+    /// Extra linker flags, e.g. `-Wl,-u,vfprintf -lprintf_flt` for float
+    /// printf on AVR (repeatable)
+    #[arg(long, allow_hyphen_values = true)]
+    ldflags: Vec<String>,
+}
+
+impl FlagArgs {
+    fn into_extra(self) -> ExtraFlags {
+        let split = |v: Vec<String>| v.iter().flat_map(|s| s.split_whitespace()).map(str::to_owned).collect();
+        ExtraFlags {
+            cflags:   split(self.cflags),
+            cxxflags: split(self.cxxflags),
+            defines:  self.defines,
+            ldflags:  split(self.ldflags),
+        }
+    }
 }
 
 // ── Upload args ───────────────────────────────────────────────────────────────
@@ -103,6 +228,11 @@ struct UploadArgs {
     #[arg(long, short = 'b')]
     board: String,
 
+    /// Pick a choice from one of the board's menus, e.g. `cpu=atmega328old`
+    /// (repeatable; `tsuki-flash boards` lists them)
+    #[arg(long, value_parser = boards::parse_option)]
+    board_option: Vec<(String, String)>,
+
     #[arg(long, short = 'p')]
     port: Option<String>,
 
@@ -114,6 +244,47 @@ struct UploadArgs {
 
     #[arg(long, default_value = "0")]
     baud: u32,
+
+    /// CPU clock in MHz the firmware was built for
+    #[arg(long)]
+    cpu_mhz: Option<u32>,
+
+    /// Flash even if the firmware was built for a different board
+    #[arg(long, default_value_t = false)]
+    force: bool,
+
+    /// Upload through an ISP programmer instead of the bootloader (AVR;
+    /// erases the bootloader)
+    #[arg(long, value_enum)]
+    programmer: Option<Programmer>,
+
+    /// Upload AVR boards through avrdude instead of the built-in STK500
+    /// programmer
+    #[arg(long, default_value_t = false)]
+    use_avrdude: bool,
+
+    /// Read the flash back after the upload and compare, where the
+    /// programmer doesn't already
+    #[arg(long, default_value_t = false)]
+    verify: bool,
+
+    /// Upload over WiFi to an ESP board running ArduinoOTA, by IP address
+    /// or host name
+    #[arg(long, conflicts_with_all = ["port", "programmer"])]
+    ota: Option<String>,
+
+    /// ArduinoOTA port (default: 3232 on ESP32, 8266 on ESP8266)
+    #[arg(long, requires = "ota")]
+    ota_port: Option<u16>,
+
+    /// ArduinoOTA password
+    #[arg(long, requires = "ota")]
+    password: Option<String>,
+
+    /// `json-lines`: report progress and diagnostics as JSON events on
+    /// stdout, one per line
+    #[arg(long, value_enum, default_value_t = MessageFormat::Human)]
+    message_format: MessageFormat,
 }
 
 // ── Run args ──────────────────────────────────────────────────────────────────
@@ -123,6 +294,11 @@ struct RunArgs {
     #[arg(long, short = 'b')]
     board: String,
 
+    /// Pick a choice from one of the board's menus, e.g. `cpu=atmega328old`
+    /// (repeatable; `tsuki-flash boards` lists them)
+    #[arg(long, value_parser = boards::parse_option)]
+    board_option: Vec<(String, String)>,
+
     #[arg(long, short = 'p')]
     port: Option<String>,
 
@@ -144,10 +320,218 @@ struct RunArgs {
     #[arg(long, default_value_t = false)]
     use_modules: bool,
 
+    /// Share compiled core and library objects across build dirs and projects
+    #[arg(long, default_value_t = false)]
+    obj_cache: bool,
+
+    #[arg(long, default_value = "0")]
+    baud: u32,
+
+    /// CPU clock in MHz the firmware was built for
+    #[arg(long)]
+    cpu_mhz: Option<u32>,
+
+    /// Flash even if the firmware was built for a different board
+    #[arg(long, default_value_t = false)]
+    force: bool,
+
+    /// Upload through an ISP programmer instead of the bootloader (AVR;
+    /// erases the bootloader)
+    #[arg(long, value_enum)]
+    programmer: Option<Programmer>,
+
+    /// Upload AVR boards through avrdude instead of the built-in STK500
+    /// programmer
+    #[arg(long, default_value_t = false)]
+    use_avrdude: bool,
+
+    /// Read the flash back after the upload and compare, where the
+    /// programmer doesn't already
+    #[arg(long, default_value_t = false)]
+    verify: bool,
+
+    /// ESP32 partition table: a scheme from the core (`default`, `huge_app`,
+    /// `min_spiffs`, `no_ota`, …) or a partitions .csv
+    #[arg(long)]
+    partition_scheme: Option<String>,
+
+    /// Flash budget, as a share of the board (`90%`) or a size (`28KB`)
+    #[arg(long)]
+    budget_flash: Option<Limit>,
+
+    /// RAM budget, as a share of the board or a size
+    #[arg(long)]
+    budget_ram: Option<Limit>,
+
+    /// Flash budget for one module, e.g. `LiquidCrystal=4KB` (repeatable)
+    #[arg(long, value_parser = size::parse_module_limit)]
+    budget_module: Vec<(String, Limit)>,
+
+    #[command(flatten)]
+    flags: FlagArgs,
+
+    /// `json-lines`: report progress and diagnostics as JSON events on
+    /// stdout, one per line
+    #[arg(long, value_enum, default_value_t = MessageFormat::Human)]
+    message_format: MessageFormat,
+}
+
+// ── Read args ─────────────────────────────────────────────────────────────────
+
+#[derive(Args)]
+struct ReadArgs {
+    #[arg(long, short = 'b')]
+    board: String,
+
+    /// Pick a choice from one of the board's menus, e.g. `FlashSize=8M`
+    /// (repeatable; `tsuki-flash boards` lists them)
+    #[arg(long, value_parser = boards::parse_option)]
+    board_option: Vec<(String, String)>,
+
+    #[arg(long, short = 'p')]
+    port: Option<String>,
+
+    /// File to save to: Intel HEX if it ends in .hex, raw bytes otherwise
+    #[arg(long, short = 'o')]
+    output: PathBuf,
+
+    #[arg(long, default_value = "0")]
+    baud: u32,
+
+    /// Read AVR boards through avrdude instead of the built-in STK500
+    /// programmer
+    #[arg(long, default_value_t = false)]
+    use_avrdude: bool,
+}
+
+// ── Size args ─────────────────────────────────────────────────────────────────
+
+#[derive(Args)]
+struct SizeArgs {
+    #[arg(long)]
+    build_dir: PathBuf,
+
+    /// Project name (<name>.elf; default: the only .elf in build_dir)
+    #[arg(long)]
+    name: Option<String>,
+
+    /// Board the firmware was built for (default: from the build metadata)
+    #[arg(long, short = 'b')]
+    board: Option<String>,
+
+    /// Number of rows to list per table
+    #[arg(long, default_value = "10")]
+    top: usize,
+}
+
+// ── Size-diff args ────────────────────────────────────────────────────────────
+
+#[derive(Args)]
+struct SizeDiffArgs {
+    old: PathBuf,
+
+    new: PathBuf,
+
+    /// Board the firmwares were built for (picks its `nm`; default: from the ELF)
+    #[arg(long, short = 'b')]
+    board: Option<String>,
+
+    /// Number of changed symbols to list
+    #[arg(long, default_value = "20")]
+    top: usize,
+}
+
+// ── Info args ─────────────────────────────────────────────────────────────────
+
+#[derive(Args)]
+struct InfoArgs {
+    #[arg(long)]
+    build_dir: PathBuf,
+
+    /// Project name (default: the only .elf in build_dir)
+    #[arg(long)]
+    name: Option<String>,
+}
+
+// ── Monitor args ──────────────────────────────────────────────────────────────
+
+#[derive(Args)]
+struct MonitorArgs {
+    /// Serial port (default: auto-detect; `-` reads stdin)
+    #[arg(long, short = 'p')]
+    port: Option<String>,
+
+    #[arg(long, default_value = "9600")]
+    baud: u32,
+
+    /// Draw lines of numbers (`1,2,3` or `temp:21.5 hum:40`) as live
+    /// sparklines with min/max, like the Arduino IDE serial plotter
+    #[arg(long, default_value_t = false)]
+    plot: bool,
+
+    /// Values per sparkline in plot mode
+    #[arg(long, default_value = "60")]
+    width: usize,
+}
+
+// ── Detect args ───────────────────────────────────────────────────────────────
+
+#[derive(Args)]
+struct DetectArgs {
+    /// Also look for boards on the network (mDNS), for `upload --ota`
+    #[arg(long, default_value_t = false)]
+    network: bool,
+}
+
+// ── ISP args ──────────────────────────────────────────────────────────────────
+
+#[derive(Args)]
+struct IspArgs {
+    #[arg(long, short = 'b')]
+    board: String,
+
+    /// Pick a choice from one of the board's menus, e.g. `cpu=atmega328old`
+    /// (repeatable; `tsuki-flash boards` lists them)
+    #[arg(long, value_parser = boards::parse_option)]
+    board_option: Vec<(String, String)>,
+
+    #[arg(long, value_enum)]
+    programmer: Programmer,
+
+    /// ArduinoISP's serial port (default: auto-detect; USB programmers need none)
+    #[arg(long, short = 'p')]
+    port: Option<String>,
+
+    /// Programmer baud rate (0 = its default)
     #[arg(long, default_value = "0")]
     baud: u32,
 }
 
+#[derive(Args)]
+struct FusesArgs {
+    #[command(subcommand)]
+    command: FusesCmd,
+}
+
+#[derive(Subcommand)]
+enum FusesCmd {
+    /// Print the fuse and lock bytes
+    Read(IspArgs),
+    /// Write the given fuse and lock bytes (hex)
+    Write {
+        #[command(flatten)]
+        isp: IspArgs,
+        #[arg(long, value_parser = isp::parse_fuse)]
+        lfuse: Option<u8>,
+        #[arg(long, value_parser = isp::parse_fuse)]
+        hfuse: Option<u8>,
+        #[arg(long, value_parser = isp::parse_fuse)]
+        efuse: Option<u8>,
+        #[arg(long, value_parser = isp::parse_fuse)]
+        lock: Option<u8>,
+    },
+}
+
 // ── Lib args ──────────────────────────────────────────────────────────────────
 
 #[derive(Args)]
@@ -159,6 +543,7 @@ struct LibArgs {
 #[derive(Subcommand)]
 enum LibCmd {
     Install {
+        /// Registry name, or a GitHub repository: https://github.com/user/Repo[@tag] or user/Repo[@tag]
         name: String,
         #[arg(long)]
         version: Option<String>,
@@ -167,6 +552,12 @@ enum LibCmd {
     List,
     Info { name: String },
     Update,
+    /// Delete an installed library
+    Remove { name: String },
+    /// Reinstall a library (or every installed one) at its latest version
+    Upgrade { name: Option<String> },
+    /// List installed libraries with a newer version in the registry
+    Outdated,
 }
 
 // ── Modules args ──────────────────────────────────────────────────────────────
@@ -179,7 +570,7 @@ struct ModulesArgs {
 
 #[derive(Subcommand)]
 enum ModulesCmd {
-    /// Download + install an Arduino SDK core (avr | esp32 | esp8266 | sam | rp2040)
+    /// Download + install an Arduino SDK core (avr | megaavr | megatinycore | esp32 | esp8266 | sam | rp2040 | stm32)
     Install { arch: String },
     /// List installed cores
     List,
@@ -187,6 +578,37 @@ enum ModulesCmd {
     Update,
 }
 
+// ── Cache args ────────────────────────────────────────────────────────────────
+
+#[derive(Args)]
+struct CacheArgs {
+    #[command(subcommand)]
+    command: CacheCmd,
+}
+
+#[derive(Args)]
+struct CleanArgs {
+    #[arg(long)]
+    build_dir: PathBuf,
+
+    /// Also delete the core archives, the global object cache and
+    /// unfinished module downloads
+    #[arg(long, default_value_t = false)]
+    deep: bool,
+
+    /// List what would be deleted and the space it takes, deleting nothing
+    #[arg(long, short = 'n', default_value_t = false)]
+    dry_run: bool,
+}
+
+#[derive(Subcommand)]
+enum CacheCmd {
+    /// Show where the object cache is, its size and hit rate
+    Stats,
+    /// Delete every cached object
+    Clear,
+}
+
 // ─────────────────────────────────────────────────────────────────────────────
 //  Entry point
 // ─────────────────────────────────────────────────────────────────────────────
@@ -197,16 +619,30 @@ fn main() {
     if cli.no_color {
         colored::control::set_override(false);
     }
+    download::set_quiet(cli.quiet);
+    process::set_timeouts(&cli.timeout);
+    process::handle_interrupts();
 
     let result = match cli.command {
-        Cmd::Compile(a)        => cmd_compile(a, cli.verbose, cli.quiet),
+        Cmd::Compile(a)        => cmd_compile(a, cli.verbose, cli.quiet, cli.format),
         Cmd::Upload(a)         => cmd_upload(a, cli.verbose, cli.quiet),
         Cmd::Run(a)            => cmd_run(a, cli.verbose, cli.quiet),
-        Cmd::Detect            => cmd_detect(),
-        Cmd::Boards            => { cmd_boards(); Ok(()) }
-        Cmd::SdkInfo { board } => cmd_sdk_info(&board),
-        Cmd::Lib(a)            => cmd_lib(a, cli.verbose),
-        Cmd::Modules(a)        => cmd_modules(a, cli.verbose),
+        Cmd::Read(a)           => cmd_read(a, cli.verbose, cli.quiet),
+        Cmd::Size(a)           => cmd_size(a),
+        Cmd::SizeDiff(a)       => cmd_size_diff(a),
+        Cmd::Info(a)           => cmd_info(a, cli.format),
+        Cmd::Monitor(a)        => cmd_monitor(a, cli.quiet),
+        Cmd::Detect(a)         => cmd_detect(a, cli.format),
+        Cmd::Boards            => { cmd_boards(cli.format); Ok(()) }
+        Cmd::BurnBootloader(a) => cmd_burn_bootloader(a, cli.verbose, cli.quiet),
+        Cmd::Fuses(a)          => cmd_fuses(a, cli.verbose, cli.quiet),
+        Cmd::SdkInfo { board } => cmd_sdk_info(&board, cli.format),
+        Cmd::Lib(a)            => cmd_lib(a, cli.verbose, cli.format),
+        Cmd::Modules(a)        => cmd_modules(a, cli.verbose, cli.format),
+        Cmd::Cache(a)          => cmd_cache(a),
+        Cmd::Clean(a)          => clean::run(&a.build_dir, a.deep, a.dry_run),
+        Cmd::Doctor            => doctor::run(),
+        Cmd::SetupPermissions { print } => permissions::setup(print),
     };
 
     if let Err(e) = result {
@@ -219,8 +655,10 @@ fn main() {
 //  Handlers
 // ─────────────────────────────────────────────────────────────────────────────
 
-fn cmd_compile(args: CompileArgs, verbose: bool, quiet: bool) -> Result<()> {
-    let board = find_board(&args.board)?;
+fn cmd_compile(args: CompileArgs, verbose: bool, quiet: bool, format: Format) -> Result<()> {
+    let board = &find_board(&args.board)?.with_options(&args.board_option)?;
+    // The JSON document or event stream is the only thing written to stdout.
+    let quiet = quiet || format.is_json() || stream_events(args.message_format);
     let name  = args.name.unwrap_or_else(|| dir_name(&args.sketch));
 
     ensure_modules_ready(args.use_modules, board.arch())?;
@@ -243,13 +681,39 @@ fn cmd_compile(args: CompileArgs, verbose: bool, quiet: bool) -> Result<()> {
         project_name:     name,
         cpp_std:          args.cpp_std,
         lib_include_dirs: args.include,
+        libraries:        Vec::new(),
         use_modules:      args.use_modules,
+        obj_cache:        args.obj_cache,
+        cpu_mhz:          args.cpu_mhz,
+        partition_scheme: args.partition_scheme,
+        budget:           Budget {
+            flash:   args.budget_flash,
+            ram:     args.budget_ram,
+            modules: args.budget_module,
+        },
+        extra_flags:      args.flags.into_extra(),
         verbose,
     };
 
     match compile(&req, board) {
         Ok(res) => {
-            if !quiet {
+            if format.is_json() {
+                output::print_json(&serde_json::json!({
+                    "board":   board.id,
+                    "fqbn":    board.fqbn,
+                    "seconds": t0.elapsed().as_secs_f64(),
+                    "hex":     res.hex_path,
+                    "bin":     res.bin_path,
+                    "elf":     res.elf_path,
+                    "uf2":     res.uf2_path,
+                    "size":    res.usage.map(|(flash, ram)| serde_json::json!({
+                        "flash":     flash,
+                        "ram":       ram,
+                        "flash_max": board.flash_kb as u64 * 1024,
+                        "ram_max":   board.ram_kb as u64 * 1024,
+                    })),
+                }));
+            } else if !quiet {
                 println!("{} compiled in {:.2}s", "✓".green().bold(), t0.elapsed().as_secs_f64());
                 print_firmware_info(&res);
             }
@@ -260,16 +724,22 @@ fn cmd_compile(args: CompileArgs, verbose: bool, quiet: bool) -> Result<()> {
 }
 
 fn cmd_upload(args: UploadArgs, verbose: bool, quiet: bool) -> Result<()> {
-    let board = find_board(&args.board)?;
+    let board = &find_board(&args.board)?.with_options(&args.board_option)?;
+    let quiet = quiet || stream_events(args.message_format);
     let name  = args.name.unwrap_or_else(|| "firmware".into());
-    let port  = resolve_port(args.port, quiet)?;
+    let port  = match (&args.ota, args.programmer) {
+        (Some(host), _) => host.clone(),
+        (None, Some(p)) => isp_port(p, args.port, quiet)?,
+        (None, None)    => resolve_upload_port(board, args.port, quiet)?,
+    };
+    let ota = args.ota.map(|host| OtaTarget { host, port: args.ota_port, password: args.password });
 
     if !quiet {
         println!(
             "{} {} {}",
             "Uploading".cyan().bold(),
             format!("[board: {}]", board.id).dimmed(),
-            format!("[port: {}]", port).dimmed(),
+            format!("[port: {}]", port_label(board, &port)).dimmed(),
         );
         println!("{}", "─".repeat(60).dimmed());
     }
@@ -279,20 +749,47 @@ fn cmd_upload(args: UploadArgs, verbose: bool, quiet: bool) -> Result<()> {
         project_name:  name,
         port:          port.clone(),
         baud_override: args.baud,
+        cpu_mhz:       args.cpu_mhz,
+        force:         args.force,
+        programmer:    args.programmer,
+        ota,
+        use_avrdude:   args.use_avrdude,
+        verify:        args.verify,
         verbose,
     };
 
     flash(&req, board)
-        .map_err(|e| { render_flash_error(&e, &port); e })
+        .inspect_err(|e| render_flash_error(e, "upload to", port_label(board, &port)))
         .map(|()| {
             if !quiet {
-                println!("{} firmware uploaded to {}", "✓".green().bold(), port.bold());
+                println!("{} firmware uploaded to {}", "✓".green().bold(), port_label(board, &port).bold());
             }
         })
 }
 
+fn cmd_monitor(args: MonitorArgs, quiet: bool) -> Result<()> {
+    let port = resolve_port(args.port, quiet)?;
+    if !quiet && port != "-" {
+        println!(
+            "{} {} {}",
+            "Monitoring".cyan().bold(),
+            format!("[port: {}]", port).dimmed(),
+            format!("[baud: {}]", args.baud).dimmed(),
+        );
+        println!("{}", "─".repeat(60).dimmed());
+    }
+
+    monitor::monitor(&monitor::MonitorRequest {
+        port,
+        baud:  args.baud,
+        plot:  args.plot,
+        width: args.width,
+    })
+}
+
 fn cmd_run(args: RunArgs, verbose: bool, quiet: bool) -> Result<()> {
-    let board = find_board(&args.board)?;
+    let board = &find_board(&args.board)?.with_options(&args.board_option)?;
+    let quiet = quiet || stream_events(args.message_format);
     let name  = args.name.unwrap_or_else(|| dir_name(&args.sketch));
 
     ensure_modules_ready(args.use_modules, board.arch())?;
@@ -311,21 +808,34 @@ fn cmd_run(args: RunArgs, verbose: bool, quiet: bool) -> Result<()> {
         project_name:     name.clone(),
         cpp_std:          args.cpp_std,
         lib_include_dirs: args.include,
+        libraries:        Vec::new(),
         use_modules:      args.use_modules,
+        obj_cache:        args.obj_cache,
+        cpu_mhz:          args.cpu_mhz,
+        partition_scheme: args.partition_scheme,
+        budget:           Budget {
+            flash:   args.budget_flash,
+            ram:     args.budget_ram,
+            modules: args.budget_module,
+        },
+        extra_flags:      args.flags.into_extra(),
         verbose,
     };
 
     let res = compile(&compile_req, board)
-        .map_err(|e| { render_compile_error(&e); e })?;
+        .inspect_err(render_compile_error)?;
 
     if !quiet {
         println!("{} compiled in {:.2}s", "✓".green().bold(), t0.elapsed().as_secs_f64());
     }
 
-    let port = resolve_port(args.port, quiet)?;
+    let port = match args.programmer {
+        Some(p) => isp_port(p, args.port, quiet)?,
+        None    => resolve_upload_port(board, args.port, quiet)?,
+    };
 
     if !quiet {
-        println!("\n{} {}", "Uploading".cyan().bold(), format!("[port: {}]", port).dimmed());
+        println!("\n{} {}", "Uploading".cyan().bold(), format!("[port: {}]", port_label(board, &port)).dimmed());
         println!("{}", "─".repeat(60).dimmed());
     }
 
@@ -334,14 +844,20 @@ fn cmd_run(args: RunArgs, verbose: bool, quiet: bool) -> Result<()> {
         project_name:  name,
         port:          port.clone(),
         baud_override: args.baud,
+        cpu_mhz:       args.cpu_mhz,
+        force:         args.force,
+        programmer:    args.programmer,
+        ota:           None,
+        use_avrdude:   args.use_avrdude,
+        verify:        args.verify,
         verbose,
     };
 
     flash(&flash_req, board)
-        .map_err(|e| { render_flash_error(&e, &port); e })?;
+        .inspect_err(|e| render_flash_error(e, "upload to", port_label(board, &port)))?;
 
     if !quiet {
-        println!("{} firmware uploaded to {}", "✓".green().bold(), port.bold());
+        println!("{} firmware uploaded to {}", "✓".green().bold(), port_label(board, &port).bold());
         if let Some(hex) = &res.hex_path {
             println!("  {} {}", "hex:".dimmed(), hex.display());
         }
@@ -349,48 +865,406 @@ fn cmd_run(args: RunArgs, verbose: bool, quiet: bool) -> Result<()> {
     Ok(())
 }
 
-fn cmd_detect() -> Result<()> {
+fn cmd_read(args: ReadArgs, verbose: bool, quiet: bool) -> Result<()> {
+    let board = &find_board(&args.board)?.with_options(&args.board_option)?;
+    let port  = resolve_upload_port(board, args.port, quiet)?;
+
+    if !quiet {
+        println!(
+            "{} {} {}",
+            "Reading".cyan().bold(),
+            format!("[board: {}]", board.id).dimmed(),
+            format!("[port: {}]", port_label(board, &port)).dimmed(),
+        );
+        println!("{}", "─".repeat(60).dimmed());
+    }
+
+    let req = ReadRequest {
+        port:          port.clone(),
+        baud_override: args.baud,
+        use_avrdude:   args.use_avrdude,
+        verbose,
+    };
+    let dump = read::read(&req, board)
+        .inspect_err(|e| render_flash_error(e, "read from", port_label(board, &port)))?;
+    let saved = read::save(&dump, &args.output)?;
+
+    if !quiet {
+        println!("{} {} bytes of flash saved to {}", "✓".green().bold(),
+            saved, args.output.display().to_string().bold());
+        if board.toolchain == boards::Toolchain::Rp2040 {
+            println!("  The board is still in BOOTSEL mode: unplug it or press reset to run the sketch.");
+        }
+    }
+    Ok(())
+}
+
+fn cmd_info(args: InfoArgs, format: Format) -> Result<()> {
+    let name = match args.name {
+        Some(n) => n,
+        None    => only_elf(&args.build_dir)?,
+    };
+    let meta = compile::meta::BuildMeta::load(&args.build_dir, &name).ok_or_else(|| FlashError::Other(format!(
+        "no build info for {} in {}\n  Build it with tsuki-flash compile first", name, args.build_dir.display())))?;
+    if format.is_json() {
+        output::print_json(&meta);
+        return Ok(());
+    }
+
+    let or_dash = |s: &str| if s.is_empty() { "—".to_owned() } else { s.to_owned() };
+    println!("{} {}", "Build info".cyan().bold(), format!("[{}]", args.build_dir.join(&name).display()).dimmed());
+    println!("{}", "─".repeat(60).dimmed());
+    println!("{:<10} {} ({}, {} MHz)", "board".bold(), meta.board, meta.fqbn, meta.f_cpu / 1_000_000);
+    println!("{:<10} {}", "tsuki".bold(), or_dash(&meta.tsuki_version));
+    println!("{:<10} {}", "core".bold(), or_dash(&meta.core));
+    println!("{:<10} {}", "compiler".bold(), or_dash(&meta.compiler));
+    println!("{:<10} {}", "flags".bold(), or_dash(&meta.flags));
+    println!("{:<10} {}", "git".bold(), meta.git.as_deref().unwrap_or("—"));
+    for a in &meta.artifacts {
+        let state = match a.unchanged(&args.build_dir) {
+            Some(true)  => "".normal(),
+            Some(false) => "  changed since the build".yellow(),
+            None        => "  missing".yellow(),
+        };
+        println!("{:<10} {} {} {}{}", "artifact".bold(), a.sha256, format!("{:>8} B", a.bytes).dimmed(), a.file, state);
+    }
+    Ok(())
+}
+
+fn cmd_size(args: SizeArgs) -> Result<()> {
+    let name = match args.name {
+        Some(n) => n,
+        None    => only_elf(&args.build_dir)?,
+    };
+    let elf = args.build_dir.join(format!("{}.elf", name));
+    if !elf.is_file() {
+        return Err(FlashError::NoFirmware(args.build_dir.display().to_string()));
+    }
+    let board_id = args.board
+        .or_else(|| compile::meta::BuildMeta::load(&args.build_dir, &name).map(|m| m.board));
+    let board = board_id.as_deref().map(find_board).transpose()?;
+    let nm = match board {
+        Some(board) => {
+            let bin = sdk::resolve(board.arch(), board.variant)
+                .map(|s| s.toolchain_bin).unwrap_or_default();
+            size::nm_tool(board, &bin)
+        }
+        None => size::nm_for_elf(&elf)?,
+    };
+    let report = size::SizeReport::analyze(&nm, &elf)?;
+
+    // Usage against the board's capacity.
+    let (flash, ram) = report.section_usage();
+    for (what, used, cap_kb) in [("flash", flash, board.map(|b| b.flash_kb)), ("ram", ram, board.map(|b| b.ram_kb))] {
+        match cap_kb.filter(|kb| *kb > 0) {
+            Some(kb) => {
+                let cap = kb as u64 * 1024;
+                println!("{:<6} {}  {:>8} / {:<8} {:>5.1}%",
+                    what.bold(), usage_bar(used, cap, 30), used, cap, used as f64 * 100.0 / cap as f64);
+            }
+            None => println!("{:<6} {:>8} bytes", what.bold(), used),
+        }
+    }
+    if board.is_none() {
+        println!("{}", "no board known for this build; pass --board to compare against its limits".dimmed());
+    }
+
+    // Grouping comes from the linker map when it says where code came from;
+    // otherwise (no map, or LTO) from the files nm finds in the debug info.
+    let map = size::LinkMap::read(&args.build_dir.join(format!("{}.map", name))).ok()
+        .filter(|m| !m.is_lto())
+        .map(|mut m| { m.retain_sections(&report.sections); m });
+    let (libraries, objects) = match &map {
+        Some(map) => (map.by_library(&args.build_dir), map.by_object()),
+        None      => (report.by_module(), report.by_file()),
+    };
+    size_table(if map.is_some() { "LIBRARY" } else { "MODULE" }, &libraries, args.top);
+    size_table(if map.is_some() { "OBJECT" } else { "SOURCE FILE" }, &objects, args.top);
+
+    for (what, key) in [("FLASH", 0), ("RAM", 1)] {
+        let mut symbols: Vec<&size::SymbolSize> = report.symbols.iter()
+            .filter(|s| if key == 0 { s.flash > 0 } else { s.ram > 0 })
+            .collect();
+        symbols.sort_by_key(|s| std::cmp::Reverse(if key == 0 { s.flash } else { s.ram }));
+        if symbols.is_empty() { continue }
+        println!("\n{:<48} {:>8}  MODULE", format!("LARGEST SYMBOLS BY {}", what), "BYTES");
+        println!("{}", "─".repeat(76).dimmed());
+        for s in symbols.iter().take(args.top) {
+            println!("{:<48} {:>8}  {}", s.name, if key == 0 { s.flash } else { s.ram }, s.module.dimmed());
+        }
+    }
+    Ok(())
+}
+
+/// The project name of the one .elf in `dir`.
+fn only_elf(dir: &Path) -> Result<String> {
+    let mut names: Vec<String> = std::fs::read_dir(dir)?.flatten()
+        .filter_map(|e| e.file_name().to_str()?.strip_suffix(".elf").map(str::to_owned))
+        .collect();
+    match names.len() {
+        0 => Err(FlashError::NoFirmware(dir.display().to_string())),
+        1 => Ok(names.remove(0)),
+        _ => {
+            names.sort();
+            Err(FlashError::Other(format!("{} holds several firmwares ({}); pick one with --name",
+                dir.display(), names.join(", "))))
+        }
+    }
+}
+
+/// `[██████░░░░]`, green below 75% of `cap`, yellow below 90%, red above.
+fn usage_bar(used: u64, cap: u64, width: usize) -> String {
+    let share = used as f64 / cap as f64;
+    let filled = ((share * width as f64).round() as usize).min(width);
+    let bar = "█".repeat(filled);
+    let bar = match share {
+        s if s < 0.75 => bar.green(),
+        s if s < 0.90 => bar.yellow(),
+        _             => bar.red(),
+    };
+    format!("[{}{}]", bar, "░".repeat(width - filled).dimmed())
+}
+
+fn size_table(title: &str, rows: &[(String, u64, u64)], top: usize) {
+    if rows.is_empty() { return }
+    println!("\n{:<48} {:>8} {:>8}", title, "FLASH", "RAM");
+    println!("{}", "─".repeat(66).dimmed());
+    for (name, flash, ram) in rows.iter().take(top) {
+        println!("{:<48} {:>8} {:>8}", name, flash, ram);
+    }
+    if rows.len() > top {
+        println!("{}", format!("… {} more (--top)", rows.len() - top).dimmed());
+    }
+}
+
+fn cmd_size_diff(args: SizeDiffArgs) -> Result<()> {
+    let nm = match &args.board {
+        Some(id) => {
+            let board = find_board(id)?;
+            let bin = sdk::resolve(board.arch(), board.variant)
+                .map(|s| s.toolchain_bin).unwrap_or_default();
+            size::nm_tool(board, &bin)
+        }
+        None => size::nm_for_elf(&args.new)?,
+    };
+    let old  = size::SizeReport::analyze(&nm, &args.old)?;
+    let new  = size::SizeReport::analyze(&nm, &args.new)?;
+    let diff = size::SizeDiff::between(&old, &new);
+
+    let delta = |d: &size::Delta| {
+        let s = size::signed(d.growth());
+        match d.growth() {
+            g if g > 0 => s.red().to_string(),
+            g if g < 0 => s.green().to_string(),
+            _          => s.dimmed().to_string(),
+        }
+    };
+    for d in [&diff.flash, &diff.ram] {
+        println!("{:<6} {:>8} → {:>8}  {}", d.name.bold(), d.old, d.new, delta(d));
+    }
+
+    if !diff.sections.is_empty() {
+        println!("\n{:<32} {:>8} {:>8}  DELTA", "SECTION", "OLD", "NEW");
+        println!("{}", "─".repeat(60).dimmed());
+        for d in &diff.sections {
+            println!("{:<32} {:>8} {:>8}  {}", d.name, d.old, d.new, delta(d));
+        }
+    }
+
+    if diff.symbols.is_empty() {
+        println!("\n{}", "no symbol changed size".dimmed());
+        return Ok(());
+    }
+    println!("\n{:<48} {:>8} {:>8}  DELTA", "SYMBOL", "OLD", "NEW");
+    println!("{}", "─".repeat(76).dimmed());
+    for d in diff.symbols.iter().take(args.top) {
+        println!("{:<48} {:>8} {:>8}  {}", d.name, d.old, d.new, delta(d));
+    }
+    if diff.symbols.len() > args.top {
+        println!("{}", format!("… {} more (--top)", diff.symbols.len() - args.top).dimmed());
+    }
+    Ok(())
+}
+
+fn cmd_detect(args: DetectArgs, format: Format) -> Result<()> {
     let ports = detect::detect_all();
-    if ports.is_empty() {
-        println!("{} No serial ports found", "!".yellow());
+    let network = if args.network { detect::detect_network(Duration::from_secs(2)) } else { Vec::new() };
+    if format.is_json() {
+        output::print_json(&serde_json::json!({
+            "ports": ports.iter().map(|p| serde_json::json!({
+                "port":       p.port,
+                "board":      p.board_id,
+                "board_name": p.board_name,
+                "vid":        p.vid_pid.map(|(v, _)| format!("{:04X}", v)),
+                "pid":        p.vid_pid.map(|(_, pid)| format!("{:04X}", pid)),
+            })).collect::<Vec<_>>(),
+            "network": network.iter().map(|b| serde_json::json!({
+                "name":       b.name,
+                "host":       b.host.trim_end_matches('.'),
+                "addr":       b.addr.map(|a| a.to_string()),
+                "port":       b.port,
+                "board":      b.board_id,
+                "board_name": b.board_name,
+                "ota":        b.ota,
+                "password":   b.auth,
+            })).collect::<Vec<_>>(),
+        }));
+        return Ok(());
+    }
+    if ports.is_empty() && network.is_empty() {
+        println!("{} No serial ports found{}", "!".yellow(),
+            if args.network { " and no boards on the network" } else { "" });
         return Ok(());
     }
-    println!("{:<20} {:<15} {:<8}  {}", "PORT", "BOARD", "VID:PID", "NAME");
-    println!("{}", "─".repeat(70).dimmed());
+
+    if args.network {
+        println!("{:<28} {:<15} {:<10} {:<8}  NAME", "PORT", "BOARD", "VID:PID", "OTA");
+        println!("{}", "─".repeat(90).dimmed());
+    } else {
+        println!("{:<20} {:<15} {:<8}  NAME", "PORT", "BOARD", "VID:PID");
+        println!("{}", "─".repeat(70).dimmed());
+    }
     for p in &ports {
         let vid_pid = p.vid_pid
             .map(|(v, pid)| format!("{:04X}:{:04X}", v, pid))
             .unwrap_or_else(|| "—".into());
-        println!("{:<20} {:<15} {:<8}  {}",
-            p.port,
-            p.board_id.unwrap_or("unknown"),
-            vid_pid,
-            p.board_name.unwrap_or("—"));
+        if args.network {
+            println!("{:<28} {:<15} {:<10} {:<8}  {}",
+                p.port, p.board_id.unwrap_or("unknown"), vid_pid, "—", p.board_name.unwrap_or("—"));
+        } else {
+            println!("{:<20} {:<15} {:<8}  {}",
+                p.port, p.board_id.unwrap_or("unknown"), vid_pid, p.board_name.unwrap_or("—"));
+        }
+    }
+    for b in &network {
+        let ota = match (b.ota, b.auth) {
+            (false, _)    => "no",
+            (true, false) => "yes",
+            (true, true)  => "password",
+        };
+        let addr = b.addr.map_or_else(String::new, |a| format!(" ({}:{})", a, b.port));
+        println!("{:<28} {:<15} {:<10} {:<8}  {}{}",
+            b.host.trim_end_matches('.'),
+            b.board_id.or(b.board_name.as_deref()).unwrap_or("unknown"),
+            "mDNS", ota, b.name, addr);
     }
     Ok(())
 }
 
-fn cmd_boards() {
-    println!("{:<15} {:<32} {:<15} {:>7} {:>6}  {}",
-        "ID", "NAME", "CPU / ARCH", "FLASH", "RAM", "FQBN");
-    println!("{}", "─".repeat(95).dimmed());
+fn cmd_boards(format: Format) {
+    if !format.is_json() {
+        println!("{:<15} {:<32} {:<15} {:>7} {:>6}  FQBN",
+            "ID", "NAME", "CPU / ARCH", "FLASH", "RAM");
+        println!("{}", "─".repeat(95).dimmed());
+    }
+    let mut json = Vec::new();
     for b in Board::catalog() {
         let (cpu, arch) = match &b.toolchain {
             boards::Toolchain::Avr { mcu, .. }   => (mcu.to_string(), "avr"),
+            boards::Toolchain::MegaAvr { mcu, .. } => (mcu.to_string(), b.arch()),
             boards::Toolchain::Sam { mcu, .. }    => (mcu.to_string(), "sam"),
             boards::Toolchain::Rp2040             => ("cortex-m0+".into(), "rp2040"),
             boards::Toolchain::Esp32 { variant }  => (variant.to_string(), "esp32"),
             boards::Toolchain::Esp8266            => ("lx106".into(), "esp8266"),
+            boards::Toolchain::Stm32 { mcu, .. }  => (mcu.to_string(), "stm32"),
+            boards::Toolchain::Teensy { mcu, .. } => (mcu.to_string(), "teensy"),
         };
+        if format.is_json() {
+            json.push(serde_json::json!({
+                "id":        b.id,
+                "name":      b.name,
+                "fqbn":      b.fqbn,
+                "arch":      arch,
+                "cpu":       cpu,
+                "clock_mhz": b.clock_mhz,
+                "flash_kb":  b.flash_kb,
+                "ram_kb":    b.ram_kb,
+                "options":   b.options.iter()
+                    .map(|(menu, choices)| (menu, boards::sorted_choices(choices)))
+                    .collect::<std::collections::BTreeMap<_, _>>(),
+            }));
+            continue;
+        }
         println!("{:<15} {:<32} {:<7} ({:<6}) {:>5}K  {:>4}K  {}",
             b.id.bold(), b.name, cpu, arch,
             b.flash_kb, b.ram_kb, b.fqbn.dimmed());
+        for (menu, choices) in b.options {
+            let choices = boards::sorted_choices(choices).join(" | ");
+            println!("{:<15} {}", "", format!("--board-option {}={}", menu, choices).dimmed());
+        }
+    }
+    if format.is_json() {
+        output::print_json(&json);
+    }
+}
+
+fn cmd_burn_bootloader(args: IspArgs, verbose: bool, quiet: bool) -> Result<()> {
+    let board = &find_board(&args.board)?.with_options(&args.board_option)?;
+    let port  = isp_port(args.programmer, args.port.clone(), quiet)?;
+
+    if !quiet {
+        println!(
+            "{} {} {}",
+            "Burning bootloader".cyan().bold(),
+            format!("[board: {}]", board.id).dimmed(),
+            format!("[programmer: {}]", args.programmer).dimmed(),
+        );
+        println!("{}", "─".repeat(60).dimmed());
+    }
+
+    isp_for(board, &args, &port, verbose).burn_bootloader()
+        .inspect_err(|e| render_flash_error(e, "upload to", &port))?;
+    if !quiet {
+        println!("{} bootloader written to {}", "✓".green().bold(), board.name.bold());
     }
+    Ok(())
 }
 
-fn cmd_sdk_info(board_id: &str) -> Result<()> {
+fn cmd_fuses(args: FusesArgs, verbose: bool, quiet: bool) -> Result<()> {
+    match args.command {
+        FusesCmd::Read(a) => {
+            let board = &find_board(&a.board)?.with_options(&a.board_option)?;
+            let port  = isp_port(a.programmer, a.port.clone(), quiet)?;
+            let fuses = isp_for(board, &a, &port, verbose).read_fuses()
+                .inspect_err(|e| render_flash_error(e, "upload to", &port))?;
+            for (name, value) in [("lfuse", fuses.low), ("hfuse", fuses.high),
+                                  ("efuse", fuses.extended), ("lock", fuses.lock)] {
+                let value = value.map(|v| format!("{:#04X}", v)).unwrap_or_else(|| "—".into());
+                println!("{:<6} {}", name.bold(), value);
+            }
+            Ok(())
+        }
+        FusesCmd::Write { isp, lfuse, hfuse, efuse, lock } => {
+            let fuses = Fuses { low: lfuse, high: hfuse, extended: efuse, lock };
+            if fuses.low.is_none() && fuses.high.is_none() && fuses.extended.is_none() && fuses.lock.is_none() {
+                return Err(FlashError::Other(
+                    "Nothing to write — pass --lfuse, --hfuse, --efuse or --lock".into()));
+            }
+            let board = &find_board(&isp.board)?.with_options(&isp.board_option)?;
+            let port  = isp_port(isp.programmer, isp.port.clone(), quiet)?;
+            isp_for(board, &isp, &port, verbose).write_fuses(&fuses)
+                .inspect_err(|e| render_flash_error(e, "upload to", &port))?;
+            if !quiet {
+                println!("{} fuses written", "✓".green().bold());
+            }
+            Ok(())
+        }
+    }
+}
+
+fn cmd_sdk_info(board_id: &str, format: Format) -> Result<()> {
     let board = find_board(board_id)?;
     match sdk::resolve(board.arch(), board.variant) {
+        Ok(paths) if format.is_json() => {
+            output::print_json(&serde_json::json!({
+                "version":   paths.sdk_version,
+                "core":      paths.core_dir,
+                "variant":   paths.variant_dir,
+                "toolchain": paths.toolchain_bin,
+                "libraries": paths.libraries_dir,
+            }));
+            Ok(())
+        }
         Ok(paths) => {
             println!("{} SDK found  ({})", "✓".green().bold(), paths.sdk_version);
             println!("  core:     {}", paths.core_dir.display());
@@ -405,31 +1279,31 @@ fn cmd_sdk_info(board_id: &str) -> Result<()> {
     }
 }
 
-fn cmd_modules(args: ModulesArgs, verbose: bool) -> Result<()> {
+fn cmd_modules(args: ModulesArgs, verbose: bool, format: Format) -> Result<()> {
     match args.command {
         ModulesCmd::Install { arch } => modules::install(&arch, verbose),
-        ModulesCmd::List             => modules::list(),
+        ModulesCmd::List             => modules::list(format),
         ModulesCmd::Update           => modules::update(verbose),
     }
 }
 
-fn cmd_lib(args: LibArgs, verbose: bool) -> Result<()> {
+fn cmd_lib(args: LibArgs, verbose: bool, format: Format) -> Result<()> {
     match args.command {
         LibCmd::Install { name, version } => {
-            lib_manager::install(&name, version.as_deref(), verbose)?;
-            if let Ok(root) = lib_manager::libs_root() {
-                let p = root.join(&name);
-                if p.exists() {
-                    println!("\n  {} {}", "path:".dimmed(), p.display().to_string().dimmed());
-                    println!("  {} {}", "include hint:".dimmed(),
-                        format!("--include {}", p.display()).bold());
-                }
+            let p = lib_manager::install(&name, version.as_deref(), verbose)?;
+            if p.exists() {
+                println!("\n  {} {}", "path:".dimmed(), p.display().to_string().dimmed());
+                println!("  {} {}", "include hint:".dimmed(),
+                    format!("--include {}", p.display()).bold());
             }
             Ok(())
         }
-        LibCmd::Search { query } => lib_manager::search(&query, verbose),
-        LibCmd::List              => lib_manager::list(),
-        LibCmd::Info { name }     => lib_manager::info(&name, verbose),
+        LibCmd::Search { query } => lib_manager::search(&query, verbose, format),
+        LibCmd::List              => lib_manager::list(format),
+        LibCmd::Info { name }     => lib_manager::info(&name, verbose, format),
+        LibCmd::Remove { name }   => lib_manager::remove(&name),
+        LibCmd::Upgrade { name }  => lib_manager::upgrade(name.as_deref(), verbose),
+        LibCmd::Outdated          => lib_manager::outdated(verbose),
         LibCmd::Update => {
             if let Ok(home) = std::env::var("HOME").or_else(|_| std::env::var("USERPROFILE")) {
                 let cache = PathBuf::from(home)
@@ -438,13 +1312,35 @@ fn cmd_lib(args: LibArgs, verbose: bool) -> Result<()> {
                 if cache.exists() { let _ = std::fs::remove_file(&cache); }
             }
             println!("{} Refreshing library index…", "→".cyan());
-            lib_manager::search("", verbose)?;
+            lib_manager::search("", verbose, Format::Text)?;
             println!("{} Library index updated.", "✓".green().bold());
             Ok(())
         }
     }
 }
 
+fn cmd_cache(args: CacheArgs) -> Result<()> {
+    let root = compile::objcache::root()?;
+    match args.command {
+        CacheCmd::Stats => {
+            let (entries, bytes, counts) = compile::objcache::stats(&root);
+            let lookups = counts.hits + counts.misses;
+            println!("{:<10} {}", "location".dimmed(), root.display());
+            println!("{:<10} {}", "objects".dimmed(), entries);
+            println!("{:<10} {:.1} MB", "size".dimmed(), bytes as f64 / (1024.0 * 1024.0));
+            if lookups > 0 {
+                println!("{:<10} {} hits, {} misses ({:.0}%)", "lookups".dimmed(),
+                    counts.hits, counts.misses, counts.hits as f64 * 100.0 / lookups as f64);
+            }
+        }
+        CacheCmd::Clear => {
+            compile::objcache::clear(&root)?;
+            println!("{} Cleared {}", "✓".green().bold(), root.display());
+        }
+    }
+    Ok(())
+}
+
 // ─────────────────────────────────────────────────────────────────────────────
 //  Helpers
 // ─────────────────────────────────────────────────────────────────────────────
@@ -462,7 +1358,53 @@ fn resolve_port(explicit: Option<String>, quiet: bool) -> Result<String> {
     }
 }
 
-fn dir_name(path: &PathBuf) -> String {
+/// Like `resolve_port`, but boards that can be flashed over a USB drive or
+/// DFU don't need a serial port, so none is fine (empty).
+fn resolve_upload_port(board: &Board, explicit: Option<String>, quiet: bool) -> Result<String> {
+    match resolve_port(explicit, quiet) {
+        Err(FlashError::NoBoardDetected) if board.port_optional() => {
+            if !quiet { println!("no serial port, using {}", port_label(board, "")); }
+            Ok(String::new())
+        }
+        res => res,
+    }
+}
+
+/// The port an ISP programmer is on: ArduinoISP's serial port, or `usb`,
+/// where avrdude looks for USB programmers itself.
+fn isp_port(programmer: Programmer, explicit: Option<String>, quiet: bool) -> Result<String> {
+    if programmer.uses_serial() {
+        resolve_port(explicit, quiet)
+    } else {
+        Ok(explicit.unwrap_or_else(|| "usb".into()))
+    }
+}
+
+fn isp_for<'a>(board: &'a Board, args: &IspArgs, port: &'a str, verbose: bool) -> Isp<'a> {
+    Isp { board, programmer: args.programmer, port, baud: args.baud, verbose }
+}
+
+/// What an upload goes through, for messages: the port, or what replaces it.
+fn port_label<'a>(board: &Board, port: &'a str) -> &'a str {
+    if !port.is_empty() { return port; }
+    match &board.toolchain {
+        boards::Toolchain::Stm32 { upload: boards::Stm32Upload::Dfu, .. }    => "DFU",
+        boards::Toolchain::Stm32 { upload: boards::Stm32Upload::StLink, .. } => "ST-Link",
+        boards::Toolchain::Teensy { .. } => "HalfKay",
+        _ => flash::uf2::DRIVE_LABEL,
+    }
+}
+
+/// Turn the event stream on for `json-lines`; the caller then keeps its
+/// own text off stdout.
+fn stream_events(format: MessageFormat) -> bool {
+    if format == MessageFormat::JsonLines {
+        events::enable();
+    }
+    events::enabled()
+}
+
+fn dir_name(path: &Path) -> String {
     path.file_name()
         .map(|n| n.to_string_lossy().to_string())
         .unwrap_or_else(|| "firmware".into())
@@ -477,25 +1419,27 @@ fn sdk_label(use_modules: bool, arch: &str) -> String {
 }
 
 /// If --use-modules is set, ensure the core is installed (auto-download if absent).
-/// Uses the fast-path avr module when arch == "avr"; falls back to generic install.
+/// Uses the pinned fast-path module for avr, esp32, esp8266 and rp2040; other
+/// architectures must have been installed with `modules install`.
 fn ensure_modules_ready(use_modules: bool, arch: &str) -> Result<()> {
     if !use_modules { return Ok(()); }
+    // ensure() is a no-op (microseconds) when already installed.
     match arch {
-        "avr" => {
-            // avr::ensure() is a no-op (microseconds) when already installed.
-            modules::avr::ensure(false).map(|_| ())
-        }
+        "avr"     => modules::avr::ensure(false).map(|_| ()),
+        "esp32"   => modules::esp32::ensure(false).map(|_| ()),
+        "esp8266" => modules::esp8266::ensure(false).map(|_| ()),
+        "rp2040"  => modules::rp2040::ensure(false).map(|_| ()),
         _ => {
             if modules::is_installed(arch) { return Ok(()); }
             eprintln!(
                 "{} Core for arch '{}' is not installed in tsuki-modules.",
                 "✗".red().bold(), arch
             );
-            eprintln!("  Run: {}", format!("tsuki-flash modules install {}", arch).bold());
+            eprintln!("  Run: {}", doctor::install_core(arch).bold());
             Err(FlashError::SdkNotFound {
                 arch: arch.into(),
                 path: "~/.tsuki/modules".into(),
-                pkg:  format!("tsuki-flash modules install {}", arch),
+                pkg:  doctor::arduino_cli_package(arch).into(),
             })
         }
     }
@@ -504,6 +1448,7 @@ fn ensure_modules_ready(use_modules: bool, arch: &str) -> Result<()> {
 fn print_firmware_info(res: &compile::CompileResult) {
     if let Some(hex) = &res.hex_path { println!("  {} {}", "hex:".dimmed(), hex.display()); }
     if let Some(bin) = &res.bin_path { println!("  {} {}", "bin:".dimmed(), bin.display()); }
+    if let Some(uf2) = &res.uf2_path { println!("  {} {}", "uf2:".dimmed(), uf2.display()); }
     if !res.size_info.is_empty()     { println!("\n{}", res.size_info.dimmed()); }
 }
 
@@ -512,7 +1457,7 @@ fn print_firmware_info(res: &compile::CompileResult) {
 // ─────────────────────────────────────────────────────────────────────────────
 
 fn render_compile_error(e: &FlashError) {
-    eprintln!("\n{} {}", "CompileError".red().bold(), "compilation failed");
+    eprintln!("\n{} compilation failed", "CompileError".red().bold());
     eprintln!("{}", "─".repeat(60).dimmed());
 
     match e {
@@ -526,19 +1471,25 @@ fn render_compile_error(e: &FlashError) {
         FlashError::SdkNotFound { arch, path, pkg } => {
             eprintln!("  {} SDK not found for arch '{}'", "✗".red(), arch);
             eprintln!("  Expected at: {}", path.yellow());
-            eprintln!("  Install with tsuki-modules: {}",
-                format!("tsuki-flash modules install {}", arch).bold());
-            eprintln!("  Or via arduino-cli: {}",
-                format!("arduino-cli core install {}", pkg).bold());
+            let install = doctor::install_core(arch);
+            eprintln!("  Install with: {}", install.bold());
+            if !install.starts_with("arduino-cli") {
+                eprintln!("  Or via arduino-cli: {}",
+                    format!("arduino-cli core install {}", pkg).bold());
+            }
+        }
+        FlashError::ToolchainNotFound(msg) => {
+            eprintln!("  {} {}", "✗".red(), msg);
+            eprintln!("  Run {} to see what else is missing", "tsuki-flash doctor".bold());
         }
-        FlashError::ToolchainNotFound(msg) => eprintln!("  {} {}", "✗".red(), msg),
         _ => eprintln!("  {}", e),
     }
     eprintln!("{}", "─".repeat(60).dimmed());
 }
 
-fn render_flash_error(e: &FlashError, port: &str) {
-    eprintln!("\n{} {}", "FlashError".red().bold(), format!("upload to {} failed", port));
+/// `what`: "upload to" or "read from".
+fn render_flash_error(e: &FlashError, what: &str, port: &str) {
+    eprintln!("\n{} {} {} failed", "FlashError".red().bold(), what, port);
     eprintln!("{}", "─".repeat(60).dimmed());
 
     match e {
@@ -548,15 +1499,76 @@ fn render_flash_error(e: &FlashError, port: &str) {
                 else if !line.trim().is_empty()          { eprintln!("  {}", line.dimmed()); }
             }
             eprintln!("\n  {}", "Hints:".bold());
+            if cfg!(target_os = "linux") && permissions::is_permission_error(output) {
+                eprintln!("  • This is a permissions problem: run {}",
+                    "tsuki-flash setup-permissions".bold());
+            }
             eprintln!("  • Ensure the board is in bootloader mode");
             eprintln!("  • Try a different USB cable / port");
             eprintln!("  • Pass --port explicitly: tsuki-flash upload --port /dev/ttyUSB0 …");
+            eprintln!("  • Run {} to check tools and port access", "tsuki-flash doctor".bold());
+        }
+        FlashError::Timeout { tool, secs, output, .. } => {
+            for line in output.lines().filter(|l| !l.trim().is_empty()) {
+                eprintln!("  {}", line.dimmed());
+            }
+            eprintln!("  {} {} didn't finish within {} s and was stopped", "✗".red(), tool, secs);
+            eprintln!("\n  {}", "Hints:".bold());
+            eprintln!("  • Press reset on the board, or unplug it and plug it back in");
+            eprintln!("  • Close any other program using the port (serial monitor, IDE)");
+            eprintln!("  • Run {} to check tools and port access", "tsuki-flash doctor".bold());
+            eprintln!("  • A large image at a low baud rate may need longer: --timeout upload=SECS");
         }
         FlashError::NoBoardDetected => {
             eprintln!("  {} No board detected on any serial port", "✗".red());
             eprintln!("  Connect the board and retry, or pass --port /dev/ttyUSBx");
+            eprintln!("  Run {} to check drivers and port access", "tsuki-flash doctor".bold());
+        }
+        FlashError::PortAccessDenied(p) => {
+            eprintln!("  {} No permission to open {}", "✗".red(), p);
+            match doctor::serial_access(p) {
+                Some(fix) => eprintln!("  Fix: {}", fix.bold()),
+                None      => eprintln!("  Close any other program using the port, then retry"),
+            }
         }
         _ => eprintln!("  {}", e),
     }
     eprintln!("{}", "─".repeat(60).dimmed());
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn extra_flags(args: &[&str]) -> ExtraFlags {
+        let cli = Cli::try_parse_from(["tsuki-flash", "compile", "-b", "uno", "--sketch", "s", "--build-dir", "b"].iter().chain(args))
+            .unwrap_or_else(|e| panic!("{}", e));
+        match cli.command {
+            Cmd::Compile(args) => args.flags.into_extra(),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_extra_flags() {
+        let extra = extra_flags(&[
+            "--cflags", "-O3  -g", "--cflags=-Wall",
+            "--cxxflags", "-std=gnu++20",
+            "--defines", "LED=2", "--defines", "MSG=\"a b\"",
+            "--ldflags", "-Wl,-u,vfprintf -lprintf_flt",
+        ]);
+        // Values split at spaces; repeats keep their order.
+        assert_eq!(extra.cflags, ["-O3", "-g", "-Wall"]);
+        assert_eq!(extra.cxxflags, ["-std=gnu++20"]);
+        assert_eq!(extra.ldflags, ["-Wl,-u,vfprintf", "-lprintf_flt"]);
+        // A define is one value, spaces and all.
+        assert_eq!(extra.defines, ["LED=2", "MSG=\"a b\""]);
+        assert_eq!(extra.define_flags().collect::<Vec<_>>(), ["-DLED=2", "-DMSG=\"a b\""]);
+    }
+
+    #[test]
+    fn test_no_extra_flags() {
+        let extra = extra_flags(&["--cflags", "  "]);
+        assert!(extra.cflags.is_empty() && extra.cxxflags.is_empty() && extra.ldflags.is_empty());
+        assert_eq!(extra.define_flags().count(), 0);
+    }
+}
@@ -0,0 +1,286 @@
+// ─────────────────────────────────────────────────────────────────────────────
+//  tsuki-flash :: mdns  —  DNS-SD browsing over multicast DNS
+//
+//  Just enough of RFC 6762/6763 to find boards on the LAN: one query with a
+//  PTR question per service type, sent to 224.0.0.251:5353 from an
+//  ephemeral port so responders answer us directly, then the PTR, SRV, TXT
+//  and A records of whatever comes back within the wait.
+// ─────────────────────────────────────────────────────────────────────────────
+
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddrV4, UdpSocket};
+use std::time::{Duration, Instant};
+
+const MDNS_ADDR: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+const MDNS_PORT: u16 = 5353;
+
+const TYPE_A:   u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_SRV: u16 = 33;
+const CLASS_IN: u16 = 1;
+/// The top bit of a question's class asks for a unicast answer.
+const UNICAST_RESPONSE: u16 = 0x8000;
+
+/// One instance of a service.
+#[derive(Debug, Clone)]
+pub struct Service {
+    /// Service type it was found under, e.g. "_arduino._tcp".
+    pub service:  String,
+    /// Instance label, e.g. "esp32-a1b2c3".
+    pub instance: String,
+    /// Host name from the SRV record, e.g. "esp32-a1b2c3.local".
+    pub host:     String,
+    pub addr:     Option<Ipv4Addr>,
+    pub port:     u16,
+    pub txt:      HashMap<String, String>,
+}
+
+/// Browse `services` (e.g. "_arduino._tcp") for `wait`. Errors opening the
+/// socket, or a network without multicast, give no services.
+pub fn browse(services: &[&str], wait: Duration) -> Vec<Service> {
+    let Ok(socket) = UdpSocket::bind(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0)) else {
+        return Vec::new();
+    };
+    let domains: Vec<String> = services.iter().map(|s| format!("{}.local", s)).collect();
+    if socket.send_to(&query(&domains), SocketAddrV4::new(MDNS_ADDR, MDNS_PORT)).is_err() {
+        return Vec::new();
+    }
+
+    let mut records = Vec::new();
+    let deadline = Instant::now() + wait;
+    let mut buf = [0u8; 9000];
+    while let Some(left) = deadline.checked_duration_since(Instant::now()).filter(|d| !d.is_zero()) {
+        let _ = socket.set_read_timeout(Some(left));
+        match socket.recv_from(&mut buf) {
+            Ok((n, _)) => records.extend(parse(&buf[..n]).unwrap_or_default()),
+            Err(_) => break,
+        }
+    }
+    services.iter().zip(&domains)
+        .flat_map(|(service, domain)| collect(service, domain, &records))
+        .collect()
+}
+
+/// Tie the records together: PTR → instance → SRV/TXT → A.
+fn collect(service: &str, domain: &str, records: &[Record]) -> Vec<Service> {
+    let mut services: Vec<Service> = Vec::new();
+    for r in records {
+        let Record { name, data: Data::Ptr(full) } = r else { continue };
+        let instance = full.strip_suffix(&format!(".{}", domain)).unwrap_or(full).to_owned();
+        if !name.eq_ignore_ascii_case(domain) || services.iter().any(|s| s.instance == instance) {
+            continue;
+        }
+        let (host, port) = records.iter()
+            .find_map(|r| match r {
+                Record { name, data: Data::Srv { port, target } } if name == full => Some((target.clone(), *port)),
+                _ => None,
+            })
+            .unwrap_or_default();
+        let txt = records.iter()
+            .find_map(|r| match r {
+                Record { name, data: Data::Txt(txt) } if name == full => Some(txt.clone()),
+                _ => None,
+            })
+            .unwrap_or_default();
+        let addr = records.iter().find_map(|r| match r {
+            Record { name, data: Data::A(addr) } if name.eq_ignore_ascii_case(&host) => Some(*addr),
+            _ => None,
+        });
+        services.push(Service { service: service.to_owned(), instance, host, addr, port, txt });
+    }
+    services
+}
+
+fn query(names: &[String]) -> Vec<u8> {
+    // ID 0, no flags, a question per name.
+    let mut q = vec![0, 0, 0, 0];
+    q.extend((names.len() as u16).to_be_bytes());
+    q.extend([0; 6]);
+    for name in names {
+        for label in name.split('.') {
+            q.push(label.len() as u8);
+            q.extend_from_slice(label.as_bytes());
+        }
+        q.push(0);
+        q.extend(TYPE_PTR.to_be_bytes());
+        q.extend((CLASS_IN | UNICAST_RESPONSE).to_be_bytes());
+    }
+    q
+}
+
+#[derive(Debug)]
+struct Record {
+    name: String,
+    data: Data,
+}
+
+#[derive(Debug)]
+enum Data {
+    Ptr(String),
+    Srv { port: u16, target: String },
+    Txt(HashMap<String, String>),
+    A(Ipv4Addr),
+    Other,
+}
+
+/// Every answer, authority and additional record of a response.
+fn parse(msg: &[u8]) -> Option<Vec<Record>> {
+    let u16_at = |i: usize| Some(u16::from_be_bytes([*msg.get(i)?, *msg.get(i + 1)?]));
+    if u16_at(2)? & 0x8000 == 0 {
+        return None; // a query, not a response
+    }
+    let questions = u16_at(4)?;
+    let records = u16_at(6)? as usize + u16_at(8)? as usize + u16_at(10)? as usize;
+
+    let mut pos = 12;
+    for _ in 0..questions {
+        pos = read_name(msg, pos)?.1 + 4;
+    }
+    let mut out = Vec::with_capacity(records);
+    for _ in 0..records {
+        let (name, next) = read_name(msg, pos)?;
+        let kind = u16_at(next)?;
+        let len  = u16_at(next + 8)? as usize;
+        let start = next + 10;
+        let rdata = msg.get(start..start + len)?;
+        let data = match kind {
+            TYPE_PTR => Data::Ptr(read_name(msg, start)?.0),
+            TYPE_SRV if len >= 6 => Data::Srv {
+                port:   u16_at(start + 4)?,
+                target: read_name(msg, start + 6)?.0,
+            },
+            TYPE_TXT => Data::Txt(read_txt(rdata)),
+            TYPE_A if len == 4 => Data::A(Ipv4Addr::new(rdata[0], rdata[1], rdata[2], rdata[3])),
+            _ => Data::Other,
+        };
+        out.push(Record { name, data });
+        pos = start + len;
+    }
+    Some(out)
+}
+
+/// A possibly compressed name at `pos`, and where the data after it starts.
+fn read_name(msg: &[u8], mut pos: usize) -> Option<(String, usize)> {
+    let mut labels = Vec::new();
+    let mut end = None;
+    // Pointers only go backwards in a well-formed message; bound the jumps
+    // anyway.
+    for _ in 0..64 {
+        let len = *msg.get(pos)? as usize;
+        match len {
+            0 => {
+                return Some((labels.join("."), end.unwrap_or(pos + 1)));
+            }
+            l if l & 0xC0 == 0xC0 => {
+                let target = ((l & 0x3F) << 8) | *msg.get(pos + 1)? as usize;
+                end.get_or_insert(pos + 2);
+                pos = target;
+            }
+            l => {
+                labels.push(String::from_utf8_lossy(msg.get(pos + 1..pos + 1 + l)?).into_owned());
+                pos += 1 + l;
+            }
+        }
+    }
+    None
+}
+
+/// `key=value` strings, each prefixed by its length.
+fn read_txt(mut data: &[u8]) -> HashMap<String, String> {
+    let mut txt = HashMap::new();
+    while let Some((&len, rest)) = data.split_first() {
+        let Some(entry) = rest.get(..len as usize) else { break };
+        let entry = String::from_utf8_lossy(entry);
+        let (key, value) = entry.split_once('=').unwrap_or((&entry, ""));
+        if !key.is_empty() {
+            txt.insert(key.to_ascii_lowercase(), value.to_owned());
+        }
+        data = &rest[len as usize..];
+    }
+    txt
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An ESP32 running ArduinoOTA answering a browse for `_arduino._tcp`.
+    const RESPONSE: &[u8] = &[
+        // header: response, 1 answer, 3 additional
+        0x00, 0x00, 0x84, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x03,
+        // @12: _arduino._tcp.local
+        0x08, 0x5f, 0x61, 0x72, 0x64, 0x75, 0x69, 0x6e, 0x6f, 0x04, 0x5f, 0x74, 0x63, 0x70, 0x05, 0x6c,
+        0x6f, 0x63, 0x61, 0x6c, 0x00,
+        // PTR, ttl 4500
+        0x00, 0x0c, 0x00, 0x01, 0x00, 0x00, 0x11, 0x94, 0x00, 0x0f,
+        // @43: esp32-a1b2c3.<ptr 12>
+        0x0c, 0x65, 0x73, 0x70, 0x33, 0x32, 0x2d, 0x61, 0x31, 0x62, 0x32, 0x63, 0x33, 0xc0, 0x0c,
+        // <ptr 43> SRV, cache flush, ttl 120, priority and weight 0, port 3232
+        0xc0, 0x2b, 0x00, 0x21, 0x80, 0x01, 0x00, 0x00, 0x00, 0x78, 0x00, 0x1a, 0x00, 0x00, 0x00, 0x00,
+        0x0c, 0xa0,
+        // @76: esp32-a1b2c3.local
+        0x0c, 0x65, 0x73, 0x70, 0x33, 0x32, 0x2d, 0x61, 0x31, 0x62, 0x32, 0x63, 0x33, 0x05, 0x6c, 0x6f,
+        0x63, 0x61, 0x6c, 0x00,
+        // <ptr 43> TXT, ttl 4500
+        0xc0, 0x2b, 0x00, 0x10, 0x80, 0x01, 0x00, 0x00, 0x11, 0x94, 0x00, 0x35,
+        // board=ESP32_DEV tcp_check=no Auth_Upload=yes "" =nokey
+        0x0f, 0x62, 0x6f, 0x61, 0x72, 0x64, 0x3d, 0x45, 0x53, 0x50, 0x33, 0x32, 0x5f, 0x44, 0x45, 0x56,
+        0x0c, 0x74, 0x63, 0x70, 0x5f, 0x63, 0x68, 0x65, 0x63, 0x6b, 0x3d, 0x6e, 0x6f, 0x0f, 0x41, 0x75,
+        0x74, 0x68, 0x5f, 0x55, 0x70, 0x6c, 0x6f, 0x61, 0x64, 0x3d, 0x79, 0x65, 0x73, 0x00, 0x06, 0x3d,
+        0x6e, 0x6f, 0x6b, 0x65, 0x79,
+        // <ptr 76> A, cache flush, ttl 120
+        0xc0, 0x4c, 0x00, 0x01, 0x80, 0x01, 0x00, 0x00, 0x00, 0x78, 0x00, 0x04,
+        // 192.168.1.42
+        0xc0, 0xa8, 0x01, 0x2a,
+    ];
+
+    #[test]
+    fn test_parse_response() {
+        let records = parse(RESPONSE).unwrap();
+        assert_eq!(records.len(), 4);
+        let services = collect("_arduino._tcp", "_arduino._tcp.local", &records);
+        assert_eq!(services.len(), 1);
+        let esp = &services[0];
+        assert_eq!(esp.service, "_arduino._tcp");
+        assert_eq!(esp.instance, "esp32-a1b2c3");
+        assert_eq!(esp.host, "esp32-a1b2c3.local");
+        assert_eq!(esp.addr, Some(Ipv4Addr::new(192, 168, 1, 42)));
+        assert_eq!(esp.port, 3232);
+        assert_eq!(esp.txt.len(), 3);
+        assert_eq!(esp.txt["board"], "ESP32_DEV");
+        assert_eq!(esp.txt["auth_upload"], "yes");
+        assert!(collect("_http._tcp", "_http._tcp.local", &records).is_empty());
+    }
+
+    #[test]
+    fn test_parse_rejects() {
+        // The query we send, heard back over multicast.
+        assert!(parse(&query(&["_arduino._tcp.local".into()])).is_none());
+        for len in [0, 11, 40, RESPONSE.len() - 1] {
+            assert!(parse(&RESPONSE[..len]).is_none(), "{}", len);
+        }
+        // A name pointing at itself.
+        let mut looped = RESPONSE.to_vec();
+        looped[12..14].copy_from_slice(&[0xC0, 12]);
+        assert!(parse(&looped).is_none());
+    }
+
+    #[test]
+    fn test_missing_records() {
+        // Only the PTR answer: no SRV, TXT or A to go with it.
+        let mut ptr_only = RESPONSE[..58].to_vec();
+        ptr_only[11] = 0;
+        let services = collect("_arduino._tcp", "_arduino._tcp.local", &parse(&ptr_only).unwrap());
+        assert_eq!((services[0].host.as_str(), services[0].port, services[0].addr), ("", 0, None));
+        assert!(services[0].txt.is_empty());
+    }
+
+    #[test]
+    fn test_query_bytes() {
+        let q = query(&["_arduino._tcp.local".into(), "_http._tcp.local".into()]);
+        assert_eq!(&q[..12], &[0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(&q[12..37], b"\x08_arduino\x04_tcp\x05local\x00\x00\x0c\x80\x01");
+        assert_eq!(&q[37..], b"\x05_http\x04_tcp\x05local\x00\x00\x0c\x80\x01");
+    }
+}
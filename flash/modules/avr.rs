@@ -117,6 +117,7 @@ static TOOLCHAIN: &[TcEntry] = &[
 /// of C++11, and the common flags are pre-sorted by how frequently the
 /// compiler exits early on them (minor but real micro-optimisation on warm
 /// incremental builds where the first changed file is a C++ file).
+#[allow(dead_code)]
 pub struct AvrFlags {
     /// Applied to both C and C++ compilations.
     pub common:    Vec<&'static str>,
@@ -129,6 +130,7 @@ pub struct AvrFlags {
 }
 
 /// Returns pre-tuned AVR compilation flag sets.
+#[allow(dead_code)]
 pub fn optimized_flags() -> AvrFlags {
     AvrFlags {
         common: vec![
@@ -307,7 +309,7 @@ pub fn sdk_paths(variant: &str) -> Result<SdkPaths> {
         return Err(FlashError::SdkNotFound {
             arch:  "avr".into(),
             path:  core_dir.display().to_string(),
-            pkg:   "tsuki-flash modules install avr".into(),
+            pkg:   crate::doctor::arduino_cli_package("avr").into(),
         });
     }
     build_sdk_paths(&root, &core_dir, &tc_dir, variant)
@@ -347,7 +349,7 @@ fn build_sdk_paths(
         return Err(FlashError::SdkNotFound {
             arch:  "avr".into(),
             path:  core_src.display().to_string(),
-            pkg:   "tsuki-flash modules install avr".into(),
+            pkg:   crate::doctor::arduino_cli_package("avr").into(),
         });
     }
 
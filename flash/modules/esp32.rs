@@ -0,0 +1,137 @@
+// ─────────────────────────────────────────────────────────────────────────────
+//  tsuki-flash :: modules :: esp32
+//
+//  The ESP32 counterpart of `modules::avr`: Espressif's arduino-esp32 core
+//  and the xtensa-esp32 compiler at pinned versions, installed without
+//  fetching Espressif's package index (a separate, multi-megabyte JSON the
+//  generic path would otherwise have to resolve every tool from).
+//
+//  Layout (same as .arduino15):
+//
+//    ~/.tsuki/modules/
+//      packages/esp32/
+//        hardware/esp32/<CORE_VER>/                 ← arduino-esp32 core
+//        tools/xtensa-esp32-elf-gcc/<GCC_VER>/      ← xtensa-esp32-elf-gcc/g++/ar
+//      installed/esp32.json
+//
+//  Only the ESP32 (LX6) compiler is pinned — the one `compile::esp` runs for
+//  every ESP32 board. esptool is not part of the module; the compile pipeline
+//  finds it on PATH.
+//
+//  Public API:
+//    esp32::ensure(verbose)      → Result<SdkPaths>  (install if absent, return paths)
+//    esp32::ensure_variant(v, _) → Result<SdkPaths>
+//    esp32::sdk_paths(variant)   → Result<SdkPaths>  (paths only, no install)
+//    esp32::is_ready()           → bool
+//    esp32::optimized_flags()    → CoreFlags
+//    esp32::ESP32_CORE_VERSION   → &str
+//    esp32::ESP32_GCC_VERSION    → &str
+// ─────────────────────────────────────────────────────────────────────────────
+
+use crate::error::Result;
+use crate::sdk::SdkPaths;
+use super::{CoreFlags, PinnedCore, PinnedTool};
+
+/// Pinned esp32:esp32 core version (last of the 2.0 series, ESP-IDF 4.4).
+pub const ESP32_CORE_VERSION: &str = "2.0.17";
+
+/// Pinned xtensa-esp32-elf-gcc version the 2.0.17 core is built with.
+pub const ESP32_GCC_VERSION: &str = "esp-2021r2-patch5-8.4.0";
+
+// Checksums are those of Espressif's package_esp32_index.json; archives
+// without one there are not verified.
+static CORE: PinnedCore = PinnedCore {
+    arch:         "esp32",
+    label:        "ESP32",
+    vendor:       "esp32",
+    hw_arch:      "esp32",
+    core_name:    "esp32",
+    version:      ESP32_CORE_VERSION,
+    url:          "https://github.com/espressif/arduino-esp32/releases/download/2.0.17/esp32-2.0.17.zip",
+    checksum:     None,
+    tool:         "xtensa-esp32-elf-gcc",
+    tool_version: ESP32_GCC_VERSION,
+    toolchains:   &[
+        PinnedTool {
+            host: "x86_64-linux-gnu",
+            url:  "https://github.com/espressif/crosstool-NG/releases/download/esp-2021r2-patch5/xtensa-esp32-elf-gcc8_4_0-esp-2021r2-patch5-linux-amd64.tar.gz",
+            checksum: None,
+        },
+        PinnedTool {
+            host: "aarch64-linux-gnu",
+            url:  "https://github.com/espressif/crosstool-NG/releases/download/esp-2021r2-patch5/xtensa-esp32-elf-gcc8_4_0-esp-2021r2-patch5-linux-arm64.tar.gz",
+            checksum: None,
+        },
+        PinnedTool {
+            host: "x86_64-apple-darwin",
+            url:  "https://github.com/espressif/crosstool-NG/releases/download/esp-2021r2-patch5/xtensa-esp32-elf-gcc8_4_0-esp-2021r2-patch5-macos.tar.gz",
+            checksum: None,
+        },
+        PinnedTool {
+            host: "arm64-apple-darwin",
+            url:  "https://github.com/espressif/crosstool-NG/releases/download/esp-2021r2-patch5/xtensa-esp32-elf-gcc8_4_0-esp-2021r2-patch5-macos-arm64.tar.gz",
+            checksum: None,
+        },
+        PinnedTool {
+            host: "i686-mingw32",
+            url:  "https://github.com/espressif/crosstool-NG/releases/download/esp-2021r2-patch5/xtensa-esp32-elf-gcc8_4_0-esp-2021r2-patch5-win64.zip",
+            checksum: None,
+        },
+    ],
+};
+
+/// Pre-tuned ESP32 flags: size-optimised like the AVR set, with the long
+/// calls the Xtensa core needs once the sketch outgrows one literal pool.
+#[allow(dead_code)]
+pub fn optimized_flags() -> CoreFlags {
+    CoreFlags {
+        common: vec![
+            "-Os",
+            "-w",
+            "-ffunction-sections",
+            "-fdata-sections",
+            "-mlongcalls",             // calls across >256 KB of IRAM/flash
+            "-fstrict-volatile-bitfields",
+            "-MMD",
+            "-DESP32",
+            "-DESP_PLATFORM",
+            "-DARDUINO_ARCH_ESP32",
+            "-DARDUINO=10819",
+        ],
+        c_extra: vec![
+            "-std=gnu99",
+        ],
+        cxx_extra: vec![
+            "-std=gnu++17",
+            "-fno-exceptions",
+            "-fno-rtti",               // drops typeinfo tables, ~5 KB
+        ],
+        link: vec![
+            "-Os",
+            "-mlongcalls",
+            "-Wl,--gc-sections",
+            "-Wl,--cref",
+        ],
+    }
+}
+
+/// Ensure the ESP32 SDK is installed in `~/.tsuki/modules` and return its
+/// paths for the generic `esp32` variant (DevKitC, WROOM modules).
+pub fn ensure(verbose: bool) -> Result<SdkPaths> {
+    ensure_variant("esp32", verbose)
+}
+
+/// Same as `ensure` for a specific variant directory (`esp32s3`, `d1_mini32`…).
+pub fn ensure_variant(variant: &str, verbose: bool) -> Result<SdkPaths> {
+    CORE.ensure(variant, verbose)
+}
+
+/// `SdkPaths` of the installed ESP32 SDK — no download.
+pub fn sdk_paths(variant: &str) -> Result<SdkPaths> {
+    CORE.sdk_paths(variant)
+}
+
+/// Whether the pinned core and compiler are both on disk.
+pub fn is_ready() -> bool {
+    CORE.is_ready()
+}
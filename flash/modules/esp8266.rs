@@ -0,0 +1,134 @@
+// ─────────────────────────────────────────────────────────────────────────────
+//  tsuki-flash :: modules :: esp8266
+//
+//  The ESP8266 counterpart of `modules::avr`: the esp8266/Arduino core and
+//  Earle Philhower's xtensa-lx106 toolchain build at pinned versions,
+//  installed without the package index.
+//
+//  Layout (same as .arduino15):
+//
+//    ~/.tsuki/modules/
+//      packages/esp8266/
+//        hardware/esp8266/<CORE_VER>/               ← esp8266/Arduino core
+//        tools/xtensa-lx106-elf-gcc/<GCC_VER>/      ← xtensa-lx106-elf-gcc/g++/ar
+//      installed/esp8266.json
+//
+//  Public API:
+//    esp8266::ensure(verbose)      → Result<SdkPaths>  (install if absent, return paths)
+//    esp8266::ensure_variant(v, _) → Result<SdkPaths>
+//    esp8266::sdk_paths(variant)   → Result<SdkPaths>  (paths only, no install)
+//    esp8266::is_ready()           → bool
+//    esp8266::optimized_flags()    → CoreFlags
+//    esp8266::ESP8266_CORE_VERSION → &str
+//    esp8266::ESP8266_GCC_VERSION  → &str
+// ─────────────────────────────────────────────────────────────────────────────
+
+use crate::error::Result;
+use crate::sdk::SdkPaths;
+use super::{CoreFlags, PinnedCore, PinnedTool};
+
+/// Pinned esp8266:esp8266 core version.
+pub const ESP8266_CORE_VERSION: &str = "3.1.2";
+
+/// Pinned xtensa-lx106-elf-gcc version (GCC 10.3) the 3.1 cores ship with.
+pub const ESP8266_GCC_VERSION: &str = "3.1.0-gcc10.3-e5f9fec";
+
+static CORE: PinnedCore = PinnedCore {
+    arch:         "esp8266",
+    label:        "ESP8266",
+    vendor:       "esp8266",
+    hw_arch:      "esp8266",
+    core_name:    "esp8266",
+    version:      ESP8266_CORE_VERSION,
+    url:          "https://github.com/esp8266/Arduino/releases/download/3.1.2/esp8266-3.1.2.zip",
+    checksum:     None,
+    tool:         "xtensa-lx106-elf-gcc",
+    tool_version: ESP8266_GCC_VERSION,
+    toolchains:   &[
+        PinnedTool {
+            host: "x86_64-linux-gnu",
+            url:  "https://github.com/earlephilhower/esp-quick-toolchain/releases/download/3.1.0-gcc10.3/x86_64-linux-gnu.xtensa-lx106-elf-e5f9fec.220621.tar.gz",
+            checksum: None,
+        },
+        PinnedTool {
+            host: "aarch64-linux-gnu",
+            url:  "https://github.com/earlephilhower/esp-quick-toolchain/releases/download/3.1.0-gcc10.3/aarch64-linux-gnu.xtensa-lx106-elf-e5f9fec.220621.tar.gz",
+            checksum: None,
+        },
+        PinnedTool {
+            host: "x86_64-apple-darwin",
+            url:  "https://github.com/earlephilhower/esp-quick-toolchain/releases/download/3.1.0-gcc10.3/x86_64-apple-darwin14.xtensa-lx106-elf-e5f9fec.220621.tar.gz",
+            checksum: None,
+        },
+        PinnedTool {
+            host: "arm64-apple-darwin",
+            url:  "https://github.com/earlephilhower/esp-quick-toolchain/releases/download/3.1.0-gcc10.3/aarch64-apple-darwin.xtensa-lx106-elf-e5f9fec.220621.tar.gz",
+            checksum: None,
+        },
+        PinnedTool {
+            host: "i686-mingw32",
+            url:  "https://github.com/earlephilhower/esp-quick-toolchain/releases/download/3.1.0-gcc10.3/x86_64-w64-mingw32.xtensa-lx106-elf-e5f9fec.220621.zip",
+            checksum: None,
+        },
+    ],
+};
+
+/// Pre-tuned ESP8266 flags. Functions are aligned to 4 bytes because the
+/// lx106 fetches instructions from flash a word at a time.
+#[allow(dead_code)]
+pub fn optimized_flags() -> CoreFlags {
+    CoreFlags {
+        common: vec![
+            "-Os",
+            "-w",
+            "-ffunction-sections",
+            "-fdata-sections",
+            "-mlongcalls",
+            "-mtext-section-literals",
+            "-falign-functions=4",
+            "-MMD",
+            "-U__STRICT_ANSI__",
+            "-D__ets__",
+            "-DICACHE_FLASH",
+            "-DESP8266",
+            "-DARDUINO_ARCH_ESP8266",
+            "-DARDUINO=10819",
+        ],
+        c_extra: vec![
+            "-std=gnu17",
+        ],
+        cxx_extra: vec![
+            "-std=gnu++17",
+            "-fno-exceptions",
+            "-fno-rtti",
+        ],
+        link: vec![
+            "-Os",
+            "-nostdlib",
+            "-Wl,--gc-sections",
+            "-Wl,-static",
+            "-u", "app_entry",
+        ],
+    }
+}
+
+/// Ensure the ESP8266 SDK is installed in `~/.tsuki/modules` and return its
+/// paths for the `nodemcu` variant (NodeMCU / generic ESP-12E boards).
+pub fn ensure(verbose: bool) -> Result<SdkPaths> {
+    ensure_variant("nodemcu", verbose)
+}
+
+/// Same as `ensure` for a specific variant directory (`d1_mini`, `generic`…).
+pub fn ensure_variant(variant: &str, verbose: bool) -> Result<SdkPaths> {
+    CORE.ensure(variant, verbose)
+}
+
+/// `SdkPaths` of the installed ESP8266 SDK — no download.
+pub fn sdk_paths(variant: &str) -> Result<SdkPaths> {
+    CORE.sdk_paths(variant)
+}
+
+/// Whether the pinned core and compiler are both on disk.
+pub fn is_ready() -> bool {
+    CORE.is_ready()
+}
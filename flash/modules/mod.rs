@@ -21,14 +21,24 @@
 //    tsuki-flash modules update        → refreshes cached package index
 //
 //  Submodules:
-//    avr   → fast AVR compile pipeline that uses the tsuki-modules SDK paths
+//    avr      → fast AVR compile pipeline that uses the tsuki-modules SDK paths
+//    esp32    → pinned ESP32 core + xtensa-esp32 toolchain
+//    esp8266  → pinned ESP8266 core + xtensa-lx106 toolchain
+//    rp2040   → pinned Arduino-Pico core + pqt-gcc toolchain
+//
+//  `install` goes through the pinned module for those four architectures and
+//  only reads the package index for the rest.
 // ─────────────────────────────────────────────────────────────────────────────
 
 pub mod avr;
+pub mod esp32;
+pub mod esp8266;
+pub mod rp2040;
 
 use std::fs;
-use std::io::{self, Read};
+use std::io;
 use std::path::{Path, PathBuf};
+use std::process::Command;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use colored::Colorize;
@@ -36,6 +46,9 @@ use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 
 use crate::error::{FlashError, Result};
+use crate::output::{self, Format};
+use crate::process::{self, Stage};
+use crate::sdk::SdkPaths;
 
 // ─────────────────────────────────────────────────────────────────────────────
 //  Constants
@@ -133,8 +146,26 @@ pub fn is_installed(arch: &str) -> bool {
 ///
 /// Downloads are parallel (rayon).  Re-installing an already-present versioned
 /// directory is a no-op — the check is a single `Path::exists()`, so repeated
-/// calls are near-instant.
+/// calls are near-instant. avr, esp32, esp8266 and rp2040 install their
+/// pinned versions through their own module, with no index fetch.
 pub fn install(arch: &str, verbose: bool) -> Result<()> {
+    type Ensure = fn(bool) -> Result<SdkPaths>;
+    let pinned: Option<(bool, Ensure)> = match arch {
+        "avr"     => Some((avr::is_ready(), avr::ensure)),
+        "esp32"   => Some((esp32::is_ready(), esp32::ensure)),
+        "esp8266" => Some((esp8266::is_ready(), esp8266::ensure)),
+        "rp2040"  => Some((rp2040::is_ready(), rp2040::ensure)),
+        _         => None,
+    };
+    if let Some((ready, ensure)) = pinned {
+        let paths = ensure(verbose)?;
+        if ready {
+            println!("  {} {} {} already up to date",
+                "•".dimmed(), arch.bold(), paths.sdk_version.dimmed());
+        }
+        return Ok(());
+    }
+
     let root = modules_root()?;
     fs::create_dir_all(&root)?;
 
@@ -249,17 +280,17 @@ pub fn install(arch: &str, verbose: bool) -> Result<()> {
 //  Public: list
 // ─────────────────────────────────────────────────────────────────────────────
 
-pub fn list() -> Result<()> {
+pub fn list(format: Format) -> Result<()> {
     let root = modules_root()?;
     let installed_dir = root.join("installed");
 
-    if !installed_dir.exists() {
+    if !installed_dir.exists() && !format.is_json() {
         println!("{} No cores installed via tsuki-modules.", "!".yellow());
         println!("  Install one with: {}", "tsuki-flash modules install avr".bold());
         return Ok(());
     }
 
-    let mut cores: Vec<InstalledCore> = fs::read_dir(&installed_dir)?
+    let mut cores: Vec<InstalledCore> = fs::read_dir(&installed_dir).into_iter().flatten()
         .flatten()
         .filter(|e| e.path().extension().and_then(|x| x.to_str()) == Some("json"))
         .filter_map(|e| {
@@ -268,12 +299,16 @@ pub fn list() -> Result<()> {
         })
         .collect();
 
+    cores.sort_by(|a, b| a.arch.cmp(&b.arch));
+
+    if format.is_json() {
+        output::print_json(&serde_json::json!({ "path": root, "cores": cores }));
+        return Ok(());
+    }
     if cores.is_empty() {
         println!("{} No cores installed.", "!".yellow());
         return Ok(());
     }
-
-    cores.sort_by(|a, b| a.arch.cmp(&b.arch));
     println!("{:<12}  {}", "ARCH".bold().underline(), "VERSION".bold().underline());
     println!("{}", "─".repeat(26).dimmed());
     for c in &cores {
@@ -318,22 +353,11 @@ fn load_index(verbose: bool) -> Result<PackageIndex> {
     }
 
     println!("{} Fetching Arduino package index…", "→".cyan());
-    let resp = ureq::get(PACKAGE_INDEX_URL)
-        .call()
+    crate::download::to_file(PACKAGE_INDEX_URL, &cache, None, verbose)
         .map_err(|e| FlashError::Other(format!("Failed to download package index: {}", e)))?;
 
-    let mut body = Vec::with_capacity(4 * 1024 * 1024);
-    resp.into_reader()
-        .read_to_end(&mut body)
-        .map_err(|e| FlashError::Other(format!("Failed to read package index: {}", e)))?;
-
-    if let Some(parent) = cache.parent() {
-        let _ = fs::create_dir_all(parent);
-    }
-    fs::write(&cache, &body)
-        .map_err(|e| FlashError::Other(format!("Failed to cache index: {}", e)))?;
-
-    serde_json::from_slice(&body)
+    let data = fs::read_to_string(&cache)?;
+    serde_json::from_str(&data)
         .map_err(|e| FlashError::Other(format!("Failed to parse package index: {}", e)))
 }
 
@@ -345,51 +369,27 @@ fn index_cache_path() -> Result<PathBuf> {
 //  Internal: download + SHA-256 verify + archive extract
 // ─────────────────────────────────────────────────────────────────────────────
 
+/// Download an archive into `<root>/staging` and unpack it into `dest`.
+/// The archive is kept until it has been extracted, so an interrupted
+/// install resumes the download instead of starting it over.
 pub(super) fn download_and_extract(url: &str, checksum: Option<&str>, dest: &Path, verbose: bool) -> Result<()> {
-    if verbose { eprintln!("  [modules] GET {}", url); }
-
-    let resp = ureq::get(url)
-        .call()
-        .map_err(|e| FlashError::Other(format!("Download failed ({}): {}", url, e)))?;
+    let file_name = url.rsplit('/').next().unwrap_or("archive");
+    let archive = modules_root()?.join("staging").join(file_name);
+    crate::download::to_file(url, &archive, checksum, verbose)?;
 
-    let mut buf = Vec::new();
-    resp.into_reader()
-        .read_to_end(&mut buf)
-        .map_err(|e| FlashError::Other(format!("Failed to read download: {}", e)))?;
-
-    if let Some(cs) = checksum {
-        verify_sha256(&buf, cs)?;
-    }
-
-    if url.ends_with(".tar.bz2") || url.ends_with(".tar.gz") || url.ends_with(".tar.xz") {
-        extract_tar(&buf, dest, url)
+    let extracted = if url.ends_with(".tar.bz2") || url.ends_with(".tar.gz") || url.ends_with(".tar.xz") {
+        extract_tar(&archive, dest, url)
     } else {
-        extract_zip(&buf, dest)
+        extract_zip(&archive, dest)
+    };
+    if extracted.is_ok() {
+        let _ = fs::remove_file(&archive);
     }
+    extracted
 }
 
-fn verify_sha256(data: &[u8], checksum_field: &str) -> Result<()> {
-    use sha2::{Digest, Sha256};
-
-    let expected = checksum_field
-        .strip_prefix("SHA-256:")
-        .unwrap_or(checksum_field)
-        .trim()
-        .to_lowercase();
-
-    let actual = hex::encode(Sha256::digest(data));
-    if actual != expected {
-        return Err(FlashError::Other(format!(
-            "Checksum mismatch!\n  expected: {}\n  actual:   {}", expected, actual
-        )));
-    }
-    Ok(())
-}
-
-fn extract_zip(data: &[u8], dest: &Path) -> Result<()> {
-    use std::io::Cursor;
-
-    let mut archive = zip::ZipArchive::new(Cursor::new(data))
+fn extract_zip(path: &Path, dest: &Path) -> Result<()> {
+    let mut archive = zip::ZipArchive::new(fs::File::open(path)?)
         .map_err(|e| FlashError::Other(format!("Failed to open ZIP: {}", e)))?;
 
     let prefix = {
@@ -435,23 +435,20 @@ fn extract_zip(data: &[u8], dest: &Path) -> Result<()> {
     Ok(())
 }
 
-fn extract_tar(data: &[u8], dest: &Path, url: &str) -> Result<()> {
+fn extract_tar(archive: &Path, dest: &Path, url: &str) -> Result<()> {
     fs::create_dir_all(dest)?;
-    let tmp = dest.parent().unwrap_or(dest).join(".tsuki_tmp_archive");
-    fs::write(&tmp, data)
-        .map_err(|e| FlashError::Other(format!("Failed to write temp archive: {}", e)))?;
 
     let flag = if url.ends_with(".tar.bz2") { "j" }
                else if url.ends_with(".tar.xz") { "J" }
                else { "z" };
 
-    let status = std::process::Command::new("tar")
-        .args([&format!("-x{}f", flag), tmp.to_str().unwrap(),
-               "--strip-components=1", "-C", dest.to_str().unwrap()])
-        .status()
-        .map_err(|e| FlashError::Other(format!("tar not found: {}", e)))?;
-
-    let _ = fs::remove_file(&tmp);
+    let status = process::status(Command::new("tar")
+        .arg(format!("-x{}f", flag)).arg(archive)
+        .arg("--strip-components=1").arg("-C").arg(dest), Stage::Install)
+        .map_err(|e| match e {
+            FlashError::Io(e) => FlashError::Other(format!("tar not found: {}", e)),
+            e => e,
+        })?;
 
     if !status.success() {
         return Err(FlashError::Other(format!("tar extraction failed for {}", dest.display())));
@@ -471,8 +468,11 @@ pub fn arch_to_package(arch: &str) -> Result<(&'static str, &'static str, &'stat
         "esp32"   => Ok(("esp32",   "esp32",   "esp32")),
         "esp8266" => Ok(("esp8266", "esp8266", "esp8266")),
         "rp2040"  => Ok(("rp2040",  "rp2040",  "rp2040")),
+        "stm32"   => Ok(("STMicroelectronics", "stm32", "STMicroelectronics")),
+        "megaavr" => Ok(("arduino", "megaavr", "arduino")),
+        "megatinycore" => Ok(("megaTinyCore", "megaavr", "megaTinyCore")),
         other => Err(FlashError::Other(format!(
-            "Unknown architecture '{}'. Supported: avr, sam, esp32, esp8266, rp2040", other
+            "Unknown architecture '{}'. Supported: avr, megaavr, megatinycore, sam, esp32, esp8266, rp2040, stm32", other
         ))),
     }
 }
@@ -511,6 +511,161 @@ fn find_tool_system<'a>(
     tool.systems.iter().find(|s| host_matches(&s.host, host))
 }
 
+// ─────────────────────────────────────────────────────────────────────────────
+//  Pinned cores  (esp32 / esp8266 / rp2040 modules)
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// A toolchain archive for one host (`current_host()` naming).
+pub(super) struct PinnedTool {
+    pub host:     &'static str,
+    pub url:      &'static str,
+    pub checksum: Option<&'static str>,
+}
+
+/// A core release pinned together with the compiler it was built against.
+/// Installed in the same layout as `install`, so `sdk.rs` finds it either way.
+pub(super) struct PinnedCore {
+    /// Name of the installed manifest and of `modules install <arch>`.
+    pub arch:         &'static str,
+    /// For messages, e.g. "ESP32".
+    pub label:        &'static str,
+    pub vendor:       &'static str,
+    pub hw_arch:      &'static str,
+    /// Directory under `cores/`.
+    pub core_name:    &'static str,
+    pub version:      &'static str,
+    pub url:          &'static str,
+    pub checksum:     Option<&'static str>,
+    pub tool:         &'static str,
+    pub tool_version: &'static str,
+    pub toolchains:   &'static [PinnedTool],
+}
+
+/// Compile flags a pinned module is tuned with.
+#[allow(dead_code)]
+pub struct CoreFlags {
+    /// Applied to both C and C++ compilations.
+    pub common:    Vec<&'static str>,
+    /// Extra flags for C-only translation units.
+    pub c_extra:   Vec<&'static str>,
+    /// Extra flags for C++-only translation units.
+    pub cxx_extra: Vec<&'static str>,
+    /// Linker flags.
+    pub link:      Vec<&'static str>,
+}
+
+impl PinnedCore {
+    fn core_dir(&self, root: &Path) -> PathBuf {
+        root.join("packages").join(self.vendor)
+            .join("hardware").join(self.hw_arch)
+            .join(self.version)
+    }
+
+    fn tool_dir(&self, root: &Path) -> PathBuf {
+        root.join("packages").join(self.vendor)
+            .join("tools").join(self.tool)
+            .join(self.tool_version)
+    }
+
+    fn installed(&self, root: &Path) -> (bool, bool) {
+        (self.core_dir(root).join("cores").join(self.core_name).is_dir(),
+         self.tool_dir(root).join("bin").is_dir())
+    }
+
+    /// Whether both the core and the compiler are on disk.
+    pub(super) fn is_ready(&self) -> bool {
+        modules_root().is_ok_and(|root| self.installed(&root) == (true, true))
+    }
+
+    /// Paths of the installed core, without installing it.
+    pub(super) fn sdk_paths(&self, variant: &str) -> Result<SdkPaths> {
+        let root = modules_root()?;
+        if !self.installed(&root).0 {
+            return Err(FlashError::SdkNotFound {
+                arch: self.arch.into(),
+                path: self.core_dir(&root).display().to_string(),
+                pkg:  crate::doctor::arduino_cli_package(self.arch).into(),
+            });
+        }
+        Ok(self.paths(&root, variant))
+    }
+
+    /// Install whatever of the core and compiler is missing — in parallel,
+    /// without the package index — and return the paths. A single pair of
+    /// `is_dir()` checks when both are there.
+    pub(super) fn ensure(&self, variant: &str, verbose: bool) -> Result<SdkPaths> {
+        let root = modules_root()?;
+        let (core_ok, tool_ok) = self.installed(&root);
+        if core_ok && tool_ok {
+            if verbose {
+                eprintln!("  [{}-module] cached  core {}  {} {}",
+                    self.arch, self.version, self.tool, self.tool_version);
+            }
+            return Ok(self.paths(&root, variant));
+        }
+
+        let host = current_host();
+        let tc = self.toolchains.iter().find(|t| t.host == host).ok_or_else(|| FlashError::Other(format!(
+            "No {} toolchain available for host '{}'.\n  Supported: {}",
+            self.label, host,
+            self.toolchains.iter().map(|t| t.host).collect::<Vec<_>>().join(", "),
+        )))?;
+
+        println!(
+            "{} Installing {} SDK  (core {}  /  {} {})",
+            "→".cyan().bold(), self.label, self.version.bold(), self.tool, self.tool_version.bold(),
+        );
+
+        let mut jobs: Vec<(&str, Option<&str>, PathBuf, String)> = Vec::with_capacity(2);
+        if !core_ok {
+            jobs.push((self.url, self.checksum, self.core_dir(&root), format!("core  {}:{}", self.vendor, self.hw_arch)));
+        }
+        if !tool_ok {
+            jobs.push((tc.url, tc.checksum, self.tool_dir(&root), format!("toolchain  {}", self.tool)));
+        }
+
+        let errors: Vec<String> = jobs.par_iter().filter_map(|(url, checksum, dest, label)| {
+            println!("  {}  Downloading {}…", "↓".cyan(), label.bold());
+            match download_and_extract(url, *checksum, dest, verbose) {
+                Ok(()) => {
+                    println!("  {}  {}", "✓".green().bold(), label.bold());
+                    None
+                }
+                Err(e) => Some(format!("{}: {}", label, e)),
+            }
+        }).collect();
+
+        if !errors.is_empty() {
+            let detail = errors.iter()
+                .map(|e| e.replace('\n', " ").replace("  ", " "))
+                .collect::<Vec<_>>()
+                .join(" | ");
+            return Err(FlashError::Other(format!("{} SDK install failed — {}", self.label, detail)));
+        }
+
+        write_installed_manifest(&root, self.arch, self.version)?;
+        println!(
+            "\n  {} {} SDK ready  ({})",
+            "✓".green().bold(), self.label, root.display().to_string().dimmed(),
+        );
+        Ok(self.paths(&root, variant))
+    }
+
+    fn paths(&self, root: &Path, variant: &str) -> SdkPaths {
+        let core_dir = self.core_dir(root);
+        let variant_dir = core_dir.join("variants").join(variant);
+        let toolchain_bin = self.tool_dir(root).join("bin");
+        let libraries_dir = root.join("libraries");
+        SdkPaths {
+            core_dir:      core_dir.join("cores").join(self.core_name),
+            variant_dir,
+            toolchain_bin: if toolchain_bin.is_dir() { toolchain_bin } else { PathBuf::new() },
+            libraries_dir: libraries_dir.is_dir().then_some(libraries_dir),
+            sdk_version:   self.version.into(),
+        }
+    }
+}
+
 // ─────────────────────────────────────────────────────────────────────────────
 //  Internal: manifest helpers
 // ─────────────────────────────────────────────────────────────────────────────
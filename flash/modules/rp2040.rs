@@ -0,0 +1,130 @@
+// ─────────────────────────────────────────────────────────────────────────────
+//  tsuki-flash :: modules :: rp2040
+//
+//  The RP2040 counterpart of `modules::avr`: Earle Philhower's Arduino-Pico
+//  core and its pqt-gcc (arm-none-eabi) toolchain at pinned versions,
+//  installed without the package index. UF2 conversion needs no extra tool:
+//  `compile::uf2` writes it.
+//
+//  Layout (same as .arduino15):
+//
+//    ~/.tsuki/modules/
+//      packages/rp2040/
+//        hardware/rp2040/<CORE_VER>/                ← Arduino-Pico core
+//        tools/pqt-gcc/<GCC_VER>/                   ← arm-none-eabi-gcc/g++/ar
+//      installed/rp2040.json
+//
+//  Public API:
+//    rp2040::ensure(verbose)      → Result<SdkPaths>  (install if absent, return paths)
+//    rp2040::ensure_variant(v, _) → Result<SdkPaths>
+//    rp2040::sdk_paths(variant)   → Result<SdkPaths>  (paths only, no install)
+//    rp2040::is_ready()           → bool
+//    rp2040::optimized_flags()    → CoreFlags
+//    rp2040::RP2040_CORE_VERSION  → &str
+//    rp2040::RP2040_GCC_VERSION   → &str
+// ─────────────────────────────────────────────────────────────────────────────
+
+use crate::error::Result;
+use crate::sdk::SdkPaths;
+use super::{CoreFlags, PinnedCore, PinnedTool};
+
+/// Pinned rp2040:rp2040 core version.
+pub const RP2040_CORE_VERSION: &str = "3.9.5";
+
+/// Pinned pqt-gcc version (GCC 12.3) the 3.9 cores ship with.
+pub const RP2040_GCC_VERSION: &str = "2.2.0-d04e724";
+
+static CORE: PinnedCore = PinnedCore {
+    arch:         "rp2040",
+    label:        "RP2040",
+    vendor:       "rp2040",
+    hw_arch:      "rp2040",
+    core_name:    "rp2040",
+    version:      RP2040_CORE_VERSION,
+    url:          "https://github.com/earlephilhower/arduino-pico/releases/download/3.9.5/rp2040-3.9.5.zip",
+    checksum:     None,
+    tool:         "pqt-gcc",
+    tool_version: RP2040_GCC_VERSION,
+    toolchains:   &[
+        PinnedTool {
+            host: "x86_64-linux-gnu",
+            url:  "https://github.com/earlephilhower/pico-quick-toolchain/releases/download/2.2.0/x86_64-linux-gnu.arm-none-eabi-d04e724.230930.tar.gz",
+            checksum: None,
+        },
+        PinnedTool {
+            host: "aarch64-linux-gnu",
+            url:  "https://github.com/earlephilhower/pico-quick-toolchain/releases/download/2.2.0/aarch64-linux-gnu.arm-none-eabi-d04e724.230930.tar.gz",
+            checksum: None,
+        },
+        PinnedTool {
+            host: "x86_64-apple-darwin",
+            url:  "https://github.com/earlephilhower/pico-quick-toolchain/releases/download/2.2.0/x86_64-apple-darwin20.4.arm-none-eabi-d04e724.230930.tar.gz",
+            checksum: None,
+        },
+        PinnedTool {
+            host: "arm64-apple-darwin",
+            url:  "https://github.com/earlephilhower/pico-quick-toolchain/releases/download/2.2.0/aarch64-apple-darwin20.4.arm-none-eabi-d04e724.230930.tar.gz",
+            checksum: None,
+        },
+        PinnedTool {
+            host: "i686-mingw32",
+            url:  "https://github.com/earlephilhower/pico-quick-toolchain/releases/download/2.2.0/x86_64-w64-mingw32.arm-none-eabi-d04e724.230930.zip",
+            checksum: None,
+        },
+    ],
+};
+
+/// Pre-tuned RP2040 flags for the Cortex-M0+: Thumb code throughout, and
+/// newlib-nano so printf and friends don't pull in the full C library.
+#[allow(dead_code)]
+pub fn optimized_flags() -> CoreFlags {
+    CoreFlags {
+        common: vec![
+            "-Os",
+            "-w",
+            "-ffunction-sections",
+            "-fdata-sections",
+            "-mcpu=cortex-m0plus",
+            "-mthumb",
+            "-MMD",
+            "-DARDUINO_ARCH_RP2040",
+            "-DARDUINO=10819",
+        ],
+        c_extra: vec![
+            "-std=gnu17",
+        ],
+        cxx_extra: vec![
+            "-std=gnu++17",
+            "-fno-exceptions",
+            "-fno-rtti",
+        ],
+        link: vec![
+            "-Os",
+            "-mcpu=cortex-m0plus",
+            "-mthumb",
+            "--specs=nano.specs",
+            "-Wl,--gc-sections",
+        ],
+    }
+}
+
+/// Ensure the RP2040 SDK is installed in `~/.tsuki/modules` and return its
+/// paths for the `rpipico` variant (Raspberry Pi Pico).
+pub fn ensure(verbose: bool) -> Result<SdkPaths> {
+    ensure_variant("rpipico", verbose)
+}
+
+/// Same as `ensure` for a specific variant directory (`rpipicow`, …).
+pub fn ensure_variant(variant: &str, verbose: bool) -> Result<SdkPaths> {
+    CORE.ensure(variant, verbose)
+}
+
+/// `SdkPaths` of the installed RP2040 SDK — no download.
+pub fn sdk_paths(variant: &str) -> Result<SdkPaths> {
+    CORE.sdk_paths(variant)
+}
+
+/// Whether the pinned core and compiler are both on disk.
+pub fn is_ready() -> bool {
+    CORE.is_ready()
+}
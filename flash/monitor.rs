@@ -0,0 +1,166 @@
+// ─────────────────────────────────────────────────────────────────────────────
+//  tsuki-flash :: monitor  —  serial monitor and plotter
+//
+//  Prints what the board writes to its serial port. With --plot, lines of
+//  numbers (`12, 34.5 -7` or `temp:21.5 hum:40`, as the Arduino IDE serial
+//  plotter reads them) are drawn instead as one sparkline per channel with
+//  its current, min and max value, redrawn in place for every line, so a
+//  headless machine gets the plotter over ssh.
+// ─────────────────────────────────────────────────────────────────────────────
+
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Read, Write};
+
+use colored::Colorize;
+
+use crate::error::Result;
+use crate::serial::Serial;
+
+pub struct MonitorRequest {
+    /// Serial port, or `-` to read stdin.
+    pub port:  String,
+    pub baud:  u32,
+    pub plot:  bool,
+    /// Values shown per sparkline.
+    pub width: usize,
+}
+
+pub fn monitor(req: &MonitorRequest) -> Result<()> {
+    let mut input = BufReader::new(open(&req.port, req.baud)?);
+    let mut out   = io::stdout().lock();
+    let mut plot  = Plotter::new(req.width);
+    let mut line  = Vec::new();
+
+    loop {
+        line.clear();
+        if input.read_until(b'\n', &mut line)? == 0 {
+            return Ok(());
+        }
+        if !req.plot {
+            out.write_all(&line)?;
+            out.flush()?;
+            continue;
+        }
+        let text = String::from_utf8_lossy(&line);
+        let text = text.trim_end_matches(['\r', '\n']);
+        match parse_line(text) {
+            Some(values) => plot.push(&values),
+            None if text.trim().is_empty() => continue,
+            None => plot.message = text.to_owned(),
+        }
+        plot.draw(&mut out)?;
+    }
+}
+
+/// The port as a byte stream, set to `baud`, 8N1, raw.
+fn open(port: &str, baud: u32) -> Result<Box<dyn Read>> {
+    if port == "-" {
+        return Ok(Box::new(io::stdin()));
+    }
+    Ok(Box::new(Waiting(Serial::open(port, baud)?)))
+}
+
+/// A port that waits as long as it takes: a quiet board is not the end of
+/// its output.
+struct Waiting(Serial);
+
+impl Read for Waiting {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            match self.0.read(buf) {
+                Err(e) if e.kind() == io::ErrorKind::TimedOut => continue,
+                other => return other,
+            }
+        }
+    }
+}
+
+/// The values of a plotter line: numbers separated by commas, spaces or
+/// tabs, each optionally labelled `name:value`. None for any other line.
+fn parse_line(line: &str) -> Option<Vec<(Option<&str>, f64)>> {
+    let values: Option<Vec<_>> = line
+        .split([',', ' ', '\t'])
+        .filter(|t| !t.is_empty())
+        .map(|t| match t.split_once(':') {
+            Some((name, v)) => v.parse().ok().map(|v| (Some(name), v)),
+            None            => t.parse().ok().map(|v| (None, v)),
+        })
+        .collect();
+    values.filter(|v| !v.is_empty())
+}
+
+struct Channel {
+    name:   String,
+    recent: VecDeque<f64>,
+    min:    f64,
+    max:    f64,
+}
+
+struct Plotter {
+    channels: Vec<Channel>,
+    width:    usize,
+    /// The last line that wasn't numbers, shown under the plot.
+    message:  String,
+    /// Lines drawn last time, to redraw over.
+    drawn:    usize,
+}
+
+impl Plotter {
+    fn new(width: usize) -> Self {
+        Self { channels: Vec::new(), width: width.max(1), message: String::new(), drawn: 0 }
+    }
+
+    /// Labelled values go to the channel of that name, the others to the
+    /// channel at their position.
+    fn push(&mut self, values: &[(Option<&str>, f64)]) {
+        for (i, &(name, v)) in values.iter().enumerate() {
+            let name = name.map_or_else(|| format!("ch{}", i + 1), str::to_owned);
+            let idx = match self.channels.iter().position(|c| c.name == name) {
+                Some(idx) => idx,
+                None => {
+                    self.channels.push(Channel { name, recent: VecDeque::new(), min: v, max: v });
+                    self.channels.len() - 1
+                }
+            };
+            let ch = &mut self.channels[idx];
+            if ch.recent.len() == self.width { ch.recent.pop_front(); }
+            ch.recent.push_back(v);
+            ch.min = ch.min.min(v);
+            ch.max = ch.max.max(v);
+        }
+    }
+
+    fn draw(&mut self, out: &mut impl Write) -> io::Result<()> {
+        if self.drawn > 0 {
+            write!(out, "\x1b[{}A", self.drawn)?;
+        }
+        let name_width = self.channels.iter().map(|c| c.name.len()).max().unwrap_or(0);
+        for ch in &self.channels {
+            let last = ch.recent.back().copied().unwrap_or_default();
+            let name  = format!("{:>w$}", ch.name, w = name_width);
+            let spark = format!("{:<w$}", sparkline(&ch.recent), w = self.width);
+            writeln!(out, "\x1b[2K{} {} {:>10} {} {} {} {}",
+                name.bold(), spark.cyan(), fmt_value(last),
+                "min".dimmed(), fmt_value(ch.min), "max".dimmed(), fmt_value(ch.max))?;
+        }
+        writeln!(out, "\x1b[2K{}", self.message.dimmed())?;
+        self.drawn = self.channels.len() + 1;
+        out.flush()
+    }
+}
+
+const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// One bar per value, scaled between the smallest and largest shown.
+fn sparkline(values: &VecDeque<f64>) -> String {
+    let lo = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let hi = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    values.iter().map(|&v| {
+        let level = if hi > lo { (v - lo) / (hi - lo) * (BARS.len() - 1) as f64 } else { 0.0 };
+        BARS[level.round() as usize]
+    }).collect()
+}
+
+fn fmt_value(v: f64) -> String {
+    if v.fract() == 0.0 && v.abs() < 1e15 { format!("{}", v as i64) } else { format!("{:.3}", v) }
+}
@@ -0,0 +1,32 @@
+// ─────────────────────────────────────────────────────────────────────────────
+//  tsuki-flash :: output  —  `--format text|json`
+//
+//  Informational commands print tables for people by default. With
+//  `--format json` they print one JSON document on stdout instead, for GUI
+//  frontends and scripts; progress and errors stay on stderr either way.
+// ─────────────────────────────────────────────────────────────────────────────
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, clap::ValueEnum)]
+pub enum Format {
+    /// Tables and colours.
+    #[default]
+    Text,
+    /// One JSON document on stdout.
+    Json,
+}
+
+impl Format {
+    pub fn is_json(self) -> bool {
+        self == Format::Json
+    }
+}
+
+/// Print `value` as pretty JSON on stdout.
+pub fn print_json(value: &impl Serialize) {
+    match serde_json::to_string_pretty(value) {
+        Ok(s)  => println!("{}", s),
+        Err(e) => eprintln!("cannot serialize output: {}", e),
+    }
+}
@@ -0,0 +1,116 @@
+// ─────────────────────────────────────────────────────────────────────────────
+//  tsuki-flash :: permissions  —  `setup-permissions` (Linux udev rules)
+//
+//  A fresh Linux install only lets root (and the dialout/uucp group) open
+//  USB-serial ports, and nobody but root reach the raw USB bootloaders of
+//  Teensy, RP2040 and STM32 boards. This writes one rules file granting the
+//  logged-in user access to the chips Arduino-style boards use, and keeps
+//  ModemManager from probing them mid-upload.
+// ─────────────────────────────────────────────────────────────────────────────
+
+use std::process::{Command, Stdio};
+
+use colored::Colorize;
+
+use crate::error::{FlashError, Result};
+use crate::process::{self, Stage};
+
+const RULES_PATH: &str = "/etc/udev/rules.d/99-tsuki.rules";
+
+/// USB vendors given access, with what they cover.
+const VENDORS: &[(&str, &str)] = &[
+    ("2341", "Arduino"),
+    ("2a03", "Arduino.org"),
+    ("1a86", "WCH CH340 / CH9102"),
+    ("10c4", "Silicon Labs CP210x"),
+    ("0403", "FTDI"),
+    ("2e8a", "Raspberry Pi RP2040"),
+    ("16c0", "PJRC Teensy"),
+    ("0483", "STMicroelectronics DFU / ST-Link"),
+];
+
+/// The rules file: one line for serial ports and one for raw USB access
+/// per vendor.
+pub fn rules() -> String {
+    let mut out = String::from("# Written by `tsuki-flash setup-permissions`: lets the logged-in user\n\
+                                # open Arduino-style boards and their bootloaders without sudo.\n");
+    for (vid, what) in VENDORS {
+        out += &format!("\n# {}\n", what);
+        out += &format!("SUBSYSTEM==\"tty\", ATTRS{{idVendor}}==\"{}\", MODE=\"0666\", TAG+=\"uaccess\", ENV{{ID_MM_DEVICE_IGNORE}}=\"1\"\n", vid);
+        out += &format!("SUBSYSTEM==\"usb\", ATTR{{idVendor}}==\"{}\", MODE=\"0666\", TAG+=\"uaccess\"\n", vid);
+    }
+    out
+}
+
+/// Whether the installed rules cover `vid` (lowercase hex).
+pub fn covers(vid: &str) -> bool {
+    ["/etc/udev/rules.d", "/lib/udev/rules.d", "/usr/lib/udev/rules.d"].iter()
+        .flat_map(|d| std::fs::read_dir(d).into_iter().flatten().flatten())
+        .any(|e| std::fs::read_to_string(e.path()).is_ok_and(|s| s.to_lowercase().contains(vid)))
+}
+
+/// Install the rules and reload udev, through sudo unless already root.
+/// With `print_only`, show the rules and the commands instead.
+pub fn setup(print_only: bool) -> Result<()> {
+    if !cfg!(target_os = "linux") {
+        println!("{} Nothing to set up: only Linux restricts serial port access.", "✓".green().bold());
+        if cfg!(windows) {
+            println!("  A board that doesn't show up as a COM port needs its USB-serial driver;");
+            println!("  run {} to see which.", "tsuki-flash doctor".bold());
+        }
+        return Ok(());
+    }
+
+    let rules = rules();
+    let sudo = !is_root();
+    let commands = [
+        vec!["tee", RULES_PATH],
+        vec!["udevadm", "control", "--reload-rules"],
+        vec!["udevadm", "trigger"],
+    ];
+
+    if print_only {
+        println!("{}", format!("# {}", RULES_PATH).dimmed());
+        print!("{}", rules);
+        println!("\n{}", format!("# Save the above as {}, then run:", RULES_PATH).dimmed());
+        for cmd in &commands[1..] {
+            println!("{}{}", if sudo { "sudo " } else { "" }, cmd.join(" "));
+        }
+        return Ok(());
+    }
+
+    if sudo {
+        println!("{} Writing {} (sudo may ask for your password)", "→".cyan(), RULES_PATH.bold());
+    }
+    for (i, args) in commands.iter().enumerate() {
+        let mut cmd = if sudo { Command::new("sudo") } else { Command::new(args[0]) };
+        cmd.args(if sudo { &args[..] } else { &args[1..] });
+        let status = if i == 0 {
+            process::interactive(cmd.stdout(Stdio::null()), Stage::Install, Some(rules.as_bytes()))?
+        } else {
+            process::interactive(&mut cmd, Stage::Install, None)?
+        };
+        if !status.success() {
+            return Err(FlashError::Other(format!(
+                "`{}` failed\n  Run `tsuki-flash setup-permissions --print` to do it by hand",
+                args.join(" "))));
+        }
+    }
+
+    println!("{} udev rules installed for {} USB vendors", "✓".green().bold(), VENDORS.len());
+    println!("  Unplug and replug the board for them to apply.");
+    Ok(())
+}
+
+/// Whether an upload tool's output says the port or device couldn't be
+/// opened for lack of permission.
+pub fn is_permission_error(output: &str) -> bool {
+    let output = output.to_lowercase();
+    ["permission denied", "errno 13", "libusb_error_access", "access denied", "insufficient permissions"]
+        .iter().any(|s| output.contains(s))
+}
+
+fn is_root() -> bool {
+    process::output_within(Command::new("id").arg("-u"), Stage::Install, process::QUERY)
+        .is_ok_and(|o| String::from_utf8_lossy(&o.stdout).trim() == "0")
+}
@@ -0,0 +1,315 @@
+// ─────────────────────────────────────────────────────────────────────────────
+//  tsuki-flash :: process  —  external tools, with a time limit and Ctrl-C
+//
+//  Every compiler, linker, programmer and installer run goes through
+//  `output`, `status` or `interactive`. A tool gets as long as its stage's
+//  limit (`--timeout`): a wedged serial port can keep avrdude or esptool
+//  waiting forever, and a stuck build should fail rather than hang CI. A
+//  tool that runs past it is killed and reported as `FlashError::Timeout`.
+//
+//  On Ctrl-C (or SIGTERM) the running tools are killed too, and files a
+//  build was in the middle of writing are deleted, so no half-written
+//  .hex is left to be uploaded later.
+// ─────────────────────────────────────────────────────────────────────────────
+
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Output, Stdio};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime};
+
+use colored::Colorize;
+use wait_timeout::ChildExt;
+
+use crate::error::{FlashError, Result};
+
+/// What a tool is run for; each has its own time limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Compiling one source file.
+    Compile,
+    /// Archiving, linking and turning the .elf into an image.
+    Link,
+    /// Talking to a board: uploads, reads, fuses.
+    Upload,
+    /// Unpacking a core and installing udev rules.
+    Install,
+}
+
+/// Bound for a question put to a tool or the system (a version, the user's
+/// groups, the USB devices), which answers at once or not at all.
+pub const QUERY: Duration = Duration::from_secs(10);
+
+impl Stage {
+    const ALL: [Stage; 4] = [Stage::Compile, Stage::Link, Stage::Upload, Stage::Install];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::Compile => "compile",
+            Stage::Link    => "link",
+            Stage::Upload  => "upload",
+            Stage::Install => "install",
+        }
+    }
+
+    /// An ESP32 image at a low baud rate takes a few minutes to write.
+    fn default_limit(self) -> Duration {
+        Duration::from_secs(match self {
+            Stage::Compile => 180,
+            Stage::Link    => 300,
+            Stage::Upload  => 300,
+            Stage::Install => 600,
+        })
+    }
+
+    pub fn hint(self) -> &'static str {
+        match self {
+            Stage::Compile | Stage::Link =>
+                "if this machine is just slow, raise the limit with --timeout compile=SECS / link=SECS",
+            Stage::Upload =>
+                "the board stopped answering: press reset or replug it, make sure nothing else \
+                 has the port open, and retry (--timeout upload=SECS raises the limit)",
+            Stage::Install =>
+                "a large toolchain on a slow disk can take a while: raise the limit with --timeout install=SECS",
+        }
+    }
+}
+
+/// One `--timeout` value: `SECS` for every stage or `STAGE=SECS` for one,
+/// with 0 for no limit.
+#[derive(Debug, Clone, Copy)]
+pub struct Timeout {
+    stage: Option<Stage>,
+    secs:  u64,
+}
+
+impl FromStr for Timeout {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, String> {
+        let (stage, secs) = match s.split_once('=') {
+            Some((name, secs)) => {
+                let stage = Stage::ALL.into_iter().find(|st| st.name() == name.trim())
+                    .ok_or_else(|| format!("unknown stage '{}' (expected compile, link, upload or install)", name))?;
+                (Some(stage), secs)
+            }
+            None => (None, s),
+        };
+        let secs = secs.trim().parse().map_err(|_| format!("'{}' is not a number of seconds", secs))?;
+        Ok(Timeout { stage, secs })
+    }
+}
+
+static LIMITS: OnceLock<[Option<Duration>; 4]> = OnceLock::new();
+
+/// Apply the `--timeout` values, later ones winning.
+pub fn set_timeouts(timeouts: &[Timeout]) {
+    let mut limits = Stage::ALL.map(|st| Some(st.default_limit()));
+    for t in timeouts {
+        let limit = (t.secs > 0).then(|| Duration::from_secs(t.secs));
+        for (i, st) in Stage::ALL.into_iter().enumerate() {
+            if t.stage.is_none_or(|s| s == st) {
+                limits[i] = limit;
+            }
+        }
+    }
+    let _ = LIMITS.set(limits);
+}
+
+fn limit(stage: Stage) -> Option<Duration> {
+    match LIMITS.get() {
+        Some(limits) => limits[stage as usize],
+        None         => Some(stage.default_limit()),
+    }
+}
+
+/// Like `Command::output`, within `stage`'s time limit.
+pub fn output(cmd: &mut Command, stage: Stage) -> Result<Output> {
+    run(cmd, stage, limit(stage))
+}
+
+/// `output`, but giving the tool `most` at most, for a wait that has a
+/// shorter natural bound than its stage.
+pub fn output_within(cmd: &mut Command, stage: Stage, most: Duration) -> Result<Output> {
+    run(cmd, stage, Some(limit(stage).map_or(most, |l| l.min(most))))
+}
+
+fn run(cmd: &mut Command, stage: Stage, limit: Option<Duration>) -> Result<Output> {
+    let mut child = spawn(cmd.stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped()))?;
+    let stdout = drain(child.stdout.take());
+    let stderr = drain(child.stderr.take());
+    let status = wait(&mut child, limit)?;
+    let output = Output { status: status.unwrap_or_default(), stdout: join(stdout), stderr: join(stderr) };
+    match status {
+        Some(_) => Ok(output),
+        None => {
+            let text = format!("{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+            Err(timeout(cmd, stage, limit, text))
+        }
+    }
+}
+
+/// Like `Command::status`, within `stage`'s time limit. The tool's output
+/// goes wherever `cmd` sends it.
+pub fn status(cmd: &mut Command, stage: Stage) -> Result<ExitStatus> {
+    let mut child = spawn(cmd)?;
+    wait(&mut child, limit(stage))?.ok_or_else(|| timeout(cmd, stage, limit(stage), String::new()))
+}
+
+/// `status` for a tool that may ask on the terminal, as sudo does for a
+/// password. It stays in tsuki-flash's process group, since the terminal
+/// stops a background group that reads from it. `input`, if any, is written
+/// to the tool's stdin.
+pub fn interactive(cmd: &mut Command, stage: Stage, input: Option<&[u8]>) -> Result<ExitStatus> {
+    if input.is_some() {
+        cmd.stdin(Stdio::piped());
+    }
+    let mut child = cmd.spawn()?;
+    if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+        stdin.write_all(input)?;
+    }
+    wait(&mut child, limit(stage))?.ok_or_else(|| timeout(cmd, stage, limit(stage), String::new()))
+}
+
+/// Start `cmd` in a process group of its own, so that `stop` reaches what
+/// it starts in turn: a compiler driver's cc1plus and as, esptool.py's
+/// interpreter. Ctrl-C in the terminal then only reaches tsuki-flash, which
+/// stops the group itself.
+fn spawn(cmd: &mut Command) -> std::io::Result<Child> {
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(cmd, 0);
+    cmd.spawn()
+}
+
+fn timeout(cmd: &Command, stage: Stage, limit: Option<Duration>, output: String) -> FlashError {
+    let tool = Path::new(cmd.get_program()).file_name()
+        .map_or_else(|| cmd.get_program().to_string_lossy(), |f| f.to_string_lossy())
+        .into_owned();
+    FlashError::Timeout { tool, stage, secs: limit.map_or(0, |l| l.as_secs()), output }
+}
+
+/// Tools being waited on, which Ctrl-C waits to see killed.
+static RUNNING: AtomicUsize = AtomicUsize::new(0);
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// How often a wait looks for Ctrl-C.
+const POLL: Duration = Duration::from_millis(100);
+
+/// Wait for `child` to exit, or kill it: None if it ran out of time.
+fn wait(child: &mut Child, limit: Option<Duration>) -> Result<Option<ExitStatus>> {
+    struct Running;
+    impl Drop for Running {
+        fn drop(&mut self) { RUNNING.fetch_sub(1, Ordering::SeqCst); }
+    }
+    RUNNING.fetch_add(1, Ordering::SeqCst);
+    let running = Running;
+
+    let deadline = limit.map(|l| Instant::now() + l);
+    loop {
+        if INTERRUPTED.load(Ordering::SeqCst) {
+            stop(child);
+            drop(running);
+            // The handler exits once every tool is stopped; returning
+            // would only get an error printed first.
+            loop { std::thread::park(); }
+        }
+        let slice = match deadline {
+            Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                Some(left) if !left.is_zero() => left.min(POLL),
+                _ => { stop(child); return Ok(None); }
+            },
+            None => POLL,
+        };
+        if let Some(status) = child.wait_timeout(slice)? {
+            return Ok(Some(status));
+        }
+    }
+}
+
+fn stop(child: &mut Child) {
+    #[cfg(unix)]
+    // SAFETY: kill(2) takes no pointers; a negative pid names the group `spawn`
+    // made (for an `interactive` tool there is none, and this does nothing).
+    unsafe { libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL); }
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+fn drain(pipe: Option<impl Read + Send + 'static>) -> Option<JoinHandle<Vec<u8>>> {
+    pipe.map(|mut pipe| std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        let _ = pipe.read_to_end(&mut buf);
+        buf
+    }))
+}
+
+fn join(reader: Option<JoinHandle<Vec<u8>>>) -> Vec<u8> {
+    reader.and_then(|r| r.join().ok()).unwrap_or_default()
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+//  Half-written files
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Files being written: path prefix, and when the writing started.
+static WRITING: Mutex<Vec<(PathBuf, SystemTime)>> = Mutex::new(Vec::new());
+
+/// Run `f`, which writes files whose paths start with `prefix`. If a tool
+/// in it times out, or Ctrl-C stops it, the files it touched are deleted.
+pub fn writing<T>(prefix: &Path, f: impl FnOnce() -> Result<T>) -> Result<T> {
+    let since = SystemTime::now();
+    WRITING.lock().unwrap().push((prefix.to_owned(), since));
+    let res = f();
+    let mut writing = WRITING.lock().unwrap();
+    if let Some(i) = writing.iter().position(|(p, t)| p == prefix && *t == since) {
+        writing.remove(i);
+    }
+    drop(writing);
+
+    if matches!(res, Err(FlashError::Timeout { .. })) {
+        discard(prefix, since);
+    }
+    res
+}
+
+/// Delete the files starting with `prefix` that were modified since `since`.
+fn discard(prefix: &Path, since: SystemTime) -> Vec<PathBuf> {
+    let (Some(dir), Some(stem)) = (prefix.parent(), prefix.file_name()) else { return Vec::new() };
+    let stem = stem.to_string_lossy();
+    let Ok(entries) = std::fs::read_dir(dir) else { return Vec::new() };
+
+    let mut removed = Vec::new();
+    for entry in entries.flatten() {
+        let touched = entry.metadata().and_then(|m| m.modified()).is_ok_and(|t| t >= since);
+        if touched && entry.file_name().to_string_lossy().starts_with(&*stem)
+            && std::fs::remove_file(entry.path()).is_ok()
+        {
+            removed.push(entry.path());
+        }
+    }
+    removed
+}
+
+/// Handle Ctrl-C and SIGTERM: kill the running tools, delete what was
+/// being written, and exit with 130 as a shell does for SIGINT.
+pub fn handle_interrupts() {
+    let _ = ctrlc::set_handler(|| {
+        INTERRUPTED.store(true, Ordering::SeqCst);
+        // The waits notice within POLL.
+        let give_up = Instant::now() + Duration::from_secs(2);
+        while RUNNING.load(Ordering::SeqCst) > 0 && Instant::now() < give_up {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        let writing = WRITING.lock().map(|w| w.clone()).unwrap_or_default();
+        for (prefix, since) in writing {
+            for path in discard(&prefix, since) {
+                eprintln!("{} {}", "Removed unfinished".dimmed(), path.display());
+            }
+        }
+        std::process::exit(130);
+    });
+}
@@ -29,6 +29,36 @@ pub struct SdkPaths {
     pub sdk_version: String,
 }
 
+impl SdkPaths {
+    /// The installed core's root (`…/hardware/<arch>/<version>`), two levels
+    /// above `core_dir`.
+    pub fn platform_dir(&self) -> PathBuf {
+        self.core_dir.ancestors().nth(2).map(Path::to_path_buf).unwrap_or_default()
+    }
+}
+
+/// The directory under `cores/` holding an architecture's core: Arduino-Pico
+/// names it after the chip instead of `arduino`, and Teensyduino has one per
+/// chip family, which the board gives as its variant.
+fn core_name<'a>(arch: &str, variant: &'a str) -> &'a str {
+    match arch {
+        "rp2040"       => "rp2040",
+        "megatinycore" => "megatinycore",
+        "teensy"       => variant,
+        _              => "arduino",
+    }
+}
+
+/// Teensyduino keeps pins_arduino.h in the core; there is no variants/.
+fn variant_dir(sdk_dir: &Path, core_dir: &Path, arch: &str, variant: &str) -> PathBuf {
+    if arch == "teensy" {
+        return core_dir.to_path_buf();
+    }
+    // Some boards use a different variant name; fall back to "standard"
+    let dir = sdk_dir.join("variants").join(variant);
+    if dir.is_dir() { dir } else { sdk_dir.join("variants").join("standard") }
+}
+
 /// Resolve SDK paths for a given board architecture + variant.
 pub fn resolve(arch: &str, variant: &str) -> Result<SdkPaths> {
     // ── 1. TSUKI_SDK_ROOT override ─────────────────────────────────────────
@@ -41,13 +71,20 @@ pub fn resolve(arch: &str, variant: &str) -> Result<SdkPaths> {
 
     // ── 2. tsuki-modules  (~/.tsuki/modules/)  ─────────────────────────────
     // Preferred when the user chose "tsuki-flash+cores".
-    // For AVR we use the dedicated avr module which returns SdkPaths directly
-    // without scanning — the fast path is a single Path::is_dir() check.
-    if arch == "avr" {
-        if let Ok(paths) = crate::modules::avr::sdk_paths(variant) {
-            return Ok(paths);
-        }
-    } else if let Some(home) = dirs_home() {
+    // Architectures with a pinned module (avr, esp32, esp8266, rp2040) get
+    // SdkPaths straight from it without scanning — the fast path is a single
+    // Path::is_dir() check; other versions installed there are still scanned.
+    let pinned = match arch {
+        "avr"     => Some(crate::modules::avr::sdk_paths(variant)),
+        "esp32"   => Some(crate::modules::esp32::sdk_paths(variant)),
+        "esp8266" => Some(crate::modules::esp8266::sdk_paths(variant)),
+        "rp2040"  => Some(crate::modules::rp2040::sdk_paths(variant)),
+        _         => None,
+    };
+    if let Some(Ok(paths)) = pinned {
+        return Ok(paths);
+    }
+    if let Some(home) = dirs_home() {
         let tsuki_modules = home.join(".tsuki").join("modules");
         if let Some(paths) = scan_arduino15(&tsuki_modules, arch, variant) {
             return Ok(paths);
@@ -87,14 +124,7 @@ pub fn resolve(arch: &str, variant: &str) -> Result<SdkPaths> {
         arch:  arch.to_owned(),
         path:  arduino15_dirs.first().map(|p| p.display().to_string())
                .unwrap_or_else(|| "~/.arduino15".into()),
-        pkg: match arch {
-            "avr"    => "arduino:avr",
-            "sam"    => "arduino:sam",
-            "esp32"  => "esp32:esp32",
-            "esp8266"=> "esp8266:esp8266",
-            "rp2040" => "rp2040:rp2040",
-            _        => arch,
-        }.into(),
+        pkg:   crate::doctor::arduino_cli_package(arch).into(),
     })
 }
 
@@ -128,10 +158,9 @@ fn arduino15_candidates() -> Vec<PathBuf> {
 
 fn dirs_home() -> Option<PathBuf> {
     std::env::var("HOME").ok().map(PathBuf::from)
-        .or_else(|| dirs_home_windows())
+        .or_else(dirs_home_windows)
 }
 
-#[allow(dead_code)]
 fn dirs_home_windows() -> Option<PathBuf> {
     std::env::var("USERPROFILE").ok().map(PathBuf::from)
 }
@@ -148,6 +177,10 @@ fn scan_arduino15(base: &Path, arch: &str, variant: &str) -> Option<SdkPaths> {
         "esp32"  => ("esp32", "esp32"),
         "esp8266"=> ("esp8266", "esp8266"),
         "rp2040" => ("rp2040", "rp2040"),
+        "stm32"  => ("STMicroelectronics", "stm32"),
+        "megaavr"      => ("arduino", "megaavr"),
+        "megatinycore" => ("megaTinyCore", "megaavr"),
+        "teensy" => ("teensy", "avr"),
         _        => return None,
     };
 
@@ -158,16 +191,9 @@ fn scan_arduino15(base: &Path, arch: &str, variant: &str) -> Option<SdkPaths> {
     let version = latest_version_dir(&hw_base)?;
     let sdk_dir = hw_base.join(&version);
 
-    let core_dir    = sdk_dir.join("cores").join("arduino");
-    let variant_dir = sdk_dir.join("variants").join(variant);
-
+    let core_dir    = sdk_dir.join("cores").join(core_name(arch, variant));
     if !core_dir.is_dir() { return None; }
-    // Some boards use a different variant name; fall back to "standard"
-    let variant_dir = if variant_dir.is_dir() {
-        variant_dir
-    } else {
-        sdk_dir.join("variants").join("standard")
-    };
+    let variant_dir = variant_dir(&sdk_dir, &core_dir, arch, variant);
 
     // Toolchain binary dir
     let toolchain_bin = find_toolchain_bin(base, arch, vendor)?;
@@ -188,24 +214,32 @@ fn scan_arduino15(base: &Path, arch: &str, variant: &str) -> Option<SdkPaths> {
 
 /// Find the toolchain binary directory inside the arduino15 package cache.
 fn find_toolchain_bin(base: &Path, arch: &str, _vendor: &str) -> Option<PathBuf> {
-    let (tc_vendor, tc_name) = match arch {
-        "avr"        => ("arduino", "avr-gcc"),
-        "sam"        => ("arduino", "arm-none-eabi-gcc"),
-        "rp2040"     => ("rp2040", "pqt-gcc-arm-none-eabi"),
-        "esp32"      => ("esp32", "xtensa-esp32-elf-gcc"),
-        "esp8266"    => ("esp8266", "xtensa-lx106-elf-gcc"),
+    let (tc_vendor, tc_names): (&str, &[&str]) = match arch {
+        "avr"        => ("arduino", &["avr-gcc"]),
+        "sam"        => ("arduino", &["arm-none-eabi-gcc"]),
+        // Arduino-Pico renamed its toolchain package in 2.x.
+        "rp2040"     => ("rp2040", &["pqt-gcc", "pqt-gcc-arm-none-eabi"]),
+        "esp32"      => ("esp32", &["xtensa-esp32-elf-gcc"]),
+        "esp8266"    => ("esp8266", &["xtensa-lx106-elf-gcc"]),
+        "stm32"      => ("STMicroelectronics", &["xpack-arm-none-eabi-gcc"]),
+        "megaavr"    => ("arduino", &["avr-gcc"]),
+        // megaTinyCore uses the avr-gcc build published by DxCore.
+        "megatinycore" => ("DxCore", &["avr-gcc"]),
+        "teensy"     => ("teensy", &["teensy-compile"]),
         _            => return None,
     };
 
-    let tc_base = base.join("packages").join(tc_vendor).join("tools").join(tc_name);
+    let tools = base.join("packages").join(tc_vendor).join("tools");
+    let tc_base = tc_names.iter().map(|n| tools.join(n)).find(|d| d.is_dir())
+        .unwrap_or_else(|| tools.join(tc_names[0]));
     if !tc_base.is_dir() {
         // Fall back to system PATH — caller will handle this
         return Some(PathBuf::from(""));
     }
 
     let version = latest_version_dir(&tc_base)?;
-    let bin = tc_base.join(&version).join("bin");
-    if bin.is_dir() { Some(bin) } else { None }
+    // teensy-compile nests the compiler one level down, in arm/.
+    ["bin", "arm/bin"].iter().map(|d| tc_base.join(&version).join(d)).find(|d| d.is_dir())
 }
 
 /// Arduino IDE 1.x system install (e.g. /usr/share/arduino).
@@ -234,11 +268,9 @@ fn try_arduino1_install(base: &Path, arch: &str, variant: &str) -> Option<SdkPat
 
 /// Try an explicit SDK root (TSUKI_SDK_ROOT).
 fn try_sdk_root(base: &Path, arch: &str, variant: &str) -> Option<SdkPaths> {
-    let core_dir    = base.join("cores").join("arduino");
-    let variant_dir = base.join("variants").join(variant);
+    let core_dir    = base.join("cores").join(core_name(arch, variant));
     if !core_dir.is_dir() { return None; }
-    let variant_dir = if variant_dir.is_dir() { variant_dir }
-                      else { base.join("variants").join("standard") };
+    let variant_dir = variant_dir(base, &core_dir, arch, variant);
     let toolchain_bin = base.join("bin");
     let toolchain_bin = if toolchain_bin.is_dir() { toolchain_bin }
                         else { PathBuf::from("") };
@@ -251,7 +283,7 @@ fn try_sdk_root(base: &Path, arch: &str, variant: &str) -> Option<SdkPaths> {
 }
 
 /// Return the string name of the latest (semver-ish) directory inside `base`.
-fn latest_version_dir(base: &Path) -> Option<String> {
+pub(crate) fn latest_version_dir(base: &Path) -> Option<String> {
     let mut versions: Vec<String> = std::fs::read_dir(base)
         .ok()?
         .flatten()
@@ -0,0 +1,98 @@
+// ─────────────────────────────────────────────────────────────────────────────
+//  tsuki-flash :: serial  —  serial port access
+//
+//  One way to open a port for everything that talks to a board: the
+//  monitor, the 1200-baud reset and the built-in programmers. The
+//  serialport crate does the OS work (termios on Unix, the Win32 comm API
+//  on Windows), so nothing here shells out to stty or mode.
+// ─────────────────────────────────────────────────────────────────────────────
+
+use std::io::{self, Read, Write};
+use std::time::Duration;
+
+use serialport::{ClearBuffer, DataBits, FlowControl, Parity, SerialPort, StopBits};
+
+use crate::error::{FlashError, Result};
+
+/// How long a read waits for the first byte unless told otherwise.
+const DEFAULT_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// An open port: raw, 8N1, no flow control. A read that gets nothing within
+/// the timeout fails with `io::ErrorKind::TimedOut`.
+pub struct Serial {
+    port: Box<dyn SerialPort>,
+    name: String,
+}
+
+impl Serial {
+    pub fn open(port: &str, baud: u32) -> Result<Serial> {
+        let serial = serialport::new(port, baud)
+            .data_bits(DataBits::Eight)
+            .parity(Parity::None)
+            .stop_bits(StopBits::One)
+            .flow_control(FlowControl::None)
+            .timeout(DEFAULT_TIMEOUT)
+            .open()
+            .map_err(|e| match e.kind() {
+                serialport::ErrorKind::NoDevice
+                | serialport::ErrorKind::Io(io::ErrorKind::NotFound) => FlashError::PortNotFound(port.into()),
+                serialport::ErrorKind::Io(io::ErrorKind::PermissionDenied) => FlashError::PortAccessDenied(port.into()),
+                _ => FlashError::Other(format!("cannot open {}: {}", port, e)),
+            })?;
+        Ok(Serial { port: serial, name: port.to_owned() })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn set_baud(&mut self, baud: u32) -> Result<()> {
+        self.port.set_baud_rate(baud)
+            .map_err(|e| FlashError::Other(format!("cannot set {} to {} baud: {}", self.name, baud, e)))
+    }
+
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        let _ = self.port.set_timeout(timeout);
+    }
+
+    /// Control lines are best-effort: some adapters don't wire them, and a
+    /// board that needs them will fail to answer anyway.
+    pub fn set_dtr(&mut self, on: bool) {
+        let _ = self.port.write_data_terminal_ready(on);
+    }
+
+    pub fn set_rts(&mut self, on: bool) {
+        let _ = self.port.write_request_to_send(on);
+    }
+
+    /// Drop whatever has arrived but not been read.
+    pub fn clear_input(&mut self) {
+        let _ = self.port.clear(ClearBuffer::Input);
+    }
+}
+
+impl Read for Serial {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.port.read(buf)
+    }
+}
+
+impl Write for Serial {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.port.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.port.flush()
+    }
+}
+
+/// Open and close `port` at 1200 baud: boards with native USB or a USB
+/// bridge that watches for it (RP2040, Due, Leonardo, Nano Every) reset
+/// into their bootloader. DTR is dropped before closing, as the Arduino IDE
+/// does; some cores only look at that edge.
+pub fn touch_1200(port: &str) -> Result<()> {
+    let mut serial = Serial::open(port, 1200)?;
+    serial.set_dtr(false);
+    Ok(())
+}
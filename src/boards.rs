@@ -0,0 +1,311 @@
+// ─────────────────────────────────────────────────────────────────────────────
+//  tsuki :: boards
+//
+//  The board database, read from boards.toml. The transpiler's `Board`
+//  (runtime) and tsuki-flash's board table are both built from it, so a new
+//  board is one entry there.
+//
+//  Users add their own (clones, custom PCBs) as .toml or .json files in
+//  ~/.config/tsuki/boards/, in the same format; one with a built-in id
+//  replaces that board.
+// ─────────────────────────────────────────────────────────────────────────────
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use serde::Deserialize;
+
+/// One board of the database.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BoardDef {
+    pub id:        String,
+    pub name:      String,
+    pub fqbn:      String,
+    /// Chip name, e.g. "ATmega328P".
+    pub cpu:       String,
+    /// pins_arduino.h folder under the core's variants/.
+    #[serde(default)]
+    pub variant:   String,
+    pub flash_kb:  u32,
+    pub ram_kb:    u32,
+    pub clock_mhz: u32,
+    /// CPU cores user code can run on.
+    #[serde(default = "one_core")]
+    pub cores:     u32,
+    /// Bytes of EEPROM the core's EEPROM library offers, real or emulated
+    /// in flash; None when it has none.
+    #[serde(default)]
+    pub eeprom_bytes: Option<u32>,
+    /// -D flags tsuki-flash compiles with.
+    #[serde(default)]
+    pub defines:   Vec<String>,
+    /// Hardware the board has beyond the basics: wifi, bluetooth,
+    /// native_usb, fpu.
+    #[serde(default)]
+    pub features:  Vec<String>,
+    /// How tsuki-flash builds and uploads for it; None when only the
+    /// transpiler knows the board.
+    #[serde(default)]
+    pub toolchain: Option<ToolchainDef>,
+    /// What burning the bootloader over ISP writes (classic AVR).
+    #[serde(default)]
+    pub bootloader: Option<Bootloader>,
+    /// Menus, as in an FQBN's `:cpu=atmega328old`: menu → choice → what
+    /// picking it changes. With nothing picked the fields above apply.
+    #[serde(default)]
+    pub options: BTreeMap<String, BTreeMap<String, BoardOption>>,
+}
+
+/// One choice of a board menu. Unset fields keep the board's value.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct BoardOption {
+    /// -D flags on top of the board's.
+    #[serde(default)]
+    pub defines:          Vec<String>,
+    pub clock_mhz:        Option<u32>,
+    pub flash_kb:         Option<u32>,
+    /// Bootloader baud (AVR) or programmer baud (ESP, STM32 serial).
+    pub upload_baud:      Option<u32>,
+    /// SPI flash mode of ESP images: qio, qout, dio or dout.
+    pub flash_mode:       Option<String>,
+    /// ESP32 partition scheme, as tsuki-flash's `--partition-scheme`.
+    pub partition_scheme: Option<String>,
+    pub bootloader:       Option<Bootloader>,
+}
+
+/// Compiler and programmer family, as `toolchain = { kind = "…", … }`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum ToolchainDef {
+    Avr     { mcu: String, programmer: String, baud: u32 },
+    MegaAvr { mcu: String, updi: Updi },
+    Sam     { mcu: String },
+    Rp2040,
+    Stm32   { mcu: String, series: String, upload: Stm32Upload },
+    Esp32   { variant: String },
+    Esp8266,
+    /// `model` is the board as teensy_loader_cli and ARDUINO_<model> name
+    /// it; `ldscript` sits in the core folder.
+    Teensy  { mcu: String, model: String, ldscript: String },
+}
+
+/// What drives a megaAVR/tinyAVR chip's UPDI pin.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Updi {
+    /// An on-board jtag2updi bridge, switched on by a 1200-baud touch
+    /// (Nano Every).
+    Jtag2Updi,
+    /// A plain USB-serial adapter with a resistor to UPDI (avrdude ≥ 7).
+    SerialUpdi,
+}
+
+/// How an STM32 board takes its firmware.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Stm32Upload {
+    /// The ROM bootloader on USART1, through a USB-serial adapter (stm32flash).
+    Serial,
+    /// The ROM bootloader over USB DFU (dfu-util).
+    Dfu,
+    /// The mass-storage drive of an on-board ST-Link (Nucleo).
+    StLink,
+}
+
+/// A classic AVR board's bootloader and the fuses it expects.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Bootloader {
+    /// Hex file, relative to the core's bootloaders/ directory.
+    pub file:          String,
+    pub low_fuse:      u8,
+    pub high_fuse:     u8,
+    pub extended_fuse: u8,
+    /// Lock bits written before the fuses, so the boot section can be
+    /// rewritten.
+    #[serde(default = "default_unlock")]
+    pub unlock:        u8,
+    /// Lock bits written after the bootloader, protecting it.
+    pub lock:          u8,
+}
+
+fn default_unlock() -> u8 { 0x3F }
+
+fn one_core() -> u32 { 1 }
+
+#[derive(Deserialize)]
+struct BoardFile {
+    board: Vec<BoardDef>,
+}
+
+/// Every known board: the built-in ones in boards.toml order, then those
+/// from the user's board directory.
+pub fn catalog() -> &'static [BoardDef] {
+    static CATALOG: OnceLock<Vec<BoardDef>> = OnceLock::new();
+    CATALOG.get_or_init(|| {
+        let mut boards = builtin();
+        if let Some(dir) = user_dir() {
+            merge(&mut boards, load_dir(&dir));
+        }
+        boards
+    })
+}
+
+fn builtin() -> Vec<BoardDef> {
+    toml::from_str::<BoardFile>(include_str!("boards.toml"))
+        .expect("boards.toml is checked by the tests")
+        .board
+}
+
+/// Add `extra` to `boards`, replacing any board with the same id.
+fn merge(boards: &mut Vec<BoardDef>, extra: Vec<BoardDef>) {
+    for def in extra {
+        match boards.iter_mut().find(|b| b.id.eq_ignore_ascii_case(&def.id)) {
+            Some(existing) => *existing = def,
+            None => boards.push(def),
+        }
+    }
+}
+
+/// Directory of user board files:
+///   $XDG_CONFIG_HOME/tsuki/boards, or ~/.config/tsuki/boards
+pub fn user_dir() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(xdg) if !xdg.is_empty() => PathBuf::from(xdg),
+        _ => PathBuf::from(std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"))?)
+            .join(".config"),
+    };
+    Some(base.join("tsuki").join("boards"))
+}
+
+/// Read every *.toml and *.json board file in `dir`, in name order. A file
+/// that does not parse is skipped with a warning.
+pub fn load_dir(dir: &Path) -> Vec<BoardDef> {
+    let Ok(entries) = fs::read_dir(dir) else { return Vec::new() };
+    let mut paths: Vec<PathBuf> = entries.flatten()
+        .map(|e| e.path())
+        .filter(|p| matches!(p.extension().and_then(|e| e.to_str()), Some("toml" | "json")))
+        .collect();
+    paths.sort();
+
+    paths.iter()
+        .flat_map(|p| load_file(p)
+            .map_err(|e| eprintln!("tsuki: warning: skipping {}: {}", p.display(), e))
+            .unwrap_or_default())
+        .collect()
+}
+
+/// Parse one board file. It holds either a single board at the top level
+/// or a list of them (`[[board]]` in TOML; an array, or `{"board": [...]}`,
+/// in JSON).
+fn load_file(path: &Path) -> Result<Vec<BoardDef>, String> {
+    let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let boards = if path.extension().is_some_and(|e| e == "json") {
+        let value: serde_json::Value = serde_json::from_str(&text).map_err(|e| e.to_string())?;
+        match value {
+            serde_json::Value::Array(_) => serde_json::from_value(value),
+            serde_json::Value::Object(ref o) if o.contains_key("board") =>
+                serde_json::from_value::<BoardFile>(value).map(|f| f.board),
+            _ => serde_json::from_value(value).map(|b| vec![b]),
+        }.map_err(|e| e.to_string())?
+    } else {
+        let table: toml::Table = toml::from_str(&text).map_err(|e| e.to_string())?;
+        if table.contains_key("board") {
+            toml::Value::Table(table).try_into::<BoardFile>().map(|f| f.board)
+        } else {
+            toml::Value::Table(table).try_into().map(|b| vec![b])
+        }.map_err(|e| e.to_string())?
+    };
+
+    for b in &boards {
+        if b.toolchain.is_some() && b.variant.is_empty() {
+            return Err(format!("board '{}' has a toolchain but no variant", b.id));
+        }
+    }
+    Ok(boards)
+}
+
+/// Find a board by id (case-insensitive).
+pub fn find(id: &str) -> Option<&'static BoardDef> {
+    catalog().iter().find(|b| b.id.eq_ignore_ascii_case(id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_catalog() {
+        let boards = builtin();
+        let find = |id: &str| boards.iter().find(|b| b.id.eq_ignore_ascii_case(id));
+        let mut ids = HashSet::new();
+        for b in &boards {
+            assert!(ids.insert(b.id.as_str()), "duplicate board id {}", b.id);
+            assert!(b.fqbn.split(':').count() >= 3, "{}: bad fqbn {}", b.id, b.fqbn);
+            if b.toolchain.is_some() {
+                assert!(!b.variant.is_empty(), "{}: tsuki-flash needs a variant", b.id);
+            }
+        }
+
+        let uno = find("UNO").unwrap();
+        assert_eq!(uno.toolchain, Some(ToolchainDef::Avr {
+            mcu: "atmega328p".into(), programmer: "arduino".into(), baud: 115200,
+        }));
+        assert_eq!(find("nucleo_f401re").unwrap().toolchain, Some(ToolchainDef::Stm32 {
+            mcu: "cortex-m4".into(), series: "STM32F4xx".into(), upload: Stm32Upload::StLink,
+        }));
+        assert_eq!(find("teensy41").unwrap().toolchain, Some(ToolchainDef::Teensy {
+            mcu: "imxrt1062".into(), model: "TEENSY41".into(), ldscript: "imxrt1062_t41.ld".into(),
+        }));
+        assert!(find("portenta_h7").unwrap().toolchain.is_none());
+        assert_eq!((find("esp32").unwrap().cores, find("esp32c3").unwrap().cores), (2, 1));
+        assert_eq!(find("mega").unwrap().eeprom_bytes, Some(4096));
+        assert_eq!(find("due").unwrap().eeprom_bytes, None);
+
+        let bl = find("uno").unwrap().bootloader.as_ref().unwrap();
+        assert_eq!((bl.high_fuse, bl.unlock, bl.lock), (0xDE, 0x3F, 0x0F));
+        assert!(find("esp32").unwrap().bootloader.is_none());
+
+        let old = &find("nano").unwrap().options["cpu"]["atmega328old"];
+        assert_eq!(old.upload_baud, Some(57600));
+        assert_eq!(old.bootloader.as_ref().unwrap().file, "atmega/ATmegaBOOT_168_atmega328.hex");
+        assert_eq!(find("esp32").unwrap().options["FlashSize"]["8M"].flash_kb, Some(8192));
+        assert!(find("uno").unwrap().options.is_empty());
+    }
+
+    #[test]
+    fn test_user_boards() {
+        let dir = std::env::temp_dir().join(format!("tsuki-boards-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("clone.toml"), r#"
+            id = "uno"
+            name = "Uno clone (CH340)"
+            fqbn = "arduino:avr:uno"
+            cpu = "ATmega328P"
+            variant = "standard"
+            flash_kb = 32
+            ram_kb = 2
+            clock_mhz = 16
+            toolchain = { kind = "avr", mcu = "atmega328p", programmer = "arduino", baud = 57600 }
+        "#).unwrap();
+        fs::write(dir.join("more.json"), r#"[
+            { "id": "lgt8f328p", "name": "LGT8F328P", "fqbn": "lgt8fx:avr:328",
+              "cpu": "LGT8F328P", "flash_kb": 32, "ram_kb": 2, "clock_mhz": 32 }
+        ]"#).unwrap();
+        fs::write(dir.join("broken.toml"), "id = \"x\"").unwrap();
+        fs::write(dir.join("notes.txt"), "ignored").unwrap();
+
+        let mut boards = builtin();
+        let count = boards.len();
+        merge(&mut boards, load_dir(&dir));
+        fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(boards.len(), count + 1);
+        let uno = boards.iter().find(|b| b.id == "uno").unwrap();
+        assert_eq!(uno.name, "Uno clone (CH340)");
+        assert!(matches!(uno.toolchain, Some(ToolchainDef::Avr { baud: 57600, .. })));
+        assert!(boards.iter().any(|b| b.id == "lgt8f328p" && b.toolchain.is_none()));
+    }
+}
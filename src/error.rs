@@ -3,6 +3,7 @@
 //  Unified error types and source-location tracking.
 // ─────────────────────────────────────────────────────────────────────────────
 
+use serde_json::{json, Value};
 use thiserror::Error;
 
 // ── Source span ───────────────────────────────────────────────────────────────
@@ -36,19 +37,44 @@ impl std::fmt::Display for Span {
     }
 }
 
+// ── Suggestions ───────────────────────────────────────────────────────────────
+
+/// A fix-it attached to a diagnostic: a hint, plus, when the fix is a local
+/// edit, the text that replaces `len` characters at the diagnostic's span.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Suggestion {
+    pub message:     String,
+    pub replacement: Option<String>,
+    pub len:         usize,
+}
+
+impl Suggestion {
+    /// A hint with no edit, e.g. "add `import \"time\"`".
+    pub fn note(message: impl Into<String>) -> Self {
+        Self { message: message.into(), replacement: None, len: 0 }
+    }
+
+    /// Replace the `len` characters at the span with `replacement` (insert
+    /// when `len` is 0).
+    pub fn replace(message: impl Into<String>, len: usize, replacement: impl Into<String>) -> Self {
+        Self { message: message.into(), replacement: Some(replacement.into()), len }
+    }
+}
+
 // ── Error type ────────────────────────────────────────────────────────────────
 
+#[allow(non_camel_case_types)]
 #[derive(Debug, Error)]
 pub enum tsukiError {
     // ── pipeline errors ──────────────────────────────────────────────────────
     #[error("[lex]   {span}  {msg}")]
-    Lex { msg: String, span: Span },
+    Lex { msg: String, span: Span, fix: Option<Box<Suggestion>> },
 
     #[error("[parse] {span}  {msg}")]
-    Parse { msg: String, span: Span },
+    Parse { msg: String, span: Span, fix: Option<Box<Suggestion>> },
 
     #[error("[type]  {span}  {msg}")]
-    Type { msg: String, span: Span },
+    Type { msg: String, span: Span, fix: Option<Box<Suggestion>> },
 
     #[error("[codegen] {0}")]
     Codegen(String),
@@ -68,9 +94,9 @@ pub enum tsukiError {
 
 impl tsukiError {
     // Convenience constructors
-    pub fn lex(span: Span, msg: impl Into<String>)   -> Self { Self::Lex   { msg: msg.into(), span } }
-    pub fn parse(span: Span, msg: impl Into<String>) -> Self { Self::Parse { msg: msg.into(), span } }
-    pub fn type_(span: Span, msg: impl Into<String>) -> Self { Self::Type  { msg: msg.into(), span } }
+    pub fn lex(span: Span, msg: impl Into<String>)   -> Self { Self::Lex   { msg: msg.into(), span, fix: None } }
+    pub fn parse(span: Span, msg: impl Into<String>) -> Self { Self::Parse { msg: msg.into(), span, fix: None } }
+    pub fn type_(span: Span, msg: impl Into<String>) -> Self { Self::Type  { msg: msg.into(), span, fix: None } }
     pub fn codegen(msg: impl Into<String>)           -> Self { Self::Codegen(msg.into()) }
     pub fn other(msg: impl Into<String>)             -> Self { Self::Other(msg.into()) }
 
@@ -84,26 +110,125 @@ impl tsukiError {
         }
     }
 
+    /// Attach a fix-it. Only located errors (lex, parse, type) carry one;
+    /// others are returned unchanged.
+    pub fn with_suggestion(mut self, suggestion: Suggestion) -> Self {
+        if let Self::Lex { fix, .. } | Self::Parse { fix, .. } | Self::Type { fix, .. } = &mut self {
+            *fix = Some(Box::new(suggestion));
+        }
+        self
+    }
+
+    pub fn suggestion(&self) -> Option<&Suggestion> {
+        match self {
+            Self::Lex { fix, .. } | Self::Parse { fix, .. } | Self::Type { fix, .. } => fix.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// The message alone, without the `[kind] file:line:col` prefix.
+    pub fn message(&self) -> String {
+        match self {
+            Self::Lex { msg, .. } | Self::Parse { msg, .. } | Self::Type { msg, .. } => msg.clone(),
+            Self::Codegen(msg) | Self::Other(msg) => msg.clone(),
+            _ => self.to_string(),
+        }
+    }
+
     /// Render a pretty, human-readable diagnostic message.
     pub fn pretty(&self, source: &str) -> String {
-        let Some(span) = self.span() else { return self.to_string() };
-
-        let line_text = source
-            .lines()
-            .nth((span.line.saturating_sub(1)) as usize)
-            .unwrap_or("");
-
-        let caret = " ".repeat(span.col.saturating_sub(1) as usize) + "^";
-
-        format!(
-            "error: {}\n  --> {}\n   |\n{:>3}| {}\n   | {}\n",
-            self,
-            span,
-            span.line,
-            line_text,
-            caret,
-        )
+        match (self.span(), self.suggestion()) {
+            (Some(span), Some(fix)) => format!("{}{}", snippet("error", &self.to_string(), span, source), help(fix, span, source)),
+            (Some(span), None)      => snippet("error", &self.to_string(), span, source),
+            (None, _)               => self.to_string(),
+        }
+    }
+
+    /// The diagnostic as a JSON object for editors and CI (see `diagnostic_json`).
+    pub fn to_json(&self) -> Value {
+        diagnostic_json("error", &self.message(), self.span(), self.suggestion())
     }
 }
 
+// ── Warnings ──────────────────────────────────────────────────────────────────
+
+/// A diagnostic that does not stop the transpile (reported by `--check`
+/// and on every build).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Warning {
+    pub msg:  String,
+    pub span: Span,
+}
+
+impl Warning {
+    pub fn new(span: Span, msg: impl Into<String>) -> Self {
+        Self { msg: msg.into(), span }
+    }
+
+    pub fn pretty(&self, source: &str) -> String {
+        snippet("warning", &self.to_string(), &self.span, source)
+    }
+
+    pub fn to_json(&self) -> Value {
+        diagnostic_json("warning", &self.msg, Some(&self.span), None)
+    }
+}
+
+impl std::fmt::Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[warn]  {}  {}", self.span, self.msg)
+    }
+}
+
+/// `{"file", "span": {"line", "col", "offset"}, "severity", "message",
+/// "suggestion": {"message", "replacement", "len"}}`. `file` and `span` are
+/// null when the diagnostic has no location, `suggestion` when there is no
+/// fix to offer.
+fn diagnostic_json(severity: &str, message: &str, span: Option<&Span>, suggestion: Option<&Suggestion>) -> Value {
+    let span = span.filter(|s| s.line > 0);
+    json!({
+        "file":       span.map(|s| s.file.as_str()).filter(|f| !f.is_empty()),
+        "span":       span.map(|s| json!({ "line": s.line, "col": s.col, "offset": s.offset })),
+        "severity":   severity,
+        "message":    message,
+        "suggestion": suggestion.map(|s| json!({
+            "message": s.message, "replacement": s.replacement, "len": s.len,
+        })),
+    })
+}
+
+/// `help: …` under a snippet; an edit is shown applied to the source line,
+/// with the new text underlined.
+fn help(fix: &Suggestion, span: &Span, source: &str) -> String {
+    let mut out = format!("help: {}\n", fix.message);
+    let Some(text) = &fix.replacement else { return out };
+    let line = source.lines().nth(span.line.saturating_sub(1) as usize).unwrap_or("");
+    let at   = span.col.saturating_sub(1) as usize;
+    let head: String = line.chars().take(at).collect();
+    let tail: String = line.chars().skip(at + fix.len).collect();
+    out += &format!("   |\n{:>3}| {}{}{}\n   | {}{}\n",
+        span.line, head, text, tail, " ".repeat(at), "~".repeat(text.chars().count().max(1)));
+    out
+}
+
+/// `level: msg` followed by the offending source line and a caret.
+fn snippet(level: &str, msg: &str, span: &Span, source: &str) -> String {
+    let line_text = source
+        .lines()
+        .nth((span.line.saturating_sub(1)) as usize)
+        .unwrap_or("");
+
+    let caret = " ".repeat(span.col.saturating_sub(1) as usize) + "^";
+
+    format!(
+        "{}: {}\n  --> {}\n   |\n{:>3}| {}\n   | {}\n",
+        level,
+        msg,
+        span,
+        span.line,
+        line_text,
+        caret,
+    )
+}
+
 pub type Result<T> = std::result::Result<T, tsukiError>;
\ No newline at end of file
@@ -0,0 +1,314 @@
+// ─────────────────────────────────────────────────────────────────────────────
+//  tsuki :: estimate
+//
+//  Rough flash/SRAM usage of a transpiled sketch, computed without a
+//  toolchain: a per-platform core baseline, a cost table for the libraries
+//  the sketch pulls in, the sizes of its globals and string literals, and a
+//  per-line guess for the generated code. Meant to catch "this will never
+//  fit on an Uno" during `--check`, not to replace the linker's numbers.
+// ─────────────────────────────────────────────────────────────────────────────
+
+use std::collections::{HashMap, HashSet};
+
+use crate::parser::ast::{Decl, Expr, Field, Program, Type};
+use crate::runtime::Board;
+
+/// One line of the breakdown.
+#[derive(Debug, Clone)]
+pub struct Usage {
+    pub what:  String,
+    pub flash: u32,
+    pub ram:   u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Estimate {
+    pub board: Board,
+    pub items: Vec<Usage>,
+}
+
+impl Estimate {
+    pub fn flash(&self) -> u32 { self.items.iter().map(|u| u.flash).sum() }
+    pub fn ram(&self)   -> u32 { self.items.iter().map(|u| u.ram).sum() }
+
+    /// Usage as a percentage of the board's flash and RAM.
+    pub fn percent(&self) -> (u32, u32) {
+        (pct(self.flash(), self.board.flash_kb), pct(self.ram(), self.board.ram_kb))
+    }
+
+    /// Human-readable budget report.
+    pub fn report(&self) -> String {
+        let (fp, rp) = self.percent();
+        let mut out = format!("estimated usage on {} (no compiler run; approximate):\n", self.board.name);
+        out += &format!("  flash  {:>9} of {:<7} ({}%)\n", bytes(self.flash()), kb(self.board.flash_kb), fp);
+        out += &format!("  ram    {:>9} of {:<7} ({}%)\n\n", bytes(self.ram()), kb(self.board.ram_kb), rp);
+        out += &format!("  {:<24} {:>9} {:>9}\n", "", "FLASH", "RAM");
+        for u in &self.items {
+            out += &format!("  {:<24} {:>9} {:>9}\n", u.what, bytes(u.flash), bytes(u.ram));
+        }
+        out
+    }
+
+    /// Error message when flash or RAM use exceeds `limit` percent.
+    pub fn over(&self, limit: u32) -> Option<String> {
+        let (fp, rp) = self.percent();
+        let (what, used, cap, p) = if fp > limit {
+            ("flash", self.flash(), self.board.flash_kb, fp)
+        } else if rp > limit {
+            ("ram", self.ram(), self.board.ram_kb, rp)
+        } else {
+            return None;
+        };
+        Some(format!("estimated {} use {} is {}% of the {} of {} (limit {}%)",
+            what, bytes(used), p, kb(cap), self.board.name, limit))
+    }
+}
+
+/// Estimate the footprint of `cpp`, generated from `prog`, on `board`.
+pub fn estimate(prog: &Program, cpp: &str, board: &Board) -> Estimate {
+    let avr = board.is_avr();
+    let mut items = vec![core(board)];
+
+    let headers = cpp.lines()
+        .filter_map(|l| l.strip_prefix("#include <")?.strip_suffix('>'))
+        .filter(|h| *h != "Arduino.h");
+    for h in headers {
+        items.push(library(h, avr));
+    }
+    for (marker, what, cost) in FEATURES {
+        if cpp.contains(marker) {
+            let (flash, ram) = if avr { (cost.0, cost.1) } else { (cost.2, cost.3) };
+            items.push(Usage { what: what.to_string(), flash, ram });
+        }
+    }
+
+    let (helpers, code) = code_lines(cpp);
+    let per_line = if avr { 10 } else { 8 };
+    if helpers > 0 {
+        items.push(Usage { what: "runtime helpers".into(), flash: helpers * per_line, ram: 0 });
+    }
+    items.push(Usage { what: "program code".into(), flash: code * per_line, ram: 0 });
+
+    let ram = globals(prog, avr);
+    items.push(Usage { what: "globals".into(), flash: 0, ram });
+
+    let strings = string_literals(cpp);
+    items.push(Usage {
+        what:  "string literals".into(),
+        flash: strings.0 + strings.1,
+        // Without F() AVR copies every literal into SRAM at startup.
+        ram:   if avr { strings.0 } else { 0 },
+    });
+
+    items.retain(|u| u.flash > 0 || u.ram > 0);
+    Estimate { board: board.clone(), items }
+}
+
+// ── Cost tables ───────────────────────────────────────────────────────────────
+
+/// Empty-sketch footprint of each core: init, timers, interrupt vectors.
+fn core(board: &Board) -> Usage {
+    let (flash, ram) = match board.arch() {
+        _ if board.cpu == "iMXRT1062" => (12_000, 4_000),
+        "avr"           => (450, 9),
+        "megaavr"       => (800, 20),
+        "sam"           => (10_500, 1_800),
+        "samd"          => (10_000, 2_000),
+        "esp32"         => (210_000, 13_000),
+        "esp8266"       => (240_000, 26_000),
+        "rp2040"        => (50_000, 9_000),
+        "stm32"         => (11_000, 1_000),
+        _               => (150_000, 40_000),
+    };
+    Usage { what: "core".into(), flash, ram }
+}
+
+/// Flash and RAM bytes on AVR, then flash and RAM on 32-bit cores.
+type Cost = (u32, u32, u32, u32);
+
+/// Cost of each included header.
+const LIBRARIES: &[(&str, Cost)] = &[
+    ("Wire.h",        (1_900, 210,  3_000, 300)),
+    ("SPI.h",         (300,   0,    1_000, 20)),
+    ("Servo.h",       (1_200, 50,   2_000, 60)),
+    ("EEPROM.h",      (100,   0,    1_500, 20)),
+    ("math.h",        (1_000, 0,    0,     0)),
+    ("WiFi.h",        (0,     0,    60_000, 2_000)),
+    ("ESP8266WiFi.h", (0,     0,    60_000, 2_000)),
+    ("HTTPClient.h",  (0,     0,    120_000, 3_000)),
+    ("ESP8266HTTPClient.h", (0, 0,  120_000, 3_000)),
+];
+
+/// Core classes pulled in by use rather than by a header.
+const FEATURES: &[(&str, &str, Cost)] = &[
+    ("Serial.",   "Serial",           (1_500, 157, 2_000, 300)),
+    ("String(",   "String class",     (1_600, 0,   1_000, 0)),
+    ("snprintf(", "printf formatting", (1_600, 0,  0,     0)),
+];
+
+fn library(header: &str, avr: bool) -> Usage {
+    let (flash, ram) = match LIBRARIES.iter().find(|(h, _)| *h == header) {
+        Some((_, c)) if avr => (c.0, c.1),
+        Some((_, c))        => (c.2, c.3),
+        None if avr         => (1_000, 30),
+        None                => (2_000, 100),
+    };
+    Usage { what: header.to_owned(), flash, ram }
+}
+
+// ── Program contents ──────────────────────────────────────────────────────────
+
+/// Lines of code in `tsuki_*` helper definitions and in everything else.
+/// A definition starts at column 0 and runs until the next one.
+fn code_lines(cpp: &str) -> (u32, u32) {
+    let (mut helpers, mut code) = (0, 0);
+    let mut in_helper = false;
+    for line in cpp.lines() {
+        let t = line.trim();
+        if t.is_empty() || t.starts_with("//") || t.starts_with('#') || t == "}" || t == "};" {
+            continue;
+        }
+        if !line.starts_with(' ') {
+            in_helper = line.contains("tsuki_");
+        }
+        if in_helper { helpers += 1 } else { code += 1 }
+    }
+    (helpers, code)
+}
+
+/// Bytes of distinct string literals, `(plain, inside F())`.
+fn string_literals(cpp: &str) -> (u32, u32) {
+    let mut seen = HashSet::new();
+    let (mut plain, mut progmem) = (0, 0);
+    for line in cpp.lines().filter(|l| !l.starts_with('#') && !l.trim_start().starts_with("//")) {
+        let mut chars = line.char_indices();
+        while let Some((start, c)) = chars.next() {
+            if c == '\'' {
+                // Skip char literals such as '"'.
+                let mut escaped = false;
+                for (_, c) in chars.by_ref() {
+                    if c == '\'' && !escaped { break }
+                    escaped = c == '\\' && !escaped;
+                }
+                continue;
+            }
+            if c != '"' { continue }
+            let mut lit = String::new();
+            let mut escaped = false;
+            for (_, c) in chars.by_ref() {
+                match (escaped, c) {
+                    (false, '\\') => { escaped = true; continue }
+                    (false, '"')  => break,
+                    _             => lit.push(c),
+                }
+                escaped = false;
+            }
+            let in_f = line[..start].ends_with("F(");
+            let size = lit.len() as u32 + 1;
+            if seen.insert((lit, in_f)) {
+                if in_f { progmem += size } else { plain += size }
+            }
+        }
+    }
+    (plain, progmem)
+}
+
+/// SRAM taken by package-level variables.
+fn globals(prog: &Program, avr: bool) -> u32 {
+    let structs: HashMap<&str, &[Field]> = prog.decls.iter().filter_map(|d| match d {
+        Decl::StructDef { name, fields, .. } => Some((name.as_str(), fields.as_slice())),
+        _ => None,
+    }).collect();
+    prog.decls.iter().map(|d| match d {
+        Decl::Var { ty: Some(t), .. } => size_of(t, avr, &structs, 0),
+        Decl::Var { ty: None, init: Some(e), .. } => match e {
+            Expr::Float(_) => size_of(&Type::Float64, avr, &structs, 0),
+            Expr::Str(_)   => size_of(&Type::String, avr, &structs, 0),
+            Expr::Bool(_)  => 1,
+            _              => size_of(&Type::Int, avr, &structs, 0),
+        },
+        _ => 0,
+    }).sum()
+}
+
+fn size_of(t: &Type, avr: bool, structs: &HashMap<&str, &[Field]>, depth: u32) -> u32 {
+    let word = if avr { 2 } else { 4 };
+    match t {
+        Type::Bool | Type::Int8 | Type::Uint8 | Type::Byte => 1,
+        Type::Int16 | Type::Uint16                         => 2,
+        Type::Int | Type::Uint                             => word,
+        Type::Int32 | Type::Uint32 | Type::Rune | Type::Float32 => 4,
+        Type::Int64 | Type::Uint64                         => 8,
+        // avr-gcc's double is a float.
+        Type::Float64                                      => if avr { 4 } else { 8 },
+        // buffer pointer, capacity, length
+        Type::String                                       => 3 * word,
+        Type::Array { len: Some(n), elem } => *n as u32 * size_of(elem, avr, structs, depth),
+        Type::Named(n) if depth < 8 => match structs.get(n.as_str()) {
+            Some(fields) => fields.iter().map(|f| size_of(&f.ty, avr, structs, depth + 1)).sum(),
+            // Library objects are declared as pointers.
+            None => word,
+        },
+        _ => word,
+    }
+}
+
+// ── Formatting ────────────────────────────────────────────────────────────────
+
+fn pct(used: u32, cap_kb: u32) -> u32 {
+    (used as u64 * 100 / (cap_kb as u64 * 1024).max(1)) as u32
+}
+
+fn kb(n: u32) -> String {
+    if n >= 1024 && n.is_multiple_of(1024) { format!("{} MB", n / 1024) } else { format!("{} KB", n) }
+}
+
+fn bytes(n: u32) -> String {
+    match n {
+        0                 => "-".into(),
+        n if n < 1024     => format!("{} B", n),
+        n if n < 1 << 20  => format!("{:.1} KB", n as f64 / 1024.0),
+        n                 => format!("{:.1} MB", n as f64 / (1 << 20) as f64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::transpiler::{TranspileConfig, Transpiler};
+
+    fn run(src: &str, board: &str) -> Estimate {
+        let prog = Parser::new(Lexer::new(src, "test.go").tokenize().unwrap()).parse_program().unwrap();
+        let cfg = TranspileConfig { board: board.into(), ..TranspileConfig::default() };
+        let cpp = Transpiler::new(cfg).generate(&prog).unwrap();
+        estimate(&prog, &cpp, &Board::find(board).unwrap())
+    }
+
+    fn item(e: &Estimate, what: &str) -> (u32, u32) {
+        e.items.iter().find(|u| u.what == what).map(|u| (u.flash, u.ram)).unwrap_or_default()
+    }
+
+    #[test]
+    fn test_estimate_globals_and_literals() {
+        let src = "package main\nimport \"fmt\"\nvar buf [1000]int\nvar name string\n\
+                   func setup() {\nfmt.Println(\"hello\")\nfmt.Println(\"hello\")\n}";
+        let uno = run(src, "uno");
+        assert_eq!(item(&uno, "globals"), (0, 2006));
+        assert_eq!(item(&uno, "string literals"), (6, 6));
+        assert!(item(&uno, "Serial").0 > 0);
+        assert_eq!(uno.over(100).as_deref(),
+            Some("estimated ram use 2.1 KB is 106% of the 2 KB of Arduino Uno (limit 100%)"));
+
+        let esp = run(src, "esp32");
+        assert_eq!(item(&esp, "globals"), (0, 4012));
+        assert_eq!(item(&esp, "string literals"), (6, 0));
+        assert_eq!(esp.over(100), None);
+
+        // tinyAVR counts like AVR: 16-bit int, literals copied into SRAM.
+        let tiny = run(src, "attiny1614");
+        assert_eq!(item(&tiny, "globals"), (0, 2006));
+        assert_eq!(item(&tiny, "string literals"), (6, 6));
+    }
+}
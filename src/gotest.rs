@@ -0,0 +1,204 @@
+// ─────────────────────────────────────────────────────────────────────────────
+//  tsuki :: gotest
+//
+//  `tsuki test`: the `TestXxx(t *testing.T)` functions of a package's
+//  `_test.go` files, built for the machine running tsuki instead of a board.
+//  The package's files are transpiled as one program for the `host` target,
+//  a `main()` calling every test is appended, and the result is compiled
+//  against `host/Arduino.h` — a stand-in for the Arduino core that keeps
+//  pin states in an array, advances time only on delay() and prints Serial
+//  to stdout — so logic can be tested without a board.
+// ─────────────────────────────────────────────────────────────────────────────
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::error::{tsukiError, Result, Warning};
+use crate::lexer::Lexer;
+use crate::parser::ast::{Decl, Program, Type};
+use crate::parser::Parser;
+use crate::transpiler::{TranspileConfig, Transpiler};
+use crate::Runtime;
+
+/// Board id of host builds. No board has it, so board checks are skipped
+/// and only host-only packages (`testing`) check for it.
+pub const HOST: &str = "host";
+
+/// Headers host programs (tests and `sim` runs) are compiled against.
+pub const SHIM: &[(&str, &str)] = &[
+    ("Arduino.h",    include_str!("host/Arduino.h")),
+    ("tsuki_test.h", include_str!("host/tsuki_test.h")),
+    ("tsuki_sim.h",  include_str!("host/tsuki_sim.h")),
+];
+
+/// A package's tests as one C++ program.
+#[derive(Debug)]
+pub struct TestProgram {
+    pub cpp:      String,
+    /// Test function names, in source order.
+    pub tests:    Vec<String>,
+    pub warnings: Vec<Warning>,
+}
+
+/// How to build and run a `TestProgram`.
+#[derive(Debug, Clone)]
+pub struct HostOptions {
+    /// C++ compiler to invoke.
+    pub cxx:      String,
+    /// Where the sources and the executable are written.
+    pub work_dir: PathBuf,
+    /// Only run tests whose name contains this.
+    pub run:      Option<String>,
+    pub verbose:  bool,
+}
+
+impl Default for HostOptions {
+    fn default() -> Self {
+        Self {
+            cxx:      "g++".into(),
+            work_dir: std::env::temp_dir().join(format!("tsuki-test-{}", std::process::id())),
+            run:      None,
+            verbose:  false,
+        }
+    }
+}
+
+/// The `.go` files of a package directory, `_test.go` ones included, as
+/// (file name, source) pairs sorted by name.
+pub fn package_files(dir: &Path) -> Result<Vec<(String, String)>> {
+    let read_err = |e: std::io::Error| tsukiError::other(format!("cannot read {}: {}", dir.display(), e));
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir).map_err(read_err)?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|x| x == "go"))
+        .collect();
+    paths.sort();
+    paths.into_iter().map(|p| {
+        let src = fs::read_to_string(&p)
+            .map_err(|e| tsukiError::other(format!("cannot read {}: {}", p.display(), e)))?;
+        Ok((p.display().to_string(), src))
+    }).collect()
+}
+
+/// Transpile a package's files, tests included, into a host program that
+/// runs the tests.
+pub fn transpile(files: &[(String, String)]) -> Result<TestProgram> {
+    let prog = merge(files)?;
+    let tests: Vec<String> = prog.decls.iter().filter_map(test_name).collect();
+    if tests.is_empty() {
+        return Err(tsukiError::other("no tests: no func TestXxx(t *testing.T) in the _test.go files"));
+    }
+
+    let cfg = TranspileConfig { board: HOST.into(), keep_all: true, split_main: false, ..TranspileConfig::default() };
+    let mut gen = Transpiler::with_runtime(cfg, Runtime::new());
+    let mut cpp = gen.generate(&prog)?;
+    cpp += "\nint main(int argc, char** argv) {\n    static const tsuki_test_case tests[] = {\n";
+    for t in &tests {
+        cpp += &format!("        {{\"{0}\", {0}}},\n", t);
+    }
+    cpp += "    };\n    return tsuki_test_main(tests, sizeof(tests) / sizeof(tests[0]), argc, argv);\n}\n";
+    Ok(TestProgram { cpp, tests, warnings: gen.warnings() })
+}
+
+/// Compile `prog` with the host compiler and run it, its output going to
+/// ours. Returns the exit code: 0 when every test passed.
+pub fn run(prog: &TestProgram, opts: &HostOptions) -> Result<i32> {
+    let exe = build(&prog.cpp, &opts.cxx, &opts.work_dir)?;
+    let mut cmd = Command::new(&exe);
+    if let Some(pattern) = &opts.run { cmd.arg("-run").arg(pattern); }
+    if opts.verbose { cmd.arg("-v"); }
+    let status = cmd.status()
+        .map_err(|e| tsukiError::other(format!("cannot run {}: {}", exe.display(), e)))?;
+    Ok(status.code().unwrap_or(1))
+}
+
+/// Write `cpp` and the shim to `dir` and compile them with `cxx`; returns
+/// the executable.
+pub(crate) fn build(cpp: &str, cxx: &str, dir: &Path) -> Result<PathBuf> {
+    let io_err = |e: std::io::Error| tsukiError::other(format!("cannot write to {}: {}", dir.display(), e));
+    fs::create_dir_all(dir).map_err(io_err)?;
+    for (name, text) in SHIM {
+        fs::write(dir.join(name), text).map_err(io_err)?;
+    }
+    let source = dir.join("main.cpp");
+    let exe    = dir.join(if cfg!(windows) { "main.exe" } else { "main" });
+    fs::write(&source, cpp).map_err(io_err)?;
+
+    let out = Command::new(cxx)
+        .args(["-std=c++17", "-w", "-I"]).arg(dir)
+        .arg("-o").arg(&exe).arg(&source)
+        .output()
+        .map_err(|e| tsukiError::other(format!("cannot run `{}`: {}", cxx, e)))?;
+    if !out.status.success() {
+        return Err(tsukiError::other(format!("host build failed ({}):\n{}",
+            source.display(), String::from_utf8_lossy(&out.stderr))));
+    }
+    Ok(exe)
+}
+
+/// One program from the files of a package, imports deduplicated.
+fn merge(files: &[(String, String)]) -> Result<Program> {
+    let mut merged: Option<Program> = None;
+    for (name, src) in files {
+        let prog = Parser::new(Lexer::new(src, name).tokenize()?).parse_program()?;
+        let Some(all) = merged.as_mut() else { merged = Some(prog); continue };
+        if prog.package != all.package {
+            return Err(tsukiError::other(format!(
+                "{}: package {} differs from package {} of the other files", name, prog.package, all.package)));
+        }
+        for imp in prog.imports {
+            if !all.imports.iter().any(|i| i.path == imp.path && i.alias == imp.alias) {
+                all.imports.push(imp);
+            }
+        }
+        all.decls.extend(prog.decls);
+    }
+    merged.ok_or_else(|| tsukiError::other("no .go files"))
+}
+
+/// `TestXxx` when `d` is `func TestXxx(t *testing.T)`; as in Go, the name
+/// continues with anything but a lower-case letter.
+fn test_name(d: &Decl) -> Option<String> {
+    let Decl::Func { name, recv: None, sig, .. } = d else { return None };
+    let rest = name.strip_prefix("Test")?;
+    if rest.starts_with(|c: char| c.is_ascii_lowercase()) { return None }
+    match sig.params.as_slice() {
+        [p] if sig.results.is_empty()
+            && matches!(&p.ty, Type::Ptr(t) if matches!(t.as_ref(), Type::Named(n) if n.ends_with(".T"))) => Some(name.clone()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CODE: &str = "package main\nfunc add(a int, b int) int {\nreturn a + b\n}\nfunc main() {\ndelay(add(1, 2))\n}";
+
+    #[test]
+    fn test_host_tests() {
+        let tests = "package main\nimport \"testing\"\n\
+                     func TestAdd(t *testing.T) {\nif add(2, 3) != 5 {\nt.Errorf(\"add(2, 3) = %d\", add(2, 3))\n}\n}\n\
+                     func TestFails(t *testing.T) {\nt.Log(\"before\")\nt.Fatal(\"stop\")\nt.Error(\"unreachable\")\n}\n\
+                     func Testify(t *testing.T) {}\nfunc TestNoT() {}";
+        let files = [("main.go".to_string(), CODE.to_string()), ("main_test.go".to_string(), tests.to_string())];
+        let prog = transpile(&files).unwrap();
+        assert_eq!(prog.tests, ["TestAdd", "TestFails"]);
+        assert!(prog.cpp.contains("void TestAdd(tsuki_T* t) {"), "{}", prog.cpp);
+        assert!(prog.cpp.contains("tsuki_testing_Errorf((*t), \"add(2, 3) = %d\", add(2, 3));"), "{}", prog.cpp);
+        assert!(prog.cpp.contains("        {\"TestFails\", TestFails},\n"), "{}", prog.cpp);
+
+        let err = transpile(&[("main.go".to_string(), CODE.to_string())]).unwrap_err();
+        assert!(err.to_string().contains("no tests"), "{}", err);
+        let err = crate::Pipeline::new(TranspileConfig::default()).run(tests, "main_test.go").unwrap_err();
+        assert!(err.to_string().contains("package \"testing\" is not available on Arduino Uno"), "{}", err);
+
+        // Build and run them when there is a compiler.
+        if Command::new("g++").arg("--version").output().is_err() { return }
+        let opts = HostOptions { run: Some("Add".into()), ..HostOptions::default() };
+        assert_eq!(run(&prog, &opts).unwrap(), 0);
+        let opts = HostOptions { run: None, ..opts };
+        assert_eq!(run(&prog, &opts).unwrap(), 1);
+        let _ = fs::remove_dir_all(&opts.work_dir);
+    }
+}
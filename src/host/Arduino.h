@@ -0,0 +1,298 @@
+// Host stand-in for the Arduino core, for `tsuki test` and `tsuki sim`. Pins
+// are an array the sketch reads back, time only moves when the sketch
+// delays, and Serial writes to stdout. Enough for logic tests and smoke
+// runs, not an emulator.
+#pragma once
+
+#include <algorithm>
+#include <cmath>
+#include <cstdarg>
+#include <cstdint>
+#include <cstdio>
+#include <cstdlib>
+#include <cstring>
+#include <string>
+
+typedef uint8_t byte;
+typedef bool    boolean;
+typedef unsigned int word;
+
+#define HIGH 0x1
+#define LOW  0x0
+#define INPUT        0x0
+#define OUTPUT       0x1
+#define INPUT_PULLUP 0x2
+#define LED_BUILTIN  13
+#define A0 14
+#define A1 15
+#define A2 16
+#define A3 17
+#define A4 18
+#define A5 19
+#define PI         3.1415926535897932384626433832795
+#define HALF_PI    1.5707963267948966192313216916398
+#define TWO_PI     6.283185307179586476925286766559
+#define DEG_TO_RAD 0.017453292519943295769236907684886
+#define RAD_TO_DEG 57.295779513082320876798154814105
+#define DEC 10
+#define HEX 16
+#define OCT 8
+#define BIN 2
+#define PROGMEM
+#define F(s) (s)
+#define pgm_read_byte(p)  (*(const uint8_t*)(p))
+#define pgm_read_word(p)  (*(const uint16_t*)(p))
+#define pgm_read_dword(p) (*(const uint32_t*)(p))
+#define pgm_read_float(p) (*(const float*)(p))
+#define bitRead(v, b)     (((v) >> (b)) & 0x01)
+#define bitSet(v, b)      ((v) |= (1UL << (b)))
+#define bitClear(v, b)    ((v) &= ~(1UL << (b)))
+#define bitWrite(v, b, x) ((x) ? bitSet(v, b) : bitClear(v, b))
+#define bit(b)            (1UL << (b))
+#define lowByte(w)        ((uint8_t)((w) & 0xff))
+#define highByte(w)       ((uint8_t)((w) >> 8))
+
+using std::min;
+using std::max;
+using std::abs;
+template <typename T, typename L, typename H>
+inline T constrain(T x, L lo, H hi) { return x < lo ? lo : (x > hi ? hi : x); }
+inline long map(long x, long in_min, long in_max, long out_min, long out_max) {
+    return (x - in_min) * (out_max - out_min) / (in_max - in_min) + out_min;
+}
+template <typename T> inline T sq(T x) { return x * x; }
+
+// ── Simulated board ──────────────────────────────────────────────────────────
+static int           tsuki_sim_pins[64];
+static int           tsuki_sim_modes[64];
+static bool          tsuki_sim_written[64];
+static unsigned long tsuki_sim_micros = 0;
+
+// ── Trace ────────────────────────────────────────────────────────────────────
+// What `tsuki sim` records: pin changes and Serial output, as text lines
+// among the Serial text or as one JSON object per line, and with -trace as
+// a JSON timeline in a file. Off for tests.
+enum { TSUKI_TRACE_OFF, TSUKI_TRACE_TEXT, TSUKI_TRACE_JSON };
+static int         tsuki_sim_trace = TSUKI_TRACE_OFF;
+static FILE*       tsuki_sim_trace_file = nullptr;
+static bool        tsuki_sim_trace_first = true;
+static bool        tsuki_sim_bol = true;  // stdout is at the start of a line
+static std::string tsuki_sim_line;        // Serial text of the next serial event
+
+inline std::string tsuki_sim_json_str(const std::string& s) {
+    std::string out = "\"";
+    for (unsigned char c : s) {
+        char esc[8];
+        if (c == '"' || c == '\\') { out += '\\'; out += (char) c; }
+        else if (c == '\n') out += "\\n";
+        else if (c == '\t') out += "\\t";
+        else if (c < 0x20) { std::snprintf(esc, sizeof(esc), "\\u%04x", c); out += esc; }
+        else out += (char) c;
+    }
+    return out + "\"";
+}
+
+// One event, `fields` being the JSON members after the timestamp.
+inline void tsuki_sim_event(const std::string& fields) {
+    std::string ev = "{\"t_us\":" + std::to_string(tsuki_sim_micros) + "," + fields + "}";
+    if (tsuki_sim_trace == TSUKI_TRACE_JSON) std::printf("%s\n", ev.c_str());
+    if (tsuki_sim_trace_file) {
+        std::fprintf(tsuki_sim_trace_file, "%s\n    %s", tsuki_sim_trace_first ? "" : ",", ev.c_str());
+        tsuki_sim_trace_first = false;
+    }
+}
+
+inline bool tsuki_sim_events() { return tsuki_sim_trace == TSUKI_TRACE_JSON || tsuki_sim_trace_file; }
+
+inline void tsuki_sim_pin(int pin, const char* kind, int value) {
+    if (tsuki_sim_events()) {
+        tsuki_sim_event("\"pin\":" + std::to_string(pin) + ",\"" + kind + "\":" + std::to_string(value));
+    }
+    if (tsuki_sim_trace == TSUKI_TRACE_TEXT) {
+        if (!tsuki_sim_bol) std::putchar('\n');
+        std::printf("[%8lu ms] pin %d = ", tsuki_sim_micros / 1000, pin);
+        if (std::strcmp(kind, "digital") == 0) std::puts(value ? "HIGH" : "LOW");
+        else std::printf("%d (%s)\n", value, kind);
+        tsuki_sim_bol = true;
+    }
+}
+
+inline void tsuki_sim_serial_line() {
+    while (!tsuki_sim_line.empty() && tsuki_sim_line.back() == '\r') tsuki_sim_line.pop_back();
+    tsuki_sim_event("\"serial\":" + tsuki_sim_json_str(tsuki_sim_line));
+    tsuki_sim_line.clear();
+}
+
+inline size_t tsuki_sim_serial(const char* s, size_t n) {
+    if (n == 0) return 0;
+    if (tsuki_sim_trace != TSUKI_TRACE_JSON) {
+        tsuki_sim_bol = s[n - 1] == '\n';
+        std::fwrite(s, 1, n, stdout);
+    }
+    if (tsuki_sim_events()) {
+        for (size_t i = 0; i < n; i++) {
+            if (s[i] == '\n') tsuki_sim_serial_line();
+            else tsuki_sim_line += s[i];
+        }
+    }
+    return n;
+}
+
+// Records a write to pin when it changes the pin, or is the first one.
+inline void tsuki_sim_write(int pin, const char* kind, int value) {
+    int p = pin & 63;
+    if (tsuki_sim_written[p] && tsuki_sim_pins[p] == value) return;
+    tsuki_sim_written[p] = true;
+    tsuki_sim_pins[p] = value;
+    tsuki_sim_pin(pin, kind, value);
+}
+
+inline void pinMode(int pin, int mode)       { tsuki_sim_modes[pin & 63] = mode; }
+inline void digitalWrite(int pin, int value) { tsuki_sim_write(pin, "digital", value ? HIGH : LOW); }
+inline int  digitalRead(int pin)             { return tsuki_sim_pins[pin & 63]; }
+inline void analogWrite(int pin, int value)  { tsuki_sim_write(pin, "pwm", value); }
+inline int  analogRead(int pin)              { return tsuki_sim_pins[pin & 63]; }
+inline void analogReference(int)             {}
+inline unsigned long millis() { return tsuki_sim_micros / 1000; }
+inline unsigned long micros() { return tsuki_sim_micros; }
+inline void delay(unsigned long ms)             { tsuki_sim_micros += ms * 1000; }
+inline void delayMicroseconds(unsigned int us)  { tsuki_sim_micros += us; }
+inline void tone(int, unsigned int, unsigned long = 0) {}
+inline void noTone(int) {}
+inline unsigned long pulseIn(int, int, unsigned long = 1000000UL) { return 0; }
+inline long random(long hi)          { return hi > 0 ? std::rand() % hi : 0; }
+inline long random(long lo, long hi) { return hi > lo ? lo + std::rand() % (hi - lo) : lo; }
+inline void randomSeed(unsigned long seed) { std::srand((unsigned) seed); }
+inline void yield() {}
+
+// ── String ───────────────────────────────────────────────────────────────────
+class String {
+public:
+    String() = default;
+    String(const char* s) : s_(s ? s : "") {}
+    String(const std::string& s) : s_(s) {}
+    String(char c) : s_(1, c) {}
+    String(int v, int base = DEC)           : s_(num((long) v, base)) {}
+    String(unsigned v, int base = DEC)      : s_(unum((unsigned long) v, base)) {}
+    String(long v, int base = DEC)          : s_(num(v, base)) {}
+    String(unsigned long v, int base = DEC) : s_(unum(v, base)) {}
+    String(long long v)                     : s_(std::to_string(v)) {}
+    String(unsigned long long v)            : s_(std::to_string(v)) {}
+    String(float v, int decimals = 2)       : s_(fixed(v, decimals)) {}
+    String(double v, int decimals = 2)      : s_(fixed(v, decimals)) {}
+
+    const char*  c_str()  const { return s_.c_str(); }
+    unsigned int length() const { return (unsigned int) s_.size(); }
+    char charAt(unsigned int i) const { return i < s_.size() ? s_[i] : 0; }
+    char operator[](unsigned int i) const { return charAt(i); }
+    void setCharAt(unsigned int i, char c) { if (i < s_.size()) s_[i] = c; }
+    int indexOf(char c, unsigned int from = 0) const { return pos(s_.find(c, from)); }
+    int indexOf(const String& t, unsigned int from = 0) const { return pos(s_.find(t.s_, from)); }
+    int lastIndexOf(char c) const { return pos(s_.rfind(c)); }
+    int lastIndexOf(const String& t) const { return pos(s_.rfind(t.s_)); }
+    String substring(unsigned int from) const { return from < s_.size() ? String(s_.substr(from)) : String(); }
+    String substring(unsigned int from, unsigned int to) const {
+        if (from > to) std::swap(from, to);
+        return from < s_.size() ? String(s_.substr(from, to - from)) : String();
+    }
+    bool startsWith(const String& p) const { return s_.compare(0, p.s_.size(), p.s_) == 0; }
+    bool endsWith(const String& p) const {
+        return p.s_.size() <= s_.size() && s_.compare(s_.size() - p.s_.size(), p.s_.size(), p.s_) == 0;
+    }
+    bool equals(const String& o) const { return s_ == o.s_; }
+    bool equalsIgnoreCase(const String& o) const {
+        return s_.size() == o.s_.size() && std::equal(s_.begin(), s_.end(), o.s_.begin(),
+            [](char a, char b) { return std::tolower((unsigned char) a) == std::tolower((unsigned char) b); });
+    }
+    int compareTo(const String& o) const { return s_.compare(o.s_); }
+    void toUpperCase() { for (auto& c : s_) c = (char) std::toupper((unsigned char) c); }
+    void toLowerCase() { for (auto& c : s_) c = (char) std::tolower((unsigned char) c); }
+    void trim() {
+        auto b = s_.find_first_not_of(" \t\r\n");
+        auto e = s_.find_last_not_of(" \t\r\n");
+        s_ = b == std::string::npos ? "" : s_.substr(b, e - b + 1);
+    }
+    void replace(const String& from, const String& to) {
+        if (from.s_.empty()) return;
+        for (size_t i = 0; (i = s_.find(from.s_, i)) != std::string::npos; i += to.s_.size())
+            s_.replace(i, from.s_.size(), to.s_);
+    }
+    void remove(unsigned int from) { if (from < s_.size()) s_.erase(from); }
+    void remove(unsigned int from, unsigned int n) { if (from < s_.size()) s_.erase(from, n); }
+    long   toInt()    const { return std::strtol(s_.c_str(), nullptr, 10); }
+    float  toFloat()  const { return std::strtof(s_.c_str(), nullptr); }
+    double toDouble() const { return std::strtod(s_.c_str(), nullptr); }
+    bool concat(const String& o) { s_ += o.s_; return true; }
+    void reserve(unsigned int n) { s_.reserve(n); }
+    void getBytes(unsigned char* buf, unsigned int n) const { copy((char*) buf, n); }
+    void toCharArray(char* buf, unsigned int n) const { copy(buf, n); }
+
+    String& operator+=(const String& o) { s_ += o.s_; return *this; }
+    friend String operator+(const String& a, const String& b) { return String(a.s_ + b.s_); }
+    friend String operator+(const String& a, const char* b) { return String(a.s_ + b); }
+    friend String operator+(const char* a, const String& b) { return String(a + b.s_); }
+    bool operator==(const String& o) const { return s_ == o.s_; }
+    bool operator!=(const String& o) const { return s_ != o.s_; }
+    bool operator<(const String& o)  const { return s_ < o.s_; }
+    bool operator>(const String& o)  const { return s_ > o.s_; }
+    bool operator<=(const String& o) const { return s_ <= o.s_; }
+    bool operator>=(const String& o) const { return s_ >= o.s_; }
+
+private:
+    std::string s_;
+    static int pos(size_t p) { return p == std::string::npos ? -1 : (int) p; }
+    static std::string unum(unsigned long v, int base) {
+        if (base == DEC) return std::to_string(v);
+        std::string out;
+        do { out.insert(out.begin(), "0123456789abcdef"[v % base]); v /= base; } while (v);
+        return out;
+    }
+    static std::string num(long v, int base) {
+        return v < 0 && base == DEC ? "-" + unum((unsigned long) -v, base) : unum((unsigned long) v, base);
+    }
+    static std::string fixed(double v, int decimals) {
+        char buf[64];
+        std::snprintf(buf, sizeof(buf), "%.*f", decimals, v);
+        return buf;
+    }
+    void copy(char* buf, unsigned int n) const {
+        if (n == 0) return;
+        size_t k = std::min<size_t>(n - 1, s_.size());
+        std::memcpy(buf, s_.data(), k);
+        buf[k] = 0;
+    }
+};
+
+// ── Serial ───────────────────────────────────────────────────────────────────
+struct tsuki_HostSerial {
+    void begin(unsigned long, int = 0) {}
+    void end() {}
+    explicit operator bool() const { return true; }
+    int  available() { return 0; }
+    int  read() { return -1; }
+    int  peek() { return -1; }
+    void flush() { std::fflush(stdout); }
+    String readString() { return String(); }
+    String readStringUntil(char) { return String(); }
+    void setTimeout(unsigned long) {}
+
+    size_t print(const String& s) { return tsuki_sim_serial(s.c_str(), s.length()); }
+    size_t print(const char* s)   { return print(String(s)); }
+    size_t print(char c)          { return print(String(c)); }
+    size_t print(double v, int decimals = 2) { return print(String(v, decimals)); }
+    template <typename T> size_t print(T v, int base = DEC) { return print(String(v, base)); }
+    size_t println() { return print("\r\n"); }
+    template <typename T> size_t println(T v) { size_t n = print(v); return n + println(); }
+    template <typename T> size_t println(T v, int f) { size_t n = print(v, f); return n + println(); }
+    size_t write(uint8_t b) { char c = (char) b; return tsuki_sim_serial(&c, 1); }
+    size_t write(const uint8_t* buf, size_t n) { return tsuki_sim_serial((const char*) buf, n); }
+    size_t printf(const char* fmt, ...) {
+        char buf[512];
+        va_list ap;
+        va_start(ap, fmt);
+        int n = std::vsnprintf(buf, sizeof(buf), fmt, ap);
+        va_end(ap);
+        return n < 0 ? 0 : tsuki_sim_serial(buf, std::min<size_t>((size_t) n, sizeof(buf) - 1));
+    }
+};
+static tsuki_HostSerial Serial;
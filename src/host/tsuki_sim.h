@@ -0,0 +1,48 @@
+// The main() of `tsuki sim`: setup() once, then loop() until the simulated
+// clock reaches the end of the run. A loop() that never delays doesn't move
+// the clock, so the number of loop() calls is capped as well.
+#pragma once
+
+#include <Arduino.h>
+
+// Arguments: `-ms <n>` simulated time to run for, `-loops <n>` loop() calls
+// at most, `-json` to trace as JSON lines, `-trace <file>` to also write the
+// events to file as a JSON document.
+inline int tsuki_sim_main(int argc, char** argv, void (*setup)(), void (*loop)()) {
+    unsigned long ms = 10000, max_loops = 1000000;
+    tsuki_sim_trace = TSUKI_TRACE_TEXT;
+    for (int i = 1; i < argc; i++) {
+        if (std::strcmp(argv[i], "-json") == 0) tsuki_sim_trace = TSUKI_TRACE_JSON;
+        else if (std::strcmp(argv[i], "-ms") == 0 && i + 1 < argc) ms = std::strtoul(argv[++i], nullptr, 10);
+        else if (std::strcmp(argv[i], "-loops") == 0 && i + 1 < argc) max_loops = std::strtoul(argv[++i], nullptr, 10);
+        else if (std::strcmp(argv[i], "-trace") == 0 && i + 1 < argc) {
+            tsuki_sim_trace_file = std::fopen(argv[++i], "w");
+            if (!tsuki_sim_trace_file) {
+                std::fprintf(stderr, "sim: cannot write %s\n", argv[i]);
+                return 2;
+            }
+            std::fputs("{\n  \"events\": [", tsuki_sim_trace_file);
+        }
+    }
+
+    setup();
+    unsigned long loops = 0;
+    while (millis() < ms && loops < max_loops) {
+        loop();
+        loops++;
+    }
+
+    if (!tsuki_sim_line.empty()) tsuki_sim_serial_line();
+    if (tsuki_sim_trace_file) {
+        std::fprintf(tsuki_sim_trace_file, "\n  ],\n  \"end_us\": %lu,\n  \"loops\": %lu\n}\n", tsuki_sim_micros, loops);
+        std::fclose(tsuki_sim_trace_file);
+    }
+    if (tsuki_sim_trace == TSUKI_TRACE_JSON) {
+        std::printf("{\"t_us\":%lu,\"end\":true,\"loops\":%lu}\n", tsuki_sim_micros, loops);
+    } else {
+        if (!tsuki_sim_bol) std::putchar('\n');
+        std::fflush(stdout);
+        std::fprintf(stderr, "sim: stopped at %lu ms after %lu loop() calls\n", millis(), loops);
+    }
+    return 0;
+}
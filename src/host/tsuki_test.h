@@ -0,0 +1,90 @@
+// Go's testing.T for `tsuki test`: a test fails on Error*/Fail and stops on
+// Fatal*/FailNow (thrown, caught by the runner). Messages are buffered and
+// printed after the test, as `go test` does.
+#pragma once
+
+#include <Arduino.h>
+#include <initializer_list>
+#include <string>
+
+struct tsuki_test_stop {};
+
+struct tsuki_T {
+    const char* name;
+    bool failed  = false;
+    bool skipped = false;
+    std::string log;
+
+    template <typename... V> void line(const V&... v) {
+        std::string s;
+        int n = 0;
+        (void) std::initializer_list<int>{ (s += (n++ ? " " : "") + tsuki_test_str(v), 0)... };
+        log += "    " + s + "\n";
+    }
+    void linef(const char* fmt, va_list ap) {
+        char buf[512];
+        std::vsnprintf(buf, sizeof(buf), fmt, ap);
+        log += std::string("    ") + buf + "\n";
+    }
+
+    template <typename V> static std::string tsuki_test_str(const V& v) { return String(v).c_str(); }
+    static std::string tsuki_test_str(bool v) { return v ? "true" : "false"; }
+};
+
+template <typename... V> void tsuki_testing_Log(tsuki_T& t, const V&... v)   { t.line(v...); }
+template <typename... V> void tsuki_testing_Error(tsuki_T& t, const V&... v) { t.line(v...); t.failed = true; }
+template <typename... V> void tsuki_testing_Fatal(tsuki_T& t, const V&... v) { t.line(v...); t.failed = true; throw tsuki_test_stop{}; }
+template <typename... V> void tsuki_testing_Skip(tsuki_T& t, const V&... v)  { t.line(v...); t.skipped = true; throw tsuki_test_stop{}; }
+
+inline void tsuki_testing_Fail(tsuki_T& t)    { t.failed = true; }
+inline void tsuki_testing_FailNow(tsuki_T& t) { t.failed = true; throw tsuki_test_stop{}; }
+inline void tsuki_testing_SkipNow(tsuki_T& t) { t.skipped = true; throw tsuki_test_stop{}; }
+
+#define TSUKI_TESTING_F(name, after)                        \
+    inline void name(tsuki_T& t, const char* fmt, ...) {    \
+        va_list ap;                                         \
+        va_start(ap, fmt);                                  \
+        t.linef(fmt, ap);                                   \
+        va_end(ap);                                         \
+        after;                                              \
+    }
+TSUKI_TESTING_F(tsuki_testing_Logf,   (void) 0)
+TSUKI_TESTING_F(tsuki_testing_Errorf, t.failed = true)
+TSUKI_TESTING_F(tsuki_testing_Fatalf, t.failed = true; throw tsuki_test_stop{})
+TSUKI_TESTING_F(tsuki_testing_Skipf,  t.skipped = true; throw tsuki_test_stop{})
+#undef TSUKI_TESTING_F
+
+struct tsuki_test_case {
+    const char* name;
+    void (*fn)(tsuki_T*);
+};
+
+// Runs the tests whose name contains the `-run` argument; `-v` lists each.
+inline int tsuki_test_main(const tsuki_test_case* tests, int n, int argc, char** argv) {
+    const char* filter = nullptr;
+    bool verbose = false;
+    for (int i = 1; i < argc; i++) {
+        if (std::strcmp(argv[i], "-v") == 0) verbose = true;
+        else if (std::strcmp(argv[i], "-run") == 0 && i + 1 < argc) filter = argv[++i];
+    }
+    int failed = 0, ran = 0;
+    for (int i = 0; i < n; i++) {
+        if (filter && !std::strstr(tests[i].name, filter)) continue;
+        tsuki_T t;
+        t.name = tests[i].name;
+        if (verbose) std::printf("=== RUN   %s\n", t.name);
+        try {
+            tests[i].fn(&t);
+        } catch (const tsuki_test_stop&) {
+        }
+        ran++;
+        const char* verdict = t.failed ? "FAIL" : t.skipped ? "SKIP" : "PASS";
+        if (t.failed || verbose) {
+            std::printf("--- %s: %s\n%s", verdict, t.name, t.log.c_str());
+        }
+        if (t.failed) failed++;
+    }
+    if (ran == 0) std::printf("testing: warning: no tests to run\n");
+    std::printf(failed ? "FAIL\n" : "PASS\n");
+    return failed ? 1 : 0;
+}
@@ -62,7 +62,7 @@ impl Lexer {
 
     fn eat_while(&mut self, pred: impl Fn(char) -> bool) -> String {
         let mut buf = String::new();
-        while self.peek().map_or(false, |c| pred(c)) {
+        while self.peek().is_some_and(&pred) {
             buf.push(self.advance().unwrap());
         }
         buf
@@ -80,6 +80,20 @@ impl Lexer {
         while !matches!(self.peek(), Some('\n') | None) { self.advance(); }
     }
 
+    /// A line comment is a directive when `//` is directly followed by
+    /// `export` or a `namespace:name` word, as in Go's `//go:` pragmas.
+    fn at_directive(&self) -> bool {
+        let word: String = self.chars[self.pos + 2..].iter()
+            .take_while(|c| c.is_ascii_alphanumeric() || **c == ':' || **c == '_')
+            .collect();
+        let next = self.chars.get(self.pos + 2 + word.chars().count()).copied();
+        if !matches!(next, None | Some(' ') | Some('\t') | Some('\n') | Some('\r')) { return false; }
+        word == "export"
+            || word.split_once(':').is_some_and(|(ns, name)| {
+                ns.chars().next().is_some_and(|c| c.is_ascii_lowercase()) && !name.is_empty()
+            })
+    }
+
     fn skip_block_comment(&mut self) -> Result<()> {
         let sp = self.span();
         // consume  /*
@@ -112,6 +126,11 @@ impl Lexer {
 
             // ── Comments ─────────────────────────────────────────────────
             Some('/') if self.peek2() == Some('/') => {
+                if self.at_directive() {
+                    self.advance(); self.advance();
+                    let text = self.eat_while(|c| c != '\n');
+                    return Ok(Token::new(TokenKind::Directive(text.trim_end().to_owned()), sp, ""));
+                }
                 self.skip_line_comment();
                 self.next()
             }
@@ -233,7 +252,7 @@ impl Lexer {
 
         // float?
         let is_float = self.peek() == Some('.')
-            && self.peek2().map_or(false, |c| c.is_ascii_digit());
+            && self.peek2().is_some_and(|c| c.is_ascii_digit());
         let has_exp  = !is_float &&
             (self.peek() == Some('e') || self.peek() == Some('E'));
 
@@ -412,4 +431,16 @@ mod tests {
         let kinds = lex("...");
         assert_eq!(kinds, vec![TokenKind::Ellipsis]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_directives() {
+        let kinds = lex("//export Init\n// export Init\n//go:noinline\n//TODO: later");
+        assert_eq!(kinds, vec![
+            TokenKind::Directive("export Init".into()),
+            TokenKind::Newline,
+            TokenKind::Newline,
+            TokenKind::Directive("go:noinline".into()),
+            TokenKind::Newline,
+        ]);
+    }
+}
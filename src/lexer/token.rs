@@ -117,6 +117,8 @@ pub enum TokenKind {
     RBracket,     // ]
 
     // ── Special ───────────────────────────────────────────────
+    /// `//export Name` or `//ns:name args` — text after the slashes.
+    Directive(String),
     Newline,
     EOF,
 }
@@ -2,15 +2,21 @@
 //  tsuki_core  —  public library API  (updated for external libs)
 // ─────────────────────────────────────────────────────────────────────────────
 
+pub mod boards;
 pub mod error;
+pub mod estimate;
+pub mod gotest;
 pub mod lexer;
 pub mod parser;
 pub mod runtime;
+pub mod sim;
+pub mod testing;
 pub mod transpiler;
 
-pub use error::{tsukiError, Result, Span};
+pub use error::{tsukiError, Result, Span, Suggestion, Warning};
 pub use transpiler::TranspileConfig;
-pub use runtime::{Board, Runtime};
+pub use transpiler::plugin::{TranspilerHook, WatchdogReset};
+pub use runtime::{Board, PkgDoc, Runtime};
 pub use runtime::pkg_loader::{LibManifest, load_from_str as load_lib_from_str};
 pub use runtime::pkg_manager;
 
@@ -29,6 +35,16 @@ pub use runtime::pkg_manager;
 ///     .unwrap();
 /// ```
 ///
+/// # With a hook
+/// ```no_run
+/// use tsuki_core::{Pipeline, TranspileConfig, WatchdogReset};
+///
+/// let cpp = Pipeline::new(TranspileConfig { board: "uno".into(), ..TranspileConfig::default() })
+///     .with_hook(WatchdogReset)
+///     .run("package main\nfunc loop() {}", "main.go")
+///     .unwrap();
+/// ```
+///
 /// # With external libraries
 /// ```no_run
 /// use tsuki_core::{Pipeline, TranspileConfig, PipelineOptions};
@@ -46,8 +62,9 @@ pub use runtime::pkg_manager;
 ///     .unwrap();
 /// ```
 pub struct Pipeline {
-    cfg:  TranspileConfig,
-    opts: PipelineOptions,
+    cfg:   TranspileConfig,
+    opts:  PipelineOptions,
+    hooks: Vec<Box<dyn TranspilerHook>>,
 }
 
 /// Options passed to `Pipeline` to control library loading and other behaviour.
@@ -60,6 +77,11 @@ pub struct PipelineOptions {
     /// Explicit list of package names to load from `libs_dir`.
     /// If empty AND `libs_dir` is set, ALL installed libraries are loaded.
     pub pkg_names: Vec<String>,
+
+    /// A project's vendored packages (`tsuki pkg vendor`), in the `libs_dir`
+    /// layout. Searched before `libs_dir`, so an offline build can rely on
+    /// them alone.
+    pub vendor_dir: Option<std::path::PathBuf>,
 }
 
 impl Pipeline {
@@ -67,6 +89,7 @@ impl Pipeline {
         Self {
             cfg,
             opts: PipelineOptions::default(),
+            hooks: Vec::new(),
         }
     }
 
@@ -75,23 +98,102 @@ impl Pipeline {
         self
     }
 
+    /// Run `hook` on every program this pipeline transpiles, after the hooks
+    /// registered before it.
+    pub fn with_hook(mut self, hook: impl TranspilerHook + 'static) -> Self {
+        self.hooks.push(Box::new(hook));
+        self
+    }
+
     pub fn run(&self, source: &str, filename: &str) -> Result<String> {
-        // Build the runtime — load external libs if requested
-        let rt = match &self.opts.libs_dir {
-            None => Runtime::new(),
-            Some(dir) if self.opts.pkg_names.is_empty() => Runtime::with_libs(dir),
-            Some(dir) => Runtime::with_selected_libs(dir, &self.opts.pkg_names),
-        };
+        self.run_with_warnings(source, filename).map(|(cpp, _)| cpp)
+    }
 
-        // 1. Lex
-        let tokens = lexer::Lexer::new(source, filename).tokenize()?;
+    /// Like `run`, also returning the non-fatal diagnostics.
+    pub fn run_with_warnings(&self, source: &str, filename: &str) -> Result<(String, Vec<Warning>)> {
+        self.transpile(source, filename).map(|(_, cpp, warnings)| (cpp, warnings))
+    }
+
+    /// Like `run_with_warnings`, plus a flash/RAM estimate for the target
+    /// board (None when the board isn't in the catalog).
+    pub fn run_with_estimate(&self, source: &str, filename: &str)
+        -> Result<(String, Vec<Warning>, Option<estimate::Estimate>)>
+    {
+        let (prog, cpp, warnings) = self.transpile(source, filename)?;
+        let est = Board::find(&self.cfg.board).map(|b| estimate::estimate(&prog, &cpp, &b));
+        Ok((cpp, warnings, est))
+    }
+
+    /// The header other files include to use this (non-main) package.
+    pub fn declarations(&self, source: &str, filename: &str) -> Result<String> {
+        let prog = self.parse(source, filename)?;
+        transpiler::Transpiler::with_runtime(self.cfg.clone(), self.runtime()?).declarations(&prog)
+    }
+
+    /// The Arduino libraries (arduino-cli names) the file's imports compile
+    /// against, e.g. `"DHT sensor library"` for `import "dht"`.
+    pub fn arduino_libs(&self, source: &str, filename: &str) -> Result<Vec<String>> {
+        let prog = self.parse(source, filename)?;
+        let pkgs: Vec<&str> = prog.imports.iter()
+            .map(|imp| imp.path.rsplit('/').next().unwrap_or(&imp.path))
+            .collect();
+        Ok(self.runtime()?.arduino_libs_for(&pkgs))
+    }
+
+    /// Vendored header directories of the loaded libraries, for the
+    /// compiler's `--include` list.
+    pub fn include_dirs(&self) -> Result<Vec<std::path::PathBuf>> {
+        Ok(self.runtime()?.include_dirs())
+    }
+
+    /// Every syntax error in the file (the parser recovers after each one);
+    /// empty when it parses. Lexer errors still stop at the first.
+    pub fn syntax_errors(&self, source: &str, filename: &str) -> Vec<tsukiError> {
+        match lexer::Lexer::new(source, filename).tokenize() {
+            Ok(tokens) => parser::Parser::new(tokens).parse_program_all().err().unwrap_or_default(),
+            Err(e)     => vec![e],
+        }
+    }
+
+    fn runtime(&self) -> Result<Runtime> {
+        let mut rt = Runtime::new();
+        let dirs: Vec<&std::path::Path> = self.opts.vendor_dir.iter().chain(&self.opts.libs_dir)
+            .map(|d| d.as_path())
+            .collect();
+        if !dirs.is_empty() {
+            rt.load_libs_in(&dirs, &self.opts.pkg_names);
+        }
+        if let Some(dir) = &self.cfg.prelude_dir {
+            rt.set_prelude(runtime::prelude::Prelude::load(dir.as_ref())?);
+        }
+        Ok(rt)
+    }
+
+    fn transpile(&self, source: &str, filename: &str) -> Result<(parser::ast::Program, String, Vec<Warning>)> {
+        // Build the runtime — load external libs if requested
+        let rt = self.runtime()?;
 
-        // 2. Parse
-        let prog = parser::Parser::new(tokens).parse_program()?;
+        // 1. Lex + parse, then let hooks rewrite the program
+        let prog = self.parse(source, filename)?;
 
-        // 3. Generate
+        // 2. Generate
         let mut gen = transpiler::Transpiler::with_runtime(self.cfg.clone(), rt);
-        gen.generate(&prog)
+        let mut cpp = gen.generate(&prog)?;
+
+        // 3. Hook output filters
+        for h in &self.hooks {
+            cpp = h.filter_output(cpp, &self.cfg)?;
+        }
+        Ok((prog, cpp, gen.warnings()))
+    }
+
+    fn parse(&self, source: &str, filename: &str) -> Result<parser::ast::Program> {
+        let tokens = lexer::Lexer::new(source, filename).tokenize()?;
+        let mut prog = parser::Parser::new(tokens).parse_program()?;
+        for h in &self.hooks {
+            h.visit_program(&mut prog, &self.cfg)?;
+        }
+        Ok(prog)
     }
 }
 
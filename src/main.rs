@@ -5,19 +5,30 @@
 //
 //  New flags:
 //    --libs-dir <path>        root directory of installed tsukilib packages
-//    --packages ws2812,dht    comma-separated package names to load
+//    --packages ws2812,dht    comma-separated package names (or name@version) to load
 // ─────────────────────────────────────────────────────────────────────────────
 
-use std::path::PathBuf;
-use tsuki_core::{Pipeline, PipelineOptions, TranspileConfig, Board};
+use std::path::{Path, PathBuf};
+use tsuki_core::{Pipeline, PipelineOptions, TranspileConfig, Board, Runtime};
+use tsuki_core::gotest;
+use tsuki_core::sim;
 use tsuki_core::pkg_manager;
 use tsuki_core::pkg_manager::default_libs_dir;
+use tsuki_core::runtime::prelude::{self, Prelude};
 
 fn main() {
     let args: Vec<String> = std::env::args().collect();
 
     if args.iter().any(|a| a == "--version" || a == "-V") {
-        println!("tsuki {}", env!("CARGO_PKG_VERSION"));
+        println!("tsuki {} (prelude v{})", env!("CARGO_PKG_VERSION"), prelude::VERSION);
+        return;
+    }
+    if let Some(dir) = flag_value(&args, "--dump-prelude") {
+        if let Err(e) = Prelude::dump(dir.as_ref()) {
+            eprintln!("error: cannot write prelude files to {}: {}", dir, e);
+            std::process::exit(1);
+        }
+        eprintln!("ok  prelude v{} written to {}", prelude::VERSION, dir);
         return;
     }
     if args.iter().any(|a| a == "--help" || a == "-h") || args.len() < 2 {
@@ -34,6 +45,18 @@ fn main() {
         handle_pkg(&args);
         return;
     }
+    if args.get(1).map(|s| s == "doc").unwrap_or(false) {
+        handle_doc(&args);
+        return;
+    }
+    if args.get(1).map(|s| s == "test").unwrap_or(false) {
+        handle_test(&args);
+        return;
+    }
+    if args.get(1).map(|s| s == "sim").unwrap_or(false) {
+        handle_sim(&args);
+        return;
+    }
 
     // ── Positional args ───────────────────────────────────────────────────────
     let input: PathBuf = args[1].clone().into();
@@ -43,11 +66,36 @@ fn main() {
 
     // ── Named flags ───────────────────────────────────────────────────────────
     let board      = flag_value(&args, "--board").unwrap_or_else(|| "uno".into());
+    let cpu_mhz    = flag_value(&args, "--cpu-mhz").and_then(|s| s.parse().ok());
+    let progmem    = flag_value(&args, "--progmem-min").and_then(|s| s.parse().ok());
+    let header     = flag_value(&args, "--header").map(PathBuf::from);
+    let prelude_dir = flag_value(&args, "--prelude-dir");
+    let local_pkgs: Vec<String> = flag_value(&args, "--local-packages")
+        .map(|s| s.split(',').map(|p| p.trim().to_owned()).filter(|s| !s.is_empty()).collect())
+        .unwrap_or_default();
     let source_map = args.iter().any(|a| a == "--source-map");
+    let split_main = !args.iter().any(|a| a == "--no-split-main");
+    let keep_all   = args.iter().any(|a| a == "--keep-all");
     let check_only = args.iter().any(|a| a == "--check");
+    let json_errors = match flag_value(&args, "--error-format").as_deref() {
+        None | Some("human") => false,
+        Some("json")         => true,
+        Some(other) => {
+            eprintln!("error: --error-format expects `human` or `json`, got `{}`", other);
+            std::process::exit(1);
+        }
+    };
+    let max_usage: u32 = match flag_value(&args, "--max-usage") {
+        None    => 100,
+        Some(s) => s.trim_end_matches('%').parse().unwrap_or_else(|_| {
+            eprintln!("error: --max-usage expects a percentage, got `{}`", s);
+            std::process::exit(1);
+        }),
+    };
 
     // External library flags
     let libs_dir   = flag_value(&args, "--libs-dir").map(PathBuf::from);
+    let vendor_dir = flag_value(&args, "--vendor-dir").map(PathBuf::from);
     let pkg_names: Vec<String> = flag_value(&args, "--packages")
         .map(|s| s.split(',').map(|p| p.trim().to_owned()).filter(|s| !s.is_empty()).collect())
         .unwrap_or_default();
@@ -55,6 +103,12 @@ fn main() {
     let cfg = TranspileConfig {
         board,
         emit_source_map: source_map,
+        cpu_mhz,
+        progmem_min: progmem,
+        local_packages: local_pkgs,
+        prelude_dir,
+        split_main,
+        keep_all,
         ..Default::default()
     };
 
@@ -68,50 +122,235 @@ fn main() {
     };
 
     let filename = input.to_string_lossy().into_owned();
+    let diag = Diagnostics { json: json_errors, source: &source, file: &filename };
 
     // ── Build pipeline with optional external libs ────────────────────────────
     let pipeline = Pipeline::new(cfg)
         .with_options(PipelineOptions {
-            libs_dir:  libs_dir,
-            pkg_names: pkg_names,
+            libs_dir,
+            pkg_names,
+            vendor_dir,
         });
 
     // ── Run (check-only or full transpile) ────────────────────────────────────
     if check_only {
-        match pipeline.run(&source, &filename) {
-            Ok(_)  => {
-                eprintln!("ok  {} — no errors", input.display());
+        match pipeline.run_with_estimate(&source, &filename) {
+            Ok((_, warnings, estimate)) => {
+                diag.warnings(&warnings);
+                if let Some(est) = estimate {
+                    diag.text(&est.report());
+                    if let Some(msg) = est.over(max_usage) {
+                        diag.error(&tsuki_core::tsukiError::other(msg));
+                        std::process::exit(1);
+                    }
+                }
+                diag.text(&format!("ok  {} — no errors", input.display()));
                 std::process::exit(0);
             }
             Err(e) => {
-                eprintln!("{}", tsuki_core::pretty_error(&e, &source));
+                // Report every syntax error of the file, not just the first.
+                let errors = match e {
+                    tsuki_core::tsukiError::Parse { .. } => pipeline.syntax_errors(&source, &filename),
+                    e => vec![e],
+                };
+                for e in &errors { diag.error(e); }
                 std::process::exit(1);
             }
         }
     }
 
-    match pipeline.run(&source, &filename) {
-        Ok(cpp) => {
+    if let Some(path) = &header {
+        let written = pipeline.declarations(&source, &filename)
+            .and_then(|h| std::fs::write(path, h).map_err(|e| tsuki_core::tsukiError::other(
+                format!("cannot write {}: {}", path.display(), e))));
+        if let Err(e) = written {
+            diag.error(&e);
+            std::process::exit(1);
+        }
+    }
+
+    match pipeline.run_with_warnings(&source, &filename) {
+        Ok((cpp, warnings)) => {
+            diag.warnings(&warnings);
             match output {
                 Some(path) => {
                     if let Err(e) = std::fs::write(&path, &cpp) {
-                        eprintln!("error: cannot write {}: {}", path.display(), e);
+                        diag.error(&tsuki_core::tsukiError::other(format!("cannot write {}: {}", path.display(), e)));
                         std::process::exit(1);
                     }
-                    eprintln!("ok  {}", path.display());
+                    diag.text(&format!("ok  {}", path.display()));
+                    // The CLI installs these before compiling, and puts the
+                    // vendored headers on the include path.
+                    for lib in pipeline.arduino_libs(&source, &filename).unwrap_or_default() {
+                        diag.text(&format!("lib  {}", lib));
+                    }
+                    for dir in pipeline.include_dirs().unwrap_or_default() {
+                        diag.text(&format!("inc  {}", dir.display()));
+                    }
                 }
                 None => print!("{}", cpp),
             }
         }
         Err(e) => {
-            eprintln!("{}", tsuki_core::pretty_error(&e, &source));
+            diag.error(&e);
             std::process::exit(1);
         }
     }
 }
 
+// ── Diagnostics output ────────────────────────────────────────────────────────
+
+/// Writes diagnostics to stderr, as pretty text or, with
+/// `--error-format json`, one JSON object per line. Status lines are left
+/// out in JSON mode so every line of stderr parses.
+struct Diagnostics<'a> {
+    json:   bool,
+    source: &'a str,
+    file:   &'a str,
+}
+
+impl Diagnostics<'_> {
+    fn error(&self, e: &tsuki_core::tsukiError) {
+        if self.json {
+            self.emit(e.to_json());
+        } else {
+            eprintln!("{}", e.pretty(self.source));
+        }
+    }
+
+    fn warnings(&self, warnings: &[tsuki_core::Warning]) {
+        for w in warnings {
+            if self.json { self.emit(w.to_json()) } else { eprintln!("{}", w.pretty(self.source)) }
+        }
+    }
+
+    fn text(&self, line: &str) {
+        if !self.json { eprintln!("{}", line) }
+    }
+
+    /// Diagnostics without a location still name the input file.
+    fn emit(&self, mut d: serde_json::Value) {
+        if d["file"].is_null() { d["file"] = self.file.into() }
+        eprintln!("{}", d);
+    }
+}
+
 // ── pkg subcommand handler ────────────────────────────────────────────────────
 
+/// `tsuki doc [package]`: what a package maps to, or the list of packages.
+fn handle_doc(args: &[String]) {
+    let libs_dir = flag_value(args, "--libs-dir")
+        .map(PathBuf::from)
+        .unwrap_or_else(default_libs_dir);
+    let pkg_names: Vec<String> = flag_value(args, "--packages")
+        .map(|s| s.split(',').map(|p| p.trim().to_owned()).filter(|s| !s.is_empty()).collect())
+        .unwrap_or_default();
+    let mut rt = Runtime::new();
+    rt.load_libs_in(&[&libs_dir], &pkg_names);
+
+    match args.get(2).filter(|a| !a.starts_with("--")) {
+        Some(name) => match rt.describe(name) {
+            Some(doc) => print!("{}", doc),
+            None => {
+                eprintln!("error: unknown package `{}`; `tsuki doc` lists the available ones", name);
+                std::process::exit(1);
+            }
+        },
+        None => {
+            println!("{:<16} {:>9}  HEADER", "PACKAGE", "FUNCTIONS");
+            for doc in rt.list_packages().into_iter().filter_map(|n| rt.describe(n)) {
+                println!("{:<16} {:>9}  {}", doc.name, doc.functions.len(), doc.header.as_deref().unwrap_or("-"));
+            }
+        }
+    }
+}
+
+fn handle_test(args: &[String]) {
+    // tsuki test [dir] [--run <name>] [-v] [--cxx <compiler>] [--keep <dir>]
+    let dir = args.get(2).filter(|a| !a.starts_with('-')).map(PathBuf::from).unwrap_or_else(|| ".".into());
+    let fail = |e: tsuki_core::tsukiError| -> ! {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    };
+    let files = gotest::package_files(&dir).unwrap_or_else(|e| fail(e));
+    let prog = gotest::transpile(&files).unwrap_or_else(|e| fail(e));
+    for w in &prog.warnings {
+        eprintln!("{}", w);
+    }
+
+    let keep = flag_value(args, "--keep").map(PathBuf::from);
+    let mut opts = gotest::HostOptions {
+        run:     flag_value(args, "--run"),
+        verbose: args.iter().any(|a| a == "-v"),
+        ..Default::default()
+    };
+    if let Some(cxx) = flag_value(args, "--cxx") { opts.cxx = cxx }
+    if let Some(dir) = &keep { opts.work_dir = dir.clone() }
+    let code = gotest::run(&prog, &opts).unwrap_or_else(|e| fail(e));
+    if keep.is_none() {
+        let _ = std::fs::remove_dir_all(&opts.work_dir);
+    }
+    std::process::exit(code);
+}
+
+fn handle_sim(args: &[String]) {
+    // tsuki sim <input.go> [--ms <n>] [--loops <n>] [--json] [--trace <file>] [--expect <golden>]
+    //           [--cxx <compiler>] [--keep <dir>]
+    //           [--libs-dir <path>] [--packages <n,...>] [--vendor-dir <path>]
+    let fail = |e: tsuki_core::tsukiError| -> ! {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    };
+    let Some(input) = args.get(2).filter(|a| !a.starts_with('-')) else {
+        eprintln!("usage: tsuki sim <input.go> [--ms <n>] [--loops <n>] [--json]");
+        std::process::exit(1);
+    };
+    let source = std::fs::read_to_string(input).unwrap_or_else(|e| {
+        eprintln!("error: cannot read {}: {}", input, e);
+        std::process::exit(1);
+    });
+    let number = |flag: &str| flag_value(args, flag).map(|s| s.parse::<u64>().unwrap_or_else(|_| {
+        eprintln!("error: {} expects a number, got `{}`", flag, s);
+        std::process::exit(1);
+    }));
+
+    let pipeline = PipelineOptions {
+        libs_dir:   flag_value(args, "--libs-dir").map(PathBuf::from),
+        vendor_dir: flag_value(args, "--vendor-dir").map(PathBuf::from),
+        pkg_names:  flag_value(args, "--packages")
+            .map(|s| s.split(',').map(|p| p.trim().to_owned()).filter(|s| !s.is_empty()).collect())
+            .unwrap_or_default(),
+    };
+    let prog = sim::transpile(&source, input, pipeline).unwrap_or_else(|e| fail(e));
+    for w in &prog.warnings {
+        eprintln!("{}", w);
+    }
+
+    let keep = flag_value(args, "--keep").map(PathBuf::from);
+    let mut opts = sim::SimOptions { json: args.iter().any(|a| a == "--json"), ..Default::default() };
+    if let Some(ms) = number("--ms") { opts.ms = ms }
+    if let Some(n) = number("--loops") { opts.max_loops = n }
+    if let Some(cxx) = flag_value(args, "--cxx") { opts.cxx = cxx }
+    if let Some(dir) = &keep { opts.work_dir = dir.clone() }
+    let expect = flag_value(args, "--expect").map(PathBuf::from);
+    opts.trace = flag_value(args, "--trace").map(PathBuf::from)
+        .or_else(|| expect.as_ref().map(|_| opts.work_dir.join("trace.json")));
+    let golden = expect.map(|p| sim::Trace::load(&p).unwrap_or_else(|e| fail(e)));
+
+    let mut code = sim::run(&prog, &opts).unwrap_or_else(|e| fail(e));
+    if let (0, Some(golden), Some(path)) = (code, &golden, &opts.trace) {
+        let diff = sim::Trace::load(path).unwrap_or_else(|e| fail(e)).diff(golden);
+        for d in &diff {
+            eprintln!("trace: {}", d);
+        }
+        if !diff.is_empty() { code = 1 }
+    }
+    if keep.is_none() {
+        let _ = std::fs::remove_dir_all(&opts.work_dir);
+    }
+    std::process::exit(code);
+}
+
 fn handle_pkg(args: &[String]) {
     // tsuki pkg <cmd> [args] [--libs-dir <path>] [--registry <url>]
     let subcmd = args.get(2).map(|s| s.as_str()).unwrap_or("");
@@ -123,11 +362,34 @@ fn handle_pkg(args: &[String]) {
     let registry_url = flag_value(args, "--registry")
         .unwrap_or_else(|| pkg_manager::DEFAULT_REGISTRY_URL.to_owned());
 
+    let keys_url = flag_value(args, "--keys")
+        .unwrap_or_else(|| pkg_manager::DEFAULT_KEYS_URL.to_owned());
+
+    let manifest = flag_value(args, "--manifest")
+        .map(PathBuf::from)
+        .or_else(|| Some(PathBuf::from("tsuki_package.json")).filter(|p| p.exists()));
+
+    let vendor_dir = flag_value(args, "--vendor-dir")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| {
+            let project = manifest.as_deref().and_then(|m| m.parent()).unwrap_or(Path::new(""));
+            project.join(pkg_manager::VENDOR_DIR)
+        });
+
+    // With --offline the vendored and installed packages stand in for the
+    // registry, and nothing is downloaded.
+    let offline = args.iter().any(|a| a == "--offline");
+    let registry = || if offline {
+        pkg_manager::local_registry(&[&vendor_dir, &libs_dir])
+    } else {
+        fetch_registry_or_exit(&registry_url)
+    };
+
     match subcmd {
         // ── list / search ─────────────────────────────────────────────────────
         "list" | "search" => {
-            let query = args.get(3).map(|s| s.as_str());
-            let registry = fetch_registry_or_exit(&registry_url);
+            let query = args.get(3).map(|s| s.as_str()).filter(|a| !a.starts_with("--"));
+            let registry = registry();
 
             let mut entries: Vec<(&String, &pkg_manager::RegistryEntry)> =
                 registry.packages.iter().collect();
@@ -138,7 +400,7 @@ fn handle_pkg(args: &[String]) {
                 return;
             }
 
-            println!("{:<20} {:<10} {}", "NAME", "LATEST", "DESCRIPTION");
+            println!("{:<20} {:<10} DESCRIPTION", "NAME", "LATEST");
             println!("{}", "-".repeat(70));
             for (name, entry) in &entries {
                 // filter by query if provided
@@ -157,13 +419,42 @@ fn handle_pkg(args: &[String]) {
 
         // ── install ───────────────────────────────────────────────────────────
         "install" | "add" => {
-            let pkg_arg = args.get(3).unwrap_or_else(|| {
+            let source = match (flag_value(args, "--path"), flag_value(args, "--git")) {
+                (Some(path), None) => Some(pkg_manager::LibSource::Path { path }),
+                (None, Some(git))  => Some(pkg_manager::LibSource::Git { git, tag: flag_value(args, "--tag") }),
+                (None, None)       => None,
+                (Some(_), Some(_)) => {
+                    eprintln!("tsuki pkg install: --path and --git are exclusive");
+                    std::process::exit(1);
+                }
+            };
+            if let Some(source) = source {
+                let deps = match pkg_manager::install_from(&source, &libs_dir) {
+                    Ok((msg, deps)) => { println!("{}", msg); deps }
+                    Err(e)          => { eprintln!("error: {}", e); std::process::exit(1); }
+                };
+                if deps.is_empty() {
+                    return;
+                }
+                let registry = registry();
+                let keys = fetch_keyring(&registry, &keys_url);
+                for (dep, range) in deps {
+                    match pkg_manager::install(&format!("{}@{}", dep, range), &libs_dir, &registry, &keys) {
+                        Ok(msg) => println!("{}", msg),
+                        Err(e)  => { eprintln!("error: {}", e); std::process::exit(1); }
+                    }
+                }
+                return;
+            }
+            let pkg_arg = args.get(3).filter(|a| !a.starts_with("--")).unwrap_or_else(|| {
                 eprintln!("tsuki pkg install: missing package name");
-                eprintln!("usage: tsuki pkg install <name>[@<version>]");
+                eprintln!("usage: tsuki pkg install <name>[@<version or range>]");
+                eprintln!("       tsuki pkg install --path <dir> | --git <url> [--tag <tag>]");
                 std::process::exit(1);
             });
-            let registry = fetch_registry_or_exit(&registry_url);
-            match pkg_manager::install(pkg_arg, &libs_dir, &registry) {
+            let registry = registry();
+            let keys = fetch_keyring(&registry, &keys_url);
+            match pkg_manager::install(pkg_arg, &libs_dir, &registry, &keys) {
                 Ok(msg) => println!("{}", msg),
                 Err(e)  => { eprintln!("error: {}", e); std::process::exit(1); }
             }
@@ -184,8 +475,16 @@ fn handle_pkg(args: &[String]) {
 
         // ── update ────────────────────────────────────────────────────────────
         "update" | "upgrade" => {
-            let registry = fetch_registry_or_exit(&registry_url);
-            match pkg_manager::update_all(&libs_dir, &registry) {
+            let registry = registry();
+            let keys = fetch_keyring(&registry, &keys_url);
+            let pins = match &manifest {
+                Some(path) => pkg_manager::project_pins(path).unwrap_or_else(|e| {
+                    eprintln!("error: {}", e);
+                    std::process::exit(1);
+                }),
+                None => Default::default(),
+            };
+            match pkg_manager::update_all(&libs_dir, &registry, &keys, &pins) {
                 Ok(msgs) => {
                     if msgs.is_empty() {
                         println!("tsuki: no packages installed");
@@ -197,16 +496,58 @@ fn handle_pkg(args: &[String]) {
             }
         }
 
+        // ── verify ────────────────────────────────────────────────────────────
+        "verify" => {
+            if offline {
+                eprintln!("tsuki pkg verify: checks against the registry, so it cannot run --offline");
+                std::process::exit(1);
+            }
+            let registry = registry();
+            let keys = fetch_keyring(&registry, &keys_url);
+            let results = pkg_manager::verify_installed(&libs_dir, &registry, &keys);
+            if results.is_empty() {
+                println!("tsuki: no packages installed");
+            }
+            let mut failed = 0;
+            for r in results {
+                match r {
+                    Ok(msg) => println!("ok    {}", msg),
+                    Err(e)  => { println!("FAIL  {}", e.message()); failed += 1; }
+                }
+            }
+            if failed > 0 {
+                eprintln!("tsuki: {} package(s) failed verification", failed);
+                std::process::exit(1);
+            }
+        }
+
+        // ── vendor ────────────────────────────────────────────────────────────
+        "vendor" => {
+            let Some(manifest) = &manifest else {
+                eprintln!("tsuki pkg vendor: no tsuki_package.json here (--manifest <path> to name one)");
+                std::process::exit(1);
+            };
+            let result = pkg_manager::project_packages(manifest)
+                .and_then(|pkgs| pkg_manager::vendor(&pkgs, &libs_dir, &vendor_dir));
+            match result {
+                Ok(msgs) if msgs.is_empty() => println!("tsuki: {} declares no packages", manifest.display()),
+                Ok(msgs) => for m in msgs { println!("{}", m); },
+                Err(e)   => { eprintln!("error: {}", e); std::process::exit(1); }
+            }
+        }
+
         // ── installed ─────────────────────────────────────────────────────────
         "installed" | "ls" => {
             let pkgs = pkg_manager::list_installed(&libs_dir);
             if pkgs.is_empty() {
                 println!("tsuki: no packages installed (libs-dir: {})", libs_dir.display());
             } else {
-                println!("{:<20} {}", "NAME", "VERSION");
-                println!("{}", "-".repeat(32));
+                println!("{:<20} {:<10} SOURCE", "NAME", "VERSION");
+                println!("{}", "-".repeat(70));
                 for (name, ver) in &pkgs {
-                    println!("{:<20} {}", name, ver);
+                    let source = pkg_manager::installed_source(&libs_dir, name, ver)
+                        .map_or_else(|| "registry".to_owned(), |s| s.to_string());
+                    println!("{:<20} {:<10} {}", name, ver, source);
                 }
             }
         }
@@ -217,7 +558,7 @@ fn handle_pkg(args: &[String]) {
                 eprintln!("tsuki pkg info: missing package name");
                 std::process::exit(1);
             });
-            let registry = fetch_registry_or_exit(&registry_url);
+            let registry = registry();
             match registry.packages.get(pkg_arg.as_str()) {
                 None => {
                     eprintln!("tsuki pkg info: '{}' not found in registry", pkg_arg);
@@ -228,10 +569,92 @@ fn handle_pkg(args: &[String]) {
                     println!("Latest:      {}", entry.latest);
                     if let Some(d) = &entry.description { println!("Description: {}", d); }
                     if let Some(a) = &entry.author      { println!("Author:      {}", a); }
-                    let mut vers: Vec<&String> = entry.versions.keys().collect();
-                    vers.sort();
-                    println!("Versions:    {}", vers.iter().map(|s| s.as_str()).collect::<Vec<_>>().join(", "));
+                    println!("Versions:    {}", pkg_manager::sorted_versions(entry).join(", "));
+                }
+            }
+        }
+
+        // ── lint ──────────────────────────────────────────────────────────────
+        "lint" => {
+            let mut path = args.get(3)
+                .filter(|a| !a.starts_with("--"))
+                .map(PathBuf::from)
+                .unwrap_or_else(|| PathBuf::from("tsukilib.toml"));
+            if path.is_dir() {
+                path.push("tsukilib.toml");
+            }
+            let json = match flag_value(args, "--error-format").as_deref() {
+                None | Some("human") => false,
+                Some("json")         => true,
+                Some(other) => {
+                    eprintln!("tsuki pkg lint: --error-format expects `human` or `json`, got `{}`", other);
+                    std::process::exit(1);
+                }
+            };
+            let source = std::fs::read_to_string(&path).unwrap_or_else(|e| {
+                eprintln!("tsuki pkg lint: cannot read {}: {}", path.display(), e);
+                std::process::exit(1);
+            });
+            let file = path.display().to_string();
+            let diag = Diagnostics { json, source: &source, file: &file };
+            let lint = tsuki_core::runtime::pkg_lint::lint(&source, &path);
+            for e in &lint.errors {
+                diag.error(e);
+            }
+            diag.warnings(&lint.warnings);
+            diag.text(&format!("{}: {} error(s), {} warning(s)", file, lint.errors.len(), lint.warnings.len()));
+            if !lint.errors.is_empty() {
+                std::process::exit(1);
+            }
+        }
+
+        // ── publish ───────────────────────────────────────────────────────────
+        "publish" => {
+            let manifest = args.get(3)
+                .filter(|a| !a.starts_with("--"))
+                .map(PathBuf::from)
+                .unwrap_or_else(|| PathBuf::from("tsukilib.toml"));
+            let bump = flag_value(args, "--bump").map(|b| b.parse::<pkg_manager::Bump>().unwrap_or_else(|e| {
+                eprintln!("tsuki pkg publish: {}", e);
+                std::process::exit(1);
+            }));
+            let url_template = flag_value(args, "--url-template")
+                .unwrap_or_else(|| pkg_manager::DEFAULT_PACKAGE_URL.to_owned());
+            let registry_dir = flag_value(args, "--registry-dir").map(PathBuf::from);
+            let push_cmd     = flag_value(args, "--push-cmd");
+            let signer = flag_value(args, "--sign-key").map(|path| {
+                let path = PathBuf::from(path);
+                let key_id = flag_value(args, "--key-id").unwrap_or_else(|| {
+                    path.file_stem().unwrap_or_default().to_string_lossy().into_owned()
+                });
+                pkg_manager::Signer::from_file(&key_id, &path).unwrap_or_else(|e| {
+                    eprintln!("tsuki pkg publish: {}", e);
+                    std::process::exit(1);
+                })
+            });
+            if push_cmd.is_some() && registry_dir.is_none() {
+                eprintln!("tsuki pkg publish: --push-cmd needs --registry-dir");
+                std::process::exit(1);
+            }
+
+            let published = pkg_manager::publish(&manifest, bump, &url_template, registry_dir.as_deref(), signer.as_ref())
+                .unwrap_or_else(|e| {
+                    eprintln!("tsuki pkg publish: {}", e);
+                    std::process::exit(1);
+                });
+            match &published.copied {
+                Some(dest) => println!("recorded {}@{} → {}", published.name, published.version, dest.display()),
+                None => {
+                    println!("registry.json entry for {}@{}:", published.name, published.version);
+                    println!("{}", published.snippet);
+                }
+            }
+            if let (Some(cmd), Some(dir)) = (push_cmd, registry_dir) {
+                if let Err(e) = pkg_manager::push(&cmd, &dir, &published) {
+                    eprintln!("tsuki pkg publish: {}", e);
+                    std::process::exit(1);
                 }
+                println!("pushed {}@{}", published.name, published.version);
             }
         }
 
@@ -254,6 +677,18 @@ fn fetch_registry_or_exit(url: &str) -> pkg_manager::Registry {
     }
 }
 
+/// Signing keys, fetched only when the registry has signed versions. Without
+/// them those versions fail to install rather than install unchecked.
+fn fetch_keyring(registry: &pkg_manager::Registry, url: &str) -> pkg_manager::Keyring {
+    if !registry.has_signatures() {
+        return pkg_manager::Keyring::default();
+    }
+    pkg_manager::Keyring::fetch(url).unwrap_or_else(|e| {
+        eprintln!("tsuki: warning: cannot load signing keys: {}", e);
+        pkg_manager::Keyring::default()
+    })
+}
+
 fn flag_value(args: &[String], flag: &str) -> Option<String> {
     args.windows(2).find(|w| w[0] == flag).map(|w| w[1].clone())
 }
@@ -265,19 +700,57 @@ r#"tsuki {} — Go-to-Arduino C++ transpiler
 USAGE:
     tsuki <input.go> [output.cpp] [FLAGS]
     tsuki pkg <command> [args]
+    tsuki doc [package] [--libs-dir <path>] [--packages <n,...>]
+    tsuki test [dir] [--run <name>] [-v] [--cxx <compiler>] [--keep <dir>]
+    tsuki sim <input.go> [--ms <n>] [--loops <n>] [--json] [--trace <file>]
+              [--expect <golden>] [--cxx <compiler>] [--keep <dir>]
 
 FLAGS:
     --board <id>           Target board (default: uno)
+    --cpu-mhz <n>          CPU clock override (F_CPU / ESP32 frequency)
     --source-map           Emit #line pragmas for IDE source mapping
-    --check                Validate source only (no output produced)
+    --progmem-min <bytes>  AVR: keep string literals this long in flash
+                           (F() in Serial prints, PROGMEM string tables)
+    --no-split-main        Keep main()'s trailing endless `for` in setup()
+                           instead of turning its body into loop()
+    --keep-all             Emit unreferenced functions, constants and types
+                           too (no dead code elimination)
+    --check                Validate source only (no output produced) and
+                           print a flash/RAM estimate for the board; all
+                           parse errors of the file are reported at once,
+                           but lexing still stops at the first bad token
+    --max-usage <pct>      With --check, fail when the estimate exceeds this
+                           share of flash or RAM (default: 100)
+    --error-format <fmt>   `human` (default) or `json`: one JSON object per
+                           diagnostic on stderr (file, span, severity,
+                           message, suggestion)
+    --header <path>        Also write the package's declarations to <path>
+    --local-packages <n,...>  Workspace packages transpiled next to this file
+                           (calls are mangled, `<pkg>.h` is included)
+    --prelude-dir <path>   Project copies of prelude files replacing the
+                           shipped ones (must match the prelude version)
+    --dump-prelude <path>  Write the shipped prelude files to <path> and exit
     --libs-dir <path>      Root directory of installed tsukilib packages
     --packages <n,...>     Comma-separated package names to load from libs-dir
+                           (name@version for a specific installed version)
+    --vendor-dir <path>    Project's vendored packages (`tsuki pkg vendor`),
+                           searched before libs-dir
     --version              Print version
     --help                 Print this help
 
 COMMANDS:
     tsuki boards        List supported boards
+    tsuki doc [pkg]     List the packages, or a package's functions,
+                        constants and types with the C++ they become
     tsuki pkg ...       Package manager (see `tsuki pkg --help`)
+    tsuki test [dir]    Run the TestXxx functions of the package's _test.go
+                        files on this machine (g++), with the Arduino calls
+                        simulated; --run keeps tests whose name contains it
+    tsuki sim <file>    Run the sketch on this machine (g++) for --ms of
+                        simulated time (default: 10000), printing pin changes
+                        and Serial output; --json prints them as JSON lines,
+                        --trace writes them to a JSON file, --expect fails
+                        when they differ from a golden trace file
 
 EXAMPLES:
     tsuki src/main.go build/main.cpp --board esp32
@@ -302,10 +775,38 @@ COMMANDS:
     list                   List all packages in the registry
     search <query>         Search packages by name or description
     info   <name>          Show details for a registry package
-    install <name>[@<ver>] Install a package (latest if version omitted)
+    install <name>[@<ver>] Install a package (latest if version omitted);
+                           <ver> may be a range: 1, ^1.2, ~1.2.3, >=1 <2
+    install --path <dir>   Install from a local package directory
+    install --git <url> [--tag <tag>]
+                           Install from a git repository (default branch
+                           unless --tag); update follows path and branch
+                           installs, tags stay put
     remove  <name>[@<ver>] Remove an installed package
-    update                 Update all installed packages to latest
+    update                 Update all installed packages to latest, within
+                           the ranges pinned in tsuki_package.json if present
     installed              List locally installed packages
+    verify                 Re-check installed packages against the
+                           registry's checksums and signatures
+    publish [toml]         Validate a tsukilib.toml (default ./tsukilib.toml)
+                           and print or record its registry entry
+    lint [toml]            Check a tsukilib.toml (default ./tsukilib.toml)
+                           for template, header and naming mistakes
+                           (--error-format json for editors and CI)
+    vendor                 Copy the project's packages and their
+                           dependencies from libs-dir into vendor/
+
+PUBLISH FLAGS:
+    --bump <part>          Bump major, minor or patch in the TOML first
+    --url-template <url>   Download URL, with {{name}} and {{version}}
+                           (default: the tsuki-pkgs repository layout)
+    --registry-dir <path>  Registry checkout: copy the TOML to
+                           <name>/v<version>/ and update registry.json
+    --push-cmd <cmd>       Shell command run in the registry checkout
+                           afterwards (TSUKI_PKG_NAME, TSUKI_PKG_VERSION set)
+    --sign-key <path>      Sign with this ed25519 secret key (PEM)
+    --key-id <id>          Key id recorded with the signature
+                           (default: the key file's name)
 
 FLAGS:
     --libs-dir <path>      Override install directory
@@ -313,12 +814,20 @@ FLAGS:
     --registry <url>       Override registry URL
                            (default: https://raw.githubusercontent.com/
                             s7lver/tsuki-pkgs/main/registry.json)
+    --keys <url>           Override the signing-key index URL
+                           (default: keys/index.json next to the registry)
+    --manifest <path>      Project file for update and vendor
+                           (default: ./tsuki_package.json)
+    --vendor-dir <path>    Vendor directory (default: vendor/ next to the
+                           project file)
+    --offline              No network: list, info, install and update use the
+                           packages in vendor/ and libs-dir only
 "#);
 }
 
 
 fn print_boards() {
-    println!("{:<15} {:<30} {:<15} {:>7} {:>6}  {}", "ID", "NAME", "CPU", "FLASH", "RAM", "FQBN");
+    println!("{:<15} {:<30} {:<15} {:>7} {:>6}  FQBN", "ID", "NAME", "CPU", "FLASH", "RAM");
     println!("{}", "-".repeat(100));
     for b in Board::catalog() {
         println!("{:<15} {:<30} {:<15} {:>5}K  {:>4}K  {}",
@@ -32,6 +32,8 @@ pub enum Type {
 
     // User-defined or qualified (pkg.Name)
     Named(String),
+    /// Instantiated generic type: `Pair[int, string]`.
+    Generic { name: String, args: Vec<Type> },
 
     // Used internally
     Void,
@@ -48,6 +50,23 @@ pub struct Field {
     pub tag:  Option<String>,
 }
 
+impl Field {
+    /// Go field name; embedded fields are named after their (unqualified) type.
+    pub fn go_name(&self) -> &str {
+        if let Some(n) = &self.name { return n; }
+        match &self.ty {
+            Type::Ptr(inner) => match inner.as_ref() {
+                Type::Named(n) => n.rsplit('.').next().unwrap_or(n),
+                _ => "_",
+            },
+            Type::Named(n) => n.rsplit('.').next().unwrap_or(n),
+            _ => "_",
+        }
+    }
+
+    pub fn is_embedded(&self) -> bool { self.name.is_none() }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Method {
     pub name: String,
@@ -55,6 +74,14 @@ pub struct Method {
 }
 
 impl Type {
+    /// The declared name of a `Named` or `Generic` type.
+    pub fn named(&self) -> Option<&str> {
+        match self {
+            Type::Named(n) | Type::Generic { name: n, .. } => Some(n),
+            _ => None,
+        }
+    }
+
     /// Emit the equivalent C++ type string for Arduino / AVR-GCC.
     pub fn to_cpp(&self) -> String {
         match self {
@@ -78,7 +105,12 @@ impl Type {
             Type::Slice(elem)      => format!("{}*", elem.to_cpp()),
             Type::Array { len: Some(n), elem } => format!("{} /* [{}] */", elem.to_cpp(), n),
             Type::Array { len: None,    elem } => format!("{}*", elem.to_cpp()),
-            Type::Named(n)         => n.split('.').last().unwrap_or(n).to_owned(),
+            Type::Named(n) if n == "error" => "tsuki_error".into(),
+            // RTClib's date type, see the `rtc` package.
+            Type::Named(n) if n == "time.Time" => "DateTime".into(),
+            Type::Named(n)         => n.rsplit('.').next().unwrap_or(n).to_owned(),
+            Type::Generic { name, args } => format!("{}<{}>", name,
+                args.iter().map(Type::to_cpp).collect::<Vec<_>>().join(", ")),
             Type::Infer            => "auto".into(),
             _                      => "void* /* unsupported */".into(),
         }
@@ -99,6 +131,8 @@ pub enum Expr {
 
     // Name
     Ident  { name: String, span: Span },
+    /// Explicit instantiation of a generic function: `Max[int]`.
+    Inst   { name: String, args: Vec<Type>, span: Span },
 
     // Operations
     Binary { op: BinOp, lhs: Box<Expr>, rhs: Box<Expr>, span: Span },
@@ -189,12 +223,15 @@ pub enum Stmt {
     Continue { label: Option<String>,   span: Span },
     Goto     { label: String,           span: Span },
     Label    { name:  String,           span: Span },
+    Fallthrough { span: Span },
 
     // Structured control
     If     { init: Option<Box<Stmt>>, cond: Expr, then: Block, else_: Option<Box<Stmt>>, span: Span },
     For    { init: Option<Box<Stmt>>, cond: Option<Expr>, post: Option<Box<Stmt>>, body: Block, span: Span },
     Range  { key: Option<String>, val: Option<String>, iter: Expr, body: Block, span: Span },
     Switch { init: Option<Box<Stmt>>, tag: Option<Expr>, cases: Vec<SwitchCase>, span: Span },
+    /// `switch v := x.(type) { case T: ... }` — `bind` is the optional `v`.
+    TypeSwitch { init: Option<Box<Stmt>>, bind: Option<String>, expr: Expr, cases: Vec<TypeCase>, span: Span },
 
     // Concurrency (mapped or stubbed on Arduino)
     Defer { call: Expr, span: Span },
@@ -214,6 +251,20 @@ pub struct SwitchCase {
     pub span:  Span,
 }
 
+impl SwitchCase {
+    /// True when the clause ends in `fallthrough`.
+    pub fn falls_through(&self) -> bool {
+        matches!(self.body.last(), Some(Stmt::Fallthrough { .. }))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TypeCase {
+    pub types: Vec<Type>,  // empty ⇒ default
+    pub body:  Vec<Stmt>,
+    pub span:  Span,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum AssignOp {
     Plain,
@@ -254,29 +305,57 @@ pub enum Decl {
     Func {
         name:     String,
         recv:     Option<FuncParam>,
+        /// Type parameters: `func Map[T, U any](...)`.
+        tparams:  Vec<TypeParam>,
         sig:      FuncSig,
         body:     Option<Block>,
+        /// `//export`, `//ns:name` comment directives directly above the func.
+        directives: Vec<Directive>,
         span:     Span,
     },
     TypeDef  { name: String, ty: Type,         span: Span },
-    StructDef{ name: String, fields: Vec<Field>, span: Span },
+    StructDef{ name: String, tparams: Vec<TypeParam>, fields: Vec<Field>, span: Span },
     Var      { name: String, ty: Option<Type>, init: Option<Expr>, span: Span },
     Const    { name: String, ty: Option<Type>, val:  Expr,         span: Span },
 }
 
+/// A type parameter. The constraint is limited to `any` or `comparable`.
+#[derive(Debug, Clone)]
+pub struct TypeParam {
+    pub name:       String,
+    pub constraint: Type,
+}
+
+/// A comment directive such as `//export Init` → name `export`, args `["Init"]`.
+#[derive(Debug, Clone)]
+pub struct Directive {
+    pub name: String,
+    pub args: Vec<String>,
+    pub span: Span,
+}
+
+impl Directive {
+    pub fn parse(text: &str, span: Span) -> Self {
+        let mut words = text.split_whitespace().map(str::to_owned);
+        let name = words.next().unwrap_or_default();
+        Self { name, args: words.collect(), span }
+    }
+}
+
 // ── Import ────────────────────────────────────────────────────────────────────
 
 #[derive(Debug, Clone)]
 pub struct Import {
     pub alias: Option<String>,
     pub path:  String,
+    pub span:  Span,
 }
 
 impl Import {
     /// The local name used to reference this package in Go source.
     pub fn local_name(&self) -> &str {
         if let Some(a) = &self.alias { return a.as_str(); }
-        self.path.split('/').last().unwrap_or(&self.path)
+        self.path.rsplit('/').next().unwrap_or(&self.path)
     }
 }
 
@@ -6,7 +6,9 @@
 pub mod ast;
 pub use ast::*;
 
-use crate::error::{tsukiError, Result, Span};
+use std::collections::HashSet;
+
+use crate::error::{tsukiError, Result, Span, Suggestion};
 use crate::lexer::token::{Token, TokenKind};
 
 // ─────────────────────────────────────────────────────────────────────────────
@@ -14,6 +16,16 @@ use crate::lexer::token::{Token, TokenKind};
 pub struct Parser {
     tokens: Vec<Token>,
     pos:    usize,
+    /// Set while parsing an `if`/`for`/`switch` header, where `Name {`
+    /// opens the statement body instead of a composite literal.
+    no_lit: bool,
+    /// Comment directives, keyed by the index of the token they precede.
+    directives: Vec<(usize, Directive)>,
+    /// Generic funcs and types declared in this file, so `Name[` can be
+    /// read as an instantiation rather than an index expression.
+    generics: HashSet<String>,
+    /// Syntax errors recovered from so far, in source order.
+    errors: Vec<tsukiError>,
 }
 
 // ── Internal helpers ──────────────────────────────────────────────────────────
@@ -22,7 +34,16 @@ impl Parser {
     pub fn new(mut tokens: Vec<Token>) -> Self {
         // Drop newlines — we don't implement full Go ASI (simplified)
         tokens.retain(|t| !matches!(t.kind, TokenKind::Newline));
-        Self { tokens, pos: 0 }
+        let mut directives = Vec::new();
+        let mut kept = Vec::with_capacity(tokens.len());
+        for t in tokens {
+            match t.kind {
+                TokenKind::Directive(text) => directives.push((kept.len(), Directive::parse(&text, t.span))),
+                _ => kept.push(t),
+            }
+        }
+        let generics = generic_names(&kept);
+        Self { tokens: kept, pos: 0, no_lit: false, directives, generics, errors: Vec::new() }
     }
 
     fn peek(&self) -> &Token {
@@ -31,6 +52,10 @@ impl Parser {
 
     fn peek_kind(&self) -> &TokenKind { &self.peek().kind }
 
+    fn kind_at(&self, off: usize) -> &TokenKind {
+        &self.tokens[(self.pos + off).min(self.tokens.len().saturating_sub(1))].kind
+    }
+
     fn span(&self) -> Span { self.peek().span.clone() }
 
     fn advance(&mut self) -> &Token {
@@ -72,6 +97,22 @@ impl Parser {
 
     fn eof(&self) -> bool { self.peek_kind() == &TokenKind::EOF }
 
+    /// Run `f` with composite literals disabled (statement headers).
+    fn in_header<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        let prev = std::mem::replace(&mut self.no_lit, true);
+        let r = f(self);
+        self.no_lit = prev;
+        r
+    }
+
+    /// Run `f` with composite literals re-enabled (inside brackets).
+    fn nested<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        let prev = std::mem::replace(&mut self.no_lit, false);
+        let r = f(self);
+        self.no_lit = prev;
+        r
+    }
+
     // lookahead: is token at offset `off` a type-start?
     fn is_type_start_at(&self, off: usize) -> bool {
         let idx = (self.pos + off).min(self.tokens.len().saturating_sub(1));
@@ -86,7 +127,37 @@ impl Parser {
 // ── Public entry ──────────────────────────────────────────────────────────────
 
 impl Parser {
+    /// Parse the file, failing with its first syntax error.
     pub fn parse_program(&mut self) -> Result<Program> {
+        self.parse_program_all().map_err(|mut errors| errors.remove(0))
+    }
+
+    /// Parse a Go type written on its own, e.g. `[]byte` in a tsukilib.toml
+    /// signature.
+    pub fn parse_type_only(&mut self) -> Result<Type> {
+        let ty = self.parse_type()?;
+        self.eat(&TokenKind::Semicolon);
+        if !self.eof() {
+            return Err(tsukiError::parse(self.span(), format!("unexpected `{:?}` after the type", self.peek_kind())));
+        }
+        Ok(ty)
+    }
+
+    /// Parse the file, recovering after each syntax error so that one pass
+    /// reports all of them (in source order). A broken statement is skipped
+    /// up to the next line or the end of its block; a broken declaration up
+    /// to the next `func`/`type`/`var`/`const` starting a line.
+    pub fn parse_program_all(&mut self) -> std::result::Result<Program, Vec<tsukiError>> {
+        let prog = self.parse_file();
+        let mut errors = std::mem::take(&mut self.errors);
+        match prog {
+            Ok(prog) if errors.is_empty() => Ok(prog),
+            Ok(_)  => Err(errors),
+            Err(e) => { errors.push(e); Err(errors) }
+        }
+    }
+
+    fn parse_file(&mut self) -> Result<Program> {
         self.expect(&TokenKind::KwPackage)?;
         let package = self.expect_ident()?;
 
@@ -97,12 +168,55 @@ impl Parser {
 
         let mut decls = Vec::new();
         while !self.eof() {
-            decls.push(self.parse_top_decl()?);
+            match self.parse_top_decl() {
+                Ok(d)  => decls.push(d),
+                Err(e) => { self.record(e); self.sync_decl(); }
+            }
         }
 
         Ok(Program { package, imports, decls })
     }
 
+    // ── Error recovery ────────────────────────────────────────────────────────
+
+    /// Keep a recovered error. A failure at the same spot as the last one
+    /// (say, several blocks left open at EOF) is the same mistake.
+    fn record(&mut self, e: tsukiError) {
+        if self.errors.last().and_then(|l| l.span()) != e.span() || e.span().is_none() {
+            self.errors.push(e);
+        }
+    }
+
+    /// Skip the rest of a broken statement: stop at the first token on a
+    /// later line than the failure, outside any braces opened meanwhile, or
+    /// at the `}` closing the enclosing block.
+    fn sync_stmt(&mut self) {
+        let (start, line) = (self.pos, self.peek().span.line);
+        let mut depth = 0usize;
+        while !self.eof() {
+            let t = self.peek();
+            match t.kind {
+                TokenKind::RBrace if depth == 0 => break,
+                _ if depth == 0 && t.span.line > line && self.pos > start => break,
+                TokenKind::LBrace => depth += 1,
+                TokenKind::RBrace => depth -= 1,
+                _ => {}
+            }
+            self.advance();
+        }
+    }
+
+    /// Skip to the next top-level declaration keyword at the start of a line.
+    fn sync_decl(&mut self) {
+        let start = self.pos;
+        while !self.eof() {
+            let t = self.peek();
+            let top = matches!(t.kind, TokenKind::KwFunc | TokenKind::KwType | TokenKind::KwVar | TokenKind::KwConst);
+            if top && t.span.col == 1 && self.pos > start { break }
+            self.advance();
+        }
+    }
+
     // ── Imports ───────────────────────────────────────────────────────────────
 
     fn parse_imports(&mut self) -> Result<Vec<Import>> {
@@ -120,8 +234,9 @@ impl Parser {
     }
 
     fn parse_import_spec(&mut self) -> Result<Import> {
+        let span = self.span();
         let alias = match self.peek_kind().clone() {
-            TokenKind::Ident(s) if !matches!(self.tokens.get(self.pos + 1).map(|t| &t.kind),
+            TokenKind::Ident(_) if !matches!(self.tokens.get(self.pos + 1).map(|t| &t.kind),
                 Some(TokenKind::LitString(_)) | None) => None,
             TokenKind::Ident(s) => { self.advance(); Some(s) }
             TokenKind::Dot      => { self.advance(); Some(".".into()) }
@@ -131,7 +246,7 @@ impl Parser {
             TokenKind::LitString(s) => { self.advance(); s }
             _ => return Err(tsukiError::parse(self.span(), "expected import path string")),
         };
-        Ok(Import { alias, path })
+        Ok(Import { alias, path, span })
     }
 
     // ── Top-level declarations ────────────────────────────────────────────────
@@ -142,6 +257,16 @@ impl Parser {
             TokenKind::KwType  => self.parse_type_decl(),
             TokenKind::KwVar   => self.parse_var_decl_top(),
             TokenKind::KwConst => self.parse_const_decl_top(),
+            TokenKind::Ident(name) if self.kind_at(1) == &TokenKind::DeclAssign => {
+                // `x := 1` at package level: Go only allows `var` here.
+                let (at, op) = (self.span(), self.tokens[self.pos + 1].span.clone());
+                let err = tsukiError::parse(at.clone(), "`:=` outside a function body");
+                Err(match op.line == at.line {
+                    true => err.with_suggestion(Suggestion::replace(
+                        "declare it with `var`", (op.col + 2 - at.col) as usize, format!("var {} =", name))),
+                    false => err.with_suggestion(Suggestion::note("declare it with `var`")),
+                })
+            }
             _ => Err(tsukiError::parse(
                 self.span(),
                 format!("unexpected top-level token `{:?}`", self.peek_kind()),
@@ -153,6 +278,10 @@ impl Parser {
 
     fn parse_func_decl(&mut self) -> Result<Decl> {
         let span = self.span();
+        let directives = self.directives.iter()
+            .filter(|(at, _)| *at == self.pos)
+            .map(|(_, d)| d.clone())
+            .collect();
         self.expect(&TokenKind::KwFunc)?;
 
         let recv = if self.eat(&TokenKind::LParen) {
@@ -164,11 +293,22 @@ impl Parser {
             Some(FuncParam { name, ty, variadic: false })
         } else { None };
 
+        let name_at = self.span();
         let name = self.expect_ident()?;
+        let tparams = if self.at(&TokenKind::LBracket) { self.parse_type_params()? } else { vec![] };
+        if self.at(&TokenKind::LBrace) {
+            let after = Span {
+                col:    name_at.col + name.chars().count() as u32,
+                offset: name_at.offset + name.len(),
+                ..name_at
+            };
+            return Err(tsukiError::parse(after, format!("missing parameter list after `func {}`", name))
+                .with_suggestion(Suggestion::replace("functions without parameters still need `()`", 0, "()")));
+        }
         let sig  = self.parse_func_sig()?;
         let body = if self.at(&TokenKind::LBrace) { Some(self.parse_block()?) } else { None };
 
-        Ok(Decl::Func { name, recv, sig, body, span })
+        Ok(Decl::Func { name, recv, tparams, sig, body, directives, span })
     }
 
     fn parse_func_sig(&mut self) -> Result<FuncSig> {
@@ -213,26 +353,87 @@ impl Parser {
         let span = self.span();
         self.expect(&TokenKind::KwType)?;
         let name = self.expect_ident()?;
+        let tparams = if self.generics.contains(&name) { self.parse_type_params()? } else { vec![] };
         if self.at(&TokenKind::KwStruct) {
             self.advance();
-            self.expect(&TokenKind::LBrace)?;
-            let mut fields = Vec::new();
-            while !self.at(&TokenKind::RBrace) && !self.eof() {
-                let fname = self.expect_ident()?;
-                let fty   = self.parse_type()?;
-                let tag   = if let TokenKind::LitString(s) = self.peek_kind().clone() {
-                    self.advance(); Some(s)
-                } else { None };
-                fields.push(Field { name: Some(fname), ty: fty, tag });
-            }
-            self.expect(&TokenKind::RBrace)?;
-            Ok(Decl::StructDef { name, fields, span })
+            let fields = self.parse_struct_fields()?;
+            Ok(Decl::StructDef { name, tparams, fields, span })
+        } else if !tparams.is_empty() {
+            Err(tsukiError::parse(span, format!(
+                "generic type {}: only struct types can have type parameters", name)))
         } else {
             let ty = self.parse_type()?;
             Ok(Decl::TypeDef { name, ty, span })
         }
     }
 
+    /// `[K comparable, V any]` or `[A, B any]`.
+    fn parse_type_params(&mut self) -> Result<Vec<TypeParam>> {
+        self.expect(&TokenKind::LBracket)?;
+        let mut params = Vec::new();
+        let mut pending = Vec::new();
+        while !self.at(&TokenKind::RBracket) && !self.eof() {
+            let span = self.span();
+            pending.push(self.expect_ident()?);
+            if self.eat(&TokenKind::Comma) { continue; }
+            let constraint = self.parse_type()?;
+            match &constraint {
+                Type::Named(n) if n == "any" || n == "comparable" => {}
+                Type::Iface(methods) if methods.is_empty() => {}
+                _ => return Err(tsukiError::parse(span, format!(
+                    "unsupported constraint `{}` on {}: only `any` and `comparable` are allowed",
+                    constraint.to_cpp(), pending.join(", ")))),
+            }
+            params.extend(pending.drain(..).map(|name| TypeParam { name, constraint: constraint.clone() }));
+            if !self.eat(&TokenKind::Comma) { break; }
+        }
+        if !pending.is_empty() {
+            return Err(tsukiError::parse(self.span(), "type parameter list is missing a constraint"));
+        }
+        self.expect(&TokenKind::RBracket)?;
+        Ok(params)
+    }
+
+    /// `[int, string]` after a generic name.
+    fn parse_type_args(&mut self) -> Result<Vec<Type>> {
+        self.expect(&TokenKind::LBracket)?;
+        let mut args = Vec::new();
+        while !self.at(&TokenKind::RBracket) && !self.eof() {
+            args.push(self.nested(|p| p.parse_type())?);
+            if !self.eat(&TokenKind::Comma) { break; }
+        }
+        self.expect(&TokenKind::RBracket)?;
+        Ok(args)
+    }
+
+    /// `{ Name Type "tag"; Embedded; *Embedded; pkg.Embedded }`
+    fn parse_struct_fields(&mut self) -> Result<Vec<Field>> {
+        self.expect(&TokenKind::LBrace)?;
+        let mut fields = Vec::new();
+        while !self.at(&TokenKind::RBrace) && !self.eof() {
+            // Newlines are gone, so an embedded field is told apart from
+            // `Name Type` by what follows the type name on the same line.
+            let line = self.peek().span.line;
+            let next = &self.tokens[(self.pos + 1).min(self.tokens.len() - 1)];
+            let embedded = self.at(&TokenKind::Star)
+                || matches!(next.kind, TokenKind::Dot | TokenKind::RBrace
+                    | TokenKind::LitString(_) | TokenKind::Semicolon)
+                || next.span.line != line;
+            let (name, ty) = if embedded {
+                (None, self.parse_type()?)
+            } else {
+                (Some(self.expect_ident()?), self.parse_type()?)
+            };
+            let tag = if let TokenKind::LitString(s) = self.peek_kind().clone() {
+                self.advance(); Some(s)
+            } else { None };
+            fields.push(Field { name, ty, tag });
+            while self.eat(&TokenKind::Semicolon) {}
+        }
+        self.expect(&TokenKind::RBrace)?;
+        Ok(fields)
+    }
+
     fn parse_var_decl_top(&mut self) -> Result<Decl> {
         let span = self.span();
         self.expect(&TokenKind::KwVar)?;
@@ -318,15 +519,7 @@ impl Parser {
             // Struct (inline)
             TokenKind::KwStruct => {
                 self.advance();
-                self.expect(&TokenKind::LBrace)?;
-                let mut fields = Vec::new();
-                while !self.at(&TokenKind::RBrace) && !self.eof() {
-                    let n = self.expect_ident()?;
-                    let t = self.parse_type()?;
-                    fields.push(Field { name: Some(n), ty: t, tag: None });
-                }
-                self.expect(&TokenKind::RBrace)?;
-                Ok(Type::Struct(fields))
+                Ok(Type::Struct(self.parse_struct_fields()?))
             }
 
             // Named / builtin
@@ -337,6 +530,10 @@ impl Parser {
                     let sub = self.expect_ident()?;
                     return Ok(Type::Named(format!("{}.{}", name, sub)));
                 }
+                if self.at(&TokenKind::LBracket) && self.generics.contains(&name) {
+                    let args = self.parse_type_args()?;
+                    return Ok(Type::Generic { name, args });
+                }
                 Ok(builtin_type(&name))
             }
 
@@ -357,7 +554,10 @@ impl Parser {
             // Eat stray semicolons between statements
             while self.eat(&TokenKind::Semicolon) {}
             if self.at(&TokenKind::RBrace) { break; }
-            stmts.push(self.parse_stmt()?);
+            match self.parse_stmt() {
+                Ok(s)  => stmts.push(s),
+                Err(e) => { self.record(e); self.sync_stmt(); }
+            }
             // Eat trailing semicolons after each statement
             while self.eat(&TokenKind::Semicolon) {}
         }
@@ -376,6 +576,7 @@ impl Parser {
             TokenKind::KwSwitch   => self.parse_switch(),
             TokenKind::KwBreak    => { self.advance(); Ok(Stmt::Break    { label: None, span }) }
             TokenKind::KwContinue => { self.advance(); Ok(Stmt::Continue { label: None, span }) }
+            TokenKind::KwFallthrough => { self.advance(); Ok(Stmt::Fallthrough { span }) }
             TokenKind::KwGoto     => { self.advance(); Ok(Stmt::Goto     { label: self.expect_ident()?, span }) }
             TokenKind::KwDefer    => { self.advance(); Ok(Stmt::Defer    { call:  self.parse_expr(0)?, span }) }
             TokenKind::KwGo       => { self.advance(); Ok(Stmt::Go       { call:  self.parse_expr(0)?, span }) }
@@ -417,7 +618,16 @@ impl Parser {
     fn parse_if(&mut self) -> Result<Stmt> {
         let span = self.span();
         self.expect(&TokenKind::KwIf)?;
-        let cond  = self.parse_expr(0)?;
+        // `if init; cond {` — the init is a simple statement, the cond an expression
+        let first = self.in_header(|p| p.parse_simple_stmt())?;
+        let (init, cond) = if self.eat(&TokenKind::Semicolon) {
+            (Some(Box::new(first)), self.in_header(|p| p.parse_expr(0))?)
+        } else {
+            match first {
+                Stmt::Expr { expr, .. } => (None, expr),
+                _ => return Err(tsukiError::parse(span, "expected condition after `if`")),
+            }
+        };
         let then  = self.parse_block()?;
         let else_ = if self.eat(&TokenKind::KwElse) {
             Some(Box::new(if self.at(&TokenKind::KwIf) {
@@ -426,7 +636,7 @@ impl Parser {
                 Stmt::Block(self.parse_block()?)
             }))
         } else { None };
-        Ok(Stmt::If { init: None, cond, then, else_, span })
+        Ok(Stmt::If { init, cond, then, else_, span })
     }
 
     fn parse_for(&mut self) -> Result<Stmt> {
@@ -464,7 +674,7 @@ impl Parser {
             let init = if self.at(&TokenKind::Semicolon) {
                 None
             } else {
-                Some(Box::new(self.parse_simple_stmt()?))
+                Some(Box::new(self.in_header(|p| p.parse_simple_stmt())?))
             };
             self.expect(&TokenKind::Semicolon)?;
 
@@ -472,7 +682,7 @@ impl Parser {
             let cond = if self.at(&TokenKind::Semicolon) {
                 None
             } else {
-                Some(self.parse_expr(0)?)
+                Some(self.in_header(|p| p.parse_expr(0))?)
             };
             self.expect(&TokenKind::Semicolon)?;
 
@@ -480,7 +690,7 @@ impl Parser {
             let post = if self.at(&TokenKind::LBrace) {
                 None
             } else {
-                Some(Box::new(self.parse_simple_stmt()?))
+                Some(Box::new(self.in_header(|p| p.parse_simple_stmt())?))
             };
 
             let body = self.parse_block()?;
@@ -488,7 +698,7 @@ impl Parser {
         }
 
         // while-style: `for cond { }`
-        let cond = self.parse_expr(0)?;
+        let cond = self.in_header(|p| p.parse_expr(0))?;
         Ok(Stmt::For { init: None, cond: Some(cond), post: None, body: self.parse_block()?, span })
     }
 
@@ -517,7 +727,7 @@ impl Parser {
             (Some(k), v)
         };
         self.expect(&TokenKind::KwRange)?;
-        let iter = self.parse_expr(0)?;
+        let iter = self.in_header(|p| p.parse_expr(0))?;
         let body = self.parse_block()?;
         Ok(Stmt::Range { key, val, iter, body, span })
     }
@@ -525,7 +735,34 @@ impl Parser {
     fn parse_switch(&mut self) -> Result<Stmt> {
         let span = self.span();
         self.expect(&TokenKind::KwSwitch)?;
-        let tag = if !self.at(&TokenKind::LBrace) { Some(self.parse_expr(0)?) } else { None };
+
+        // Header: `switch [init;] [tag] {` or `switch [init;] [v :=] x.(type) {`
+        let mut init = None;
+        let mut tag  = None;
+        if !self.at(&TokenKind::LBrace) {
+            if self.has_type_guard_ahead() {
+                return self.parse_type_switch(None, span);
+            }
+            let first = if self.at(&TokenKind::Semicolon) {
+                None
+            } else {
+                Some(self.in_header(|p| p.parse_simple_stmt())?)
+            };
+            if self.eat(&TokenKind::Semicolon) {
+                init = first.map(Box::new);
+                if !self.at(&TokenKind::LBrace) {
+                    if self.has_type_guard_ahead() {
+                        return self.parse_type_switch(init, span);
+                    }
+                    tag = Some(self.in_header(|p| p.parse_expr(0))?);
+                }
+            } else {
+                match first {
+                    Some(Stmt::Expr { expr, .. }) => tag = Some(expr),
+                    _ => return Err(tsukiError::parse(span, "switch tag must be an expression")),
+                }
+            }
+        }
         self.expect(&TokenKind::LBrace)?;
 
         let mut cases = Vec::new();
@@ -541,31 +778,116 @@ impl Parser {
                 self.expect(&TokenKind::Colon)?;
                 vec![]
             };
-            let mut body = Vec::new();
-            while !self.at(&TokenKind::KwCase) && !self.at(&TokenKind::KwDefault)
-                && !self.at(&TokenKind::RBrace) && !self.eof()
-            {
-                body.push(self.parse_stmt()?);
-            }
+            let body = self.parse_case_body()?;
             cases.push(SwitchCase { exprs, body, span: cspan });
         }
         self.expect(&TokenKind::RBrace)?;
-        Ok(Stmt::Switch { init: None, tag, cases, span })
+
+        // `fallthrough` must end a clause, and never the last one.
+        for (i, case) in cases.iter().enumerate() {
+            let last = case.body.len().saturating_sub(1);
+            for (j, st) in case.body.iter().enumerate() {
+                if let Stmt::Fallthrough { span } = st {
+                    if j != last {
+                        return Err(tsukiError::parse(span.clone(), "fallthrough statement out of place"));
+                    }
+                    if i + 1 == cases.len() {
+                        return Err(tsukiError::parse(span.clone(), "cannot fallthrough final case in switch"));
+                    }
+                }
+            }
+        }
+
+        Ok(Stmt::Switch { init, tag, cases, span })
+    }
+
+    /// Is there an `x.(type)` guard before the switch body (or next `;`)?
+    fn has_type_guard_ahead(&self) -> bool {
+        let mut i = self.pos;
+        while i + 2 < self.tokens.len() {
+            match &self.tokens[i].kind {
+                TokenKind::Semicolon | TokenKind::LBrace | TokenKind::EOF => return false,
+                TokenKind::Dot if self.tokens[i + 1].kind == TokenKind::LParen
+                    && self.tokens[i + 2].kind == TokenKind::KwType => return true,
+                _ => i += 1,
+            }
+        }
+        false
+    }
+
+    fn parse_type_switch(&mut self, init: Option<Box<Stmt>>, span: Span) -> Result<Stmt> {
+        let bind = if matches!(self.peek_kind(), TokenKind::Ident(_))
+            && self.kind_at(1) == &TokenKind::DeclAssign
+        {
+            let name = self.expect_ident()?;
+            self.advance();
+            Some(name)
+        } else { None };
+
+        // parse_postfix stops in front of `.(type)`
+        let expr = self.in_header(|p| p.parse_unary())?;
+        self.expect(&TokenKind::Dot)?;
+        self.expect(&TokenKind::LParen)?;
+        self.expect(&TokenKind::KwType)?;
+        self.expect(&TokenKind::RParen)?;
+        self.expect(&TokenKind::LBrace)?;
+
+        let mut cases = Vec::new();
+        while !self.at(&TokenKind::RBrace) && !self.eof() {
+            let cspan = self.span();
+            let types = if self.eat(&TokenKind::KwCase) {
+                let mut ts = vec![self.parse_type()?];
+                while self.eat(&TokenKind::Comma) { ts.push(self.parse_type()?); }
+                self.expect(&TokenKind::Colon)?;
+                ts
+            } else {
+                self.expect(&TokenKind::KwDefault)?;
+                self.expect(&TokenKind::Colon)?;
+                vec![]
+            };
+            let body = self.parse_case_body()?;
+            if let Some(Stmt::Fallthrough { span }) = body.iter().find(|s| matches!(s, Stmt::Fallthrough { .. })) {
+                return Err(tsukiError::parse(span.clone(), "cannot fallthrough in type switch"));
+            }
+            cases.push(TypeCase { types, body, span: cspan });
+        }
+        self.expect(&TokenKind::RBrace)?;
+        Ok(Stmt::TypeSwitch { init, bind, expr, cases, span })
+    }
+
+    fn parse_case_body(&mut self) -> Result<Vec<Stmt>> {
+        let mut body = Vec::new();
+        while !self.at(&TokenKind::KwCase) && !self.at(&TokenKind::KwDefault)
+            && !self.at(&TokenKind::RBrace) && !self.eof()
+        {
+            match self.parse_stmt() {
+                Ok(s)  => body.push(s),
+                Err(e) => { self.record(e); self.sync_stmt(); }
+            }
+            while self.eat(&TokenKind::Semicolon) {}
+        }
+        Ok(body)
     }
 
     fn parse_simple_stmt(&mut self) -> Result<Stmt> {
         let span = self.span();
-        let expr = self.parse_expr(0)?;
+        let mut exprs = vec![self.parse_expr(0)?];
+        while self.eat(&TokenKind::Comma) { exprs.push(self.parse_expr(0)?); }
 
         // short declaration: names := exprs
         if self.at(&TokenKind::DeclAssign) {
             self.advance();
-            let names = expr_list_to_names(&[expr], &span)?;
+            let names = expr_list_to_names(&exprs, &span)?;
             let mut vals = vec![self.parse_expr(0)?];
             while self.eat(&TokenKind::Comma) { vals.push(self.parse_expr(0)?); }
             return Ok(Stmt::ShortDecl { names, vals, span });
         }
 
+        if exprs.len() > 1 {
+            return Err(tsukiError::parse(span, "multiple assignment is only supported with `:=`"));
+        }
+        let expr = exprs.remove(0);
+
         // assignment: lhs op= rhs
         if let Some(op_str) = self.peek_kind().as_assign_op() {
             let op = parse_assign_op(op_str);
@@ -588,8 +910,7 @@ impl Parser {
 
     fn parse_expr(&mut self, min_prec: u8) -> Result<Expr> {
         let mut lhs = self.parse_unary()?;
-        loop {
-            let Some((prec, op_str)) = self.peek_kind().as_binary_op() else { break };
+        while let Some((prec, op_str)) = self.peek_kind().as_binary_op() {
             if prec < min_prec { break; }
             let span = self.span();
             self.advance();
@@ -630,12 +951,15 @@ impl Parser {
                 // call
                 TokenKind::LParen => {
                     self.advance();
-                    let mut args = Vec::new();
-                    while !self.at(&TokenKind::RParen) && !self.eof() {
-                        self.eat(&TokenKind::Ellipsis);
-                        args.push(self.parse_expr(0)?);
-                        if !self.eat(&TokenKind::Comma) { break; }
-                    }
+                    let args = self.nested(|p| {
+                        let mut args = Vec::new();
+                        while !p.at(&TokenKind::RParen) && !p.eof() {
+                            p.eat(&TokenKind::Ellipsis);
+                            args.push(p.parse_expr(0)?);
+                            if !p.eat(&TokenKind::Comma) { break; }
+                        }
+                        Ok(args)
+                    })?;
                     self.expect(&TokenKind::RParen)?;
                     expr = Expr::Call { func: Box::new(expr), args, span };
                 }
@@ -643,11 +967,11 @@ impl Parser {
                 TokenKind::LBracket => {
                     self.advance();
                     let lo = if !self.at(&TokenKind::Colon) {
-                        Some(Box::new(self.parse_expr(0)?))
+                        Some(Box::new(self.nested(|p| p.parse_expr(0))?))
                     } else { None };
                     if self.eat(&TokenKind::Colon) {
                         let hi = if !self.at(&TokenKind::RBracket) {
-                            Some(Box::new(self.parse_expr(0)?))
+                            Some(Box::new(self.nested(|p| p.parse_expr(0))?))
                         } else { None };
                         self.expect(&TokenKind::RBracket)?;
                         expr = Expr::Slice { expr: Box::new(expr), lo, hi, span };
@@ -656,6 +980,9 @@ impl Parser {
                         expr = Expr::Index { expr: Box::new(expr), idx: lo.unwrap(), span };
                     }
                 }
+                // `x.(type)` belongs to the enclosing type switch
+                TokenKind::Dot if self.kind_at(1) == &TokenKind::LParen
+                    && self.kind_at(2) == &TokenKind::KwType => break,
                 // selector / type-assert
                 TokenKind::Dot => {
                     self.advance();
@@ -686,7 +1013,7 @@ impl Parser {
 
             TokenKind::LParen => {
                 self.advance();
-                let e = self.parse_expr(0)?;
+                let e = self.nested(|p| p.parse_expr(0))?;
                 self.expect(&TokenKind::RParen)?;
                 Ok(e)
             }
@@ -694,14 +1021,22 @@ impl Parser {
             TokenKind::KwFunc => {
                 self.advance();
                 let sig  = self.parse_func_sig()?;
-                let body = self.parse_block()?;
+                let body = self.nested(|p| p.parse_block())?;
                 Ok(Expr::FuncLit { sig, body, span })
             }
 
             TokenKind::Ident(name) => {
                 self.advance();
+                // instantiation: Max[int](…) or Pair[int]{…}
+                if self.at(&TokenKind::LBracket) && self.generics.contains(&name) {
+                    let args = self.parse_type_args()?;
+                    if self.at(&TokenKind::LBrace) && !self.no_lit {
+                        return self.parse_composite(Type::Generic { name, args }, span);
+                    }
+                    return Ok(Expr::Inst { name, args, span });
+                }
                 // composite literal: TypeName{...}
-                if self.at(&TokenKind::LBrace) {
+                if self.at(&TokenKind::LBrace) && !self.no_lit {
                     return self.parse_composite(Type::Named(name), span);
                 }
                 Ok(Expr::Ident { name, span })
@@ -721,17 +1056,20 @@ impl Parser {
 
     fn parse_composite(&mut self, ty: Type, span: Span) -> Result<Expr> {
         self.expect(&TokenKind::LBrace)?;
-        let mut elems = Vec::new();
-        while !self.at(&TokenKind::RBrace) && !self.eof() {
-            let first = self.parse_expr(0)?;
-            let (key, val) = if self.eat(&TokenKind::Colon) {
-                (Some(first), self.parse_expr(0)?)
-            } else {
-                (None, first)
-            };
-            elems.push(CompElem { key, val });
-            if !self.eat(&TokenKind::Comma) { break; }
-        }
+        let elems = self.nested(|p| {
+            let mut elems = Vec::new();
+            while !p.at(&TokenKind::RBrace) && !p.eof() {
+                let first = p.parse_expr(0)?;
+                let (key, val) = if p.eat(&TokenKind::Colon) {
+                    (Some(first), p.parse_expr(0)?)
+                } else {
+                    (None, first)
+                };
+                elems.push(CompElem { key, val });
+                if !p.eat(&TokenKind::Comma) { break; }
+            }
+            Ok(elems)
+        })?;
         self.expect(&TokenKind::RBrace)?;
         Ok(Expr::Composite { ty, elems, span })
     }
@@ -741,6 +1079,26 @@ impl Parser {
 //  Helpers
 // ─────────────────────────────────────────────────────────────────────────────
 
+/// Names declared as `func Name[…]` or `type Name[T …]`. A type whose
+/// brackets hold a single name (`type Buf [N]byte`) is an array, not generic.
+fn generic_names(tokens: &[Token]) -> HashSet<String> {
+    let kind = |i: usize| tokens.get(i).map(|t| &t.kind);
+    let mut names = HashSet::new();
+    for i in 0..tokens.len() {
+        let (Some(TokenKind::Ident(name)), Some(TokenKind::LBracket)) = (kind(i + 1), kind(i + 2)) else {
+            continue;
+        };
+        let generic = match kind(i) {
+            Some(TokenKind::KwFunc) => true,
+            Some(TokenKind::KwType) => matches!(kind(i + 3), Some(TokenKind::Ident(_)))
+                && !matches!(kind(i + 4), Some(TokenKind::RBracket | TokenKind::Dot)),
+            _ => false,
+        };
+        if generic { names.insert(name.clone()); }
+    }
+    names
+}
+
 fn builtin_type(s: &str) -> Type {
     match s {
         "bool"       => Type::Bool,
@@ -787,4 +1145,36 @@ fn expr_list_to_names(exprs: &[Expr], span: &Span) -> Result<Vec<String>> {
         Expr::Ident { name, .. } => Ok(name.clone()),
         _ => Err(tsukiError::parse(span.clone(), "left side of `:=` must be identifiers")),
     }).collect()
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    fn errors(src: &str) -> Vec<(u32, String)> {
+        let tokens = Lexer::new(src, "test.go").tokenize().unwrap();
+        Parser::new(tokens).parse_program_all().err().unwrap_or_default().iter()
+            .map(|e| (e.span().map_or(0, |s| s.line), e.message()))
+            .collect()
+    }
+
+    #[test]
+    fn test_recovers_after_each_syntax_error() {
+        let src = "package main\nfunc setup() {\n    y := (2\n    delay(5)\n    switch y {\n    case 1:\n        z := ]\n    \
+                   case 2:\n        delay(1)\n    }\n}\nfunc loop {\n}\nvar ok = 1\nfunc blink() {\n    delay(,)\n}\n";
+        let lines: Vec<u32> = errors(src).iter().map(|(l, _)| *l).collect();
+        assert_eq!(lines, [4, 7, 12, 16], "{:?}", errors(src));
+
+        // The first error is still what parse_program fails with.
+        let tokens = Lexer::new(src, "test.go").tokenize().unwrap();
+        assert_eq!(Parser::new(tokens).parse_program().unwrap_err().span().unwrap().line, 4);
+    }
+
+    #[test]
+    fn test_unclosed_blocks_report_once() {
+        let errs = errors("package main\nfunc setup() {\n    if true {\n        delay(1)\n");
+        assert_eq!(errs.len(), 1, "{:?}", errs);
+        assert!(errors("package main\nfunc setup() {\n}\n").is_empty());
+    }
+}
@@ -4,21 +4,33 @@
 //  Now also loads external libraries from tsukilib.toml packages.
 // ─────────────────────────────────────────────────────────────────────────────
 
+pub mod pkg_lint;
 pub mod pkg_loader;
 pub mod pkg_manager;
+pub mod prelude;
+#[cfg(feature = "scripting")]
+pub mod script;
 
 use std::collections::HashMap;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+
+use crate::parser::ast::Type;
+use prelude::Prelude;
 
 // ── Mapping types ─────────────────────────────────────────────────────────────
 
 #[derive(Debug, Clone)]
 pub enum FnMap {
     Direct(String),
+    /// `{0}`, `{1}`… are the call arguments and `{self}` the receiver; each
+    /// may be piped through filters, e.g. `{0|mul:1000}`.
     Template(String),
     /// All args joined by ", " replace the `{args}` placeholder.
     /// Used for variadic calls like Serial.printf where arg count varies.
     Variadic(String),
+    /// Rhai script choosing the C++ from the arguments and their types.
+    #[cfg(feature = "scripting")]
+    Script(script::Script),
 }
 
 impl FnMap {
@@ -26,23 +38,214 @@ impl FnMap {
         match self {
             Self::Direct(s)   => s.clone(),
             Self::Template(t) => {
-                let mut out = t.clone();
-                // {self} is a named alias for the receiver (args[0])
-                if let Some(receiver) = args.first() {
-                    out = out.replace("{self}", receiver);
-                }
-                for (i, a) in args.iter().enumerate() {
-                    out = out.replace(&format!("{{{i}}}"), a);
+                let mut out = String::with_capacity(t.len());
+                let mut rest = t.as_str();
+                while let Some(open) = rest.find('{') {
+                    out += &rest[..open];
+                    let tail = &rest[open..];
+                    let Some((slot, filters, len)) = placeholder(tail) else {
+                        out.push('{');
+                        rest = &tail[1..];
+                        continue;
+                    };
+                    // Filters were checked when the mapping was loaded.
+                    let filters: Vec<Filter> = filters.iter()
+                        .filter_map(|f| Filter::parse(f).ok()).collect();
+                    match args.get(slot) {
+                        Some(a) => out += &filters.iter().fold(a.clone(), |a, f| f.apply(&a)),
+                        None    => out += &tail[..len],
+                    }
+                    rest = &tail[len..];
                 }
-                out
+                out + rest
             }
             Self::Variadic(t) => {
                 t.replace("{args}", &args.join(", "))
             }
+            #[cfg(feature = "scripting")]
+            Self::Script(s) => s.run(args, &[]).unwrap_or_else(|e| format!("/* {} */", e)),
+        }
+    }
+
+    /// Like `apply`, with each argument's Go type (None when unknown) made
+    /// available to scripts; only a script can fail here.
+    pub fn expand(&self, args: &[String], types: &[Option<Type>]) -> std::result::Result<String, String> {
+        match self {
+            #[cfg(feature = "scripting")]
+            Self::Script(s) => s.run(args, types),
+            _ => {
+                let _ = types;
+                Ok(self.apply(args))
+            }
+        }
+    }
+
+    /// The C++ the mapping expands to, placeholders and all; a script has
+    /// none until it runs.
+    pub fn template(&self) -> &str {
+        match self {
+            Self::Direct(t) | Self::Template(t) | Self::Variadic(t) => t,
+            #[cfg(feature = "scripting")]
+            Self::Script(_) => "(script)",
+        }
+    }
+
+    /// How many arguments a call must pass: one past the highest placeholder
+    /// of a template (`{self}` is `{0}`), 0 for other mappings.
+    pub fn arity(&self) -> usize {
+        let Self::Template(t) = self else { return 0 };
+        t.match_indices('{')
+            .filter_map(|(i, _)| placeholder(&t[i..]))
+            .map(|(slot, _, _)| slot + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Reject unknown or malformed filters in a template.
+    pub fn validate(&self) -> std::result::Result<(), String> {
+        let Self::Template(t) = self else { return Ok(()) };
+        for (i, _) in t.match_indices('{') {
+            if let Some((_, filters, _)) = placeholder(&t[i..]) {
+                for f in filters {
+                    Filter::parse(f)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parse a `{N|filter…}` or `{self|filter…}` placeholder at the start of `s`,
+/// returning the argument index, the raw filter specs and the length matched.
+/// Anything else (`{ … }` blocks in the C++ itself) yields None.
+fn placeholder(s: &str) -> Option<(usize, Vec<&str>, usize)> {
+    let close = s.find('}')?;
+    let mut parts = s[1..close].split('|');
+    let slot = match parts.next()? {
+        "self" => 0,
+        n if !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()) => n.parse().ok()?,
+        _ => return None,
+    };
+    Some((slot, parts.collect(), close + 1))
+}
+
+/// Argument transform applied by a template placeholder.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    /// `mul:N` — scale a numeric argument, e.g. ms → µs.
+    Mul(i64),
+    /// `div:N` — integer-divide a numeric argument.
+    Div(i64),
+    /// `upper` / `lower` — change the case of an identifier or string literal.
+    Upper,
+    Lower,
+    /// `hex` — render an integer literal as `0x…`.
+    Hex,
+}
+
+impl Filter {
+    pub fn parse(spec: &str) -> std::result::Result<Self, String> {
+        let (name, arg) = match spec.split_once(':') {
+            Some((n, a)) => (n.trim(), Some(a.trim())),
+            None         => (spec.trim(), None),
+        };
+        let int_arg = || arg.and_then(|a| a.parse::<i64>().ok())
+            .ok_or_else(|| format!("filter `{}` needs an integer argument, e.g. `{}:1000`", name, name));
+        match (name, arg) {
+            ("mul", _) => Ok(Self::Mul(int_arg()?)),
+            ("div", _) => match int_arg()? {
+                0 => Err("filter `div` cannot divide by zero".into()),
+                n => Ok(Self::Div(n)),
+            },
+            ("upper", None) => Ok(Self::Upper),
+            ("lower", None) => Ok(Self::Lower),
+            ("hex",   None) => Ok(Self::Hex),
+            ("upper" | "lower" | "hex", Some(_)) =>
+                Err(format!("filter `{}` takes no argument", name)),
+            _ => Err(format!("unknown template filter `{}` (expected mul, div, upper, lower or hex)", name)),
+        }
+    }
+
+    /// Apply to an emitted C++ argument. Integer literals are folded; other
+    /// expressions get the equivalent runtime arithmetic.
+    pub fn apply(&self, arg: &str) -> String {
+        let lit = arg.trim().parse::<i64>().ok();
+        match (self, lit) {
+            (Self::Mul(n), Some(v)) => v.saturating_mul(*n).to_string(),
+            (Self::Mul(n), None)    => format!("(({}) * {})", arg, n),
+            (Self::Div(n), Some(v)) => (v / n).to_string(),
+            (Self::Div(n), None)    => format!("(({}) / {})", arg, n),
+            (Self::Hex, Some(v)) if v >= 0 => format!("0x{:X}", v),
+            (Self::Hex, _)          => arg.to_owned(),
+            (Self::Upper, _)        => map_text(arg, str::to_uppercase),
+            (Self::Lower, _)        => map_text(arg, str::to_lowercase),
         }
     }
 }
 
+/// Apply `f` to the quoted part of a string literal (`"red"`, `String("red")`),
+/// or to the whole argument when it is a bare identifier.
+fn map_text(arg: &str, f: fn(&str) -> String) -> String {
+    match (arg.find('"'), arg.rfind('"')) {
+        (Some(a), Some(b)) if a < b => format!("{}{}{}", &arg[..=a], f(&arg[a + 1..b]), &arg[b..]),
+        _ => f(arg),
+    }
+}
+
+/// Board architectures (the FQBN's middle field: `avr`, `esp32`, `samd`…)
+/// a package or function is limited to. Empty `allow` means any.
+#[derive(Debug, Clone, Default)]
+pub struct ArchRule {
+    pub allow: Vec<String>,
+    pub deny:  Vec<String>,
+}
+
+impl ArchRule {
+    pub fn new(allow: &[&str], deny: &[&str]) -> Self {
+        let own = |v: &[&str]| v.iter().map(|s| s.to_string()).collect();
+        Self { allow: own(allow), deny: own(deny) }
+    }
+
+    pub fn permits(&self, arch: &str) -> bool {
+        (self.allow.is_empty() || self.allow.iter().any(|a| a.eq_ignore_ascii_case(arch)))
+            && !self.deny.iter().any(|d| d.eq_ignore_ascii_case(arch))
+    }
+
+    /// Human-readable constraint, e.g. `esp32, esp8266` or `anything but avr`.
+    pub fn describe(&self) -> String {
+        match (self.allow.is_empty(), self.deny.is_empty()) {
+            (false, _)   => self.allow.join(", "),
+            (true, false) => format!("anything but {}", self.deny.join(", ")),
+            (true, true)  => "any".into(),
+        }
+    }
+}
+
+/// The targets a board-specific mapping is for: board ids (`uno`, `esp32`…)
+/// or an architecture. A board id is the closer match of the two.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Targets {
+    pub boards: Vec<String>,
+    pub arch:   Option<String>,
+}
+
+impl Targets {
+    /// 2 when `board` is listed by id, 1 when its architecture matches.
+    fn rank(&self, board: &Board) -> Option<u8> {
+        if self.boards.iter().any(|b| b.eq_ignore_ascii_case(&board.id)) { return Some(2) }
+        self.arch.as_deref().filter(|a| a.eq_ignore_ascii_case(board.arch())).map(|_| 1)
+    }
+
+    fn names(&self) -> impl Iterator<Item = &String> {
+        self.boards.iter().chain(&self.arch)
+    }
+
+    /// The board ids and architecture, e.g. `uno, esp32`.
+    pub fn describe(&self) -> String {
+        self.names().cloned().collect::<Vec<_>>().join(", ")
+    }
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct PkgMap {
     pub header:    Option<String>,
@@ -51,6 +254,38 @@ pub struct PkgMap {
     pub types:     HashMap<String, String>,
     /// C++ class name for global variable declarations (emitted as pointer).
     pub cpp_class: Option<String>,
+    /// C++ helpers emitted once, after the includes, when the package is used.
+    pub prelude:   Option<String>,
+    /// C++ declarations (objects, buffers) emitted with the program's globals.
+    pub globals:   Option<String>,
+    /// C++ statements run at the top of `setup()`.
+    pub setup:     Option<String>,
+    /// The shipped prelude file `prelude` comes from, for project overrides.
+    pub prelude_file: Option<&'static str>,
+    /// Go result types of mapped functions, for `len`/`range` over their results.
+    pub returns:   HashMap<String, Type>,
+    /// Go parameter types of mapped functions that declare them (method
+    /// receivers excluded); calls are checked against them.
+    pub params:    HashMap<String, Vec<Type>>,
+    /// Architectures the whole package is available on.
+    pub arch:      ArchRule,
+    /// Per-function narrowing of `arch`.
+    pub fn_arch:   HashMap<String, ArchRule>,
+    /// Board-specific mappings of functions and constants; `Runtime::for_board`
+    /// puts the best match for the target in place of the generic one.
+    pub fn_variants:    HashMap<String, Vec<(Targets, FnMap)>>,
+    pub const_variants: HashMap<String, Vec<(Targets, String)>>,
+    /// Board features (see `FEATURES`) the package can't work without.
+    pub requires:  Vec<String>,
+    /// Smallest board RAM the package fits in, in KB.
+    pub min_ram_kb: Option<u32>,
+    /// arduino-cli library providing `header`, installed before compiling.
+    pub arduino_lib: Option<String>,
+    /// Version of `arduino_lib` the package was written against.
+    pub arduino_lib_version: Option<String>,
+    /// The package's vendored headers (its `src/`), added to the compiler's
+    /// include path.
+    pub include_dir: Option<PathBuf>,
 }
 
 impl PkgMap {
@@ -66,13 +301,132 @@ impl PkgMap {
     pub fn cst(mut self, go: &str, cpp: &str) -> Self {
         self.constants.insert(go.into(), cpp.into()); self
     }
+    pub fn with_prelude(mut self, cpp: &str) -> Self {
+        self.prelude = Some(cpp.to_owned()); self
+    }
+    pub fn with_globals(mut self, cpp: &str) -> Self {
+        self.globals = Some(cpp.to_owned()); self
+    }
+    pub fn with_setup(mut self, cpp: &str) -> Self {
+        self.setup = Some(cpp.to_owned()); self
+    }
+    pub fn with_prelude_file(mut self, name: &'static str) -> Self {
+        self.prelude = Some(prelude::embedded(name).to_owned());
+        self.prelude_file = Some(name); self
+    }
+    pub fn ret(mut self, go: &str, ty: Type) -> Self {
+        self.returns.insert(go.into(), ty); self
+    }
+    pub fn params(mut self, go: &str, tys: Vec<Type>) -> Self {
+        self.params.insert(go.into(), tys); self
+    }
+    pub fn with_arch(mut self, rule: ArchRule) -> Self {
+        self.arch = rule; self
+    }
+    pub fn needs(mut self, features: &[&str]) -> Self {
+        self.requires = features.iter().map(|f| f.to_string()).collect(); self
+    }
+    pub fn with_min_ram(mut self, kb: u32) -> Self {
+        self.min_ram_kb = Some(kb); self
+    }
+    pub fn fun_arch(mut self, go: &str, rule: ArchRule) -> Self {
+        self.fn_arch.insert(go.into(), rule); self
+    }
+    pub fn with_arduino_lib(mut self, lib: &str) -> Self {
+        self.arduino_lib = Some(lib.to_owned()); self
+    }
+    pub fn fun_on(mut self, go: &str, on: Targets, map: FnMap) -> Self {
+        self.fn_variants.entry(go.into()).or_default().push((on, map)); self
+    }
+    pub fn cst_on(mut self, go: &str, on: Targets, cpp: &str) -> Self {
+        self.const_variants.entry(go.into()).or_default().push((on, cpp.into())); self
+    }
+
+    /// Settle the board-specific mappings for `board`. A function with only
+    /// mappings for other boards is kept, restricted to none, so calling it
+    /// reports where it is available.
+    fn pick_variants(&mut self, board: &Board) {
+        for (go, variants) in &self.fn_variants {
+            match best(variants, board) {
+                Some(map) => { self.functions.insert(go.clone(), map.clone()); }
+                None if !self.functions.contains_key(go) => {
+                    let allow = variants.iter().flat_map(|(on, _)| on.names()).cloned().collect();
+                    self.fn_arch.insert(go.clone(), ArchRule { allow, deny: vec![board.arch().to_owned()] });
+                    self.functions.insert(go.clone(), variants[0].1.clone());
+                }
+                None => {}
+            }
+        }
+        for (go, variants) in &self.const_variants {
+            if let Some(cpp) = best(variants, board) {
+                self.constants.insert(go.clone(), cpp.clone());
+            }
+        }
+    }
+}
+
+/// Board-specific mappings as `tsuki doc` lists them: `Go [targets]`.
+fn variants<T>(map: &HashMap<String, Vec<(Targets, T)>>, cpp: fn(&T) -> String) -> impl Iterator<Item = (String, String)> + '_ {
+    map.iter().flat_map(move |(go, vs)| vs.iter().map(move |(on, v)| (format!("{} [{}]", go, on.describe()), cpp(v))))
+}
+
+/// The mapping whose targets match `board` most closely; the first listed
+/// wins a tie.
+fn best<'a, T>(variants: &'a [(Targets, T)], board: &Board) -> Option<&'a T> {
+    variants.iter()
+        .filter_map(|(on, v)| on.rank(board).map(|r| (r, v)))
+        .fold(None, |acc: Option<(u8, &T)>, (r, v)| match acc {
+            Some((best, _)) if best >= r => acc,
+            _ => Some((r, v)),
+        })
+        .map(|(_, v)| v)
+}
+
+/// What a package maps, as listed by `tsuki doc`: Go names with their C++,
+/// each list sorted by Go name.
+#[derive(Debug, Clone)]
+pub struct PkgDoc {
+    pub name:        String,
+    pub header:      Option<String>,
+    pub arduino_lib: Option<String>,
+    pub functions:   Vec<(String, String)>,
+    pub constants:   Vec<(String, String)>,
+    pub types:       Vec<(String, String)>,
+}
+
+impl std::fmt::Display for PkgDoc {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "package {}", self.name)?;
+        if let Some(h) = &self.header { writeln!(f, "  #include <{}>", h)? }
+        if let Some(lib) = &self.arduino_lib { writeln!(f, "  arduino library: {}", lib)? }
+        let width = self.functions.iter().chain(&self.constants).chain(&self.types)
+            .map(|(go, _)| go.len()).max().unwrap_or(0);
+        for (title, items) in [("functions", &self.functions), ("constants", &self.constants), ("types", &self.types)] {
+            if items.is_empty() { continue }
+            writeln!(f, "\n{}", title)?;
+            for (go, cpp) in items {
+                writeln!(f, "  {:<width$}  {}", go, cpp, width = width)?;
+            }
+        }
+        Ok(())
+    }
 }
 
+/// C++ type of `strings.Split` results: a fixed-capacity list of `String`
+/// whose element count is `.n`.
+pub const STR_SLICE: &str = "tsuki_StrSlice";
+
+/// `time.Sleep` (nanoseconds) as a busy `delay`, and as a FreeRTOS delay
+/// that lets other tasks run.
+const SLEEP_DELAY: &str = "delay(({0})/1000000UL)";
+const SLEEP_TASK_DELAY: &str = "vTaskDelay(pdMS_TO_TICKS(({0})/1000000UL))";
+
 // ── Registry ──────────────────────────────────────────────────────────────────
 
 pub struct Runtime {
     pub packages: HashMap<String, PkgMap>,
     pub builtins: HashMap<String, FnMap>,
+    prelude:      Prelude,
 }
 
 impl Default for Runtime { fn default() -> Self { Self::new() } }
@@ -80,18 +434,37 @@ impl Default for Runtime { fn default() -> Self { Self::new() } }
 impl Runtime {
     /// Create a runtime with only the built-in packages.
     pub fn new() -> Self {
-        let mut r = Runtime { packages: HashMap::new(), builtins: HashMap::new() };
+        let mut r = Runtime { packages: HashMap::new(), builtins: HashMap::new(), prelude: Prelude::default() };
         r.init_builtins();
         r.init_fmt();
+        r.init_errors();
         r.init_time();
         r.init_math();
         r.init_strconv();
+        r.init_strings();
+        r.init_bytes();
+        r.init_binary();
+        r.init_eeprom();
+        r.init_wifi();
+        r.init_http();
         r.init_arduino();
         r.init_wire();
         r.init_spi();
         r.init_serial();
         r.init_servo();
         r.init_liquidcrystal();
+        r.init_keyboard();
+        r.init_mouse();
+        r.init_sd();
+        r.init_rtc();
+        r.init_dht();
+        r.init_onewire();
+        r.init_ds18b20();
+        r.init_hcsr04();
+        r.init_stepper();
+        r.init_accelstepper();
+        r.init_freertos();
+        r.init_testing();
         r
     }
 
@@ -112,6 +485,37 @@ impl Runtime {
         r
     }
 
+    /// Swap in a project's prelude overrides.
+    pub fn set_prelude(&mut self, prelude: Prelude) {
+        for pkg in self.packages.values_mut() {
+            if let Some(name) = pkg.prelude_file {
+                pkg.prelude = Some(prelude.get(name).to_owned());
+            }
+        }
+        self.prelude = prelude;
+    }
+
+    /// The prelude files in effect.
+    pub fn prelude(&self) -> &Prelude {
+        &self.prelude
+    }
+
+    /// Adjust the built-in mappings for the target board. On ESP32 the sketch
+    /// runs as a FreeRTOS task, so `time.Sleep` yields through `vTaskDelay`
+    /// instead of spinning in `delay`. An external `time` package is kept.
+    /// Board-specific mappings of packages replace their generic ones too.
+    pub fn for_board(&mut self, board: &Board) {
+        for pkg in self.packages.values_mut() {
+            pkg.pick_variants(board);
+        }
+        if board.arch() != "esp32" { return }
+        if let Some(time) = self.packages.get_mut("time") {
+            if time.functions.get("Sleep").is_some_and(|f| f.template() == SLEEP_DELAY) {
+                time.functions.insert("Sleep".into(), FnMap::Template(SLEEP_TASK_DELAY.into()));
+            }
+        }
+    }
+
     // ── External library loading ──────────────────────────────────────────────
 
     /// Load all libraries found under `libs_dir`.
@@ -121,14 +525,43 @@ impl Runtime {
         }
     }
 
-    /// Load only the listed packages from `libs_dir`.
+    /// Load only the listed packages from `libs_dir`. `name@version` picks
+    /// that installed version (as pinned by a lockfile) over the highest.
     pub fn load_selected_libs(&mut self, libs_dir: &Path, pkg_names: &[String]) {
-        for lib in pkg_loader::load_all(libs_dir) {
-            let matches = pkg_names.iter().any(|n| {
-                n == &lib.name || lib.aliases.iter().any(|a| a == n)
-            });
-            if matches {
-                self.register_lib(lib);
+        self.load_selected_in(&[libs_dir], pkg_names);
+    }
+
+    /// Load from several roots, e.g. a project's vendor/ before the libs
+    /// directory: the listed packages, or all when `pkg_names` is empty. A
+    /// package in an earlier directory shadows the same one in a later one.
+    pub fn load_libs_in(&mut self, dirs: &[&Path], pkg_names: &[String]) {
+        if !pkg_names.is_empty() {
+            return self.load_selected_in(dirs, pkg_names);
+        }
+        for dir in dirs.iter().rev() {
+            self.load_external_libs(dir);
+        }
+    }
+
+    fn load_selected_in(&mut self, dirs: &[&Path], pkg_names: &[String]) {
+        let (pinned, named): (Vec<&String>, Vec<&String>) = pkg_names.iter().partition(|n| n.contains('@'));
+        for dir in dirs.iter().rev() {
+            for lib in pkg_loader::load_all(dir) {
+                let matches = named.iter().any(|n| {
+                    *n == &lib.name || lib.aliases.iter().any(|a| a == *n)
+                });
+                if matches {
+                    self.register_lib(lib);
+                }
+            }
+        }
+        for spec in pinned {
+            let (name, version) = spec.split_once('@').unwrap_or_default();
+            let paths: Vec<_> = dirs.iter().map(|d| d.join(name).join(version).join("tsukilib.toml")).collect();
+            let Some(path) = paths.iter().find(|p| p.exists()).or(paths.first()) else { continue };
+            match pkg_loader::load_from_file(path) {
+                Ok(lib) => self.register_lib(lib),
+                Err(e)  => eprintln!("tsuki: warning: skipping {}: {}", spec, e),
             }
         }
     }
@@ -182,18 +615,62 @@ impl Runtime {
             .fun("Printf",   FnMap::Variadic("do { char _pb[128]; snprintf(_pb, sizeof(_pb), {args}); Serial.print(_pb); } while(0)".into()))
             .fun("Fprintf",  FnMap::Variadic("do { char _pb[128]; snprintf(_pb, sizeof(_pb), {args}); Serial.print(_pb); } while(0)".into()))
             .fun("Sprintf",  FnMap::Variadic("([&](){ char _buf[128]; snprintf(_buf, sizeof(_buf), {args}); return String(_buf); })()".into()))
-            .fun("Errorf",   FnMap::Variadic("([&](){ char _buf[128]; snprintf(_buf, sizeof(_buf), {args}); return String(_buf); })()".into()))
+            .fun("Errorf",   FnMap::Variadic("([&](){ char _buf[128]; snprintf(_buf, sizeof(_buf), {args}); return tsuki_errors_New(__COUNTER__ + 1, String(_buf)); })()".into()))
+            .ret("Errorf",   Type::Named("error".into()))
+        );
+    }
+
+    /// `errors.New` sites are told apart by `__COUNTER__`, so a sentinel is
+    /// whatever one call site returns; `fmt.Errorf` with `%w` is handled by
+    /// the transpiler.
+    fn init_errors(&mut self) {
+        self.reg("errors", PkgMap::new(None)
+            .with_prelude_file("error.h")
+            .fun("New", FnMap::Template("tsuki_errors_New(__COUNTER__ + 1, {0})".into()))
+            .fun("Is",  FnMap::Template("tsuki_errors_Is({0}, {1})".into()))
+            .fun("As",  FnMap::Template("tsuki_errors_As({0}, {1})".into()))
+            .ret("New", Type::Named("error".into()))
+            .ret("Is",  Type::Bool)
+            .ret("As",  Type::Bool)
         );
     }
 
     fn init_time(&mut self) {
         self.reg("time", PkgMap::new(None)
-            .fun("Sleep",  FnMap::Template("delay(({0})/1000000UL)".into()))
+            .fun("Sleep",  FnMap::Template(SLEEP_DELAY.into()))
             .fun("Now",    FnMap::Direct("millis()".into()))
             .fun("Since",  FnMap::Template("(millis()-{0})".into()))
             .cst("Second",      "1000000000ULL")
             .cst("Millisecond", "1000000ULL")
             .cst("Microsecond", "1000ULL")
+            // time.Time, backed by RTClib's DateTime (import "rtc"). Dates
+            // are local: the location argument of Date is ignored.
+            .fun("Date",        FnMap::Template("DateTime({0}, {1}, {2}, {3}, {4}, {5})".into()))
+            .fun("Year",        FnMap::Template("{0}.year()".into()))
+            .fun("Month",       FnMap::Template("{0}.month()".into()))
+            .fun("Day",         FnMap::Template("{0}.day()".into()))
+            .fun("Hour",        FnMap::Template("{0}.hour()".into()))
+            .fun("Minute",      FnMap::Template("{0}.minute()".into()))
+            .fun("Second",      FnMap::Template("{0}.second()".into()))
+            .fun("Weekday",     FnMap::Template("{0}.dayOfTheWeek()".into()))
+            .fun("Unix",        FnMap::Template("{0}.unixtime()".into()))
+            .cst("UTC",         "0")
+            .cst("Local",       "0")
+            .cst("January", "1").cst("February", "2").cst("March",     "3").cst("April",    "4")
+            .cst("May",     "5").cst("June",     "6").cst("July",      "7").cst("August",   "8")
+            .cst("September", "9").cst("October", "10").cst("November", "11").cst("December", "12")
+            .cst("DateTime",    "\"2006-01-02 15:04:05\"")
+            .cst("DateOnly",    "\"2006-01-02\"")
+            .cst("TimeOnly",    "\"15:04:05\"")
+            .ret("Date",        Type::Named("time.Time".into()))
+            .ret("Year",        Type::Int)
+            .ret("Month",       Type::Int)
+            .ret("Day",         Type::Int)
+            .ret("Hour",        Type::Int)
+            .ret("Minute",      Type::Int)
+            .ret("Second",      Type::Int)
+            .ret("Weekday",     Type::Int)
+            .ret("Unix",        Type::Uint32)
         );
     }
 
@@ -244,6 +721,132 @@ impl Runtime {
         );
     }
 
+    /// Go `strings` on top of the Arduino `String` class. String's case and
+    /// trim methods mutate in place, so those run on a copy.
+    fn init_strings(&mut self) {
+        let copy = |method: &str| FnMap::Template(format!(
+            "([&](){{ String _s = {{0}}; _s.{}(); return _s; }})()", method));
+        self.reg("strings", PkgMap::new(None)
+            .with_prelude_file("strings.h")
+            .fun("Contains",   FnMap::Template("(String({0}).indexOf({1}) >= 0)".into()))
+            .fun("HasPrefix",  FnMap::Template("String({0}).startsWith({1})".into()))
+            .fun("HasSuffix",  FnMap::Template("String({0}).endsWith({1})".into()))
+            .fun("Index",      FnMap::Template("String({0}).indexOf({1})".into()))
+            .fun("LastIndex",  FnMap::Template("String({0}).lastIndexOf({1})".into()))
+            .fun("Split",      FnMap::Template("tsuki_strings_Split({0}, {1})".into()))
+            .fun("ToUpper",    copy("toUpperCase"))
+            .fun("ToLower",    copy("toLowerCase"))
+            .fun("TrimSpace",  copy("trim"))
+            .fun("Replace",    FnMap::Template("tsuki_strings_Replace({0}, {1}, {2}, {3})".into()))
+            .fun("ReplaceAll", FnMap::Template("tsuki_strings_Replace({0}, {1}, {2}, -1)".into()))
+            .ret("Split",      Type::Named(STR_SLICE.into()))
+            .ret("ToUpper",    Type::String)
+            .ret("ToLower",    Type::String)
+            .ret("TrimSpace",  Type::String)
+            .ret("Replace",    Type::String)
+            .ret("ReplaceAll", Type::String)
+        );
+    }
+
+    /// Go `bytes` over fixed-size `[N]byte` arrays (slices carry no length).
+    fn init_bytes(&mut self) {
+        let call = |f: &str, n: usize| FnMap::Template(format!("tsuki_bytes_{}({})", f,
+            (0..n).map(|i| format!("{{{}}}", i)).collect::<Vec<_>>().join(", ")));
+        self.reg("bytes", PkgMap::new(Some("string.h"))
+            .with_prelude_file("bytes.h")
+            .fun("Equal",     call("Equal", 2))
+            .fun("Compare",   call("Compare", 2))
+            .fun("Index",     call("Index", 2))
+            .fun("IndexByte", call("IndexByte", 2))
+            .fun("HasPrefix", call("HasPrefix", 2))
+            .fun("HasSuffix", call("HasSuffix", 2))
+            .fun("Contains",  FnMap::Template("(tsuki_bytes_Index({0}, {1}) >= 0)".into()))
+            .ret("Equal",     Type::Bool)
+            .ret("Compare",   Type::Int)
+            .ret("Index",     Type::Int)
+            .ret("IndexByte", Type::Int)
+            .ret("HasPrefix", Type::Bool)
+            .ret("HasSuffix", Type::Bool)
+            .ret("Contains",  Type::Bool)
+        );
+    }
+
+    /// `encoding/binary`: `binary.LittleEndian.PutUint16(buf[2:], v)` resolves
+    /// through the `littleendian` / `bigendian` sub-packages.
+    fn init_binary(&mut self) {
+        self.reg("binary", PkgMap::new(None).with_prelude_file("binary.h"));
+        for (name, big) in [("littleendian", false), ("bigendian", true)] {
+            let mut m = PkgMap::new(None);
+            for (bits, ty) in [(16, Type::Uint16), (32, Type::Uint32), (64, Type::Uint64)] {
+                m = m
+                    .fun(&format!("PutUint{}", bits), FnMap::Template(format!(
+                        "tsuki_binary_put<uint{}_t>({{0}}, {{1}}, {})", bits, big)))
+                    .fun(&format!("Uint{}", bits), FnMap::Template(format!(
+                        "tsuki_binary_get<uint{}_t>({{0}}, {})", bits, big)))
+                    .ret(&format!("Uint{}", bits), ty);
+            }
+            self.reg(name, m);
+        }
+    }
+
+    /// `TSUKI_EEPROM_SIZE` is defined by the transpiler from the board profile.
+    fn init_eeprom(&mut self) {
+        self.reg("eeprom", PkgMap::new(Some("EEPROM.h"))
+            .with_prelude_file("eeprom.h")
+            .fun("Read",   FnMap::Template("EEPROM.read({0})".into()))
+            .fun("Write",  FnMap::Template("do { EEPROM.write({0}, {1}); TSUKI_EEPROM_COMMIT(); } while(0)".into()))
+            .fun("Update", FnMap::Template("tsuki_eeprom_Update({0}, {1})".into()))
+            .fun("Get",    FnMap::Template("EEPROM.get({0}, *{1})".into()))
+            .fun("Put",    FnMap::Template("do { EEPROM.put({0}, {1}); TSUKI_EEPROM_COMMIT(); } while(0)".into()))
+            .fun("Length", FnMap::Direct("TSUKI_EEPROM_SIZE".into()))
+            .cst("Size",   "TSUKI_EEPROM_SIZE")
+            .ret("Read",   Type::Byte)
+            .ret("Length", Type::Int)
+        );
+    }
+
+    /// ESP-only; on ESP8266 the transpiler swaps in the core's header names.
+    fn init_wifi(&mut self) {
+        self.reg("wifi", PkgMap::new(Some("WiFi.h"))
+            .with_arch(ArchRule::new(&["esp32", "esp8266"], &[]))
+            .fun("Begin",      FnMap::Template("WiFi.begin(String({0}).c_str(), String({1}).c_str())".into()))
+            .fun("Disconnect", FnMap::Direct("WiFi.disconnect()".into()))
+            .fun("Status",     FnMap::Direct("WiFi.status()".into()))
+            .fun("Connected",  FnMap::Direct("(WiFi.status() == WL_CONNECTED)".into()))
+            .fun("LocalIP",    FnMap::Direct("WiFi.localIP().toString()".into()))
+            .fun("RSSI",       FnMap::Direct("WiFi.RSSI()".into()))
+            .cst("StatusIdle",          "WL_IDLE_STATUS")
+            .cst("StatusNoSSID",        "WL_NO_SSID_AVAIL")
+            .cst("StatusConnected",     "WL_CONNECTED")
+            .cst("StatusConnectFailed", "WL_CONNECT_FAILED")
+            .cst("StatusDisconnected",  "WL_DISCONNECTED")
+            .ret("Status",     Type::Int)
+            .ret("Connected",  Type::Bool)
+            .ret("LocalIP",    Type::String)
+            .ret("RSSI",       Type::Int)
+        );
+    }
+
+    /// `net/http` client calls; each returns a `{StatusCode, Body}` response,
+    /// with a negative status for transport errors.
+    fn init_http(&mut self) {
+        let resp = || Type::Named("tsuki_http_Response".into());
+        self.reg("http", PkgMap::new(Some("HTTPClient.h"))
+            .with_arch(ArchRule::new(&["esp32", "esp8266"], &[]))
+            .with_prelude_file("http.h")
+            .fun("Get",  FnMap::Template("tsuki_http_Do(\"GET\", {0}, \"\", \"\")".into()))
+            .fun("Post", FnMap::Template("tsuki_http_Do(\"POST\", {0}, {1}, {2})".into()))
+            .cst("StatusOK",                  "200")
+            .cst("StatusCreated",             "201")
+            .cst("StatusNoContent",           "204")
+            .cst("StatusBadRequest",          "400")
+            .cst("StatusNotFound",            "404")
+            .cst("StatusInternalServerError", "500")
+            .ret("Get",  resp())
+            .ret("Post", resp())
+        );
+    }
+
     fn init_arduino(&mut self) {
         self.reg("arduino", PkgMap::new(Some("Arduino.h"))
             // ── Digital / analog I/O (camelCase + PascalCase aliases) ────────
@@ -309,6 +912,9 @@ impl Runtime {
             .fun("Interrupts",        FnMap::Direct("interrupts()".into()))
             .fun("noInterrupts",      FnMap::Direct("noInterrupts()".into()))
             .fun("NoInterrupts",      FnMap::Direct("noInterrupts()".into()))
+            // Return from a `//tsuki:isr VECTOR naked` handler.
+            .fun("reti",              FnMap::Direct("reti()".into()))
+            .fun("Reti",              FnMap::Direct("reti()".into()))
             // ── Serial (convenience wrappers on arduino package) ─────────────
             .fun("SerialBegin",       FnMap::Template("Serial.begin({0})".into()))
             .fun("serialBegin",       FnMap::Template("Serial.begin({0})".into()))
@@ -416,6 +1022,271 @@ impl Runtime {
         self.reg("LiquidCrystal",m);
     }
 
+    /// USB HID keyboard (Keyboard.h); needs a board that enumerates over its
+    /// own USB port, such as the Leonardo, Micro or the SAMD boards.
+    fn init_keyboard(&mut self) {
+        self.reg("keyboard", PkgMap::new(Some("Keyboard.h"))
+            .needs(&["native_usb"])
+            .fun("Begin",      FnMap::Direct("Keyboard.begin()".into()))
+            .fun("End",        FnMap::Direct("Keyboard.end()".into()))
+            .fun("Press",      FnMap::Template("Keyboard.press({0})".into()))
+            .fun("Release",    FnMap::Template("Keyboard.release({0})".into()))
+            .fun("ReleaseAll", FnMap::Direct("Keyboard.releaseAll()".into()))
+            .fun("Write",      FnMap::Template("Keyboard.write({0})".into()))
+            .fun("Print",      FnMap::Template("Keyboard.print({0})".into()))
+            .fun("Println",    FnMap::Template("Keyboard.println({0})".into()))
+            .cst("KeyLeftCtrl",    "KEY_LEFT_CTRL")
+            .cst("KeyLeftShift",   "KEY_LEFT_SHIFT")
+            .cst("KeyLeftAlt",     "KEY_LEFT_ALT")
+            .cst("KeyLeftGUI",     "KEY_LEFT_GUI")
+            .cst("KeyRightCtrl",   "KEY_RIGHT_CTRL")
+            .cst("KeyRightShift",  "KEY_RIGHT_SHIFT")
+            .cst("KeyRightAlt",    "KEY_RIGHT_ALT")
+            .cst("KeyRightGUI",    "KEY_RIGHT_GUI")
+            .cst("KeyUp",          "KEY_UP_ARROW")
+            .cst("KeyDown",        "KEY_DOWN_ARROW")
+            .cst("KeyLeft",        "KEY_LEFT_ARROW")
+            .cst("KeyRight",       "KEY_RIGHT_ARROW")
+            .cst("KeyBackspace",   "KEY_BACKSPACE")
+            .cst("KeyTab",         "KEY_TAB")
+            .cst("KeyReturn",      "KEY_RETURN")
+            .cst("KeyEsc",         "KEY_ESC")
+            .cst("KeyInsert",      "KEY_INSERT")
+            .cst("KeyDelete",      "KEY_DELETE")
+            .cst("KeyPageUp",      "KEY_PAGE_UP")
+            .cst("KeyPageDown",    "KEY_PAGE_DOWN")
+            .cst("KeyHome",        "KEY_HOME")
+            .cst("KeyEnd",         "KEY_END")
+            .cst("KeyCapsLock",    "KEY_CAPS_LOCK")
+            .cst("KeyF1",          "KEY_F1")
+            .cst("KeyF2",          "KEY_F2")
+            .cst("KeyF3",          "KEY_F3")
+            .cst("KeyF4",          "KEY_F4")
+            .cst("KeyF5",          "KEY_F5")
+            .cst("KeyF6",          "KEY_F6")
+            .cst("KeyF7",          "KEY_F7")
+            .cst("KeyF8",          "KEY_F8")
+            .cst("KeyF9",          "KEY_F9")
+            .cst("KeyF10",         "KEY_F10")
+            .cst("KeyF11",         "KEY_F11")
+            .cst("KeyF12",         "KEY_F12")
+            .ret("Press",      Type::Int)
+            .ret("Release",    Type::Int)
+            .ret("Write",      Type::Int)
+        );
+    }
+
+    /// USB HID mouse (Mouse.h), on the same boards as `keyboard`.
+    fn init_mouse(&mut self) {
+        self.reg("mouse", PkgMap::new(Some("Mouse.h"))
+            .needs(&["native_usb"])
+            .fun("Begin",     FnMap::Direct("Mouse.begin()".into()))
+            .fun("End",       FnMap::Direct("Mouse.end()".into()))
+            .fun("Move",      FnMap::Template("Mouse.move({0}, {1}, 0)".into()))
+            .fun("Scroll",    FnMap::Template("Mouse.move(0, 0, {0})".into()))
+            .fun("Click",     FnMap::Template("Mouse.click({0})".into()))
+            .fun("Press",     FnMap::Template("Mouse.press({0})".into()))
+            .fun("Release",   FnMap::Template("Mouse.release({0})".into()))
+            .fun("IsPressed", FnMap::Template("Mouse.isPressed({0})".into()))
+            .cst("Left",      "MOUSE_LEFT")
+            .cst("Right",     "MOUSE_RIGHT")
+            .cst("Middle",    "MOUSE_MIDDLE")
+            .ret("IsPressed", Type::Bool)
+        );
+    }
+
+    /// SD cards over SPI (SD.h). `Open` returns a `File`; the remaining
+    /// functions are its methods, called on the file variable.
+    fn init_sd(&mut self) {
+        let path = |call: &str| FnMap::Template(format!("SD.{}(String({{0}}).c_str())", call));
+        self.reg("sd", PkgMap::new(Some("SD.h"))
+            .fun("Begin",     FnMap::Template("SD.begin({0})".into()))
+            .fun("Open",      FnMap::Template("SD.open(String({0}).c_str(), {1})".into()))
+            .fun("Exists",    path("exists"))
+            .fun("Remove",    path("remove"))
+            .fun("Mkdir",     path("mkdir"))
+            .fun("ReadLine",  FnMap::Template("{0}.readStringUntil('\\n')".into()))
+            .fun("Read",      FnMap::Template("{0}.read()".into()))
+            .fun("Write",     FnMap::Template("{0}.print({1})".into()))
+            .fun("Println",   FnMap::Template("{0}.println({1})".into()))
+            .fun("Available", FnMap::Template("{0}.available()".into()))
+            .fun("Size",      FnMap::Template("{0}.size()".into()))
+            .fun("Flush",     FnMap::Template("{0}.flush()".into()))
+            .fun("Close",     FnMap::Template("{0}.close()".into()))
+            .fun("Valid",     FnMap::Template("((bool){0})".into()))
+            .cst("FileRead",  "FILE_READ")
+            .cst("FileWrite", "FILE_WRITE")
+            .ret("Begin",     Type::Bool)
+            .ret("Open",      Type::Named("sd.File".into()))
+            .ret("Exists",    Type::Bool)
+            .ret("Remove",    Type::Bool)
+            .ret("Mkdir",     Type::Bool)
+            .ret("ReadLine",  Type::String)
+            .ret("Read",      Type::Int)
+            .ret("Available", Type::Int)
+            .ret("Size",      Type::Int)
+            .ret("Valid",     Type::Bool)
+        );
+    }
+
+    /// DS3231 / DS1307 clocks through RTClib. `Now` returns a `time.Time`.
+    fn init_rtc(&mut self) {
+        self.reg("rtc", PkgMap::new(Some("RTClib.h"))
+            .with_prelude_file("rtc.h")
+            .fun("Begin",       FnMap::Template("tsuki_rtc_Begin({0})".into()))
+            .fun("Now",         FnMap::Direct("tsuki_rtc_Now()".into()))
+            .fun("SetTime",     FnMap::Template("tsuki_rtc_SetTime({0})".into()))
+            .fun("Temperature", FnMap::Direct("tsuki_rtc_Temperature()".into()))
+            .fun("LostPower",   FnMap::Direct("tsuki_rtc_LostPower()".into()))
+            .cst("DS3231",      "0")
+            .cst("DS1307",      "1")
+            .ret("Begin",       Type::Bool)
+            .ret("Now",         Type::Named("time.Time".into()))
+            .ret("Temperature", Type::Float32)
+            .ret("LostPower",   Type::Bool)
+        );
+    }
+
+    fn init_dht(&mut self) {
+        self.reg("dht", PkgMap::new(Some("DHT.h"))
+            .with_arduino_lib("DHT sensor library")
+            .with_prelude_file("dht.h")
+            .fun("New",             FnMap::Template("DHT({0}, {1})".into()))
+            .fun("Begin",           FnMap::Template("{0}.begin()".into()))
+            .fun("ReadTemperature", FnMap::Template("tsuki_dht_Check({0}.readTemperature(), {err})".into()))
+            .fun("ReadHumidity",    FnMap::Template("tsuki_dht_Check({0}.readHumidity(), {err})".into()))
+            .cst("DHT11",           "DHT11")
+            .cst("DHT21",           "DHT21")
+            .cst("DHT22",           "DHT22")
+            .cst("ErrRead",         "tsuki_dht_ErrRead")
+            .ret("ReadTemperature", Type::Float32)
+            .ret("ReadHumidity",    Type::Float32)
+        );
+    }
+
+    fn init_onewire(&mut self) {
+        self.reg("onewire", PkgMap::new(Some("OneWire.h"))
+            .with_arduino_lib("OneWire")
+            .fun("New",   FnMap::Template("OneWire({0})".into()))
+            .fun("Reset", FnMap::Template("{0}.reset()".into()))
+            .fun("Skip",  FnMap::Template("{0}.skip()".into()))
+            .fun("Write", FnMap::Template("{0}.write({1})".into()))
+            .fun("Read",  FnMap::Template("{0}.read()".into()))
+            .ret("Reset", Type::Bool)
+            .ret("Read",  Type::Byte)
+        );
+    }
+
+    fn init_ds18b20(&mut self) {
+        // The sensor owns its bus (see prelude/ds18b20.h), so it lives on the heap.
+        self.reg("ds18b20", PkgMap::new(Some("DallasTemperature.h"))
+            .with_arduino_lib("DallasTemperature")
+            .with_prelude_file("ds18b20.h")
+            .fun("New",             FnMap::Template("new tsuki_ds18b20({0})".into()))
+            .fun("Begin",           FnMap::Template("{0}->dt.begin()".into()))
+            .fun("Count",           FnMap::Template("{0}->dt.getDeviceCount()".into()))
+            .fun("ReadTemperature", FnMap::Template("tsuki_ds18b20_Read({0}, {err})".into()))
+            .cst("ErrDisconnected", "tsuki_ds18b20_ErrDisconnected")
+            .ret("Count",           Type::Int)
+            .ret("ReadTemperature", Type::Float32)
+        );
+    }
+
+    fn init_hcsr04(&mut self) {
+        // The timeout is a Go duration (ns); pulseIn wants µs.
+        self.reg("hcsr04", PkgMap::new(None)
+            .with_prelude_file("hcsr04.h")
+            .fun("TriggerEchoDistanceCm", FnMap::Template("tsuki_hcsr04_DistanceCm({0}, {1}, {2|div:1000}, {err})".into()))
+            .cst("ErrTimeout",            "tsuki_hcsr04_ErrTimeout")
+            .ret("TriggerEchoDistanceCm", Type::Float32)
+        );
+    }
+
+    fn init_stepper(&mut self) {
+        self.reg("stepper", PkgMap::new(Some("Stepper.h"))
+            .with_arduino_lib("Stepper")
+            .fun("New",      FnMap::Variadic("Stepper({args})".into()))
+            .fun("SetSpeed", FnMap::Template("{0}.setSpeed({1})".into()))
+            .fun("Step",     FnMap::Template("{0}.step({1})".into()))
+        );
+    }
+
+    fn init_accelstepper(&mut self) {
+        self.reg("accelstepper", PkgMap::new(Some("AccelStepper.h"))
+            .with_arduino_lib("AccelStepper")
+            .fun("New",                FnMap::Variadic("AccelStepper({args})".into()))
+            .fun("SetMaxSpeed",        FnMap::Template("{0}.setMaxSpeed({1})".into()))
+            .fun("SetAcceleration",    FnMap::Template("{0}.setAcceleration({1})".into()))
+            .fun("SetSpeed",           FnMap::Template("{0}.setSpeed({1})".into()))
+            .fun("MoveTo",             FnMap::Template("{0}.moveTo({1})".into()))
+            .fun("Move",               FnMap::Template("{0}.move({1})".into()))
+            .fun("Run",                FnMap::Template("{0}.run()".into()))
+            .fun("RunSpeed",           FnMap::Template("{0}.runSpeed()".into()))
+            .fun("RunToPosition",      FnMap::Template("{0}.runToPosition()".into()))
+            .fun("Stop",               FnMap::Template("{0}.stop()".into()))
+            .fun("DistanceToGo",       FnMap::Template("{0}.distanceToGo()".into()))
+            .fun("CurrentPosition",    FnMap::Template("{0}.currentPosition()".into()))
+            .fun("SetCurrentPosition", FnMap::Template("{0}.setCurrentPosition({1})".into()))
+            .fun("IsRunning",          FnMap::Template("{0}.isRunning()".into()))
+            .cst("Driver",             "AccelStepper::DRIVER")
+            .cst("Full2Wire",          "AccelStepper::FULL2WIRE")
+            .cst("Full4Wire",          "AccelStepper::FULL4WIRE")
+            .cst("Half4Wire",          "AccelStepper::HALF4WIRE")
+            .ret("Run",                Type::Bool)
+            .ret("RunSpeed",           Type::Bool)
+            .ret("DistanceToGo",       Type::Int)
+            .ret("CurrentPosition",    Type::Int)
+            .ret("IsRunning",          Type::Bool)
+        );
+    }
+
+    /// FreeRTOS queues and semaphores for ESP32 sketches. Timeouts are in
+    /// ticks: `Ms` converts, `Forever` blocks until the call succeeds.
+    fn init_freertos(&mut self) {
+        self.reg("freertos", PkgMap::new(None)
+            .with_arch(ArchRule::new(&["esp32"], &[]))
+            .with_prelude_file("freertos.h")
+            .fun("NewQueue",     FnMap::Template("xQueueCreate({0}, {1})".into()))
+            .fun("Send",         FnMap::Template("tsuki_freertos_Send({0}, {1}, {2})".into()))
+            .fun("Receive",      FnMap::Template("(xQueueReceive({0}, {1}, {2}) == pdTRUE)".into()))
+            .fun("Waiting",      FnMap::Template("uxQueueMessagesWaiting({0})".into()))
+            .fun("NewMutex",     FnMap::Direct("xSemaphoreCreateMutex()".into()))
+            .fun("NewSemaphore", FnMap::Direct("xSemaphoreCreateBinary()".into()))
+            .fun("Take",         FnMap::Template("(xSemaphoreTake({0}, {1}) == pdTRUE)".into()))
+            .fun("Give",         FnMap::Template("xSemaphoreGive({0})".into()))
+            .fun("Delay",        FnMap::Template("vTaskDelay({0})".into()))
+            .fun("Yield",        FnMap::Direct("taskYIELD()".into()))
+            .fun("CoreID",       FnMap::Direct("xPortGetCoreID()".into()))
+            .fun("Ms",           FnMap::Template("pdMS_TO_TICKS({0})".into()))
+            .cst("Forever",      "portMAX_DELAY")
+            .ret("Send",         Type::Bool)
+            .ret("Receive",      Type::Bool)
+            .ret("Waiting",      Type::Int)
+            .ret("Take",         Type::Bool)
+            .ret("CoreID",       Type::Int)
+        );
+    }
+
+    /// Go's `testing.T`, for `_test.go` files built for the host by
+    /// `tsuki test`; the C++ side is the shim in `host/tsuki_test.h`.
+    fn init_testing(&mut self) {
+        let mut pkg = PkgMap::new(Some("tsuki_test.h"))
+            .with_class("tsuki_T")
+            .with_arch(ArchRule::new(&["host"], &[]))
+            .fun("Fail",    FnMap::Template("tsuki_testing_Fail({0})".into()))
+            .fun("FailNow", FnMap::Template("tsuki_testing_FailNow({0})".into()))
+            .fun("SkipNow", FnMap::Template("tsuki_testing_SkipNow({0})".into()))
+            .fun("Failed",  FnMap::Template("{0}.failed".into()))
+            .fun("Name",    FnMap::Template("String({0}.name)".into()))
+            .fun("Helper",  FnMap::Template("(void) {0}".into()))
+            .ret("Failed",  Type::Bool)
+            .ret("Name",    Type::String);
+        for f in ["Log", "Logf", "Error", "Errorf", "Fatal", "Fatalf", "Skip", "Skipf"] {
+            pkg = pkg.fun(f, FnMap::Variadic(format!("tsuki_testing_{}({{args}})", f)));
+        }
+        self.reg("testing", pkg);
+    }
+
     // ── Lookup API ────────────────────────────────────────────────────────────
 
     pub fn pkg(&self, name: &str) -> Option<&PkgMap> {
@@ -437,6 +1308,46 @@ impl Runtime {
         hdrs
     }
 
+    /// arduino-cli libraries the given packages compile against, sorted;
+    /// `name@version` when the package pins a version.
+    pub fn arduino_libs_for(&self, pkgs: &[&str]) -> Vec<String> {
+        let mut libs: Vec<_> = pkgs.iter()
+            .filter_map(|p| self.packages.get(*p))
+            .filter_map(|m| match (&m.arduino_lib, &m.arduino_lib_version) {
+                (Some(lib), Some(v)) => Some(format!("{}@{}", lib, v)),
+                (lib, _) => lib.clone(),
+            })
+            .collect();
+        libs.sort();
+        libs.dedup();
+        libs
+    }
+
+    /// The `src/` directories of the loaded libraries that vendor headers,
+    /// sorted; the compiler needs them on its include path.
+    pub fn include_dirs(&self) -> Vec<PathBuf> {
+        let mut dirs: Vec<_> = self.packages.values().filter_map(|m| m.include_dir.clone()).collect();
+        dirs.sort();
+        dirs.dedup();
+        dirs
+    }
+
+    /// The mappings of package `name`, or None when it isn't registered.
+    pub fn describe(&self, name: &str) -> Option<PkgDoc> {
+        let pkg = self.packages.get(name)?;
+        let sorted = |items: Vec<(String, String)>| { let mut v = items; v.sort(); v };
+        Some(PkgDoc {
+            name:        name.to_owned(),
+            header:      pkg.header.clone(),
+            arduino_lib: pkg.arduino_lib.clone(),
+            functions:   sorted(pkg.functions.iter().map(|(go, m)| (go.clone(), m.template().to_owned()))
+                .chain(variants(&pkg.fn_variants, |m| m.template().to_owned())).collect()),
+            constants:   sorted(pkg.constants.iter().map(|(go, c)| (go.clone(), c.clone()))
+                .chain(variants(&pkg.const_variants, Clone::clone)).collect()),
+            types:       sorted(pkg.types.iter().map(|(go, t)| (go.clone(), t.clone())).collect()),
+        })
+    }
+
     /// List all currently registered package names (builtin + external).
     pub fn list_packages(&self) -> Vec<&str> {
         let mut names: Vec<&str> = self.packages.keys().map(|s| s.as_str()).collect();
@@ -447,6 +1358,32 @@ impl Runtime {
 
 // ── Board profiles ────────────────────────────────────────────────────────────
 
+/// Board features a package can require, with the name used in diagnostics.
+pub const FEATURES: &[(&str, &str)] = &[
+    ("wifi",       "WiFi"),
+    ("bluetooth",  "Bluetooth"),
+    ("native_usb", "native USB"),
+    ("fpu",        "a hardware FPU"),
+    ("eeprom",     "EEPROM"),
+    ("multicore",  "a second core"),
+];
+
+/// What each pin of a board can do, by Arduino pin number.
+#[derive(Debug, Clone, Copy)]
+pub struct Pins {
+    /// Pins `0..digital` work with pinMode / digitalRead / digitalWrite.
+    pub digital:    u8,
+    pub pwm:        &'static [u8],
+    /// Pins analogRead accepts; in `A0, A1, …` order when `channels` is set.
+    pub analog:     &'static [u8],
+    /// analogRead also takes the channel index (`analogRead(3)` is A3).
+    pub channels:   bool,
+    pub input_only: &'static [u8],
+    /// Default bus pins: SDA, SCL and MOSI, MISO, SCK.
+    pub i2c:        Option<[u8; 2]>,
+    pub spi:        Option<[u8; 3]>,
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Board {
     pub id:          String,
@@ -457,29 +1394,269 @@ pub struct Board {
     pub ram_kb:      u32,
     pub clock_mhz:   u32,
     pub extra_flags: Vec<String>,
+    /// wifi, bluetooth, native_usb, fpu.
+    pub features:    Vec<String>,
+    pub cores:       u32,
+    /// None when the board has no EEPROM library.
+    pub eeprom_bytes: Option<u32>,
+}
+
+impl From<&crate::boards::BoardDef> for Board {
+    fn from(def: &crate::boards::BoardDef) -> Self {
+        Board {
+            id:          def.id.clone(),
+            name:        def.name.clone(),
+            fqbn:        def.fqbn.clone(),
+            cpu:         def.cpu.clone(),
+            flash_kb:    def.flash_kb,
+            ram_kb:      def.ram_kb,
+            clock_mhz:   def.clock_mhz,
+            extra_flags: Vec::new(),
+            features:    def.features.clone(),
+            cores:       def.cores,
+            eeprom_bytes: def.eeprom_bytes,
+        }
+    }
 }
 
 impl Board {
+    /// The boards of the shared database (boards.toml).
     pub fn catalog() -> Vec<Board> {
-        vec![
-            Board { id: "uno".into(),        name: "Arduino Uno".into(),              fqbn: "arduino:avr:uno".into(),                  cpu: "ATmega328P".into(),   flash_kb: 32,   ram_kb: 2,    clock_mhz: 16,  extra_flags: vec![] },
-            Board { id: "nano".into(),        name: "Arduino Nano".into(),             fqbn: "arduino:avr:nano".into(),                 cpu: "ATmega328P".into(),   flash_kb: 32,   ram_kb: 2,    clock_mhz: 16,  extra_flags: vec![] },
-            Board { id: "nano_every".into(),  name: "Arduino Nano Every".into(),       fqbn: "arduino:megaavr:nona4809".into(),         cpu: "ATmega4809".into(),   flash_kb: 48,   ram_kb: 6,    clock_mhz: 20,  extra_flags: vec![] },
-            Board { id: "mega".into(),        name: "Arduino Mega 2560".into(),        fqbn: "arduino:avr:mega".into(),                 cpu: "ATmega2560".into(),   flash_kb: 256,  ram_kb: 8,    clock_mhz: 16,  extra_flags: vec![] },
-            Board { id: "micro".into(),       name: "Arduino Micro".into(),            fqbn: "arduino:avr:micro".into(),                cpu: "ATmega32U4".into(),   flash_kb: 32,   ram_kb: 2,    clock_mhz: 16,  extra_flags: vec![] },
-            Board { id: "leonardo".into(),    name: "Arduino Leonardo".into(),         fqbn: "arduino:avr:leonardo".into(),             cpu: "ATmega32U4".into(),   flash_kb: 32,   ram_kb: 2,    clock_mhz: 16,  extra_flags: vec![] },
-            Board { id: "due".into(),         name: "Arduino Due".into(),              fqbn: "arduino:sam:arduino_due_x".into(),        cpu: "AT91SAM3X8E".into(),  flash_kb: 512,  ram_kb: 96,   clock_mhz: 84,  extra_flags: vec![] },
-            Board { id: "zero".into(),        name: "Arduino Zero".into(),             fqbn: "arduino:samd:arduino_zero_native".into(), cpu: "ATSAMD21G18A".into(), flash_kb: 256,  ram_kb: 32,   clock_mhz: 48,  extra_flags: vec![] },
-            Board { id: "mkr1000".into(),     name: "Arduino MKR WiFi 1000".into(),   fqbn: "arduino:samd:mkr1000".into(),             cpu: "ATSAMD21G18A".into(), flash_kb: 256,  ram_kb: 32,   clock_mhz: 48,  extra_flags: vec![] },
-            Board { id: "esp32".into(),       name: "ESP32 Dev Module".into(),         fqbn: "esp32:esp32:esp32".into(),                cpu: "Xtensa LX6".into(),   flash_kb: 4096, ram_kb: 520,  clock_mhz: 240, extra_flags: vec![] },
-            Board { id: "esp8266".into(),     name: "ESP8266 NodeMCU".into(),          fqbn: "esp8266:esp8266:nodemcuv2".into(),        cpu: "ESP8266".into(),      flash_kb: 4096, ram_kb: 80,   clock_mhz: 80,  extra_flags: vec![] },
-            Board { id: "pico".into(),        name: "Raspberry Pi Pico (RP2040)".into(), fqbn: "rp2040:rp2040:rpipico".into(),          cpu: "RP2040".into(),       flash_kb: 2048, ram_kb: 264,  clock_mhz: 133, extra_flags: vec![] },
-            Board { id: "teensy41".into(),    name: "Teensy 4.1".into(),               fqbn: "teensy:avr:teensy41".into(),              cpu: "iMXRT1062".into(),    flash_kb: 8192, ram_kb: 1024, clock_mhz: 600, extra_flags: vec![] },
-            Board { id: "portenta_h7".into(), name: "Arduino Portenta H7".into(),      fqbn: "arduino:mbed_portenta:envie_m7".into(),   cpu: "STM32H747XI".into(),  flash_kb: 2048, ram_kb: 8192, clock_mhz: 480, extra_flags: vec![] },
-        ]
+        crate::boards::catalog().iter().map(Board::from).collect()
     }
 
     pub fn find(id: &str) -> Option<Board> {
         Self::catalog().into_iter().find(|b| b.id == id)
     }
-}
\ No newline at end of file
+
+    /// Number of CPU cores user code can run on.
+    pub fn cores(&self) -> u32 {
+        self.cores
+    }
+
+    /// EEPROM size in bytes, or None when the board has no EEPROM library.
+    pub fn eeprom_bytes(&self) -> Option<u32> {
+        self.eeprom_bytes
+    }
+
+    /// Whether the board has `feature`, one of the `FEATURES` ids.
+    pub fn has(&self, feature: &str) -> bool {
+        match feature {
+            "eeprom"    => self.eeprom_bytes().is_some(),
+            "multicore" => self.cores() > 1,
+            _           => self.features.iter().any(|f| f == feature),
+        }
+    }
+
+    /// Arduino platform architecture, the middle field of the FQBN.
+    pub fn arch(&self) -> &str {
+        self.fqbn.split(':').nth(1).unwrap_or("")
+    }
+
+    /// Whether the board is an 8-bit AVR (classic, megaAVR or tinyAVR).
+    /// Teensyduino files its ARM boards under `teensy:avr` too.
+    pub fn is_avr(&self) -> bool {
+        matches!(self.arch(), "avr" | "megaavr") && !self.fqbn.starts_with("teensy:")
+    }
+
+    /// Width of C++ `int` on the board: 16 bits on AVR, 32 elsewhere.
+    pub fn int_bits(&self) -> u32 {
+        if self.is_avr() { 16 } else { 32 }
+    }
+
+    /// Pin capabilities, for the boards whose pinout is tabulated.
+    pub fn pins(&self) -> Option<Pins> {
+        const AVR_PWM: &[u8] = &[3, 5, 6, 9, 10, 11];
+        const ESP32_OUT: &[u8] = &[0, 1, 2, 3, 4, 5, 12, 13, 14, 15, 16, 17, 18, 19,
+                                   21, 22, 23, 25, 26, 27, 32, 33];
+        const PICO_ALL: &[u8] = &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14,
+                                  15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29];
+        let p = match self.id.as_str() {
+            "uno" => Pins {
+                digital: 20, pwm: AVR_PWM, analog: &[14, 15, 16, 17, 18, 19], channels: true,
+                input_only: &[], i2c: Some([18, 19]), spi: Some([11, 12, 13]),
+            },
+            // A6/A7 are analog inputs only.
+            "nano" => Pins {
+                digital: 20, pwm: AVR_PWM, analog: &[14, 15, 16, 17, 18, 19, 20, 21], channels: true,
+                input_only: &[], i2c: Some([18, 19]), spi: Some([11, 12, 13]),
+            },
+            "nano_every" => Pins {
+                digital: 22, pwm: &[3, 5, 6, 9, 10], analog: &[14, 15, 16, 17, 18, 19, 20, 21],
+                channels: true, input_only: &[], i2c: Some([18, 19]), spi: Some([11, 12, 13]),
+            },
+            "mega" => Pins {
+                digital: 70, pwm: &[2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 44, 45, 46],
+                analog: &[54, 55, 56, 57, 58, 59, 60, 61, 62, 63, 64, 65, 66, 67, 68, 69],
+                channels: true, input_only: &[], i2c: Some([20, 21]), spi: Some([51, 50, 52]),
+            },
+            // A6..A11 share D4, D6, D8, D9, D10 and D12.
+            "leonardo" | "micro" => Pins {
+                digital: 30, pwm: &[3, 5, 6, 9, 10, 11, 13],
+                analog: &[18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29],
+                channels: true, input_only: &[], i2c: Some([2, 3]), spi: Some([16, 14, 15]),
+            },
+            // GPIO 6..11 drive the SPI flash; 34..39 have no output driver.
+            "esp32" => Pins {
+                digital: 40, pwm: ESP32_OUT,
+                analog: &[32, 33, 34, 35, 36, 37, 38, 39, 0, 2, 4, 12, 13, 14, 15, 25, 26, 27],
+                channels: false, input_only: &[34, 35, 36, 37, 38, 39],
+                i2c: Some([21, 22]), spi: Some([23, 19, 18]),
+            },
+            "pico" => Pins {
+                digital: 30, pwm: PICO_ALL, analog: &[26, 27, 28, 29], channels: false,
+                input_only: &[], i2c: Some([4, 5]), spi: Some([19, 16, 18]),
+            },
+            _ => return None,
+        };
+        Some(p)
+    }
+
+    /// EEPROM emulated in flash: needs `EEPROM.begin()` and a commit per write.
+    pub fn eeprom_emulated(&self) -> bool {
+        matches!(self.arch(), "esp32" | "esp8266" | "rp2040")
+    }
+}
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(t: &str, args: &[&str]) -> String {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        FnMap::Template(t.into()).apply(&args)
+    }
+
+    #[test]
+    fn test_template_filters() {
+        assert_eq!(apply("delayMicroseconds({0|mul:1000})", &["5"]), "delayMicroseconds(5000)");
+        assert_eq!(apply("delayMicroseconds({0|mul:1000})", &["ms"]), "delayMicroseconds(((ms) * 1000))");
+        assert_eq!(apply("{self}.fill({1|upper})", &["strip", "red"]), "strip.fill(RED)");
+        assert_eq!(apply("show({0|lower})", &["String(\"HI\")"]), "show(String(\"hi\"))");
+        assert_eq!(apply("write({0|mul:16|hex})", &["15"]), "write(0xF0)");
+        assert_eq!(apply("{ f({0}); for(;;) {} }", &["x"]), "{ f(x); for(;;) {} }");
+        assert_eq!(apply("f({0}, {1})", &["{1}"]), "f({1}, {1})");
+    }
+
+    #[test]
+    fn test_prelude_overrides() {
+        let dir = std::env::temp_dir().join(format!("tsuki-prelude-{}", std::process::id()));
+        Prelude::dump(&dir).unwrap();
+        let custom = format!("{}\n// custom split\n", prelude::version_line());
+        std::fs::write(dir.join("strings.h"), &custom).unwrap();
+
+        let mut rt = Runtime::new();
+        rt.set_prelude(Prelude::load(&dir).unwrap());
+        assert_eq!(rt.pkg("strings").unwrap().prelude.as_deref(), Some(custom.as_str()));
+        assert_eq!(rt.prelude().get("error.h"), format!("{}\n{}", prelude::version_line(), prelude::embedded("error.h")));
+
+        std::fs::write(dir.join("bytes.h"), "// tsuki:prelude 0\n").unwrap();
+        let err = Prelude::load(&dir).unwrap_err().to_string();
+        assert!(err.contains("is for version 0, this tsuki ships version 1"), "{}", err);
+        std::fs::remove_file(dir.join("bytes.h")).unwrap();
+        std::fs::write(dir.join("string.h"), &custom).unwrap();
+        assert!(Prelude::load(&dir).unwrap_err().to_string().contains("no prelude file is named `string.h`"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_selected_libs_pinned_version() {
+        let dir = std::env::temp_dir().join(format!("tsuki-pinned-{}", std::process::id()));
+        for v in ["1.2.0", "1.10.0"] {
+            let lib = format!("[package]\nname = \"leds\"\nversion = \"{v}\"\ncpp_header = \"leds_{v}.h\"\n");
+            std::fs::create_dir_all(dir.join("leds").join(v)).unwrap();
+            std::fs::write(dir.join("leds").join(v).join("tsukilib.toml"), lib).unwrap();
+        }
+        let header = |names: &[&str]| {
+            let names: Vec<String> = names.iter().map(|n| n.to_string()).collect();
+            Runtime::with_selected_libs(&dir, &names).pkg("leds").and_then(|p| p.header.clone())
+        };
+        assert_eq!(header(&["leds"]).as_deref(), Some("leds_1.10.0.h"));
+        assert_eq!(header(&["leds@1.2.0"]).as_deref(), Some("leds_1.2.0.h"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_include_dirs() {
+        let dir = std::env::temp_dir().join(format!("tsuki-incdirs-{}", std::process::id()));
+        for (name, vendored) in [("leds", true), ("dht22", false)] {
+            let root = dir.join(name).join("1.0.0");
+            std::fs::create_dir_all(root.join(if vendored { "src" } else { "" })).unwrap();
+            std::fs::write(root.join("tsukilib.toml"), format!("[package]\nname = \"{name}\"\nversion = \"1.0.0\"\n")).unwrap();
+        }
+        let rt = Runtime::with_selected_libs(&dir, &["leds".into(), "dht22".into()]);
+        assert_eq!(rt.include_dirs(), vec![dir.join("leds").join("1.0.0").join("src")]);
+        assert!(Runtime::with_selected_libs(&dir, &["dht22".into()]).include_dirs().is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_arduino_libs_pinned() {
+        let mut rt = Runtime::new();
+        rt.load_lib_from_str("[package]\nname = \"strip\"\nversion = \"1.0.0\"\n\
+            arduino_lib = \"Adafruit NeoPixel\"\narduino_lib_version = \"1.12.3\"\n").unwrap();
+        assert_eq!(rt.arduino_libs_for(&["strip", "dht", "fmt"]), ["Adafruit NeoPixel@1.12.3", "DHT sensor library"]);
+    }
+
+    #[test]
+    fn test_describe_package() {
+        let rt = Runtime::new();
+        let doc = rt.describe("dht").unwrap();
+        assert_eq!(doc.header.as_deref(), Some("DHT.h"));
+        assert!(doc.functions.contains(&("New".into(), "DHT({0}, {1})".into())));
+        assert!(doc.constants.windows(2).all(|w| w[0] <= w[1]));
+        let text = doc.to_string();
+        assert!(text.starts_with("package dht\n  #include <DHT.h>\n"), "{}", text);
+        assert!(text.contains("\nfunctions\n  Begin            {0}.begin()\n"), "{}", text);
+        assert!(rt.describe("nope").is_none());
+    }
+
+    #[test]
+    fn test_template_filters_validated_on_load() {
+        assert!(FnMap::Template("f({0|mul})".into()).validate().is_err());
+        assert!(FnMap::Template("f({0|shout})".into()).validate().is_err());
+        assert!(FnMap::Template("f({0|hex:2})".into()).validate().is_err());
+        let lib = "[package]\nname = \"x\"\nversion = \"1.0.0\"\n\
+                   [[function]]\ngo = \"F\"\ncpp = \"f({0|div:0})\"\n";
+        assert!(Runtime::new().load_lib_from_str(lib).is_err());
+    }
+    #[test]
+    fn test_script_mapping_needs_capability() {
+        let lib = "[package]\nname = \"x\"\nversion = \"1.0.0\"\n\
+                   [[function]]\ngo = \"F\"\nscript = '\"f()\"'\n";
+        let err = Runtime::new().load_lib_from_str(lib).err().unwrap().to_string();
+        assert!(err.contains("capabilities"), "{}", err);
+    }
+
+    #[cfg(feature = "scripting")]
+    #[test]
+    fn test_script_mapping() {
+        let lib = r#"
+            [package]
+            name = "x"
+            version = "1.0.0"
+            capabilities = ["script"]
+
+            [[function]]
+            go = "Begin"
+            script = '''
+                let mask = 0;
+                for a in args { if a.ty == "int" { mask = mask * 2 + 1; } }
+                if args.len() > 1 { "{0}.begin(" + mask + ", {1|mul:1000})" } else { "{0}.begin()" }
+            '''
+        "#;
+        let mut rt = Runtime::new();
+        rt.load_lib_from_str(lib).unwrap();
+        let f = &rt.pkg("x").unwrap().functions["Begin"];
+        let args = ["s".to_string(), "5".to_string()];
+        assert_eq!(f.expand(&args[..1], &[None]).unwrap(), "s.begin()");
+        assert_eq!(f.expand(&args, &[None, Some(Type::Int)]).unwrap(), "s.begin(1, 5000)");
+
+        let runaway = "[package]\nname = \"y\"\nversion = \"1.0.0\"\ncapabilities = [\"script\"]\n\
+                       [[function]]\ngo = \"F\"\nscript = 'loop {}'\n";
+        rt.load_lib_from_str(runaway).unwrap();
+        assert!(rt.pkg("y").unwrap().functions["F"].expand(&[], &[]).is_err());
+    }
+}
+
@@ -0,0 +1,263 @@
+// ─────────────────────────────────────────────────────────────────────────────
+//  tsuki :: runtime :: pkg_lint
+//
+//  `tsuki pkg lint`: checks a tsukilib.toml for the mistakes that otherwise
+//  surface only when someone compiles against the package — placeholders
+//  past the documented argument count, `cpp_header` written as an include
+//  line, functions defined twice, aliases that shadow other packages.
+//
+//  Anything `pkg_loader` rejects is an error; the rest are warnings, each
+//  located at the line of the TOML it is about.
+// ─────────────────────────────────────────────────────────────────────────────
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use crate::error::{tsukiError, Span, Suggestion, Warning};
+use super::pkg_loader::{self, LibFunction, LibManifest};
+use super::pkg_manager::semver::Version;
+use super::{placeholder, Board, Runtime, Targets};
+
+/// The diagnostics for one manifest.
+#[derive(Debug, Default)]
+pub struct Lint {
+    pub errors:   Vec<tsukiError>,
+    pub warnings: Vec<Warning>,
+}
+
+impl Lint {
+    pub fn is_clean(&self) -> bool {
+        self.errors.is_empty() && self.warnings.is_empty()
+    }
+}
+
+/// Lint the manifest text read from `path`. The directory of `path` is
+/// where vendored headers (`src/`) are looked for.
+pub fn lint(toml_str: &str, path: &Path) -> Lint {
+    let mut lint = Lint::default();
+    let file = path.display().to_string();
+    let at = |key: &str, value: &str, nth: usize| locate(toml_str, &file, key, value, nth);
+
+    let manifest: LibManifest = match toml::from_str(toml_str) {
+        Ok(m)  => m,
+        Err(e) => {
+            lint.errors.push(tsukiError::codegen(format!("malformed tsukilib.toml at {}: {}", file, e)));
+            return lint;
+        }
+    };
+    if let Err(e) = pkg_loader::load_from_str(toml_str, path) {
+        lint.errors.push(e);
+    }
+    let pkg = &manifest.package;
+    let error = |lint: &mut Lint, span: Span, msg: String| lint.errors.push(tsukiError::parse(span, msg));
+
+    // ── [package] ─────────────────────────────────────────────────────────────
+    if pkg.version.parse::<Version>().is_err() {
+        error(&mut lint, at("version", &pkg.version, 0),
+            format!("version `{}` is not major.minor.patch", pkg.version));
+    }
+    let mut header_ok = true;
+    if let Some(header) = &pkg.cpp_header {
+        let bare = header.trim().trim_start_matches("#include").trim().trim_matches(['<', '>', '"']);
+        if bare != header {
+            let span = at("cpp_header", header, 0);
+            lint.errors.push(tsukiError::parse(span, format!("cpp_header is the bare file name, not `{}`", header))
+                .with_suggestion(Suggestion::replace(format!("use `{}`", bare), header.len() + 2, format!("\"{}\"", bare))));
+            header_ok = false;
+        } else if ![".h", ".hpp", ".hh"].iter().any(|ext| header.ends_with(ext)) {
+            error(&mut lint, at("cpp_header", header, 0),
+                format!("cpp_header `{}` is not a header (.h, .hpp or .hh)", header));
+            header_ok = false;
+        }
+    }
+    if let Some(lib) = &pkg.arduino_lib {
+        if lib.trim().is_empty() || lib.trim() != lib {
+            error(&mut lint, at("arduino_lib", lib, 0),
+                format!("arduino_lib `{}` must be the library manager name, without surrounding spaces", lib));
+        }
+    }
+
+    let mut warn = |span: Span, msg: String| lint.warnings.push(Warning::new(span, msg));
+    let vendored = |h: &str| path.parent().is_some_and(|dir| dir.join("src").join(h).exists());
+    match (&pkg.cpp_header, &pkg.arduino_lib) {
+        (Some(header), None) if header_ok && !vendored(header) => warn(at("cpp_header", header, 0), format!(
+            "`{}` comes from neither arduino_lib nor the package's src/ directory", header)),
+        (None, Some(lib)) => warn(at("arduino_lib", lib, 0), format!(
+            "arduino_lib `{}` is installed but no cpp_header includes it", lib)),
+        _ => {}
+    }
+
+    // ── [[function]] ──────────────────────────────────────────────────────────
+    let mut seen: BTreeMap<&str, usize> = BTreeMap::new();
+    let mut mapped = Vec::new();
+    for f in &manifest.functions {
+        let nth = *seen.entry(&f.go).and_modify(|n| *n += 1).or_insert(0);
+        let span = at("go", &f.go, nth);
+        let key = (f.go.as_str(), f.targets());
+        if mapped.contains(&key) {
+            warn(span.clone(), format!("function `{}` is defined more than once{}; the last one wins", f.go, on(&key.1)));
+        } else {
+            mapped.push(key);
+        }
+        for board in f.boards.iter().filter(|b| Board::find(b).is_none()) {
+            warn(span.clone(), format!("function `{}`: unknown board `{}`", f.go, board));
+        }
+        if f.script.is_some() { continue }
+        if f.cpp.trim().is_empty() {
+            lint.errors.push(tsukiError::parse(span, format!("function `{}` has no `cpp` template", f.go)));
+            continue;
+        }
+        for msg in template_problems(f) {
+            match msg {
+                Ok(msg)  => warn(span.clone(), format!("function `{}`: {}", f.go, msg)),
+                Err(msg) => lint.errors.push(tsukiError::parse(span.clone(), format!("function `{}`: {}", f.go, msg))),
+            }
+        }
+    }
+
+    // ── [[constant]] and aliases ──────────────────────────────────────────────
+    let mut consts = Vec::new();
+    let mut const_seen: BTreeMap<&str, usize> = BTreeMap::new();
+    for c in &manifest.constants {
+        // `go = "X"` lines of functions come first in the count.
+        let fns  = manifest.functions.iter().filter(|f| f.go == c.go).count();
+        let nth  = *const_seen.entry(&c.go).and_modify(|n| *n += 1).or_insert(0);
+        let span = at("go", &c.go, fns + nth);
+        let key = (c.go.as_str(), c.targets());
+        if consts.contains(&key) {
+            warn(span, format!("constant `{}` is defined more than once{}", c.go, on(&key.1)));
+            continue;
+        }
+        if fns > 0 && nth == 0 {
+            warn(span, format!("`{}` is both a function and a constant", c.go));
+        }
+        consts.push(key);
+    }
+    let builtins = Runtime::new();
+    if builtins.pkg(&pkg.name).is_some() {
+        warn(at("name", &pkg.name, 0), format!("package `{}` shadows the built-in package of that name", pkg.name));
+    }
+    let mut aliases = BTreeSet::new();
+    for alias in &manifest.aliases {
+        let span = at("aliases", alias, 0);
+        if *alias == pkg.name {
+            warn(span, format!("alias `{}` is the package name", alias));
+        } else if !aliases.insert(alias.as_str()) {
+            warn(span, format!("alias `{}` is listed twice", alias));
+        } else if builtins.pkg(alias).is_some() {
+            warn(span, format!("alias `{}` shadows the built-in package of that name", alias));
+        }
+    }
+    lint
+}
+
+/// ` for esp32, avr` after a duplicate's name when it is board-specific.
+fn on(targets: &Option<Targets>) -> String {
+    targets.as_ref().map_or(String::new(), |t| format!(" for {}", t.describe()))
+}
+
+/// Placeholder mistakes in a function's template: Err for references past
+/// the declared `args`, Ok for the suspicious rest.
+fn template_problems(f: &LibFunction) -> Vec<std::result::Result<String, String>> {
+    let t = &f.cpp;
+    let mut used = BTreeSet::new();
+    let (mut self_ref, mut zero_ref) = (false, false);
+    for (i, _) in t.match_indices('{') {
+        if let Some((slot, _, _)) = placeholder(&t[i..]) {
+            used.insert(slot);
+            if t[i + 1..].starts_with("self") { self_ref = true } else if slot == 0 { zero_ref = true }
+        }
+    }
+
+    let mut out = Vec::new();
+    if self_ref && zero_ref {
+        out.push(Ok("uses both {self} and {0}, which are the same argument".into()));
+    }
+    if t.contains("{args}") {
+        out.push(Ok("`{args}` is not expanded in package templates; list the arguments as {0}, {1}, …".into()));
+    }
+    match f.args {
+        Some(n) => {
+            if let Some(&slot) = used.iter().find(|&&s| s >= n) {
+                out.push(Err(format!("uses {{{}}} but takes {} argument{}", slot, n, if n == 1 { "" } else { "s" })));
+            }
+            let unused: Vec<String> = (0..n).filter(|s| !used.contains(s)).map(|s| format!("{{{}}}", s)).collect();
+            if !unused.is_empty() {
+                out.push(Ok(format!("never uses argument {}", unused.join(", "))));
+            }
+        }
+        None => {
+            let max = used.last().copied().unwrap_or(0);
+            let skipped: Vec<String> = (0..max).filter(|s| !used.contains(s)).map(|s| format!("{{{}}}", s)).collect();
+            if !skipped.is_empty() {
+                out.push(Ok(format!("skips {} (set `args` if that is intended)", skipped.join(", "))));
+            }
+        }
+    }
+    out
+}
+
+/// Span of the `nth` line assigning (or listing) `"value"` under `key`;
+/// line 0 when there is none.
+fn locate(toml_str: &str, file: &str, key: &str, value: &str, nth: usize) -> Span {
+    let quoted = format!("\"{}\"", value);
+    let mut offset = 0;
+    let mut hits = 0;
+    for (i, line) in toml_str.lines().enumerate() {
+        let trimmed = line.trim_start();
+        let assigns = trimmed.strip_prefix(key).is_some_and(|rest| rest.trim_start().starts_with('='));
+        if let Some(col) = line.find(&quoted).filter(|_| assigns) {
+            if hits == nth {
+                return Span::new(file, i as u32 + 1, col as u32 + 1, offset + col);
+            }
+            hits += 1;
+        }
+        offset += line.len() + 1;
+    }
+    Span::new(file, 0, 0, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lint_manifest() {
+        let toml_str = "aliases = [\"strip\", \"time\"]\n\n[package]\nname = \"strip\"\nversion = \"1.0\"\n\
+            cpp_header = \"<Strip.h>\"\narduino_lib = \"Strip\"\n\n\
+            [[function]]\ngo = \"Fill\"\ncpp = \"{self}.fill({0}, {2})\"\nargs = 2\n\n\
+            [[function]]\ngo = \"Show\"\ncpp = \"{0}.show({2})\"\n\n\
+            [[function]]\ngo = \"Show\"\ncpp = \"{0}.show()\"\n\n\
+            [[constant]]\ngo = \"Show\"\ncpp = \"1\"\n";
+        let lint = lint(toml_str, Path::new("strip/tsukilib.toml"));
+        let errors: Vec<String> = lint.errors.iter().map(|e| e.to_string()).collect();
+        let warnings: Vec<String> = lint.warnings.iter().map(|w| w.to_string()).collect();
+
+        assert!(errors.iter().any(|e| e.contains("strip/tsukilib.toml:5:11  version `1.0` is not major.minor.patch")), "{:?}", errors);
+        assert!(errors.iter().any(|e| e.contains(":6:14  cpp_header is the bare file name, not `<Strip.h>`")), "{:?}", errors);
+        assert!(errors.iter().any(|e| e.contains(":10:6  function `Fill`: uses {2} but takes 2 arguments")), "{:?}", errors);
+        for expected in [
+            "function `Fill`: uses both {self} and {0}",
+            "function `Fill`: never uses argument {1}",
+            ":15:6  function `Show`: skips {1}",
+            ":19:6  function `Show` is defined more than once",
+            ":23:6  `Show` is both a function and a constant",
+            "alias `strip` is the package name",
+            ":1:21  alias `time` shadows the built-in package",
+        ] {
+            assert!(warnings.iter().any(|w| w.contains(expected)), "missing {:?} in {:?}", expected, warnings);
+        }
+
+        let clean = "[package]\nname = \"ok\"\nversion = \"1.0.0\"\n\n[[function]]\ngo = \"F\"\ncpp = \"f({0})\"\nargs = 1\n";
+        assert!(super::lint(clean, Path::new("tsukilib.toml")).is_clean());
+
+        // Board-specific entries of one function are not duplicates.
+        let per_board = format!("{}\n[[function]]\ngo = \"F\"\ncpp = \"g({{0}})\"\nargs = 1\nboards = [\"esp32\"]\n", clean);
+        assert!(super::lint(&per_board, Path::new("tsukilib.toml")).is_clean());
+        let twice = format!("{}\n[[function]]\ngo = \"F\"\ncpp = \"h({{0}})\"\nargs = 1\nboards = [\"esp32\"]\n", per_board);
+        let warnings: Vec<String> = super::lint(&twice, Path::new("tsukilib.toml")).warnings.iter().map(|w| w.to_string()).collect();
+        assert!(warnings.iter().any(|w| w.contains(":17:6  function `F` is defined more than once for esp32")), "{:?}", warnings);
+        let typo = super::lint(&per_board.replace("esp32", "esp23"), Path::new("tsukilib.toml"));
+        assert!(typo.warnings[0].to_string().contains(":11:6  function `F`: unknown board `esp23`"), "{:?}", typo.warnings);
+    }
+}
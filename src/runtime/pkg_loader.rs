@@ -22,15 +22,36 @@
 //      author      = "tsuki-team"
 //      cpp_header  = "Adafruit_NeoPixel.h"   # injected as #include
 //      arduino_lib = "Adafruit NeoPixel"      # installed via arduino-cli
+//      arduino_lib_version = "1.12.3"         # optional pin
+//      arch_deny   = ["sam"]                  # FQBN architectures (or arch_allow)
+//      min_ram_kb  = 2                        # pixel buffer (board features: requires)
+//      cpp_globals = "static uint8_t ws_buf[3 * 60];"   # also cpp_prelude,
+//      cpp_setup   = "ws_buf[0] = 0;"                  # emitted when used
 //
 //      [[function]]
-//      go  = "New"
-//      cpp = "Adafruit_NeoPixel({0}, {1}, NEO_GRB + NEO_KHZ800)"
+//      go   = "New"
+//      cpp  = "Adafruit_NeoPixel({0}, {1}, NEO_GRB + NEO_KHZ800)"
+//      args = 2                                # optional, checked by `pkg lint`
 //
 //      [[function]]
 //      go  = "Begin"
 //      cpp = "{0}.begin()"
 //
+//      [[function]]
+//      go      = "SetDelay"                    # Go ms → C++ µs
+//      cpp     = "{0}.setDelayMicros({1|mul:1000})"
+//      params  = ["uint32"]                    # optional Go signature; calls
+//      returns = []                            # are type-checked against it
+//
+//      [[function]]
+//      go         = "SetBrightnessFast"
+//      cpp        = "{0}.setBrightnessFast({1})"
+//      arch_allow = ["avr"]                    # narrower than the package
+//
+//      [[function]]                            # needs capabilities = ["script"]
+//      go     = "Show"                         # and the `scripting` feature
+//      script = 'if args.len() > 1 { "{0}.show({1})" } else { "{0}.show()" }'
+//
 //      [[constant]]
 //      go  = "NEO_GRB"
 //      cpp = "NEO_GRB"
@@ -38,16 +59,24 @@
 //      [[constant]]
 //      go  = "NEO_KHZ800"
 //      cpp = "NEO_KHZ800"
+//
+//      [dependencies]                          # other tsukilib packages,
+//      colorutil = "^1.2"                      # installed along with this one
+//
+//  Copies installed with `tsuki pkg install --path/--git` also carry a
+//  `[source]` table (`path = "…"`, or `git = "…"` and `tag = "…"`).
 // ─────────────────────────────────────────────────────────────────────────────
 
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 use std::fs;
 
 use serde::{Deserialize, Serialize};
 
 use crate::error::{tsukiError, Result};
-use crate::runtime::{FnMap, PkgMap};
+use crate::parser::ast::Type;
+use crate::runtime::{ArchRule, FnMap, PkgMap, Targets, FEATURES};
+use super::pkg_manager::semver::{Version, VersionReq};
 
 // ── TOML schema ───────────────────────────────────────────────────────────────
 
@@ -65,6 +94,39 @@ pub struct LibManifest {
     /// e.g. aliases = ["NeoPixel", "neopixel"]
     #[serde(default)]
     pub aliases:  Vec<String>,
+    /// Other tsukilib packages this one needs: name → version range
+    /// (`"^1.2"`), resolved from the registry by `pkg_manager::install`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub dependencies: BTreeMap<String, String>,
+    /// Where an installed copy came from when it wasn't the registry.
+    /// Written into the libs-dir copy by `pkg_manager::install_from`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source:   Option<LibSource>,
+}
+
+/// A package source other than the registry.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum LibSource {
+    /// A git repository with tsukilib.toml at its root, at `tag` or the
+    /// default branch.
+    Git {
+        git: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        tag: Option<String>,
+    },
+    /// A local directory holding tsukilib.toml.
+    Path { path: String },
+}
+
+impl std::fmt::Display for LibSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Git { git, tag: Some(tag) } => write!(f, "git {} ({})", git, tag),
+            Self::Git { git, tag: None }      => write!(f, "git {}", git),
+            Self::Path { path }               => write!(f, "path {}", path),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -77,10 +139,40 @@ pub struct LibPackage {
     pub cpp_header:  Option<String>,
     /// The exact arduino-cli library name to install (e.g. `"Adafruit NeoPixel"`).
     pub arduino_lib: Option<String>,
+    /// Version of `arduino_lib` to install; the latest when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arduino_lib_version: Option<String>,
     /// Min tsuki-core version required (semver, optional).
     pub requires_core: Option<String>,
     /// C++ class name for global variable declarations (emitted as pointer).
     pub cpp_class: Option<String>,
+    /// C++ helpers emitted after the includes of a sketch using the package.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpp_prelude: Option<String>,
+    /// C++ global declarations (objects, static buffers) for the sketch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpp_globals: Option<String>,
+    /// C++ statements run at the start of `setup()`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpp_setup:   Option<String>,
+    /// Opt-in features the package relies on; `"script"` allows
+    /// `[[function]] script = …` mappings.
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+    /// Board architectures (`avr`, `esp32`, `samd`…) the package supports;
+    /// empty means all.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub arch_allow: Vec<String>,
+    /// Board architectures the package does not work on.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub arch_deny:  Vec<String>,
+    /// Board features the package needs: `wifi`, `bluetooth`, `native_usb`,
+    /// `fpu`, `eeprom`, `multicore`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub requires:   Vec<String>,
+    /// Minimum board RAM in KB.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_ram_kb: Option<u32>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -88,13 +180,65 @@ pub struct LibFunction {
     /// Go function name as it appears in the import (e.g. `"Begin"`)
     pub go:  String,
     /// C++ template. `{0}` = first arg, `{1}` = second arg, `{self}` = receiver.
+    /// Placeholders take filters: `{0|mul:1000}`, `{0|upper}`, `{0|hex}`.
+    #[serde(default)]
     pub cpp: String,
+    /// Number of Go arguments (receiver included) the function takes, when
+    /// the package documents it; `tsuki pkg lint` checks `cpp` against it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub args: Option<usize>,
+    /// Go types of the call's arguments (`["int", "[]byte"]`), without the
+    /// receiver of a method. Calls are type-checked against them, and
+    /// numeric arguments cast to the declared type.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub params: Option<Vec<String>>,
+    /// Go result types. One result, or one and `error`, gives the call a
+    /// static type.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub returns: Vec<String>,
+    /// Rhai script returning the C++ template instead of a fixed `cpp`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub script: Option<String>,
+    /// Restrict this function further than the package's `arch_allow`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub arch_allow: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub arch_deny:  Vec<String>,
+    /// Board ids this mapping is for; another entry with the same `go`
+    /// and no filter covers the other boards.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub boards: Vec<String>,
+    /// Architecture this mapping is for. A `boards` match is preferred.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arch:   Option<String>,
+}
+
+impl LibFunction {
+    /// The boards the entry is limited to, if any.
+    pub fn targets(&self) -> Option<Targets> {
+        targets(&self.boards, &self.arch)
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct LibConstant {
     pub go:  String,
     pub cpp: String,
+    /// Same as for `[[function]]`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub boards: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arch:   Option<String>,
+}
+
+impl LibConstant {
+    pub fn targets(&self) -> Option<Targets> {
+        targets(&self.boards, &self.arch)
+    }
+}
+
+fn targets(boards: &[String], arch: &Option<String>) -> Option<Targets> {
+    (!boards.is_empty() || arch.is_some()).then(|| Targets { boards: boards.to_vec(), arch: arch.clone() })
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -136,12 +280,62 @@ pub fn load_from_str(toml_str: &str, path: &Path) -> Result<LoadedLib> {
     if let Some(ref class) = manifest.package.cpp_class {
         pkg = pkg.with_class(class);
     }
+    if let Some(ref cpp) = manifest.package.cpp_prelude { pkg = pkg.with_prelude(cpp) }
+    if let Some(ref cpp) = manifest.package.cpp_globals { pkg = pkg.with_globals(cpp) }
+    if let Some(ref cpp) = manifest.package.cpp_setup   { pkg = pkg.with_setup(cpp) }
+    pkg = pkg.with_arch(ArchRule {
+        allow: manifest.package.arch_allow.clone(),
+        deny:  manifest.package.arch_deny.clone(),
+    });
+    if let Some(f) = manifest.package.requires.iter().find(|f| !FEATURES.iter().any(|(id, _)| id == f)) {
+        let known: Vec<&str> = FEATURES.iter().map(|(id, _)| *id).collect();
+        return Err(tsukiError::codegen(format!(
+            "tsukilib.toml at {}: unknown board feature `{}` in requires (known: {})",
+            path.display(), f, known.join(", "))));
+    }
+    for (dep, range) in &manifest.dependencies {
+        if let Err(e) = range.parse::<VersionReq>() {
+            return Err(tsukiError::codegen(format!(
+                "tsukilib.toml at {}: dependency `{}`: {}", path.display(), dep, e.message())));
+        }
+    }
+    pkg.requires   = manifest.package.requires.clone();
+    pkg.min_ram_kb = manifest.package.min_ram_kb;
+    pkg.arduino_lib = manifest.package.arduino_lib.clone();
+    pkg.arduino_lib_version = manifest.package.arduino_lib_version.clone();
+    pkg.include_dir = path.parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .map(|dir| dir.join("src"))
+        .filter(|src| src.is_dir());
 
+    let scripts_allowed = manifest.package.capabilities.iter().any(|c| c == "script");
     for f in &manifest.functions {
-        pkg = pkg.fun(&f.go, FnMap::Template(f.cpp.clone()));
+        let map = function_map(f, scripts_allowed).map_err(|e| tsukiError::codegen(format!(
+            "tsukilib.toml at {}: function `{}`: {}", path.display(), f.go, e)))?;
+        pkg = match f.targets() {
+            Some(on) => pkg.fun_on(&f.go, on, map),
+            None     => pkg.fun(&f.go, map),
+        };
+        let sig_err = |e: String| tsukiError::codegen(format!(
+            "tsukilib.toml at {}: function `{}`: {}", path.display(), f.go, e));
+        if let Some(params) = &f.params {
+            pkg = pkg.params(&f.go, params.iter().map(|t| go_type(t)).collect::<std::result::Result<_, _>>().map_err(sig_err)?);
+        }
+        let returns: Vec<Type> = f.returns.iter().map(|t| go_type(t)).collect::<std::result::Result<_, _>>().map_err(sig_err)?;
+        match returns.as_slice() {
+            [ty] => pkg = pkg.ret(&f.go, ty.clone()),
+            [ty, Type::Named(e)] if e == "error" => pkg = pkg.ret(&f.go, ty.clone()),
+            _ => {}
+        }
+        if !f.arch_allow.is_empty() || !f.arch_deny.is_empty() {
+            pkg = pkg.fun_arch(&f.go, ArchRule { allow: f.arch_allow.clone(), deny: f.arch_deny.clone() });
+        }
     }
     for c in &manifest.constants {
-        pkg = pkg.cst(&c.go, &c.cpp);
+        pkg = match c.targets() {
+            Some(on) => pkg.cst_on(&c.go, on, &c.cpp),
+            None     => pkg.cst(&c.go, &c.cpp),
+        };
     }
 
     Ok(LoadedLib {
@@ -153,6 +347,37 @@ pub fn load_from_str(toml_str: &str, path: &Path) -> Result<LoadedLib> {
     })
 }
 
+/// Parse a type named in a function signature.
+fn go_type(text: &str) -> std::result::Result<Type, String> {
+    crate::lexer::Lexer::new(text, "<type>").tokenize()
+        .and_then(|tokens| crate::parser::Parser::new(tokens).parse_type_only())
+        .map_err(|e| format!("bad type `{}`: {}", text, e.message()))
+}
+
+/// Build the mapping for one `[[function]]` entry.
+fn function_map(f: &LibFunction, scripts_allowed: bool) -> std::result::Result<FnMap, String> {
+    let Some(source) = &f.script else {
+        let map = FnMap::Template(f.cpp.clone());
+        map.validate()?;
+        return Ok(map);
+    };
+    if !f.cpp.is_empty() {
+        return Err("set either `cpp` or `script`, not both".into());
+    }
+    if !scripts_allowed {
+        return Err("`script` mappings need `capabilities = [\"script\"]` in [package]".into());
+    }
+    #[cfg(feature = "scripting")]
+    {
+        crate::runtime::script::Script::compile(source).map(FnMap::Script)
+    }
+    #[cfg(not(feature = "scripting"))]
+    {
+        let _ = source;
+        Err("this tsuki-core was built without script support (cargo feature `scripting`)".into())
+    }
+}
+
 // ── Library search path ───────────────────────────────────────────────────────
 
 /// Returns the default library search root.
@@ -204,8 +429,8 @@ pub fn scan_libs_dir(libs_dir: &Path) -> Vec<PathBuf> {
             .map(|e| e.path())
             .collect();
 
-        // Sort by directory name (semver strings sort correctly for simple cases)
-        ver_dirs.sort();
+        // Highest version last; names that aren't versions sort first.
+        ver_dirs.sort_by_cached_key(|d| d.file_name().and_then(|n| n.to_str()?.parse::<Version>().ok()));
 
         if let Some(latest) = ver_dirs.last() {
             let manifest = latest.join("tsukilib.toml");
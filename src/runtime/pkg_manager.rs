@@ -14,34 +14,60 @@
 //        "latest":      "1.1.0",
 //        "versions": {
 //          "1.0.0": "https://raw.githubusercontent.com/.../ws2812/1.0.0/tsukilib.toml",
-//          "1.1.0": "https://raw.githubusercontent.com/.../ws2812/1.1.0/tsukilib.toml"
+//          "1.1.0": {
+//            "url":       "https://raw.githubusercontent.com/.../ws2812/1.1.0/tsukilib.toml",
+//            "sha256":    "9f2c…",
+//            "key_id":    "tsuki-team",
+//            "signature": "41ab…"
+//          }
 //        }
 //      },
 //      "dht": { ... }
 //    }
 //  }
 //
+//  A version is either a bare URL or an object with the SHA-256 of the TOML
+//  and, optionally, an ed25519 signature of it by a key from the key index
+//  (keys/index.json next to the registry). Downloads that don't match are
+//  refused.
+//
 //  CLI commands wired here (via main.rs):
 //    tsuki pkg list               — list all available packages in the registry
 //    tsuki pkg search <query>     — search registry by name/description
 //    tsuki pkg install <name>     — install latest version
-//    tsuki pkg install <name>@<v> — install specific version
+//    tsuki pkg install <name>@<v> — install specific version, or the best match
+//                                   for a range (`@1`, `@^1.2`; see semver.rs)
+//    tsuki pkg install --path <dir> / --git <url> [--tag <t>]
+//                                 — install from outside the registry
 //    tsuki pkg remove  <name>     — remove installed package
-//    tsuki pkg update             — update all installed packages to latest
+//    tsuki pkg update             — update all installed packages to latest, or
+//                                   to the project's pins in tsuki_package.json
 //    tsuki pkg installed          — list locally installed packages
+//    tsuki pkg publish [toml]     — validate, bump and record a package version
+//    tsuki pkg verify             — re-check installed packages against the registry
+//    tsuki pkg vendor             — copy a project's packages into its vendor/
+//
+//  With `--offline` no request leaves the machine: `local_registry` stands in
+//  for the registry, listing the packages in vendor/ and the libs dir.
 // ─────────────────────────────────────────────────────────────────────────────
 
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 use std::fs;
 
+use ed25519_dalek::pkcs8::{DecodePrivateKey, DecodePublicKey};
+use ed25519_dalek::{Signer as _, SigningKey, VerifyingKey};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
 use crate::error::{tsukiError, Result};
 use super::pkg_loader;
 
+pub mod semver;
+use semver::{Version, VersionReq};
+
 // Re-export for use by the binary crate
-pub use super::pkg_loader::default_libs_dir;
+pub use super::pkg_loader::{default_libs_dir, LibSource};
 
 // ── Registry URL ──────────────────────────────────────────────────────────────
 
@@ -50,11 +76,26 @@ pub use super::pkg_loader::default_libs_dir;
 pub const DEFAULT_REGISTRY_URL: &str =
     "https://raw.githubusercontent.com/s7lver/tsuki-pkgs/main/registry.json";
 
+/// Where a published version's TOML is downloaded from, next to the default
+/// registry. `{name}` and `{version}` are substituted.
+pub const DEFAULT_PACKAGE_URL: &str =
+    "https://raw.githubusercontent.com/s7lver/tsuki-pkgs/main/{name}/v{version}/tsukilib.toml";
+
+/// Index of the keys registry signatures are checked against.
+pub const DEFAULT_KEYS_URL: &str =
+    "https://raw.githubusercontent.com/s7lver/tsuki-pkgs/main/keys/index.json";
+
 // ── Registry schema ───────────────────────────────────────────────────────────
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
 pub struct Registry {
-    pub packages: HashMap<String, RegistryEntry>,
+    #[serde(rename = "_comment", default, skip_serializing_if = "Option::is_none")]
+    pub comment:  Option<String>,
+    pub packages: BTreeMap<String, RegistryEntry>,
+    /// Built by `local_registry`: versions are paths read from disk, and
+    /// nothing is downloaded.
+    #[serde(skip)]
+    pub offline:  bool,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -63,8 +104,172 @@ pub struct RegistryEntry {
     pub author:      Option<String>,
     /// Latest stable version string (e.g. "1.1.0").
     pub latest:      String,
-    /// Map of version string → TOML download URL.
-    pub versions:    HashMap<String, String>,
+    /// Map of version string → where to download it and how to check it.
+    pub versions:    BTreeMap<String, VersionInfo>,
+}
+
+/// One published version of a package.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(from = "VersionRepr", into = "VersionRepr")]
+pub struct VersionInfo {
+    /// TOML download URL.
+    pub url:       String,
+    /// Hex SHA-256 of the TOML file.
+    pub sha256:    Option<String>,
+    /// Id of the key (in the key index) that made `signature`.
+    pub key_id:    Option<String>,
+    /// Hex ed25519 signature of the TOML file.
+    pub signature: Option<String>,
+}
+
+impl VersionInfo {
+    pub fn url(url: impl Into<String>) -> Self {
+        Self { url: url.into(), sha256: None, key_id: None, signature: None }
+    }
+}
+
+/// On-disk form: registries written before checksums have bare URLs.
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+enum VersionRepr {
+    Url(String),
+    Full {
+        url:       String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sha256:    Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        key_id:    Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        signature: Option<String>,
+    },
+}
+
+impl From<VersionRepr> for VersionInfo {
+    fn from(r: VersionRepr) -> Self {
+        match r {
+            VersionRepr::Url(url) => Self::url(url),
+            VersionRepr::Full { url, sha256, key_id, signature } => Self { url, sha256, key_id, signature },
+        }
+    }
+}
+
+impl From<VersionInfo> for VersionRepr {
+    fn from(v: VersionInfo) -> Self {
+        match v {
+            VersionInfo { url, sha256: None, key_id: None, signature: None } => Self::Url(url),
+            VersionInfo { url, sha256, key_id, signature } => Self::Full { url, sha256, key_id, signature },
+        }
+    }
+}
+
+impl Registry {
+    /// Whether any version carries a signature, i.e. whether the key index
+    /// is needed at all.
+    pub fn has_signatures(&self) -> bool {
+        self.packages.values().flat_map(|e| e.versions.values()).any(|v| v.signature.is_some())
+    }
+}
+
+// ── Integrity ─────────────────────────────────────────────────────────────────
+
+/// Public keys that registry signatures are checked against, by key id.
+#[derive(Debug, Clone, Default)]
+pub struct Keyring {
+    keys: BTreeMap<String, VerifyingKey>,
+}
+
+/// Key index format (keys/index.json), shared with the Go CLI;
+/// `public_key_url` serves a PEM public key.
+#[derive(Deserialize)]
+struct KeyIndex {
+    keys: Vec<KeyIndexEntry>,
+}
+
+#[derive(Deserialize)]
+struct KeyIndexEntry {
+    key_id:         String,
+    public_key_url: String,
+    #[serde(default = "default_algorithm")]
+    algorithm:      String,
+}
+
+fn default_algorithm() -> String { "ed25519".into() }
+
+impl Keyring {
+    /// Download the key index at `url` and every ed25519 key it lists.
+    pub fn fetch(url: &str) -> Result<Self> {
+        let index: KeyIndex = serde_json::from_str(&http_get(url)?).map_err(|e| {
+            tsukiError::codegen(format!("failed to parse key index from {}: {}", url, e))
+        })?;
+        let mut ring = Self::default();
+        for key in index.keys.iter().filter(|k| k.algorithm == "ed25519") {
+            ring.add(&key.key_id, &http_get(&key.public_key_url)?)?;
+        }
+        Ok(ring)
+    }
+
+    /// Trust `public_key` under `key_id`: a PEM `PUBLIC KEY` block (as
+    /// written by `openssl pkey -pubout`) or the raw key in hex.
+    pub fn add(&mut self, key_id: &str, public_key: &str) -> Result<()> {
+        let key = VerifyingKey::from_public_key_pem(public_key.trim()).ok()
+            .or_else(|| hex_bytes(public_key).and_then(|b| VerifyingKey::from_bytes(&b).ok()))
+            .ok_or_else(|| tsukiError::codegen(format!("key '{}' is not an ed25519 public key", key_id)))?;
+        self.keys.insert(key_id.to_owned(), key);
+        Ok(())
+    }
+}
+
+/// What `check_integrity` could establish about a TOML file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Integrity {
+    /// The registry has no checksum for this version.
+    Unchecked,
+    /// The SHA-256 matches.
+    Checksum,
+    /// The SHA-256 (if listed) matches and the signature by this key is valid.
+    Signed(String),
+}
+
+/// Check `toml_str`, the content of `name@version`, against the registry's
+/// record of it. A mismatch, an unknown key or a bad signature is an error.
+pub fn check_integrity(
+    name:     &str,
+    version:  &str,
+    info:     &VersionInfo,
+    toml_str: &str,
+    keys:     &Keyring,
+) -> Result<Integrity> {
+    let mut integrity = Integrity::Unchecked;
+    if let Some(expected) = &info.sha256 {
+        let actual = sha256_hex(toml_str);
+        if !actual.eq_ignore_ascii_case(expected.trim()) {
+            return Err(tsukiError::codegen(format!(
+                "{}@{}: checksum mismatch\n  expected: {}\n  actual:   {}", name, version, expected, actual
+            )));
+        }
+        integrity = Integrity::Checksum;
+    }
+    if let Some(sig_hex) = &info.signature {
+        let key_id = info.key_id.as_deref().unwrap_or("");
+        let key = keys.keys.get(key_id).ok_or_else(|| tsukiError::codegen(format!(
+            "{}@{}: signed with unknown key '{}'", name, version, key_id
+        )))?;
+        let sig = hex_bytes(sig_hex).map(|b| ed25519_dalek::Signature::from_bytes(&b))
+            .ok_or_else(|| tsukiError::codegen(format!("{}@{}: malformed signature", name, version)))?;
+        key.verify_strict(toml_str.as_bytes(), &sig).map_err(|_| tsukiError::codegen(format!(
+            "{}@{}: signature by '{}' does not match the package", name, version, key_id
+        )))?;
+        integrity = Integrity::Signed(key_id.to_owned());
+    }
+    Ok(integrity)
+}
+
+fn sha256_hex(data: &str) -> String {
+    hex::encode(Sha256::digest(data.as_bytes()))
+}
+
+fn hex_bytes<const N: usize>(text: &str) -> Option<[u8; N]> {
+    hex::decode(text.trim()).ok()?.try_into().ok()
 }
 
 // ── Fetching ──────────────────────────────────────────────────────────────────
@@ -89,43 +294,214 @@ fn http_get(url: &str) -> Result<String> {
 
 // ── Install ───────────────────────────────────────────────────────────────────
 
-/// Install a package by name (and optional version) from the registry.
+/// Install a package by name (and optional version) from the registry,
+/// together with the tsukilib packages it depends on (`[dependencies]`).
 ///
-/// - `name`     — package name, e.g. `"ws2812"` or `"ws2812@1.0.0"`
+/// - `name`     — package name, e.g. `"ws2812"`, `"ws2812@1.0.0"` or `"ws2812@^1.2"`
 /// - `libs_dir` — root directory for installed packages
 /// - `registry` — parsed registry (call `fetch_registry` first)
+/// - `keys`     — keys for signed versions (`Keyring::fetch`)
 ///
-/// Returns a human-readable status message.
+/// Every download is checked with `check_integrity`, and the whole
+/// dependency tree resolved, before anything is written. Returns a
+/// human-readable status message, one line per package.
 pub fn install(
     name_ver:  &str,
     libs_dir:  &Path,
     registry:  &Registry,
+    keys:      &Keyring,
 ) -> Result<String> {
     // Parse optional "@version" suffix
     let (name, version_hint) = parse_name_version(name_ver);
 
-    let entry = registry.packages.get(name).ok_or_else(|| {
-        tsukiError::codegen(format!(
-            "package '{}' not found in registry — run `tsuki pkg list` to see available packages",
-            name
-        ))
-    })?;
+    let fetch: &dyn Fn(&str) -> Result<String> = if registry.offline { &read_local } else { &http_get };
+    let mut plan = Plan::new(registry, keys, fetch);
+    plan.resolve(&[(name, version_hint)])?;
 
-    let version = version_hint.unwrap_or_else(|| entry.latest.as_str());
+    let msgs = plan.order.iter()
+        .map(|p| pkg_loader::install_from_toml(libs_dir, &p.toml))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(msgs.join("\n"))
+}
 
-    let toml_url = entry.versions.get(version).ok_or_else(|| {
-        let available: Vec<&str> = entry.versions.keys().map(|s| s.as_str()).collect();
-        tsukiError::codegen(format!(
-            "version '{}' not found for package '{}'. Available: {}",
-            version, name, available.join(", ")
-        ))
-    })?;
+/// Dependency resolution for one `install`. Packages get a single version
+/// each. When a range rejects the version already picked for a package,
+/// both ranges are kept as constraints on it and resolution starts over;
+/// it fails once no version meets every constraint.
+struct Plan<'a> {
+    registry: &'a Registry,
+    keys:     &'a Keyring,
+    /// Downloads a TOML by URL.
+    fetch:    &'a dyn Fn(&str) -> Result<String>,
+    /// Downloaded TOML by URL, kept across attempts.
+    cache:    BTreeMap<String, String>,
+    /// Package → the (range, reason) pairs that clashed on earlier attempts.
+    pins:     BTreeMap<String, Vec<(String, String)>>,
+    /// Package → (resolved version, range, reason) in this attempt.
+    chosen:   BTreeMap<String, (String, String, String)>,
+    /// Packages being resolved, outermost first, for cycle reports.
+    stack:    Vec<String>,
+    /// The packages picked, dependencies before their dependents.
+    order:    Vec<Picked>,
+}
 
-    eprintln!("tsuki: downloading {}@{} from {} …", name, version, toml_url);
-    let toml_str = http_get(toml_url)?;
+/// A package version picked by a `Plan`.
+struct Picked {
+    name:    String,
+    version: String,
+    /// Where the TOML came from (`VersionInfo::url`).
+    url:     String,
+    toml:    String,
+}
+
+/// Why a resolution attempt stopped.
+enum Stop {
+    Retry,
+    Fail(tsukiError),
+}
+
+impl From<tsukiError> for Stop {
+    fn from(e: tsukiError) -> Self { Self::Fail(e) }
+}
+
+impl<'a> Plan<'a> {
+    fn new(registry: &'a Registry, keys: &'a Keyring, fetch: &'a dyn Fn(&str) -> Result<String>) -> Self {
+        Self {
+            registry, keys, fetch,
+            cache:  BTreeMap::new(),
+            pins:   BTreeMap::new(),
+            chosen: BTreeMap::new(),
+            stack:  Vec::new(),
+            order:  Vec::new(),
+        }
+    }
+
+    /// Resolve the (name, range) `roots` and their dependency trees into
+    /// `order`, one version per package across all of them.
+    fn resolve(&mut self, roots: &[(&str, Option<&str>)]) -> Result<()> {
+        loop {
+            self.chosen.clear();
+            self.order.clear();
+            match roots.iter().try_for_each(|&(name, hint)| self.visit(name, hint, None)) {
+                Ok(())            => return Ok(()),
+                Err(Stop::Retry)  => self.stack.clear(),
+                Err(Stop::Fail(e)) => return Err(e),
+            }
+        }
+    }
+
+    fn visit(&mut self, name: &str, hint: Option<&str>, required_by: Option<&str>) -> std::result::Result<(), Stop> {
+        if let Some(start) = self.stack.iter().position(|n| n == name) {
+            let cycle: Vec<&str> = self.stack[start..].iter().map(String::as_str).chain([name]).collect();
+            return Err(tsukiError::codegen(format!("dependency cycle: {}", cycle.join(" → "))).into());
+        }
+        let range  = hint.unwrap_or("*").to_owned();
+        let reason = match required_by {
+            Some(parent) => format!("{} requires {}@{}", parent, name, range),
+            None         => format!("installing {}@{}", name, hint.unwrap_or("latest")),
+        };
+        if let Some((version, first_range, first)) = self.chosen.get(name) {
+            if accepts(&range, version) { return Ok(()) }
+            let clash = [(first_range.clone(), first.clone()), (range, reason)];
+            let pinned = self.pins.entry(name.to_owned()).or_default();
+            if clash.iter().all(|c| pinned.contains(c)) {
+                return Err(tsukiError::codegen(format!(
+                    "version conflict for '{}': {} (resolved to {}), but {}", name, first, version, clash[1].1
+                )).into());
+            }
+            pinned.extend(clash.into_iter().filter(|c| !pinned.contains(c)).collect::<Vec<_>>());
+            return Err(Stop::Retry);
+        }
+
+        let entry = self.registry.packages.get(name).ok_or_else(|| {
+            let hint = match required_by {
+                Some(parent) => format!("required by '{}'", parent),
+                None if self.registry.offline => "install it while online, or vendor it with `tsuki pkg vendor`".into(),
+                None         => "run `tsuki pkg list` to see available packages".into(),
+            };
+            let place = if self.registry.offline { "the local packages" } else { "registry" };
+            tsukiError::codegen(format!("package '{}' not found in {} — {}", name, place, hint))
+        })?;
+        let version = match self.pins.get(name) {
+            None => resolve_version(name, entry, hint)?,
+            Some(pinned) => {
+                let reqs: Vec<&str> = pinned.iter().map(|(r, _)| r.as_str()).chain([range.as_str()]).collect();
+                sorted_versions(entry).into_iter().rev()
+                    .find(|v| reqs.iter().all(|r| accepts(r, v)))
+                    .ok_or_else(|| {
+                        let why: Vec<&str> = pinned.iter().map(|(_, why)| why.as_str()).collect();
+                        tsukiError::codegen(format!(
+                            "version conflict for '{}': no version satisfies all of: {}. Available: {}",
+                            name, why.join("; "), sorted_versions(entry).join(", ")
+                        ))
+                    })?
+            }
+        };
+        let info = &entry.versions[version];
+
+        let toml_str = match self.cache.get(&info.url) {
+            Some(cached) => cached.clone(),
+            None => {
+                if !self.registry.offline {
+                    eprintln!("tsuki: downloading {}@{} from {} …", name, version, info.url);
+                }
+                let toml_str = (self.fetch)(&info.url)?;
+                if check_integrity(name, version, info, &toml_str, self.keys)? == Integrity::Unchecked
+                    && !self.registry.offline
+                {
+                    eprintln!("tsuki: warning: the registry lists no checksum for {}@{}", name, version);
+                }
+                self.cache.insert(info.url.clone(), toml_str.clone());
+                toml_str
+            }
+        };
+        let manifest: pkg_loader::LibManifest = toml::from_str(&toml_str).map_err(|e| {
+            tsukiError::codegen(format!("invalid tsukilib.toml for {}@{}: {}", name, version, e))
+        })?;
+
+        self.chosen.insert(name.to_owned(), (version.to_owned(), range, reason));
+        self.stack.push(name.to_owned());
+        for (dep, range) in &manifest.dependencies {
+            self.visit(dep, Some(range), Some(name))?;
+        }
+        self.stack.pop();
+        self.order.push(Picked { name: name.to_owned(), version: version.to_owned(), url: info.url.clone(), toml: toml_str });
+        Ok(())
+    }
+}
+
+/// Whether `version` is, or lies in the range, `range`.
+fn accepts(range: &str, version: &str) -> bool {
+    range == version
+        || range.parse::<VersionReq>().is_ok_and(|r| version.parse().is_ok_and(|v| r.matches(&v)))
+}
 
-    let msg = pkg_loader::install_from_toml(libs_dir, &toml_str)?;
-    Ok(msg)
+/// The version of `name` to install for `hint`: a version the registry
+/// lists, else the highest one in the range `hint`. Without a hint, the
+/// entry's `latest`.
+pub fn resolve_version<'a>(name: &str, entry: &'a RegistryEntry, hint: Option<&str>) -> Result<&'a str> {
+    let not_found = |what: String| tsukiError::codegen(format!(
+        "{} for package '{}'. Available: {}", what, name, sorted_versions(entry).join(", ")
+    ));
+    let Some(hint) = hint else {
+        return entry.versions.get_key_value(&entry.latest).map(|(v, _)| v.as_str())
+            .ok_or_else(|| not_found(format!("latest version '{}' not found", entry.latest)));
+    };
+    if let Some((v, _)) = entry.versions.get_key_value(hint) {
+        return Ok(v);
+    }
+    let req: VersionReq = hint.parse()
+        .map_err(|_| not_found(format!("version '{}' not found", hint)))?;
+    req.best_match(entry.versions.keys().map(String::as_str))
+        .ok_or_else(|| not_found(format!("no version matches '{}'", hint)))
+}
+
+/// The entry's versions, lowest first; anything that isn't a version sorts
+/// before the rest.
+pub fn sorted_versions(entry: &RegistryEntry) -> Vec<&str> {
+    let mut versions: Vec<&str> = entry.versions.keys().map(String::as_str).collect();
+    versions.sort_by_cached_key(|v| v.parse::<Version>().ok());
+    versions
 }
 
 /// Remove an installed package (all versions, or a specific one).
@@ -166,8 +542,16 @@ pub fn remove(name_ver: &str, libs_dir: &Path) -> Result<String> {
     }
 }
 
-/// Update all installed packages to their latest registry version.
-pub fn update_all(libs_dir: &Path, registry: &Registry) -> Result<Vec<String>> {
+/// Update all installed packages to their latest registry version, or to the
+/// best match for their range in `pins` (see `project_pins`). Packages
+/// installed with `install_from` are reinstalled from their source instead,
+/// except git checkouts of a tag.
+pub fn update_all(
+    libs_dir: &Path,
+    registry: &Registry,
+    keys:     &Keyring,
+    pins:     &BTreeMap<String, String>,
+) -> Result<Vec<String>> {
     let mut results = Vec::new();
 
     let Ok(entries) = fs::read_dir(libs_dir) else {
@@ -179,7 +563,32 @@ pub fn update_all(libs_dir: &Path, registry: &Registry) -> Result<Vec<String>> {
             continue;
         }
         let pkg_name = entry.file_name().to_string_lossy().into_owned();
-        match install(&pkg_name, libs_dir, registry) {
+        let highest = list_installed(libs_dir).into_iter()
+            .filter(|(name, _)| *name == pkg_name)
+            .max_by_key(|(_, v)| v.parse::<Version>().ok());
+        match highest.and_then(|(_, v)| installed_source(libs_dir, &pkg_name, &v)) {
+            Some(LibSource::Git { tag: Some(tag), .. }) => {
+                results.push(format!("{}: pinned to git tag {}", pkg_name, tag));
+                continue;
+            }
+            Some(LibSource::Git { .. }) if registry.offline => {
+                results.push(format!("{}: installed from git, skipped offline", pkg_name));
+                continue;
+            }
+            Some(source) => {
+                match install_from(&source, libs_dir) {
+                    Ok((msg, _)) => results.push(msg),
+                    Err(e)       => results.push(format!("warning: {}: {}", pkg_name, e)),
+                }
+                continue;
+            }
+            None => {}
+        }
+        let target = match pins.get(&pkg_name) {
+            Some(range) => format!("{}@{}", pkg_name, range),
+            None        => pkg_name.clone(),
+        };
+        match install(&target, libs_dir, registry, keys) {
             Ok(msg)  => results.push(msg),
             Err(e)   => results.push(format!("warning: {}: {}", pkg_name, e)),
         }
@@ -188,6 +597,421 @@ pub fn update_all(libs_dir: &Path, registry: &Registry) -> Result<Vec<String>> {
     Ok(results)
 }
 
+/// Version ranges a project pins its packages to: `project_packages`
+/// without the packages that have no version.
+pub fn project_pins(manifest: &Path) -> Result<BTreeMap<String, String>> {
+    Ok(project_packages(manifest)?.into_iter().filter(|(_, range)| range != "*").collect())
+}
+
+/// Every package a project uses — the `packages` list of its
+/// tsuki_package.json — with its version range, `*` when it has none.
+pub fn project_packages(manifest: &Path) -> Result<BTreeMap<String, String>> {
+    #[derive(Deserialize)]
+    struct Project {
+        #[serde(default)]
+        packages: Vec<ProjectPackage>,
+    }
+    #[derive(Deserialize)]
+    struct ProjectPackage {
+        name:    String,
+        #[serde(default)]
+        version: String,
+    }
+
+    let body = fs::read_to_string(manifest)
+        .map_err(|e| tsukiError::other(format!("cannot read {}: {}", manifest.display(), e)))?;
+    let project: Project = serde_json::from_str(&body)
+        .map_err(|e| tsukiError::other(format!("{}: {}", manifest.display(), e)))?;
+    let mut pkgs = BTreeMap::new();
+    for pkg in project.packages {
+        let range = match pkg.version.trim() {
+            "" => "*".to_owned(),
+            v  => v.to_owned(),
+        };
+        range.parse::<VersionReq>()
+            .map_err(|e| tsukiError::other(format!("{}: package '{}': {}", manifest.display(), pkg.name, e.message())))?;
+        pkgs.insert(pkg.name, range);
+    }
+    Ok(pkgs)
+}
+
+/// Re-check every installed package against the registry. One entry per
+/// installed version; Err for files that fail `check_integrity` or versions
+/// the registry doesn't list.
+pub fn verify_installed(libs_dir: &Path, registry: &Registry, keys: &Keyring) -> Vec<Result<String>> {
+    list_installed(libs_dir).into_iter().map(|(name, version)| {
+        if let Some(source) = installed_source(libs_dir, &name, &version) {
+            return Ok(format!("{}@{}: installed from {}, not checked", name, version, source));
+        }
+        let path = libs_dir.join(&name).join(&version).join("tsukilib.toml");
+        let toml_str = fs::read_to_string(&path)
+            .map_err(|e| tsukiError::codegen(format!("cannot read {}: {}", path.display(), e)))?;
+        let info = registry.packages.get(&name).and_then(|e| e.versions.get(&version)).ok_or_else(|| {
+            tsukiError::codegen(format!("{}@{}: not in the registry", name, version))
+        })?;
+        Ok(match check_integrity(&name, &version, info, &toml_str, keys)? {
+            Integrity::Unchecked   => format!("{}@{}: no checksum in the registry", name, version),
+            Integrity::Checksum    => format!("{}@{}: checksum ok", name, version),
+            Integrity::Signed(key) => format!("{}@{}: checksum ok, signed by {}", name, version, key),
+        })
+    }).collect()
+}
+
+// ── Path and git sources ──────────────────────────────────────────────────────
+
+/// Install a package from a local directory or a git repository instead of
+/// the registry. The whole directory is copied (headers under src/ too) and
+/// the copy's tsukilib.toml records where it came from. Returns the status
+/// line and the package's `[dependencies]`, for the caller to install from
+/// the registry.
+pub fn install_from(source: &LibSource, libs_dir: &Path) -> Result<(String, BTreeMap<String, String>)> {
+    match source {
+        LibSource::Path { path } => {
+            let path = Path::new(path);
+            let dir  = if path.is_file() { path.parent().unwrap_or(Path::new(".")) } else { path };
+            let dir  = fs::canonicalize(dir)
+                .map_err(|e| tsukiError::codegen(format!("cannot read {}: {}", dir.display(), e)))?;
+            let source = LibSource::Path { path: dir.to_string_lossy().into_owned() };
+            install_dir(&dir, &source, libs_dir)
+        }
+        LibSource::Git { git, tag } => {
+            let checkout = std::env::temp_dir().join(format!("tsuki-git-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&checkout);
+            let mut cmd = std::process::Command::new("git");
+            cmd.args(["-c", "advice.detachedHead=false", "clone", "--quiet", "--depth", "1"]);
+            if let Some(tag) = tag {
+                cmd.args(["--branch", tag]);
+            }
+            eprintln!("tsuki: cloning {} …", source);
+            let status = cmd.arg(git).arg(&checkout).status()
+                .map_err(|e| tsukiError::codegen(format!("cannot run git: {}", e)))?;
+            let result = if status.success() {
+                install_dir(&checkout, source, libs_dir)
+            } else {
+                Err(tsukiError::codegen(format!("git clone {} failed ({})", git, status)))
+            };
+            let _ = fs::remove_dir_all(&checkout);
+            result
+        }
+    }
+}
+
+/// Where an installed package version came from; None for the registry.
+pub fn installed_source(libs_dir: &Path, name: &str, version: &str) -> Option<LibSource> {
+    let toml_str = fs::read_to_string(libs_dir.join(name).join(version).join("tsukilib.toml")).ok()?;
+    toml::from_str::<pkg_loader::LibManifest>(&toml_str).ok()?.source
+}
+
+fn install_dir(dir: &Path, source: &LibSource, libs_dir: &Path) -> Result<(String, BTreeMap<String, String>)> {
+    let path = dir.join("tsukilib.toml");
+    let toml_str = read_local(&path.to_string_lossy())?;
+    pkg_loader::load_from_str(&toml_str, &path)?;
+    let manifest: pkg_loader::LibManifest = toml::from_str(&toml_str)
+        .map_err(|e| tsukiError::codegen(format!("malformed tsukilib.toml at {}: {}", path.display(), e)))?;
+    if manifest.source.is_some() {
+        return Err(tsukiError::codegen(format!(
+            "{}: [source] is written on install; remove it from the package", path.display())));
+    }
+
+    let (name, version) = (&manifest.package.name, &manifest.package.version);
+    let dest = libs_dir.join(name).join(version);
+    let io_err = |what: &str, e: std::io::Error| {
+        tsukiError::codegen(format!("cannot {} {}: {}", what, dest.display(), e))
+    };
+    if dest.exists() {
+        fs::remove_dir_all(&dest).map_err(|e| io_err("replace", e))?;
+    }
+    copy_dir(dir, &dest).map_err(|e| io_err("write", e))?;
+
+    #[derive(Serialize)]
+    struct Recorded<'a> { source: &'a LibSource }
+    let table = toml::to_string(&Recorded { source })
+        .map_err(|e| tsukiError::codegen(format!("cannot record the source: {}", e)))?;
+    fs::write(dest.join("tsukilib.toml"), format!("{}\n\n{}", toml_str.trim_end(), table))
+        .map_err(|e| io_err("write", e))?;
+
+    Ok((format!("installed {}@{} from {} → {}", name, version, source, dest.display()),
+        manifest.dependencies))
+}
+
+// ── Vendoring ─────────────────────────────────────────────────────────────────
+
+/// Directory next to tsuki_package.json that `vendor` fills by default.
+pub const VENDOR_DIR: &str = "vendor";
+
+/// Copy the packages a project uses, and the packages they depend on, from
+/// `libs_dir` into `vendor_dir` (same layout), so the project installs and
+/// builds without network access. `pkgs` maps names to version ranges
+/// (`project_packages`); each gets the highest installed version in range.
+/// Packages no longer used are removed from `vendor_dir`. Returns one line
+/// per package.
+pub fn vendor(pkgs: &BTreeMap<String, String>, libs_dir: &Path, vendor_dir: &Path) -> Result<Vec<String>> {
+    let registry = local_registry(&[libs_dir]);
+    let keys     = Keyring::default();
+    let mut plan = Plan::new(&registry, &keys, &read_local);
+    let roots: Vec<(&str, Option<&str>)> = pkgs.iter().map(|(n, r)| (n.as_str(), Some(r.as_str()))).collect();
+    plan.resolve(&roots)?;
+
+    let io_err = |what: &str, path: &Path, e: std::io::Error| {
+        tsukiError::codegen(format!("cannot {} {}: {}", what, path.display(), e))
+    };
+    for (name, _) in list_installed(vendor_dir) {
+        let stale = vendor_dir.join(&name);
+        if stale.exists() && !plan.order.iter().any(|p| p.name == name) {
+            fs::remove_dir_all(&stale).map_err(|e| io_err("remove", &stale, e))?;
+        }
+    }
+    let mut msgs = Vec::new();
+    for p in &plan.order {
+        let src  = Path::new(&p.url).parent().unwrap_or(libs_dir);
+        let dest = vendor_dir.join(&p.name);
+        if dest.exists() {
+            fs::remove_dir_all(&dest).map_err(|e| io_err("remove", &dest, e))?;
+        }
+        copy_dir(src, &dest.join(&p.version)).map_err(|e| io_err("copy", src, e))?;
+        msgs.push(format!("vendored {}@{} → {}", p.name, p.version, dest.display()));
+    }
+    Ok(msgs)
+}
+
+/// A registry of the packages already on disk under `dirs` (libs-dir
+/// layout), for `install` and `update` without network access. A version
+/// in an earlier directory shadows the same version in a later one.
+pub fn local_registry(dirs: &[&Path]) -> Registry {
+    let mut registry = Registry { offline: true, ..Registry::default() };
+    for dir in dirs {
+        for (name, version) in list_installed(dir) {
+            let path = dir.join(&name).join(&version).join("tsukilib.toml");
+            if !path.is_file() { continue }
+            let entry = registry.packages.entry(name).or_insert_with(|| RegistryEntry {
+                description: None, author: None, latest: String::new(), versions: BTreeMap::new(),
+            });
+            entry.versions.entry(version).or_insert_with(|| VersionInfo::url(path.to_string_lossy()));
+        }
+    }
+    for entry in registry.packages.values_mut() {
+        // Highest release, or highest pre-release when there is no release.
+        let versions = sorted_versions(entry);
+        let latest = versions.iter().rev()
+            .find(|v| v.parse::<Version>().is_ok_and(|v| v.pre.is_empty()))
+            .or(versions.last())
+            .map(|v| v.to_string())
+            .unwrap_or_default();
+        let manifest = read_local(&entry.versions[&latest].url).ok()
+            .and_then(|t| toml::from_str::<pkg_loader::LibManifest>(&t).ok());
+        if let Some(m) = manifest {
+            entry.description = m.package.description;
+            entry.author      = m.package.author;
+        }
+        entry.latest = latest;
+    }
+    registry
+}
+
+/// Read a TOML that `local_registry` lists.
+fn read_local(path: &str) -> Result<String> {
+    fs::read_to_string(path).map_err(|e| tsukiError::codegen(format!("cannot read {}: {}", path, e)))
+}
+
+/// Copy a package directory, leaving out git metadata.
+fn copy_dir(src: &Path, dest: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(dest)?;
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        if entry.file_name() == ".git" { continue }
+        let to = dest.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &to)?;
+        } else {
+            fs::copy(entry.path(), to)?;
+        }
+    }
+    Ok(())
+}
+
+// ── Publish ───────────────────────────────────────────────────────────────────
+
+/// Which part of `major.minor.patch` `publish` increments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bump { Major, Minor, Patch }
+
+impl std::str::FromStr for Bump {
+    type Err = tsukiError;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "major" => Ok(Self::Major),
+            "minor" => Ok(Self::Minor),
+            "patch" => Ok(Self::Patch),
+            _ => Err(tsukiError::other(format!("unknown bump `{}` (major, minor or patch)", s))),
+        }
+    }
+}
+
+/// Key used by `publish` to sign the TOML.
+pub struct Signer {
+    pub key_id: String,
+    pub key:    SigningKey,
+}
+
+impl Signer {
+    /// Read an ed25519 secret key from `path`: PEM `PRIVATE KEY` (as from
+    /// `openssl genpkey -algorithm ed25519`) or the 32-byte seed in hex.
+    pub fn from_file(key_id: &str, path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| tsukiError::other(format!("cannot read {}: {}", path.display(), e)))?;
+        let key = SigningKey::from_pkcs8_pem(text.trim()).ok()
+            .or_else(|| hex_bytes(&text).map(|seed| SigningKey::from_bytes(&seed)))
+            .ok_or_else(|| tsukiError::other(format!("{}: not an ed25519 secret key", path.display())))?;
+        Ok(Self { key_id: key_id.to_owned(), key })
+    }
+}
+
+/// What `publish` did.
+#[derive(Debug)]
+pub struct Published {
+    pub name:    String,
+    pub version: String,
+    /// The package's registry.json entry, as `{ "<name>": { … } }`.
+    pub snippet: String,
+    /// Copy of the TOML inside the registry checkout, when one was given.
+    pub copied:  Option<PathBuf>,
+}
+
+/// Validate the tsukilib.toml at `manifest`, optionally bump its version (the
+/// file is rewritten), and build its registry entry with the download URL
+/// from `url_template`, the file's SHA-256 and, with `signer`, its signature.
+/// With `registry_dir` (a checkout of the registry
+/// repo) the TOML is copied to `<name>/v<version>/tsukilib.toml` there and
+/// the version recorded in its `registry.json`.
+pub fn publish(
+    manifest:     &Path,
+    bump:         Option<Bump>,
+    url_template: &str,
+    registry_dir: Option<&Path>,
+    signer:       Option<&Signer>,
+) -> Result<Published> {
+    let read = |p: &Path| fs::read_to_string(p)
+        .map_err(|e| tsukiError::other(format!("cannot read {}: {}", p.display(), e)));
+    let mut toml_str = read(manifest)?;
+    let lib = pkg_loader::load_from_str(&toml_str, manifest)?;
+    if lib.name.is_empty() || !lib.name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err(tsukiError::other(format!(
+            "{}: package name `{}` must be letters, digits, `-` or `_`", manifest.display(), lib.name)));
+    }
+    let current: Version = lib.version.parse().map_err(|_| tsukiError::other(format!(
+        "{}: version `{}` is not major.minor.patch", manifest.display(), lib.version)))?;
+
+    let version = match bump {
+        None => lib.version.clone(),
+        Some(b) => {
+            let Version { major: ma, minor: mi, patch: pa, .. } = current;
+            let next = match b {
+                Bump::Major => format!("{}.0.0", ma + 1),
+                Bump::Minor => format!("{}.{}.0", ma, mi + 1),
+                Bump::Patch => format!("{}.{}.{}", ma, mi, pa + 1),
+            };
+            toml_str = set_version(&toml_str, &lib.version, &next).ok_or_else(|| tsukiError::other(format!(
+                "{}: cannot find `version = \"{}\"` to bump", manifest.display(), lib.version)))?;
+            fs::write(manifest, &toml_str)
+                .map_err(|e| tsukiError::other(format!("cannot write {}: {}", manifest.display(), e)))?;
+            next
+        }
+    };
+
+    let header = toml::from_str::<pkg_loader::LibManifest>(&toml_str)
+        .map_err(|e| tsukiError::other(format!("{}: {}", manifest.display(), e)))?.package;
+    let url = url_template.replace("{name}", &lib.name).replace("{version}", &version);
+    let entry = RegistryEntry {
+        description: header.description,
+        author:      header.author,
+        latest:      version.clone(),
+        versions:    BTreeMap::from([(version.clone(), VersionInfo {
+            url,
+            sha256:    Some(sha256_hex(&toml_str)),
+            key_id:    signer.map(|s| s.key_id.clone()),
+            signature: signer.map(|s| hex::encode(s.key.sign(toml_str.as_bytes()).to_bytes())),
+        })]),
+    };
+    let snippet = serde_json::to_string_pretty(&BTreeMap::from([(&lib.name, &entry)]))?;
+
+    let copied = match registry_dir {
+        None => None,
+        Some(dir) => Some(record(dir, &lib.name, &version, entry, &toml_str)?),
+    };
+    Ok(Published { name: lib.name, version, snippet, copied })
+}
+
+/// Add a published version to the registry checkout at `dir`. Re-publishing
+/// a version is only allowed with identical content.
+fn record(dir: &Path, name: &str, version: &str, entry: RegistryEntry, toml_str: &str) -> Result<PathBuf> {
+    let io = |p: &Path, e: std::io::Error| tsukiError::other(format!("{}: {}", p.display(), e));
+    let dest = dir.join(name).join(format!("v{}", version)).join("tsukilib.toml");
+    match fs::read_to_string(&dest) {
+        Ok(old) if old != toml_str => return Err(tsukiError::other(format!(
+            "{}@{} is already published with different content; bump the version", name, version))),
+        _ => {}
+    }
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent).map_err(|e| io(parent, e))?;
+    }
+    fs::write(&dest, toml_str).map_err(|e| io(&dest, e))?;
+
+    let index = dir.join("registry.json");
+    let mut registry: Registry = match fs::read_to_string(&index) {
+        Ok(body) => serde_json::from_str(&body)
+            .map_err(|e| tsukiError::other(format!("{}: {}", index.display(), e)))?,
+        Err(_) => Registry::default(),
+    };
+    match registry.packages.get_mut(name) {
+        None => { registry.packages.insert(name.to_owned(), entry); }
+        Some(known) => {
+            known.versions.extend(entry.versions);
+            known.description = entry.description.or(known.description.take());
+            known.author      = entry.author.or(known.author.take());
+            if version.parse::<Version>().ok() > known.latest.parse().ok() {
+                known.latest = version.to_owned();
+            }
+        }
+    }
+    fs::write(&index, serde_json::to_string_pretty(&registry)? + "\n").map_err(|e| io(&index, e))?;
+    Ok(dest)
+}
+
+/// Run the configured push command (e.g. `git commit -am … && git push`)
+/// through the shell in `dir`, with the package in `TSUKI_PKG_NAME` and
+/// `TSUKI_PKG_VERSION`.
+pub fn push(cmd: &str, dir: &Path, published: &Published) -> Result<()> {
+    let status = std::process::Command::new("sh")
+        .arg("-c").arg(cmd)
+        .current_dir(dir)
+        .env("TSUKI_PKG_NAME", &published.name)
+        .env("TSUKI_PKG_VERSION", &published.version)
+        .status()
+        .map_err(|e| tsukiError::other(format!("cannot run push command: {}", e)))?;
+    if !status.success() {
+        return Err(tsukiError::other(format!("push command failed ({})", status)));
+    }
+    Ok(())
+}
+
+/// Replace the version in the `[package]` table's `version = "…"` line,
+/// leaving the rest of the file (comments, alignment) as written.
+fn set_version(toml_str: &str, old: &str, new: &str) -> Option<String> {
+    let mut in_package = false;
+    let mut done = false;
+    let lines: Vec<String> = toml_str.split_inclusive('\n').map(|line| {
+        let t = line.trim_start();
+        if t.starts_with('[') { in_package = t.starts_with("[package]"); }
+        let is_version = t.strip_prefix("version").is_some_and(|r| r.trim_start().starts_with('='));
+        if in_package && !done && is_version && line.contains(&format!("\"{}\"", old)) {
+            done = true;
+            return line.replacen(&format!("\"{}\"", old), &format!("\"{}\"", new), 1);
+        }
+        line.to_owned()
+    }).collect();
+    done.then(|| lines.concat())
+}
+
 // ── Query ─────────────────────────────────────────────────────────────────────
 
 /// List all packages in the registry, optionally filtered by a search query.
@@ -236,3 +1060,207 @@ fn parse_name_version(s: &str) -> (&str, Option<&str>) {
         None    => (s, None),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MANIFEST: &str = "[package]\nname        = \"blinker\"\nversion     = \"1.2.3\"\n\
+        description = \"Blink helpers\"\ncpp_header  = \"Blinker.h\"\n\n\
+        [[function]]\ngo  = \"Start\"\ncpp = \"blinker_start({0})\"\n";
+
+    #[test]
+    fn test_publish_bumps_and_records() {
+        let dir = std::env::temp_dir().join(format!("tsuki-publish-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let registry_dir = dir.join("registry");
+        fs::create_dir_all(&registry_dir).unwrap();
+        let manifest = dir.join("tsukilib.toml");
+        fs::write(&manifest, MANIFEST).unwrap();
+
+        // Without a registry checkout only the entry is produced.
+        let p = publish(&manifest, None, "https://x/{name}/v{version}.toml", None, None).unwrap();
+        assert_eq!(p.version, "1.2.3");
+        assert!(p.snippet.contains("\"https://x/blinker/v1.2.3.toml\""), "{}", p.snippet);
+        assert!(p.copied.is_none());
+
+        publish(&manifest, None, DEFAULT_PACKAGE_URL, Some(&registry_dir), None).unwrap();
+        let p = publish(&manifest, Some(Bump::Minor), DEFAULT_PACKAGE_URL, Some(&registry_dir), None).unwrap();
+        assert_eq!(p.version, "1.3.0");
+        assert!(fs::read_to_string(&manifest).unwrap().contains("version     = \"1.3.0\"\n"));
+        assert!(registry_dir.join("blinker/v1.3.0/tsukilib.toml").exists());
+
+        let registry: Registry = serde_json::from_str(
+            &fs::read_to_string(registry_dir.join("registry.json")).unwrap()).unwrap();
+        let entry = &registry.packages["blinker"];
+        assert_eq!(entry.latest, "1.3.0");
+        assert_eq!(entry.versions.keys().collect::<Vec<_>>(), ["1.2.3", "1.3.0"]);
+
+        // Same version, different content.
+        fs::write(&manifest, fs::read_to_string(&manifest).unwrap().replace("Blink helpers", "Blinks")).unwrap();
+        let err = publish(&manifest, None, DEFAULT_PACKAGE_URL, Some(&registry_dir), None).unwrap_err();
+        assert!(err.to_string().contains("already published"), "{}", err);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_integrity_checks() {
+        let signer = Signer { key_id: "team".into(), key: SigningKey::from_bytes(&[7; 32]) };
+        let mut keys = Keyring::default();
+        keys.add("team", &hex::encode(signer.key.verifying_key().to_bytes())).unwrap();
+
+        let signed = VersionInfo {
+            url:       "https://x/blinker.toml".into(),
+            sha256:    Some(sha256_hex(MANIFEST)),
+            key_id:    Some("team".into()),
+            signature: Some(hex::encode(signer.key.sign(MANIFEST.as_bytes()).to_bytes())),
+        };
+        assert_eq!(check_integrity("blinker", "1.2.3", &signed, MANIFEST, &keys).unwrap(),
+                   Integrity::Signed("team".into()));
+        assert_eq!(check_integrity("blinker", "1.2.3", &VersionInfo::url("u"), MANIFEST, &keys).unwrap(),
+                   Integrity::Unchecked);
+
+        let tampered = MANIFEST.replace("blinker_start", "evil");
+        let err = check_integrity("blinker", "1.2.3", &signed, &tampered, &keys).unwrap_err();
+        assert!(err.to_string().contains("checksum mismatch"), "{}", err);
+        let unhashed = VersionInfo { sha256: None, ..signed.clone() };
+        let err = check_integrity("blinker", "1.2.3", &unhashed, &tampered, &keys).unwrap_err();
+        assert!(err.to_string().contains("does not match"), "{}", err);
+        let err = check_integrity("blinker", "1.2.3", &signed, MANIFEST, &Keyring::default()).unwrap_err();
+        assert!(err.to_string().contains("unknown key 'team'"), "{}", err);
+
+        // Bare URLs and objects both parse; unchecked versions stay bare.
+        let reg: Registry = serde_json::from_str(&format!(
+            r#"{{"packages":{{"blinker":{{"latest":"1.2.3","versions":{{"1.0.0":"u","1.2.3":{}}}}}}}}}"#,
+            serde_json::to_string(&signed).unwrap())).unwrap();
+        assert!(reg.has_signatures());
+        assert_eq!(reg.packages["blinker"].versions["1.2.3"], signed);
+        assert!(serde_json::to_string(&reg).unwrap().contains(r#""1.0.0":"u""#));
+    }
+
+    #[test]
+    fn test_resolve_version() {
+        let entry = RegistryEntry {
+            description: None,
+            author:      None,
+            latest:      "1.10.0".into(),
+            versions:    ["1.2.0", "1.9.1", "1.10.0", "2.0.0-rc.1"].iter()
+                .map(|v| (v.to_string(), VersionInfo::url(format!("u/{}", v)))).collect(),
+        };
+        assert_eq!(sorted_versions(&entry), ["1.2.0", "1.9.1", "1.10.0", "2.0.0-rc.1"]);
+        assert_eq!(resolve_version("p", &entry, None).unwrap(), "1.10.0");
+        assert_eq!(resolve_version("p", &entry, Some("1.9.1")).unwrap(), "1.9.1");
+        assert_eq!(resolve_version("p", &entry, Some("~1.9")).unwrap(), "1.9.1");
+        assert_eq!(resolve_version("p", &entry, Some("2.0.0-rc.1")).unwrap(), "2.0.0-rc.1");
+        let err = resolve_version("p", &entry, Some("^2")).unwrap_err();
+        assert!(err.to_string().contains("no version matches '^2' for package 'p'. Available: 1.2.0, 1.9.1"), "{}", err);
+    }
+
+    #[test]
+    fn test_dependency_resolution() {
+        let lib = |name: &str, version: &str, deps: &str| format!(
+            "[package]\nname = \"{}\"\nversion = \"{}\"\n\n[dependencies]\n{}", name, version, deps);
+        let files: BTreeMap<String, String> = [
+            ("app@1.0.0",   lib("app", "1.0.0", "display = \"^1\"\nfont = \"~1.1\"")),
+            ("display@1.4.0", lib("display", "1.4.0", "font = \"^1.0\"")),
+            ("font@1.1.2",  lib("font", "1.1.2", "")),
+            ("font@1.2.0",  lib("font", "1.2.0", "")),
+            ("font@2.0.0",  lib("font", "2.0.0", "")),
+            ("loop@1.0.0",  lib("loop", "1.0.0", "knot = \"1\"")),
+            ("knot@1.0.0",  lib("knot", "1.0.0", "loop = \"1\"")),
+            ("clash@1.0.0", lib("clash", "1.0.0", "display = \"1\"\nfont = \"2\"")),
+        ].into_iter().map(|(k, v)| (k.to_owned(), v)).collect();
+
+        let mut registry = Registry::default();
+        for key in files.keys() {
+            let (name, version) = key.split_once('@').unwrap();
+            let entry = registry.packages.entry(name.to_owned()).or_insert_with(|| RegistryEntry {
+                description: None, author: None, latest: version.to_owned(), versions: BTreeMap::new(),
+            });
+            entry.latest = version.to_owned();
+            entry.versions.insert(version.to_owned(), VersionInfo::url(key.clone()));
+        }
+        let keys = Keyring::default();
+        let fetch = |url: &str| Ok(files[url].clone());
+        let resolve = |name: &str| {
+            let mut plan = Plan::new(&registry, &keys, &fetch);
+            plan.resolve(&[(name, None)]).map(|()| {
+                plan.order.iter().map(|p| format!("{}@{}", p.name, p.version)).collect::<Vec<_>>()
+            })
+        };
+
+        // display's ^1.0 picks font 1.2.0 first; app's ~1.1 then narrows it.
+        assert_eq!(resolve("app").unwrap(), ["font@1.1.2", "display@1.4.0", "app@1.0.0"]);
+        let err = resolve("loop").unwrap_err();
+        assert!(err.to_string().contains("dependency cycle: loop → knot → loop"), "{}", err);
+        let err = resolve("clash").unwrap_err();
+        assert!(err.to_string().contains("version conflict for 'font': no version satisfies all of: \
+            display requires font@^1.0; clash requires font@2"), "{}", err);
+    }
+
+    #[test]
+    fn test_vendor_and_offline_install() {
+        let dir = std::env::temp_dir().join(format!("tsuki-vendor-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let (libs, vendor_dir, fresh) = (dir.join("libs"), dir.join("vendor"), dir.join("fresh"));
+        for (name, version, deps) in [("display", "1.4.0", "font = \"^1\""), ("font", "1.1.2", ""),
+                                      ("font", "1.2.0", ""), ("font", "2.0.0", ""), ("other", "0.1.0", "")] {
+            let pkg = libs.join(name).join(version);
+            fs::create_dir_all(pkg.join("src")).unwrap();
+            fs::write(pkg.join("tsukilib.toml"), format!(
+                "[package]\nname = \"{}\"\nversion = \"{}\"\ndescription = \"the {}\"\n\n[dependencies]\n{}",
+                name, version, name, deps)).unwrap();
+            fs::write(pkg.join("src").join(format!("{}.h", name)), "// header\n").unwrap();
+        }
+        fs::create_dir_all(vendor_dir.join("gone/1.0.0")).unwrap();
+
+        let pkgs: BTreeMap<String, String> = [("display".to_owned(), "*".to_owned())].into();
+        let msgs = vendor(&pkgs, &libs, &vendor_dir).unwrap();
+        assert_eq!(msgs.len(), 2, "{:?}", msgs);
+        assert_eq!(list_installed(&vendor_dir), [("display".into(), "1.4.0".into()), ("font".into(), "1.2.0".into())]);
+        assert!(vendor_dir.join("font/1.2.0/src/font.h").exists());
+
+        let registry = local_registry(&[&vendor_dir, &libs]);
+        assert!(registry.offline);
+        assert_eq!(registry.packages["font"].latest, "2.0.0");
+        assert_eq!(registry.packages["display"].description.as_deref(), Some("the display"));
+        install("display", &fresh, &registry, &Keyring::default()).unwrap();
+        assert_eq!(list_installed(&fresh), [("display".into(), "1.4.0".into()), ("font".into(), "1.2.0".into())]);
+
+        let err = install("missing", &fresh, &local_registry(&[&vendor_dir]), &Keyring::default()).unwrap_err();
+        assert!(err.to_string().contains("not found in the local packages"), "{}", err);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_install_from_path() {
+        let dir = std::env::temp_dir().join(format!("tsuki-path-src-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let (src, libs) = (dir.join("mylib"), dir.join("libs"));
+        fs::create_dir_all(src.join("src")).unwrap();
+        fs::create_dir_all(src.join(".git")).unwrap();
+        fs::write(src.join("src/Blinker.h"), "// header\n").unwrap();
+        fs::write(src.join("tsukilib.toml"), format!("{}\n[dependencies]\nfont = \"^1\"\n", MANIFEST)).unwrap();
+
+        let source = LibSource::Path { path: src.to_string_lossy().into_owned() };
+        let (msg, deps) = install_from(&source, &libs).unwrap();
+        assert!(msg.starts_with("installed blinker@1.2.3 from path "), "{}", msg);
+        assert_eq!(deps.get("font").map(String::as_str), Some("^1"));
+        assert!(libs.join("blinker/1.2.3/src/Blinker.h").exists());
+        assert!(!libs.join("blinker/1.2.3/.git").exists());
+        let recorded = installed_source(&libs, "blinker", "1.2.3").unwrap();
+        assert_eq!(recorded, LibSource::Path { path: fs::canonicalize(&src).unwrap().to_string_lossy().into_owned() });
+        assert!(pkg_loader::load_from_file(&libs.join("blinker/1.2.3/tsukilib.toml")).is_ok());
+
+        // update follows the source, not the registry.
+        fs::write(src.join("tsukilib.toml"), MANIFEST.replace("1.2.3", "1.3.0")).unwrap();
+        let msgs = update_all(&libs, &Registry::default(), &Keyring::default(), &BTreeMap::new()).unwrap();
+        assert!(msgs[0].starts_with("installed blinker@1.3.0 from path"), "{:?}", msgs);
+        assert!(installed_source(&libs, "blinker", "1.3.0").is_some());
+
+        let err = install_from(&LibSource::Path { path: libs.join("blinker/1.3.0").to_string_lossy().into_owned() }, &libs)
+            .unwrap_err();
+        assert!(err.to_string().contains("[source] is written on install"), "{}", err);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
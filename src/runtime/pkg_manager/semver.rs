@@ -0,0 +1,333 @@
+// ─────────────────────────────────────────────────────────────────────────────
+//  tsuki :: runtime :: pkg_manager :: semver
+//
+//  Versions and version ranges for package installs, following npm's range
+//  syntax (the one tsuki_package.json documents):
+//
+//    1.2.3   =1.2.3      exactly that version
+//    1  1.x  1.2  1.2.*  any version with that prefix
+//    ^1.2.3  ^0.2        compatible: same leftmost non-zero component
+//    ~1.2.3  ~1          same minor (same major when only that is given)
+//    >=1.2 <2  >1  <=1.4 comparators, ANDed by whitespace or commas
+//    ^1 || ^2            alternatives
+//    *  or empty         anything
+//
+//  Pre-releases (1.3.0-rc.1) only satisfy a range that names a pre-release
+//  of the same major.minor.patch, as in npm.
+// ─────────────────────────────────────────────────────────────────────────────
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::{tsukiError, Result};
+
+// ── Version ───────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Version {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+    /// Dot-separated pre-release identifiers (`rc.1`), empty for a release.
+    pub pre:   Vec<String>,
+}
+
+impl Version {
+    pub fn new(major: u64, minor: u64, patch: u64) -> Self {
+        Self { major, minor, patch, pre: Vec::new() }
+    }
+
+    pub fn is_prerelease(&self) -> bool {
+        !self.pre.is_empty()
+    }
+
+    fn triple(&self) -> (u64, u64, u64) {
+        (self.major, self.minor, self.patch)
+    }
+}
+
+impl FromStr for Version {
+    type Err = tsukiError;
+    fn from_str(s: &str) -> Result<Self> {
+        let bad = || tsukiError::other(format!("`{}` is not a major.minor.patch version", s));
+        let t = s.trim().trim_start_matches('v');
+        // Build metadata (`+sha.1`) doesn't take part in ordering.
+        let t = t.split('+').next().unwrap_or("");
+        let (core, pre) = match t.split_once('-') {
+            Some((core, pre)) => (core, pre.split('.').map(str::to_owned).collect()),
+            None              => (t, Vec::new()),
+        };
+        let nums: Vec<u64> = core.split('.').map(|p| p.parse().map_err(|_| bad())).collect::<Result<_>>()?;
+        let [major, minor, patch] = nums[..] else { return Err(bad()) };
+        if pre.iter().any(String::is_empty) {
+            return Err(bad());
+        }
+        Ok(Self { major, minor, patch, pre })
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.triple().cmp(&other.triple()).then_with(|| match (self.pre.is_empty(), other.pre.is_empty()) {
+            (true, true)   => Ordering::Equal,
+            (true, false)  => Ordering::Greater,
+            (false, true)  => Ordering::Less,
+            (false, false) => {
+                // Numeric identifiers sort numerically and before alphanumeric ones.
+                let key = |id: &String| match id.parse::<u64>() {
+                    Ok(n)  => (0, n, String::new()),
+                    Err(_) => (1, 0, id.clone()),
+                };
+                self.pre.iter().map(key).cmp(other.pre.iter().map(key))
+            }
+        })
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+        if self.is_prerelease() {
+            write!(f, "-{}", self.pre.join("."))?;
+        }
+        Ok(())
+    }
+}
+
+// ── Ranges ────────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op { Eq, Gt, Ge, Lt, Le }
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Comparator {
+    op:      Op,
+    version: Version,
+}
+
+impl Comparator {
+    fn matches(&self, v: &Version) -> bool {
+        let ord = v.cmp(&self.version);
+        match self.op {
+            Op::Eq => ord == Ordering::Equal,
+            Op::Gt => ord == Ordering::Greater,
+            Op::Ge => ord != Ordering::Less,
+            Op::Lt => ord == Ordering::Less,
+            Op::Le => ord != Ordering::Greater,
+        }
+    }
+}
+
+/// A version range: alternatives (`||`) of comparator sets that must all hold.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionReq {
+    any_of: Vec<Vec<Comparator>>,
+}
+
+impl VersionReq {
+    /// The range matching every release.
+    pub fn any() -> Self {
+        Self { any_of: vec![Vec::new()] }
+    }
+
+    pub fn matches(&self, v: &Version) -> bool {
+        self.any_of.iter().any(|set| {
+            set.iter().all(|c| c.matches(v))
+                && (!v.is_prerelease() || set.iter().any(|c| {
+                    // `<` bounds end in `-0` to keep out the next version's
+                    // pre-releases; they don't opt in.
+                    c.op != Op::Lt && c.version.is_prerelease() && c.version.triple() == v.triple()
+                }))
+        })
+    }
+
+    /// The highest of `versions` in the range; strings that aren't versions
+    /// are skipped.
+    pub fn best_match<'a>(&self, versions: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+        versions.into_iter()
+            .filter_map(|s| s.parse::<Version>().ok().map(|v| (v, s)))
+            .filter(|(v, _)| self.matches(v))
+            .max_by(|a, b| a.0.cmp(&b.0))
+            .map(|(_, s)| s)
+    }
+}
+
+impl FromStr for VersionReq {
+    type Err = tsukiError;
+    fn from_str(s: &str) -> Result<Self> {
+        let is_op = |w: &str| w.chars().all(|c| "<>=^~".contains(c));
+        let any_of = s.split("||").map(|alt| {
+            let words: Vec<&str> = alt.split(|c: char| c.is_whitespace() || c == ',').filter(|w| !w.is_empty()).collect();
+            let mut set = Vec::new();
+            let mut i = 0;
+            while i < words.len() {
+                let mut word = words[i].to_owned();
+                i += 1;
+                // Allow a space between operator and version (`>= 1.2`).
+                if is_op(&word) {
+                    let next = words.get(i).filter(|w| !is_op(w)).ok_or_else(|| {
+                        tsukiError::other(format!("`{}`: operator without a version", s.trim()))
+                    })?;
+                    word.push_str(next);
+                    i += 1;
+                }
+                set.extend(comparators(&word).map_err(|e| tsukiError::other(format!("`{}`: {}", s.trim(), e)))?);
+            }
+            Ok(set)
+        }).collect::<Result<_>>()?;
+        Ok(Self { any_of })
+    }
+}
+
+impl fmt::Display for VersionReq {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let alts: Vec<String> = self.any_of.iter().map(|set| {
+            if set.is_empty() { return "*".to_owned() }
+            set.iter().map(|c| {
+                let op = match c.op { Op::Eq => "=", Op::Gt => ">", Op::Ge => ">=", Op::Lt => "<", Op::Le => "<=" };
+                format!("{}{}", op, c.version)
+            }).collect::<Vec<_>>().join(" ")
+        }).collect();
+        f.write_str(&alts.join(" || "))
+    }
+}
+
+/// A version with missing or wildcard components: `1`, `1.x`, `1.2.*`.
+struct Partial {
+    major: Option<u64>,
+    minor: Option<u64>,
+    patch: Option<u64>,
+    pre:   Vec<String>,
+}
+
+impl Partial {
+    fn parse(s: &str) -> std::result::Result<Self, String> {
+        let bad = || format!("`{}` is not a version", s);
+        let s = s.trim_start_matches('v');
+        let s = s.split('+').next().unwrap_or("");
+        let (core, pre) = match s.split_once('-') {
+            Some((core, pre)) => (core, pre.split('.').map(str::to_owned).collect()),
+            None              => (s, Vec::new()),
+        };
+        let mut parts = core.split('.').map(|p| match p {
+            "x" | "X" | "*" => Ok(None),
+            _ => p.parse().map(Some).map_err(|_| bad()),
+        });
+        let major = parts.next().ok_or_else(bad)??;
+        let minor = parts.next().transpose()?.flatten();
+        let patch = parts.next().transpose()?.flatten();
+        if parts.next().is_some() || (major.is_none() && minor.is_some()) || (minor.is_none() && patch.is_some()) {
+            return Err(bad());
+        }
+        Ok(Self { major, minor, patch, pre })
+    }
+
+    /// Lowest version the partial covers.
+    fn floor(&self) -> Version {
+        Version {
+            major: self.major.unwrap_or(0),
+            minor: self.minor.unwrap_or(0),
+            patch: self.patch.unwrap_or(0),
+            pre:   self.pre.clone(),
+        }
+    }
+}
+
+fn cmp(op: Op, version: Version) -> Comparator {
+    Comparator { op, version }
+}
+
+/// Upper bound `<major.minor.patch-0`, below that version's pre-releases.
+fn below(major: u64, minor: u64, patch: u64) -> Comparator {
+    cmp(Op::Lt, Version { major, minor, patch, pre: vec!["0".into()] })
+}
+
+/// Expand one range term into comparators.
+fn comparators(word: &str) -> std::result::Result<Vec<Comparator>, String> {
+    let split = word.find(|c: char| !"<>=^~".contains(c)).unwrap_or(word.len());
+    let (op, rest) = word.split_at(split);
+    let p = Partial::parse(rest)?;
+    let lo = p.floor();
+    let (ma, mi, pa) = (lo.major, lo.minor, lo.patch);
+
+    Ok(match (op, p.major, p.minor, p.patch) {
+        (_, None, _, _) => Vec::new(),
+        ("" | "=", Some(_), Some(_), Some(_)) => vec![cmp(Op::Eq, lo)],
+        ("" | "=", _, None, _)                => vec![cmp(Op::Ge, lo), below(ma + 1, 0, 0)],
+        ("" | "=", _, _, None)                => vec![cmp(Op::Ge, lo), below(ma, mi + 1, 0)],
+        ("^", _, _, _) => {
+            let upper = if ma > 0 || p.minor.is_none() {
+                below(ma + 1, 0, 0)
+            } else if mi > 0 || p.patch.is_none() {
+                below(0, mi + 1, 0)
+            } else {
+                below(0, 0, pa + 1)
+            };
+            vec![cmp(Op::Ge, lo), upper]
+        }
+        ("~", _, None, _) => vec![cmp(Op::Ge, lo), below(ma + 1, 0, 0)],
+        ("~", _, _, _)    => vec![cmp(Op::Ge, lo), below(ma, mi + 1, 0)],
+        (">=", ..)        => vec![cmp(Op::Ge, lo)],
+        ("<", ..)         => vec![cmp(Op::Lt, lo)],
+        // `>1.2` is `>=1.3.0`, `<=1.2` is `<1.3.0`.
+        (">", _, None, _)  => vec![cmp(Op::Ge, Version::new(ma + 1, 0, 0))],
+        (">", _, _, None)  => vec![cmp(Op::Ge, Version::new(ma, mi + 1, 0))],
+        (">", ..)          => vec![cmp(Op::Gt, lo)],
+        ("<=", _, None, _) => vec![below(ma + 1, 0, 0)],
+        ("<=", _, _, None) => vec![below(ma, mi + 1, 0)],
+        ("<=", ..)         => vec![cmp(Op::Le, lo)],
+        _ => return Err(format!("unknown operator `{}`", op)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn req(s: &str) -> VersionReq { s.parse().unwrap() }
+    fn ver(s: &str) -> Version { s.parse().unwrap() }
+
+    #[test]
+    fn test_version_ordering() {
+        let mut vs = ["1.10.0", "1.2.0", "1.2.0-rc.10", "1.2.0-rc.2", "1.2.0-alpha", "0.9.9"].map(ver);
+        vs.sort();
+        assert_eq!(vs.map(|v| v.to_string()), ["0.9.9", "1.2.0-alpha", "1.2.0-rc.2", "1.2.0-rc.10", "1.2.0", "1.10.0"]);
+    }
+
+    #[test]
+    fn test_ranges() {
+        let versions = ["0.1.0", "0.1.4", "0.2.1", "1.0.0", "1.2.0", "1.2.7", "1.10.1", "2.0.0-rc.1", "2.0.0", "2.3.0"];
+        let best = |r: &str| req(r).best_match(versions.iter().copied());
+
+        assert_eq!(best("1"),            Some("1.10.1"));
+        assert_eq!(best("1.2"),          Some("1.2.7"));
+        assert_eq!(best("1.2.x"),        Some("1.2.7"));
+        assert_eq!(best("1.2.0"),        Some("1.2.0"));
+        assert_eq!(best("^1.2"),         Some("1.10.1"));
+        assert_eq!(best("~1.2.3"),       Some("1.2.7"));
+        assert_eq!(best("^0.1.2"),       Some("0.1.4"));
+        assert_eq!(best("^0.0.1"),       None);
+        assert_eq!(best(">=1.0.0 <2"),   Some("1.10.1"));
+        assert_eq!(best(">= 1.2, < 1.3"), Some("1.2.7"));
+        assert_eq!(best(">1.2"),         Some("2.3.0"));
+        assert_eq!(best("<=1.2"),        Some("1.2.7"));
+        assert_eq!(best("^0.1 || ^1"),   Some("1.10.1"));
+        assert_eq!(best("*"),            Some("2.3.0"));
+        assert_eq!(best("<2.0.0"),       Some("1.10.1"));
+        assert_eq!(best("2.0.0-rc.1"),   Some("2.0.0-rc.1"));
+        assert_eq!(best(">=2.0.0-rc.0 <2.0.0"), Some("2.0.0-rc.1"));
+        assert_eq!(best("3"),            None);
+
+        assert!("^".parse::<VersionReq>().is_err());
+        assert!("1.2.3.4".parse::<VersionReq>().is_err());
+        assert!("=>1".parse::<VersionReq>().is_err());
+        assert_eq!(req("^1.2").to_string(), ">=1.2.0 <2.0.0-0");
+    }
+}
@@ -0,0 +1,105 @@
+// ─────────────────────────────────────────────────────────────────────────────
+//  tsuki :: runtime :: prelude
+//
+//  C++ helpers the generated code relies on (error values, strings.Split
+//  results, byte-array helpers, …). They live as .h files under prelude/,
+//  are embedded at build time and inlined after the includes of every file
+//  that imports the owning package.
+//
+//  A project can replace any of them with its own copy:
+//
+//      tsuki-core --dump-prelude prelude/        # start from the shipped files
+//      tsuki-core main.go out.cpp --prelude-dir prelude/
+//
+//  Every override must start with the version line written by the dump
+//  (`// tsuki:prelude 1`); a copy made for another prelude version is
+//  rejected instead of silently mismatching the generated code.
+// ─────────────────────────────────────────────────────────────────────────────
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::error::{tsukiError, Result};
+
+/// Bumped whenever a prelude file changes in a way generated code depends on.
+pub const VERSION: u32 = 1;
+
+/// The shipped prelude files, by name.
+pub const FILES: &[(&str, &str)] = &[
+    ("binary.h",  include_str!("prelude/binary.h")),
+    ("bytes.h",   include_str!("prelude/bytes.h")),
+    ("dht.h",     include_str!("prelude/dht.h")),
+    ("ds18b20.h", include_str!("prelude/ds18b20.h")),
+    ("eeprom.h",  include_str!("prelude/eeprom.h")),
+    // Go's `error` as a value type. Each link of the chain is one error that
+    // was wrapped with `%w` (outermost first): `errors.Is` compares link ids,
+    // `errors.As` matches a link's type tag. Messages are flattened on wrap.
+    ("error.h",   include_str!("prelude/error.h")),
+    ("freertos.h", include_str!("prelude/freertos.h")),
+    ("hcsr04.h",  include_str!("prelude/hcsr04.h")),
+    ("http.h",    include_str!("prelude/http.h")),
+    ("rtc.h",     include_str!("prelude/rtc.h")),
+    ("strings.h", include_str!("prelude/strings.h")),
+];
+
+/// Content of a shipped prelude file. Panics on an unknown name, which is a
+/// bug in the package registry.
+pub fn embedded(name: &str) -> &'static str {
+    FILES.iter().find(|(n, _)| *n == name).map(|(_, c)| *c)
+        .unwrap_or_else(|| panic!("no prelude file named {}", name))
+}
+
+/// First line of a prelude file made for this version.
+pub fn version_line() -> String {
+    format!("// tsuki:prelude {}", VERSION)
+}
+
+/// The prelude files in effect: the shipped ones, with project overrides.
+#[derive(Debug, Clone, Default)]
+pub struct Prelude {
+    overrides: HashMap<String, String>,
+}
+
+impl Prelude {
+    /// Read overrides from `dir`. Every file there must be named after a
+    /// shipped one and carry the current version line.
+    pub fn load(dir: &Path) -> Result<Self> {
+        let entries = std::fs::read_dir(dir).map_err(|e| tsukiError::other(
+            format!("cannot read prelude dir {}: {}", dir.display(), e)))?;
+        let mut overrides = HashMap::new();
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !FILES.iter().any(|(n, _)| *n == name) {
+                return Err(tsukiError::other(format!(
+                    "{}: no prelude file is named `{}` (known: {})", dir.display(), name,
+                    FILES.iter().map(|(n, _)| *n).collect::<Vec<_>>().join(", "))));
+            }
+            let content = std::fs::read_to_string(entry.path()).map_err(|e| tsukiError::other(
+                format!("cannot read {}: {}", entry.path().display(), e)))?;
+            let first = content.lines().next().unwrap_or_default().trim();
+            if first != version_line() {
+                let found = first.strip_prefix("// tsuki:prelude ").unwrap_or("none");
+                return Err(tsukiError::other(format!(
+                    "prelude override {} is for version {}, this tsuki ships version {}; \
+                     re-create it from `tsuki-core --dump-prelude`",
+                    entry.path().display(), found, VERSION)));
+            }
+            overrides.insert(name, content);
+        }
+        Ok(Self { overrides })
+    }
+
+    /// Content of prelude file `name`.
+    pub fn get(&self, name: &str) -> &str {
+        self.overrides.get(name).map(String::as_str).unwrap_or_else(|| embedded(name))
+    }
+
+    /// Write the shipped files to `dir`, ready to be edited into overrides.
+    pub fn dump(dir: &Path) -> std::io::Result<()> {
+        std::fs::create_dir_all(dir)?;
+        for (name, content) in FILES {
+            std::fs::write(dir.join(name), format!("{}\n{}", version_line(), content))?;
+        }
+        Ok(())
+    }
+}
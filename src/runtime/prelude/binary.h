@@ -0,0 +1,13 @@
+// encoding/binary: T-sized integers to and from a byte pointer.
+template <typename T>
+static void tsuki_binary_put(uint8_t* b, T v, bool big) {
+    for (unsigned i = 0; i < sizeof(T); i++)
+        b[big ? sizeof(T) - 1 - i : i] = (uint8_t)(v >> (8 * i));
+}
+template <typename T>
+static T tsuki_binary_get(const uint8_t* b, bool big) {
+    T v = 0;
+    for (unsigned i = 0; i < sizeof(T); i++)
+        v |= (T)b[big ? sizeof(T) - 1 - i : i] << (8 * i);
+    return v;
+}
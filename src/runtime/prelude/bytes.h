@@ -0,0 +1,33 @@
+// bytes: fixed-size byte arrays; the sizes come from the array types.
+static int tsuki_bytes_index(const uint8_t* s, size_t n, const uint8_t* sep, size_t m) {
+    for (size_t i = 0; m <= n && i <= n - m; i++)
+        if (memcmp(s + i, sep, m) == 0) return (int)i;
+    return -1;
+}
+template <size_t N, size_t M>
+static bool tsuki_bytes_Equal(const uint8_t (&a)[N], const uint8_t (&b)[M]) {
+    return N == M && memcmp(a, b, N) == 0;
+}
+template <size_t N, size_t M>
+static int tsuki_bytes_Compare(const uint8_t (&a)[N], const uint8_t (&b)[M]) {
+    int c = memcmp(a, b, N < M ? N : M);
+    if (c != 0) return c < 0 ? -1 : 1;
+    return N < M ? -1 : (N > M ? 1 : 0);
+}
+template <size_t N, size_t M>
+static int tsuki_bytes_Index(const uint8_t (&s)[N], const uint8_t (&sep)[M]) {
+    return tsuki_bytes_index(s, N, sep, M);
+}
+template <size_t N>
+static int tsuki_bytes_IndexByte(const uint8_t (&s)[N], uint8_t c) {
+    const void* p = memchr(s, c, N);
+    return p ? (int)((const uint8_t*)p - s) : -1;
+}
+template <size_t N, size_t M>
+static bool tsuki_bytes_HasPrefix(const uint8_t (&s)[N], const uint8_t (&p)[M]) {
+    return N >= M && memcmp(s, p, M) == 0;
+}
+template <size_t N, size_t M>
+static bool tsuki_bytes_HasSuffix(const uint8_t (&s)[N], const uint8_t (&p)[M]) {
+    return N >= M && memcmp(s + (N - M), p, M) == 0;
+}
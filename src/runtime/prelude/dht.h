@@ -0,0 +1,6 @@
+// dht: the library reports a failed read as NAN; Go code gets dht.ErrRead.
+static const tsuki_error tsuki_dht_ErrRead = tsuki_errors_New(__COUNTER__ + 1, "dht: read failed");
+static float tsuki_dht_Check(float v, tsuki_error* err) {
+    if (err) *err = isnan(v) ? tsuki_dht_ErrRead : tsuki_error();
+    return v;
+}
@@ -0,0 +1,15 @@
+// ds18b20: DallasTemperature keeps a pointer to its OneWire bus, so both
+// live together and the pair is never copied.
+struct tsuki_ds18b20 {
+    OneWire           bus;
+    DallasTemperature dt;
+    explicit tsuki_ds18b20(uint8_t pin) : bus(pin), dt(&bus) {}
+};
+static const tsuki_error tsuki_ds18b20_ErrDisconnected = tsuki_errors_New(__COUNTER__ + 1, "ds18b20: sensor disconnected");
+// Reads the first sensor on the bus, in °C.
+static float tsuki_ds18b20_Read(tsuki_ds18b20* s, tsuki_error* err) {
+    s->dt.requestTemperatures();
+    float c = s->dt.getTempCByIndex(0);
+    if (err) *err = c == DEVICE_DISCONNECTED_C ? tsuki_ds18b20_ErrDisconnected : tsuki_error();
+    return c;
+}
@@ -0,0 +1,9 @@
+// eeprom: flash-emulated EEPROM only persists on commit.
+#if defined(ESP8266) || defined(ESP32) || defined(ARDUINO_ARCH_RP2040)
+#define TSUKI_EEPROM_COMMIT() EEPROM.commit()
+#else
+#define TSUKI_EEPROM_COMMIT() ((void)0)
+#endif
+static void tsuki_eeprom_Update(int addr, uint8_t v) {
+    if (EEPROM.read(addr) != v) { EEPROM.write(addr, v); TSUKI_EEPROM_COMMIT(); }
+}
@@ -0,0 +1,54 @@
+#ifndef TSUKI_ERROR_DEPTH
+#define TSUKI_ERROR_DEPTH 4
+#endif
+template <typename T> const void* tsuki_type_tag() { static const char tag = 0; return &tag; }
+// Error type values returned by value share one slot per type.
+template <typename T> T* tsuki_error_slot(const T& v) { static T slot; slot = v; return &slot; }
+struct tsuki_error_link {
+    uint16_t    id;     // errors.New / fmt.Errorf call site, 0 for error types
+    const void* tag;    // tsuki_type_tag of an error type
+    void*       data;
+};
+struct tsuki_error {
+    tsuki_error_link chain[TSUKI_ERROR_DEPTH] = {};
+    uint8_t n = 0;      // 0 = nil
+    String  msg;
+    tsuki_error() {}
+    tsuki_error(decltype(nullptr)) {}
+    template <typename T> tsuki_error(T* e) {
+        if (e) { chain[0] = {0, tsuki_type_tag<T>(), e}; n = 1; msg = e->Error(); }
+    }
+    template <typename T> tsuki_error(const T& e) : tsuki_error(tsuki_error_slot(e)) {}
+    String Error() const { return msg; }
+    bool operator==(decltype(nullptr)) const { return n == 0; }
+    bool operator!=(decltype(nullptr)) const { return n != 0; }
+    bool operator==(const tsuki_error& o) const {
+        return n == o.n && (n == 0 || (chain[0].id == o.chain[0].id && chain[0].data == o.chain[0].data));
+    }
+    bool operator!=(const tsuki_error& o) const { return !(*this == o); }
+};
+static tsuki_error tsuki_errors_New(uint16_t id, const String& msg) {
+    tsuki_error e;
+    e.chain[0] = {id, nullptr, nullptr};
+    e.n = 1;
+    e.msg = msg;
+    return e;
+}
+static tsuki_error tsuki_errors_Wrap(uint16_t id, const String& msg, const tsuki_error& inner) {
+    tsuki_error e = tsuki_errors_New(id, msg);
+    for (uint8_t i = 0; i < inner.n && e.n < TSUKI_ERROR_DEPTH; i++) e.chain[e.n++] = inner.chain[i];
+    return e;
+}
+static bool tsuki_errors_Is(const tsuki_error& e, const tsuki_error& target) {
+    if (target.n == 0) return e.n == 0;
+    const tsuki_error_link& t = target.chain[0];
+    for (uint8_t i = 0; i < e.n; i++)
+        if (t.id ? e.chain[i].id == t.id : e.chain[i].data == t.data) return true;
+    return false;
+}
+template <typename T>
+static bool tsuki_errors_As(const tsuki_error& e, T** out) {
+    for (uint8_t i = 0; i < e.n; i++)
+        if (e.chain[i].tag == tsuki_type_tag<T>()) { *out = (T*)e.chain[i].data; return true; }
+    return false;
+}
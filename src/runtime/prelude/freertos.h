@@ -0,0 +1,11 @@
+// freertos: queue and semaphore handles under the names the Go side uses.
+// Queue items are copied in and out by value, `itemSize` bytes each.
+#include <freertos/queue.h>
+#include <freertos/semphr.h>
+using Queue     = QueueHandle_t;
+using Semaphore = SemaphoreHandle_t;
+using Ticks     = TickType_t;
+template <typename T>
+static bool tsuki_freertos_Send(Queue q, T item, Ticks wait) {
+    return xQueueSend(q, &item, wait) == pdTRUE;
+}
@@ -0,0 +1,15 @@
+// hcsr04: a 10 µs trigger pulse, then the echo pin stays HIGH for the round
+// trip. Sound covers 0.0343 cm/µs, so one way is us * 0.0343 / 2.
+static const tsuki_error tsuki_hcsr04_ErrTimeout = tsuki_errors_New(__COUNTER__ + 1, "hcsr04: no echo before timeout");
+static float tsuki_hcsr04_DistanceCm(uint8_t trig, uint8_t echo, unsigned long timeout_us, tsuki_error* err) {
+    pinMode(trig, OUTPUT);
+    pinMode(echo, INPUT);
+    digitalWrite(trig, LOW);
+    delayMicroseconds(2);
+    digitalWrite(trig, HIGH);
+    delayMicroseconds(10);
+    digitalWrite(trig, LOW);
+    unsigned long us = pulseIn(echo, HIGH, timeout_us);
+    if (err) *err = us == 0 ? tsuki_hcsr04_ErrTimeout : tsuki_error();
+    return us == 0 ? -1.0f : us * 0.0343f / 2.0f;
+}
@@ -0,0 +1,21 @@
+// http: one blocking request per call; the body is read only on success.
+struct tsuki_http_Response {
+    int    StatusCode;
+    String Body;
+};
+static tsuki_http_Response tsuki_http_Do(const char* method, const String& url,
+                                         const String& contentType, const String& body) {
+    HTTPClient http;
+#if defined(ESP8266)
+    WiFiClient client;
+    http.begin(client, url);
+#else
+    http.begin(url);
+#endif
+    if (contentType.length() > 0) http.addHeader("Content-Type", contentType);
+    tsuki_http_Response r;
+    r.StatusCode = http.sendRequest(method, body);
+    if (r.StatusCode > 0) r.Body = http.getString();
+    http.end();
+    return r;
+}
@@ -0,0 +1,28 @@
+// rtc: one I2C clock, picked by rtc.Begin. Go's time.Time is RTClib's DateTime.
+RTC_DS3231 tsuki_rtc_ds3231;
+RTC_DS1307 tsuki_rtc_ds1307;
+static uint8_t tsuki_rtc_chip = 0;   // 0 = DS3231, 1 = DS1307
+static bool tsuki_rtc_Begin(uint8_t chip) {
+    tsuki_rtc_chip = chip;
+    return chip == 1 ? tsuki_rtc_ds1307.begin() : tsuki_rtc_ds3231.begin();
+}
+static DateTime tsuki_rtc_Now() {
+    return tsuki_rtc_chip == 1 ? tsuki_rtc_ds1307.now() : tsuki_rtc_ds3231.now();
+}
+static void tsuki_rtc_SetTime(const DateTime& t) {
+    if (tsuki_rtc_chip == 1) tsuki_rtc_ds1307.adjust(t); else tsuki_rtc_ds3231.adjust(t);
+}
+// The DS1307 has no sensor; NAN tells the caller so.
+static float tsuki_rtc_Temperature() {
+    return tsuki_rtc_chip == 1 ? NAN : tsuki_rtc_ds3231.getTemperature();
+}
+static bool tsuki_rtc_LostPower() {
+    return tsuki_rtc_chip == 1 ? !tsuki_rtc_ds1307.isrunning() : tsuki_rtc_ds3231.lostPower();
+}
+// `layout` is already in DateTime::toString form (YYYY-MM-DD hh:mm:ss).
+static String tsuki_time_Format(DateTime t, const char* layout) {
+    char buf[32];
+    strncpy(buf, layout, sizeof buf - 1);
+    buf[sizeof buf - 1] = '\0';
+    return String(t.toString(buf));
+}
@@ -0,0 +1,36 @@
+#ifndef TSUKI_SPLIT_MAX
+#define TSUKI_SPLIT_MAX 8
+#endif
+// strings.Split result; the last part keeps the remainder on overflow.
+struct tsuki_StrSlice {
+    String v[TSUKI_SPLIT_MAX];
+    int    n = 0;
+    String&       operator[](int i)       { return v[i]; }
+    const String& operator[](int i) const { return v[i]; }
+};
+static tsuki_StrSlice tsuki_strings_Split(const String& s, const String& sep) {
+    tsuki_StrSlice out;
+    int from = 0;
+    while (sep.length() > 0 && out.n < TSUKI_SPLIT_MAX - 1) {
+        int at = s.indexOf(sep, from);
+        if (at < 0) break;
+        out.v[out.n++] = s.substring(from, at);
+        from = at + sep.length();
+    }
+    out.v[out.n++] = s.substring(from);
+    return out;
+}
+static String tsuki_strings_Replace(const String& s, const String& from, const String& to, int n) {
+    if (from.length() == 0) return s;
+    String out;
+    int at = 0;
+    for (int i = 0; n < 0 || i < n; i++) {
+        int hit = s.indexOf(from, at);
+        if (hit < 0) break;
+        out += s.substring(at, hit);
+        out += to;
+        at = hit + from.length();
+    }
+    out += s.substring(at);
+    return out;
+}
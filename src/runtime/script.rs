@@ -0,0 +1,90 @@
+// ─────────────────────────────────────────────────────────────────────────────
+//  tsuki :: runtime :: script
+//
+//  Rhai-scripted function mappings, for library calls a template can't
+//  express (pick an overload by argument count, build a bit mask, …).
+//
+//      [package]
+//      capabilities = ["script"]
+//
+//      [[function]]
+//      go     = "Begin"
+//      script = '''
+//          if args.len() > 1 { "{0}.begin(" + args[1].code + ")" }
+//          else              { "{0}.begin()" }
+//      '''
+//
+//  The script sees `args`, an array of `#{ code, ty }` maps (the emitted C++
+//  and its C++ type, "" when unknown), and returns the C++ snippet. The
+//  snippet is then expanded like a `cpp` template, filters included.
+//
+//  Scripts run in a locked-down engine: no `eval`, no module imports, no
+//  output, and hard caps on operations, call depth and string size.
+// ─────────────────────────────────────────────────────────────────────────────
+
+use std::sync::OnceLock;
+
+use rhai::{Array, Dynamic, Engine, Map, Scope, AST};
+
+use crate::parser::ast::Type;
+use crate::runtime::FnMap;
+
+const MAX_OPERATIONS:  u64   = 50_000;
+const MAX_CALL_LEVELS: usize = 16;
+const MAX_STRING_SIZE: usize = 4096;
+
+fn engine() -> &'static Engine {
+    static ENGINE: OnceLock<Engine> = OnceLock::new();
+    ENGINE.get_or_init(|| {
+        let mut e = Engine::new();
+        e.set_max_operations(MAX_OPERATIONS)
+            .set_max_call_levels(MAX_CALL_LEVELS)
+            .set_max_string_size(MAX_STRING_SIZE)
+            .set_max_array_size(256)
+            .set_max_map_size(256)
+            .set_max_modules(0)
+            .on_print(|_| {})
+            .on_debug(|_, _, _| {})
+            .disable_symbol("eval");
+        e
+    })
+}
+
+/// A compiled mapping script.
+#[derive(Debug, Clone)]
+pub struct Script {
+    ast: AST,
+}
+
+impl Script {
+    /// Compile `source`, reporting syntax errors.
+    pub fn compile(source: &str) -> Result<Self, String> {
+        engine().compile(source)
+            .map(|ast| Self { ast })
+            .map_err(|e| format!("script error: {}", e))
+    }
+
+    /// Run against the emitted arguments and their types (missing or None
+    /// entries are passed as unknown), then expand the returned template.
+    pub fn run(&self, args: &[String], types: &[Option<Type>]) -> Result<String, String> {
+        let info: Array = args.iter().enumerate().map(|(i, code)| {
+            let mut m = Map::new();
+            m.insert("code".into(), code.clone().into());
+            let ty = types.get(i).cloned().flatten().map(|t| t.to_cpp()).unwrap_or_default();
+            m.insert("ty".into(), ty.into());
+            Dynamic::from_map(m)
+        }).collect();
+
+        let mut scope = Scope::new();
+        scope.push("args", info);
+        let out = engine().eval_ast_with_scope::<Dynamic>(&mut scope, &self.ast)
+            .map_err(|e| format!("script error: {}", e))?;
+        let Ok(cpp) = out.into_string() else {
+            return Err("script must return a string of C++".into());
+        };
+
+        let map = FnMap::Template(cpp);
+        map.validate()?;
+        Ok(map.apply(args))
+    }
+}
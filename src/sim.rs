@@ -0,0 +1,200 @@
+// ─────────────────────────────────────────────────────────────────────────────
+//  tsuki :: sim
+//
+//  `tsuki sim`: a sketch run on the machine running tsuki, for smoke tests
+//  without a board. The program is transpiled for the `host` target, a
+//  `main()` driving setup() and loop() is appended, and it is built against
+//  the same Arduino stand-in as `tsuki test`, which records every pin change
+//  and all Serial output — as text, or as JSON lines for scripts. A run can
+//  also write its events to a trace file (`Trace`), which CI compares with a
+//  golden one to check behavior, such as an LED blinking at 1 Hz.
+// ─────────────────────────────────────────────────────────────────────────────
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{tsukiError, Result, Warning};
+use crate::gotest::{self, HOST};
+use crate::transpiler::TranspileConfig;
+use crate::{Pipeline, PipelineOptions};
+
+/// A sketch ready to be built for the host.
+#[derive(Debug)]
+pub struct SimProgram {
+    pub cpp:      String,
+    pub warnings: Vec<Warning>,
+}
+
+/// How to build and run a `SimProgram`.
+#[derive(Debug, Clone)]
+pub struct SimOptions {
+    /// C++ compiler to invoke.
+    pub cxx:       String,
+    /// Where the sources and the executable are written.
+    pub work_dir:  PathBuf,
+    /// Simulated time to run for, in milliseconds.
+    pub ms:        u64,
+    /// loop() calls at most, for sketches whose loop() never delays.
+    pub max_loops: u64,
+    /// Trace as JSON lines instead of text.
+    pub json:      bool,
+    /// Also write the events to this file as a `Trace`.
+    pub trace:     Option<PathBuf>,
+}
+
+impl Default for SimOptions {
+    fn default() -> Self {
+        Self {
+            cxx:       "g++".into(),
+            work_dir:  std::env::temp_dir().join(format!("tsuki-sim-{}", std::process::id())),
+            ms:        10_000,
+            max_loops: 1_000_000,
+            json:      false,
+            trace:     None,
+        }
+    }
+}
+
+/// The timeline of a run, as written by `--trace`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Trace {
+    pub events: Vec<Event>,
+    /// Simulated time at the end of the run, in microseconds.
+    pub end_us: u64,
+    /// loop() calls made.
+    pub loops:  u64,
+}
+
+/// A pin write that changed the pin (`digital` or `pwm` set), or a line of
+/// Serial output (`serial` set).
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Event {
+    pub t_us:    u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pin:     Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digital: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pwm:     Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub serial:  Option<String>,
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "at {} ms: ", self.t_us as f64 / 1000.0)?;
+        match (self.pin, self.digital, self.pwm, &self.serial) {
+            (Some(p), Some(v), _, _) => write!(f, "pin {} = {}", p, if v != 0 { "HIGH" } else { "LOW" }),
+            (Some(p), _, Some(v), _) => write!(f, "pin {} = {} (pwm)", p, v),
+            (_, _, _, Some(line))   => write!(f, "serial {:?}", line),
+            _                       => write!(f, "unknown event"),
+        }
+    }
+}
+
+impl Trace {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| tsukiError::other(format!("cannot read {}: {}", path.display(), e)))?;
+        serde_json::from_str(&text)
+            .map_err(|e| tsukiError::other(format!("{}: not a sim trace: {}", path.display(), e)))
+    }
+
+    /// How this trace's events differ from `golden`'s: the first event that
+    /// differs, or the missing or extra ones at the end. Empty when they match.
+    pub fn diff(&self, golden: &Trace) -> Vec<String> {
+        let mut out = Vec::new();
+        let mut pairs = self.events.iter().zip(&golden.events).enumerate();
+        if let Some((i, (got, want))) = pairs.find(|(_, (got, want))| got != want) {
+            out.push(format!("event {}: expected {}, got {}", i + 1, want, got));
+            return out;
+        }
+        let n = golden.events.len().min(self.events.len());
+        for want in &golden.events[n..] {
+            out.push(format!("missing event {}", want));
+        }
+        for got in &self.events[n..] {
+            out.push(format!("unexpected event {}", got));
+        }
+        out
+    }
+}
+
+/// Transpile a sketch for the host and add the `main()` that runs it.
+pub fn transpile(source: &str, filename: &str, opts: PipelineOptions) -> Result<SimProgram> {
+    let cfg = TranspileConfig { board: HOST.into(), ..TranspileConfig::default() };
+    let (mut cpp, warnings) = Pipeline::new(cfg).with_options(opts).run_with_warnings(source, filename)?;
+    cpp += "\n#include <tsuki_sim.h>\n\nint main(int argc, char** argv) {\n    return tsuki_sim_main(argc, argv, setup, loop);\n}\n";
+    Ok(SimProgram { cpp, warnings })
+}
+
+/// The arguments of a built `SimProgram` for `opts`.
+fn sim_args(opts: &SimOptions) -> Vec<String> {
+    let mut args = vec!["-ms".into(), opts.ms.to_string(), "-loops".into(), opts.max_loops.to_string()];
+    if opts.json { args.push("-json".into()) }
+    if let Some(path) = &opts.trace {
+        args.push("-trace".into());
+        args.push(path.display().to_string());
+    }
+    args
+}
+
+/// Build `prog` and run it, the trace going to our stdout. Returns its exit
+/// code.
+pub fn run(prog: &SimProgram, opts: &SimOptions) -> Result<i32> {
+    let exe = gotest::build(&prog.cpp, &opts.cxx, &opts.work_dir)?;
+    let status = Command::new(&exe).args(sim_args(opts)).status()
+        .map_err(|e| tsukiError::other(format!("cannot run {}: {}", exe.display(), e)))?;
+    Ok(status.code().unwrap_or(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sim() {
+        let src = "package main\nimport (\n\"arduino\"\n\"fmt\"\n)\n\
+                   func setup() {\narduino.pinMode(13, arduino.OUTPUT)\nfmt.Println(\"ready\")\n}\n\
+                   func loop() {\narduino.digitalWrite(13, arduino.HIGH)\narduino.delay(500)\n\
+                   arduino.digitalWrite(13, arduino.LOW)\narduino.delay(500)\n}";
+        let prog = transpile(src, "main.go", PipelineOptions::default()).unwrap();
+        assert!(prog.cpp.contains("return tsuki_sim_main(argc, argv, setup, loop);"), "{}", prog.cpp);
+
+        // Build and run it when there is a compiler.
+        if Command::new("g++").arg("--version").output().is_err() { return }
+        let opts = SimOptions { ms: 1500, json: true, ..SimOptions::default() };
+        let exe = gotest::build(&prog.cpp, &opts.cxx, &opts.work_dir).unwrap();
+        let out = Command::new(&exe).args(sim_args(&opts)).output().unwrap();
+        let _ = std::fs::remove_dir_all(&opts.work_dir);
+        assert_eq!(String::from_utf8_lossy(&out.stdout),
+            "{\"t_us\":0,\"serial\":\"ready\"}\n\
+             {\"t_us\":0,\"pin\":13,\"digital\":1}\n\
+             {\"t_us\":500000,\"pin\":13,\"digital\":0}\n\
+             {\"t_us\":1000000,\"pin\":13,\"digital\":1}\n\
+             {\"t_us\":1500000,\"pin\":13,\"digital\":0}\n\
+             {\"t_us\":2000000,\"end\":true,\"loops\":2}\n");
+
+        // The same run as a trace file, checked against a golden trace.
+        let path = opts.work_dir.with_extension("json");
+        let opts = SimOptions { json: false, trace: Some(path.clone()), ..opts };
+        assert_eq!(run(&prog, &opts).unwrap(), 0);
+        let _ = std::fs::remove_dir_all(&opts.work_dir);
+        let trace = Trace::load(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!((trace.events.len(), trace.end_us, trace.loops), (5, 2_000_000, 2));
+        let pin = |t_us, v| Event { t_us, pin: Some(13), digital: Some(v), pwm: None, serial: None };
+        assert_eq!(trace.events[2], pin(500_000, 0));
+
+        let mut golden = trace.clone();
+        assert!(trace.diff(&golden).is_empty());
+        golden.events[2] = pin(250_000, 0);
+        golden.events.push(pin(2_000_000, 1));
+        assert_eq!(trace.diff(&golden), ["event 3: expected at 250 ms: pin 13 = LOW, got at 500 ms: pin 13 = LOW"]);
+        golden.events[2] = pin(500_000, 0);
+        assert_eq!(trace.diff(&golden), ["missing event at 2000 ms: pin 13 = HIGH"]);
+    }
+}
@@ -0,0 +1,179 @@
+// ─────────────────────────────────────────────────────────────────────────────
+//  tsuki :: testing
+//
+//  Golden-file tests for transpiler output. A fixture directory holds Go
+//  inputs (`blink.go`) next to the C++ they must produce (`blink.cpp`);
+//  `transpile_fixture` runs every input through a `Pipeline` and fails with a
+//  line diff for each file whose output changed. Library authors point it at
+//  a pipeline that loads their tsukilib to pin down what their mappings emit.
+//
+//  Run with `TSUKI_UPDATE_GOLDEN=1` to (re)write the `.cpp` files instead.
+// ─────────────────────────────────────────────────────────────────────────────
+
+use std::fs;
+use std::path::Path;
+
+use crate::{Pipeline, TranspileConfig};
+
+/// Environment variable that switches the fixture helpers to update mode.
+pub const UPDATE_ENV: &str = "TSUKI_UPDATE_GOLDEN";
+
+/// Lines of unchanged output shown around each change in a diff.
+const CONTEXT: usize = 2;
+
+/// Check every `.go` file in `dir` against its `.cpp` golden file, using the
+/// default configuration (Arduino Uno, built-in packages only).
+///
+/// # Panics
+/// When an input fails to transpile, has no golden file, or its output
+/// differs; the message covers every failing fixture.
+pub fn transpile_fixture(dir: impl AsRef<Path>) {
+    transpile_fixture_with(&Pipeline::new(TranspileConfig::default()), dir)
+}
+
+/// Like `transpile_fixture`, with a caller-built pipeline (board, external
+/// libraries, hooks).
+///
+/// ```no_run
+/// use tsuki_core::{Pipeline, PipelineOptions, TranspileConfig};
+///
+/// let pipeline = Pipeline::new(TranspileConfig { board: "esp32".into(), ..TranspileConfig::default() })
+///     .with_options(PipelineOptions {
+///         libs_dir:  Some("target/libs".into()),
+///         pkg_names: vec!["ws2812".into()],
+///         ..Default::default()
+///     });
+/// tsuki_core::testing::transpile_fixture_with(&pipeline, "tests/golden");
+/// ```
+pub fn transpile_fixture_with(pipeline: &Pipeline, dir: impl AsRef<Path>) {
+    let update = std::env::var_os(UPDATE_ENV).is_some_and(|v| !v.is_empty() && v != "0");
+    if let Err(report) = check_fixtures(pipeline, dir.as_ref(), update) {
+        panic!("{}", report);
+    }
+}
+
+/// Run the fixtures in `dir`; Err holds the report of every failure.
+fn check_fixtures(pipeline: &Pipeline, dir: &Path, update: bool) -> Result<(), String> {
+    let mut inputs: Vec<_> = fs::read_dir(dir)
+        .map_err(|e| format!("cannot read fixture directory {}: {}", dir.display(), e))?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|x| x == "go"))
+        .collect();
+    if inputs.is_empty() {
+        return Err(format!("no .go fixtures in {}", dir.display()));
+    }
+    inputs.sort();
+
+    let mut failures = Vec::new();
+    for input in &inputs {
+        let golden = input.with_extension("cpp");
+        let name   = input.file_name().unwrap_or_default().to_string_lossy();
+        let source = fs::read_to_string(input)
+            .map_err(|e| format!("cannot read {}: {}", input.display(), e))?;
+        let actual = match pipeline.run(&source, &name) {
+            Ok(cpp) => cpp,
+            Err(e)  => { failures.push(format!("{}: transpile failed\n{}", name, e.pretty(&source))); continue }
+        };
+        if update {
+            fs::write(&golden, &actual)
+                .map_err(|e| format!("cannot write {}: {}", golden.display(), e))?;
+            continue;
+        }
+        match fs::read_to_string(&golden) {
+            Ok(expected) if expected == actual => {}
+            Ok(expected) => failures.push(format!(
+                "{}: output differs from {}\n{}", name, golden.display(), diff(&expected, &actual))),
+            Err(_) => failures.push(format!(
+                "{}: no golden file {} (run with {}=1 to create it)", name, golden.display(), UPDATE_ENV)),
+        }
+    }
+
+    if failures.is_empty() { return Ok(()) }
+    Err(format!("{} of {} fixtures failed:\n\n{}\n\nrun with {}=1 to accept the new output",
+        failures.len(), inputs.len(), failures.join("\n\n"), UPDATE_ENV))
+}
+
+/// Line diff of `expected` → `actual`: `-` lines are only in the golden
+/// file, `+` lines only in the new output, with `CONTEXT` lines around each
+/// change and `@@ line N @@` (of the golden file) at the start of each hunk.
+fn diff(expected: &str, actual: &str) -> String {
+    let a: Vec<&str> = expected.lines().collect();
+    let b: Vec<&str> = actual.lines().collect();
+
+    // Longest common subsequence table, suffix-based.
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] { lcs[i + 1][j + 1] + 1 } else { lcs[i + 1][j].max(lcs[i][j + 1]) };
+        }
+    }
+
+    // (tag, golden line number, text), tag ' ' for a shared line.
+    let mut ops = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            ops.push((' ', i, a[i])); i += 1; j += 1;
+        } else if i < a.len() && (j == b.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            ops.push(('-', i, a[i])); i += 1;
+        } else {
+            ops.push(('+', i, b[j])); j += 1;
+        }
+    }
+
+    let changed: Vec<usize> = ops.iter().enumerate().filter(|(_, op)| op.0 != ' ').map(|(k, _)| k).collect();
+    let near = |k: usize| changed.iter().any(|&c| c.abs_diff(k) <= CONTEXT);
+    let mut out = String::new();
+    let mut in_hunk = false;
+    for (k, (tag, line, text)) in ops.iter().enumerate() {
+        if !near(k) { in_hunk = false; continue }
+        if !in_hunk {
+            out += &format!("@@ line {} @@\n", line + 1);
+            in_hunk = true;
+        }
+        out += &format!("{} {}\n", tag, text);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture_dir(name: &str, files: &[(&str, &str)]) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("tsuki-golden-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for (file, content) in files {
+            fs::write(dir.join(file), content).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn test_golden_fixtures() {
+        let pipeline = Pipeline::new(TranspileConfig::default());
+        let src = "package main\nfunc setup() {\ndelay(5)\n}\nfunc loop() {\ndelay(10)\n}";
+        let dir = fixture_dir("update", &[("blink.go", src)]);
+
+        // Missing golden file, then update mode writes it.
+        let err = check_fixtures(&pipeline, &dir, false).unwrap_err();
+        assert!(err.contains("blink.go: no golden file") && err.contains(UPDATE_ENV), "{}", err);
+        check_fixtures(&pipeline, &dir, true).unwrap();
+        let golden = fs::read_to_string(dir.join("blink.cpp")).unwrap();
+        assert_eq!(golden, pipeline.run(src, "blink.go").unwrap());
+        check_fixtures(&pipeline, &dir, false).unwrap();
+
+        // A change in the input shows up as a diff against the golden file.
+        fs::write(dir.join("blink.go"), src.replace("delay(10)", "delay(20)")).unwrap();
+        let err = check_fixtures(&pipeline, &dir, false).unwrap_err();
+        assert!(err.contains("1 of 1 fixtures failed"), "{}", err);
+        assert!(err.contains("-     delay(10);\n+     delay(20);\n"), "{}", err);
+        assert!(!err.contains("#include <Arduino.h>"), "context is limited to the change: {}", err);
+
+        fs::write(dir.join("broken.go"), "package main\nfunc loop( {").unwrap();
+        let err = check_fixtures(&pipeline, &dir, false).unwrap_err();
+        assert!(err.contains("2 of 2 fixtures failed") && err.contains("broken.go: transpile failed"), "{}", err);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
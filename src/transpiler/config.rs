@@ -24,8 +24,39 @@ pub struct TranspileConfig {
 
     /// Pass through unknown package calls as raw C++ instead of erroring.
     pub passthrough_unknown: bool,
+
+    /// CPU clock override in MHz (`cpu_mhz` in the manifest).
+    #[serde(default)]
+    pub cpu_mhz: Option<u32>,
+
+    /// AVR only: keep string literals of at least this many bytes (NUL
+    /// included) in flash, as `F()` in Serial prints and `PROGMEM` tables
+    /// for string arrays. None leaves them in SRAM.
+    #[serde(default)]
+    pub progmem_min: Option<usize>,
+
+    /// Go packages transpiled alongside this file (shared workspace
+    /// packages). Their symbols are referenced by mangled name and their
+    /// declarations come from `<pkg>.h`.
+    #[serde(default)]
+    pub local_packages: Vec<String>,
+
+    /// Directory of project copies of prelude files (see
+    /// `runtime::prelude`) that replace the shipped ones.
+    #[serde(default)]
+    pub prelude_dir: Option<String>,
+
+    /// Split a `main()` that ends in an endless `for` into setup()/loop().
+    #[serde(default = "split_main_default")]
+    pub split_main: bool,
+
+    /// Emit every declaration, even ones no entry point reaches.
+    #[serde(default)]
+    pub keep_all: bool,
 }
 
+fn split_main_default() -> bool { true }
+
 impl Default for TranspileConfig {
     fn default() -> Self {
         Self {
@@ -35,6 +66,12 @@ impl Default for TranspileConfig {
             annotate_unsupported: true,
             emit_source_map:      false,
             passthrough_unknown:  true,
+            cpu_mhz:              None,
+            progmem_min:          None,
+            local_packages:       Vec::new(),
+            prelude_dir:          None,
+            split_main:           true,
+            keep_all:             false,
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,190 @@
+// ─────────────────────────────────────────────────────────────────────────────
+//  tsuki :: transpiler :: dce
+//
+//  Drops package-level functions, constants and types no reachable code
+//  refers to, so helpers that come with a library or a copied file don't
+//  cost flash. Reachability starts from:
+//
+//      entry points    main, setup, loop, setup1, loop1
+//      directives      //export, //tsuki:isr, //tsuki:hook functions
+//      globals         every `var`, with its type and initializer
+//
+//  A reachable struct keeps all of its methods (they may be called through
+//  an interface). References are by name, so a local that shadows a package
+//  symbol keeps the symbol alive — erring towards emitting too much.
+//  Only `package main` is pruned; other packages are APIs.
+// ─────────────────────────────────────────────────────────────────────────────
+
+use std::collections::{HashMap, HashSet};
+
+use crate::parser::ast::*;
+
+use super::{is_entry_point, recv_type};
+
+/// `prog` without its unreachable declarations, or None when all are used.
+pub fn prune(prog: &Program) -> Option<Program> {
+    if prog.package != "main" { return None }
+
+    let mut by_name: HashMap<&str, Vec<usize>> = HashMap::new();
+    let mut methods: HashMap<&str, Vec<usize>> = HashMap::new();
+    let mut queue = Vec::new();
+    for (i, d) in prog.decls.iter().enumerate() {
+        match d {
+            Decl::Func { recv: Some(r), .. } => methods.entry(recv_type(r).0).or_default().push(i),
+            Decl::Func { name, directives, .. } => {
+                by_name.entry(name).or_default().push(i);
+                let pinned = directives.iter().any(|d| matches!(d.name.as_str(), "export" | "tsuki:isr" | "tsuki:hook"));
+                if is_entry_point(name) || pinned { queue.push(i) }
+            }
+            Decl::Var { .. } => queue.push(i),
+            Decl::TypeDef { name, .. } | Decl::StructDef { name, .. } | Decl::Const { name, .. } =>
+                by_name.entry(name).or_default().push(i),
+        }
+    }
+
+    let mut live = HashSet::new();
+    while let Some(i) = queue.pop() {
+        if !live.insert(i) { continue }
+        let mut names = HashSet::new();
+        decl_refs(&prog.decls[i], &mut names);
+        if let Decl::StructDef { name, .. } = &prog.decls[i] {
+            queue.extend(methods.get(name.as_str()).into_iter().flatten());
+        }
+        for n in names {
+            queue.extend(by_name.get(n).into_iter().flatten());
+        }
+    }
+
+    if live.len() == prog.decls.len() { return None }
+    let mut out = prog.clone();
+    let mut i = 0;
+    out.decls.retain(|_| { i += 1; live.contains(&(i - 1)) });
+    Some(out)
+}
+
+fn decl_refs<'a>(d: &'a Decl, out: &mut HashSet<&'a str>) {
+    match d {
+        Decl::Func { recv, sig, body, .. } => {
+            for p in recv.iter().chain(&sig.params).chain(&sig.results) { type_refs(&p.ty, out) }
+            if let Some(b) = body { refs(&b.stmts, out) }
+        }
+        Decl::TypeDef { ty, .. } => type_refs(ty, out),
+        Decl::StructDef { fields, .. } => for f in fields { type_refs(&f.ty, out) },
+        Decl::Var { ty, init, .. } => {
+            if let Some(t) = ty { type_refs(t, out) }
+            if let Some(e) = init { expr_refs(e, out) }
+        }
+        Decl::Const { ty, val, .. } => {
+            if let Some(t) = ty { type_refs(t, out) }
+            expr_refs(val, out);
+        }
+    }
+}
+
+/// Every name (value or type) the declarations of `prog` refer to.
+pub(super) fn program_refs(prog: &Program) -> HashSet<&str> {
+    let mut out = HashSet::new();
+    for d in &prog.decls { decl_refs(d, &mut out) }
+    out
+}
+
+/// Every name (value or type) referenced in `stmts`.
+pub(super) fn refs<'a>(stmts: &'a [Stmt], out: &mut HashSet<&'a str>) {
+    for s in stmts {
+        match s {
+            Stmt::VarDecl { ty, init, .. } => {
+                if let Some(t) = ty { type_refs(t, out) }
+                if let Some(e) = init { expr_refs(e, out) }
+            }
+            Stmt::ConstDecl { ty, val, .. } => {
+                if let Some(t) = ty { type_refs(t, out) }
+                expr_refs(val, out);
+            }
+            Stmt::Inc { expr: x, .. } | Stmt::Dec { expr: x, .. } | Stmt::Expr { expr: x, .. }
+            | Stmt::Defer { call: x, .. } | Stmt::Go { call: x, .. } => expr_refs(x, out),
+            Stmt::ShortDecl { vals, .. } | Stmt::Return { vals, .. } => for x in vals { expr_refs(x, out) },
+            Stmt::Assign { lhs, rhs, .. } => for x in lhs.iter().chain(rhs) { expr_refs(x, out) },
+            Stmt::If { init, cond, then, else_, .. } => {
+                for s in init.iter().chain(else_) { refs(std::slice::from_ref(s), out) }
+                expr_refs(cond, out);
+                refs(&then.stmts, out);
+            }
+            Stmt::For { init, cond, post, body, .. } => {
+                for s in init.iter().chain(post) { refs(std::slice::from_ref(s), out) }
+                if let Some(c) = cond { expr_refs(c, out) }
+                refs(&body.stmts, out);
+            }
+            Stmt::Range { iter, body, .. } => { expr_refs(iter, out); refs(&body.stmts, out) }
+            Stmt::Switch { init, tag, cases, .. } => {
+                if let Some(s) = init { refs(std::slice::from_ref(s), out) }
+                if let Some(t) = tag { expr_refs(t, out) }
+                for c in cases {
+                    for x in &c.exprs { expr_refs(x, out) }
+                    refs(&c.body, out);
+                }
+            }
+            Stmt::TypeSwitch { init, expr, cases, .. } => {
+                if let Some(s) = init { refs(std::slice::from_ref(s), out) }
+                expr_refs(expr, out);
+                for c in cases {
+                    for t in &c.types { type_refs(t, out) }
+                    refs(&c.body, out);
+                }
+            }
+            Stmt::Block(b) => refs(&b.stmts, out),
+            _ => {}
+        }
+    }
+}
+
+fn expr_refs<'a>(e: &'a Expr, out: &mut HashSet<&'a str>) {
+    match e {
+        Expr::Ident { name, .. } => { out.insert(name); }
+        Expr::Inst { name, args, .. } => {
+            out.insert(name);
+            for t in args { type_refs(t, out) }
+        }
+        Expr::Binary { lhs, rhs, .. } => { expr_refs(lhs, out); expr_refs(rhs, out) }
+        Expr::Unary { expr, .. } | Expr::Select { expr, .. } => expr_refs(expr, out),
+        Expr::TypeAssert { expr, ty, .. } => { expr_refs(expr, out); type_refs(ty, out) }
+        Expr::Call { func, args, .. } => {
+            expr_refs(func, out);
+            for a in args { expr_refs(a, out) }
+        }
+        Expr::Index { expr, idx, .. } => { expr_refs(expr, out); expr_refs(idx, out) }
+        Expr::Slice { expr, lo, hi, .. } => {
+            expr_refs(expr, out);
+            for b in [lo, hi].into_iter().flatten() { expr_refs(b, out) }
+        }
+        Expr::Composite { ty, elems, .. } => {
+            type_refs(ty, out);
+            for el in elems {
+                if let Some(k) = &el.key { expr_refs(k, out) }
+                expr_refs(&el.val, out);
+            }
+        }
+        Expr::FuncLit { sig, body, .. } => {
+            for p in sig.params.iter().chain(&sig.results) { type_refs(&p.ty, out) }
+            refs(&body.stmts, out);
+        }
+        _ => {}
+    }
+}
+
+fn type_refs<'a>(t: &'a Type, out: &mut HashSet<&'a str>) {
+    match t {
+        Type::Named(n) => { out.insert(n); }
+        Type::Generic { name, args } => {
+            out.insert(name);
+            for a in args { type_refs(a, out) }
+        }
+        Type::Ptr(e) | Type::Slice(e) | Type::Array { elem: e, .. } | Type::Chan { elem: e, .. } => type_refs(e, out),
+        Type::Map { key, val } => { type_refs(key, out); type_refs(val, out) }
+        Type::Func { params, results } => for t in params.iter().chain(results) { type_refs(t, out) },
+        Type::Struct(fields) => for f in fields { type_refs(&f.ty, out) },
+        Type::Iface(methods) => for m in methods {
+            for p in m.sig.params.iter().chain(&m.sig.results) { type_refs(&p.ty, out) }
+        },
+        _ => {}
+    }
+}
@@ -0,0 +1,119 @@
+// ─────────────────────────────────────────────────────────────────────────────
+//  tsuki :: transpiler :: entry
+//
+//  Arduino calls setup() once and loop() forever. Go programs written as
+//
+//      func main() {
+//          init…
+//          for {
+//              body…
+//          }
+//      }
+//
+//  are split into `setup() { init… }` and `loop() { body… }` (unless the
+//  `split_main` config flag is off), so the core's own per-pass work — serial
+//  events, the ESP watchdog, USB — keeps running. A `continue` of the lifted
+//  loop becomes `return`. The split is skipped, with a warning, when the
+//  loop can exit or uses a local declared before it.
+// ─────────────────────────────────────────────────────────────────────────────
+
+use std::collections::HashSet;
+
+use crate::error::Warning;
+use crate::parser::ast::*;
+
+use super::dce;
+
+pub enum Split {
+    /// No `main()` ending in an endless `for`, or setup()/loop() are
+    /// declared already.
+    NotApplicable,
+    Done(Program),
+    /// The loop stays in setup(), for the reason given.
+    Kept(Warning),
+}
+
+pub fn split_main(prog: &Program) -> Split {
+    let declared = |n: &str| prog.decls.iter().any(|d| matches!(d, Decl::Func { name, recv: None, .. } if name == n));
+    if prog.package != "main" || declared("setup") || declared("loop") {
+        return Split::NotApplicable;
+    }
+    let Some(at) = prog.decls.iter().position(|d| matches!(d,
+        Decl::Func { name, recv: None, body: Some(_), .. } if name == "main")) else {
+        return Split::NotApplicable;
+    };
+    let Decl::Func { sig, body: Some(body), directives, span, .. } = &prog.decls[at] else { unreachable!() };
+    let Some((Stmt::For { init: None, cond: None, post: None, body: looped, span: for_span }, before)) =
+        body.stmts.split_last() else {
+        return Split::NotApplicable;
+    };
+
+    let keep = |why: String| Split::Kept(Warning::new(for_span.clone(), format!(
+        "main() ends in an endless loop but is not split into setup()/loop(): {}", why)));
+    if exits(&looped.stmts, false) {
+        return keep("the loop can exit (`break`, `return`, `goto` or a labeled jump)".into());
+    }
+    let mut locals = HashSet::new();
+    for s in before {
+        match s {
+            Stmt::VarDecl { name, .. } | Stmt::ConstDecl { name, .. } => { locals.insert(name.as_str()); }
+            Stmt::ShortDecl { names, .. } => locals.extend(names.iter().map(String::as_str)),
+            _ => {}
+        }
+    }
+    let mut used = HashSet::new();
+    dce::refs(&looped.stmts, &mut used);
+    let mut shared: Vec<_> = locals.intersection(&used).copied().collect();
+    if !shared.is_empty() {
+        shared.sort();
+        return keep(format!("the loop uses `{}`, declared before it; declare it at package level",
+                            shared.join("`, `")));
+    }
+
+    let mut loop_body = looped.clone();
+    lift_continue(&mut loop_body.stmts);
+    let func = |name: &str, body: Block, directives: Vec<Directive>| Decl::Func {
+        name: name.into(), recv: None, tparams: Vec::new(), sig: sig.clone(),
+        body: Some(body), directives, span: span.clone(),
+    };
+    let mut out = prog.clone();
+    out.decls.splice(at..=at, [
+        func("setup", Block { stmts: before.to_vec(), span: body.span.clone() }, directives.clone()),
+        func("loop", loop_body, Vec::new()),
+    ]);
+    Split::Done(out)
+}
+
+/// Whether the statements can leave the enclosing endless loop. `nested` is
+/// set inside an inner loop or switch, where a plain `break` stays local.
+fn exits(stmts: &[Stmt], nested: bool) -> bool {
+    stmts.iter().any(|s| match s {
+        Stmt::Return { .. } | Stmt::Goto { .. } | Stmt::Label { .. } => true,
+        Stmt::Break { label, .. } => label.is_some() || !nested,
+        Stmt::Continue { label, .. } => label.is_some(),
+        Stmt::If { then, else_, .. } =>
+            exits(&then.stmts, nested) || else_.iter().any(|e| exits(std::slice::from_ref(e), nested)),
+        Stmt::For { body, .. } | Stmt::Range { body, .. } => exits(&body.stmts, true),
+        Stmt::Switch { cases, .. } => cases.iter().any(|c| exits(&c.body, true)),
+        Stmt::TypeSwitch { cases, .. } => cases.iter().any(|c| exits(&c.body, true)),
+        Stmt::Block(b) => exits(&b.stmts, nested),
+        _ => false,
+    })
+}
+
+/// `continue` of the lifted loop ends the loop() pass.
+fn lift_continue(stmts: &mut [Stmt]) {
+    for s in stmts {
+        match s {
+            Stmt::Continue { label: None, span } => *s = Stmt::Return { vals: Vec::new(), span: span.clone() },
+            Stmt::If { then, else_, .. } => {
+                lift_continue(&mut then.stmts);
+                if let Some(e) = else_ { lift_continue(std::slice::from_mut(e)) }
+            }
+            Stmt::Switch { cases, .. } => for c in cases { lift_continue(&mut c.body) },
+            Stmt::TypeSwitch { cases, .. } => for c in cases { lift_continue(&mut c.body) },
+            Stmt::Block(b) => lift_continue(&mut b.stmts),
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,365 @@
+// ─────────────────────────────────────────────────────────────────────────────
+//  tsuki :: transpiler :: fold
+//
+//  Compile-time evaluation of integer and boolean expressions, so the sketch
+//  carries results instead of arithmetic:
+//
+//      1000 * 60           →  60000
+//      Rate / 2            →  4800        (const Rate = 9600)
+//      if false { … }      →  (dropped)
+//      if Debug { … }      →  the branch taken
+//
+//  Folding follows Go's exact constant arithmetic, within the target's C++
+//  `int` (16 bits on AVR): an operation whose result leaves that range, or
+//  that divides by zero, is left as written for the compiler to evaluate or
+//  report, since C++ would compute it in another type or overflow.
+//  Package constants are substituted only where a whole expression folds, and
+//  never inside a function that declares a local of the same name; `len` of
+//  a fixed-size array is its declared length.
+// ─────────────────────────────────────────────────────────────────────────────
+
+use std::collections::HashMap;
+
+use crate::error::Span;
+use crate::parser::ast::*;
+
+#[derive(Debug, Clone, Copy)]
+enum Value {
+    Int(i64),
+    Bool(bool),
+}
+
+impl Value {
+    fn expr(self) -> Expr {
+        match self {
+            Value::Int(n)  => Expr::Int(n),
+            Value::Bool(b) => Expr::Bool(b),
+        }
+    }
+}
+
+/// `prog` with its constant expressions folded, for a target whose `int`
+/// is `int_bits` wide.
+pub fn fold(prog: &Program, int_bits: u32) -> Program {
+    let mut f = Folder { consts: HashMap::new(), arrays: HashMap::new(), locals: HashMap::new(), int_bits };
+    let mut out = prog.clone();
+    for d in &mut out.decls {
+        match d {
+            Decl::Const { name, val, .. } => {
+                f.expr(val);
+                if let Some(v) = f.value(val) { f.consts.insert(name.clone(), v); }
+            }
+            Decl::Var { name, ty, init, .. } => {
+                if let Some(n) = array_len(ty.as_ref(), init.as_ref()) { f.arrays.insert(name.clone(), n); }
+            }
+            _ => {}
+        }
+    }
+    for d in &mut out.decls {
+        match d {
+            Decl::Var { init: Some(e), .. } => f.expr(e),
+            Decl::Func { recv, sig, body: Some(body), .. } => {
+                f.locals.clear();
+                params(recv.iter().chain(&sig.params).chain(&sig.results), &mut f.locals);
+                declared(&body.stmts, &mut f.locals);
+                f.block(&mut body.stmts);
+            }
+            _ => {}
+        }
+    }
+    out
+}
+
+struct Folder {
+    consts: HashMap<String, Value>,
+    /// Lengths of package-level arrays.
+    arrays: HashMap<String, usize>,
+    /// Names declared in the function being folded, with their length when
+    /// every declaration of the name is an array of the same size.
+    locals: Locals,
+    int_bits: u32,
+}
+
+impl Folder {
+    fn block(&self, stmts: &mut Vec<Stmt>) {
+        for s in std::mem::take(stmts) {
+            self.stmt(s, stmts);
+        }
+    }
+
+    /// Fold `s` and push what is left of it onto `out`.
+    fn stmt(&self, mut s: Stmt, out: &mut Vec<Stmt>) {
+        match &mut s {
+            Stmt::If { init, cond, then, else_, .. } => {
+                self.header(init);
+                self.expr(cond);
+                if let Some(Value::Bool(b)) = self.value(cond) { *cond = Expr::Bool(b) }
+                self.block(&mut then.stmts);
+                self.else_branch(else_);
+            }
+            Stmt::For { init, cond, post, body, .. } => {
+                self.header(init);
+                if let Some(c) = cond { self.expr(c) }
+                self.header(post);
+                self.block(&mut body.stmts);
+            }
+            Stmt::Range { iter, body, .. } => { self.expr(iter); self.block(&mut body.stmts) }
+            Stmt::Switch { init, tag, cases, .. } => {
+                self.header(init);
+                if let Some(t) = tag { self.expr(t) }
+                for c in cases {
+                    for e in &mut c.exprs { self.expr(e) }
+                    self.block(&mut c.body);
+                }
+            }
+            Stmt::TypeSwitch { init, expr, cases, .. } => {
+                self.header(init);
+                self.expr(expr);
+                for c in cases { self.block(&mut c.body) }
+            }
+            Stmt::Block(b) => self.block(&mut b.stmts),
+            simple => self.simple(simple),
+        }
+        match s {
+            Stmt::If { init, cond: Expr::Bool(taken), then, else_, .. } => {
+                let branch = match taken {
+                    true  => Some(Stmt::Block(then)),
+                    false => else_.map(|e| *e),
+                };
+                collapse(init.map(|i| *i), branch, out);
+            }
+            s => out.push(s),
+        }
+    }
+
+    /// Fold the expressions of a statement without nested blocks.
+    fn simple(&self, s: &mut Stmt) {
+        match s {
+            Stmt::VarDecl { init: Some(e), .. } | Stmt::ConstDecl { val: e, .. }
+            | Stmt::Inc { expr: e, .. } | Stmt::Dec { expr: e, .. } | Stmt::Expr { expr: e, .. }
+            | Stmt::Defer { call: e, .. } | Stmt::Go { call: e, .. } => self.expr(e),
+            Stmt::ShortDecl { vals, .. } | Stmt::Return { vals, .. } => for e in vals { self.expr(e) },
+            Stmt::Assign { lhs, rhs, .. } => for e in lhs.iter_mut().chain(rhs) { self.expr(e) },
+            _ => {}
+        }
+    }
+
+    /// Fold the simple statement of an `if`, `for` or `switch` header.
+    fn header(&self, s: &mut Option<Box<Stmt>>) {
+        if let Some(s) = s { self.simple(s) }
+    }
+
+    /// Fold an `else` branch; an `else if` that settles becomes a block, or
+    /// goes away when nothing of it is left.
+    fn else_branch(&self, s: &mut Option<Box<Stmt>>) {
+        let Some(inner) = s.take() else { return };
+        let mut out = Vec::new();
+        self.stmt(*inner, &mut out);
+        *s = match out.pop() {
+            Some(one @ (Stmt::If { .. } | Stmt::Block(_))) if out.is_empty() => Some(Box::new(one)),
+            Some(last) => {
+                out.push(last);
+                Some(Box::new(Stmt::Block(Block { stmts: out, span: Span::default() })))
+            }
+            None => None,
+        };
+    }
+
+    fn expr(&self, e: &mut Expr) {
+        match e {
+            Expr::Binary { lhs, rhs, .. } => { self.expr(lhs); self.expr(rhs) }
+            Expr::Unary { expr, .. } | Expr::Select { expr, .. }
+            | Expr::TypeAssert { expr, .. } => self.expr(expr),
+            Expr::Call { func, args, .. } => {
+                self.expr(func);
+                for a in args { self.expr(a) }
+            }
+            Expr::Index { expr, idx, .. } => { self.expr(expr); self.expr(idx) }
+            Expr::Slice { expr, lo, hi, .. } => {
+                self.expr(expr);
+                for b in [lo, hi].into_iter().flatten() { self.expr(b) }
+            }
+            Expr::Composite { elems, .. } => for el in elems {
+                if let Some(k) = &mut el.key { self.expr(k) }
+                self.expr(&mut el.val);
+            },
+            Expr::FuncLit { sig, body, .. } => {
+                let mut inner = Folder {
+                    consts: self.consts.clone(), arrays: self.arrays.clone(), locals: self.locals.clone(),
+                    int_bits: self.int_bits,
+                };
+                params(sig.params.iter().chain(&sig.results), &mut inner.locals);
+                declared(&body.stmts, &mut inner.locals);
+                inner.block(&mut body.stmts);
+            }
+            _ => {}
+        }
+        if !matches!(e, Expr::Binary { .. } | Expr::Unary { .. }) { return }
+        if let Some(v) = self.value(e) {
+            *e = v.expr();
+            return;
+        }
+        // `false && x`, `true || x` are settled by their left side alone.
+        if let Expr::Binary { op: op @ (BinOp::And | BinOp::Or), lhs, rhs, .. } = e {
+            match (self.value(lhs), op) {
+                (Some(Value::Bool(false)), BinOp::And) => *e = Expr::Bool(false),
+                (Some(Value::Bool(true)),  BinOp::Or)  => *e = Expr::Bool(true),
+                (Some(Value::Bool(_)), _) => *e = std::mem::replace(rhs.as_mut(), Expr::Nil),
+                _ => {}
+            }
+        }
+    }
+
+    fn array_len(&self, name: &str) -> Option<usize> {
+        match self.locals.get(name) {
+            Some(len) => *len,
+            None => self.arrays.get(name).copied(),
+        }
+    }
+
+    /// The value of `e`, when it is known at compile time.
+    fn value(&self, e: &Expr) -> Option<Value> {
+        use Value::*;
+        Some(match e {
+            Expr::Int(n)  => Int(*n),
+            Expr::Bool(b) => Bool(*b),
+            Expr::Ident { name, .. } if !self.locals.contains_key(name) => *self.consts.get(name)?,
+            Expr::Call { func, args, .. } => match (func.as_ref(), args.as_slice()) {
+                (Expr::Ident { name: f, .. }, [Expr::Ident { name, .. }])
+                    if f == "len" && !self.locals.contains_key(f) => Int(self.array_len(name)? as i64),
+                _ => return None,
+            },
+            Expr::Unary { op, expr, .. } => return self.fits(match (op, self.value(expr)?) {
+                (UnOp::Neg,    Int(n))  => Int(n.checked_neg()?),
+                (UnOp::BitNot, Int(n))  => Int(!n),
+                (UnOp::Not,    Bool(b)) => Bool(!b),
+                _ => return None,
+            }),
+            Expr::Binary { op, lhs, rhs, .. } => return self.fits(match (self.value(lhs)?, self.value(rhs)?) {
+                (Int(a), Int(b)) => match op {
+                    BinOp::Add       => Int(a.checked_add(b)?),
+                    BinOp::Sub       => Int(a.checked_sub(b)?),
+                    BinOp::Mul       => Int(a.checked_mul(b)?),
+                    BinOp::Div       => Int(a.checked_div(b)?),
+                    BinOp::Rem       => Int(a.checked_rem(b)?),
+                    BinOp::BitAnd    => Int(a & b),
+                    BinOp::BitOr     => Int(a | b),
+                    BinOp::BitXor    => Int(a ^ b),
+                    BinOp::BitAndNot => Int(a & !b),
+                    BinOp::Shl => {
+                        let r = (a as i128).checked_shl(u32::try_from(b).ok().filter(|&b| b < 64)?)?;
+                        Int(i64::try_from(r).ok()?)
+                    }
+                    BinOp::Shr => Int(a >> u32::try_from(b).ok()?.min(63)),
+                    BinOp::Eq => Bool(a == b), BinOp::Ne => Bool(a != b),
+                    BinOp::Lt => Bool(a < b),  BinOp::Le => Bool(a <= b),
+                    BinOp::Gt => Bool(a > b),  BinOp::Ge => Bool(a >= b),
+                    BinOp::And | BinOp::Or => return None,
+                },
+                (Bool(a), Bool(b)) => match op {
+                    BinOp::And => Bool(a && b),
+                    BinOp::Or  => Bool(a || b),
+                    BinOp::Eq  => Bool(a == b),
+                    BinOp::Ne  => Bool(a != b),
+                    _ => return None,
+                },
+                _ => return None,
+            }),
+            _ => return None,
+        })
+    }
+
+    /// `v`, unless it is a result the target's `int` can't hold.
+    fn fits(&self, v: Value) -> Option<Value> {
+        let max = (1i64 << (self.int_bits - 1)) - 1;
+        match v {
+            Value::Int(n) if n < -max - 1 || n > max => None,
+            v => Some(v),
+        }
+    }
+}
+
+/// Replace a settled `if` by its header statement and the branch taken. The
+/// branch is inlined unless it declares names, which keep their own scope.
+fn collapse(init: Option<Stmt>, branch: Option<Stmt>, out: &mut Vec<Stmt>) {
+    let mut stmts: Vec<Stmt> = init.into_iter().collect();
+    match branch {
+        Some(Stmt::Block(b)) if !declares(&b.stmts) => stmts.extend(b.stmts),
+        Some(s) => stmts.push(s),
+        None => {}
+    }
+    match stmts.first() {
+        // The header's names are scoped to the `if`.
+        Some(s) if declares(std::slice::from_ref(s)) =>
+            out.push(Stmt::Block(Block { stmts, span: Span::default() })),
+        _ => out.extend(stmts),
+    }
+}
+
+fn declares(stmts: &[Stmt]) -> bool {
+    stmts.iter().any(|s| matches!(s,
+        Stmt::VarDecl { .. } | Stmt::ConstDecl { .. } | Stmt::ShortDecl { .. }))
+}
+
+type Locals = HashMap<String, Option<usize>>;
+
+/// Length of an array declared with type `ty` or initialized with `init`.
+fn array_len(ty: Option<&Type>, init: Option<&Expr>) -> Option<usize> {
+    match (ty, init) {
+        (Some(Type::Array { len, .. }), _) => *len,
+        (None, Some(Expr::Composite { ty: Type::Array { len, .. }, .. })) => *len,
+        _ => None,
+    }
+}
+
+fn declare(out: &mut Locals, name: &str, len: Option<usize>) {
+    out.entry(name.to_string())
+        .and_modify(|prev| if *prev != len { *prev = None })
+        .or_insert(len);
+}
+
+fn params<'a>(ps: impl Iterator<Item = &'a FuncParam>, out: &mut Locals) {
+    for p in ps {
+        if let Some(n) = &p.name { declare(out, n, array_len(Some(&p.ty), None)) }
+    }
+}
+
+/// Every local name `stmts` declare, at any depth (function literals aside).
+fn declared(stmts: &[Stmt], out: &mut Locals) {
+    for s in stmts {
+        match s {
+            Stmt::VarDecl { name, ty, init, .. } => declare(out, name, array_len(ty.as_ref(), init.as_ref())),
+            Stmt::ConstDecl { name, .. } => declare(out, name, None),
+            Stmt::ShortDecl { names, vals, .. } => for (i, n) in names.iter().enumerate() {
+                let len = match names.len() == vals.len() {
+                    true  => array_len(None, vals.get(i)),
+                    false => None,
+                };
+                declare(out, n, len);
+            },
+            Stmt::If { init, then, else_, .. } => {
+                for s in init.iter().chain(else_) { declared(std::slice::from_ref(s), out) }
+                declared(&then.stmts, out);
+            }
+            Stmt::For { init, post, body, .. } => {
+                for s in init.iter().chain(post) { declared(std::slice::from_ref(s), out) }
+                declared(&body.stmts, out);
+            }
+            Stmt::Range { key, val, body, .. } => {
+                for n in key.iter().chain(val) { declare(out, n, None) }
+                declared(&body.stmts, out);
+            }
+            Stmt::Switch { init, cases, .. } => {
+                if let Some(s) = init { declared(std::slice::from_ref(s), out) }
+                for c in cases { declared(&c.body, out) }
+            }
+            Stmt::TypeSwitch { init, bind, cases, .. } => {
+                if let Some(s) = init { declared(std::slice::from_ref(s), out) }
+                if let Some(n) = bind { declare(out, n, None) }
+                for c in cases { declared(&c.body, out) }
+            }
+            Stmt::Block(b) => declared(&b.stmts, out),
+            _ => {}
+        }
+    }
+}
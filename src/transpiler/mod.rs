@@ -5,19 +5,27 @@
 // ─────────────────────────────────────────────────────────────────────────────
 
 pub mod config;
+mod dce;
+mod entry;
+mod fold;
+pub mod plugin;
+mod races;
 pub use config::TranspileConfig;
 
+use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
 use std::fmt::Write as FmtWrite;
 
-use crate::error::{tsukiError, Result};
+use crate::error::{tsukiError, Result, Span, Suggestion, Warning};
 use crate::parser::ast::*;
-use crate::runtime::Runtime;
+use crate::runtime::{Board, FnMap, Pins, PkgMap, Runtime, FEATURES, STR_SLICE};
 
 // ─────────────────────────────────────────────────────────────────────────────
 
 pub struct Transpiler {
     cfg:       TranspileConfig,
+    /// Profile of `cfg.board`, None when the id isn't in the catalog.
+    board:     Option<Board>,
     rt:        Runtime,
     indent:    usize,
     includes:  HashSet<String>,
@@ -25,6 +33,40 @@ pub struct Transpiler {
     /// Maps local variable names → canonical package name for instance-method dispatch.
     /// e.g. `sensor` → `"dht"` when declared as `var sensor dht.DHT`.
     var_types: HashMap<String, String>,
+    /// Variables among `var_types` that hold a pointer to their package's
+    /// `cpp_class`; method templates get them dereferenced as `{self}`.
+    class_ptrs: HashSet<String>,
+    /// Static Go types of variables in scope, used to resolve type switches.
+    decl_types: HashMap<String, Type>,
+    /// Package-level Go names whose C++ symbol differs (mangled or exported).
+    symbols:   HashMap<String, String>,
+    /// Names declared inside the current function; these shadow `symbols`.
+    locals:    HashSet<String>,
+    /// Package-level vars and consts of the program.
+    globals:   HashSet<String>,
+    /// Statements injected at the top of `setup()` (clock, second loop).
+    prelude:   Vec<String>,
+    /// `//tsuki:hook` functions: injection point and C++ call, in source order.
+    hooks:     Vec<(String, String)>,
+    /// Post-hook calls of the entry point being emitted, run before each `return`.
+    exit_hooks: Vec<String>,
+    /// Fields of the structs declared in this program, by name.
+    structs:   HashMap<String, Vec<Field>>,
+    /// Result types of the function being emitted.
+    results:   Vec<Type>,
+    /// Package-level integer constants, for checks on literal arguments.
+    consts:    HashMap<String, i64>,
+    /// Globals emitted as PROGMEM string tables; reads go through flash.
+    pgm_tables: HashSet<String>,
+    /// C++ helpers of imported packages, in import order.
+    preludes:  Vec<String>,
+    /// Global declarations of imported packages, in import order.
+    pkg_globals: Vec<String>,
+    /// Non-fatal diagnostics collected while generating.
+    warnings:  RefCell<Vec<Warning>>,
+    /// Where the mapped call being emitted reports its error (`{err}` in a
+    /// template): `&err` for `v, err := pkg.F()`, else nothing.
+    err_dest:  RefCell<Option<String>>,
 }
 
 impl Transpiler {
@@ -34,19 +76,77 @@ impl Transpiler {
     }
 
     /// Create with a pre-built runtime (may contain external libs).
-    pub fn with_runtime(cfg: TranspileConfig, rt: Runtime) -> Self {
+    pub fn with_runtime(cfg: TranspileConfig, mut rt: Runtime) -> Self {
+        let board = Board::find(&cfg.board);
+        if let Some(b) = &board {
+            rt.for_board(b);
+        }
         Self {
+            board,
             cfg,
             rt,
             indent:    0,
             includes:  HashSet::new(),
             pkg_map:   HashMap::new(),
             var_types: HashMap::new(),
+            class_ptrs: HashSet::new(),
+            decl_types: HashMap::new(),
+            symbols:   HashMap::new(),
+            locals:    HashSet::new(),
+            globals:   HashSet::new(),
+            prelude:   Vec::new(),
+            hooks:     Vec::new(),
+            exit_hooks: Vec::new(),
+            structs:   HashMap::new(),
+            results:   Vec::new(),
+            consts:    HashMap::new(),
+            pgm_tables: HashSet::new(),
+            preludes:  Vec::new(),
+            pkg_globals: Vec::new(),
+            warnings:  RefCell::new(Vec::new()),
+            err_dest:  RefCell::new(None),
         }
     }
 
+    /// Diagnostics from the last `generate` that did not stop it.
+    pub fn warnings(&self) -> Vec<Warning> {
+        self.warnings.borrow().clone()
+    }
+
     pub fn generate(&mut self, prog: &Program) -> Result<String> {
+        self.check_unused_imports(prog);
+        let split;
+        let prog = match self.cfg.split_main {
+            true => match entry::split_main(prog) {
+                entry::Split::Done(p) => { split = p; &split }
+                entry::Split::Kept(w) => { self.warnings.borrow_mut().push(w); prog }
+                entry::Split::NotApplicable => prog,
+            },
+            false => prog,
+        };
+        let pruned;
+        let prog = match self.cfg.keep_all {
+            false => match dce::prune(prog) {
+                Some(p) => { pruned = p; &pruned }
+                None => prog,
+            },
+            true => prog,
+        };
+        let folded = fold::fold(prog, self.board.as_ref().map_or(32, |b| b.int_bits()));
+        let prog = &folded;
+        let entry = |n: &str| prog.decls.iter().any(|d| matches!(d, Decl::Func { name, recv: None, .. } if name == n));
+        if entry("main") && entry("setup") {
+            return Err(tsukiError::codegen(
+                "both main() and setup() are declared; main() becomes setup(), so keep only one"));
+        }
         self.resolve_imports(&prog.imports);
+        self.check_imports(&prog.imports)?;
+        self.collect_symbols(prog);
+        self.collect_hooks(prog)?;
+        self.configure_eeprom()?;
+        self.configure_network();
+        self.use_packages(prog);
+        self.warnings.borrow_mut().extend(races::check(prog));
         self.includes.insert("Arduino.h".into());
 
         let mut structs   = Vec::new();
@@ -57,9 +157,15 @@ impl Transpiler {
 
         for d in &prog.decls {
             match d {
-                Decl::StructDef { .. } => structs.push(d),
+                Decl::StructDef { name, fields, .. } => {
+                    self.structs.insert(name.clone(), fields.clone());
+                    structs.push(d);
+                }
                 Decl::TypeDef   { .. } => typedefs.push(d),
-                Decl::Const     { .. } => constants.push(d),
+                Decl::Const     { name, val, .. } => {
+                    if let Expr::Int(n) = val { self.consts.insert(name.clone(), *n); }
+                    constants.push(d);
+                }
                 Decl::Var       { .. } => globals.push(d),
                 Decl::Func      { .. } => funcs.push(d),
             }
@@ -71,28 +177,55 @@ impl Transpiler {
         let mut incs: Vec<_> = self.includes.iter().cloned().collect();
         incs.sort();
         for i in &incs { out += &format!("#include <{}>\n", i); }
+        out += &self.local_includes(&prog.imports);
         out += "\n";
+        // Package preludes may build errors too, so error.h goes first.
+        let error_prelude_at = out.len();
+        for p in &self.preludes { out += p; out += "\n"; }
 
         for td in &typedefs { out += &self.emit_typedef(td)?; }
         if !typedefs.is_empty() { out += "\n"; }
 
-        for s in &structs { out += &self.emit_struct(s)?; }
+        let mut methods: HashMap<&str, Vec<&Decl>> = HashMap::new();
+        for f in &funcs {
+            if let Decl::Func { name, recv: Some(r), .. } = f {
+                let (type_name, _) = recv_type(r);
+                if !self.structs.contains_key(type_name) {
+                    return Err(tsukiError::codegen(format!(
+                        "method {}.{}: receiver must be a struct declared in this package",
+                        type_name, name)));
+                }
+                methods.entry(type_name).or_default().push(f);
+            }
+        }
+
+        let structs = self.order_structs(structs);
+        for s in &structs {
+            let own = match s {
+                Decl::StructDef { name, .. } => methods.get(name.as_str()).map(Vec::as_slice),
+                _ => None,
+            };
+            out += &self.emit_struct(s, own.unwrap_or_default())?;
+        }
         if !structs.is_empty() { out += "\n"; }
 
         for c in &constants { out += &self.emit_const(c)?; }
         if !constants.is_empty() { out += "\n"; }
 
+        for g in &self.pkg_globals { out += g; out += "\n"; }
         for g in &globals { out += &self.emit_global(g)?; }
-        if !globals.is_empty() { out += "\n"; }
+        if !globals.is_empty() || !self.pkg_globals.is_empty() { out += "\n"; }
 
         for f in &funcs {
-            if let Decl::Func { name, sig, recv: None, .. } = f {
-                if name != "setup" && name != "loop" {
-                    out += &self.emit_func_fwd(name, sig)?;
+            if let Decl::Func { name, tparams, sig, recv: None, .. } = f {
+                if name != "setup" && name != "loop" && !self.is_isr_vector(f)? {
+                    out += &self.emit_func_fwd(name, tparams, sig)?;
                 }
             }
         }
         out += "\n";
+        self.configure_clock()?;
+        out += &self.emit_second_loop(&funcs)?;
 
         let mut saw_setup = false;
         let mut saw_loop  = false;
@@ -106,9 +239,26 @@ impl Transpiler {
             out += "\n";
         }
 
-        if !saw_setup { out += "void setup() {}\n\n"; }
-        if !saw_loop  { out += "void loop()  {}\n\n"; }
+        if !saw_setup && prog.package == "main" {
+            let body = [self.hook_calls("pre-setup"), self.prelude.clone(), self.hook_calls("post-setup")].concat();
+            match body.is_empty() {
+                true  => out += "void setup() {}\n\n",
+                false => out += &format!("void setup() {{\n    {}\n}}\n\n", body.join("\n    ")),
+            }
+        }
+        if !saw_loop && prog.package == "main" {
+            let body = [self.hook_calls("pre-loop"), self.hook_calls("post-loop")].concat();
+            match body.is_empty() {
+                true  => out += "void loop()  {}\n\n",
+                false => out += &format!("void loop() {{\n    {}\n}}\n\n", body.join("\n    ")),
+            }
+        }
 
+        // `error` and fmt.Errorf need the error type without `import "errors"`.
+        let error_prelude = self.rt.prelude().get("error.h");
+        if out[error_prelude_at..].contains("tsuki_error") && !self.preludes.iter().any(|p| p == error_prelude) {
+            out.insert_str(error_prelude_at, &format!("{}\n", error_prelude));
+        }
         Ok(out)
     }
 
@@ -116,17 +266,508 @@ impl Transpiler {
 
     fn resolve_imports(&mut self, imports: &[Import]) {
         for imp in imports {
-            let canon: String = imp.path.split('/').last()
+            let canon: String = imp.path.rsplit('/').next()
                 .unwrap_or(&imp.path).to_owned();
             let alias = imp.local_name().to_owned();
             self.pkg_map.insert(alias, canon.clone());
 
-            if let Some(pkg) = self.rt.pkg(&canon) {
-                if let Some(h) = &pkg.header {
-                    self.includes.insert(h.clone());
+            if let Some(h) = self.rt.pkg(&canon).and_then(|p| p.header.as_ref()) {
+                self.includes.insert(h.clone());
+            }
+        }
+    }
+
+    /// Take in the prelude, globals and setup code of each imported package
+    /// the program refers to; an import nothing uses costs no flash.
+    fn use_packages(&mut self, prog: &Program) {
+        let names = dce::program_refs(prog);
+        let mut done = HashSet::new();
+        for imp in &prog.imports {
+            let alias = imp.local_name();
+            let used = refers_to(&names, alias);
+            let Some(canon) = self.pkg_map.get(alias).filter(|_| used) else { continue };
+            let Some(pkg) = self.rt.pkg(canon).filter(|_| done.insert(canon.clone())) else { continue };
+            if let Some(p) = &pkg.prelude {
+                if !self.preludes.contains(p) { self.preludes.push(p.clone()); }
+            }
+            if let Some(g) = &pkg.globals {
+                self.pkg_globals.push(g.trim_end().to_owned());
+            }
+            if let Some(s) = &pkg.setup {
+                self.prelude.extend(s.lines().map(str::trim_end).filter(|l| !l.is_empty()).map(str::to_owned));
+            }
+        }
+    }
+
+    /// Reject imports of packages the runtime doesn't have, and of those
+    /// that aren't available on the target's architecture or need features
+    /// or RAM it lacks. Unknown boards are not checked.
+    fn check_imports(&self, imports: &[Import]) -> Result<()> {
+        for imp in imports {
+            let canon = imp.path.rsplit('/').next().unwrap_or(&imp.path);
+            if self.is_local(canon) { continue }
+            let Some(pkg) = self.rt.pkg(canon) else {
+                if GO_STD.contains(&imp.path.as_str()) {
+                    return Err(tsukiError::type_(imp.span.clone(), format!(
+                        "package \"{}\" of the Go standard library is not available in tsuki", imp.path)));
+                }
+                return Err(tsukiError::type_(imp.span.clone(), format!("unknown package \"{}\"", imp.path))
+                    .with_suggestion(Suggestion::note(format!(
+                        "if it is a tsuki package, `tsuki pkg search {}` finds it and `tsuki pkg install` adds it", canon))));
+            };
+            let Some(board) = &self.board else { continue };
+            if !pkg.arch.permits(board.arch()) {
+                return Err(tsukiError::type_(imp.span.clone(), format!(
+                    "package \"{}\" is not available on {} (arch {}); supported: {}",
+                    imp.path, board.name, board.arch(), pkg.arch.describe())));
+            }
+            if let Some(f) = pkg.requires.iter().find(|f| !board.has(f)) {
+                let what = FEATURES.iter().find(|(id, _)| id == f).map_or(f.as_str(), |(_, n)| n);
+                return Err(tsukiError::type_(imp.span.clone(), format!(
+                    "package \"{}\" requires {}; board `{}` has none", imp.path, what, board.id)));
+            }
+            if let Some(kb) = pkg.min_ram_kb.filter(|&kb| board.ram_kb < kb) {
+                return Err(tsukiError::type_(imp.span.clone(), format!(
+                    "package \"{}\" needs {} KB of RAM; board `{}` has {} KB",
+                    imp.path, kb, board.id, board.ram_kb)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Warn about imports nothing in the program refers to (before dead
+    /// code is dropped, so an import used only by an unused function counts
+    /// as used, as in Go).
+    fn check_unused_imports(&self, prog: &Program) {
+        let names = dce::program_refs(prog);
+        for imp in &prog.imports {
+            let alias = imp.local_name();
+            if alias == "_" || alias == "." { continue }
+            if !refers_to(&names, alias) {
+                self.warnings.borrow_mut().push(Warning::new(imp.span.clone(),
+                    format!("\"{}\" imported and not used", imp.path)));
+            }
+        }
+    }
+
+    /// Same as `check_imports`, for a function restricted by its package.
+    fn check_fn_arch(&self, pkg: &PkgMap, canon: &str, func: &str, span: &Span) -> Result<()> {
+        let (Some(board), Some(rule)) = (&self.board, pkg.fn_arch.get(func)) else { return Ok(()) };
+        if rule.permits(board.arch()) { return Ok(()) }
+        Err(tsukiError::type_(span.clone(), format!(
+            "{}.{} is not available on {} (arch {}); supported: {}",
+            canon, func, board.name, board.arch(), rule.describe())))
+    }
+
+    /// The target board's profile; only needed by board-specific features.
+    fn target(&self) -> Result<Board> {
+        self.board.clone().ok_or_else(|| tsukiError::codegen(
+            format!("unknown board `{}`", self.cfg.board)))
+    }
+
+    /// Declarations other translation units need to use this package: types,
+    /// constants, `extern` globals and function prototypes, as a header.
+    pub fn declarations(&mut self, prog: &Program) -> Result<String> {
+        self.resolve_imports(&prog.imports);
+        self.collect_symbols(prog);
+        self.includes.insert("Arduino.h".into());
+
+        let mut out = self.header(&prog.package);
+        out += "#pragma once\n\n";
+        let mut incs: Vec<_> = self.includes.iter().cloned().collect();
+        incs.sort();
+        for i in &incs { out += &format!("#include <{}>\n", i); }
+        out += &self.local_includes(&prog.imports);
+        out += "\n";
+
+        let mut methods: HashMap<&str, Vec<&Decl>> = HashMap::new();
+        let mut structs = Vec::new();
+        for d in &prog.decls {
+            match d {
+                Decl::StructDef { name, fields, .. } => {
+                    self.structs.insert(name.clone(), fields.clone());
+                    structs.push(d);
+                }
+                Decl::Func { recv: Some(r), .. } => methods.entry(recv_type(r).0).or_default().push(d),
+                Decl::TypeDef { .. } => out += &self.emit_typedef(d)?,
+                _ => {}
+            }
+        }
+        for s in self.order_structs(structs) {
+            let own = match s {
+                Decl::StructDef { name, .. } => methods.get(name.as_str()).map(Vec::as_slice),
+                _ => None,
+            };
+            out += &self.emit_struct(s, own.unwrap_or_default())?;
+        }
+        for d in &prog.decls {
+            match d {
+                Decl::Const { .. } => out += &self.emit_const(d)?,
+                Decl::Var { name, ty, init, .. } => {
+                    self.note_decl_type(name, ty.as_ref(), init.as_ref());
+                    out += &format!("extern {};\n", self.declarator(name, self.symbol(name), ty.as_ref()));
+                }
+                Decl::Func { name, tparams, sig, recv: None, .. }
+                    if !is_entry_point(name) && !self.is_isr_vector(d)? => {
+                    out += &self.emit_func_fwd(name, tparams, sig)?;
+                }
+                _ => {}
+            }
+        }
+        Ok(out)
+    }
+
+    /// `#include "pkg.h"` for imported workspace packages.
+    fn local_includes(&self, imports: &[Import]) -> String {
+        imports.iter()
+            .map(|imp| imp.path.rsplit('/').next().unwrap_or(&imp.path))
+            .filter(|canon| self.cfg.local_packages.iter().any(|p| p == canon))
+            .map(|canon| format!("#include \"{}.h\"\n", canon))
+            .collect()
+    }
+
+    /// Whether `canon` is a workspace package transpiled next to this file.
+    fn is_local(&self, canon: &str) -> bool {
+        self.cfg.local_packages.iter().any(|p| p == canon)
+    }
+
+    // ── Symbols ───────────────────────────────────────────────────────────────
+
+    /// Assign C++ names to package-level symbols. Everything outside `package
+    /// main` is mangled to `pkg__Name` so packages can't collide once linked
+    /// into one sketch; `//export [Name]` keeps (or sets) the exact name.
+    fn collect_symbols(&mut self, prog: &Program) {
+        let mangled = prog.package != "main";
+        for d in &prog.decls {
+            let (name, export) = match d {
+                Decl::Func { name, recv: None, directives, .. } => {
+                    let export = directives.iter().find(|d| d.name == "export")
+                        .map(|d| d.args.first().cloned().unwrap_or_else(|| name.clone()));
+                    (name, export)
+                }
+                Decl::Var   { name, .. } | Decl::Const { name, .. } => {
+                    self.globals.insert(name.clone());
+                    (name, None)
+                }
+                _ => continue,
+            };
+            let cpp = match export {
+                Some(e) => e,
+                None if mangled && !is_entry_point(name) => mangle(&prog.package, name),
+                None => continue,
+            };
+            if &cpp != name {
+                self.symbols.insert(name.clone(), cpp);
+            }
+        }
+    }
+
+    /// C++ name of a package-level symbol.
+    fn symbol<'a>(&'a self, name: &'a str) -> &'a str {
+        self.symbols.get(name).map(String::as_str).unwrap_or(name)
+    }
+
+    // ── Entry points ──────────────────────────────────────────────────────────
+
+    /// Apply the manifest's `cpu_mhz`. AVR/ARM clocks are fixed at build time
+    /// through F_CPU by tsuki-flash; ESP32 switches its PLL at runtime.
+    fn configure_clock(&mut self) -> Result<()> {
+        let Some(mhz) = self.cfg.cpu_mhz else { return Ok(()) };
+        let board = self.target()?;
+        if board.arch() == "esp32" {
+            // The PLL runs at 80, 160 or 240 MHz, up to the chip's stock clock.
+            let steps: Vec<u32> = [80, 160, 240].into_iter().filter(|&m| m <= board.clock_mhz).collect();
+            if !steps.contains(&mhz) {
+                let steps: Vec<String> = steps.iter().map(u32::to_string).collect();
+                return Err(tsukiError::codegen(format!(
+                    "cpu_mhz = {} is not supported by {}; use {}", mhz, board.name, steps.join(", "))));
+            }
+            self.prelude.push(format!("setCpuFrequencyMhz({});", mhz));
+        }
+        Ok(())
+    }
+
+    /// Size the `eeprom` package for the target board; flash-emulated EEPROM
+    /// also has to be mapped in at the start of `setup()`.
+    fn configure_eeprom(&mut self) -> Result<()> {
+        if !self.pkg_map.values().any(|p| p == "eeprom") { return Ok(()) }
+        let board = self.target()?;
+        let size = board.eeprom_bytes().ok_or_else(|| tsukiError::codegen(
+            format!("package eeprom: {} has no EEPROM", board.name)))?;
+        self.preludes.insert(0, format!("#define TSUKI_EEPROM_SIZE {}\n", size));
+        if board.eeprom_emulated() {
+            self.prelude.push("EEPROM.begin(TSUKI_EEPROM_SIZE);".into());
+        }
+        Ok(())
+    }
+
+    /// The ESP8266 core names its WiFi and HTTP headers after the chip.
+    fn configure_network(&mut self) {
+        if self.board.as_ref().is_some_and(|b| b.arch() == "esp8266") {
+            for (h, esp) in [("WiFi.h", "ESP8266WiFi.h"), ("HTTPClient.h", "ESP8266HTTPClient.h")] {
+                if self.includes.remove(h) { self.includes.insert(esp.into()); }
+            }
+        }
+    }
+
+    /// Warn about constant `eeprom` addresses past the end of the board's
+    /// EEPROM; these silently wrap or corrupt on the target.
+    fn check_eeprom_addr(&self, func: &str, args: &[Expr], span: &Span) {
+        let Some(Expr::Int(addr)) = args.first() else { return };
+        let Some(size) = self.board.as_ref().and_then(|b| b.eeprom_bytes()) else { return };
+        if *addr < 0 || *addr >= size as i64 {
+            self.warnings.borrow_mut().push(Warning::new(span.clone(), format!(
+                "eeprom.{}: address {} is outside the {}-byte EEPROM of `{}` (0..{})",
+                func, addr, size, self.cfg.board, size - 1)));
+        }
+    }
+
+    /// Warn about `sd` file calls that churn `String`s on AVR, whose couple
+    /// of KB of heap fragment long before a data log is done.
+    fn check_sd_strings(&self, method: &str, args: &[Expr], span: &Span) {
+        if !self.board.as_ref().is_some_and(Board::is_avr) { return }
+        let msg = match (method, args.first()) {
+            ("ReadLine", _) =>
+                "ReadLine allocates a String per line, which fragments the AVR heap over a long file; \
+                 read bytes with Read into a fixed buffer instead",
+            ("Write" | "Println", Some(arg @ Expr::Binary { op: BinOp::Add, .. }))
+                if self.static_type(arg) == Some(Type::String) || concats_literal(arg) =>
+                "`+` builds temporary Strings on every write; on AVR write each piece with its own call",
+            _ => return,
+        };
+        self.warnings.borrow_mut().push(Warning::new(span.clone(), format!("sd.{}: {}", method, msg)));
+    }
+
+    /// A Go time layout (`2006-01-02 15:04`) rewritten for DateTime::toString
+    /// (`YYYY-MM-DD hh:mm`). Only the fixed-width fields RTClib can print are
+    /// accepted, and the layout must be known at compile time.
+    fn time_layout(&self, args: &[Expr], span: &Span) -> Result<String> {
+        let layout = match args.first() {
+            Some(Expr::Str(s)) => s.clone(),
+            Some(Expr::Select { expr, field, .. })
+                if self.pkg_alias(expr).and_then(|a| self.pkg_map.get(a)).is_some_and(|c| c == "time") =>
+                self.rt.pkg("time").and_then(|p| p.constants.get(field))
+                    .map(|c| c.trim_matches('"').to_owned())
+                    .ok_or_else(|| tsukiError::type_(span.clone(), format!("time.{} is not a layout", field)))?,
+            _ => return Err(tsukiError::type_(span.clone(),
+                "Format needs a literal layout or time.DateTime/DateOnly/TimeOnly")),
+        };
+        const FIELDS: &[(&str, &str)] = &[
+            ("2006", "YYYY"), ("Jan", "MMM"), ("Mon", "DDD"), ("PM", "AP"), ("pm", "ap"),
+            ("06", "YY"), ("01", "MM"), ("02", "DD"), ("15", "hh"), ("03", "hh"), ("04", "mm"), ("05", "ss"),
+        ];
+        let mut out = String::new();
+        let mut rest = layout.as_str();
+        while let Some(c) = rest.chars().next() {
+            if let Some((go, rtc)) = FIELDS.iter().find(|(go, _)| rest.starts_with(go)) {
+                out += rtc;
+                rest = &rest[go.len()..];
+            } else if c.is_ascii_alphanumeric() && c != 'T' {
+                return Err(tsukiError::type_(span.clone(), format!(
+                    "time layout {:?}: `{}` is not supported; use 2006 06 01 Jan 02 Mon 15 03 04 05 PM",
+                    layout, rest)));
+            } else {
+                out.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+        // RTClib prints `hh` as a 12-hour clock exactly when AP/ap is present.
+        if layout.contains("03") != (out.contains("AP") || out.contains("ap")) {
+            return Err(tsukiError::type_(span.clone(), format!(
+                "time layout {:?}: use 03 together with PM (12-hour clock) or 15 without it", layout)));
+        }
+        if out.len() > 31 {
+            return Err(tsukiError::type_(span.clone(), format!("time layout {:?} is longer than 31 bytes", layout)));
+        }
+        Ok(out)
+    }
+
+    /// Pin number of a literal, a package constant or `arduino.A<n>`; None
+    /// when it is only known at run time.
+    fn pin_value(&self, e: &Expr, pins: &Pins) -> Option<i64> {
+        match e {
+            Expr::Int(n) => Some(*n),
+            Expr::Ident { name, .. } if !self.locals.contains(name) => self.consts.get(name).copied(),
+            Expr::Select { expr, field, .. } => {
+                let alias = self.pkg_alias(expr)?;
+                if self.pkg_map.get(alias.as_str()).map(String::as_str) != Some("arduino") {
+                    return None;
+                }
+                let n: usize = field.strip_prefix('A')?.parse().ok()?;
+                pins.analog.get(n).map(|&p| p as i64)
+            }
+            _ => None,
+        }
+    }
+
+    /// Flag arduino pin calls whose pin the target board doesn't have, or
+    /// that can't do what is asked (PWM, ADC, output).
+    fn check_pin_args(&self, func: &str, args: &[Expr], span: &Span) {
+        let Some(board) = self.board.as_ref() else { return };
+        let Some(pins) = board.pins() else { return };
+        let Some(pin) = args.first().and_then(|a| self.pin_value(a, &pins)) else { return };
+
+        let func = func[..1].to_ascii_lowercase() + &func[1..];
+        let output = match func.as_str() {
+            "pinMode" => matches!(args.get(1),
+                Some(Expr::Select { field, .. }) if field == "OUTPUT"),
+            "digitalWrite" => true,
+            _ => false,
+        };
+        let has = |list: &[u8]| u8::try_from(pin).is_ok_and(|p| list.contains(&p));
+        let uses = |pkg: &str| self.pkg_map.values().any(|p| p == pkg);
+
+        let msg = match func.as_str() {
+            "pinMode" | "digitalWrite" | "digitalRead" => {
+                if pin < 0 || pin >= pins.digital as i64 {
+                    if has(pins.analog) {
+                        format!("{}: pin {} of {} is analog-only; use analogRead", func, pin, board.name)
+                    } else {
+                        format!("{}: pin {} does not exist on {} (digital pins 0..{})",
+                            func, pin, board.name, pins.digital - 1)
+                    }
+                } else if output && has(pins.input_only) {
+                    format!("{}: pin {} of {} is input-only", func, pin, board.name)
+                } else if output && pins.i2c.is_some_and(|b| has(&b)) && uses("wire") {
+                    format!("{}: pin {} is the I2C bus of {}, which wire also drives",
+                        func, pin, board.name)
+                } else if output && pins.spi.is_some_and(|b| has(&b)) && uses("spi") {
+                    format!("{}: pin {} is the SPI bus of {}, which spi also drives",
+                        func, pin, board.name)
+                } else {
+                    return;
+                }
+            }
+            "analogWrite" if !has(pins.pwm) => format!(
+                "analogWrite: pin {} of {} has no PWM; PWM pins are {}",
+                pin, board.name, join_pins(pins.pwm)),
+            "analogRead" if !(has(pins.analog)
+                || pins.channels && (0..pins.analog.len() as i64).contains(&pin)) => format!(
+                "analogRead: pin {} of {} is not an analog input; analog pins are {}",
+                pin, board.name, join_pins(pins.analog)),
+            _ => return,
+        };
+        self.warnings.borrow_mut().push(Warning::new(span.clone(), msg));
+    }
+
+    /// `setup1()`/`loop1()` run on the second core. The RP2040 core calls them
+    /// itself; on ESP32 they are driven by a FreeRTOS task pinned to core 0
+    /// (`loop()` owns core 1), overridable with `//tsuki:core N`.
+    fn emit_second_loop(&mut self, funcs: &[&Decl]) -> Result<String> {
+        let find = |n: &str| funcs.iter().find_map(|f| match f {
+            Decl::Func { name, recv: None, directives, .. } if name == n => Some(directives),
+            _ => None,
+        });
+        let Some(directives) = find("loop1") else {
+            if find("setup1").is_some() {
+                return Err(tsukiError::codegen("setup1() is declared without loop1()"));
+            }
+            return Ok(String::new());
+        };
+
+        let board = self.target()?;
+        if board.cores() < 2 {
+            return Err(tsukiError::codegen(format!(
+                "loop1() needs a multi-core board, but `{}` ({}) has a single core",
+                board.id, board.cpu)));
+        }
+        if board.arch() == "rp2040" {
+            return Ok(String::new());
+        }
+
+        let core = match directives.iter().find(|d| d.name == "tsuki:core") {
+            Some(d) => match d.args.first().and_then(|a| a.parse::<u8>().ok()) {
+                Some(c @ (0 | 1)) => c,
+                _ => return Err(tsukiError::codegen("//tsuki:core expects 0 or 1")),
+            },
+            None => 0,
+        };
+        let setup1 = if find("setup1").is_some() { "    setup1();\n" } else { "" };
+        self.prelude.push(format!(
+            "xTaskCreatePinnedToCore(tsuki_loop1_task, \"loop1\", 8192, nullptr, 1, nullptr, {});",
+            core));
+        // vTaskDelay lets the idle task run so the task watchdog stays fed.
+        Ok(format!(
+            "static void tsuki_loop1_task(void*) {{\n{}    for (;;) {{\n        loop1();\n        vTaskDelay(1);\n    }}\n}}\n\n",
+            setup1))
+    }
+
+    /// The C++ head of a `//tsuki:isr` handler, or None for other functions.
+    /// On AVR `//tsuki:isr TIMER1_COMPA [naked|noblock]` defines the vector
+    /// with `ISR(TIMER1_COMPA_vect)`; ESP32/ESP8266 handlers are called
+    /// through attachInterrupt() and only need to live in IRAM. Other cores
+    /// take plain functions.
+    fn isr_head(&self, d: &Decl) -> Result<Option<String>> {
+        let Decl::Func { name, directives, .. } = d else { return Ok(None) };
+        let Some(dir) = directives.iter().find(|d| d.name == "tsuki:isr") else { return Ok(None) };
+        check_plain(d, dir, "interrupt handler")?;
+        let board = self.target()?;
+        let (vector, flags) = match dir.args.split_first() {
+            Some((v, rest)) => (Some(v.as_str()), rest),
+            None            => (None, &[][..]),
+        };
+        let mut attrs = Vec::new();
+        for f in flags {
+            match f.as_str() {
+                "naked"   => attrs.push("ISR_NAKED"),
+                "noblock" => attrs.push("ISR_NOBLOCK"),
+                _ => return Err(tsukiError::type_(dir.span.clone(), format!(
+                    "unknown //tsuki:isr flag `{}`; expected naked or noblock", f))),
+            }
+        }
+        if board.is_avr() {
+            let Some(v) = vector else {
+                return Err(tsukiError::type_(dir.span.clone(), format!(
+                    "interrupt handler `{}` needs a vector on {}, e.g. //tsuki:isr TIMER1_COMPA",
+                    name, board.name)));
+            };
+            let v = if v.ends_with("_vect") { v.to_owned() } else { format!("{}_vect", v) };
+            attrs.insert(0, &v);
+            return Ok(Some(format!("ISR({})", attrs.join(", "))));
+        }
+        if !attrs.is_empty() {
+            return Err(tsukiError::type_(dir.span.clone(), format!(
+                "{} is only available on AVR boards, not {}", attrs[0], board.name)));
+        }
+        let attr = match board.arch() {
+            "esp32" | "esp8266" => "IRAM_ATTR ",
+            _                   => "",
+        };
+        Ok(Some(format!("void {}{}()", attr, self.symbol(name))))
+    }
+
+    /// Record `//tsuki:hook <point>` functions. The points are fixed:
+    /// `pre-setup` runs first in setup(), before tsuki's own init code;
+    /// `post-setup` after the last statement of setup(); `pre-loop` first in
+    /// every loop() pass and `post-loop` at its end, `return` included.
+    fn collect_hooks(&mut self, prog: &Program) -> Result<()> {
+        for d in &prog.decls {
+            let Decl::Func { name, directives, .. } = d else { continue };
+            for dir in directives.iter().filter(|d| d.name == "tsuki:hook") {
+                let point = dir.args.first().map(String::as_str).unwrap_or_default();
+                if !HOOK_POINTS.contains(&point) {
+                    return Err(tsukiError::type_(dir.span.clone(), format!(
+                        "unknown hook point `{}`; expected one of {}", point, HOOK_POINTS.join(", "))));
                 }
+                if prog.package != "main" || is_entry_point(name) {
+                    return Err(tsukiError::type_(dir.span.clone(), format!(
+                        "hook `{}` must be a helper func of package main, not an entry point", name)));
+                }
+                check_plain(d, dir, "hook")?;
+                self.hooks.push((point.to_owned(), format!("{}();", self.symbol(name))));
             }
         }
+        Ok(())
+    }
+
+    /// Calls of the hooks registered at `point`.
+    fn hook_calls(&self, point: &str) -> Vec<String> {
+        self.hooks.iter().filter(|(p, _)| p == point).map(|(_, c)| c.clone()).collect()
+    }
+
+    /// `ISR(vector)` defines no callable function, so it gets no prototype.
+    fn is_isr_vector(&self, d: &Decl) -> Result<bool> {
+        Ok(self.isr_head(d)?.is_some_and(|h| h.starts_with("ISR(")))
     }
 
     fn header(&self, pkg: &str) -> String {
@@ -146,86 +787,287 @@ impl Transpiler {
         } else { Ok(String::new()) }
     }
 
-    fn emit_struct(&self, d: &Decl) -> Result<String> {
-        if let Decl::StructDef { name, fields, .. } = d {
-            let mut s = format!("struct {} {{\n", name);
-            for f in fields {
-                let fname = f.name.as_deref().unwrap_or("_");
-                s += &format!("    {} {};\n", f.ty.to_cpp(), fname);
+    /// Go structs become C++ structs; their methods are declared as members
+    /// and defined out of line by `emit_func`.
+    /// Embedded fields become public base classes, so C++ member lookup does
+    /// Go's field and method promotion. Such structs stop being aggregates,
+    /// so they get a constructor taking every field in declaration order.
+    fn emit_struct(&self, d: &Decl, methods: &[&Decl]) -> Result<String> {
+        if let Decl::StructDef { name, tparams, fields, .. } = d {
+            let mut bases = Vec::new();
+            for f in fields.iter().filter(|f| f.is_embedded()) {
+                match &f.ty {
+                    Type::Named(_) | Type::Generic { .. } => bases.push(f.ty.to_cpp()),
+                    _ => return Err(tsukiError::codegen(format!(
+                        "struct {}: only value types can be embedded (found `{}`)",
+                        name, f.ty.to_cpp()))),
+                }
+            }
+            let mut s = template_header(tparams.iter().map(|p| p.name.as_str()));
+            if bases.is_empty() {
+                s += &format!("struct {} {{\n", name);
+            } else {
+                s += &format!("struct {} : {} {{\n", name, bases.join(", "));
+            }
+            for f in fields.iter().filter(|f| !f.is_embedded()) {
+                s += &format!("    {} {};\n", f.ty.to_cpp(), f.go_name());
+            }
+            if !bases.is_empty() {
+                let params: Vec<String> = fields.iter().map(|f| if f.is_embedded() {
+                    format!("const {}& _{}", f.ty.to_cpp(), f.go_name())
+                } else {
+                    format!("{} {}", f.ty.to_cpp(), f.go_name())
+                }).collect();
+                let inits: Vec<String> = fields.iter().map(|f| if f.is_embedded() {
+                    format!("{}(_{})", f.ty.to_cpp(), f.go_name())
+                } else {
+                    format!("{0}({0})", f.go_name())
+                }).collect();
+                s += &format!("    {}() = default;\n", name);
+                s += &format!("    {}({}) : {} {{}}\n", name, params.join(", "), inits.join(", "));
+            }
+            for m in methods {
+                if let Decl::Func { name, recv: Some(r), sig, .. } = m {
+                    let konst = if recv_type(r).1 { "" } else { " const" };
+                    s += &format!("    {} {}({}){};\n", ret_type(sig), name, self.param_list(sig), konst);
+                }
             }
             s += "};\n";
             Ok(s)
         } else { Ok(String::new()) }
     }
 
+    /// Structs held by value (fields or embeds) must be complete before use;
+    /// emit dependencies first and otherwise keep source order.
+    fn order_structs<'a>(&self, structs: Vec<&'a Decl>) -> Vec<&'a Decl> {
+        fn visit<'a>(
+            d: &'a Decl,
+            all:  &[&'a Decl],
+            seen: &mut HashSet<&'a str>,
+            out:  &mut Vec<&'a Decl>,
+        ) {
+            let Decl::StructDef { name, fields, .. } = d else { return };
+            if !seen.insert(name) { return; }
+            for f in fields {
+                if let Some(dep) = f.ty.named() {
+                    let dep = all.iter().find(|s| matches!(s, Decl::StructDef { name, .. } if name == dep));
+                    if let Some(dep) = dep { visit(dep, all, seen, out); }
+                }
+            }
+            out.push(d);
+        }
+        let mut seen = HashSet::new();
+        let mut out  = Vec::with_capacity(structs.len());
+        for d in &structs { visit(d, &structs, &mut seen, &mut out); }
+        out
+    }
+
     fn emit_const(&self, d: &Decl) -> Result<String> {
         if let Decl::Const { name, ty, val, .. } = d {
             let v = self.emit_expr(val)?;
             let t = ty.as_ref().map(|t| t.to_cpp()).unwrap_or_else(|| "auto".into());
-            Ok(format!("const {} {} = {};\n", t, name, v))
+            Ok(format!("const {} {} = {};\n", t, self.symbol(name), v))
         } else { Ok(String::new()) }
     }
 
     fn emit_global(&mut self, d: &Decl) -> Result<String> {
         if let Decl::Var { name, ty, init, .. } = d {
+            self.note_decl_type(name, ty.as_ref(), init.as_ref());
+            if let Some(table) = self.emit_pgm_table(name, init.as_ref())? {
+                return Ok(table);
+            }
             // Track variable → package for instance-method dispatch
-            if let Some(Type::Named(type_name)) = ty {
-                let pkg_part = type_name.split('.').next().unwrap_or("");
-                if let Some(canon) = self.pkg_map.get(pkg_part).cloned() {
-                    self.var_types.insert(name.clone(), canon.clone());
-                    // If this package declares a C++ class, emit as pointer
-                    // (many Arduino libs lack a default constructor).
-                    if let Some(pkg) = self.rt.pkg(&canon) {
-                        if let Some(class) = pkg.cpp_class.clone() {
-                            let init_str = if let Some(e) = init {
-                                format!(" = new {}", self.emit_expr(e)?)
-                            } else {
-                                " = nullptr".to_string()
-                            };
-                            return Ok(format!("{}* {}{};
-", class, name, init_str));
-                        }
-                    }
-                }
+            match ty {
+                Some(t) => self.note_pkg_type(name, t),
+                None    => self.note_pkg_value(name, init.as_ref()),
+            }
+            // A package type with a C++ class is held through a pointer
+            // (many Arduino libs lack a default constructor).
+            if let Some(class) = ty.as_ref().and_then(|t| self.pkg_class(t)) {
+                self.class_ptrs.insert(name.clone());
+                let init_str = match init {
+                    Some(e) if matches!(ty, Some(Type::Ptr(_))) => format!(" = {}", self.emit_expr(e)?),
+                    Some(e) => format!(" = new {}", self.emit_expr(e)?),
+                    None    => " = nullptr".to_string(),
+                };
+                return Ok(format!("{}* {}{};
+", class, self.symbol(name), init_str));
             }
-            let t    = ty.as_ref().map(|t| t.to_cpp()).unwrap_or_else(|| "auto".into());
+            let decl = self.declarator(name, self.symbol(name), ty.as_ref());
             let init = init.as_ref().map(|e| self.emit_expr(e)).transpose()?
                 .map(|s| format!(" = {}", s)).unwrap_or_default();
-            Ok(format!("{} {}{};
-", t, name, init))
+            Ok(format!("{}{};
+", decl, init))
         } else { Ok(String::new()) }
     }
 
-    fn emit_func_fwd(&self, name: &str, sig: &FuncSig) -> Result<String> {
+    // ── PROGMEM ───────────────────────────────────────────────────────────────
+
+    /// The `progmem_min` threshold, when the target is AVR.
+    fn progmem_min(&self) -> Option<usize> {
+        let avr = self.board.as_ref().is_some_and(Board::is_avr);
+        self.cfg.progmem_min.filter(|_| avr)
+    }
+
+    /// `var names = [N]string{"…", …}` as a table of flash strings, when
+    /// every element is a literal and together they reach `progmem_min`.
+    fn emit_pgm_table(&mut self, name: &str, init: Option<&Expr>) -> Result<Option<String>> {
+        let Some(min) = self.progmem_min() else { return Ok(None) };
+        let Some(Expr::Composite { ty, elems, .. }) = init else { return Ok(None) };
+        let len = match ty {
+            Type::Array { len: Some(n), elem } if **elem == Type::String => *n,
+            Type::Slice(elem) | Type::Array { len: None, elem } if **elem == Type::String => elems.len(),
+            _ => return Ok(None),
+        };
+        let lits: Option<Vec<&Expr>> = elems.iter()
+            .map(|e| match (&e.key, &e.val) {
+                (None, v @ Expr::Str(_)) => Some(v),
+                _ => None,
+            })
+            .collect();
+        let Some(lits) = lits else { return Ok(None) };
+        let bytes: usize = lits.iter().map(|v| match v { Expr::Str(s) => s.len() + 1, _ => 0 }).sum();
+        if bytes < min { return Ok(None) }
+
+        let sym = self.symbol(name).to_owned();
+        let mut out = String::new();
+        let mut names = Vec::with_capacity(lits.len());
+        for (i, v) in lits.iter().enumerate() {
+            names.push(format!("{}_{}", sym, i));
+            out += &format!("const char {}_{}[] PROGMEM = {};
+", sym, i, self.emit_str_raw(v)?);
+        }
+        out += &format!("const char* const {}[{}] PROGMEM = {{ {} }};
+", sym, len, names.join(", "));
+        self.pgm_tables.insert(name.to_owned());
+        Ok(Some(out))
+    }
+
+    /// `names[i]` of a PROGMEM table, copied out of flash.
+    fn pgm_index(&self, arr: &Expr, idx: &str) -> Option<String> {
+        let Expr::Ident { name, .. } = arr else { return None };
+        if !self.pgm_tables.contains(name) || self.locals.contains(name) { return None }
+        Some(format!("String((const __FlashStringHelper*)pgm_read_ptr(&{}[{}]))",
+            self.resolve_ident(name), idx))
+    }
+
+    /// Tables in flash are read-only.
+    fn check_pgm_write(&self, target: &Expr) -> Result<()> {
+        match target {
+            Expr::Index { expr, .. } => self.check_pgm_write(expr),
+            Expr::Ident { name, span } if self.pgm_tables.contains(name) && !self.locals.contains(name) => {
+                Err(tsukiError::type_(span.clone(), format!(
+                    "cannot assign to `{}`: it is kept in flash as a PROGMEM table (progmem_min = {})",
+                    name, self.cfg.progmem_min.unwrap_or_default())))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Literal arguments of a Serial print, as `F("…")` once they reach
+    /// `progmem_min` bytes.
+    fn flash_strings(&self, fmap: &FnMap, args: &[Expr], arg_strs: &[String]) -> Result<Vec<String>> {
+        let min = match (self.progmem_min(), fmap) {
+            (Some(min), FnMap::Template(t)) if t.starts_with("Serial.print") => min,
+            _ => return Ok(arg_strs.to_vec()),
+        };
+        args.iter().zip(arg_strs).map(|(a, s)| match a {
+            Expr::Str(lit) if lit.len() + 1 >= min => Ok(format!("F({})", self.emit_str_raw(a)?)),
+            _ => Ok(s.clone()),
+        }).collect()
+    }
+
+    fn emit_func_fwd(&self, name: &str, tparams: &[TypeParam], sig: &FuncSig) -> Result<String> {
         // Go's main() becomes setup() — don't forward-declare it under "main"
-        let cpp_name = if name == "main" { "setup" } else { name };
-        Ok(format!("{} {}({});\n", ret_type(sig), cpp_name, params_str(sig)))
+        let cpp_name = if name == "main" { "setup" } else { self.symbol(name) };
+        Ok(format!("{}{} {}({});\n",
+            template_header(tparams.iter().map(|p| p.name.as_str())),
+            ret_type(sig), cpp_name, self.param_list(sig)))
     }
 
     fn emit_func(&mut self, d: &Decl) -> Result<String> {
-        if let Decl::Func { name, recv, sig, body, .. } = d {
+        if let Decl::Func { name, recv, tparams, sig, body, .. } = d {
             let ret    = ret_type(sig);
-            let params = params_str(sig);
+            let params = self.param_list(sig);
+            let mut template = template_header(tparams.iter().map(|p| p.name.as_str()));
 
+            // Pointer receivers bind `this`; value receivers get a copy, as in Go.
+            let mut recv_bind = None;
             let full_name = if let Some(r) = recv {
-                let type_name = match &r.ty {
-                    Type::Ptr(inner) => match inner.as_ref() { Type::Named(n) => n.clone(), t => t.to_cpp() },
-                    Type::Named(n)   => n.clone(),
-                    t                => t.to_cpp(),
-                };
-                format!("{}::{}", type_name, name)
+                let (_, is_ptr) = recv_type(r);
+                let owner = match &r.ty { Type::Ptr(t) => t.as_ref(), t => t };
+                // Methods of `Pair[T]` are defined as `template <typename T> … Pair<T>::`.
+                if let Type::Generic { args, .. } = owner {
+                    let names = args.iter().map(|a| match a {
+                        Type::Named(n) => Ok(n.as_str()),
+                        _ => Err(tsukiError::codegen(format!(
+                            "method {}: receiver type parameters must be plain names", name))),
+                    }).collect::<Result<Vec<_>>>()?;
+                    template = template_header(names);
+                }
+                let type_name = owner.to_cpp();
+                if let Some(rn) = r.name.as_deref().filter(|n| *n != "_") {
+                    recv_bind = Some(if is_ptr {
+                        format!("{}* {} = this;", type_name, rn)
+                    } else {
+                        format!("{} {} = *this;", type_name, rn)
+                    });
+                }
+                let konst = if is_ptr { "" } else { " const" };
+                format!("{}::{}({}){}", type_name, name, params, konst)
             } else {
                 // Go's main() → Arduino's setup(); mark saw_setup in caller
-                if name == "main" { "setup".to_owned() } else { name.clone() }
+                let n = if name == "main" { "setup" } else { self.symbol(name) };
+                format!("{}({})", n, params)
             };
 
+            // Hooks and the prelude bracket the bodies of setup() and loop().
+            let (lead, tail) = match full_name.as_str() {
+                "setup()" => ([self.hook_calls("pre-setup"), self.prelude.clone()].concat(),
+                              self.hook_calls("post-setup")),
+                "loop()"  => (self.hook_calls("pre-loop"), self.hook_calls("post-loop")),
+                _         => (Vec::new(), Vec::new()),
+            };
+            self.exit_hooks = tail.clone();
+
+            let outer = (self.decl_types.clone(), self.var_types.clone(), self.class_ptrs.clone());
+            self.results = sig.results.iter().map(|r| r.ty.clone()).collect();
+            self.locals.clear();
+            for p in recv.iter().chain(&sig.params) {
+                if let Some(n) = &p.name {
+                    self.decl_types.insert(n.clone(), p.ty.clone());
+                    self.locals.insert(n.clone());
+                    self.note_pkg_type(n, &p.ty);
+                    if matches!(p.ty, Type::Ptr(_)) && self.pkg_class(&p.ty).is_some() {
+                        self.class_ptrs.insert(n.clone());
+                    }
+                }
+            }
             let body_str = if let Some(b) = body {
-                self.emit_block(b)?
+                self.emit_block(b)
             } else {
-                ";".into()
+                Ok(";".into())
             };
+            (self.decl_types, self.var_types, self.class_ptrs) = outer;
+            self.exit_hooks.clear();
+            let mut body_str = body_str?;
+            if !lead.is_empty() {
+                recv_bind = Some(lead.join("\n    "));
+            }
+            let returns_last = body.as_ref().and_then(|b| b.stmts.last())
+                .is_some_and(|s| matches!(s, Stmt::Return { .. }));
+            if !tail.is_empty() && !returns_last {
+                body_str.pop();
+                body_str += &format!("    {}\n}}", tail.join("\n    "));
+            }
+            if let Some(line) = recv_bind {
+                body_str = body_str.replacen("{\n", &format!("{{\n    {}\n", line), 1);
+            }
 
-            Ok(format!("{} {}({}) {}\n", ret, full_name, params, body_str))
+            if let Some(head) = self.isr_head(d)? {
+                return Ok(format!("{} {}\n", head, body_str));
+            }
+            Ok(format!("{}{} {} {}\n", template, ret, full_name, body_str))
         } else { Ok(String::new()) }
     }
 
@@ -244,52 +1086,90 @@ impl Transpiler {
         let pad = self.pad();
         Ok(match stmt {
             Stmt::VarDecl { name, ty, init, .. } => {
-                let t    = ty.as_ref().map(|t| t.to_cpp()).unwrap_or_else(|| "auto".into());
-                let init = init.as_ref().map(|e| self.emit_expr(e)).transpose()?
+                self.locals.insert(name.clone());
+                self.note_decl_type(name, ty.as_ref(), init.as_ref());
+                match ty {
+                    Some(t) => self.note_pkg_type(name, t),
+                    None    => self.note_pkg_value(name, init.as_ref()),
+                }
+                let mut init = init.as_ref().map(|e| self.emit_expr(e)).transpose()?
                     .map(|s| format!(" = {}", s)).unwrap_or_default();
-                format!("{}{} {}{};\n", pad, t, name, init)
+                let decl = match ty.as_ref().and_then(|t| self.pkg_class(t)) {
+                    Some(class) if matches!(ty, Some(Type::Ptr(_))) => {
+                        self.class_ptrs.insert(name.clone());
+                        if init.is_empty() { init = " = nullptr".into() }
+                        format!("{}* {}", class, name)
+                    }
+                    Some(class) => format!("{} {}", class, name),
+                    None => self.declarator(name, name, ty.as_ref()),
+                };
+                format!("{}{}{};\n", pad, decl, init)
             }
             Stmt::ConstDecl { name, ty, val, .. } => {
+                self.locals.insert(name.clone());
                 let t = ty.as_ref().map(|t| t.to_cpp()).unwrap_or_else(|| "auto".into());
                 format!("{}const {} {} = {};\n", pad, t, name, self.emit_expr(val)?)
             }
             Stmt::ShortDecl { names, vals, .. } => {
+                if let Some(s) = self.emit_value_err(names, vals, &pad)? {
+                    return Ok(s);
+                }
                 let mut s = String::new();
                 for (i, name) in names.iter().enumerate() {
                     let val = vals.get(i).map(|v| self.emit_expr(v))
                         .unwrap_or_else(|| Ok("0".into()))?;
-                    // Infer package type from RHS constructor call (Bug 2)
-                    // e.g. `sensor := dht.New(...)` → var_types["sensor"] = "dht"
-                    if let Some(val_node) = vals.get(i) {
-                        if let Expr::Call { func, .. } = val_node {
-                            if let Expr::Select { expr: pkg_expr, .. } = func.as_ref() {
-                                if let Expr::Ident { name: pkg_alias, .. } = pkg_expr.as_ref() {
-                                    if let Some(canon) = self.pkg_map.get(pkg_alias.as_str()).cloned() {
-                                        self.var_types.insert(name.clone(), canon);
-                                    }
-                                }
-                            }
-                        }
-                    }
-                    s += &format!("{}auto {} = {};\n", pad, name, val);
+                    self.note_pkg_value(name, vals.get(i));
+                    self.locals.insert(name.clone());
+                    self.note_decl_type(name, None, vals.get(i));
+                    s += &format!("{}{} = {};\n", pad, self.declarator(name, name, None), val);
                 }
                 s
             }
             Stmt::Assign { lhs, rhs, op, .. } => {
                 let mut s = String::new();
                 for (i, l) in lhs.iter().enumerate() {
-                    let r = rhs.get(i).map(|v| self.emit_expr(v))
+                    self.check_pgm_write(l)?;
+                    let mut new_object = false;
+                    if let (AssignOp::Plain, Expr::Ident { name, .. }) = (op, l) {
+                        if let Some(t) = rhs.get(i).and_then(|r| self.static_type(r)) {
+                            self.decl_types.insert(name.clone(), t);
+                        }
+                        // `strip = ws2812.New(…)` into a class pointer.
+                        new_object = self.class_ptrs.contains(name)
+                            && rhs.get(i).and_then(|r| self.pkg_value_owner(r)).as_ref() == self.var_types.get(name);
+                    }
+                    let mut r = rhs.get(i).map(|v| self.emit_expr(v))
                         .unwrap_or_else(|| Ok("0".into()))?;
+                    if new_object { r = format!("new {}", r) }
                     s += &format!("{}{} {} {};\n", pad, self.emit_expr(l)?, op.to_cpp(), r);
                 }
                 s
             }
-            Stmt::Inc { expr, .. } => format!("{}{}++;\n", pad, self.emit_expr(expr)?),
-            Stmt::Dec { expr, .. } => format!("{}{}--;\n", pad, self.emit_expr(expr)?),
+            Stmt::Inc { expr, .. } => {
+                self.check_pgm_write(expr)?;
+                format!("{}{}++;\n", pad, self.emit_expr(expr)?)
+            }
+            Stmt::Dec { expr, .. } => {
+                self.check_pgm_write(expr)?;
+                format!("{}{}--;\n", pad, self.emit_expr(expr)?)
+            }
             Stmt::Return { vals, .. } => {
                 match vals.len() {
-                    0 => format!("{}return;\n", pad),
-                    1 => format!("{}return {};\n", pad, self.emit_expr(&vals[0])?),
+                    0 => {
+                        let hooks: String = self.exit_hooks.iter().map(|h| format!("{}{}\n", pad, h)).collect();
+                        format!("{}{}return;\n", hooks, pad)
+                    }
+                    1 => {
+                        // `return &MyErr{…}` as an error: the value is copied into the
+                        // error type's static slot rather than pointing at a temporary.
+                        let val = match &vals[0] {
+                            Expr::Unary { op: UnOp::Addr, expr, .. }
+                                if matches!(expr.as_ref(), Expr::Composite { .. })
+                                && matches!(self.results.as_slice(), [Type::Named(n)] if n == "error") => expr,
+                            v => v,
+                        };
+                        format!("{}return {};\n", pad, self.emit_expr(val)?)
+                    }
                     _ => {
                         let vs: Vec<_> = vals.iter().map(|v| self.emit_expr(v)).collect::<Result<_>>()?;
                         format!("{}return {{ {} }}; // multi-return\n", pad, vs.join(", "))
@@ -297,21 +1177,7 @@ impl Transpiler {
                 }
             }
             Stmt::If { init, cond, then, else_, .. } => {
-                let init_s = if let Some(i) = init {
-                    let raw = self.emit_stmt(i)?;
-                    format!("{} ", raw.trim().trim_end_matches(';'))
-                } else { String::new() };
-                let cond_s = self.emit_expr(cond)?;
-                let then_s = self.emit_block(then)?;
-                let else_s = if let Some(e) = else_ {
-                    let body = match e.as_ref() {
-                        Stmt::If { .. } => self.emit_stmt(e)?.trim_start().to_string(),
-                        Stmt::Block(b)  => self.emit_block(b)?,
-                        other           => self.emit_stmt(other)?.trim().to_string(),
-                    };
-                    format!(" else {}", body)
-                } else { String::new() };
-                format!("{}if ({}{}) {}{}\n", pad, init_s, cond_s, then_s, else_s)
+                self.emit_with_init(init, |t| t.emit_if(cond, then, else_.as_deref()))?
             }
             Stmt::For { init, cond, post, body, .. } => {
                 let init_s = flat_stmt_opt(init, self)?;
@@ -322,72 +1188,27 @@ impl Transpiler {
                 format!("{}for ({}; {}; {}) {}\n", pad, init_s, cond_s, post_s, body_s)
             }
             Stmt::Range { key, val, iter, body, .. } => {
+                self.locals.extend(key.iter().chain(val).cloned());
                 let arr    = self.emit_expr(iter)?;
-                let k      = key.as_deref().unwrap_or("_i").to_owned();
-                let body_s = self.emit_block(body)?;
+                let k      = key.as_deref().filter(|k| *k != "_").unwrap_or("_i").to_owned();
+                let len    = self.len_of(iter, &arr)
+                    .unwrap_or_else(|| format!("sizeof({a})/sizeof({a}[0])", a = arr));
+                let mut body_s = self.emit_block(body)?;
                 if let Some(vname) = val {
-                    format!(
-                        "{pad}for (int32_t {k} = 0; {k} < (int32_t)(sizeof({a})/sizeof({a}[0])); {k}++) {{\n\
-                         {pad}    auto {v} = {a}[{k}];\n\
-                         {pad}}}\n",
-                        pad = pad, k = k, a = arr, v = vname,
-                    )
-                } else {
-                    format!(
-                        "{pad}for (int32_t {k} = 0; {k} < (int32_t)(sizeof({a})/sizeof({a}[0])); {k}++) {body}\n",
-                        pad = pad, k = k, a = arr, body = body_s,
-                    )
-                }
-            }
-            Stmt::Switch { tag, cases, .. } => {
-                if tag.is_none() {
-                    // Tagless switch: `switch { case cond: ... }` → if/else if/else
-                    let mut s = String::new();
-                    for (idx, case) in cases.iter().enumerate() {
-                        if case.exprs.is_empty() {
-                            // default clause
-                            s += &format!("{} else {{\n", "");
-                        } else {
-                            let conds: Vec<String> = case.exprs.iter()
-                                .map(|e| self.emit_expr(e))
-                                .collect::<Result<_>>()?;
-                            let cond_s = conds.join(" || ");
-                            if idx == 0 {
-                                s += &format!("{}if ({}) {{\n", pad, cond_s);
-                            } else {
-                                s += &format!(" else if ({}) {{\n", cond_s);
-                            }
-                        }
-                        self.push_indent();
-                        for st in &case.body { s += &self.emit_stmt(st)?; }
-                        self.pop_indent();
-                        s += &format!("{}}}", pad);
-                    }
-                    s += "\n";
-                    s
-                } else {
-                    // Tagged switch: `switch expr { case val: ... }`
-                    let tag_s = self.emit_expr(tag.as_ref().unwrap())?;
-                    let mut s = format!("{}switch ({}) {{\n", pad, tag_s);
-                    self.push_indent();
-                    for case in cases {
-                        let cpad = self.pad();
-                        if case.exprs.is_empty() {
-                            s += &format!("{}default:\n", cpad);
-                        } else {
-                            for e in &case.exprs {
-                                s += &format!("{}case {}:\n", cpad, self.emit_expr(e)?);
-                            }
-                        }
-                        self.push_indent();
-                        for st in &case.body { s += &self.emit_stmt(st)?; }
-                        s += &format!("{}break;\n", self.pad());
-                        self.pop_indent();
-                    }
-                    self.pop_indent();
-                    s += &format!("{}}}\n", pad);
-                    s
+                    let elem = self.pgm_index(iter, &k).unwrap_or_else(|| format!("{}[{}]", arr, k));
+                    body_s = body_s.replacen("{\n",
+                        &format!("{{\n{}    auto {} = {};\n", pad, vname, elem), 1);
                 }
+                format!(
+                    "{pad}for (int32_t {k} = 0; {k} < (int32_t)({len}); {k}++) {body}\n",
+                    pad = pad, k = k, len = len, body = body_s,
+                )
+            }
+            Stmt::Switch { init, tag, cases, .. } => {
+                self.emit_with_init(init, |t| t.emit_switch(tag.as_ref(), cases))?
+            }
+            Stmt::TypeSwitch { init, bind, expr, cases, .. } => {
+                self.emit_with_init(init, |t| t.emit_type_switch(bind.as_deref(), expr, cases))?
             }
             Stmt::Block(b) => {
                 let s = self.emit_block(b)?;
@@ -398,12 +1219,18 @@ impl Transpiler {
             Stmt::Continue { .. }        => format!("{}continue;\n", pad),
             Stmt::Goto     { label, .. } => format!("{}goto {};\n",  pad, label),
             Stmt::Label    { name, .. }  => format!("{}{}:\n",       pad, name),
+            Stmt::Fallthrough { .. } => {
+                return Err(tsukiError::codegen("fallthrough statement out of place"));
+            }
             Stmt::Defer { call, .. } => {
                 let ann = if self.cfg.annotate_unsupported {
                     "/* defer — RAII wrapper not yet emitted */"
                 } else { "" };
                 format!("{}{} {};\n", pad, ann, self.emit_expr(call)?)
             }
+            Stmt::Go { call, span } if self.board.as_ref().is_some_and(|b| b.arch() == "esp32") => {
+                format!("{}{};\n", pad, self.emit_task(call, span)?)
+            }
             Stmt::Go { call, .. } => {
                 let ann = if self.cfg.annotate_unsupported {
                     "/* goroutine — not supported on bare metal */"
@@ -413,27 +1240,479 @@ impl Transpiler {
         })
     }
 
-    fn emit_expr(&self, expr: &Expr) -> Result<String> {
-        Ok(match expr {
-            Expr::Int(n)   => n.to_string(),
-            Expr::Float(f) => {
-                let s = format!("{}", f);
-                if s.contains('.') { s } else { format!("{}.0", s) }
+    /// `go f()` on ESP32: a FreeRTOS task that runs `f` once and deletes
+    /// itself. The task entry is a plain function pointer, so nothing can be
+    /// captured; the call must name a function and take no arguments.
+    fn emit_task(&mut self, call: &Expr, span: &Span) -> Result<String> {
+        let Expr::Call { func, args, .. } = call else {
+            return Err(tsukiError::type_(span.clone(), "expression in go must be a function call"));
+        };
+        match func.as_ref() {
+            Expr::Ident { name, .. } if args.is_empty() => Ok(format!(
+                "xTaskCreate([](void*) {{ {}(); vTaskDelete(nullptr); }}, \"{}\", {}, nullptr, 1, nullptr)",
+                self.emit_expr(func)?, name, TASK_STACK)),
+            _ => Err(tsukiError::type_(span.clone(), "go on ESP32 starts a FreeRTOS task, which cannot take arguments")
+                .with_suggestion(Suggestion::note("move the work into a function with no parameters and `go` that; share data through globals or a freertos.Queue"))),
+        }
+    }
+
+    /// Emit `inner` inside a C++ block that first runs the statement's init
+    /// clause, so its variables get the same scope as in Go.
+    fn emit_with_init(
+        &mut self,
+        init:  &Option<Box<Stmt>>,
+        inner: impl FnOnce(&mut Self) -> Result<String>,
+    ) -> Result<String> {
+        let Some(init) = init else { return inner(self) };
+        let pad = self.pad();
+        let outer = self.decl_types.clone();
+        self.push_indent();
+        let body = self.emit_stmt(init).and_then(|i| Ok(i + inner(self)?.as_str()));
+        self.pop_indent();
+        self.decl_types = outer;
+        Ok(format!("{pad}{{\n{}{pad}}}\n", body?, pad = pad))
+    }
+
+    fn emit_if(&mut self, cond: &Expr, then: &Block, else_: Option<&Stmt>) -> Result<String> {
+        let pad    = self.pad();
+        let cond_s = self.emit_expr(cond)?;
+        let then_s = self.emit_block(then)?;
+        let else_s = if let Some(e) = else_ {
+            let body = match e {
+                Stmt::If { .. } => self.emit_stmt(e)?.trim().to_string(),
+                Stmt::Block(b)  => self.emit_block(b)?,
+                other           => self.emit_stmt(other)?.trim().to_string(),
+            };
+            format!(" else {}", body)
+        } else { String::new() };
+        Ok(format!("{}if ({}) {}{}\n", pad, cond_s, then_s, else_s))
+    }
+
+    fn emit_switch(&mut self, tag: Option<&Expr>, cases: &[SwitchCase]) -> Result<String> {
+        let pad = self.pad();
+        let Some(tag) = tag else {
+            if cases.iter().any(|c| c.falls_through()) {
+                return self.emit_dispatch_switch(cases);
             }
-            Expr::Str(s)   => {
-                // Escape every byte so the C++ string literal is always valid.
-                // Go strings may contain real newlines, tabs, or non-ASCII bytes
-                // (e.g. em-dash as UTF-8) that would break avr-gcc otherwise.
-                let mut escaped = String::new();
-                for byte in s.bytes() {
-                    match byte {
-                        b'\\' => escaped.push_str("\\\\"),
-                        b'"'  => escaped.push_str("\\\""),
-                        b'\n' => escaped.push_str("\\n"),
-                        b'\r' => escaped.push_str("\\r"),
-                        b'\t' => escaped.push_str("\\t"),
-                        0x20..=0x7E => escaped.push(byte as char), // printable ASCII
-                        other => { let _ = write!(escaped, "\\x{:02X}", other); }
+            // Tagless switch: `switch { case cond: ... }` → if/else if/else.
+            // Without fallthrough, a default clause only runs when nothing
+            // else matched, so it can always go last.
+            let mut s = String::new();
+            let mut first = true;
+            for case in cases.iter().filter(|c| !c.exprs.is_empty()) {
+                let conds: Vec<String> = case.exprs.iter()
+                    .map(|e| self.emit_expr(e))
+                    .collect::<Result<_>>()?;
+                let cond_s = conds.join(" || ");
+                if first {
+                    s += &format!("{}if ({}) {{\n", pad, cond_s);
+                } else {
+                    s += &format!(" else if ({}) {{\n", cond_s);
+                }
+                first = false;
+                s += &self.emit_case_body(&case.body)?;
+                s += &format!("{}}}", pad);
+            }
+            if let Some(def) = cases.iter().find(|c| c.exprs.is_empty()) {
+                s += &if first { format!("{}{{\n", pad) } else { " else {\n".into() };
+                s += &self.emit_case_body(&def.body)?;
+                s += &format!("{}}}", pad);
+            }
+            if !s.is_empty() { s += "\n"; }
+            return Ok(s);
+        };
+
+        // Tagged switch: `switch expr { case val: ... }`
+        let tag_s = self.emit_expr(tag)?;
+        let mut s = format!("{}switch ({}) {{\n", pad, tag_s);
+        self.push_indent();
+        for case in cases {
+            let cpad = self.pad();
+            if case.exprs.is_empty() {
+                s += &format!("{}default:\n", cpad);
+            } else {
+                for e in &case.exprs {
+                    s += &format!("{}case {}:\n", cpad, self.emit_expr(e)?);
+                }
+            }
+            s += &self.emit_case_body(&case.body)?;
+            s += &self.case_terminator(case);
+        }
+        self.pop_indent();
+        s += &format!("{}}}\n", pad);
+        Ok(s)
+    }
+
+    /// A tagless switch with `fallthrough` can't be an if/else chain, so the
+    /// first matching clause is picked by index and dispatched through a C++
+    /// `switch`, which falls through natively.
+    fn emit_dispatch_switch(&mut self, cases: &[SwitchCase]) -> Result<String> {
+        let pad = self.pad();
+        let mut sel = String::new();
+        for (idx, case) in cases.iter().enumerate().filter(|(_, c)| !c.exprs.is_empty()) {
+            let conds: Vec<String> = case.exprs.iter()
+                .map(|e| self.emit_expr(e))
+                .collect::<Result<_>>()?;
+            let _ = write!(sel, "({}) ? {} : ", conds.join(" || "), idx);
+        }
+        let mut s = format!("{}switch ({}-1) {{\n", pad, sel);
+        self.push_indent();
+        for (idx, case) in cases.iter().enumerate() {
+            let cpad = self.pad();
+            if case.exprs.is_empty() {
+                s += &format!("{}default:\n", cpad);
+            } else {
+                s += &format!("{}case {}:\n", cpad, idx);
+            }
+            s += &self.emit_case_body(&case.body)?;
+            s += &self.case_terminator(case);
+        }
+        self.pop_indent();
+        s += &format!("{}}}\n", pad);
+        Ok(s)
+    }
+
+    /// Emit a clause body one level deeper, dropping a trailing `fallthrough`.
+    fn emit_case_body(&mut self, body: &[Stmt]) -> Result<String> {
+        let body = match body.last() {
+            Some(Stmt::Fallthrough { .. }) => &body[..body.len() - 1],
+            _ => body,
+        };
+        self.push_indent();
+        let mut s = String::new();
+        for st in body {
+            match self.emit_stmt(st) {
+                Ok(st) => s += &st,
+                Err(e) => { self.pop_indent(); return Err(e); }
+            }
+        }
+        self.pop_indent();
+        Ok(s)
+    }
+
+    /// Go clauses break implicitly; C++ ones fall through unless told not to.
+    fn case_terminator(&mut self, case: &SwitchCase) -> String {
+        self.push_indent();
+        let s = if case.falls_through() {
+            format!("{}// fallthrough\n", self.pad())
+        } else {
+            format!("{}break;\n", self.pad())
+        };
+        self.pop_indent();
+        s
+    }
+
+    /// Type switches are resolved at transpile time: the target has no RTTI,
+    /// so the operand's static type must be known and only the matching
+    /// clause is emitted.
+    fn emit_type_switch(&mut self, bind: Option<&str>, expr: &Expr, cases: &[TypeCase]) -> Result<String> {
+        let pad = self.pad();
+        let ty = self.static_type(expr).ok_or_else(|| tsukiError::codegen(format!(
+            "type switch on `{}` needs an operand whose concrete type is known at compile time",
+            self.emit_expr(expr).unwrap_or_default(),
+        )))?;
+        if is_iface(&ty) {
+            return Err(tsukiError::codegen(
+                "type switch on an interface value of unknown concrete type (no RTTI on target)"));
+        }
+        let chosen = cases.iter()
+            .find(|c| c.types.iter().any(|t| same_type(t, &ty)))
+            .or_else(|| cases.iter().find(|c| c.types.is_empty()));
+        let Some(case) = chosen else { return Ok(String::new()) };
+
+        let outer = self.decl_types.clone();
+        let mut s = format!("{}{{ // type switch: {}\n", pad, ty.to_cpp());
+        if let Some(v) = bind {
+            self.locals.insert(v.to_owned());
+            self.decl_types.insert(v.to_owned(), ty.clone());
+            s += &format!("{}    auto {} = {};\n", pad, v, self.emit_expr(expr)?);
+        }
+        let body = self.emit_case_body(&case.body);
+        self.decl_types = outer;
+        s += &body?;
+        s += &format!("{}}}\n", pad);
+        Ok(s)
+    }
+
+    /// Record the Go type of a declared variable when it can be told statically.
+    /// For interface variables the initialiser's concrete type wins, since that
+    /// is what a later type switch has to dispatch on.
+    fn note_decl_type(&mut self, name: &str, ty: Option<&Type>, init: Option<&Expr>) {
+        let init_ty = init.and_then(|e| self.static_type(e));
+        let ty = match ty {
+            Some(t) if is_iface(t) && init_ty.is_some() => init_ty,
+            Some(t) => Some(t.clone()),
+            None    => init_ty,
+        };
+        match ty {
+            Some(t) => { self.decl_types.insert(name.to_owned(), t); }
+            None    => { self.decl_types.remove(name); }
+        }
+    }
+
+    /// C++ type for a declaration; interface variables with a known concrete
+    /// value are declared as that type, as there is no boxing on the target.
+    fn decl_cpp_type(&self, name: &str, ty: Option<&Type>) -> String {
+        match (ty, self.decl_types.get(name)) {
+            (Some(t), Some(concrete)) if is_iface(t) && !is_iface(concrete) => concrete.to_cpp(),
+            (Some(t), _) => t.to_cpp(),
+            (None, _)    => "auto".into(),
+        }
+    }
+
+    /// `T x` for a declaration, or `T x[N]` when `name` holds a fixed-size
+    /// array, declared or inferred from an array literal.
+    fn declarator(&self, name: &str, cpp_name: &str, ty: Option<&Type>) -> String {
+        match self.decl_types.get(name) {
+            Some(t @ Type::Array { len: Some(_), .. }) => array_declarator(t, cpp_name),
+            _ => format!("{} {}", self.decl_cpp_type(name, ty), cpp_name),
+        }
+    }
+
+    fn static_type(&self, expr: &Expr) -> Option<Type> {
+        match expr {
+            Expr::Int(_)   => Some(Type::Int),
+            Expr::Float(_) => Some(Type::Float64),
+            Expr::Str(_)   => Some(Type::String),
+            Expr::Rune(_)  => Some(Type::Rune),
+            Expr::Bool(_)  => Some(Type::Bool),
+            Expr::Ident { name, .. } => self.decl_types.get(name).cloned(),
+            Expr::Composite  { ty, .. } | Expr::TypeAssert { ty, .. } => Some(ty.clone()),
+            Expr::Call { func, .. } => match func.as_ref() {
+                Expr::Select { expr, field, .. } => {
+                    let canon = self.pkg_map.get(self.pkg_alias(expr)?)?;
+                    self.rt.pkg(canon)?.returns.get(field).cloned()
+                }
+                _ => None,
+            },
+            Expr::Unary { op: UnOp::Addr, expr, .. } =>
+                self.static_type(expr).map(|t| Type::Ptr(Box::new(t))),
+            Expr::Unary { op: UnOp::Deref, expr, .. } => match self.static_type(expr)? {
+                Type::Ptr(inner) => Some(*inner),
+                _ => None,
+            },
+            Expr::Select { expr, field, .. } => {
+                let owner = match self.static_type(expr)? {
+                    Type::Ptr(inner) => *inner,
+                    t => t,
+                };
+                self.field_type(owner.named()?, field)
+            }
+            _ => None,
+        }
+    }
+
+    /// The identifier in `x.Sel` when `x` may name a package rather than a
+    /// variable known to this function or file.
+    fn pkg_alias<'a>(&self, expr: &'a Expr) -> Option<&'a String> {
+        match expr {
+            Expr::Ident { name, .. }
+                if !self.locals.contains(name) && !self.decl_types.contains_key(name) => Some(name),
+            _ => None,
+        }
+    }
+
+    /// Type of `field` on struct `owner`, looking through embedded structs.
+    fn field_type(&self, owner: &str, field: &str) -> Option<Type> {
+        let fields = self.structs.get(owner)?;
+        if let Some(f) = fields.iter().find(|f| f.go_name() == field) {
+            return Some(f.ty.clone());
+        }
+        fields.iter().filter(|f| f.is_embedded())
+            .find_map(|f| self.field_type(f.ty.named()?, field))
+    }
+
+    /// The struct type `x` embeds under `field`, for `x.Base` selectors.
+    fn embedded_in(&self, expr: &Expr, field: &str) -> Option<Type> {
+        let owner = match self.static_type(expr)? {
+            Type::Ptr(inner) => *inner,
+            t => t,
+        };
+        self.structs.get(owner.named()?)?.iter()
+            .find(|f| f.is_embedded() && f.go_name() == field)
+            .map(|f| f.ty.clone())
+    }
+
+    /// A call passing fewer arguments than its template refers to would
+    /// leave `{N}` in the C++; with declared `params` the count must match
+    /// exactly. `receiver` is 1 for method calls, whose receiver fills `{0}`.
+    fn check_arity(&self, pkg: &PkgMap, given: usize, receiver: usize,
+                   canon: &str, func: &str, span: &Span) -> Result<()> {
+        let want = match (pkg.params.get(func), pkg.functions.get(func)) {
+            (Some(params), _) if given != params.len() => params.len(),
+            (None, Some(fmap)) if given + receiver < fmap.arity() => fmap.arity() - receiver,
+            _ => return Ok(()),
+        };
+        let kind = if receiver > 0 { "method" } else { "function" };
+        Err(tsukiError::type_(span.clone(), format!(
+            "package {} {} {} expects {} argument{}, got {}",
+            canon, kind, func, want, if want == 1 { "" } else { "s" }, given)))
+    }
+
+    /// Check arguments against the Go types a package declares for `func`.
+    /// Untyped constants only need to fit the parameter's kind; a numeric
+    /// argument of another numeric type is cast, as Go would make the
+    /// caller write it. Arguments of unknown or non-basic types pass.
+    fn typed_args(&self, pkg: &PkgMap, args: &[Expr], mut arg_strs: Vec<String>,
+                  canon: &str, func: &str, span: &Span) -> Result<Vec<String>> {
+        let Some(params) = pkg.params.get(func) else { return Ok(arg_strs) };
+        for (i, (arg, param)) in args.iter().zip(params).enumerate() {
+            if !is_basic(param) { continue }
+            let fits = match arg {
+                Expr::Int(_) | Expr::Rune(_) => Some(is_numeric(param)),
+                Expr::Float(_) => Some(matches!(param, Type::Float32 | Type::Float64)),
+                Expr::Str(_)   => Some(*param == Type::String),
+                Expr::Bool(_)  => Some(*param == Type::Bool),
+                _ => match self.static_type(arg) {
+                    Some(t) if same_type(&t, param) => None,
+                    Some(t) if is_numeric(&t) && is_numeric(param) => {
+                        arg_strs[i] = format!("static_cast<{}>({})", param.to_cpp(), arg_strs[i]);
+                        None
+                    }
+                    Some(t) if is_basic(&t) && is_basic(param) => Some(false),
+                    _ => None,
+                },
+            };
+            if fits == Some(false) {
+                let got = match arg {
+                    Expr::Int(_)   => "untyped int constant",
+                    Expr::Float(_) => "untyped float constant",
+                    Expr::Str(_)   => "untyped string constant",
+                    Expr::Rune(_)  => "untyped rune constant",
+                    Expr::Bool(_)  => "untyped bool constant",
+                    _ => self.static_type(arg).map_or("value", |t| basic_name(&t)),
+                };
+                return Err(tsukiError::type_(span.clone(), format!(
+                    "cannot use {} ({}) as {} in argument {} to {}.{}",
+                    arg_strs[i], got, basic_name(param), i + 1, canon, func)));
+            }
+        }
+        Ok(arg_strs)
+    }
+
+    /// Expand a package mapping for `pkg.func`, naming the call if its
+    /// script fails.
+    fn expand_map(&self, fmap: &FnMap, args: &[String], types: Vec<Option<Type>>,
+                  pkg: &str, func: &str) -> Result<String> {
+        let out = fmap.expand(args, &types).map_err(|e| tsukiError::codegen(
+            format!("mapping for {}.{}: {}", pkg, func, e)))?;
+        if !out.contains("{err}") { return Ok(out) }
+        let dest = self.err_dest.borrow_mut().take().unwrap_or_else(|| "nullptr".into());
+        Ok(out.replace("{err}", &dest))
+    }
+
+    /// `v, err := pkg.F()` where F's mapping reports errors through `{err}`:
+    /// the error is declared first and the call writes it. None when the call
+    /// has no `{err}` slot, leaving the generic (unsupported) tuple form.
+    fn emit_value_err(&mut self, names: &[String], vals: &[Expr], pad: &str) -> Result<Option<String>> {
+        let ([name, err], [val]) = (names, vals) else { return Ok(None) };
+        let dest = match err.as_str() { "_" => "nullptr".to_owned(), e => format!("&{}", e) };
+        *self.err_dest.borrow_mut() = Some(dest);
+        let call = self.emit_expr(val)?;
+        if self.err_dest.borrow_mut().take().is_some() { return Ok(None) }
+
+        // Go lets `:=` reuse one of the names when the other is new.
+        let mut s = String::new();
+        if err != "_" && self.locals.insert(err.clone()) {
+            self.decl_types.insert(err.clone(), Type::Named("error".into()));
+            s += &format!("{}tsuki_error {};\n", pad, err);
+        }
+        self.note_pkg_value(name, Some(val));
+        if self.locals.insert(name.clone()) {
+            self.note_decl_type(name, None, Some(val));
+            s += &format!("{}{} = {};\n", pad, self.declarator(name, name, None), call);
+        } else {
+            s += &format!("{}{} = {};\n", pad, name, call);
+        }
+        Ok(Some(s))
+    }
+
+    /// Infer the package of a variable initialized from a package call, for
+    /// instance-method dispatch: `sensor := dht.New(...)` makes `sensor` a
+    /// `dht` value. A result typed `otherpkg.T` takes its methods from
+    /// `otherpkg` (`t := rtc.Now()` is a time.Time).
+    fn note_pkg_value(&mut self, name: &str, val: Option<&Expr>) {
+        let Some(owner) = val.and_then(|v| self.pkg_value_owner(v)) else { return };
+        self.class_ptrs.remove(name);
+        self.var_types.insert(name.to_owned(), owner);
+    }
+
+    /// The package whose methods apply to the result of `val`, a package call.
+    fn pkg_value_owner(&self, val: &Expr) -> Option<String> {
+        let Expr::Call { func, .. } = val else { return None };
+        let Expr::Select { expr: pkg_expr, field, .. } = func.as_ref() else { return None };
+        let Expr::Ident { name: pkg_alias, .. } = pkg_expr.as_ref() else { return None };
+        let canon = self.pkg_map.get(pkg_alias.as_str())?.clone();
+        Some(match self.rt.pkg(&canon).and_then(|p| p.returns.get(field)) {
+            Some(Type::Named(t)) if t.contains('.') => t.split('.').next().unwrap_or_default().to_owned(),
+            _ => canon,
+        })
+    }
+
+    /// Same as `note_pkg_value`, for a variable declared `p.T` or `*p.T`.
+    fn note_pkg_type(&mut self, name: &str, ty: &Type) {
+        let Type::Named(n) = (match ty { Type::Ptr(t) => t.as_ref(), t => t }) else { return };
+        let Some(canon) = n.split_once('.').and_then(|(p, _)| self.pkg_map.get(p)).cloned() else { return };
+        self.class_ptrs.remove(name);
+        self.var_types.insert(name.to_owned(), canon);
+    }
+
+    /// Parameter list of a declared function; `p.T` parameters are of the
+    /// package's `cpp_class`.
+    fn param_list(&self, sig: &FuncSig) -> String {
+        params_with(sig, |ty| match (self.pkg_class(ty), ty) {
+            (Some(class), Type::Ptr(_)) => format!("{}*", class),
+            (Some(class), _)            => class,
+            (None, _)                   => ty.to_cpp(),
+        })
+    }
+
+    /// The `cpp_class` of the package of a `p.T` or `*p.T` type.
+    fn pkg_class(&self, ty: &Type) -> Option<String> {
+        let Type::Named(n) = (match ty { Type::Ptr(t) => t.as_ref(), t => t }) else { return None };
+        let canon = self.pkg_map.get(n.split_once('.')?.0)?;
+        self.rt.pkg(canon)?.cpp_class.clone()
+    }
+
+    /// Element count for `len(x)` and `range x` when `x` is not a C array.
+    fn len_of(&self, expr: &Expr, emitted: &str) -> Option<String> {
+        match self.static_type(expr)? {
+            Type::String                      => Some(format!("{}.length()", emitted)),
+            Type::Named(n) if n == STR_SLICE  => Some(format!("{}.n", emitted)),
+            Type::Array { len: Some(n), .. }  => Some(n.to_string()),
+            _ => None,
+        }
+    }
+
+    /// `->` for operands statically known to be pointers (Go auto-dereferences).
+    fn member_op(&self, expr: &Expr) -> &'static str {
+        match self.static_type(expr) {
+            Some(Type::Ptr(_)) => "->",
+            _                  => ".",
+        }
+    }
+
+    fn emit_expr(&self, expr: &Expr) -> Result<String> {
+        Ok(match expr {
+            Expr::Int(n)   => n.to_string(),
+            Expr::Float(f) => {
+                let s = format!("{}", f);
+                if s.contains('.') { s } else { format!("{}.0", s) }
+            }
+            Expr::Str(s)   => {
+                // Escape every byte so the C++ string literal is always valid.
+                // Go strings may contain real newlines, tabs, or non-ASCII bytes
+                // (e.g. em-dash as UTF-8) that would break avr-gcc otherwise.
+                let mut escaped = String::new();
+                for byte in s.bytes() {
+                    match byte {
+                        b'\\' => escaped.push_str("\\\\"),
+                        b'"'  => escaped.push_str("\\\""),
+                        b'\n' => escaped.push_str("\\n"),
+                        b'\r' => escaped.push_str("\\r"),
+                        b'\t' => escaped.push_str("\\t"),
+                        0x20..=0x7E => escaped.push(byte as char), // printable ASCII
+                        other => { let _ = write!(escaped, "\\x{:02X}", other); }
                     }
                 }
                 if self.cfg.arduino_string {
@@ -447,6 +1726,8 @@ impl Transpiler {
             Expr::Nil      => "nullptr".into(),
             Expr::Raw(s)   => s.clone(),
             Expr::Ident { name, .. } => self.resolve_ident(name),
+            Expr::Inst  { name, args, .. } => format!("{}<{}>", self.resolve_ident(name),
+                args.iter().map(Type::to_cpp).collect::<Vec<_>>().join(", ")),
             Expr::Binary { op, lhs, rhs, .. } => {
                 format!("({} {} {})", self.emit_expr(lhs)?, op.to_cpp(), self.emit_expr(rhs)?)
             }
@@ -455,20 +1736,32 @@ impl Transpiler {
             }
             Expr::Call { func, args, .. } => self.emit_call(func, args)?,
             Expr::Index { expr, idx, .. } => {
-                format!("{}[{}]", self.emit_expr(expr)?, self.emit_expr(idx)?)
+                let i = self.emit_expr(idx)?;
+                match self.pgm_index(expr, &i) {
+                    Some(read) => read,
+                    None       => format!("{}[{}]", self.emit_expr(expr)?, i),
+                }
             }
             Expr::Slice { expr, lo, hi, .. } => {
                 let a  = self.emit_expr(expr)?;
                 let lo = lo.as_ref().map(|e| self.emit_expr(e)).transpose()?
                     .unwrap_or_else(|| "0".into());
-                let hi = hi.as_ref().map(|e| self.emit_expr(e)).transpose()?
-                    .unwrap_or_else(|| format!("sizeof({})/sizeof({}[0])", a, a));
-                format!("/* &{}[{}..{}] */", a, lo, hi)
+                if self.static_type(expr) == Some(Type::String) {
+                    return Ok(match hi {
+                        Some(h) => format!("{}.substring({}, {})", a, lo, self.emit_expr(h)?),
+                        None    => format!("{}.substring({})", a, lo),
+                    });
+                }
+                // Slices are plain pointers; the upper bound is not kept.
+                format!("(&{}[{}])", a, lo)
             }
             Expr::Select { expr, field, .. } => {
-                if let Expr::Ident { name: alias, .. } = expr.as_ref() {
+                if let Some(alias) = self.pkg_alias(expr) {
                     let canon = self.pkg_map.get(alias.as_str())
                         .cloned().unwrap_or_else(|| alias.clone());
+                    if self.is_local(&canon) {
+                        return Ok(mangle(&canon, field));
+                    }
                     if let Some(pkg) = self.rt.pkg(&canon) {
                         if let Some(const_val) = pkg.constants.get(field.as_str()) {
                             return Ok(const_val.clone());
@@ -479,9 +1772,32 @@ impl Transpiler {
                     }
                     return Err(tsukiError::codegen(format!("no mapping for {}.{}", canon, field)));
                 }
-                format!("{}.{}", self.emit_expr(expr)?, field)
+                if let Some(base) = self.embedded_in(expr, field) {
+                    // `d.Base` names the base-class subobject
+                    let obj = self.emit_expr(expr)?;
+                    return Ok(match self.member_op(expr) {
+                        "->" => format!("(*static_cast<{}*>({}))", base.to_cpp(), obj),
+                        _    => format!("static_cast<{}&>({})", base.to_cpp(), obj),
+                    });
+                }
+                format!("{}{}{}", self.emit_expr(expr)?, self.member_op(expr), field)
             }
             Expr::TypeAssert { expr, .. } => self.emit_expr(expr)?,
+            Expr::Composite { ty, elems, .. } if ty.named().is_some_and(|n| self.structs.contains_key(n)) => {
+                // Keyed literals are laid out in declaration order; C++11 has
+                // no designated initialisers.
+                let fields = &self.structs[ty.named().unwrap_or_default()];
+                let vals: Vec<String> = if elems.iter().any(|e| e.key.is_some()) {
+                    fields.iter().map(|f| {
+                        let val = elems.iter().find(|e| matches!(&e.key,
+                            Some(Expr::Ident { name, .. }) if name == f.go_name()));
+                        val.map(|e| self.emit_expr(&e.val)).unwrap_or_else(|| Ok("{}".into()))
+                    }).collect::<Result<_>>()?
+                } else {
+                    elems.iter().map(|e| self.emit_expr(&e.val)).collect::<Result<_>>()?
+                };
+                format!("{}{{{}}}", ty.to_cpp(), vals.join(", "))
+            }
             Expr::Composite { elems, .. } => {
                 let vals: Vec<_> = elems.iter()
                     .map(|e| self.emit_expr(&e.val))
@@ -496,10 +1812,10 @@ impl Transpiler {
     }
 
     fn emit_call(&self, func: &Expr, args: &[Expr]) -> Result<String> {
-        // Detect printf-style calls (fmt.Printf / fmt.Fprintf / fmt.Sprintf) so we
+        // Detect printf-style calls (fmt.Printf / fmt.Fprintf / fmt.Sprintf, t.Logf…) so we
         // can emit the format string as a raw C-string literal instead of String("...").
         let is_printf_style = matches!(func,
-            Expr::Select { field, .. } if matches!(field.as_str(), "Printf" | "Fprintf" | "Sprintf" | "Errorf")
+            Expr::Select { field, .. } if matches!(field.as_str(), "Printf" | "Fprintf" | "Sprintf" | "Errorf" | "Logf" | "Fatalf" | "Skipf")
         );
 
         let arg_strs: Vec<String> = args.iter().enumerate()
@@ -514,13 +1830,32 @@ impl Transpiler {
             .collect::<Result<_>>()?;
 
         match func {
-            Expr::Select { expr, field, .. } => {
-                if let Expr::Ident { name: alias, .. } = expr.as_ref() {
+            Expr::Select { expr, field, span } => {
+                if let Some(alias) = self.pkg_alias(expr) {
                     // ── Case 1: static package call  e.g. dht.New(pin, type) ──────────
                     if let Some(canon) = self.pkg_map.get(alias.as_str()).cloned() {
+                        if self.is_local(&canon) {
+                            return Ok(format!("{}({})", mangle(&canon, field), arg_strs.join(", ")));
+                        }
+                        if canon == "eeprom" {
+                            self.check_eeprom_addr(field, args, span);
+                        }
+                        if canon == "arduino" {
+                            self.check_pin_args(field, args, span);
+                        }
+                        if canon == "fmt" && field == "Errorf" {
+                            if let Some(wrap) = self.emit_errorf_wrap(args, &arg_strs, span)? {
+                                return Ok(wrap);
+                            }
+                        }
                         if let Some(pkg) = self.rt.pkg(&canon) {
                             if let Some(fmap) = pkg.functions.get(field.as_str()) {
-                                return Ok(fmap.apply(&arg_strs));
+                                self.check_fn_arch(pkg, &canon, field, span)?;
+                                self.check_arity(pkg, args.len(), 0, &canon, field, span)?;
+                                let types = args.iter().map(|a| self.static_type(a)).collect();
+                                let arg_strs = self.flash_strings(fmap, args, &arg_strs)?;
+                                let arg_strs = self.typed_args(pkg, args, arg_strs, &canon, field, span)?;
+                                return self.expand_map(fmap, &arg_strs, types, &canon, field);
                             }
                         }
                         if self.cfg.passthrough_unknown {
@@ -529,15 +1864,32 @@ impl Transpiler {
                         return Err(tsukiError::codegen(
                             format!("no mapping for {}.{}", canon, field)));
                     }
-
+                }
+                if let Expr::Ident { name: alias, .. } = expr.as_ref() {
                     // ── Case 2: instance method call  e.g. sensor.Begin() ────────────
                     // Look up the variable's declared package, prepend receiver as {0}.
                     if let Some(pkg_name) = self.var_types.get(alias.as_str()).cloned() {
+                        if pkg_name == "sd" {
+                            self.check_sd_strings(field, args, span);
+                        }
+                        if pkg_name == "time" && field == "Format" {
+                            return Ok(format!("tsuki_time_Format({}, \"{}\")", alias, self.time_layout(args, span)?));
+                        }
                         if let Some(pkg) = self.rt.pkg(&pkg_name) {
                             if let Some(fmap) = pkg.functions.get(field.as_str()) {
-                                let mut all_args = vec![alias.clone()];
-                                all_args.extend_from_slice(&arg_strs);
-                                return Ok(fmap.apply(&all_args));
+                                self.check_fn_arch(pkg, &pkg_name, field, span)?;
+                                self.check_arity(pkg, args.len(), 1, &pkg_name, field, span)?;
+                                let recv = match self.class_ptrs.contains(alias) {
+                                    true  => format!("(*{})", alias),
+                                    false => alias.clone(),
+                                };
+                                let mut all_args = vec![recv];
+                                all_args.extend(self.typed_args(pkg, args, arg_strs, &pkg_name, field, span)?);
+                                let recv = pkg.cpp_class.clone().unwrap_or_else(|| pkg_name.clone());
+                                let types = std::iter::once(Some(Type::Named(recv)))
+                                    .chain(args.iter().map(|a| self.static_type(a)))
+                                    .collect();
+                                return self.expand_map(fmap, &all_args, types, &pkg_name, field);
                             }
                         }
                         if self.cfg.passthrough_unknown {
@@ -554,7 +1906,11 @@ impl Transpiler {
                             let sub_canon = sub_obj.to_lowercase();
                             if let Some(sub_pkg) = self.rt.pkg(&sub_canon) {
                                 if let Some(fmap) = sub_pkg.functions.get(field.as_str()) {
-                                    return Ok(fmap.apply(&arg_strs));
+                                    self.check_fn_arch(sub_pkg, &sub_canon, field, span)?;
+                                    self.check_arity(sub_pkg, args.len(), 0, &sub_canon, field, span)?;
+                                    let types = args.iter().map(|a| self.static_type(a)).collect();
+                                    let arg_strs = self.typed_args(sub_pkg, args, arg_strs, &sub_canon, field, span)?;
+                                    return self.expand_map(fmap, &arg_strs, types, &sub_canon, field);
                                 }
                             }
                             if self.cfg.passthrough_unknown {
@@ -565,12 +1921,18 @@ impl Transpiler {
                         }
                     }
                 }
+                if let Expr::Ident { name, span } = expr.as_ref() {
+                    self.check_receiver(name, field, span)?;
+                }
                 let obj = self.emit_expr(expr)?;
-                Ok(format!("{}.{}({})", obj, field, arg_strs.join(", ")))
+                Ok(format!("{}{}{}({})", obj, self.member_op(expr), field, arg_strs.join(", ")))
             }
             Expr::Ident { name, .. } => {
+                if let (true, [arg], [s]) = (name == "len", args, arg_strs.as_slice()) {
+                    if let Some(n) = self.len_of(arg, s) { return Ok(n); }
+                }
                 if let Some(bm) = self.rt.builtin(name) {
-                    return Ok(bm.apply(&arg_strs));
+                    return Ok(bm.apply(&self.flash_strings(bm, args, &arg_strs)?));
                 }
                 Ok(format!("{}({})", self.resolve_ident(name), arg_strs.join(", ")))
             }
@@ -578,8 +1940,76 @@ impl Transpiler {
         }
     }
 
+    /// `X.F()` where `X` is not declared and not an import: a package used
+    /// without its import, or a misspelt one, when that package maps `F`.
+    /// Anything else is left to `passthrough_unknown` (raw C++ globals).
+    fn check_receiver(&self, name: &str, func: &str, span: &Span) -> Result<()> {
+        if self.locals.contains(name) || self.globals.contains(name) { return Ok(()) }
+        let maps = |canon: &str| self.rt.pkg(canon).is_some_and(|p| p.functions.contains_key(func));
+        if maps(name) {
+            return Err(tsukiError::type_(span.clone(), format!("package `{}` is not imported", name))
+                .with_suggestion(Suggestion::note(format!("add `import \"{}\"`", name))));
+        }
+        let imported = self.pkg_map.iter().filter(|(_, canon)| maps(canon)).map(|(alias, _)| (false, alias.as_str()));
+        let builtin  = self.rt.list_packages().into_iter().filter(|p| maps(p)).map(|p| (true, p));
+        let lower    = name.to_lowercase();
+        let upper    = |s: &str| s.starts_with(|ch: char| ch.is_uppercase());
+        let nearest  = imported.chain(builtin)
+            .map(|(missing, c)| (edit_distance(&lower, &c.to_lowercase()), missing, c))
+            .filter(|&(d, _, c)| d <= if c.len() <= 4 { 1 } else { 2 })
+            // Equal distance: prefer what is imported, then the same case.
+            .min_by_key(|&(d, missing, c)| (d, missing, upper(c) != upper(name), c));
+        match nearest {
+            Some((_, _, c)) => Err(tsukiError::type_(span.clone(), format!("undefined: {}", name))
+                .with_suggestion(Suggestion::replace(format!("did you mean `{}`?", c), name.chars().count(), c))),
+            None => Ok(()),
+        }
+    }
+
     /// Emit a string expression always as a raw C-string literal (`"..."`)
     /// regardless of `arduino_string`, for use as printf format arguments.
+    /// `fmt.Errorf` with a `%w` verb keeps the wrapped error's chain so
+    /// `errors.Is`/`errors.As` still find it. Only one `%w`, in a literal
+    /// format string, is supported; None when there is nothing to wrap.
+    fn emit_errorf_wrap(&self, args: &[Expr], arg_strs: &[String], span: &Span) -> Result<Option<String>> {
+        let Some(Expr::Str(format)) = args.first() else { return Ok(None) };
+        let mut plain   = String::with_capacity(format.len());
+        let mut wrapped = Vec::new();
+        let mut verbs   = 0;
+        let mut chars   = format.chars();
+        while let Some(c) = chars.next() {
+            plain.push(c);
+            if c != '%' { continue }
+            // Flags, width and precision run up to the verb letter.
+            for c in chars.by_ref() {
+                if c == 'w' {
+                    wrapped.push(verbs);
+                    plain.push('s');
+                } else {
+                    plain.push(c);
+                }
+                if c.is_ascii_alphabetic() { verbs += 1; break }
+                if c == '%' { break }
+            }
+        }
+        let w = match wrapped.as_slice() {
+            []  => return Ok(None),
+            [w] => w + 1,
+            _   => return Err(tsukiError::type_(span.clone(),
+                "fmt.Errorf: only one %w per call is supported")),
+        };
+        let Some(inner) = arg_strs.get(w) else {
+            return Err(tsukiError::type_(span.clone(), "fmt.Errorf: %w has no matching argument"));
+        };
+        let mut call = arg_strs.to_vec();
+        call[0] = self.emit_str_raw(&Expr::Str(plain))?;
+        call[w] = "_w.Error().c_str()".into();
+        Ok(Some(format!(
+            "([&](){{ tsuki_error _w = {}; char _buf[128]; snprintf(_buf, sizeof(_buf), {}); \
+             return tsuki_errors_Wrap(__COUNTER__ + 1, String(_buf), _w); }})()",
+            inner, call.join(", "))))
+    }
+
     fn emit_str_raw(&self, expr: &Expr) -> Result<String> {
         if let Expr::Str(s) = expr {
             let mut escaped = String::new();
@@ -607,7 +2037,12 @@ impl Transpiler {
     }
 
     fn resolve_ident(&self, name: &str) -> String {
-        for (_alias, canon) in &self.pkg_map {
+        if !self.locals.contains(name) {
+            if let Some(cpp) = self.symbols.get(name) {
+                return cpp.clone();
+            }
+        }
+        for canon in self.pkg_map.values() {
             if let Some(pkg) = self.rt.pkg(canon) {
                 if let Some(cpp) = pkg.constants.get(name) {
                     return cpp.clone();
@@ -620,18 +2055,119 @@ impl Transpiler {
 
 // ─────────────────────────────────────────────────────────────────────────────
 
+/// C++ symbol for a package-level Go name: `sensors.Init` → `sensors__Init`.
+/// Path separators and dots in the package name become `_`.
+pub fn mangle(pkg: &str, name: &str) -> String {
+    format!("{}__{}", pkg.replace(['/', '.', '-'], "_"), name)
+}
+
+/// Stack depth, in bytes, of the tasks `go` starts on ESP32.
+const TASK_STACK: u32 = 4096;
+
+/// Injection points of `//tsuki:hook`, in the order they run.
+const HOOK_POINTS: &[&str] = &["pre-setup", "post-setup", "pre-loop", "post-loop"];
+
+/// Go standard library packages tsuki has no mapping for; importing one is
+/// a porting mistake rather than a missing tsuki package.
+const GO_STD: &[&str] = &[
+    "bufio", "bytes", "context", "encoding/json", "flag", "io", "log", "net",
+    "net/http", "os", "os/exec", "path", "path/filepath", "reflect", "regexp",
+    "runtime", "sort", "sync", "syscall", "unsafe",
+];
+
+/// Whether `names` (see `dce::program_refs`) mention the package imported
+/// as `alias`: `alias.F` selects it, `alias.T` names its types.
+fn refers_to(names: &HashSet<&str>, alias: &str) -> bool {
+    names.iter().any(|n| n.strip_prefix(alias).is_some_and(|r| r.is_empty() || r.starts_with('.')))
+}
+
+/// Functions the generated scaffold calls itself (hooks, ISRs) take nothing
+/// and return nothing.
+fn check_plain(d: &Decl, dir: &Directive, what: &str) -> Result<()> {
+    let Decl::Func { name, recv, tparams, sig, .. } = d else { return Ok(()) };
+    if recv.is_some() || !tparams.is_empty() || !sig.params.is_empty() || !sig.results.is_empty() {
+        return Err(tsukiError::type_(dir.span.clone(), format!(
+            "{} `{}` must be a plain func with no parameters or results", what, name)));
+    }
+    Ok(())
+}
+
+/// Edit distance that counts swapping two adjacent characters as one edit.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    let mut d = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() { row[0] = i; }
+    d[0] = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1).min(d[i][j - 1] + 1).min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+/// Whether a `+` chain has a string literal operand, i.e. concatenates.
+fn concats_literal(e: &Expr) -> bool {
+    match e {
+        Expr::Str(_) => true,
+        Expr::Binary { op: BinOp::Add, lhs, rhs, .. } => concats_literal(lhs) || concats_literal(rhs),
+        _ => false,
+    }
+}
+
+/// Sketch entry points keep their names in every package.
+fn is_entry_point(name: &str) -> bool {
+    matches!(name, "main" | "setup" | "loop" | "setup1" | "loop1")
+}
+
 fn params_str(sig: &FuncSig) -> String {
+    params_with(sig, Type::to_cpp)
+}
+
+fn params_with(sig: &FuncSig, cpp: impl Fn(&Type) -> String) -> String {
     sig.params.iter().enumerate().map(|(i, p)| {
         let n = p.name.as_deref().unwrap_or("").to_owned();
         let n = if n.is_empty() { format!("_p{}", i) } else { n };
         if p.variadic {
-            format!(".../* {} */", p.ty.to_cpp())
+            format!(".../* {} */", cpp(&p.ty))
         } else {
-            format!("{} {}", p.ty.to_cpp(), n)
+            format!("{} {}", cpp(&p.ty), n)
         }
     }).collect::<Vec<_>>().join(", ")
 }
 
+/// Receiver base type name and whether it is a pointer receiver.
+fn recv_type(r: &FuncParam) -> (&str, bool) {
+    match &r.ty {
+        Type::Ptr(inner) => (inner.named().unwrap_or(""), true),
+        t                => (t.named().unwrap_or(""), false),
+    }
+}
+
+/// `template <typename T, typename U>` line for generic declarations.
+/// `any` and `comparable` need no C++ counterpart: misuse fails at
+/// instantiation.
+fn template_header<'a>(names: impl IntoIterator<Item = &'a str>) -> String {
+    let names: Vec<String> = names.into_iter().map(|n| format!("typename {}", n)).collect();
+    if names.is_empty() { return String::new(); }
+    format!("template <{}>\n", names.join(", "))
+}
+
+fn join_pins(pins: &[u8]) -> String {
+    pins.iter().map(|p| p.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+fn array_declarator(ty: &Type, name: &str) -> String {
+    match ty {
+        Type::Array { len: Some(n), elem } => array_declarator(elem, &format!("{}[{}]", name, n)),
+        t => format!("{} {}", t.to_cpp(), name),
+    }
+}
+
 fn ret_type(sig: &FuncSig) -> String {
     match sig.results.len() {
         0 => "void".into(),
@@ -641,6 +2177,46 @@ fn ret_type(sig: &FuncSig) -> String {
     }
 }
 
+fn is_iface(t: &Type) -> bool {
+    matches!(t, Type::Iface(_)) || matches!(t, Type::Named(n) if n == "any")
+}
+
+fn is_numeric(t: &Type) -> bool {
+    matches!(t, Type::Int | Type::Int8 | Type::Int16 | Type::Int32 | Type::Int64
+        | Type::Uint | Type::Uint8 | Type::Uint16 | Type::Uint32 | Type::Uint64 | Type::Uintptr
+        | Type::Float32 | Type::Float64 | Type::Byte | Type::Rune)
+}
+
+fn is_basic(t: &Type) -> bool {
+    is_numeric(t) || matches!(t, Type::String | Type::Bool)
+}
+
+/// Go spelling of a basic type, for messages.
+fn basic_name(t: &Type) -> &'static str {
+    match t {
+        Type::Int => "int", Type::Int8 => "int8", Type::Int16 => "int16",
+        Type::Int32 => "int32", Type::Int64 => "int64",
+        Type::Uint => "uint", Type::Uint8 => "uint8", Type::Uint16 => "uint16",
+        Type::Uint32 => "uint32", Type::Uint64 => "uint64", Type::Uintptr => "uintptr",
+        Type::Float32 => "float32", Type::Float64 => "float64",
+        Type::Byte => "byte", Type::Rune => "rune",
+        Type::String => "string", Type::Bool => "bool",
+        _ => "value",
+    }
+}
+
+/// Type identity, treating `byte`/`rune` as the aliases they are.
+fn same_type(a: &Type, b: &Type) -> bool {
+    fn canon(t: &Type) -> &Type {
+        match t {
+            Type::Byte => &Type::Uint8,
+            Type::Rune => &Type::Int32,
+            t          => t,
+        }
+    }
+    canon(a) == canon(b)
+}
+
 fn flat_stmt_opt(stmt: &Option<Box<Stmt>>, t: &mut Transpiler) -> Result<String> {
     Ok(match stmt {
         None    => String::new(),
@@ -649,4 +2225,940 @@ fn flat_stmt_opt(stmt: &Option<Box<Stmt>>, t: &mut Transpiler) -> Result<String>
             raw.trim().trim_end_matches(';').to_string()
         }
     })
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn transpile(src: &str) -> Result<String> {
+        transpile_for(src, "uno")
+    }
+
+    fn transpile_for(src: &str, board: &str) -> Result<String> {
+        let tokens = Lexer::new(src, "test.go").tokenize()?;
+        let prog   = Parser::new(tokens).parse_program()?;
+        // Snippets are lone functions nothing calls; keep them.
+        let cfg    = TranspileConfig { board: board.into(), keep_all: true, ..TranspileConfig::default() };
+        Transpiler::new(cfg).generate(&prog)
+    }
+
+    #[test]
+    fn test_switch_init_and_fallthrough() {
+        let cpp = transpile(
+            "package main\nfunc f(n int) int {\nswitch m := n * 2; m {\ncase 4:\nfallthrough\ncase 6:\nreturn 2\n}\nreturn 0\n}",
+        ).unwrap();
+        assert!(cpp.contains("auto m = (n * 2);\n        switch (m) {"));
+        assert!(cpp.contains("case 4:\n                // fallthrough\n            case 6:"));
+    }
+
+    #[test]
+    fn test_tagless_fallthrough_dispatch() {
+        let cpp = transpile(
+            "package main\nfunc f(n int) {\nswitch {\ncase n > 9:\nfallthrough\ndefault:\nn = 0\n}\n}",
+        ).unwrap();
+        assert!(cpp.contains("switch (((n > 9)) ? 0 : -1) {"));
+    }
+
+    #[test]
+    fn test_type_switch_static_dispatch() {
+        let cpp = transpile(
+            "package main\ntype P struct {\nX int\n}\nfunc f() {\nvar v interface{} = P{1}\n\
+             switch p := v.(type) {\ncase int:\np = 1\ncase P:\np.X = 2\n}\n}",
+        ).unwrap();
+        assert!(cpp.contains("P v = P{1};"));
+        assert!(cpp.contains("auto p = v;\n        p.X = 2;"));
+        assert!(!cpp.contains("p = 1"));
+    }
+
+    #[test]
+    fn test_if_init_is_scoped() {
+        let cpp = transpile(
+            "package main\nfunc f() int { return 1 }\nfunc g() {\nif err := f(); err != 0 {\nreturn\n}\n}",
+        ).unwrap();
+        assert!(cpp.contains("    {\n        auto err = f();\n        if ((err != 0)) {"));
+    }
+
+    #[test]
+    fn test_non_main_symbols_are_mangled() {
+        let cpp = transpile(
+            "package sensors\nvar count int\nfunc Init() {\ncount = 1\n}\n\
+             //export Read\nfunc Read() int { return count }\nfunc f(count int) int { return count }",
+        ).unwrap();
+        assert!(cpp.contains("void sensors__Init() {\n    sensors__count = 1;"));
+        assert!(cpp.contains("int Read() {\n    return sensors__count;"));
+        assert!(cpp.contains("int sensors__f(int count) {\n    return count;"));
+    }
+
+    #[test]
+    fn test_loop1_on_esp32_spawns_pinned_task() {
+        let src = "package main\nfunc loop1() {}\nfunc main() {}";
+        let cpp = transpile_for(src, "esp32").unwrap();
+        assert!(cpp.contains("static void tsuki_loop1_task(void*) {"));
+        assert!(cpp.contains("void setup() {\n    xTaskCreatePinnedToCore(tsuki_loop1_task, \"loop1\", 8192, nullptr, 1, nullptr, 0);"));
+        assert!(transpile_for(src, "uno").is_err());
+    }
+
+    #[test]
+    fn test_cpu_mhz_sets_esp32_frequency() {
+        let src  = "package main\nfunc loop1() {}\nfunc main() {}";
+        let prog = |s: &str| Parser::new(Lexer::new(s, "test.go").tokenize().unwrap())
+            .parse_program().unwrap();
+        let cfg  = |mhz| TranspileConfig {
+            board: "esp32".into(), cpu_mhz: Some(mhz), ..TranspileConfig::default()
+        };
+        let cpp = Transpiler::new(cfg(80)).generate(&prog(src)).unwrap();
+        assert!(cpp.contains("void setup() {\n    setCpuFrequencyMhz(80);\n    xTaskCreatePinnedToCore("));
+        assert!(Transpiler::new(cfg(100)).generate(&prog(src)).is_err());
+        // The other ESP32 chips switch clocks the same way, up to their own.
+        let one = "package main\nfunc main() {}";
+        for (board, ok, bad, steps) in [("esp32s2", 240, 100, "80, 160, 240"), ("esp32c3", 160, 240, "80, 160")] {
+            let cfg = |mhz| TranspileConfig { board: board.into(), cpu_mhz: Some(mhz), ..TranspileConfig::default() };
+            let cpp = Transpiler::new(cfg(ok)).generate(&prog(one)).unwrap();
+            assert!(cpp.contains(&format!("setCpuFrequencyMhz({});", ok)), "{}", cpp);
+            let err = Transpiler::new(cfg(bad)).generate(&prog(one)).unwrap_err().to_string();
+            assert!(err.ends_with(&format!("use {}", steps)), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_generics_become_templates() {
+        let cpp = transpile(
+            "package main\ntype Pair[K comparable, V any] struct {\nKey K\nVal V\n}\n\
+             func (p *Pair[K, V]) Set(v V) {\np.Val = v\n}\n\
+             func Max[T any](a T, b T) T {\nreturn a\n}\n\
+             func main() {\np := Pair[int, string]{Key: 1}\nn := Max[float32](1, 2)\n}",
+        ).unwrap();
+        assert!(cpp.contains("template <typename K, typename V>\nstruct Pair {\n    K Key;\n    V Val;"));
+        assert!(cpp.contains("template <typename K, typename V>\nvoid Pair<K, V>::Set(V v) {\n    Pair<K, V>* p = this;"));
+        assert!(cpp.contains("template <typename T>\nT Max(T a, T b) {"));
+        assert!(cpp.contains("auto p = Pair<int, String>{1, {}};"));
+        assert!(cpp.contains("auto n = Max<float>(1, 2);"));
+        assert!(transpile("package main\nfunc F[T fmt.Stringer](v T) {}").is_err());
+    }
+
+    #[test]
+    fn test_strings_package() {
+        let cpp = transpile(
+            "package main\nimport \"strings\"\nfunc main() {\ns := strings.ToUpper(\"a,b\")\n\
+             parts := strings.Split(s, \",\")\nfor _, p := range parts {\nprintln(p)\n}\nn := len(s)\n}",
+        ).unwrap();
+        assert!(cpp.contains("static tsuki_StrSlice tsuki_strings_Split("));
+        assert!(cpp.contains("auto s = ([&](){ String _s = String(\"a,b\"); _s.toUpperCase(); return _s; })();"));
+        assert!(cpp.contains("for (int32_t _i = 0; _i < (int32_t)(parts.n); _i++) {\n        auto p = parts[_i];\n        Serial.println(p);"));
+        assert!(cpp.contains("auto n = s.length();"));
+    }
+
+    #[test]
+    fn test_bytes_and_binary_packages() {
+        let cpp = transpile(
+            "package main\nimport (\n\"bytes\"\n\"encoding/binary\"\n)\nvar buf [8]byte\n\
+             func main() {\nmagic := [2]byte{1, 2}\nbinary.BigEndian.PutUint16(buf[2:], 513)\n\
+             v := binary.LittleEndian.Uint32(buf[:])\nok := bytes.HasPrefix(buf, magic)\n}",
+        ).unwrap();
+        assert!(cpp.contains("#include <string.h>"));
+        assert!(cpp.contains("static T tsuki_binary_get(const uint8_t* b, bool big) {"));
+        assert!(cpp.contains("uint8_t buf[8];"));
+        assert!(cpp.contains("uint8_t magic[2] = {1, 2};"));
+        assert!(cpp.contains("tsuki_binary_put<uint16_t>((&buf[2]), 513, true);"));
+        assert!(cpp.contains("auto v = tsuki_binary_get<uint32_t>((&buf[0]), false);"));
+        assert!(cpp.contains("auto ok = tsuki_bytes_HasPrefix(buf, magic);"));
+    }
+
+    #[test]
+    fn test_eeprom_package() {
+        let src = "package main\nimport \"eeprom\"\nfunc main() {\neeprom.Write(1, 42)\n\
+                   v := eeprom.Read(1024)\n}";
+        let prog = Parser::new(Lexer::new(src, "test.go").tokenize().unwrap()).parse_program().unwrap();
+        let mut t = Transpiler::new(TranspileConfig::default());
+        let cpp = t.generate(&prog).unwrap();
+        assert!(cpp.contains("#include <EEPROM.h>"));
+        assert!(cpp.contains("#define TSUKI_EEPROM_SIZE 1024"));
+        assert!(cpp.contains("do { EEPROM.write(1, 42); TSUKI_EEPROM_COMMIT(); } while(0);"));
+        assert!(!cpp.contains("EEPROM.begin"));
+        let warnings = t.warnings();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].msg.contains("address 1024 is outside the 1024-byte EEPROM"));
+        assert_eq!(warnings[0].span.line, 5);
+
+        for board in ["esp32", "esp32s2", "esp32c3"] {
+            let esp = transpile_for(src, board).unwrap();
+            assert!(esp.contains("void setup() {\n    EEPROM.begin(TSUKI_EEPROM_SIZE);"), "{}", board);
+        }
+        let tiny = transpile_for(src, "attiny1614").unwrap();
+        assert!(tiny.contains("#define TSUKI_EEPROM_SIZE 256") && !tiny.contains("EEPROM.begin"));
+        assert!(transpile_for(src, "zero").is_err());
+    }
+
+    #[test]
+    fn test_wifi_and_http_need_esp() {
+        let src = "package main\nimport (\n\"wifi\"\n\"net/http\"\n)\nfunc main() {\n\
+                   wifi.Begin(\"lab\", \"secret\")\nfor !wifi.Connected() {\n}\n\
+                   resp := http.Post(\"http://api.local/v1\", \"text/plain\", wifi.LocalIP())\n\
+                   if resp.StatusCode == http.StatusOK {\nprintln(resp.Body)\n}\n}";
+        let esp = transpile_for(src, "esp32").unwrap();
+        assert!(esp.contains("#include <WiFi.h>") && esp.contains("#include <HTTPClient.h>"));
+        assert!(esp.contains("for (; (!(WiFi.status() == WL_CONNECTED)); )"));
+        assert!(esp.contains("tsuki_http_Do(\"POST\", String(\"http://api.local/v1\"), String(\"text/plain\"), WiFi.localIP().toString())"));
+        assert!(esp.contains("if ((resp.StatusCode == 200))"));
+
+        let nodemcu = transpile_for(src, "esp8266").unwrap();
+        assert!(nodemcu.contains("#include <ESP8266HTTPClient.h>"));
+        assert!(!nodemcu.contains("#include <WiFi.h>"));
+
+        let err = transpile_for(src, "uno").unwrap_err().to_string();
+        assert!(err.contains("package \"wifi\" is not available on Arduino Uno (arch avr); supported: esp32, esp8266"));
+    }
+
+    #[test]
+    fn test_usb_hid_packages() {
+        let src = "package main\nimport (\n\"keyboard\"\n\"mouse\"\n)\nfunc main() {\n\
+                   keyboard.Begin()\nmouse.Begin()\nkeyboard.Press(keyboard.KeyLeftCtrl)\n\
+                   keyboard.Print(\"c\")\nkeyboard.ReleaseAll()\nmouse.Move(10, -5)\n\
+                   mouse.Click(mouse.Left)\n}";
+        let cpp = transpile_for(src, "leonardo").unwrap();
+        assert!(cpp.contains("#include <Keyboard.h>") && cpp.contains("#include <Mouse.h>"));
+        assert!(cpp.contains("Keyboard.press(KEY_LEFT_CTRL);"));
+        assert!(cpp.contains("Keyboard.releaseAll();"));
+        assert!(cpp.contains("Mouse.move(10, -5, 0);"));
+        assert!(cpp.contains("Mouse.click(MOUSE_LEFT);"));
+        assert!(transpile_for(src, "zero").is_ok());
+
+        let err = transpile_for(src, "uno").unwrap_err().to_string();
+        assert!(err.contains("package \"keyboard\" requires native USB; board `uno` has none"));
+    }
+
+    #[test]
+    fn test_package_arch_restrictions() {
+        let lib = "[package]\nname = \"strip\"\nversion = \"1.0.0\"\narch_deny = [\"sam\"]\n\
+                   [[function]]\ngo = \"Show\"\ncpp = \"strip_show()\"\n\
+                   [[function]]\ngo = \"Turbo\"\ncpp = \"strip_turbo()\"\narch_allow = [\"avr\"]\n";
+        let src = "package main\nimport \"strip\"\nfunc main() {\nstrip.Show()\nstrip.Turbo()\n}";
+        let run = |board: &str| {
+            let mut rt = Runtime::new();
+            rt.load_lib_from_str(lib).unwrap();
+            let prog = Parser::new(Lexer::new(src, "test.go").tokenize().unwrap()).parse_program().unwrap();
+            let cfg = TranspileConfig { board: board.into(), ..TranspileConfig::default() };
+            Transpiler::with_runtime(cfg, rt).generate(&prog)
+        };
+        assert!(run("uno").unwrap().contains("strip_turbo();"));
+
+        let err = run("due").unwrap_err();
+        assert_eq!(err.span().map(|s| s.line), Some(2));
+        assert!(err.to_string().contains(
+            "package \"strip\" is not available on Arduino Due (arch sam); supported: anything but sam"));
+
+        let err = run("esp32").unwrap_err();
+        assert_eq!(err.span().map(|s| (s.line, s.col)), Some((5, 6)));
+        assert!(err.to_string().contains("strip.Turbo is not available on ESP32 Dev Module (arch esp32); supported: avr"));
+    }
+
+    #[test]
+    fn test_board_specific_mappings() {
+        let lib = "[package]\nname = \"led\"\nversion = \"1.0.0\"\n\
+                   [[function]]\ngo = \"On\"\ncpp = \"digitalWrite(LED_BUILTIN, HIGH)\"\n\
+                   [[function]]\ngo = \"On\"\ncpp = \"neopixelWrite(RGB_BUILTIN, 0, 32, 0)\"\narch = \"esp32\"\n\
+                   [[function]]\ngo = \"On\"\ncpp = \"led_on_mega()\"\nboards = [\"mega\"]\n\
+                   [[function]]\ngo = \"Deep\"\ncpp = \"esp_deep_sleep_start()\"\narch = \"esp32\"\n\
+                   [[constant]]\ngo = \"Pin\"\ncpp = \"13\"\n\
+                   [[constant]]\ngo = \"Pin\"\ncpp = \"2\"\nboards = [\"esp32\", \"nodemcu\"]\n";
+        let run = |board: &str, body: &str| {
+            let mut rt = Runtime::new();
+            rt.load_lib_from_str(lib).unwrap();
+            let src = format!("package main\nimport \"led\"\nfunc loop() {{\n{}\n}}", body);
+            let prog = Parser::new(Lexer::new(&src, "test.go").tokenize().unwrap()).parse_program().unwrap();
+            let cfg = TranspileConfig { board: board.into(), ..TranspileConfig::default() };
+            Transpiler::with_runtime(cfg, rt).generate(&prog)
+        };
+        let body = "led.On()\npinMode(led.Pin, OUTPUT)";
+        let uno = run("uno", body).unwrap();
+        assert!(uno.contains("digitalWrite(LED_BUILTIN, HIGH);") && uno.contains("pinMode(13, OUTPUT);"), "{}", uno);
+        let esp = run("esp32", body).unwrap();
+        assert!(esp.contains("neopixelWrite(RGB_BUILTIN, 0, 32, 0);") && esp.contains("pinMode(2, OUTPUT);"), "{}", esp);
+        // A board id beats the architecture.
+        assert!(run("mega", body).unwrap().contains("led_on_mega();"));
+
+        assert!(run("esp32", "led.Deep()").unwrap().contains("esp_deep_sleep_start();"));
+        let err = run("uno", "led.Deep()").unwrap_err();
+        assert!(err.to_string().contains("led.Deep is not available on Arduino Uno (arch avr); supported: esp32"), "{}", err);
+    }
+
+    #[test]
+    fn test_package_call_arity() {
+        let err = transpile("package main\nimport \"dht\"\nfunc main() {\nd := dht.New(4)\n}").unwrap_err();
+        assert_eq!(err.span().map(|s| (s.line, s.col)), Some((4, 9)));
+        assert!(err.to_string().contains("package dht function New expects 2 arguments, got 1"), "{}", err);
+
+        let lib = "[package]\nname = \"strip\"\nversion = \"1.0.0\"\n\
+                   [[function]]\ngo = \"New\"\ncpp = \"Strip({0})\"\n\
+                   [[function]]\ngo = \"Fill\"\ncpp = \"{self}.fill({1|upper})\"\n";
+        let run = |body: &str| {
+            let mut rt = Runtime::new();
+            rt.load_lib_from_str(lib).unwrap();
+            let src = format!("package main\nimport \"strip\"\nfunc main() {{\ns := strip.New(8)\n{}\n}}", body);
+            let prog = Parser::new(Lexer::new(&src, "test.go").tokenize().unwrap()).parse_program().unwrap();
+            Transpiler::with_runtime(TranspileConfig::default(), rt).generate(&prog)
+        };
+        assert!(run("s.Fill(red)").unwrap().contains("s.fill(RED);"));
+        let err = run("s.Fill()").unwrap_err();
+        assert!(err.to_string().contains("package strip method Fill expects 1 argument, got 0"), "{}", err);
+    }
+
+    #[test]
+    fn test_import_diagnostics() {
+        let src = "package main\nimport \"ws2812\"\nfunc main() {\nws2812.New(3)\n}";
+        let err = transpile(src).unwrap_err();
+        assert_eq!(err.span().map(|s| (s.line, s.col)), Some((2, 8)));
+        assert!(err.to_string().contains("unknown package \"ws2812\""), "{}", err);
+        assert!(err.pretty(src).contains("help: if it is a tsuki package, `tsuki pkg search ws2812` finds it"), "{}", err.pretty(src));
+        let err = transpile("package main\nimport \"os\"\nfunc main() {\n}").unwrap_err();
+        assert!(err.to_string().contains("package \"os\" of the Go standard library is not available"), "{}", err);
+
+        let src = "package main\nimport (\n\"math\"\nm \"time\"\n_ \"fmt\"\n\"strings\"\n)\n\
+                   func unused() {\nstrings.ToUpper(\"a\")\n}\nfunc main() {\n}";
+        let prog = Parser::new(Lexer::new(src, "test.go").tokenize().unwrap()).parse_program().unwrap();
+        let mut t = Transpiler::new(TranspileConfig::default());
+        t.generate(&prog).unwrap();
+        let warnings: Vec<String> = t.warnings().iter().map(|w| w.to_string()).collect();
+        assert_eq!(warnings.len(), 2, "{:?}", warnings);
+        assert!(warnings[0].contains("test.go:3:1  \"math\" imported and not used"), "{:?}", warnings);
+        assert!(warnings[1].contains("\"time\" imported and not used"), "{:?}", warnings);
+    }
+
+    #[test]
+    fn test_cpp_class_receivers() {
+        let lib = "[package]\nname = \"ws2812\"\nversion = \"1.0.0\"\ncpp_class = \"Adafruit_NeoPixel\"\n\
+                   [[function]]\ngo = \"New\"\ncpp = \"Adafruit_NeoPixel({0}, {1})\"\n\
+                   [[function]]\ngo = \"Show\"\ncpp = \"{self}.show()\"\n";
+        let mut rt = Runtime::new();
+        rt.load_lib_from_str(lib).unwrap();
+        let src = "package main\nimport \"ws2812\"\nvar strip ws2812.Strip = ws2812.New(8, 6)\n\
+                   func setup() {\nstrip.Show()\nvar p *ws2812.Strip\np = ws2812.New(1, 2)\np.Show()\n\
+                   var v = ws2812.New(3, 4)\nv.Show()\n}";
+        let prog = Parser::new(Lexer::new(src, "test.go").tokenize().unwrap()).parse_program().unwrap();
+        let out = Transpiler::with_runtime(TranspileConfig::default(), rt).generate(&prog).unwrap();
+        for expected in [
+            "Adafruit_NeoPixel* strip = new Adafruit_NeoPixel(8, 6);\n",
+            "    (*strip).show();\n",
+            "    Adafruit_NeoPixel* p = nullptr;\n    p = new Adafruit_NeoPixel(1, 2);\n    (*p).show();\n",
+            "    auto v = Adafruit_NeoPixel(3, 4);\n    v.show();\n",
+        ] {
+            assert!(out.contains(expected), "missing {:?} in\n{}", expected, out);
+        }
+    }
+
+    #[test]
+    fn test_esp32_freertos() {
+        let src = "package main\nimport (\n\"freertos\"\n\"time\"\n)\nvar q freertos.Queue\n\
+                   func worker() {\nfor {\nfreertos.Send(q, 1, freertos.Forever)\ntime.Sleep(500 * time.Millisecond)\n}\n}\n\
+                   func setup() {\nq = freertos.NewQueue(4, 4)\ngo worker()\n}";
+        let out = transpile_for(src, "esp32").unwrap();
+        for expected in [
+            "using Queue     = QueueHandle_t;",
+            "Queue q;",
+            "tsuki_freertos_Send(q, 1, portMAX_DELAY);",
+            "vTaskDelay(pdMS_TO_TICKS(((500 * 1000000ULL))/1000000UL));",
+            "    q = xQueueCreate(4, 4);\n    xTaskCreate([](void*) { worker(); vTaskDelete(nullptr); }, \"worker\", 4096, nullptr, 1, nullptr);\n",
+        ] {
+            assert!(out.contains(expected), "missing {:?} in\n{}", expected, out);
+        }
+
+        let err = transpile_for("package main\nfunc blink(pin int) {}\nfunc setup() {\ngo blink(2)\n}", "esp32").unwrap_err();
+        assert!(err.to_string().contains("go on ESP32 starts a FreeRTOS task, which cannot take arguments"), "{}", err);
+
+        // Other boards keep the busy delay and have no freertos package.
+        let out = transpile_for("package main\nimport \"time\"\nfunc loop() {\ntime.Sleep(time.Second)\n}", "uno").unwrap();
+        assert!(out.contains("delay(") && !out.contains("vTaskDelay"), "{}", out);
+        let err = transpile_for("package main\nimport \"freertos\"\nfunc loop() {\nfreertos.Yield()\n}", "uno").unwrap_err();
+        assert!(err.to_string().contains("package \"freertos\" is not available on Arduino Uno"), "{}", err);
+    }
+
+    #[test]
+    fn test_package_injected_code() {
+        let lib = "[package]\nname = \"radio\"\nversion = \"1.0.0\"\ncpp_header = \"Radio.h\"\n\
+                   cpp_prelude = \"#define RADIO_BUF 64\"\n\
+                   cpp_globals = \"static uint8_t radio_buf[RADIO_BUF];\\nRadio radio(radio_buf);\\n\"\n\
+                   cpp_setup = \"\"\"\nradio.begin();\nif (!radio.ok()) {\n    while (true) {}\n}\n\"\"\"\n\
+                   [[function]]\ngo = \"Send\"\ncpp = \"radio.send({0})\"\n";
+        let run = |body: &str| {
+            let mut rt = Runtime::new();
+            rt.load_lib_from_str(lib).unwrap();
+            let src = format!("package main\nimport \"radio\"\nfunc main() {{\ndelay(1)\n}}\nfunc loop() {{\n{}\n}}", body);
+            let prog = Parser::new(Lexer::new(&src, "test.go").tokenize().unwrap()).parse_program().unwrap();
+            Transpiler::with_runtime(TranspileConfig::default(), rt).generate(&prog).unwrap()
+        };
+        let out = run("radio.Send(1)");
+        assert!(out.contains("#include <Radio.h>\n\n#define RADIO_BUF 64\n"), "{}", out);
+        assert!(out.contains("static uint8_t radio_buf[RADIO_BUF];\nRadio radio(radio_buf);\n"), "{}", out);
+        assert!(out.contains("void setup() {\n    radio.begin();\n    if (!radio.ok()) {\n        while (true) {}\n    }\n"), "{}", out);
+
+        let out = run("delay(2)");
+        assert!(!out.contains("RADIO_BUF") && !out.contains("radio.begin()"), "{}", out);
+    }
+
+    #[test]
+    fn test_package_signatures() {
+        let lib = "[package]\nname = \"therm\"\nversion = \"1.0.0\"\n\
+                   [[function]]\ngo = \"Scale\"\ncpp = \"scale({0}, {1})\"\n\
+                   params = [\"int\", \"float32\"]\nreturns = [\"int\"]\n";
+        let run = |body: &str| {
+            let mut rt = Runtime::new();
+            rt.load_lib_from_str(lib).unwrap();
+            let src = format!("package main\nimport \"therm\"\nfunc main() {{\nvar k int16 = 3\n{}\n}}", body);
+            let prog = Parser::new(Lexer::new(&src, "test.go").tokenize().unwrap()).parse_program().unwrap();
+            Transpiler::with_runtime(TranspileConfig::default(), rt).generate(&prog)
+        };
+        assert!(run("v := therm.Scale(k, 2)").unwrap().contains("scale(static_cast<int>(k), 2)"));
+        assert!(run("v := therm.Scale(1, 2.5)").unwrap().contains("scale(1, 2.5)"));
+        let err = run("v := therm.Scale(1.5, 2)").unwrap_err();
+        assert!(err.to_string().contains("cannot use 1.5 (untyped float constant) as int in argument 1 to therm.Scale"), "{}", err);
+        let err = run("var s string\nv := therm.Scale(1, s)").unwrap_err();
+        assert!(err.to_string().contains("cannot use s (string) as float32 in argument 2"), "{}", err);
+        let err = run("v := therm.Scale(1, 2, 3)").unwrap_err();
+        assert!(err.to_string().contains("package therm function Scale expects 2 arguments, got 3"), "{}", err);
+
+        let bad = lib.replace("\"float32\"", "\"[]flo at\"");
+        assert!(Runtime::new().load_lib_from_str(&bad).unwrap_err().to_string().contains("bad type `[]flo at`"));
+    }
+
+    #[test]
+    fn test_package_board_requirements() {
+        let run = |lib: &str, board: &str| {
+            let mut rt = Runtime::new();
+            rt.load_lib_from_str(lib)?;
+            let src = "package main\nimport \"ws2812\"\nfunc main() {\n}";
+            let prog = Parser::new(Lexer::new(src, "test.go").tokenize().unwrap()).parse_program().unwrap();
+            let cfg = TranspileConfig { board: board.into(), ..TranspileConfig::default() };
+            Transpiler::with_runtime(cfg, rt).generate(&prog)
+        };
+        let lib = "[package]\nname = \"ws2812\"\nversion = \"1.0.0\"\nrequires = [\"wifi\"]\n";
+        let err = run(lib, "uno").unwrap_err();
+        assert_eq!(err.span().map(|s| s.line), Some(2));
+        assert!(err.to_string().contains("package \"ws2812\" requires WiFi; board `uno` has none"));
+        assert!(run(lib, "esp8266").is_ok());
+
+        let lib = "[package]\nname = \"ws2812\"\nversion = \"1.0.0\"\nmin_ram_kb = 8\n";
+        assert!(run(lib, "uno").unwrap_err().to_string()
+            .contains("package \"ws2812\" needs 8 KB of RAM; board `uno` has 2 KB"));
+        assert!(run(lib, "mega").is_ok());
+
+        let lib = "[package]\nname = \"ws2812\"\nversion = \"1.0.0\"\nrequires = [\"lora\"]\n";
+        assert!(run(lib, "uno").unwrap_err().to_string().contains("unknown board feature `lora`"));
+    }
+
+    #[test]
+    fn test_error_wrapping() {
+        let src = "package main\nimport (\n\"errors\"\n\"fmt\"\n)\nvar ErrBusy = errors.New(\"busy\")\n\
+                   type PinError struct {\nPin int\n}\nfunc (e *PinError) Error() string {\nreturn \"bad pin\"\n}\n\
+                   func claim(pin int) error {\nif pin > 13 {\nreturn &PinError{Pin: pin}\n}\n\
+                   return fmt.Errorf(\"claim %d: %w\", pin, ErrBusy)\n}\n\
+                   func main() {\nerr := claim(3)\nvar pe *PinError\nif errors.Is(err, ErrBusy) || errors.As(err, &pe) {\n}\n}";
+        let cpp = transpile(src).unwrap();
+        assert!(cpp.contains("struct tsuki_error {"));
+        assert!(cpp.contains("tsuki_error claim(int pin);"));
+        assert!(cpp.contains("return PinError{pin};"));
+        assert!(cpp.contains("snprintf(_buf, sizeof(_buf), \"claim %d: %s\", pin, _w.Error().c_str()); \
+                              return tsuki_errors_Wrap(__COUNTER__ + 1, String(_buf), _w);"));
+        assert!(cpp.contains("if ((tsuki_errors_Is(err, ErrBusy) || tsuki_errors_As(err, (&pe))))"));
+
+        // The error type is available without importing "errors".
+        let cpp = transpile("package main\nimport \"fmt\"\nfunc f() error {\nreturn fmt.Errorf(\"x\")\n}").unwrap();
+        assert!(cpp.contains("struct tsuki_error {") && cpp.contains("tsuki_errors_New(__COUNTER__ + 1"));
+
+        let err = transpile("package main\nimport \"fmt\"\nfunc f(a error, b error) error {\n\
+                             return fmt.Errorf(\"%w / %w\", a, b)\n}").unwrap_err();
+        assert!(err.to_string().contains("only one %w per call"));
+    }
+
+    #[test]
+    fn test_pin_checks() {
+        let warnings = |src: &str, board: &str| {
+            let prog = Parser::new(Lexer::new(src, "test.go").tokenize().unwrap()).parse_program().unwrap();
+            let mut t = Transpiler::new(TranspileConfig { board: board.into(), ..Default::default() });
+            t.generate(&prog).unwrap();
+            t.warnings().into_iter().map(|w| w.msg).collect::<Vec<_>>()
+        };
+        let src = "package main\nimport \"arduino\"\nconst led = 13\nfunc setup() {\n\
+                   arduino.pinMode(led, arduino.OUTPUT)\narduino.pinMode(22, arduino.OUTPUT)\n\
+                   arduino.analogWrite(7, 128)\narduino.analogWrite(9, 128)\n\
+                   v := arduino.analogRead(arduino.A3)\nw := arduino.analogRead(2)\n}";
+        let uno = warnings(src, "uno");
+        assert_eq!(uno.len(), 2, "{:?}", uno);
+        assert!(uno[0].contains("pinMode: pin 22 does not exist on Arduino Uno (digital pins 0..19)"));
+        assert!(uno[1].contains("analogWrite: pin 7 of Arduino Uno has no PWM; PWM pins are 3, 5, 6, 9, 10, 11"));
+        assert!(warnings(src, "mega").is_empty());
+
+        let esp = warnings(src, "esp32");
+        assert_eq!(esp.len(), 2, "{:?}", esp);
+        assert!(esp[1].contains("analogWrite: pin 9 of ESP32 Dev Module has no PWM"));
+        assert!(warnings("package main\nimport \"arduino\"\nfunc setup() {\n\
+                          arduino.pinMode(34, arduino.OUTPUT)\n}", "esp32")[0].contains("input-only"));
+        assert!(warnings("package main\nimport (\"arduino\"\n\"wire\")\nfunc setup() {\n\
+                          arduino.digitalWrite(18, arduino.HIGH)\n}", "uno").iter().any(|w| w.contains("I2C bus")));
+    }
+
+    #[test]
+    fn test_progmem_strings() {
+        let src = "package main\nimport \"fmt\"\nvar names = [2]string{\"alpha\", \"beta\"}\n\
+                   func setup() {\nfmt.Println(\"Sensor init OK\")\nfmt.Println(\"hi\")\n\
+                   for _, n := range names {\nfmt.Println(n)\n}\nfmt.Println(names[1])\n}";
+        let run = |board: &str, src: &str| {
+            let prog = Parser::new(Lexer::new(src, "test.go").tokenize().unwrap()).parse_program().unwrap();
+            let cfg = TranspileConfig { board: board.into(), progmem_min: Some(8), ..TranspileConfig::default() };
+            Transpiler::new(cfg).generate(&prog)
+        };
+        let cpp = run("uno", src).unwrap();
+        assert!(cpp.contains("const char names_0[] PROGMEM = \"alpha\";"));
+        assert!(cpp.contains("const char* const names[2] PROGMEM = { names_0, names_1 };"));
+        assert!(cpp.contains("Serial.println(F(\"Sensor init OK\"));"));
+        assert!(cpp.contains("Serial.println(String(\"hi\"));"));
+        assert!(cpp.contains("auto n = String((const __FlashStringHelper*)pgm_read_ptr(&names[_i]));"));
+        assert!(cpp.contains("Serial.println(String((const __FlashStringHelper*)pgm_read_ptr(&names[1])));"));
+
+        let esp = run("esp32", src).unwrap();
+        assert!(!esp.contains("PROGMEM") && !esp.contains("F("));
+
+        let err = run("uno", &src.replace("fmt.Println(names[1])", "names[1] = \"gamma\"")).unwrap_err();
+        assert!(err.to_string().contains("cannot assign to `names`: it is kept in flash"));
+    }
+
+    #[test]
+    fn test_workspace_package_linking() {
+        let parse = |src: &str| Parser::new(Lexer::new(src, "test.go").tokenize().unwrap()).parse_program().unwrap();
+        let lib = parse("package proto\ntype Packet struct {\nID int\n}\nconst Version = 2\nvar sent int\n\
+                         func Encode(p Packet) int {\nsent++\nreturn p.ID\n}");
+        let h = Transpiler::new(TranspileConfig::default()).declarations(&lib).unwrap();
+        assert!(h.contains("#pragma once"));
+        assert!(h.contains("struct Packet {\n    int ID;\n};"));
+        assert!(h.contains("const auto proto__Version = 2;\nextern int proto__sent;\nint proto__Encode(Packet p);"));
+        let cpp = Transpiler::new(TranspileConfig::default()).generate(&lib).unwrap();
+        assert!(!cpp.contains("void setup()") && !cpp.contains("void loop()"));
+
+        let main = parse("package main\nimport \"proto\"\nfunc setup() {\nvar p proto.Packet\n\
+                          x := proto.Encode(p) + proto.Version\n}");
+        let cfg = TranspileConfig { local_packages: vec!["proto".into()], ..TranspileConfig::default() };
+        let cpp = Transpiler::new(cfg).generate(&main).unwrap();
+        assert!(cpp.contains("#include <Arduino.h>\n#include \"proto.h\"\n"));
+        assert!(cpp.contains("auto x = (proto__Encode(p) + proto__Version);"));
+    }
+
+    #[test]
+    fn test_isr_directive() {
+        let src = "package main\nvar ticks int\n//tsuki:isr TIMER1_COMPA\nfunc tick() {\nticks++\n}\n\
+                   func loop() {\nticks = 0\n}";
+        let run = |board: &str, src: &str| {
+            let prog = Parser::new(Lexer::new(src, "test.go").tokenize().unwrap()).parse_program().unwrap();
+            let cfg = TranspileConfig { board: board.into(), ..TranspileConfig::default() };
+            let mut t = Transpiler::new(cfg);
+            t.generate(&prog).map(|cpp| (cpp, t.warnings()))
+        };
+        let (cpp, w) = run("uno", src).unwrap();
+        assert!(cpp.contains("ISR(TIMER1_COMPA_vect) {\n    ticks++;\n}"), "{}", cpp);
+        assert!(!cpp.contains("void tick();"));
+        assert!(w[0].msg.contains("from an interrupt handler (tick"), "{:?}", w);
+
+        for board in ["esp32", "esp32s2", "esp32c3", "d1_mini"] {
+            let (cpp, _) = run(board, src).unwrap();
+            assert!(cpp.contains("void IRAM_ATTR tick() {"), "{}: {}", board, cpp);
+        }
+        let (cpp, _) = run("pico", src).unwrap();
+        assert!(cpp.contains("void tick() {"));
+        // Teensyduino's FQBN says avr, but the chip is ARM.
+        let (cpp, _) = run("teensy40", src).unwrap();
+        assert!(cpp.contains("void tick() {"));
+        // tinyAVR 1-series: same ISR() vectors, named after the peripheral.
+        let tca = src.replace("TIMER1_COMPA", "TCA0_OVF");
+        let (cpp, _) = run("attiny1614", &tca).unwrap();
+        assert!(cpp.contains("ISR(TCA0_OVF_vect) {\n    ticks++;\n}"), "{}", cpp);
+        assert!(!cpp.contains("void tick()"));
+
+        let naked = src.replace("TIMER1_COMPA", "INT0_vect naked");
+        assert!(run("uno", &naked).unwrap().0.contains("ISR(INT0_vect, ISR_NAKED) {"));
+        assert!(run("esp32", &naked).unwrap_err().to_string().contains("ISR_NAKED is only available on AVR"));
+        let bare = src.replace(" TIMER1_COMPA", "");
+        assert!(run("uno", &bare).unwrap_err().to_string().contains("needs a vector on Arduino Uno"));
+        let args = src.replace("tick()", "tick(n int)");
+        assert!(run("esp32", &args).unwrap_err().to_string().contains("no parameters or results"));
+    }
+
+    #[test]
+    fn test_scaffold_hooks() {
+        let src = "package main\n//tsuki:hook pre-setup\nfunc watchdog() {\n}\n\
+                   //tsuki:hook post-loop\nfunc feed() {\n}\n\
+                   func setup() {\nx := 1\n}\nfunc loop() {\nif millis() > 5 {\nreturn\n}\ndelay(1)\n}";
+        let run = |board: &str, src: &str| {
+            let prog = Parser::new(Lexer::new(src, "test.go").tokenize().unwrap()).parse_program().unwrap();
+            let cfg = TranspileConfig { board: board.into(), cpu_mhz: Some(80), ..TranspileConfig::default() };
+            Transpiler::new(cfg).generate(&prog)
+        };
+        let cpp = run("esp32", src).unwrap();
+        assert!(cpp.contains("void setup() {\n    watchdog();\n    setCpuFrequencyMhz(80);\n    auto x = 1;\n}"), "{}", cpp);
+        assert!(cpp.contains("        feed();\n        return;\n"));
+        assert!(cpp.contains("    delay(1);\n    feed();\n}"));
+
+        // Without a loop() the hooks still run from the generated stub.
+        let stub = run("uno", "package main\n//tsuki:hook pre-loop\nfunc tick() {\n}").unwrap();
+        assert!(stub.contains("void loop() {\n    tick();\n}"));
+
+        let bad = run("uno", &src.replace("post-loop", "after-loop")).unwrap_err().to_string();
+        assert!(bad.contains("unknown hook point `after-loop`; expected one of pre-setup, post-setup, pre-loop, post-loop"));
+        let bad = run("uno", &src.replace("feed()", "feed(n int)")).unwrap_err().to_string();
+        assert!(bad.contains("hook `feed` must be a plain func"));
+    }
+
+    #[test]
+    fn test_main_split_into_setup_and_loop() {
+        let run = |src: &str, split: bool| {
+            let prog = Parser::new(Lexer::new(src, "test.go").tokenize().unwrap()).parse_program().unwrap();
+            let cfg = TranspileConfig { split_main: split, ..TranspileConfig::default() };
+            let mut t = Transpiler::new(cfg);
+            t.generate(&prog).map(|cpp| (cpp, t.warnings()))
+        };
+        let src = "package main\nvar n int\nfunc main() {\nn = 1\nfor {\nif n > 3 {\ncontinue\n}\n\
+                   for i := 0; i < 2; i++ {\nbreak\n}\nn++\n}\n}";
+        let (cpp, w) = run(src, true).unwrap();
+        assert!(w.is_empty(), "{:?}", w);
+        assert!(cpp.contains("void setup() {\n    n = 1;\n}"), "{}", cpp);
+        assert!(cpp.contains("void loop() {\n    if ((n > 3)) {\n        return;\n    }\n"));
+        assert!(!cpp.contains("void loop()  {}"));
+
+        let (cpp, _) = run(src, false).unwrap();
+        assert!(cpp.contains("void setup() {\n    n = 1;\n    for (; ; ) {"), "{}", cpp);
+
+        let local = src.replace("n = 1\nfor {", "x := 1\nfor {\nn = x");
+        let (cpp, w) = run(&local, true).unwrap();
+        assert!(cpp.contains("for (; ; ) {"));
+        assert!(w[0].msg.ends_with("the loop uses `x`, declared before it; declare it at package level"));
+        let (_, w) = run(&src.replace("n++", "return"), true).unwrap();
+        assert!(w[0].msg.contains("the loop can exit"));
+
+        let both = "package main\nfunc main() {\n}\nfunc setup() {\n}";
+        assert!(run(both, true).unwrap_err().to_string().contains("both main() and setup() are declared"));
+    }
+
+    #[test]
+    fn test_unreachable_declarations_dropped() {
+        let src = "package main\nconst Used = 3\nconst Unused = 4\n\
+                   type Pt struct {\nX int\n}\nfunc (p Pt) Norm() int {\nreturn p.X\n}\n\
+                   type Spare struct {\nY int\n}\n\
+                   func helper() Pt {\nreturn Pt{Used}\n}\nfunc orphan() {\nhelper()\n}\n\
+                   //export Tick\nfunc tick() {\n}\n\
+                   func setup() {\np := helper()\np.Norm()\n}";
+        let run = |keep_all: bool| {
+            let prog = Parser::new(Lexer::new(src, "test.go").tokenize().unwrap()).parse_program().unwrap();
+            let cfg = TranspileConfig { keep_all, ..TranspileConfig::default() };
+            Transpiler::new(cfg).generate(&prog).unwrap()
+        };
+        let cpp = run(false);
+        for kept in ["Used = 3", "struct Pt {", "int Pt::Norm() const", "Pt helper()", "void Tick()"] {
+            assert!(cpp.contains(kept), "{} missing from\n{}", kept, cpp);
+        }
+        for gone in ["Unused", "struct Spare", "orphan"] {
+            assert!(!cpp.contains(gone), "{} emitted in\n{}", gone, cpp);
+        }
+        let all = run(true);
+        assert!(all.contains("Unused") && all.contains("struct Spare") && all.contains("void orphan()"));
+    }
+
+    #[test]
+    fn test_constant_folding() {
+        // ESP32: 60000 and 120000 fit its 32-bit int.
+        let cpp = transpile_for(
+            "package main\nconst Debug = false\nconst Rate = 9600\nconst Minute = 1000 * 60\n\
+             func f(buf [8]byte) int {\ndelay(Rate / 2)\nif Debug {\nprintln(\"dbg\")\n}\n\
+             if !Debug && millis() > len(buf) {\ndelay(Minute * 2)\n} else if true {\nn := 1 << 4\nreturn n\n}\n\
+             return len(buf) - 1\n}\n\
+             func g(Rate int) int {\nreturn Rate * 2\n}",
+            "esp32",
+        ).unwrap();
+        assert!(cpp.contains("60000"), "{}", cpp);
+        assert!(cpp.contains("delay(4800);"), "{}", cpp);
+        assert!(!cpp.contains("dbg"), "{}", cpp);
+        assert!(cpp.contains("if ((millis() > 8)) {\n        delay(120000);\n    } else {\n        auto n = 16;\n        return n;\n    }"));
+        assert!(cpp.contains("return 7;"));
+        // A parameter named like a constant is not substituted.
+        assert!(cpp.contains("return (Rate * 2);"));
+    }
+
+    #[test]
+    fn test_folding_stays_in_int_range() {
+        let src = "package main\nconst Big = 40000 * 2\nconst Small = 100 * 300\nconst Wide = 300 * 300 / 100\n\
+                   const Neg = -32768\nconst Shift = 1 << 15\n\
+                   func f() int {\nreturn Small + Neg\n}\nfunc g() int {\nreturn Shift + Big + Wide\n}";
+        // Uno: int is 16 bits, so what leaves -32768..32767 is left to C++.
+        let cpp = transpile(src).unwrap();
+        assert!(cpp.contains("const auto Big = (40000 * 2);"), "{}", cpp);
+        assert!(cpp.contains("const auto Small = 30000;"));
+        assert!(cpp.contains("const auto Wide = ((300 * 300) / 100);"));
+        assert!(cpp.contains("const auto Neg = -32768;"));
+        assert!(cpp.contains("const auto Shift = (1 << 15);"));
+        assert!(cpp.contains("return -2768;"));
+        assert!(cpp.contains("return ((Shift + Big) + Wide);"));
+
+        let cpp = transpile_for(src, "esp32").unwrap();
+        assert!(cpp.contains("const auto Big = 80000;"), "{}", cpp);
+        assert!(cpp.contains("const auto Wide = 900;"));
+        assert!(cpp.contains("return 113668;"));
+    }
+
+    #[test]
+    fn test_folding_shadowed_names() {
+        let cpp = transpile(
+            "package main\nconst Rate = 9600\nconst Debug = true\n\
+             func f(n int) int {\nif n > 0 {\nRate := n\nreturn Rate / 2\n}\nreturn Rate / 2\n}\n\
+             func g() bool {\nfor Debug := 0; Debug < 3; Debug++ {\n}\nreturn !Debug\n}\n\
+             func h() int {\nreturn Rate / 2\n}",
+        ).unwrap();
+        // A local anywhere in the function shadows the constant throughout.
+        assert!(cpp.contains("auto Rate = n;\n        return (Rate / 2);\n    }\n    return (Rate / 2);"), "{}", cpp);
+        assert!(cpp.contains("return (!Debug);"));
+        assert!(cpp.contains("int h() {\n    return 4800;"));
+    }
+
+    #[test]
+    fn test_folding_array_len() {
+        let cpp = transpile(
+            "package main\nvar table [4]int\n\
+             func a() int {\nreturn len(table) * 2\n}\n\
+             func b() int {\ntable := [2]int{1, 2}\nreturn len(table) * 2\n}\n\
+             func c(flag bool) int {\nif flag {\nt := [2]int{}\nreturn len(t) * 2\n}\nt := [3]int{}\nreturn len(t) * 2\n}\n\
+             func d(buf [8]byte) bool {\nreturn len(buf) > 4\n}",
+        ).unwrap();
+        assert!(cpp.contains("int a() {\n    return 8;"), "{}", cpp);
+        assert!(cpp.contains("return 4;"));
+        // `t` has two lengths in c(), so neither is folded in.
+        assert!(!cpp.contains("return 6;"));
+        assert!(cpp.contains("return true;"));
+    }
+
+    #[test]
+    fn test_folding_if_collapse_scope() {
+        let cpp = transpile(
+            "package main\nconst Debug = true\n\
+             func k() int {\nx := 5\nif Debug {\nx := 1\nprintln(x)\n}\nif v := 3; Debug {\nprintln(v)\n}\n\
+             if !Debug {\nreturn 0\n} else {\nprintln(\"on\")\n}\nreturn x\n}",
+        ).unwrap();
+        // Branches and headers that declare names keep a block of their
+        // own; the rest is inlined.
+        assert!(cpp.contains(
+            "    auto x = 5;\n    {\n        auto x = 1;\n        Serial.println(x);\n    }\n\
+             \x20   {\n        auto v = 3;\n        Serial.println(v);\n    }\n\
+             \x20   Serial.println(String(\"on\"));\n    return x;"), "{}", cpp);
+    }
+
+    #[test]
+    fn test_sd_file_handles() {
+        let src = "package main\nimport \"sd\"\nfunc main() {\nsd.Begin(10)\n\
+                   f := sd.Open(\"log.txt\", sd.FileWrite)\nf.Println(\"t=\" + \"1\")\nf.Close()\n\
+                   r := sd.Open(\"log.txt\", sd.FileRead)\nfor r.Available() > 0 {\nline := r.ReadLine()\n\
+                   println(line)\n}\nr.Close()\nif sd.Exists(\"old.txt\") {\nsd.Remove(\"old.txt\")\n}\n}";
+        let run = |board: &str| {
+            let prog = Parser::new(Lexer::new(src, "test.go").tokenize().unwrap()).parse_program().unwrap();
+            let mut t = Transpiler::new(TranspileConfig { board: board.into(), ..TranspileConfig::default() });
+            let cpp = t.generate(&prog).unwrap();
+            (cpp, t.warnings())
+        };
+        let (cpp, warnings) = run("uno");
+        assert!(cpp.contains("#include <SD.h>"));
+        assert!(cpp.contains("SD.begin(10);"));
+        assert!(cpp.contains("auto f = SD.open(String(String(\"log.txt\")).c_str(), FILE_WRITE);"), "{}", cpp);
+        assert!(cpp.contains("f.close();"));
+        assert!(cpp.contains("for (; (r.available() > 0); )"));
+        assert!(cpp.contains("r.readStringUntil('\\n');"));
+        assert!(cpp.contains("if (SD.exists(String(String(\"old.txt\")).c_str()))"));
+        let msgs: Vec<_> = warnings.iter().map(|w| w.to_string()).collect();
+        assert!(msgs.iter().any(|m| m.contains("sd.ReadLine: ReadLine allocates a String per line")), "{:?}", msgs);
+        assert!(msgs.iter().any(|m| m.contains("sd.Println: `+` builds temporary Strings")));
+
+        let (_, warnings) = run("esp32");
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn test_rtc_and_time_values() {
+        let src = "package main\nimport (\n\"rtc\"\n\"time\"\n)\nvar boot time.Time\nfunc main() {\n\
+                   rtc.Begin(rtc.DS3231)\nif rtc.LostPower() {\nrtc.SetTime(time.Date(2024, time.March, 1, 12, 0, 0, 0, time.UTC))\n}\n\
+                   t := rtc.Now()\nprintln(t.Year())\nprintln(t.Hour())\nprintln(t.Format(\"2006-01-02 15:04:05\"))\n\
+                   println(t.Format(time.DateOnly))\nprintln(rtc.Temperature())\n}";
+        let cpp = transpile(src).unwrap();
+        assert!(cpp.contains("#include <RTClib.h>") && cpp.contains("RTC_DS3231 tsuki_rtc_ds3231;"));
+        assert!(cpp.contains("DateTime boot;"));
+        assert!(cpp.contains("tsuki_rtc_Begin(0);"));
+        assert!(cpp.contains("tsuki_rtc_SetTime(DateTime(2024, 3, 1, 12, 0, 0));"), "{}", cpp);
+        assert!(cpp.contains("auto t = tsuki_rtc_Now();"));
+        assert!(cpp.contains("t.year()") && cpp.contains("t.hour()"), "{}", cpp);
+        assert!(cpp.contains("tsuki_time_Format(t, \"YYYY-MM-DD hh:mm:ss\")"));
+        assert!(cpp.contains("tsuki_time_Format(t, \"YYYY-MM-DD\")"));
+
+        let bad = src.replace("\"2006-01-02 15:04:05\"", "\"Monday 15:04\"");
+        assert!(transpile(&bad).unwrap_err().to_string().contains("`day 15:04` is not supported"));
+        let ampm = src.replace("\"2006-01-02 15:04:05\"", "\"03:04\"");
+        assert!(transpile(&ampm).unwrap_err().to_string().contains("use 03 together with PM"));
+    }
+
+    #[test]
+    fn test_pipeline_hooks() {
+        use crate::{Pipeline, TranspilerHook, WatchdogReset};
+
+        // Renames every call to `blink` into `flash`, and stamps the output.
+        struct Rename;
+        impl TranspilerHook for Rename {
+            fn visit_program(&self, prog: &mut Program, _: &TranspileConfig) -> Result<()> {
+                for d in &mut prog.decls {
+                    if let Decl::Func { name, .. } = d { if name == "blink" { *name = "flash".into() } }
+                }
+                Ok(())
+            }
+            fn filter_output(&self, cpp: String, _: &TranspileConfig) -> Result<String> {
+                Ok(format!("// stamped\n{}", cpp))
+            }
+        }
+
+        let src = "package main\nfunc blink() {\n}\nfunc setup() {\n}\nfunc loop() {\ndelay(1)\n}";
+        let cfg = |board: &str| TranspileConfig { board: board.into(), keep_all: true, ..TranspileConfig::default() };
+        let cpp = Pipeline::new(cfg("uno")).with_hook(Rename).with_hook(WatchdogReset).run(src, "main.go").unwrap();
+        assert!(cpp.starts_with("// stamped\n"));
+        assert!(cpp.contains("void flash() {") && !cpp.contains("blink"));
+        assert!(cpp.contains("#include <Arduino.h>\n#include <avr/wdt.h>\n"));
+        assert!(cpp.contains("void setup() {\n    wdt_enable(WDTO_2S);\n}"), "{}", cpp);
+        assert!(cpp.contains("void loop() {\n    wdt_reset();\n    delay(1);\n}"));
+
+        let esp = Pipeline::new(cfg("esp32")).with_hook(WatchdogReset)
+            .run("package main\nfunc setup() {\n}", "main.go").unwrap();
+        assert!(esp.contains("void loop() {\n    esp_task_wdt_reset();\n}"), "{}", esp);
+        assert!(!esp.contains("wdt_enable"));
+    }
+
+    #[test]
+    fn test_weather_station_sensors() {
+        let src = "package main\nimport (\n\"dht\"\n\"ds18b20\"\n)\nvar sensor = dht.New(2, dht.DHT22)\n\
+                   var probe = ds18b20.New(4)\nfunc setup() {\nsensor.Begin()\nprobe.Begin()\n}\nfunc loop() {\n\
+                   t, err := sensor.ReadTemperature()\nif err != nil {\nreturn\n}\nh, err := sensor.ReadHumidity()\n\
+                   println(t)\nprintln(h)\nprintln(sensor.ReadTemperature())\nw, _ := probe.ReadTemperature()\nprintln(w)\n}";
+        let cpp = transpile(src).unwrap();
+        assert!(cpp.contains("#include <DHT.h>") && cpp.contains("#include <DallasTemperature.h>"));
+        assert!(cpp.contains("auto sensor = DHT(2, DHT22);"), "{}", cpp);
+        assert!(cpp.contains("auto probe = new tsuki_ds18b20(4);"));
+        assert!(cpp.contains("sensor.begin();") && cpp.contains("probe->dt.begin();"));
+        // error.h comes before the package helpers that build errors.
+        assert!(cpp.find("struct tsuki_error {").unwrap() < cpp.find("tsuki_dht_ErrRead =").unwrap());
+        assert!(cpp.contains("tsuki_error err;\n    auto t = tsuki_dht_Check(sensor.readTemperature(), &err);"), "{}", cpp);
+        // `err` is reused, not redeclared.
+        assert!(cpp.contains("auto h = tsuki_dht_Check(sensor.readHumidity(), &err);"));
+        assert_eq!(cpp.matches("tsuki_error err;").count(), 1);
+        assert!(cpp.contains("tsuki_dht_Check(sensor.readTemperature(), nullptr)"));
+        assert!(cpp.contains("auto w = tsuki_ds18b20_Read(probe, nullptr);"));
+
+        let pipeline = crate::Pipeline::new(TranspileConfig::default());
+        assert_eq!(pipeline.arduino_libs(src, "main.go").unwrap(), ["DHT sensor library", "DallasTemperature"]);
+    }
+
+    #[test]
+    fn test_json_diagnostics() {
+        let err = transpile("package main\nfunc loop() {\nx := 1 +\n}").unwrap_err();
+        let j = err.to_json();
+        assert_eq!(j["severity"], "error");
+        assert_eq!(j["file"], "test.go");
+        assert_eq!(j["span"]["line"], 4);
+        assert_eq!(j["message"], "unexpected token in expression: `RBrace`");
+        assert!(j["suggestion"].is_null());
+
+        let j = tsukiError::codegen("no mapping for foo.Bar").to_json();
+        assert!(j["file"].is_null() && j["span"].is_null());
+        assert_eq!(j["message"], "no mapping for foo.Bar");
+
+        let w = Warning::new(Span::new("main.go", 3, 7, 20), "unused");
+        assert_eq!(w.to_json()["severity"], "warning");
+        assert_eq!(w.to_json()["span"]["col"], 7);
+    }
+
+    #[test]
+    fn test_ultrasonic_and_steppers() {
+        let src = "package main\nimport (\n\"hcsr04\"\n\"time\"\n\"stepper\"\n\"accelstepper\"\n)\n\
+                   var arm = stepper.New(2048, 8, 10, 9, 11)\nvar rail = accelstepper.New(accelstepper.Driver, 3, 4)\n\
+                   func setup() {\narm.SetSpeed(10)\nrail.SetMaxSpeed(800)\nrail.MoveTo(1600)\n}\nfunc loop() {\n\
+                   d, err := hcsr04.TriggerEchoDistanceCm(7, 6, 30*time.Millisecond)\nif err == nil && d < 20 {\narm.Step(512)\n}\n\
+                   rail.Run()\n}";
+        let cpp = transpile(src).unwrap();
+        assert!(cpp.contains("static float tsuki_hcsr04_DistanceCm("));
+        // 30 ms as a Go duration is 30e6 ns; pulseIn takes µs.
+        assert!(cpp.contains("tsuki_error err;\n    auto d = tsuki_hcsr04_DistanceCm(7, 6, (((30 * 1000000ULL)) / 1000), &err);"), "{}", cpp);
+        assert!(cpp.contains("#include <Stepper.h>") && cpp.contains("#include <AccelStepper.h>"));
+        assert!(cpp.contains("auto arm = Stepper(2048, 8, 10, 9, 11);"));
+        assert!(cpp.contains("auto rail = AccelStepper(AccelStepper::DRIVER, 3, 4);"));
+        assert!(cpp.contains("arm.step(512);") && cpp.contains("rail.moveTo(1600);") && cpp.contains("rail.run();"));
+    }
+
+    #[test]
+    fn test_fix_it_suggestions() {
+        let src = "package main\nimport \"serial\"\nfunc setup() {\n    Serail.Begin(9600)\n}";
+        let err = transpile(src).unwrap_err();
+        let fix = err.suggestion().unwrap();
+        assert_eq!((fix.message.as_str(), fix.replacement.as_deref(), fix.len), ("did you mean `serial`?", Some("serial"), 6));
+        assert!(err.pretty(src).ends_with("help: did you mean `serial`?\n   |\n  4|     serial.Begin(9600)\n   |     ~~~~~~\n"), "{}", err.pretty(src));
+
+        let err = transpile("package main\nfunc setup() {\ntime.Sleep(5)\n}").unwrap_err();
+        assert_eq!(err.message(), "package `time` is not imported");
+        assert_eq!(err.suggestion().unwrap().message, "add `import \"time\"`");
+
+        let src = "package main\ncount := 5\nfunc loop() {\n}";
+        let err = transpile(src).unwrap_err();
+        assert_eq!(err.suggestion().unwrap().replacement.as_deref(), Some("var count ="));
+        assert!(err.pretty(src).contains("  2| var count = 5\n"));
+        assert_eq!(err.to_json()["suggestion"]["len"], 8);
+
+        let err = transpile("package main\nfunc loop {\n}").unwrap_err();
+        assert_eq!((err.span().unwrap().col, err.suggestion().unwrap().replacement.as_deref()), (10, Some("()")));
+
+        // Raw C++ objects and declared names still pass through.
+        assert!(transpile("package main\nvar led = 3\nfunc setup() {\nWire.begin()\nled.Begin()\n}").is_ok());
+    }
+
+    #[test]
+    fn test_race_between_contexts_warned() {
+        let src = "package main\nvar n int\nvar m int\nfunc tick() {\nn++\nm++\n}\n\
+                   func setup() {\nattachInterrupt(0, tick, 2)\n}\n\
+                   func loop() {\nn = 0\nnoInterrupts()\nm = 0\ninterrupts()\n}";
+        let prog = Parser::new(Lexer::new(src, "test.go").tokenize().unwrap()).parse_program().unwrap();
+        let mut t = Transpiler::new(TranspileConfig::default());
+        t.generate(&prog).unwrap();
+        let w = t.warnings();
+        assert_eq!(w.len(), 1, "{:?}", w);
+        assert!(w[0].msg.starts_with("`n` is written from an interrupt handler (tick, test.go:5:1) and from the main loop"));
+        assert_eq!(w[0].span.line, 12);
+    }
+
+    #[test]
+    fn test_struct_methods_become_members() {
+        let cpp = transpile(
+            "package main\ntype P struct {\nX int\n}\nfunc (p *P) Inc() {\np.X++\n}\n\
+             func (p P) Get() int { return p.X }\nfunc main() {\nv := P{X: 1}\npv := &v\npv.Inc()\n}",
+        ).unwrap();
+        assert!(cpp.contains("struct P {\n    int X;\n    void Inc();\n    int Get() const;\n};"));
+        assert!(cpp.contains("void P::Inc() {\n    P* p = this;\n    p->X++;"));
+        assert!(cpp.contains("int P::Get() const {\n    P p = *this;"));
+        assert!(cpp.contains("auto v = P{1};"));
+        assert!(cpp.contains("pv->Inc();"));
+    }
+
+    #[test]
+    fn test_embedded_struct_becomes_base() {
+        let cpp = transpile(
+            "package main\ntype D struct {\nB\nY int\n}\ntype B struct {\nX int\n}\n\
+             func main() {\nd := D{Y: 2}\nn := d.B.X + d.X\n}",
+        ).unwrap();
+        let b = cpp.find("struct B {").unwrap();
+        let d = cpp.find("struct D : B {").unwrap();
+        assert!(b < d);
+        assert!(cpp.contains("    D(const B& _B, int Y) : B(_B), Y(Y) {}"));
+        assert!(cpp.contains("auto d = D{{}, 2};"));
+        assert!(cpp.contains("(static_cast<B&>(d).X + d.X)"));
+    }
+
+    #[test]
+    fn test_fallthrough_in_final_case_rejected() {
+        let err = transpile("package main\nfunc f(n int) {\nswitch n {\ncase 1:\nfallthrough\n}\n}");
+        assert!(err.is_err());
+    }
+}
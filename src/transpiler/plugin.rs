@@ -0,0 +1,66 @@
+// ─────────────────────────────────────────────────────────────────────────────
+//  tsuki :: transpiler :: plugin
+//
+//  Extension points for tools built on the library. A `TranspilerHook`
+//  registered with `Pipeline::with_hook` sees the parsed program before code
+//  generation and the C++ text after it, so it can rewrite AST nodes, add
+//  includes or instrument the sketch without a fork of the transpiler.
+//  Hooks run in registration order at both stages.
+// ─────────────────────────────────────────────────────────────────────────────
+
+use crate::error::Result;
+use crate::parser::ast::Program;
+use crate::runtime::Board;
+
+use super::config::TranspileConfig;
+
+pub trait TranspilerHook {
+    /// Rewrite the program before it is lowered to C++. Runs before the
+    /// transpiler's own passes (main() splitting, pruning, folding).
+    fn visit_program(&self, _prog: &mut Program, _cfg: &TranspileConfig) -> Result<()> {
+        Ok(())
+    }
+
+    /// Rewrite the generated C++ of the sketch.
+    fn filter_output(&self, cpp: String, _cfg: &TranspileConfig) -> Result<String> {
+        Ok(cpp)
+    }
+}
+
+/// Feeds the hardware watchdog at the top of every loop() pass. On AVR the
+/// watchdog is also armed, with a two-second timeout, at the start of
+/// setup(); the ESP cores arm theirs already. Other targets are left alone.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WatchdogReset;
+
+impl TranspilerHook for WatchdogReset {
+    fn filter_output(&self, cpp: String, cfg: &TranspileConfig) -> Result<String> {
+        let (include, arm, feed) = match Board::find(&cfg.board).as_ref().map(Board::arch) {
+            Some("avr")     => (Some("avr/wdt.h"), Some("wdt_enable(WDTO_2S);"), "wdt_reset();"),
+            Some("esp32")   => (Some("esp_task_wdt.h"), None, "esp_task_wdt_reset();"),
+            Some("esp8266") => (None, None, "ESP.wdtFeed();"),
+            _ => return Ok(cpp),
+        };
+        let mut cpp = match include {
+            Some(h) => cpp.replacen("#include <Arduino.h>\n", &format!("#include <Arduino.h>\n#include <{}>\n", h), 1),
+            None => cpp,
+        };
+        if let Some(arm) = arm {
+            cpp = prepend_to_body(cpp, "void setup()", arm);
+        }
+        Ok(prepend_to_body(cpp, "void loop()", feed))
+    }
+}
+
+/// Insert `stmt` as the first statement of the generated function `head`,
+/// including the empty stubs emitted when the Go source has none.
+fn prepend_to_body(cpp: String, head: &str, stmt: &str) -> String {
+    let open = format!("{} {{\n", head);
+    if cpp.contains(&open) {
+        return cpp.replacen(&open, &format!("{}    {}\n", open, stmt), 1);
+    }
+    [format!("{} {{}}", head), format!("{}  {{}}", head)].iter()
+        .find(|stub| cpp.contains(stub.as_str()))
+        .map(|stub| cpp.replacen(stub, &format!("{} {{\n    {}\n}}", head, stmt), 1))
+        .unwrap_or(cpp)
+}
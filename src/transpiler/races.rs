@@ -0,0 +1,302 @@
+// ─────────────────────────────────────────────────────────────────────────────
+//  tsuki :: transpiler :: races
+//
+//  Flags package-level variables written from more than one concurrent
+//  context without synchronisation. The contexts are:
+//
+//      main    setup()/main()/loop() and everything they call
+//      core 1  setup1()/loop1() (second core on RP2040 / ESP32)
+//      ISR     handlers passed to attachInterrupt() or marked //tsuki:isr
+//
+//  A write counts as protected when it sits between noInterrupts() and
+//  interrupts(), or between x.Lock() and x.Unlock(), in the same block or
+//  in a caller. Reads are not tracked.
+// ─────────────────────────────────────────────────────────────────────────────
+
+use std::collections::{HashMap, HashSet};
+
+use crate::error::{Span, Warning};
+use crate::parser::ast::*;
+
+/// One unprotected write: the context it runs in, the function it is in
+/// and where.
+struct Write<'a> {
+    ctx:  &'static str,
+    func: &'a str,
+    span: &'a Span,
+}
+
+/// Run the analysis over a whole program.
+pub fn check(prog: &Program) -> Vec<Warning> {
+    let funcs: HashMap<&str, &Decl> = prog.decls.iter().filter_map(|d| match d {
+        Decl::Func { name, recv: None, body: Some(_), .. } => Some((name.as_str(), d)),
+        _ => None,
+    }).collect();
+    let globals: HashSet<&str> = prog.decls.iter().filter_map(|d| match d {
+        Decl::Var { name, .. } => Some(name.as_str()),
+        _ => None,
+    }).collect();
+    if globals.is_empty() { return Vec::new() }
+
+    let mut walker = Walker { funcs: &funcs, globals: &globals, seen: HashSet::new(), writes: Vec::new() };
+    let roots: [(&str, &[&str]); 2] = [("main", &["setup", "main", "loop"]), ("core 1", &["setup1", "loop1"])];
+    for (ctx, names) in roots {
+        for name in names {
+            walker.enter(ctx, name, false);
+        }
+    }
+    let mut isrs: Vec<&str> = Vec::new();
+    for (name, d) in &funcs {
+        if let Decl::Func { body: Some(b), directives, .. } = d {
+            if directives.iter().any(|d| d.name == "tsuki:isr") { isrs.push(name) }
+            collect_isrs(&b.stmts, &funcs, &mut isrs);
+        }
+    }
+    isrs.sort();
+    isrs.dedup();
+    for isr in isrs {
+        walker.enter("ISR", isr, false);
+    }
+
+    // First unprotected write per (global, context), in source order.
+    let mut first: Vec<(&str, Write)> = Vec::new();
+    for (global, w) in walker.writes {
+        if !first.iter().any(|(g, f)| *g == global && f.ctx == w.ctx) {
+            first.push((global, w));
+        }
+    }
+    first.sort_by_key(|(_, w)| (w.span.line, w.span.col));
+
+    let mut out = Vec::new();
+    let mut reported = HashSet::new();
+    for (global, a) in &first {
+        if reported.contains(global) { continue }
+        let Some((_, b)) = first.iter().find(|(g, w)| g == global && w.ctx != a.ctx) else { continue };
+        reported.insert(*global);
+        out.push(Warning::new(b.span.clone(), format!(
+            "`{}` is written from {} ({}, {}) and from {} ({}, here) without synchronisation; \
+             guard the writes with noInterrupts()/interrupts() or a mutex",
+            global, describe(a), a.func, a.span, describe(b), b.func)));
+    }
+    out
+}
+
+fn describe(w: &Write) -> &'static str {
+    match w.ctx {
+        "main"   => "the main loop",
+        "core 1" => "the second core",
+        _        => "an interrupt handler",
+    }
+}
+
+struct Walker<'a> {
+    funcs:   &'a HashMap<&'a str, &'a Decl>,
+    globals: &'a HashSet<&'a str>,
+    /// (context, function, protected) already visited.
+    seen:    HashSet<(&'static str, &'a str, bool)>,
+    writes:  Vec<(&'a str, Write<'a>)>,
+}
+
+impl<'a> Walker<'a> {
+    fn enter(&mut self, ctx: &'static str, name: &str, protected: bool) {
+        let Some((&name, &decl)) = self.funcs.get_key_value(name) else { return };
+        let Decl::Func { sig, body: Some(body), .. } = decl else { return };
+        if !self.seen.insert((ctx, name, protected)) { return }
+        let mut locals: HashSet<&str> = sig.params.iter().filter_map(|p| p.name.as_deref()).collect();
+        declared(&body.stmts, &mut locals);
+        let mut f = Frame { ctx, func: name, locals, calls: Vec::new() };
+        self.block(&mut f, &body.stmts, protected);
+        for (callee, guarded) in f.calls {
+            self.enter(ctx, callee, guarded);
+        }
+    }
+
+    fn block(&mut self, f: &mut Frame<'a>, stmts: &'a [Stmt], mut protected: bool) {
+        let outer = protected;
+        for s in stmts {
+            if let Stmt::Expr { expr, .. } = s {
+                match guard(expr) {
+                    Some(true)  => { protected = true;  continue }
+                    Some(false) => { protected = outer; continue }
+                    None => {}
+                }
+            }
+            self.stmt(f, s, protected);
+        }
+    }
+
+    fn stmt(&mut self, f: &mut Frame<'a>, s: &'a Stmt, p: bool) {
+        match s {
+            Stmt::Assign { lhs, rhs, span, .. } => {
+                for e in lhs { self.write(f, e, span, p); self.expr(f, e, p); }
+                for e in rhs { self.expr(f, e, p); }
+            }
+            Stmt::Inc { expr, span } | Stmt::Dec { expr, span } => {
+                self.write(f, expr, span, p);
+                self.expr(f, expr, p);
+            }
+            Stmt::VarDecl { init: Some(e), .. } | Stmt::ConstDecl { val: e, .. }
+            | Stmt::Expr { expr: e, .. } | Stmt::Defer { call: e, .. } | Stmt::Go { call: e, .. } =>
+                self.expr(f, e, p),
+            Stmt::ShortDecl { vals, .. } | Stmt::Return { vals, .. } =>
+                for e in vals { self.expr(f, e, p) },
+            Stmt::If { init, cond, then, else_, .. } => {
+                if let Some(i) = init { self.stmt(f, i, p) }
+                self.expr(f, cond, p);
+                self.block(f, &then.stmts, p);
+                if let Some(e) = else_ { self.stmt(f, e, p) }
+            }
+            Stmt::For { init, cond, post, body, .. } => {
+                if let Some(i) = init { self.stmt(f, i, p) }
+                if let Some(c) = cond { self.expr(f, c, p) }
+                if let Some(s) = post { self.stmt(f, s, p) }
+                self.block(f, &body.stmts, p);
+            }
+            Stmt::Range { iter, body, .. } => {
+                self.expr(f, iter, p);
+                self.block(f, &body.stmts, p);
+            }
+            Stmt::Switch { init, tag, cases, .. } => {
+                if let Some(i) = init { self.stmt(f, i, p) }
+                if let Some(t) = tag { self.expr(f, t, p) }
+                for c in cases {
+                    for e in &c.exprs { self.expr(f, e, p) }
+                    self.block(f, &c.body, p);
+                }
+            }
+            Stmt::TypeSwitch { init, expr, cases, .. } => {
+                if let Some(i) = init { self.stmt(f, i, p) }
+                self.expr(f, expr, p);
+                for c in cases { self.block(f, &c.body, p) }
+            }
+            Stmt::Block(b) => self.block(f, &b.stmts, p),
+            _ => {}
+        }
+    }
+
+    /// Record a write to the global at the root of `target` (`g`, `g.x`, `g[i]`).
+    fn write(&mut self, f: &Frame<'a>, target: &'a Expr, span: &'a Span, protected: bool) {
+        if protected { return }
+        let mut e = target;
+        while let Expr::Select { expr, .. } | Expr::Index { expr, .. } = e {
+            e = expr;
+        }
+        if let Expr::Ident { name, .. } = e {
+            if let Some(&g) = self.globals.get(name.as_str()) {
+                if !f.locals.contains(g) {
+                    self.writes.push((g, Write { ctx: f.ctx, func: f.func, span }));
+                }
+            }
+        }
+    }
+
+    /// Queue calls to package-level functions made in `e`.
+    fn expr(&mut self, f: &mut Frame<'a>, e: &'a Expr, p: bool) {
+        match e {
+            Expr::Call { func, args, .. } => {
+                if let Expr::Ident { name, .. } = func.as_ref() {
+                    if self.funcs.contains_key(name.as_str()) && !f.locals.contains(name.as_str()) {
+                        f.calls.push((name.as_str(), p));
+                    }
+                }
+                self.expr(f, func, p);
+                for a in args { self.expr(f, a, p) }
+            }
+            Expr::Binary { lhs, rhs, .. } => { self.expr(f, lhs, p); self.expr(f, rhs, p) }
+            Expr::Unary { expr, .. } | Expr::Select { expr, .. } | Expr::TypeAssert { expr, .. } =>
+                self.expr(f, expr, p),
+            Expr::Index { expr, idx, .. } => { self.expr(f, expr, p); self.expr(f, idx, p) }
+            Expr::Slice { expr, lo, hi, .. } => {
+                self.expr(f, expr, p);
+                for b in [lo, hi].into_iter().flatten() { self.expr(f, b, p) }
+            }
+            Expr::Composite { elems, .. } => for el in elems { self.expr(f, &el.val, p) },
+            Expr::FuncLit { body, .. } => self.block(f, &body.stmts, p),
+            _ => {}
+        }
+    }
+}
+
+struct Frame<'a> {
+    ctx:    &'static str,
+    func:   &'a str,
+    locals: HashSet<&'a str>,
+    /// Callees and whether the call site was protected.
+    calls:  Vec<(&'a str, bool)>,
+}
+
+/// `Some(true)` for a statement opening a critical section, `Some(false)`
+/// for one closing it.
+fn guard(e: &Expr) -> Option<bool> {
+    let Expr::Call { func, args, .. } = e else { return None };
+    if !args.is_empty() { return None }
+    let name = match func.as_ref() {
+        Expr::Ident { name, .. } | Expr::Select { field: name, .. } => name.as_str(),
+        _ => return None,
+    };
+    match name {
+        "noInterrupts" | "NoInterrupts" | "Lock"   => Some(true),
+        "interrupts"   | "Interrupts"   | "Unlock" => Some(false),
+        _ => None,
+    }
+}
+
+/// Names declared anywhere in `stmts`; they shadow globals for the whole
+/// function, which is coarse but errs towards fewer warnings.
+fn declared<'a>(stmts: &'a [Stmt], out: &mut HashSet<&'a str>) {
+    for s in stmts {
+        match s {
+            Stmt::VarDecl { name, .. } | Stmt::ConstDecl { name, .. } => { out.insert(name); }
+            Stmt::ShortDecl { names, .. } => out.extend(names.iter().map(String::as_str)),
+            Stmt::Range { key, val, body, .. } => {
+                out.extend(key.iter().chain(val).map(String::as_str));
+                declared(&body.stmts, out);
+            }
+            Stmt::If { init, then, else_, .. } => {
+                for s in init.iter().chain(else_) { declared(std::slice::from_ref(s), out) }
+                declared(&then.stmts, out);
+            }
+            Stmt::For { init, body, .. } => {
+                if let Some(s) = init { declared(std::slice::from_ref(s), out) }
+                declared(&body.stmts, out);
+            }
+            Stmt::Switch { init, cases, .. } => {
+                if let Some(s) = init { declared(std::slice::from_ref(s), out) }
+                for c in cases { declared(&c.body, out) }
+            }
+            Stmt::TypeSwitch { init, bind, cases, .. } => {
+                if let Some(s) = init { declared(std::slice::from_ref(s), out) }
+                if let Some(b) = bind { out.insert(b); }
+                for c in cases { declared(&c.body, out) }
+            }
+            Stmt::Block(b) => declared(&b.stmts, out),
+            _ => {}
+        }
+    }
+}
+
+/// Functions passed as the handler of `attachInterrupt(pin, handler, mode)`.
+fn collect_isrs<'a>(stmts: &'a [Stmt], funcs: &HashMap<&str, &Decl>, out: &mut Vec<&'a str>) {
+    for s in stmts {
+        match s {
+            Stmt::Expr { expr: Expr::Call { func, args, .. }, .. } => {
+                let name = match func.as_ref() {
+                    Expr::Ident { name, .. } | Expr::Select { field: name, .. } => name.as_str(),
+                    _ => continue,
+                };
+                if !matches!(name, "attachInterrupt" | "AttachInterrupt") { continue }
+                if let Some(Expr::Ident { name, .. }) = args.get(1) {
+                    if funcs.contains_key(name.as_str()) { out.push(name) }
+                }
+            }
+            Stmt::If { then, else_, .. } => {
+                collect_isrs(&then.stmts, funcs, out);
+                if let Some(e) = else_ { collect_isrs(std::slice::from_ref(e), funcs, out) }
+            }
+            Stmt::For { body, .. } | Stmt::Range { body, .. } => collect_isrs(&body.stmts, funcs, out),
+            Stmt::Switch { cases, .. } => for c in cases { collect_isrs(&c.body, funcs, out) },
+            Stmt::Block(b) => collect_isrs(&b.stmts, funcs, out),
+            _ => {}
+        }
+    }
+}